@@ -0,0 +1,111 @@
+//! Checks the intervals of the bars and the aggregation of the trades into bars, late and out of order trades
+//! included.
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeDelta, Utc};
+    use dixit_core::bars::{Bar, Interval, TradeAggregator};
+    use rust_decimal::Decimal;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        format!("2024-03-01T{time}Z").parse().unwrap()
+    }
+
+    fn interval(interval: &str) -> Interval {
+        interval.parse().unwrap()
+    }
+
+    /// start, open, high, low, close, volume and trade count of the bar
+    fn ohlcv(bar: &Bar) -> (DateTime<Utc>, i64, i64, i64, i64, i64, u64) {
+        let int = |value: Decimal| i64::try_from(value).unwrap();
+        (
+            bar.start,
+            int(bar.open),
+            int(bar.high),
+            int(bar.low),
+            int(bar.close),
+            int(bar.volume),
+            bar.trade_count,
+        )
+    }
+
+    #[test]
+    fn test_interval() {
+        for (text, duration, displayed) in [
+            ("500ms", 500, "500ms"),
+            ("1s", 1_000, "1s"),
+            ("90s", 90_000, "90s"),
+            ("60s", 60_000, "1m"),
+            ("5m", 300_000, "5m"),
+            ("120m", 7_200_000, "2h"),
+        ] {
+            let parsed = interval(text);
+            assert_eq!(duration, parsed.duration().as_millis(), "{text}");
+            assert_eq!(displayed, parsed.to_string(), "{text}");
+        }
+        for invalid in ["", "m", "0s", "-1s", "1d", "1.5m", "s1"] {
+            assert!(invalid.parse::<Interval>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_boundaries() {
+        let mut bars = TradeAggregator::new(vec![interval("1s"), interval("1m")]);
+        assert!(bars.is_enabled());
+        assert!(bars.update("BTC", at("10:00:00"), 100.into(), 1.into()).is_empty());
+        assert!(bars.update("BTC", at("10:00:00.999"), 103.into(), 2.into()).is_empty());
+        // the 1s bar closes on the next second, the 1m bar is still open
+        let closed = bars.update("BTC", at("10:00:59.999"), 98.into(), 1.into());
+        assert_eq!(
+            vec![(at("10:00:00"), 100, 103, 100, 103, 3, 2)],
+            closed.iter().map(ohlcv).collect::<Vec<_>>()
+        );
+        assert_eq!("1s", closed[0].interval);
+        // another symbol has bars of its own
+        assert!(bars.update("ETH", at("10:00:30"), 10.into(), 1.into()).is_empty());
+
+        let closed = bars.update("BTC", at("10:01:00"), 101.into(), 1.into());
+        let closed = closed
+            .iter()
+            .map(|bar| (bar.interval.as_str(), ohlcv(bar)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("1s", (at("10:00:59"), 98, 98, 98, 98, 1, 1)),
+                ("1m", (at("10:00:00"), 100, 103, 98, 98, 4, 3)),
+            ],
+            closed
+        );
+        assert!(!TradeAggregator::new(vec![]).is_enabled());
+    }
+
+    #[test]
+    fn test_late_trades() {
+        let mut bars = TradeAggregator::new(vec![interval("1m")]);
+        bars.update("BTC", at("10:00:10"), 100.into(), 1.into());
+        let closed = bars.update("BTC", at("10:01:10"), 101.into(), 1.into());
+        assert_eq!(1, closed.len());
+        // older than the open bar, its bar is already emitted
+        assert!(bars.update("BTC", at("10:00:50"), 90.into(), 1.into()).is_empty());
+
+        // the open bar is kept a little after its end for the late trades
+        assert!(bars.close(at("10:02:01")).is_empty());
+        assert!(bars.update("BTC", at("10:01:59"), 102.into(), 1.into()).is_empty());
+        let closed = bars.close(at("10:02:02"));
+        assert_eq!(
+            vec![(at("10:01:00"), 101, 102, 101, 102, 2, 2)],
+            closed.iter().map(ohlcv).collect::<Vec<_>>()
+        );
+
+        // then dropped, even without an open bar for the symbol
+        assert!(bars.update("BTC", at("10:01:30"), 80.into(), 1.into()).is_empty());
+        assert!(bars.close(at("10:05:00")).is_empty());
+        // the next bars open as usual
+        assert!(bars.update("BTC", at("10:05:00"), 103.into(), 1.into()).is_empty());
+        let closed = bars.close(at("10:05:00") + TimeDelta::minutes(2));
+        assert_eq!(
+            vec![(at("10:05:00"), 103, 103, 103, 103, 1, 1)],
+            closed.iter().map(ohlcv).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Checks the Bitfinex handler, routing the array payloads by the channel ids assigned on subscription.
+#![cfg(feature = "bitfinex")]
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::DateTime;
+    use dixit_core::bitfinex::{self, Handler};
+    use dixit_core::clock::{self, ManualClock};
+    use dixit_core::model::{Record, Severity, SkipReason, VenueData};
+    use rust_decimal::Decimal;
+    use tokio_tungstenite::tungstenite::Message;
+
+    fn handle(handler: &mut Handler, text: &str) -> Vec<VenueData> {
+        handler
+            .handle(Message::Text(text.to_owned()))
+            .into_iter()
+            .map(|record| match record {
+                Record::Data { data, .. } => *data,
+                _ => panic!("no data in {text}"),
+            })
+            .collect()
+    }
+
+    fn is_skipped(handler: &mut Handler, text: &str) -> bool {
+        let records = handler.handle(Message::Text(text.to_owned()));
+        matches!(
+            records.as_slice(),
+            [Record::Skip {
+                reason: SkipReason::Unsubscribed,
+                ..
+            }]
+        )
+    }
+
+    fn subscribed(handler: &mut Handler) {
+        for event in [
+            r#"{"event":"subscribed","channel":"trades","chanId":17,"symbol":"tBTCUSD","pair":"BTCUSD"}"#,
+            r#"{"event":"subscribed","channel":"ticker","chanId":5,"symbol":"tBTCUSD","pair":"BTCUSD"}"#,
+            r#"{"event":"subscribed","channel":"candles","chanId":42,"key":"trade:1m:tBTCUSD"}"#,
+        ] {
+            assert!(is_skipped(handler, event), "{event}");
+        }
+    }
+
+    fn decimal(value: &str) -> Decimal {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let messages = bitfinex::subscribe(&["tBTCUSD".to_owned()]);
+        assert_eq!(
+            vec![
+                Message::Text(r#"{"channel":"trades","event":"subscribe","symbol":"tBTCUSD"}"#.to_owned()),
+                Message::Text(r#"{"channel":"ticker","event":"subscribe","symbol":"tBTCUSD"}"#.to_owned()),
+                Message::Text(r#"{"channel":"candles","event":"subscribe","key":"trade:1m:tBTCUSD"}"#.to_owned()),
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn test_trades() {
+        let mut handler = Handler::new(clock::system());
+        subscribed(&mut handler);
+
+        let snapshot = handle(
+            &mut handler,
+            "[17,[[1,1700000000000,0.5,37000],[2,1700000001000,-0.25,37001.5]]]",
+        );
+        let trades = snapshot
+            .iter()
+            .map(|data| match data {
+                VenueData::BitfinexTrade(trade) => trade,
+                _ => panic!("{data:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(2, trades.len());
+        assert_eq!(
+            ("tBTCUSD", 1, DateTime::from_timestamp_millis(1700000000000).unwrap()),
+            (trades[0].symbol.as_str(), trades[0].trade_id, trades[0].time)
+        );
+        assert_eq!((decimal("0.5"), "buy"), (trades[0].size, trades[0].side.as_str()));
+        assert_eq!(
+            (decimal("0.25"), decimal("37001.5"), "sell"),
+            (trades[1].size, trades[1].price, trades[1].side.as_str())
+        );
+
+        let executed = handle(&mut handler, r#"[17,"te",[3,1700000002000,1,37002]]"#);
+        assert!(matches!(executed.as_slice(), [VenueData::BitfinexTrade(trade)] if trade.trade_id == 3));
+        // the same trade is sent again once updated, and heartbeats keep the channel alive
+        assert!(is_skipped(&mut handler, r#"[17,"tu",[3,1700000002000,1,37002]]"#));
+        assert!(is_skipped(&mut handler, r#"[17,"hb"]"#));
+    }
+
+    #[test]
+    fn test_ticker_and_candles() {
+        // tickers carry no timestamp, they are dated by the clock of the handler
+        let received = DateTime::from_timestamp_millis(1700000003000).unwrap();
+        let mut handler = Handler::new(Arc::new(ManualClock::new(received)));
+        subscribed(&mut handler);
+
+        let ticker = handle(
+            &mut handler,
+            "[5,[37000,1.5,37001,2.5,-100,-0.0027,37000.5,1234.5,37500,36500]]",
+        );
+        let [VenueData::BitfinexTicker(ticker)] = ticker.as_slice() else {
+            panic!("{ticker:?}");
+        };
+        assert_eq!(("tBTCUSD", received), (ticker.symbol.as_str(), ticker.time));
+        assert_eq!(
+            ["37000", "1.5", "37001", "2.5", "-100", "-0.0027", "37000.5", "1234.5", "37500", "36500"].map(decimal),
+            [
+                ticker.bid,
+                ticker.bid_size,
+                ticker.ask,
+                ticker.ask_size,
+                ticker.daily_change,
+                ticker.daily_change_relative,
+                ticker.last_price,
+                ticker.volume,
+                ticker.high,
+                ticker.low
+            ]
+        );
+
+        // the snapshot of past candles is not recorded, the updates are
+        assert!(is_skipped(
+            &mut handler,
+            "[42,[[1700000040000,1,2,3,0.5,10],[1700000000000,1,1,1,1,1]]]"
+        ));
+        let candle = handle(&mut handler, "[42,[1700000040000,1,2,3,0.5,10]]");
+        let [VenueData::Candle(candle)] = candle.as_slice() else {
+            panic!("{candle:?}");
+        };
+        assert_eq!(
+            ("tBTCUSD", DateTime::from_timestamp_millis(1700000040000).unwrap()),
+            (candle.symbol.as_str(), candle.start)
+        );
+        assert_eq!(
+            ["1", "3", "0.5", "2", "10"].map(decimal),
+            [candle.open, candle.high, candle.low, candle.close, candle.volume]
+        );
+    }
+
+    #[test]
+    fn test_events() {
+        let mut handler = Handler::new(clock::system());
+        // data of a channel never subscribed
+        assert!(is_skipped(&mut handler, r#"[17,"te",[3,1700000002000,1,37002]]"#));
+        subscribed(&mut handler);
+
+        let mut unsubscribe = handler
+            .unsubscribe(&["tBTCUSD".to_owned()])
+            .into_iter()
+            .map(|message| message.into_text().unwrap())
+            .collect::<Vec<_>>();
+        unsubscribe.sort();
+        assert_eq!(
+            vec![
+                r#"{"chanId":17,"event":"unsubscribe"}"#,
+                r#"{"chanId":42,"event":"unsubscribe"}"#,
+                r#"{"chanId":5,"event":"unsubscribe"}"#,
+            ],
+            unsubscribe
+        );
+        assert!(handler.unsubscribe(&["tETHUSD".to_owned()]).is_empty());
+
+        assert!(is_skipped(
+            &mut handler,
+            r#"{"event":"unsubscribed","status":"OK","chanId":17}"#
+        ));
+        assert!(is_skipped(&mut handler, r#"[17,"te",[3,1700000002000,1,37002]]"#));
+
+        let error = handler.handle(Message::Text(
+            r#"{"event":"error","msg":"symbol: invalid","code":10300}"#.to_owned(),
+        ));
+        let [Record::Error {
+            severity,
+            message,
+            reason,
+            ..
+        }] = error.as_slice()
+        else {
+            panic!("{} records instead of an error", error.len());
+        };
+        assert_eq!(
+            (Severity::Warning, "symbol: invalid", "code 10300"),
+            (*severity, message.as_str(), reason.as_str())
+        );
+    }
+}
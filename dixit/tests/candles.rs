@@ -0,0 +1,106 @@
+//! Checks the closing and the gap filling of the candles, and the candles parsed from the venues.
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeDelta, Utc};
+    use dixit_core::candles::{Candle, CandleAggregator, MAX_FILLED};
+    use rust_decimal::Decimal;
+
+    fn candle(minute: i64, close: i64) -> Candle {
+        Candle {
+            symbol: "BTC-USDT-SWAP".to_owned(),
+            instrument: None,
+            start: start(minute),
+            open: Decimal::from(close - 1),
+            high: Decimal::from(close + 1),
+            low: Decimal::from(close - 2),
+            close: Decimal::from(close),
+            volume: Decimal::ONE,
+            trade_count: Some(3),
+            missing: false,
+        }
+    }
+
+    fn start(minute: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_040, 0).unwrap() + TimeDelta::minutes(minute)
+    }
+
+    #[test]
+    fn test_close() {
+        let mut candles = CandleAggregator::default();
+        assert!(candles.update(candle(0, 100)).is_empty());
+        // updates of the open candle replace it, late ones are dropped
+        assert!(candles.update(candle(0, 101)).is_empty());
+        assert!(candles.update(candle(-1, 90)).is_empty());
+
+        let closed = candles.update(candle(1, 102));
+        assert_eq!(1, closed.len());
+        assert_eq!(
+            (start(0), Decimal::from(101), false),
+            (closed[0].start, closed[0].close, closed[0].missing)
+        );
+        // the closed candle is not emitted again
+        assert!(candles.update(candle(0, 103)).is_empty());
+    }
+
+    #[test]
+    fn test_gap_fill() {
+        let mut candles = CandleAggregator::default();
+        candles.update(candle(0, 100));
+        let closed = candles.update(candle(4, 104));
+        let starts = closed.iter().map(|candle| candle.start).collect::<Vec<_>>();
+        assert_eq!(vec![start(0), start(1), start(2), start(3)], starts);
+        for missing in &closed[1..] {
+            assert!(missing.missing);
+            assert_eq!(Decimal::from(100), missing.open);
+            assert_eq!(Decimal::from(100), missing.close);
+            assert_eq!((Decimal::ZERO, Some(0)), (missing.volume, missing.trade_count));
+        }
+    }
+
+    #[test]
+    fn test_long_gap() {
+        let mut candles = CandleAggregator::default();
+        candles.update(candle(0, 100));
+        let closed = candles.update(candle(MAX_FILLED + 1, 100));
+        assert_eq!(MAX_FILLED as usize + 1, closed.len());
+
+        // a longer gap is only marked by its first missing candle
+        let reopened = MAX_FILLED + 1 + 7 * 24 * 60;
+        let closed = candles.update(candle(reopened, 100));
+        assert_eq!(2, closed.len());
+        assert_eq!((start(MAX_FILLED + 2), true), (closed[1].start, closed[1].missing));
+        assert_eq!(1, candles.update(candle(reopened + 1, 100)).len());
+    }
+
+    #[test]
+    #[cfg(feature = "okx")]
+    fn test_okx_candles() {
+        use dixit_core::model::{Record, VenueData};
+        use dixit_core::okx;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let subscribe = okx::subscribe_candles(&["BTC-USDT-SWAP".to_owned()]);
+        assert_eq!(
+            vec![Message::Text(
+                r#"{"args":[{"channel":"candle1m","instId":"BTC-USDT-SWAP"}],"op":"subscribe"}"#.to_owned()
+            )],
+            subscribe
+        );
+
+        let push = r#"{"arg":{"channel":"candle1m","instId":"BTC-USDT-SWAP"},"data":[["1700000040000","37000.1","37010","36990.5","37005","120","1.2","44406","0"]]}"#;
+        let records = okx::handle(Message::Text(push.to_owned()));
+        let [Record::Data { channel, data, .. }] = records.as_slice() else {
+            panic!("{} records instead of a single candle", records.len());
+        };
+        let VenueData::Candle(candle) = &**data else {
+            panic!("{data:?}");
+        };
+        assert_eq!("candle1m", channel);
+        assert_eq!(("BTC-USDT-SWAP", start(0)), (candle.symbol.as_str(), candle.start));
+        assert_eq!(
+            ["37000.1", "37010", "36990.5", "37005", "1.2"].map(|value| value.parse::<Decimal>().unwrap()),
+            [candle.open, candle.high, candle.low, candle.close, candle.volume]
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Checks the registration of the recorded tables in the Glue catalog, against a mock of the Glue API.
+#![cfg(feature = "glue")]
+
+use std::{env, path::PathBuf, sync::LazyLock};
+
+mod mock_exchange;
+
+static TMP_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| {
+    let mut path_buf = env::current_dir().unwrap();
+    path_buf.push("target");
+    path_buf.push("test");
+    path_buf.push("catalog");
+    path_buf
+});
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use anyhow::{Context, Result};
+    use dixit_core::catalog::{self, Credentials};
+    use serde_json::{json, Value};
+
+    use crate::mock_exchange::{MockExchange, MockRest, Step};
+    use crate::TMP_FOLDER;
+
+    fn credentials() -> Credentials {
+        Credentials {
+            access_key: "AKIDEXAMPLE".to_owned(),
+            secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_owned(),
+            session_token: None,
+        }
+    }
+
+    #[test]
+    fn test_sigv4() -> Result<()> {
+        // the get-vanilla case of the AWS signature version 4 test suite
+        let headers = [
+            ("Host".to_owned(), "example.amazonaws.com".to_owned()),
+            ("X-Amz-Date".to_owned(), "20150830T123600Z".to_owned()),
+        ];
+        let now = "2015-08-30T12:36:00Z".parse()?;
+        let authorization =
+            catalog::authorization(&credentials(), "us-east-1", "service", "GET", "/", &headers, b"", now);
+        assert_eq!(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31",
+            authorization
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_name() {
+        let date = catalog::date("coinbase/trades/2024-03-01");
+        assert_eq!(Some(("2024-03-01".to_owned(), 16)), date);
+        assert_eq!(
+            "coinbase_trades",
+            catalog::table_name("coinbase/trades/2024-03-01", date.as_ref())
+        );
+        let date = catalog::date("coinbase_ticker_2024-03-01");
+        assert_eq!(
+            "coinbase_ticker",
+            catalog::table_name("coinbase_ticker_2024-03-01", date.as_ref())
+        );
+        assert_eq!(None, catalog::date("coinbase/_events"));
+        assert_eq!("coinbase_events", catalog::table_name("coinbase/_events", None));
+    }
+
+    #[tokio::test]
+    async fn test_glue_registration() -> Result<()> {
+        let exchange = MockExchange::start(vec![vec![
+            Step::text(r#"{"id":0,"code":0,"msg":"spot@public.deals.v3.api@BTCUSDT"}"#),
+            Step::text(
+                r#"{"c":"spot@public.deals.v3.api@BTCUSDT","d":{"deals":[{"S":1,"p":"37000.10","t":1700000000000,"v":"0.5"}],"e":"spot@public.deals.v3.api"},"s":"BTCUSDT","t":1700000000000}"#,
+            ),
+            Step::Close,
+        ]])
+        .await?;
+        let glue = MockRest::start(vec![("/", vec!["{}"])]).await?;
+        env::set_var("AWS_ACCESS_KEY_ID", "AKIDEXAMPLE");
+        env::set_var("AWS_SECRET_ACCESS_KEY", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY");
+        env::set_var("AWS_REGION", "us-east-1");
+        let endpoint = format!("{}/", glue.url());
+        let args = [
+            "--symbols",
+            "BTCUSDT",
+            "--table-route",
+            "{venue}/{channel}/{date}",
+            "--glue-database",
+            "markets",
+            "--glue-location",
+            "s3://bucket/dixit",
+            "--glue-endpoint",
+            &endpoint,
+        ];
+        exchange.record("mexc", &TMP_FOLDER.join("glue"), &args).await?;
+
+        let requests = glue.requests();
+        assert!(requests
+            .iter()
+            .all(|request| request.headers["authorization"].starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/")));
+        let calls = requests
+            .iter()
+            .map(|request| {
+                Ok((
+                    request.headers["x-amz-target"].clone(),
+                    serde_json::from_str(&request.body)?,
+                ))
+            })
+            .collect::<Result<Vec<(String, Value)>>>()?;
+
+        let (_, trades) = calls
+            .iter()
+            .find(|(target, body)| target == "AWSGlue.CreateTable" && body["TableInput"]["Name"] == "mexc_trades")
+            .context("trades not created")?;
+        let input = &trades["TableInput"];
+        assert_eq!("markets", trades["DatabaseName"]);
+        assert_eq!(json!([{"Name": "date", "Type": "string"}]), input["PartitionKeys"]);
+        assert_eq!("s3://bucket/dixit/mexc/trades/", input["StorageDescriptor"]["Location"]);
+        let columns = input["StorageDescriptor"]["Columns"].as_array().context("no columns")?;
+        assert!(columns.contains(&json!({"Name": "price", "Type": "double"})));
+        assert!(columns.contains(&json!({"Name": "time", "Type": "timestamp"})));
+
+        let (_, partition) = calls
+            .iter()
+            .find(|(target, body)| target == "AWSGlue.CreatePartition" && body["TableName"] == "mexc_trades")
+            .context("partition not added")?;
+        assert_eq!(json!(["2023-11-14"]), partition["PartitionInput"]["Values"]);
+        assert_eq!(
+            "s3://bucket/dixit/mexc/trades/2023-11-14/",
+            partition["PartitionInput"]["StorageDescriptor"]["Location"]
+        );
+
+        // the recorder tables are not dated
+        assert!(calls
+            .iter()
+            .any(|(target, body)| target == "AWSGlue.CreateTable" && body["TableInput"]["Name"] == "mexc_events"));
+
+        Ok(())
+    }
+}
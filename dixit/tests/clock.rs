@@ -0,0 +1,37 @@
+//! Checks the time-based behaviors of the recorder against a manual clock, passed to them instead of the system one.
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use dixit_core::clock::{Clock, ManualClock, NtpAdjustedClock};
+    use dixit_core::supervisor::Freshness;
+
+    #[test]
+    fn test_manual_clock() -> Result<()> {
+        let start = "2024-03-01T23:59:30Z".parse()?;
+        let manual = Arc::new(ManualClock::new(start));
+        // adjusted with the last measured offset only if enabled
+        let adjusted = NtpAdjustedClock::new(manual.clone(), true);
+        assert_eq!(start, adjusted.now());
+        adjusted.set_offset(-1_500_000_000);
+        assert_eq!(start - Duration::from_millis(1_500), adjusted.now());
+        let local = NtpAdjustedClock::new(manual.clone(), false);
+        local.set_offset(-1_500_000_000);
+        assert_eq!(start, local.now());
+
+        // the feed goes stale with the clock only
+        let freshness = Freshness::new(manual.clone());
+        freshness.record_received();
+        assert_eq!(Duration::ZERO, freshness.staleness());
+        manual.advance(Duration::from_secs(90));
+        assert_eq!(Duration::from_secs(90), freshness.staleness());
+        freshness.record_received();
+        assert_eq!(Duration::ZERO, freshness.staleness());
+        assert_eq!(start + Duration::from_secs(90), manual.now());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1188 @@
+use std::{env, path::PathBuf, sync::LazyLock};
+
+mod mock_exchange;
+
+static TMP_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| {
+    let mut path_buf = env::current_dir().unwrap();
+    path_buf.push("target");
+    path_buf.push("test");
+    path_buf.push("end_to_end");
+    path_buf
+});
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use anyhow::{Context, Result};
+    use chrono::{DateTime, Utc};
+    use clap::Parser;
+    use dixit_core::clock::{ManualClock, SharedClock};
+    use dixit_core::config::Args;
+    use dixit_core::events::{Event, EventKind};
+    use dixit_core::model::Record;
+    use dixit_core::{run_venue, CustomVenue};
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::RowAccessor;
+    use tokio_tungstenite::tungstenite::Message;
+
+    use crate::mock_exchange::{MockExchange, MockRest, Step};
+    use crate::TMP_FOLDER;
+
+    /// Rows of a recorded table, read from every parquet file of its directory.
+    struct Table {
+        columns: Vec<String>,
+        rows: Vec<parquet::record::Row>,
+    }
+
+    impl Table {
+        fn read(directory: &Path, table: &str) -> Result<Self> {
+            let mut files = fs::read_dir(directory.join(table))
+                .with_context(|| format!("table {table} not written"))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            files.retain(|file| file.extension().is_some_and(|extension| extension == "parquet"));
+            files.sort();
+
+            let (mut columns, mut rows) = (vec![], vec![]);
+            for file in files {
+                let reader = SerializedFileReader::new(File::open(file)?)?;
+                columns = reader
+                    .metadata()
+                    .file_metadata()
+                    .schema_descr()
+                    .columns()
+                    .iter()
+                    .map(|column| column.name().to_owned())
+                    .collect();
+                for row in reader.get_row_iter(None)? {
+                    rows.push(row?);
+                }
+            }
+            Ok(Self { columns, rows })
+        }
+
+        /// Values of a string column, in the order recorded.
+        fn strings(&self, column: &str) -> Result<Vec<String>> {
+            let index = self
+                .columns
+                .iter()
+                .position(|name| name == column)
+                .with_context(|| format!("no column {column}"))?;
+            self.rows.iter().map(|row| Ok(row.get_string(index)?.clone())).collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_okx_channels() -> Result<()> {
+        let exchange = MockExchange::start(vec![vec![
+            Step::text(r#"{"event":"subscribe","arg":{"channel":"funding-rate","instId":"BTC-USDT-SWAP"}}"#),
+            Step::text(
+                r#"{"arg":{"channel":"funding-rate","instId":"BTC-USDT-SWAP"},"data":[{"instId":"BTC-USDT-SWAP","fundingRate":"0.0001","fundingTime":"1700006400000","ts":"1700000000000"}]}"#,
+            ),
+            Step::text(
+                r#"{"arg":{"channel":"open-interest","instId":"BTC-USDT-SWAP"},"data":[{"instId":"BTC-USDT-SWAP","oi":"12345.6","ts":"1700000000100"}]}"#,
+            ),
+            Step::Ping,
+            Step::text(
+                r#"{"arg":{"channel":"index-tickers","instId":"BTC-USDT"},"data":[{"instId":"BTC-USDT","idxPx":"37000.5","ts":"1700000000200"}]}"#,
+            ),
+            // malformed messages are skipped without ending the session
+            Step::text(r#"{"arg":{"channel":"funding-rate""#),
+            Step::text(r#"{"arg":{"channel":"funding-rate"},"data":[{"instId":"BTC-USDT-SWAP"}]}"#),
+            Step::text(
+                r#"{"arg":{"channel":"liquidation-orders","instType":"SWAP"},"data":[{"instId":"BTC-USDT-SWAP","details":[{"side":"sell","bkPx":"36900","sz":"2","ts":"1700000000300"},{"side":"buy","bkPx":"37100","sz":"1","ts":"1700000000400"}]}]}"#,
+            ),
+            Step::text(
+                r#"{"arg":{"channel":"funding-rate","instId":"BTC-USDT-SWAP"},"data":[{"instId":"BTC-USDT-SWAP","fundingRate":"0.0002","fundingTime":"1700006400000","ts":"1700000060000"}]}"#,
+            ),
+            Step::Close,
+        ]])
+        .await?;
+        let directory = exchange
+            .record("okx", &TMP_FOLDER.join("okx_channels"), &["--symbols", "BTC-USDT-SWAP"])
+            .await?;
+
+        let requests = exchange.requests().await?;
+        assert_eq!(1, requests[0].len());
+        assert!(
+            requests[0][0].contains(r#""instId":"BTC-USDT-SWAP""#),
+            "{}",
+            requests[0][0]
+        );
+
+        let funding = Table::read(&directory, "funding")?;
+        assert_eq!(
+            vec!["symbol", "instrument", "time", "funding_rate", "next_funding_time"],
+            funding.columns
+        );
+        assert_eq!(2, funding.rows.len());
+        let open_interest = Table::read(&directory, "open_interest")?;
+        assert_eq!(
+            vec!["symbol", "instrument", "time", "open_interest"],
+            open_interest.columns
+        );
+        assert_eq!(1, open_interest.rows.len());
+        let index = Table::read(&directory, "index")?;
+        assert_eq!(vec!["symbol", "instrument", "time", "index_price"], index.columns);
+        assert_eq!(vec!["BTC-USDT"], index.strings("symbol")?);
+        let liquidations = Table::read(&directory, "liquidations")?;
+        assert_eq!(
+            vec!["symbol", "instrument", "time", "side", "price", "size"],
+            liquidations.columns
+        );
+        assert_eq!(vec!["sell", "buy"], liquidations.strings("side")?);
+
+        let events = Table::read(&directory, "_events")?;
+        let kinds = events.strings("kind")?;
+        assert_eq!(Some("connect"), kinds.first().map(String::as_str));
+        assert!(kinds.contains(&"disconnect".to_owned()), "{kinds:?}");
+        assert!(directory.join("_manifest.json").exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_okx_reconnect() -> Result<()> {
+        let funding = |ts: &str| {
+            Step::Text(format!(
+                r#"{{"arg":{{"channel":"funding-rate","instId":"ETH-USDT-SWAP"}},"data":[{{"instId":"ETH-USDT-SWAP","fundingRate":"0.0001","fundingTime":"1700006400000","ts":"{ts}"}}]}}"#
+            ))
+        };
+        let exchange = MockExchange::start(vec![
+            vec![
+                funding("1700000000000"),
+                // the recorder reconnects on its own, closing the session
+                Step::text(r#"{"event":"error","code":"60012","msg":"Invalid request"}"#),
+            ],
+            vec![funding("1700000060000"), Step::Close],
+        ])
+        .await?;
+        let args = ["--symbols", "ETH-USDT-SWAP", "--on-error", "warning=reconnect"];
+        let directory = exchange.record("okx", &TMP_FOLDER.join("okx_reconnect"), &args).await?;
+
+        // subscribed again on the second session
+        let requests = exchange.requests().await?;
+        assert_eq!(2, requests.len());
+        assert_eq!(requests[0][0], requests[1][0]);
+
+        assert_eq!(2, Table::read(&directory, "funding")?.rows.len());
+        let kinds = Table::read(&directory, "_events")?.strings("kind")?;
+        let connections = kinds.iter().filter(|kind| *kind == "connect").count();
+        assert_eq!(2, connections, "{kinds:?}");
+        assert!(kinds.contains(&"error".to_owned()), "{kinds:?}");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_okx_symbology() -> Result<()> {
+        let funding = |symbol: &str| {
+            Step::Text(format!(
+                r#"{{"arg":{{"channel":"funding-rate","instId":"{symbol}"}},"data":[{{"instId":"{symbol}","fundingRate":"0.0001","fundingTime":"1700006400000","ts":"1700000000000"}}]}}"#
+            ))
+        };
+        let exchange = MockExchange::start(vec![vec![
+            funding("BTC-USDT-SWAP"),
+            funding("SOL-USDT-SWAP"),
+            Step::Close,
+        ]])
+        .await?;
+        let folder = TMP_FOLDER.join("okx_symbology");
+        fs::create_dir_all(TMP_FOLDER.as_path())?;
+        let symbology = TMP_FOLDER.join("okx_symbology.yaml");
+        fs::write(&symbology, "okx:\n  SOL-USDT-SWAP: SOL-USDT-PERP\n")?;
+        let args = [
+            "--symbols",
+            "BTC-USDT-SWAP,SOL-USDT-SWAP",
+            "--symbology",
+            symbology.to_str().context("invalid path")?,
+        ];
+        let directory = exchange.record("okx", &folder, &args).await?;
+        exchange.requests().await?;
+
+        // the built-in mapping and the one of the file
+        let funding = Table::read(&directory, "funding")?;
+        assert_eq!(vec!["BTC-USDT-PERP", "SOL-USDT-PERP"], funding.strings("instrument")?);
+        let mappings = Table::read(&directory, "_symbology")?;
+        assert_eq!(vec!["venue", "symbol", "instrument"], mappings.columns);
+        let symbols = mappings.strings("symbol")?;
+        assert!(symbols.contains(&"SOL-USDT-SWAP".to_owned()), "{symbols:?}");
+        assert!(mappings.strings("venue")?.iter().all(|venue| venue == "okx"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_okx_reference() -> Result<()> {
+        let instruments = r#"{"code":"0","msg":"","data":[{"instType":"SWAP","instId":"BTC-USDT-SWAP","tickSz":"0.1","lotSz":"0.01","state":"live","expTime":""},{"instType":"SWAP","instId":"ETH-USDT-SWAP","tickSz":"0.01","lotSz":"0.1","state":"suspend","expTime":""}]}"#;
+        let rest = MockRest::start(vec![("/api/v5/public/instruments", vec![instruments])]).await?;
+        let exchange = MockExchange::start(vec![vec![
+            // the instruments are pulled on startup, while the session goes on
+            Step::Wait(Duration::from_millis(500)),
+            Step::Close,
+        ]])
+        .await?;
+        let args = ["--symbols", "BTC-USDT-SWAP", "--rest-url", &rest.url()];
+        let directory = exchange.record("okx", &TMP_FOLDER.join("okx_reference"), &args).await?;
+        exchange.requests().await?;
+        assert_eq!(vec!["/api/v5/public/instruments?instType=SWAP"], rest.paths());
+
+        let reference = Table::read(&directory, "reference")?;
+        assert_eq!(
+            vec![
+                "symbol",
+                "instrument",
+                "as_of",
+                "tick_size",
+                "lot_size",
+                "status",
+                "expiry"
+            ],
+            reference.columns
+        );
+        assert_eq!(vec!["BTC-USDT-SWAP", "ETH-USDT-SWAP"], reference.strings("symbol")?);
+        assert_eq!(vec!["live", "suspend"], reference.strings("status")?);
+        assert_eq!(vec!["BTC-USDT-PERP", "ETH-USDT-PERP"], reference.strings("instrument")?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_coinbase_full_sequence() -> Result<()> {
+        let open = |sequence: u64| {
+            Step::Text(format!(
+                r#"{{"type":"open","time":"2024-03-01T10:40:03Z","product_id":"BTC-USD","sequence":{sequence},"order_id":"o-{sequence}","price":"62000.00","remaining_size":"0.25","side":"sell"}}"#
+            ))
+        };
+        let exchange = MockExchange::start(vec![vec![
+            open(100),
+            open(101),
+            // stale message, skipped
+            open(100),
+            // gap of 102 and 103
+            open(104),
+            Step::Close,
+        ]])
+        .await?;
+        let args = ["--symbols", "BTC-USD", "--coinbase-channels", "full"];
+        let directory = exchange
+            .record("coinbase", &TMP_FOLDER.join("coinbase_full"), &args)
+            .await?;
+
+        let requests = exchange.requests().await?;
+        assert!(requests[0][0].contains(r#""channels":["full"]"#), "{}", requests[0][0]);
+        assert!(
+            requests[0][0].contains(r#""product_ids":["BTC-USD"]"#),
+            "{}",
+            requests[0][0]
+        );
+
+        let open = Table::read(&directory, "full_open")?;
+        assert_eq!(vec!["o-100", "o-101", "o-104"], open.strings("order_id")?);
+
+        let events = Table::read(&directory, "_events")?;
+        let kinds = events.strings("kind")?;
+        let gap = kinds.iter().position(|kind| kind == "gap").context("no gap event")?;
+        assert_eq!("coinbase/BTC-USD", events.strings("source")?[gap]);
+        assert_eq!("expected 102 received 104", events.strings("detail")?[gap]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_coinbase_status() -> Result<()> {
+        let status = |btc: (&str, bool), eth: &str| {
+            Step::Text(format!(
+                r#"{{"type":"status","currencies":[],"products":[{{"id":"BTC-USD","status":"{}","status_message":"","post_only":{},"limit_only":false,"cancel_only":false,"trading_disabled":false}},{{"id":"ETH-USD","status":"{eth}","status_message":"","post_only":false,"limit_only":false,"cancel_only":false,"trading_disabled":false}}]}}"#,
+                btc.0, btc.1
+            ))
+        };
+        let exchange = MockExchange::start(vec![vec![
+            status(("online", false), "online"),
+            // unchanged, nothing recorded
+            status(("online", false), "online"),
+            status(("online", true), "online"),
+            status(("online", true), "delisted"),
+            Step::Close,
+        ]])
+        .await?;
+        let args = ["--coinbase-channels", "status"];
+        let directory = exchange
+            .record("coinbase", &TMP_FOLDER.join("coinbase_status"), &args)
+            .await?;
+
+        let requests = exchange.requests().await?;
+        assert!(
+            requests[0][0].contains(r#""channels":["status"]"#),
+            "{}",
+            requests[0][0]
+        );
+
+        let status = Table::read(&directory, "status")?;
+        assert_eq!(
+            vec!["BTC-USD", "ETH-USD", "BTC-USD", "ETH-USD"],
+            status.strings("product_id")?
+        );
+        assert_eq!(
+            vec!["online", "online", "post_only", "delisted"],
+            status.strings("state")?
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_websocket_headers() -> Result<()> {
+        let exchange = MockExchange::start(vec![vec![Step::Close]]).await?;
+        let args = [
+            "--symbols",
+            "tBTCUSD",
+            "--user-agent",
+            "dixit-test/1.0",
+            "--ws-header",
+            "X-Api-Key=secret",
+            "--ws-header",
+            "Origin=https://example.com",
+        ];
+        exchange
+            .record("bitfinex", &TMP_FOLDER.join("websocket_headers"), &args)
+            .await?;
+
+        let headers = exchange.headers();
+        assert_eq!(Some("dixit-test/1.0"), headers[0].get("user-agent").map(String::as_str));
+        assert_eq!(Some("secret"), headers[0].get("x-api-key").map(String::as_str));
+        assert_eq!(
+            Some("https://example.com"),
+            headers[0].get("origin").map(String::as_str)
+        );
+        exchange.requests().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_coinbase_advanced_trade() -> Result<()> {
+        let exchange = MockExchange::start(vec![vec![
+            Step::text(
+                r#"{"channel":"subscriptions","client_id":"","timestamp":"2024-03-01T10:40:00Z","sequence_num":0,"events":[{"subscriptions":{"market_trades":["BTC-USD"]}}]}"#,
+            ),
+            Step::text(
+                r#"{"channel":"market_trades","client_id":"","timestamp":"2024-03-01T10:40:01Z","sequence_num":1,"events":[{"type":"snapshot","trades":[{"trade_id":"101","product_id":"BTC-USD","price":"62000.00","size":"0.1","side":"BUY","time":"2024-03-01T10:39:59Z"},{"trade_id":"102","product_id":"BTC-USD","price":"62000.50","size":"0.2","side":"SELL","time":"2024-03-01T10:40:00Z"}]}]}"#,
+            ),
+            Step::text(
+                r#"{"channel":"l2_data","client_id":"","timestamp":"2024-03-01T10:40:01Z","sequence_num":2,"events":[{"type":"snapshot","product_id":"BTC-USD","updates":[{"side":"bid","event_time":"2024-03-01T10:40:01Z","price_level":"61999.00","new_quantity":"1.5"},{"side":"offer","event_time":"2024-03-01T10:40:01Z","price_level":"62001.00","new_quantity":"0.7"}]}]}"#,
+            ),
+            Step::text(
+                r#"{"channel":"heartbeats","client_id":"","timestamp":"2024-03-01T10:40:02Z","sequence_num":3,"events":[{"current_time":"2024-03-01 10:40:02 +0000 UTC","heartbeat_counter":1}]}"#,
+            ),
+            // gap of 4
+            Step::text(
+                r#"{"channel":"market_trades","client_id":"","timestamp":"2024-03-01T10:40:03Z","sequence_num":5,"events":[{"type":"update","trades":[{"trade_id":"103","product_id":"BTC-USD","price":"62001.00","size":"0.3","side":"BUY","time":"2024-03-01T10:40:03Z"}]}]}"#,
+            ),
+            Step::text(r#"{"type":"error","message":"failure to subscribe"}"#),
+            Step::Close,
+        ]])
+        .await?;
+        let key_file = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/coinbase/advanced_trade_key.pem"
+        );
+        let args = [
+            "--symbols",
+            "BTC-USD",
+            "--coinbase-api",
+            "advanced-trade",
+            "--coinbase-channels",
+            "market_trades,level2",
+            "--coinbase-key-name",
+            "organizations/org/apiKeys/key",
+            "--coinbase-key-file",
+            key_file,
+        ];
+        let directory = exchange
+            .record("coinbase", &TMP_FOLDER.join("coinbase_advanced_trade"), &args)
+            .await?;
+
+        // one request per channel, each one with its own JWT
+        let requests = exchange.requests().await?;
+        let channels = ["market_trades", "level2", "heartbeats"];
+        assert_eq!(channels.len(), requests[0].len(), "{:?}", requests[0]);
+        for (request, channel) in requests[0].iter().zip(channels) {
+            let request: serde_json::Value = serde_json::from_str(request)?;
+            assert_eq!("subscribe", request["type"]);
+            assert_eq!(channel, request["channel"]);
+            let jwt = request["jwt"].as_str().context("no jwt")?;
+            assert_eq!(3, jwt.split('.').filter(|part| !part.is_empty()).count(), "{jwt}");
+        }
+        assert!(
+            requests[0][0].contains(r#""product_ids":["BTC-USD"]"#),
+            "{}",
+            requests[0][0]
+        );
+
+        let trades = Table::read(&directory, "market_trades")?;
+        assert_eq!(
+            vec!["time", "product_id", "trade_id", "side", "price", "size"],
+            trades.columns
+        );
+        assert_eq!(vec!["101", "102", "103"], trades.strings("trade_id")?);
+        let level2 = Table::read(&directory, "level2")?;
+        assert_eq!(vec!["bid", "offer"], level2.strings("side")?);
+
+        let events = Table::read(&directory, "_events")?;
+        let kinds = events.strings("kind")?;
+        let gap = kinds.iter().position(|kind| kind == "gap").context("no gap event")?;
+        assert_eq!("expected 4 received 5", events.strings("detail")?[gap]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mexc_trades() -> Result<()> {
+        let exchange = MockExchange::start(vec![vec![
+            Step::text(r#"{"id":0,"code":0,"msg":"spot@public.deals.v3.api@BTCUSDT"}"#),
+            Step::text(
+                r#"{"c":"spot@public.deals.v3.api@BTCUSDT","d":{"deals":[{"S":1,"p":"37000.10","t":1700000000000,"v":"0.5"},{"S":2,"p":"37000.00","t":1700000000100,"v":"0.2"}],"e":"spot@public.deals.v3.api"},"s":"BTCUSDT","t":1700000000100}"#,
+            ),
+            Step::text(r#"{"id":0,"code":0,"msg":"PONG"}"#),
+            Step::text(
+                r#"{"id":0,"code":0,"msg":"Not Subscribed successfully! [spot@public.deals.v3.api@XYZUSDT].  Reason： Blocked! "}"#,
+            ),
+            Step::Close,
+        ]])
+        .await?;
+        let args = ["--symbols", "BTCUSDT,XYZUSDT"];
+        let directory = exchange.record("mexc", &TMP_FOLDER.join("mexc_trades"), &args).await?;
+
+        let requests = exchange.requests().await?;
+        assert!(
+            requests[0][0]
+                .contains(r#""params":["spot@public.deals.v3.api@BTCUSDT","spot@public.deals.v3.api@XYZUSDT"]"#),
+            "{}",
+            requests[0][0]
+        );
+
+        let trades = Table::read(&directory, "trades")?;
+        assert_eq!(
+            vec!["symbol", "instrument", "time", "price", "size", "side"],
+            trades.columns
+        );
+        assert_eq!(vec!["buy", "sell"], trades.strings("side")?);
+        assert_eq!(vec!["BTC-USDT", "BTC-USDT"], trades.strings("instrument")?);
+        let kinds = Table::read(&directory, "_events")?.strings("kind")?;
+        assert!(kinds.contains(&"error".to_owned()), "{kinds:?}");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_table_route() -> Result<()> {
+        let exchange = MockExchange::start(vec![vec![
+            Step::text(r#"{"id":0,"code":0,"msg":"spot@public.deals.v3.api@BTCUSDT"}"#),
+            Step::text(
+                r#"{"c":"spot@public.deals.v3.api@BTCUSDT","d":{"deals":[{"S":1,"p":"37000.10","t":1700000000000,"v":"0.5"}],"e":"spot@public.deals.v3.api"},"s":"BTCUSDT","t":1700000000000}"#,
+            ),
+            Step::Close,
+        ]])
+        .await?;
+        let root = TMP_FOLDER.join("table_route");
+        let args = [
+            "--symbols",
+            "BTCUSDT",
+            "--table-route",
+            "lake/{venue}_{channel}_{date}",
+            "--table-formats",
+            "trades=jsonl",
+        ];
+        let directory = exchange.record("mexc", &root, &args).await?;
+
+        // the format of the channel applies whatever the date
+        let tables = fs::read_dir(root.join("lake"))?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(1, tables.len(), "{tables:?}");
+        let date = tables[0].strip_prefix("mexc_trades_").context("trades not routed")?;
+        let digits = date.split('-').map(str::len).collect::<Vec<_>>();
+        assert_eq!(vec![4, 2, 2], digits, "{date}");
+        let lines = fs::read_to_string(root.join("lake").join(&tables[0]).join("000000000.jsonl"))?;
+        assert_eq!(1, lines.lines().count());
+
+        // the recorder tables stay beside the manifest
+        assert!(!Table::read(&directory, "_events")?.rows.is_empty());
+        assert!(directory.join("_manifest.json").exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_column_stats() -> Result<()> {
+        let exchange = MockExchange::start(vec![vec![
+            Step::text(r#"{"id":0,"code":0,"msg":"spot@public.deals.v3.api@BTCUSDT"}"#),
+            Step::text(
+                r#"{"c":"spot@public.deals.v3.api@BTCUSDT","d":{"deals":[{"S":1,"p":"37000.10","t":1700000000000,"v":"0.5"},{"S":2,"p":"37001.20","t":1700000000001,"v":"1.5"}],"e":"spot@public.deals.v3.api"},"s":"BTCUSDT","t":1700000000001}"#,
+            ),
+            Step::Close,
+        ]])
+        .await?;
+        let root = TMP_FOLDER.join("column_stats");
+        let directory = exchange
+            .record("mexc", &root, &["--symbols", "BTCUSDT", "--column-stats"])
+            .await?;
+
+        let stats = Table::read(&directory, "_stats")?;
+        let tables = stats.strings("table")?;
+        // the recorder tables are not measured
+        assert!(tables.iter().all(|table| table == "mexc/trades"), "{tables:?}");
+        let columns = stats.strings("column")?;
+        let price = columns
+            .iter()
+            .position(|column| column == "price")
+            .context("price not measured")?;
+        let index = |name: &str| stats.columns.iter().position(|column| column == name).unwrap();
+        let row = &stats.rows[price];
+        assert_eq!(2, row.get_long(index("rows"))?);
+        assert_eq!(2, row.get_long(index("distinct"))?);
+        assert_eq!(37000.1, row.get_double(index("min"))?);
+        assert_eq!(37001.2, row.get_double(index("max"))?);
+        let instrument = columns.iter().position(|column| column == "instrument").unwrap();
+        assert_eq!(0, stats.rows[instrument].get_long(index("nulls"))?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate() -> Result<()> {
+        let exchange = MockExchange::start(vec![vec![
+            Step::text(r#"{"id":0,"code":0,"msg":"spot@public.deals.v3.api@BTCUSDT"}"#),
+            Step::text(
+                r#"{"c":"spot@public.deals.v3.api@BTCUSDT","d":{"deals":[{"S":1,"p":"37000.10","t":1700000000000,"v":"0.5"},{"S":2,"p":"37001.20","t":1700000000001,"v":"0"}],"e":"spot@public.deals.v3.api"},"s":"BTCUSDT","t":1700000000001}"#,
+            ),
+            Step::Close,
+        ]])
+        .await?;
+        let root = TMP_FOLDER.join("validate");
+        let args = [
+            "--symbols",
+            "BTCUSDT",
+            "--validate",
+            "trades=price>0,trades=size>0,*=time~100000h",
+            "--persist-violations",
+            "--reject-violations",
+        ];
+        let directory = exchange.record("mexc", &root, &args).await?;
+
+        // the trade of zero size is rejected
+        let trades = Table::read(&directory, "trades")?;
+        assert_eq!(1, trades.rows.len());
+        let price = trades.columns.iter().position(|column| column == "price").unwrap();
+        assert_eq!(37000.1, trades.rows[0].get_double(price)?);
+        let violations = Table::read(&directory, "_violations")?;
+        assert_eq!(vec!["trades=size>0"], violations.strings("rule")?);
+        assert_eq!(vec!["trades"], violations.strings("table")?);
+        assert_eq!(vec!["BTCUSDT"], violations.strings("symbol")?);
+        assert_eq!(vec!["0.0"], violations.strings("value")?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_late_table() -> Result<()> {
+        let deal = |time: u128| {
+            Step::Text(format!(
+                r#"{{"c":"spot@public.deals.v3.api@BTCUSDT","d":{{"deals":[{{"S":1,"p":"37000.10","t":{time},"v":"0.5"}}],"e":"spot@public.deals.v3.api"}},"s":"BTCUSDT","t":{time}}}"#
+            ))
+        };
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_millis();
+        let exchange = MockExchange::start(vec![vec![
+            Step::text(r#"{"id":0,"code":0,"msg":"spot@public.deals.v3.api@BTCUSDT"}"#),
+            deal(now),
+            // days after the lateness window of its date
+            deal(1_700_000_000_000),
+            Step::Close,
+        ]])
+        .await?;
+        let root = TMP_FOLDER.join("late_table");
+        let args = [
+            "--symbols",
+            "BTCUSDT",
+            "--table-route",
+            "{venue}/{channel}/{date}",
+            "--late-data",
+            "late-table",
+        ];
+        let directory = exchange.record("mexc", &root, &args).await?;
+
+        let dates = |table: &str| -> Result<Vec<String>> {
+            let mut dates = fs::read_dir(directory.join(table))?
+                .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                .collect::<Result<Vec<_>>>()?;
+            dates.sort();
+            Ok(dates)
+        };
+        let today = dates("trades")?;
+        assert_eq!(1, today.len(), "{today:?}");
+        assert_eq!(today, dates("trades_late")?);
+        assert_eq!(1, Table::read(&directory.join("trades"), &today[0])?.rows.len());
+        let late = Table::read(&directory.join("trades_late"), &today[0])?;
+        assert_eq!(vec!["BTCUSDT"], late.strings("symbol")?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_gate_trades() -> Result<()> {
+        let exchange = MockExchange::start(vec![vec![
+            Step::text(
+                r#"{"time":1700000000,"time_ms":1700000000001,"channel":"spot.trades","event":"subscribe","error":null,"result":{"status":"success"}}"#,
+            ),
+            Step::text(
+                r#"{"time":1700000000,"time_ms":1700000000231,"channel":"spot.trades","event":"update","result":{"id":309143071,"create_time":1700000000,"create_time_ms":"1700000000213.4578","side":"sell","currency_pair":"BTC_USDT","amount":"0.25","price":"37000.5","range":"2390902-2390902"}}"#,
+            ),
+            Step::text(
+                r#"{"time":1700000001,"time_ms":1700000001000,"channel":"spot.pong","event":"","error":null,"result":null}"#,
+            ),
+            Step::text(
+                r#"{"time":1700000001,"time_ms":1700000001001,"channel":"spot.trades","event":"subscribe","error":{"code":2,"message":"unknown currency pair XYZ_USDT"},"result":null}"#,
+            ),
+            Step::Close,
+        ]])
+        .await?;
+        let args = ["--symbols", "BTC_USDT,XYZ_USDT"];
+        let directory = exchange.record("gate", &TMP_FOLDER.join("gate_trades"), &args).await?;
+
+        let requests = exchange.requests().await?;
+        assert!(
+            requests[0][0].contains(r#""payload":["BTC_USDT","XYZ_USDT"]"#),
+            "{}",
+            requests[0][0]
+        );
+
+        let trades = Table::read(&directory, "trades")?;
+        assert_eq!(vec!["BTC_USDT"], trades.strings("symbol")?);
+        assert_eq!(vec!["sell"], trades.strings("side")?);
+        let kinds = Table::read(&directory, "_events")?.strings("kind")?;
+        assert!(kinds.contains(&"error".to_owned()), "{kinds:?}");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upbit_trades() -> Result<()> {
+        let trade = |price: &str, side: &str| {
+            Step::Binary(format!(
+                r#"{{"type":"trade","code":"KRW-BTC","timestamp":1700000000200,"trade_date":"2023-11-14","trade_time":"22:13:20","trade_timestamp":1700000000177,"trade_price":{price},"trade_volume":0.00015,"ask_bid":"{side}","prev_closing_price":50000000,"change":"RISE","change_price":70000,"sequential_id":1700000000177000,"stream_type":"REALTIME"}}"#
+            ).into_bytes())
+        };
+        let exchange = MockExchange::start(vec![vec![
+            trade("50070000", "BID"),
+            Step::Binary(br#"{"status":"UP"}"#.to_vec()),
+            trade("50069000", "ASK"),
+            Step::Binary(br#"{"error":{"name":"INVALID_PARAM","message":"invalid codes"}}"#.to_vec()),
+            Step::Close,
+        ]])
+        .await?;
+        let directory = exchange
+            .record("upbit", &TMP_FOLDER.join("upbit_trades"), &["--symbols", "KRW-BTC"])
+            .await?;
+
+        let requests = exchange.requests().await?;
+        let request: serde_json::Value = serde_json::from_str(&requests[0][0])?;
+        assert_eq!(r#"{"codes":["KRW-BTC"],"type":"trade"}"#, request[1].to_string());
+
+        let trades = Table::read(&directory, "trades")?;
+        assert_eq!(vec!["buy", "sell"], trades.strings("side")?);
+        assert_eq!(vec!["BTC-KRW", "BTC-KRW"], trades.strings("instrument")?);
+        let kinds = Table::read(&directory, "_events")?.strings("kind")?;
+        assert!(kinds.contains(&"error".to_owned()), "{kinds:?}");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bithumb_trades() -> Result<()> {
+        let exchange = MockExchange::start(vec![vec![
+            Step::text(r#"{"status":"0000","resmsg":"Connected Successfully"}"#),
+            Step::text(r#"{"status":"0000","resmsg":"Filter Registered Successfully"}"#),
+            Step::text(
+                r#"{"type":"transaction","content":{"list":[{"symbol":"BTC_KRW","buySellGb":"1","contPrice":"50070000","contQty":"0.01","contAmt":"500700.00","contDtm":"2023-11-15 07:13:20.177000","updn":"dn"},{"symbol":"BTC_KRW","buySellGb":"2","contPrice":"50071000","contQty":"0.02","contAmt":"1001420.00","contDtm":"2023-11-15 07:13:20.300000","updn":"up"}]}}"#,
+            ),
+            Step::text(r#"{"status":"5100","resmsg":"Invalid Filter Syntax"}"#),
+            Step::Close,
+        ]])
+        .await?;
+        let directory = exchange
+            .record("bithumb", &TMP_FOLDER.join("bithumb_trades"), &["--symbols", "BTC_KRW"])
+            .await?;
+
+        let requests = exchange.requests().await?;
+        assert_eq!(r#"{"symbols":["BTC_KRW"],"type":"transaction"}"#, requests[0][0]);
+
+        let trades = Table::read(&directory, "trades")?;
+        assert_eq!(vec!["sell", "buy"], trades.strings("side")?);
+        let time = trades
+            .columns
+            .iter()
+            .position(|column| column == "time")
+            .context("no time")?;
+        // 07:13:20.177 in Korea is 22:13:20.177 UTC the day before
+        assert_eq!(1_700_000_000_177_000_000, trades.rows[0].get_long(time)?);
+        let kinds = Table::read(&directory, "_events")?.strings("kind")?;
+        assert!(kinds.contains(&"error".to_owned()), "{kinds:?}");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_polygon_stocks() -> Result<()> {
+        let exchange = MockExchange::start(vec![vec![
+            Step::text(r#"[{"ev":"status","status":"auth_success","message":"authenticated"}]"#),
+            Step::text(r#"[{"ev":"status","status":"success","message":"subscribed to: T.AAPL"}]"#),
+            Step::text(
+                r#"[{"ev":"T","sym":"AAPL","x":4,"i":"52983525029461","z":3,"p":189.25,"s":100,"c":[12,37],"t":1700000000123,"q":1063},{"ev":"Q","sym":"AAPL","bx":11,"bp":189.24,"bs":3,"ax":12,"ap":189.26,"as":5,"c":1,"t":1700000000150,"q":1064,"z":3},{"ev":"T","sym":"AAPL"}]"#,
+            ),
+            Step::Close,
+        ]])
+        .await?;
+        let args = ["--symbols", "aapl", "--polygon-api-key", "test-key"];
+        let directory = exchange
+            .record("polygon", &TMP_FOLDER.join("polygon_stocks"), &args)
+            .await?;
+
+        // authenticated before subscribing, with the tickers upper cased
+        let requests = exchange.requests().await?;
+        assert_eq!(r#"{"action":"auth","params":"test-key"}"#, requests[0][0]);
+        assert_eq!(r#"{"action":"subscribe","params":"T.AAPL,Q.AAPL"}"#, requests[0][1]);
+
+        let trades = Table::read(&directory, "stock_trades")?;
+        assert_eq!(
+            vec![
+                "symbol",
+                "instrument",
+                "time",
+                "trade_id",
+                "exchange",
+                "tape",
+                "price",
+                "size",
+                "sequence"
+            ],
+            trades.columns
+        );
+        assert_eq!(vec!["52983525029461"], trades.strings("trade_id")?);
+        assert_eq!(vec!["AAPL"], trades.strings("instrument")?);
+        let quotes = Table::read(&directory, "stock_quotes")?;
+        assert_eq!(1, quotes.rows.len());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_binance_depth_sync() -> Result<()> {
+        let diff = |first: u64, last: u64, previous: u64| {
+            Step::Text(format!(
+                r#"{{"e":"depthUpdate","E":1700000000000,"T":1700000000000,"s":"BTCUSDT","U":{first},"u":{last},"pu":{previous},"b":[["37000.0","1.5"]],"a":[["37001.0","0"]]}}"#
+            ))
+        };
+        let snapshot = |last_update_id: u64| {
+            format!(
+                r#"{{"lastUpdateId":{last_update_id},"E":1700000000000,"T":1700000000000,"bids":[["37000.0","1.0"],["36999.0","2.0"]],"asks":[["37001.0","0.5"]]}}"#
+            )
+        };
+        let (first, second) = (snapshot(103), snapshot(110));
+        let rest = MockRest::start(vec![("/fapi/v1/depth", vec![&first, &second])]).await?;
+        let wait = Step::Wait(Duration::from_millis(500));
+        let exchange = MockExchange::start(vec![vec![
+            // buffered until the first snapshot, the first diff is older than the snapshot and dropped
+            diff(100, 102, 99),
+            diff(103, 104, 102),
+            wait.clone(),
+            diff(105, 107, 104),
+            // broken chain, synchronized again from the second snapshot
+            diff(110, 111, 109),
+            wait,
+            diff(112, 113, 111),
+            Step::Close,
+        ]])
+        .await?;
+        let args = ["--symbols", "BTCUSDT", "--binance-depth", "--rest-url", &rest.url()];
+        let directory = exchange
+            .record("binance-futures", &TMP_FOLDER.join("binance_depth"), &args)
+            .await?;
+
+        let requests = exchange.requests().await?;
+        assert!(requests[0][0].contains("btcusdt@depth@100ms"), "{}", requests[0][0]);
+        let snapshots = rest
+            .paths()
+            .into_iter()
+            .filter(|path| path.starts_with("/fapi/v1/depth"))
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["/fapi/v1/depth?symbol=BTCUSDT&limit=1000"; 2], snapshots);
+
+        // every diff received, both snapshots applied
+        let diffs = Table::read(&directory, "depth_diffs")?;
+        assert_eq!(10, diffs.rows.len());
+        let snapshots = Table::read(&directory, "depth_snapshots")?;
+        assert_eq!(
+            vec![
+                "symbol",
+                "time",
+                "first_update_id",
+                "last_update_id",
+                "previous_update_id",
+                "side",
+                "price",
+                "size"
+            ],
+            snapshots.columns
+        );
+        assert_eq!(
+            vec!["bid", "bid", "ask", "bid", "bid", "ask"],
+            snapshots.strings("side")?
+        );
+
+        let events = Table::read(&directory, "_events")?;
+        let kinds = events.strings("kind")?;
+        let gap = kinds.iter().position(|kind| kind == "gap").context("no gap event")?;
+        assert_eq!(
+            "expected previous update 107 received 109",
+            events.strings("detail")?[gap]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bitfinex_dropped_connection() -> Result<()> {
+        let exchange = MockExchange::start(vec![vec![
+            Step::text(r#"{"event":"info","version":2}"#),
+            Step::text(r#"{"event":"subscribed","channel":"trades","chanId":17,"symbol":"tBTCUSD","pair":"BTCUSD"}"#),
+            Step::text(r#"{"event":"subscribed","channel":"ticker","chanId":18,"symbol":"tBTCUSD","pair":"BTCUSD"}"#),
+            Step::text(r#"[17,[[1,1700000000000,0.5,37000],[2,1700000000100,-0.25,37001]]]"#),
+            Step::text(r#"[17,"hb"]"#),
+            Step::text(r#"[17,"te",[3,1700000000200,1.5,36999]]"#),
+            Step::text(r#"[18,[37000,1.2,37001,0.8,-50,-0.0013,37000.5,1234.5,37500,36500]]"#),
+            // unknown channel and malformed trade, both skipped
+            Step::text(r#"[99,"te",[4,1700000000300,1,37000]]"#),
+            Step::text(r#"[17,"te",[5,"yesterday",1,37000]]"#),
+            Step::Ping,
+            Step::Disconnect,
+        ]])
+        .await?;
+        let directory = exchange
+            .record(
+                "bitfinex",
+                &TMP_FOLDER.join("bitfinex_dropped"),
+                &["--symbols", "tBTCUSD"],
+            )
+            .await?;
+
+        // trades, ticker and candles subscribed for the symbol
+        let requests = exchange.requests().await?;
+        assert_eq!(3, requests[0].len(), "{:?}", requests[0]);
+
+        let trades = Table::read(&directory, "trades")?;
+        assert_eq!(
+            vec!["symbol", "trade_id", "time", "size", "price", "side"],
+            trades.columns
+        );
+        assert_eq!(vec!["buy", "sell", "buy"], trades.strings("side")?);
+        let ticker = Table::read(&directory, "ticker")?;
+        assert_eq!(1, ticker.rows.len());
+        assert_eq!(12, ticker.columns.len());
+
+        let events = Table::read(&directory, "_events")?;
+        let kinds = events.strings("kind")?;
+        assert_eq!(vec!["connect", "disconnect"], kinds);
+        let detail = &events.strings("detail")?[1];
+        assert!(detail.contains("closing handshake"), "{detail}");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bitfinex_derived() -> Result<()> {
+        let exchange = MockExchange::start(vec![vec![
+            Step::text(r#"{"event":"info","version":2}"#),
+            Step::text(r#"{"event":"subscribed","channel":"ticker","chanId":18,"symbol":"tBTCUSD","pair":"BTCUSD"}"#),
+            Step::text(r#"[18,[37000,1.2,37001,0.8,-50,-0.0013,37000.5,1234.5,37500,36500]]"#),
+            Step::Close,
+        ]])
+        .await?;
+        let directory = exchange
+            .record(
+                "bitfinex",
+                &TMP_FOLDER.join("bitfinex_derived"),
+                &["--symbols", "tBTCUSD", "--derive", "bitfinex/ticker,okx/tickers"],
+            )
+            .await?;
+
+        // the ticker is still recorded as is
+        assert_eq!(1, Table::read(&directory, "ticker")?.rows.len());
+        let derived = Table::read(&directory, "derived")?;
+        assert_eq!(
+            vec![
+                "symbol",
+                "instrument",
+                "source",
+                "time",
+                "mid",
+                "spread_bps",
+                "microprice",
+                "imbalance"
+            ],
+            derived.columns
+        );
+        assert_eq!(vec!["ticker"], derived.strings("source")?);
+        assert_eq!(vec!["BTC-USD"], derived.strings("instrument")?);
+        let row = &derived.rows[0];
+        assert_eq!(37000.5, row.get_double(4)?);
+        assert!((row.get_double(5)? - 0.27027).abs() < 1e-5);
+        assert_eq!(37000.6, row.get_double(6)?);
+        assert_eq!(0.2, row.get_double(7)?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_auto_inference() -> Result<()> {
+        let exchange = MockExchange::start(vec![vec![
+            Step::text(r#"{"event":"subscribed"}"#),
+            Step::text(r#"{"channel":"ticks","data":[{"px":1,"qty":2,"meta":{"src":"a"}},{"px":1.5,"qty":3}]}"#),
+            Step::text(r#"{"channel":"ticks","data":{"px":2,"qty":5,"meta":{"src":"b"},"extra":true,"tags":[1,2]}}"#),
+            // after the inference: the unseen key is dropped and the string in the float column is null
+            Step::text(r#"{"channel":"ticks","data":{"px":"bad","qty":4,"unseen":1}}"#),
+            // not enough messages to infer the status table
+            Step::text(r#"{"channel":"status","data":{"ok":true}}"#),
+            Step::Close,
+        ]])
+        .await?;
+        let directory = exchange
+            .record(
+                "auto",
+                &TMP_FOLDER.join("auto_inference"),
+                &[
+                    "--auto-url",
+                    "ws://unused",
+                    "--auto-subscribe",
+                    r#"{"op":"subscribe","args":{symbols}}"#,
+                    "--auto-data",
+                    "/data",
+                    "--auto-infer",
+                    "3",
+                    "--symbols",
+                    "BTC,ETH",
+                ],
+            )
+            .await?;
+
+        let requests = exchange.requests().await?;
+        assert_eq!(vec![r#"{"op":"subscribe","args":["BTC","ETH"]}"#], requests[0]);
+
+        let ticks = Table::read(&directory, "ticks")?;
+        // columns in the order first seen, the keys of an object in alphabetical order
+        assert_eq!(vec!["meta_src", "px", "qty", "extra", "tags"], ticks.columns);
+        assert_eq!(4, ticks.rows.len());
+        // px is widened to a float column
+        let px = ticks.rows.iter().map(|row| row.get_double(1).ok()).collect::<Vec<_>>();
+        assert_eq!(vec![Some(1.0), Some(1.5), Some(2.0), None], px);
+        let qty = ticks
+            .rows
+            .iter()
+            .map(|row| row.get_long(2))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(vec![2, 3, 5, 4], qty);
+        assert_eq!("[1,2]", ticks.rows[2].get_string(4)?);
+        assert!(ticks.rows[2].get_bool(3)?);
+        assert!(!directory.join("status").exists());
+
+        Ok(())
+    }
+
+    /// Venue of an application embedding the recorder, reporting every message as a gap event.
+    struct Embedded {
+        url: String,
+        clock: SharedClock,
+    }
+
+    impl CustomVenue for Embedded {
+        fn name(&self) -> &'static str {
+            "embedded"
+        }
+
+        fn url(&self) -> String {
+            self.url.clone()
+        }
+
+        fn subscribe(&self, symbols: &[String]) -> Vec<Message> {
+            vec![Message::Text(format!("subscribe {}", symbols.join(",")))]
+        }
+
+        fn handle(&mut self, message: Message) -> Vec<Record> {
+            match message {
+                Message::Text(text) => vec![Event::new(self.clock.now(), EventKind::Gap, "embedded", text).record()],
+                _ => vec![],
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_venue() -> Result<()> {
+        let exchange = MockExchange::start(vec![vec![Step::text("missed 3"), Step::Close]]).await?;
+        let directory = TMP_FOLDER.join("custom_venue");
+        let _ = fs::remove_dir_all(&directory);
+        let mut args = Args::parse_from([
+            "dixit",
+            "--venue",
+            "coinbase",
+            "--no-reference",
+            "--symbols",
+            "A,B",
+            "--directory",
+            directory.to_str().unwrap(),
+        ]);
+        // the application embedding the recorder passes its clock with the arguments
+        let start = "2024-03-01T12:00:00Z".parse::<DateTime<Utc>>()?;
+        let clock: SharedClock = Arc::new(ManualClock::new(start));
+        args.clock = clock.clone();
+        let venue = Embedded {
+            url: exchange.url(),
+            clock,
+        };
+        tokio::time::timeout(Duration::from_secs(30), run_venue(args, venue)).await??;
+
+        let requests = exchange.requests().await?;
+        assert_eq!(vec!["subscribe A,B"], requests[0]);
+        // recorded under the directory of the custom venue, whatever the venue of the arguments
+        let events = Table::read(&directory.join("embedded"), "_events")?;
+        assert_eq!(vec!["connect", "gap", "disconnect"], events.strings("kind")?);
+        assert_eq!("missed 3", events.strings("detail")?[1]);
+        let time = events
+            .columns
+            .iter()
+            .position(|column| column == "time")
+            .context("no time")?;
+        for row in &events.rows {
+            assert_eq!(start.timestamp_nanos_opt(), Some(row.get_long(time)?));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recorder_error() -> Result<()> {
+        use dixit_core::error::RecorderError;
+        use dixit_core::model::Severity;
+        use dixit_core::websocket::{self, ErrorPolicy, Subscriptions};
+
+        let exchange = MockExchange::start(vec![vec![Step::text("logged out"), Step::Close]]).await?;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let (_control, control_rx) = tokio::sync::mpsc::channel(1);
+        let subscribe = |symbols: &[String]| vec![Message::Text(format!("subscribe {}", symbols.join(",")))];
+        let subscriptions = Subscriptions::new(vec!["A".to_owned()], subscribe, |_: &[String]| vec![]);
+        let handle = |message: Message| {
+            vec![Record::error(
+                "embedded",
+                "session",
+                Severity::Fatal,
+                message.to_string(),
+                "session closed".to_owned(),
+            )]
+        };
+        let url = exchange.url();
+        let e = websocket::run(tx, &url, ErrorPolicy::default(), subscriptions, control_rx, handle)
+            .await
+            .unwrap_err();
+
+        // the fatal error of the venue aborts the connection, the venue error kept as its cause
+        let Some(RecorderError::Aborted { url: aborted, cause }) = e.downcast_ref::<RecorderError>() else {
+            panic!("not an abort: {e}");
+        };
+        assert_eq!(&url, aborted);
+        assert_eq!(Some(Severity::Fatal), cause.severity());
+        assert_eq!(
+            "[embedded] [session] Fatal logged out: session closed",
+            cause.to_string()
+        );
+        let mut errors = vec![];
+        while let Some(record) = rx.recv().await {
+            errors.extend(RecorderError::venue(&record));
+        }
+        assert_eq!(1, errors.len());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "okx")]
+    fn test_skip_log() {
+        use dixit_core::model::SkipReason;
+        use dixit_core::okx;
+        use dixit_core::skips::SkipLog;
+
+        let reason = |message| match okx::handle(message).as_slice() {
+            [Record::Skip { reason, .. }] => Some(*reason),
+            _ => None,
+        };
+        let ack = r#"{"event":"subscribe","arg":{"channel":"trades","instId":"BTC-USDT"},"connId":"a4d3ae55"}"#;
+        assert_eq!(Some(SkipReason::Unsubscribed), reason(Message::Text(ack.into())));
+        assert_eq!(Some(SkipReason::Parse), reason(Message::Text("{\"arg\":".into())));
+        assert_eq!(Some(SkipReason::NonText), reason(Message::Ping(Default::default())));
+
+        // the first two of each reason, then every third
+        let mut skips = SkipLog::new(2, 3);
+        let logged = (0..10)
+            .map(|_| skips.skip(SkipReason::Unsubscribed, ack))
+            .collect::<Vec<_>>();
+        let expected = [true, true, false, false, true, false, false, true, false, false];
+        assert_eq!(expected.to_vec(), logged);
+        assert!(skips.skip(SkipReason::Parse, "{"));
+        assert_eq!(
+            (10, 1, 0),
+            (
+                skips.count(SkipReason::Unsubscribed),
+                skips.count(SkipReason::Parse),
+                skips.count(SkipReason::NonText)
+            )
+        );
+
+        // only the first ones without sampling
+        let mut skips = SkipLog::new(1, 0);
+        assert_eq!(
+            vec![true, false, false],
+            (0..3).map(|_| skips.skip(SkipReason::Other, "")).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_bench_feed() -> Result<()> {
+        let root = TMP_FOLDER.join("bench_feed");
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_dixit"))
+            .args(["bench-feed", "--rate", "2000", "--duration", "1s", "--symbols", "3"])
+            .arg("--directory")
+            .arg(&root)
+            .output()?;
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let report = String::from_utf8(output.stdout)?;
+        assert!(report.contains("msgs/s sustained, 2000 msgs/s targeted"), "{report}");
+        assert!(report.contains("append latency: p50"), "{report}");
+        assert!(report.contains("flush stalls:"), "{report}");
+
+        let directory = root.join("bench");
+        let trades = Table::read(&directory, "trades")?;
+        let book = Table::read(&directory, "book")?;
+        assert_eq!(vec!["symbol", "time", "side", "price", "size"], book.columns);
+        // about a second of messages, mostly book updates
+        let messages = trades.rows.len() + book.rows.len();
+        assert!((1_500..=2_000).contains(&messages), "{messages}");
+        assert!(book.rows.len() > trades.rows.len());
+        assert!(book.strings("symbol")?.iter().all(|symbol| symbol.starts_with("SYM")));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+//! Checks the framing of the FIX messages and the session layer of the FIX source, the acceptor being played by the
+//! test.
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::{ensure, Context, Result};
+    use chrono::{DateTime, TimeZone, Utc};
+    use dixit_core::clock;
+    use dixit_core::model::{Record, VenueData};
+    use dixit_core::source::fix::{self, FixConfig, FixMessage};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
+    use tokio::time;
+
+    fn sending_time() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap()
+    }
+
+    fn refresh() -> FixMessage {
+        FixMessage::new("W")
+            .with(55, "BTC-USD")
+            .with(268, 1)
+            .with(269, 0)
+            .with(270, "64000.5")
+            .with(271, "0.25")
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let mut buffer = b"garbage".to_vec();
+        buffer.extend(refresh().encode(7, "ACCEPTOR", "DIXIT", sending_time()));
+        buffer.extend(FixMessage::new("0").encode(8, "ACCEPTOR", "DIXIT", sending_time()));
+
+        // the header fields are added, the framing fields are dropped
+        let expected = FixMessage::new("W")
+            .with(49, "ACCEPTOR")
+            .with(56, "DIXIT")
+            .with(34, 7)
+            .with(52, "20240301-10:00:00.000")
+            .with(55, "BTC-USD")
+            .with(268, 1)
+            .with(269, 0)
+            .with(270, "64000.5")
+            .with(271, "0.25");
+        assert_eq!(Some(expected), FixMessage::decode(&mut buffer)?);
+        // the next message is left in the buffer
+        let heartbeat = FixMessage::decode(&mut buffer)?.context("heartbeat not decoded")?;
+        assert_eq!(("0", Some("8")), (heartbeat.msg_type(), heartbeat.get(34)));
+        assert!(buffer.is_empty());
+        assert_eq!(None, FixMessage::decode(&mut buffer)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_checksum() -> Result<()> {
+        let encoded = refresh().encode(7, "ACCEPTOR", "DIXIT", sending_time());
+        let body = String::from_utf8(encoded)?.replace("BTC-USD", "BTC-USE");
+        let mut buffer = body.into_bytes();
+        buffer.extend(FixMessage::new("0").encode(8, "ACCEPTOR", "DIXIT", sending_time()));
+
+        let error = FixMessage::decode(&mut buffer).expect_err("corrupted message decoded");
+        assert!(error.to_string().starts_with("invalid checksum"), "{error}");
+        // the corrupted message is dropped, not the next one
+        let heartbeat = FixMessage::decode(&mut buffer)?.context("heartbeat not decoded")?;
+        assert_eq!("0", heartbeat.msg_type());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_partial_buffer() -> Result<()> {
+        let encoded = refresh().encode(7, "ACCEPTOR", "DIXIT", sending_time());
+        // whatever the split of the reads, including right after the first byte of the message
+        for split in 1..encoded.len() {
+            let mut buffer = b"garbage".to_vec();
+            buffer.extend_from_slice(&encoded[..split]);
+            assert_eq!(None, FixMessage::decode(&mut buffer)?, "split at {split}");
+            buffer.extend_from_slice(&encoded[split..]);
+            let message = FixMessage::decode(&mut buffer)?.with_context(|| format!("split at {split}"))?;
+            assert_eq!(Some("64000.5"), message.get(270));
+            assert!(buffer.is_empty());
+        }
+
+        Ok(())
+    }
+
+    /// Reads the next message sent by the source.
+    async fn read(stream: &mut TcpStream, buffer: &mut Vec<u8>) -> Result<FixMessage> {
+        let mut read = [0u8; 4096];
+        loop {
+            if let Some(message) = FixMessage::decode(buffer)? {
+                return Ok(message);
+            }
+            let n = time::timeout(Duration::from_secs(5), stream.read(&mut read)).await??;
+            ensure!(n > 0, "connection closed by the source");
+            buffer.extend_from_slice(&read[..n]);
+        }
+    }
+
+    async fn send(stream: &mut TcpStream, message: FixMessage, seq_num: u64) -> Result<()> {
+        let bytes = message.encode(seq_num, "ACCEPTOR", "DIXIT", sending_time());
+        stream.write_all(&bytes).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_gap_resend_request() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let config = FixConfig {
+            address: listener.local_addr()?.to_string(),
+            sender_comp_id: "DIXIT".to_owned(),
+            target_comp_id: "ACCEPTOR".to_owned(),
+            heartbeat: Duration::from_secs(30),
+            resume: None,
+            clock: clock::system(),
+        };
+        let (tx, mut rx) = mpsc::channel(16);
+        let source = tokio::spawn(fix::run(tx, config, vec!["BTC-USD".to_owned()], None));
+        let (mut stream, _) = listener.accept().await?;
+        let mut buffer = vec![];
+
+        let logon = read(&mut stream, &mut buffer).await?;
+        assert_eq!(
+            ("A", Some("1"), Some("Y")),
+            (logon.msg_type(), logon.get(34), logon.get(141))
+        );
+        send(&mut stream, FixMessage::new("A"), 1).await?;
+        let request = read(&mut stream, &mut buffer).await?;
+        assert_eq!(("V", Some("BTC-USD")), (request.msg_type(), request.get(55)));
+
+        // 2 and 3 are lost, the source asks for them
+        send(&mut stream, FixMessage::new("0"), 4).await?;
+        let resend = read(&mut stream, &mut buffer).await?;
+        assert_eq!(
+            ("2", Some("2"), Some("3")),
+            (resend.msg_type(), resend.get(7), resend.get(16))
+        );
+
+        // the resent messages are accepted, then the logout ends the session
+        send(&mut stream, refresh().with(43, "Y"), 2).await?;
+        send(&mut stream, FixMessage::new("0").with(43, "Y"), 3).await?;
+        send(&mut stream, FixMessage::new("5").with(58, "done"), 5).await?;
+        time::timeout(Duration::from_secs(5), source).await???;
+
+        let mut gaps = vec![];
+        let mut entries = 0;
+        while let Some(record) = rx.recv().await {
+            if let Record::Data { data, .. } = record {
+                match *data {
+                    VenueData::Event(event) if event.kind == "gap" => gaps.push(event.detail),
+                    VenueData::FixMarketData(_) => entries += 1,
+                    _ => {}
+                }
+            }
+        }
+        assert_eq!(vec!["expected 2 received 4".to_owned()], gaps);
+        assert_eq!(1, entries);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+//! Checks `DoGet` of the Arrow Flight server, including the tables whose files were written with different schemas.
+#![cfg(feature = "flight")]
+
+use std::env;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+static TMP_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| {
+    let mut path_buf = env::current_dir().unwrap();
+    path_buf.push("target");
+    path_buf.push("test");
+    path_buf.push("flight");
+    path_buf
+});
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::io::Cursor;
+    use std::net::SocketAddr;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use anyhow::{anyhow, Result};
+    use arrow::array::{ArrayRef, AsArray, Float32Array, Float64Array, RecordBatch, TimestampNanosecondArray};
+    use arrow::datatypes::{DataType, Float64Type};
+    use arrow::ipc::reader::StreamReader;
+    use dixit_core::flight::{self, FlightData, Ticket};
+    use parquet::arrow::ArrowWriter;
+    use tonic::codec::ProstCodec;
+    use tonic::codegen::http::uri::PathAndQuery;
+    use tonic::transport::Channel;
+    use tonic::Code;
+
+    use crate::TMP_FOLDER;
+
+    fn write(path: &Path, columns: Vec<(&str, ArrayRef)>) -> Result<()> {
+        fs::create_dir_all(path.parent().unwrap())?;
+        let batch = RecordBatch::try_from_iter(columns)?;
+        let mut writer = ArrowWriter::try_new(File::create(path)?, batch.schema(), None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        Ok(())
+    }
+
+    fn times(times: &[i64]) -> ArrayRef {
+        Arc::new(TimestampNanosecondArray::from(times.to_vec()))
+    }
+
+    /// Sends a ticket and rebuilds the IPC stream of the Flight messages received.
+    async fn do_get(address: SocketAddr, ticket: &str) -> Result<Vec<RecordBatch>, tonic::Status> {
+        let mut channel = None;
+        for _ in 0..50 {
+            match Channel::from_shared(format!("http://{address}"))
+                .unwrap()
+                .connect()
+                .await
+            {
+                Ok(connected) => {
+                    channel = Some(connected);
+                    break;
+                }
+                // the server is still starting
+                Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+            }
+        }
+        let mut grpc = tonic::client::Grpc::new(channel.expect("flight server not started"));
+        grpc.ready().await.unwrap();
+        let request = tonic::Request::new(Ticket {
+            ticket: ticket.as_bytes().to_vec(),
+        });
+        let path = PathAndQuery::from_static("/arrow.flight.protocol.FlightService/DoGet");
+        let codec = ProstCodec::<Ticket, FlightData>::default();
+        let mut messages = grpc.server_streaming(request, path, codec).await?.into_inner();
+
+        let mut stream = vec![];
+        while let Some(data) = messages.message().await? {
+            let padding = (8 - data.data_header.len() % 8) % 8;
+            stream.extend_from_slice(&[0xff; 4]);
+            stream.extend_from_slice(&((data.data_header.len() + padding) as i32).to_le_bytes());
+            stream.extend_from_slice(&data.data_header);
+            stream.extend(std::iter::repeat(0).take(padding));
+            stream.extend_from_slice(&data.data_body);
+        }
+        let reader = StreamReader::try_new(Cursor::new(stream), None).unwrap();
+        Ok(reader.collect::<Result<_, _>>().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_do_get() -> Result<()> {
+        let _ = fs::remove_dir_all(&*TMP_FOLDER);
+        let trades = TMP_FOLDER.join("okx/trades");
+        write(
+            &trades.join("000000000.parquet"),
+            vec![
+                ("time", times(&[1, 2])),
+                ("price", Arc::new(Float64Array::from(vec![1.5, 2.5]))),
+            ],
+        )?;
+        // the price recorded with a narrower type and an extra column
+        write(
+            &trades.join("000000001.parquet"),
+            vec![
+                ("time", times(&[3])),
+                ("price", Arc::new(Float32Array::from(vec![3.5]))),
+                ("size", Arc::new(Float64Array::from(vec![0.1]))),
+            ],
+        )?;
+        let funding = TMP_FOLDER.join("okx/funding");
+        write(
+            &funding.join("000000000.parquet"),
+            vec![
+                ("time", times(&[1])),
+                ("rate", Arc::new(Float64Array::from(vec![0.01]))),
+            ],
+        )?;
+        write(&funding.join("000000001.parquet"), vec![("time", times(&[2]))])?;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        drop(listener);
+        let server = tokio::spawn(flight::run(address, TMP_FOLDER.clone()));
+
+        let batches = do_get(address, r#"{"table": "okx/trades"}"#).await?;
+        let schema = batches[0].schema();
+        assert_eq!(
+            vec!["time", "price"],
+            schema.fields().iter().map(|f| f.name()).collect::<Vec<_>>()
+        );
+        let prices: Vec<f64> = batches
+            .iter()
+            .map(|batch| {
+                assert_eq!(&DataType::Float64, batch.column(1).data_type());
+                batch.column(1).as_primitive::<Float64Type>().values().to_vec()
+            })
+            .collect::<Vec<_>>()
+            .concat();
+        assert_eq!(vec![1.5, 2.5, 3.5], prices);
+
+        let ticket = r#"{"table": "okx/trades", "start": "1970-01-01T00:00:00.000000002Z"}"#;
+        let rows: usize = do_get(address, ticket).await?.iter().map(RecordBatch::num_rows).sum();
+        assert_eq!(2, rows);
+
+        let status = do_get(address, r#"{"table": "okx/funding"}"#)
+            .await
+            .err()
+            .ok_or(anyhow!("not rejected"))?;
+        assert_eq!(Code::FailedPrecondition, status.code());
+        assert!(status.message().contains("no column rate"), "{}", status.message());
+
+        server.abort();
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+//! Replays the frames captured from each venue, stored in `tests/fixtures/{venue}/frames.jsonl` (one frame per line),
+//! and checks every table recorded against its golden snapshot in `tests/fixtures/{venue}/golden/{table}.jsonl`.
+//!
+//! A change of the venue payloads or of the parsers shows as a diff of the snapshots, once reviewed the snapshots are
+//! rewritten with `DIXIT_UPDATE_GOLDEN=1 cargo test -p dixit --test golden`.
+
+use std::{env, path::PathBuf, sync::LazyLock};
+
+mod mock_exchange;
+
+static TMP_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| {
+    let mut path_buf = env::current_dir().unwrap();
+    path_buf.push("target");
+    path_buf.push("test");
+    path_buf.push("golden");
+    path_buf
+});
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+
+    use anyhow::{Context, Result};
+    use serde_json::Value;
+
+    use crate::mock_exchange::{MockExchange, Step};
+    use crate::TMP_FOLDER;
+
+    const UPDATE: &str = "DIXIT_UPDATE_GOLDEN";
+
+    /// Columns holding the receive time rather than a venue timestamp, masked in the snapshots.
+    const RECEIVE_TIME: &[(&str, &str, &str)] = &[("bitfinex", "ticker", "time")];
+
+    /// Records the frames of a venue as JSON lines, keyed by table, the tables of the recorder (`_events`) excluded.
+    async fn record(venue: &str, fixtures: &Path) -> Result<BTreeMap<String, String>> {
+        let frames = fs::read_to_string(fixtures.join("frames.jsonl"))?;
+        let mut steps = frames
+            .lines()
+            .filter(|frame| !frame.trim().is_empty())
+            .map(Step::text)
+            .collect::<Vec<_>>();
+        steps.push(Step::Close);
+
+        let exchange = MockExchange::start(vec![steps]).await?;
+        let directory = exchange
+            .record(venue, &TMP_FOLDER.join(venue), &["--format", "jsonl"])
+            .await?;
+        exchange.requests().await?;
+
+        let mut tables = BTreeMap::new();
+        for entry in fs::read_dir(&directory)? {
+            let path = entry?.path();
+            let table = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            if !path.is_dir() || table.starts_with('_') {
+                continue;
+            }
+            let mut files = fs::read_dir(&path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            files.retain(|file| file.extension().is_some_and(|extension| extension == "jsonl"));
+            files.sort();
+
+            let mut rows = String::new();
+            for file in files {
+                for row in fs::read_to_string(file)?.lines() {
+                    rows.push_str(&mask(venue, &table, row)?);
+                    rows.push('\n');
+                }
+            }
+            tables.insert(table, rows);
+        }
+        Ok(tables)
+    }
+
+    fn mask(venue: &str, table: &str, row: &str) -> Result<String> {
+        let columns = RECEIVE_TIME
+            .iter()
+            .filter(|(v, t, _)| *v == venue && *t == table)
+            .map(|(_, _, column)| *column)
+            .collect::<Vec<_>>();
+        if columns.is_empty() {
+            return Ok(row.to_owned());
+        }
+        let mut value = serde_json::from_str::<Value>(row)?;
+        for column in columns {
+            if let Some(field) = value.get_mut(column) {
+                *field = Value::from("<receive time>");
+            }
+        }
+        Ok(value.to_string())
+    }
+
+    fn golden(golden: &Path) -> Result<BTreeMap<String, String>> {
+        let mut tables = BTreeMap::new();
+        for entry in fs::read_dir(golden).with_context(|| format!("no snapshots, run with {UPDATE}=1"))? {
+            let path = entry?.path();
+            if let Some(table) = path
+                .file_stem()
+                .filter(|_| path.extension().is_some_and(|e| e == "jsonl"))
+            {
+                tables.insert(table.to_string_lossy().into_owned(), fs::read_to_string(&path)?);
+            }
+        }
+        Ok(tables)
+    }
+
+    async fn check(venue: &str) -> Result<()> {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(venue);
+        let recorded = record(venue, &fixtures).await?;
+        let snapshots = fixtures.join("golden");
+
+        if env::var_os(UPDATE).is_some() {
+            let _ = fs::remove_dir_all(&snapshots);
+            fs::create_dir_all(&snapshots)?;
+            for (table, rows) in recorded.iter() {
+                fs::write(snapshots.join(format!("{table}.jsonl")), rows)?;
+            }
+            return Ok(());
+        }
+
+        let expected = golden(&snapshots)?;
+        assert_eq!(
+            expected.keys().collect::<Vec<_>>(),
+            recorded.keys().collect::<Vec<_>>(),
+            "{venue} tables differ from the snapshots"
+        );
+        for (table, rows) in expected.iter() {
+            assert_eq!(
+                rows, &recorded[table],
+                "{venue}/{table} differs from its snapshot, rerun with {UPDATE}=1 if the change is expected"
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_coinbase() -> Result<()> {
+        check("coinbase").await
+    }
+
+    #[tokio::test]
+    async fn test_bitfinex() -> Result<()> {
+        check("bitfinex").await
+    }
+
+    // binance-futures is left out, it also polls its REST endpoints that the mock exchange does not serve
+
+    #[tokio::test]
+    async fn test_okx() -> Result<()> {
+        check("okx").await
+    }
+
+    #[tokio::test]
+    async fn test_bybit() -> Result<()> {
+        check("bybit").await
+    }
+
+    #[tokio::test]
+    async fn test_deribit() -> Result<()> {
+        check("deribit").await
+    }
+}
@@ -0,0 +1,287 @@
+//! Local websocket server replaying canned venue messages, so the recorder can be run end to end without a venue.
+//!
+//! ## Features
+//! - Serves one scripted session per connection, in order, so reconnections can be replayed as well.
+//! - Waits for the first request of the recorder (its subscription) before replaying a session.
+//! - Replays text and binary messages (malformed ones included), pings, closing handshakes and dropped connections,
+//!   reading the requests of the recorder until it closes the session.
+//! - Collects the requests received on each session, e.g. to check the subscriptions sent, and the headers of their
+//!   upgrade requests.
+//! - Runs the recorder against the mock exchange until every session is replayed.
+//! - Answers the REST requests of the recorder with canned bodies, e.g. the snapshots of a depth stream, collecting
+//!   the requests with their headers and bodies.
+
+// shared by several test crates, each using part of it
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::process::Command;
+use tokio::task::JoinHandle;
+use tokio::time;
+use tokio_tungstenite::tungstenite::handshake::server::{Request as Upgrade, Response};
+use tokio_tungstenite::{accept_hdr_async, tungstenite::Message, WebSocketStream};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const RECORD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Steps of a session, which ends once the recorder closes it (e.g. answering a [`Step::Close`] or reconnecting).
+#[derive(Debug, Clone)]
+pub enum Step {
+    Text(String),
+    /// binary frame, e.g. the JSON of the venues sending their messages as bytes
+    Binary(Vec<u8>),
+    Ping,
+    /// starts a closing handshake, the stream of the recorder ends
+    Close,
+    /// shuts the connection down without a closing handshake, the stream of the recorder fails
+    Disconnect,
+    /// pauses the session, e.g. while the recorder fetches a snapshot
+    Wait(Duration),
+}
+
+impl Step {
+    pub fn text(text: &str) -> Self {
+        Step::Text(text.to_owned())
+    }
+}
+
+pub struct MockExchange {
+    address: SocketAddr,
+    /// headers of the upgrade request of each session, their names lower cased
+    headers: Arc<Mutex<Vec<HashMap<String, String>>>>,
+    server: JoinHandle<Result<Vec<Vec<String>>>>,
+}
+
+impl MockExchange {
+    /// Listens on a local port and serves the sessions, ends once every session is replayed.
+    pub async fn start(sessions: Vec<Vec<Step>>) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let headers = Arc::new(Mutex::new(vec![]));
+        let upgrades = headers.clone();
+        let server = tokio::spawn(async move {
+            let mut requests = vec![];
+            for steps in sessions {
+                let (stream, _) = time::timeout(TIMEOUT, listener.accept()).await??;
+                // the error response is the one of the tungstenite callback
+                #[allow(clippy::result_large_err)]
+                let collect = |upgrade: &Upgrade, response: Response| {
+                    let headers = upgrade
+                        .headers()
+                        .iter()
+                        .map(|(name, value)| (name.to_string(), value.to_str().unwrap_or_default().to_owned()))
+                        .collect();
+                    upgrades.lock().expect("headers poisoned").push(headers);
+                    std::result::Result::Ok(response)
+                };
+                requests.push(replay(accept_hdr_async(stream, collect).await?, steps).await?);
+            }
+            Ok(requests)
+        });
+        Ok(Self {
+            address,
+            headers,
+            server,
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://{}", self.address)
+    }
+
+    /// Records a venue from the mock exchange until its sessions are replayed, returns the venue directory.
+    ///
+    /// The reference data is only pulled from a [`MockRest`] given with `--rest-url`, never from the venue.
+    pub async fn record(&self, venue: &str, directory: &Path, args: &[&str]) -> Result<PathBuf> {
+        let _ = std::fs::remove_dir_all(directory);
+        let mut recorder = Command::new(env!("CARGO_BIN_EXE_dixit"));
+        recorder
+            .args(["--venue", venue, "--ws-url", &self.url()])
+            .arg("--directory")
+            .arg(directory)
+            .args(args);
+        if !args.contains(&"--rest-url") {
+            recorder.arg("--no-reference");
+        }
+        let mut recorder = recorder.stdout(Stdio::null()).kill_on_drop(true).spawn()?;
+        let status = time::timeout(RECORD_TIMEOUT, recorder.wait()).await??;
+        if !status.success() {
+            bail!("recorder failed with {status}");
+        }
+        Ok(directory.join(venue))
+    }
+
+    /// The headers of the upgrade request of each session accepted so far.
+    pub fn headers(&self) -> Vec<HashMap<String, String>> {
+        self.headers.lock().expect("headers poisoned").clone()
+    }
+
+    /// The text requests received on each session, once every session is replayed.
+    pub async fn requests(self) -> Result<Vec<Vec<String>>> {
+        time::timeout(TIMEOUT, self.server).await??
+    }
+}
+
+async fn replay(mut stream: WebSocketStream<TcpStream>, steps: Vec<Step>) -> Result<Vec<String>> {
+    let mut requests = vec![];
+    match time::timeout(TIMEOUT, stream.next()).await? {
+        Some(Ok(Message::Text(text))) => requests.push(text),
+        other => bail!("expected a subscription, got {other:?}"),
+    }
+
+    for step in steps {
+        match step {
+            Step::Text(text) => stream.send(Message::Text(text)).await?,
+            Step::Binary(bytes) => stream.send(Message::Binary(bytes)).await?,
+            Step::Ping => stream.send(Message::Ping(b"mock".to_vec())).await?,
+            Step::Close => stream.send(Message::Close(None)).await?,
+            Step::Disconnect => stream.get_mut().shutdown().await?,
+            Step::Wait(duration) => time::sleep(duration).await,
+        }
+    }
+
+    // reads until the recorder closes the session, answering its pings, so no request is left unread on close
+    while let Some(Ok(message)) = time::timeout(TIMEOUT, stream.next()).await? {
+        if let Message::Text(text) = message {
+            requests.push(text);
+        }
+    }
+    Ok(requests)
+}
+
+/// Local HTTP server answering the REST requests of the recorder, until dropped.
+/// A request received by the [`MockRest`], with its header names lower cased.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+pub struct MockRest {
+    address: SocketAddr,
+    requests: Arc<Mutex<Vec<Request>>>,
+    server: JoinHandle<Result<()>>,
+}
+
+impl MockRest {
+    /// Answers the requests of each path with its bodies in order, the last one repeated, and the other paths with
+    /// `404 Not Found`.
+    pub async fn start(routes: Vec<(&str, Vec<&str>)>) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let mut routes = routes
+            .into_iter()
+            .map(|(path, bodies)| {
+                (
+                    path.to_owned(),
+                    bodies.into_iter().map(str::to_owned).collect::<Vec<_>>(),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let requests = Arc::new(Mutex::new(vec![]));
+        let requested = requests.clone();
+        let server = tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await?;
+                let request = read_request(&mut stream).await?;
+                let route = request.path.split('?').next().unwrap_or_default().to_owned();
+                requested.lock().expect("requests poisoned").push(request);
+                let response = match routes.get_mut(&route) {
+                    Some(bodies) => {
+                        let body = if bodies.len() > 1 {
+                            bodies.remove(0)
+                        } else {
+                            bodies[0].clone()
+                        };
+                        format!(
+                            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                            body.len()
+                        )
+                    }
+                    None => "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n".to_owned(),
+                };
+                stream.write_all(response.as_bytes()).await?;
+                stream.shutdown().await?;
+            }
+        });
+        Ok(Self {
+            address,
+            requests,
+            server,
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    /// The paths requested so far, queries included.
+    pub fn paths(&self) -> Vec<String> {
+        self.requests().into_iter().map(|request| request.path).collect()
+    }
+
+    /// The requests received so far.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().expect("requests poisoned").clone()
+    }
+}
+
+impl Drop for MockRest {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+/// Reads a request, its body being read up to its `content-length`.
+async fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    let mut content = vec![];
+    let mut buffer = [0; 1024];
+    let end = loop {
+        if let Some(end) = content.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end;
+        }
+        let read = time::timeout(TIMEOUT, stream.read(&mut buffer)).await??;
+        if read == 0 {
+            bail!("request ended before its head");
+        }
+        content.extend_from_slice(&buffer[..read]);
+    };
+    let head = String::from_utf8_lossy(&content[..end]).into_owned();
+    let Some(path) = head.split_whitespace().nth(1) else {
+        bail!("invalid request {head}");
+    };
+    let headers = head
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_owned()))
+        .collect::<HashMap<_, _>>();
+    let length = headers
+        .get("content-length")
+        .and_then(|length| length.parse::<usize>().ok())
+        .unwrap_or_default();
+    let mut body = content.split_off(end + 4);
+    while body.len() < length {
+        let read = time::timeout(TIMEOUT, stream.read(&mut buffer)).await??;
+        if read == 0 {
+            bail!("request ended before its body");
+        }
+        body.extend_from_slice(&buffer[..read]);
+    }
+    Ok(Request {
+        path: path.to_owned(),
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
@@ -0,0 +1,75 @@
+//! Checks the SQL over the recorded tables, including the tables routed by date and the files not finalized yet.
+#![cfg(feature = "query")]
+
+use std::{env, path::PathBuf, sync::LazyLock};
+
+static TMP_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| {
+    let mut path_buf = env::current_dir().unwrap();
+    path_buf.push("target");
+    path_buf.push("test");
+    path_buf.push("query");
+    path_buf
+});
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use anyhow::Result;
+    use chrono::DateTime;
+    use dixit_core::model::FundingRate;
+    use dixit_core::query;
+    use dixit_persist::config::{OverwriteMode, PersistConfig};
+    use dixit_persist::writer::TableWriter;
+    use rust_decimal::Decimal;
+
+    use crate::TMP_FOLDER;
+
+    fn write(config: &PersistConfig, table: &str, symbols: &[&str]) -> Result<PathBuf> {
+        let mut writer = TableWriter::new(table, config)?;
+        for (i, symbol) in symbols.iter().enumerate() {
+            let funding = FundingRate {
+                symbol: symbol.to_string(),
+                instrument: None,
+                time: DateTime::from_timestamp(1_700_000_000 + i as i64, 0).unwrap(),
+                funding_rate: Decimal::new(1, 4),
+                next_funding_time: None,
+            };
+            writer.begin()?.record(&funding)?.end()?;
+        }
+        writer.flush()?;
+        Ok(writer.current_file_path.clone())
+    }
+
+    #[test]
+    fn test_query() -> Result<()> {
+        let _ = fs::remove_dir_all(&*TMP_FOLDER);
+        let config = PersistConfig {
+            tables: HashSet::new(),
+            overwrite: OverwriteMode::Purge,
+            ..PersistConfig::new(&TMP_FOLDER, "")
+        };
+        // a table routed by date, its second day written with markers
+        write(&config, "okx/funding/2024-03-01", &["BTC-USDT-SWAP", "ETH-USDT-SWAP"])?;
+        let marked = PersistConfig {
+            finalize_markers: true,
+            ..config.clone()
+        };
+        let day = write(&marked, "okx/funding/2024-03-02", &["BTC-USDT-SWAP"])?;
+        // a file still being written, without its marker
+        fs::copy(day.join("000000000.parquet"), day.join("000000001.parquet"))?;
+        write(&config, "binance-futures/funding", &["BTCUSDT"])?;
+
+        let result = query::execute(&TMP_FOLDER, "SELECT count(*) FROM okx_funding")?;
+        assert_eq!(Some(3), result.get_columns()[0].u32()?.get(0));
+
+        let result = query::execute(&TMP_FOLDER, "SELECT symbol FROM binance_futures_funding")?;
+        assert_eq!(Some("BTCUSDT"), result.column("symbol")?.str()?.get(0));
+
+        assert!(query::execute(&TMP_FOLDER, "SELECT * FROM okx_trades").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+//! Checks the replay of recorded tables, recorded from a mock exchange then replayed as the records of a live source.
+
+use std::{env, path::PathBuf, sync::LazyLock};
+
+mod mock_exchange;
+
+static TMP_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| {
+    let mut path_buf = env::current_dir().unwrap();
+    path_buf.push("target");
+    path_buf.push("test");
+    path_buf.push("replay");
+    path_buf
+});
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{bail, Result};
+    use chrono::{DateTime, Utc};
+    use dixit_core::model::{Record, VenueData};
+    use dixit_core::source::replay::ReplayFeed;
+    use serde_json::Value;
+    use tokio::sync::mpsc;
+
+    use crate::mock_exchange::{MockExchange, Step};
+    use crate::TMP_FOLDER;
+
+    /// Symbol and JSON row of a replayed record.
+    fn row(record: Record) -> Result<(String, Value)> {
+        let Record::Data {
+            exchange,
+            channel,
+            symbol,
+            data,
+        } = record
+        else {
+            bail!("not a data record");
+        };
+        assert_eq!(("mexc", "trades"), (exchange.as_str(), channel.as_str()));
+        let VenueData::Auto(row) = *data else {
+            bail!("not a replayed row");
+        };
+        Ok((symbol, serde_json::from_slice(&row.to_json()?)?))
+    }
+
+    fn symbols(feed: ReplayFeed) -> Result<Vec<String>> {
+        feed.records()?.map(|record| Ok(row(record?)?.0)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_replay_feed() -> Result<()> {
+        let exchange = MockExchange::start(vec![vec![
+            Step::text(r#"{"id":0,"code":0,"msg":"spot@public.deals.v3.api@BTCUSDT,spot@public.deals.v3.api@ETHUSDT"}"#),
+            Step::text(
+                r#"{"c":"spot@public.deals.v3.api@BTCUSDT","d":{"deals":[{"S":1,"p":"37000.10","t":1700000000000,"v":"0.5"}],"e":"spot@public.deals.v3.api"},"s":"BTCUSDT","t":1700000000000}"#,
+            ),
+            Step::text(
+                r#"{"c":"spot@public.deals.v3.api@ETHUSDT","d":{"deals":[{"S":2,"p":"2000.50","t":1700000000050,"v":"1.5"}],"e":"spot@public.deals.v3.api"},"s":"ETHUSDT","t":1700000000050}"#,
+            ),
+            Step::text(
+                r#"{"c":"spot@public.deals.v3.api@BTCUSDT","d":{"deals":[{"S":2,"p":"37000.00","t":1700000000100,"v":"0.2"}],"e":"spot@public.deals.v3.api"},"s":"BTCUSDT","t":1700000000100}"#,
+            ),
+            Step::Close,
+        ]])
+        .await?;
+        let root = TMP_FOLDER.join("mexc");
+        exchange
+            .record("mexc", &root, &["--symbols", "BTCUSDT,ETHUSDT"])
+            .await?;
+
+        // the records of a live source, with the columns of the table
+        let (tx, mut rx) = mpsc::channel(16);
+        ReplayFeed::new(&root).subscribe("mexc", "trades", &[]).run(tx).await?;
+        let mut rows = vec![];
+        while let Some(record) = rx.recv().await {
+            rows.push(row(record)?);
+        }
+        let symbols_sent = rows.iter().map(|(symbol, _)| symbol.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["BTCUSDT", "ETHUSDT", "BTCUSDT"], symbols_sent);
+        let (_, eth) = &rows[1];
+        assert_eq!(2000.5, eth["price"]);
+        assert_eq!("sell", eth["side"]);
+        assert_eq!("2023-11-14T22:13:20.050+00:00", eth["time"]);
+
+        // filtered by symbol, pushed down to the reader for a single symbol
+        let feed = ReplayFeed::new(&root).subscribe("mexc", "trades", &["ETHUSDT"]);
+        assert_eq!(vec!["ETHUSDT"], symbols(feed)?);
+        let feed = ReplayFeed::new(&root).subscribe("mexc", "trades", &["BTCUSDT", "XYZUSDT"]);
+        assert_eq!(vec!["BTCUSDT", "BTCUSDT"], symbols(feed)?);
+
+        // the subscriptions are merged by time
+        let feed = ReplayFeed::new(&root)
+            .subscribe("mexc", "trades", &["BTCUSDT"])
+            .subscribe("mexc", "trades", &["ETHUSDT"]);
+        assert_eq!(vec!["BTCUSDT", "ETHUSDT", "BTCUSDT"], symbols(feed)?);
+
+        // restricted to a time range
+        let start = DateTime::<Utc>::from_timestamp_millis(1700000000050).unwrap_or_default();
+        let end = DateTime::<Utc>::from_timestamp_millis(1700000000100).unwrap_or_default();
+        let feed = ReplayFeed::new(&root)
+            .subscribe("mexc", "trades", &[])
+            .between(start, end);
+        assert_eq!(vec!["ETHUSDT"], symbols(feed)?);
+
+        // a venue not recorded
+        assert!(ReplayFeed::new(&root)
+            .subscribe("okx", "trades", &[])
+            .records()
+            .is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+//! Checks the tables of the records routed by date, and the files added to a table reopened by late records.
+
+use std::{env, path::PathBuf, sync::LazyLock};
+
+static TMP_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| {
+    let mut path_buf = env::current_dir().unwrap();
+    path_buf.push("target");
+    path_buf.push("test");
+    path_buf.push("route");
+    path_buf
+});
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::fs;
+
+    use anyhow::Result;
+    use chrono::{DateTime, TimeDelta, Utc};
+    use dixit_core::model::FundingRate;
+    use dixit_core::route::{LatePolicy, Router};
+    use dixit_persist::config::PersistConfig;
+    use dixit_persist::service::{PersistCmd, PersistService, ServiceOptions};
+    use rust_decimal::Decimal;
+    use tokio::sync::{mpsc, oneshot};
+
+    use crate::TMP_FOLDER;
+
+    fn time(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn funding(time: DateTime<Utc>) -> FundingRate {
+        FundingRate {
+            symbol: "BTC-USDT-SWAP".to_owned(),
+            instrument: None,
+            time,
+            funding_rate: Decimal::new(1, 4),
+            next_funding_time: None,
+        }
+    }
+
+    async fn write(tx: &mpsc::Sender<PersistCmd>, router: &mut Router, event: DateTime<Utc>, now: DateTime<Utc>) {
+        let table = router.table("okx", "funding", Some(event), now);
+        let record = Box::new(funding(event));
+        tx.send(PersistCmd::Write { table, record }).await.unwrap();
+    }
+
+    /// Completes the tables expired at `now`, as the recorder does every second.
+    async fn tick(tx: &mpsc::Sender<PersistCmd>, router: &mut Router, now: DateTime<Utc>) -> Vec<String> {
+        let tables = router.expired(now);
+        for table in &tables {
+            let (reply, done) = oneshot::channel();
+            tx.send(PersistCmd::Rotate(table.clone(), reply)).await.unwrap();
+            done.await.unwrap().unwrap();
+        }
+        tables
+    }
+
+    fn files(table: &str) -> usize {
+        fs::read_dir(TMP_FOLDER.join(table)).map_or(0, |entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().extension().is_some_and(|extension| extension == "parquet"))
+                .count()
+        })
+    }
+
+    #[test]
+    fn test_table() {
+        let route = "{venue}/{channel}/{date}".parse().unwrap();
+        let mut router = Router::new(route, LatePolicy::LateTable, TimeDelta::minutes(5));
+        let now = time("2024-03-02T00:10:00Z");
+        assert_eq!(
+            "okx/funding/2024-03-02",
+            router.table("okx", "funding", Some(time("2024-03-02T00:00:00Z")), now)
+        );
+        assert_eq!(
+            "okx/funding_late/2024-03-02",
+            router.table("okx", "funding", Some(time("2024-03-01T23:59:59Z")), now)
+        );
+        assert_eq!("okx/_events", router.table("okx", "_events", None, now));
+        assert!(router.expired(now).is_empty());
+        assert_eq!(
+            vec!["okx/funding/2024-03-02", "okx/funding_late/2024-03-02"],
+            router.expired(time("2024-03-03T00:06:00Z"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reopen() -> Result<()> {
+        let _ = fs::remove_dir_all(&*TMP_FOLDER);
+        let config = PersistConfig {
+            tables: HashSet::new(),
+            ..PersistConfig::new(&TMP_FOLDER, "")
+        };
+        let (service, tx) = PersistService::new(config, ServiceOptions::default());
+        let service = tokio::spawn(service.run());
+
+        let route = "{venue}/{channel}/{date}".parse().unwrap();
+        let mut router = Router::new(route, LatePolicy::Reopen, TimeDelta::minutes(5));
+        let day = time("2024-03-01T12:00:00Z");
+        write(&tx, &mut router, day, day).await;
+        let midnight = time("2024-03-02T00:00:00Z");
+        assert_eq!(
+            vec!["okx/funding/2024-03-01"],
+            tick(&tx, &mut router, midnight + TimeDelta::minutes(6)).await
+        );
+        assert_eq!(1, files("okx/funding/2024-03-01"));
+
+        // late records a few seconds apart, completed together once their lateness window has passed
+        for seconds in 0..10 {
+            let now = midnight + TimeDelta::minutes(10) + TimeDelta::seconds(seconds);
+            write(&tx, &mut router, day, now).await;
+            assert!(tick(&tx, &mut router, now).await.is_empty());
+        }
+        assert_eq!(1, files("okx/funding/2024-03-01"));
+        let now = midnight + TimeDelta::minutes(16);
+        assert_eq!(vec!["okx/funding/2024-03-01"], tick(&tx, &mut router, now).await);
+        assert_eq!(2, files("okx/funding/2024-03-01"));
+        assert!(tick(&tx, &mut router, now + TimeDelta::minutes(10)).await.is_empty());
+
+        drop(tx);
+        service.await??;
+        assert_eq!(2, files("okx/funding/2024-03-01"));
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+//! Checks the reload of the config file while recording, the file being replaced as editors do.
+
+use std::{env, path::PathBuf, sync::LazyLock};
+
+static TMP_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| {
+    let mut path_buf = env::current_dir().unwrap();
+    path_buf.push("target");
+    path_buf.push("test");
+    path_buf.push("settings");
+    path_buf
+});
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use dixit_core::config::Venue;
+    use dixit_core::sampling::Sampling;
+    use dixit_core::settings::{self, Settings, Targets};
+    use dixit_persist::service::{PersistCmd, ServiceOptions};
+    use tokio::sync::{mpsc, watch};
+    use tokio::time;
+
+    use crate::TMP_FOLDER;
+
+    #[tokio::test]
+    async fn test_reload() -> Result<()> {
+        let _ = fs::remove_dir_all(&*TMP_FOLDER);
+        fs::create_dir_all(&*TMP_FOLDER)?;
+        let path = TMP_FOLDER.join("config.yaml");
+        fs::write(&path, "rotation_interval: 1h\nsample:\n  ticker: 5/s\n")?;
+
+        let settings = Settings::load(&path)?;
+        let (control, _control) = mpsc::channel(1);
+        let (persist, mut persisted) = mpsc::channel(1);
+        let (sampling, mut sampled) = watch::channel(vec![]);
+        let targets = Targets {
+            venue: Venue::Multicast,
+            control,
+            persist: persist.downgrade(),
+            options: ServiceOptions::default(),
+            sampling,
+        };
+        let reload = tokio::spawn(settings::run(path.clone(), settings, targets));
+
+        // replaced until seen, the watch being set up by the task
+        let replaced = TMP_FOLDER.join("config.yaml.tmp");
+        let changed = async {
+            loop {
+                fs::write(&replaced, "rotation_interval: 30m\nsample:\n  ticker: 10\n")?;
+                fs::rename(&replaced, &path)?;
+                if time::timeout(Duration::from_millis(500), sampled.changed())
+                    .await
+                    .is_ok()
+                {
+                    return Ok::<_, anyhow::Error>(());
+                }
+            }
+        };
+        time::timeout(Duration::from_secs(10), changed).await??;
+        assert_eq!(vec![("ticker".to_owned(), Sampling::Every(10))], *sampled.borrow());
+        let Some(PersistCmd::SetOptions(options)) = persisted.recv().await else {
+            panic!("rotation interval not applied");
+        };
+        assert_eq!(Some(Duration::from_secs(1800)), options.rotation_interval);
+
+        // an invalid file is ignored, the next valid one applied
+        fs::write(&replaced, "rotation_interval: [\n")?;
+        fs::rename(&replaced, &path)?;
+        fs::write(&replaced, "rotation_interval: 15m\nsample:\n  ticker: 10\n")?;
+        fs::rename(&replaced, &path)?;
+        let Some(PersistCmd::SetOptions(options)) = time::timeout(Duration::from_secs(10), persisted.recv()).await?
+        else {
+            panic!("rotation interval not applied");
+        };
+        assert_eq!(Some(Duration::from_secs(900)), options.rotation_interval);
+
+        reload.abort();
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+//! Checks the uploads of the recorded tables to an object store, against a bucket on the local filesystem.
+#![cfg(feature = "s3")]
+
+use std::env;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+static TMP_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| {
+    let mut path_buf = env::current_dir().unwrap();
+    path_buf.push("target");
+    path_buf.push("test");
+    path_buf.push("sync");
+    path_buf
+});
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::Result;
+    use dixit_core::upload::{UploadManifest, Uploader, MANIFEST_FILE};
+
+    use crate::TMP_FOLDER;
+
+    #[tokio::test]
+    async fn test_sync() -> Result<()> {
+        let _ = fs::remove_dir_all(&*TMP_FOLDER);
+        let output = TMP_FOLDER.join("output");
+        let bucket = TMP_FOLDER.join("bucket");
+        fs::create_dir_all(output.join("mexc/trades"))?;
+        fs::create_dir_all(&bucket)?;
+        fs::write(output.join("mexc/trades/000000000.parquet"), b"first")?;
+        fs::write(output.join("mexc/_manifest.json"), b"{}")?;
+        fs::write(output.join("mexc/trades/.lock"), b"")?;
+        let url = format!("file://{}/dixit", bucket.display());
+        let uploader = Uploader::new(&url, &output)?;
+
+        // the lock and the upload manifest are not uploaded
+        let report = uploader.sync().await?;
+        assert_eq!((2, 0), (report.uploaded, report.skipped));
+        assert_eq!(
+            b"first".as_slice(),
+            fs::read(bucket.join("dixit/mexc/trades/000000000.parquet"))?
+        );
+        let manifest = UploadManifest::load(&output.join("mexc"))?;
+        assert_eq!(5, manifest.files["trades/000000000.parquet"].size);
+        assert!(output.join("mexc").join(MANIFEST_FILE).exists());
+
+        // a retry uploads nothing
+        let report = uploader.sync().await?;
+        assert_eq!((0, 2), (report.uploaded, report.skipped));
+
+        // the changed files and the objects missing from the bucket are uploaded again
+        fs::write(output.join("mexc/_manifest.json"), b"{\"end\":1}")?;
+        fs::remove_file(bucket.join("dixit/mexc/trades/000000000.parquet"))?;
+        let report = uploader.sync().await?;
+        assert_eq!((2, 0), (report.uploaded, report.skipped));
+        assert_eq!(
+            b"{\"end\":1}".as_slice(),
+            fs::read(bucket.join("dixit/mexc/_manifest.json"))?
+        );
+
+        // the objects without a local file are reported, not deleted
+        fs::write(bucket.join("dixit/mexc/trades/000000001.parquet"), b"remote")?;
+        let report = uploader.sync().await?;
+        assert_eq!(1, report.remote_only.len());
+        assert!(report.remote_only[0].ends_with("dixit/mexc/trades/000000001.parquet"));
+        assert!(bucket.join("dixit/mexc/trades/000000001.parquet").exists());
+
+        Ok(())
+    }
+}
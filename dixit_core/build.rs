@@ -0,0 +1,17 @@
+//! Embeds the git commit of the build (`DIXIT_GIT_COMMIT`), recorded in the session manifests.
+
+use std::process::Command;
+
+fn main() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned());
+    if let Some(commit) = commit {
+        println!("cargo:rustc-env=DIXIT_GIT_COMMIT={commit}");
+    }
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs");
+}
@@ -0,0 +1,182 @@
+//! Serves an admin HTTP API to operate a running recorder (`admin` feature), e.g. to force a flush before a
+//! maintenance or to pause recording without restarting.
+//!
+//! ## Features
+//! - `GET /tables` returns the stats of every table written so far and whether it is enabled, named by the table
+//!   route (e.g. `okx/trades`).
+//! - `POST /flush/{table}` flushes the rows buffered by a table, `POST /rotate/{table}` also completes its open file.
+//! - `POST /disable/{table}` stops writing a table, flushing it and completing its open file, until
+//!   `POST /enable/{table}`, a table not written yet being disabled from its first record.
+//! - `GET /subscriptions` returns the symbols subscribed, `POST /subscriptions` changes them as the control socket
+//!   does, e.g. with `{"subscribe": ["SOL-USDT-SWAP"], "unsubscribe": ["ETH-USDT-SWAP"]}`.
+//! - `POST /pause` stops persisting and publishing the records until `POST /resume`, the venue connection is kept.
+//! - Holds the persist service weakly, so the recorder still finishes once the venue stream ends.
+//!
+//! ## Example
+//! ```sh
+//! dixit --venue okx --admin-address 127.0.0.1:8080
+//! curl -X POST 127.0.0.1:8080/flush/okx/trades
+//! ```
+
+use std::net::SocketAddr;
+
+use anyhow::Result;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use dixit_persist::error::PersistError;
+use dixit_persist::service::PersistCmd;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{Sender, WeakSender};
+use tokio::sync::oneshot;
+use tracing::info;
+
+use crate::config::Venue;
+use crate::control::{self, ControlCmd};
+use crate::persister;
+
+#[derive(Clone)]
+pub struct Admin {
+    pub venue: Venue,
+    pub persist: WeakSender<PersistCmd>,
+    pub control: Sender<ControlCmd>,
+}
+
+/// Symbols to unsubscribe and to subscribe, in this order.
+#[derive(Debug, Default, Deserialize)]
+struct SubscriptionChange {
+    #[serde(default)]
+    subscribe: Vec<String>,
+    #[serde(default)]
+    unsubscribe: Vec<String>,
+}
+
+type Reply = Result<Json<Value>, (StatusCode, String)>;
+
+pub async fn serve(address: SocketAddr, admin: Admin) -> Result<()> {
+    let router = Router::new()
+        .route("/tables", get(tables))
+        .route("/flush/*table", post(flush))
+        .route("/rotate/*table", post(rotate))
+        .route("/enable/*table", post(enable))
+        .route("/disable/*table", post(disable))
+        .route("/subscriptions", get(subscriptions).post(change_subscriptions))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .with_state(admin);
+
+    let listener = TcpListener::bind(address).await?;
+    info!("admin API listening on {address}");
+    axum::serve(listener, router).await?;
+    Ok(())
+}
+
+async fn tables(State(admin): State<Admin>) -> Reply {
+    let stats = request(&admin, PersistCmd::Stats).await?;
+    let tables = stats
+        .tables
+        .iter()
+        .map(|(table, stats)| {
+            let stats = json!({
+                "rows_buffered": stats.rows_buffered,
+                "rows_written": stats.rows_written,
+                "files_written": stats.files_written,
+                "enabled": stats.enabled,
+            });
+            (table.clone(), stats)
+        })
+        .collect::<serde_json::Map<_, _>>();
+    Ok(Json(Value::Object(tables)))
+}
+
+async fn flush(State(admin): State<Admin>, Path(table): Path<String>) -> Reply {
+    known_table(&admin, &table).await?;
+    let result = request(&admin, |reply| PersistCmd::FlushTable(table.clone(), reply)).await?;
+    reply_table(table, result)
+}
+
+async fn rotate(State(admin): State<Admin>, Path(table): Path<String>) -> Reply {
+    known_table(&admin, &table).await?;
+    let result = request(&admin, |reply| PersistCmd::Rotate(table.clone(), reply)).await?;
+    reply_table(table, result)
+}
+
+async fn enable(State(admin): State<Admin>, Path(table): Path<String>) -> Reply {
+    let result = request(&admin, |reply| PersistCmd::SetEnabled(table.clone(), true, reply)).await?;
+    reply_table(table, result)
+}
+
+async fn disable(State(admin): State<Admin>, Path(table): Path<String>) -> Reply {
+    let result = request(&admin, |reply| PersistCmd::SetEnabled(table.clone(), false, reply)).await?;
+    reply_table(table, result)
+}
+
+async fn subscriptions(State(admin): State<Admin>) -> Reply {
+    let symbols = control::apply(&admin.control, admin.venue, ControlCmd::List)
+        .await
+        .map_err(|e| (StatusCode::NOT_IMPLEMENTED, e.to_string()))?;
+    Ok(Json(json!(symbols)))
+}
+
+async fn change_subscriptions(State(admin): State<Admin>, Json(change): Json<SubscriptionChange>) -> Reply {
+    let unsupported = |e: anyhow::Error| (StatusCode::NOT_IMPLEMENTED, e.to_string());
+    let mut symbols = control::apply(&admin.control, admin.venue, ControlCmd::List)
+        .await
+        .map_err(unsupported)?;
+    if !change.unsubscribe.is_empty() {
+        symbols = control::apply(&admin.control, admin.venue, |reply| {
+            ControlCmd::Unsubscribe(change.unsubscribe, reply)
+        })
+        .await
+        .map_err(unsupported)?;
+    }
+    if !change.subscribe.is_empty() {
+        symbols = control::apply(&admin.control, admin.venue, |reply| {
+            ControlCmd::Subscribe(change.subscribe, reply)
+        })
+        .await
+        .map_err(unsupported)?;
+    }
+    Ok(Json(json!(symbols)))
+}
+
+async fn pause() -> Reply {
+    persister::set_paused(true);
+    Ok(Json(json!({ "paused": true })))
+}
+
+async fn resume() -> Reply {
+    persister::set_paused(false);
+    Ok(Json(json!({ "paused": false })))
+}
+
+/// Sends a command to the persist service and waits for its reply, fails once the service stopped.
+async fn request<T>(
+    admin: &Admin,
+    command: impl FnOnce(oneshot::Sender<T>) -> PersistCmd,
+) -> Result<T, (StatusCode, String)> {
+    let stopped = || (StatusCode::SERVICE_UNAVAILABLE, "persist service stopped".to_owned());
+    let tx = admin.persist.upgrade().ok_or_else(stopped)?;
+    let (reply, rx) = oneshot::channel();
+    tx.send(command(reply)).await.map_err(|_| stopped())?;
+    rx.await.map_err(|_| stopped())
+}
+
+/// Fails with a not found if the table has no writer yet, the tables are never removed once written.
+async fn known_table(admin: &Admin, table: &str) -> Result<(), (StatusCode, String)> {
+    let stats = request(admin, PersistCmd::Stats).await?;
+    if !stats.tables.contains_key(table) {
+        return Err((StatusCode::NOT_FOUND, format!("unknown table {table}")));
+    }
+    Ok(())
+}
+
+fn reply_table(table: String, result: Result<(), PersistError>) -> Reply {
+    match result {
+        Ok(()) => Ok(Json(json!({ "table": table }))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
@@ -0,0 +1,313 @@
+//! Records any JSON websocket feed without a typed handler (`auto` venue), so a brand-new channel can be captured
+//! before writing its structs.
+//!
+//! ## Features
+//! - Sends the `--auto-subscribe` messages on connection, `{symbols}` being replaced by the JSON array of the symbols.
+//! - Routes every message to the table of its channel, read at `--auto-channel` (a JSON pointer, e.g. `/arg/channel`),
+//!   the messages without a channel go to the `messages` table.
+//! - Records the payload at `--auto-data` (e.g. `/data`) if set, an array of objects being recorded as one row each.
+//! - Infers a flat schema from the first `--auto-infer` messages of each channel: nested objects are flattened into
+//!   `parent_child` columns, arrays are kept as JSON strings and every column is optional. The columns are in the
+//!   order first seen, the keys of an object in alphabetical order.
+//! - Widens the type of a column seen with different types: integers and floats to floats, anything else to strings.
+//!
+//! Once the schema of a channel is inferred, the keys not seen while inferring are dropped and the values not fitting
+//! their column are recorded as nulls (e.g. a string in a float column). The messages buffered while inferring are
+//! lost if the recorder stops before the channel reaches `--auto-infer` messages.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use dixit_persist::dynamic::{ColumnType, Descriptor, DynamicRow, DynamicValue, SchemaBuilder};
+use dixit_persist::writer::TableWriter;
+use serde_json::{from_str, Map, Value};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::info;
+
+use crate::model::{Record, SkipReason, VenueData};
+
+pub const EXCHANGE: &str = "auto";
+/// Table of the messages without a channel.
+const MESSAGES: &str = "messages";
+
+/// Settings of the `auto` venue, see [`crate::config::Args::auto_config`].
+#[derive(Debug, Clone)]
+pub struct AutoConfig {
+    pub url: String,
+    pub subscribe: Vec<String>,
+    pub channel: String,
+    pub data: Option<String>,
+    pub infer: usize,
+}
+
+pub fn subscribe(templates: &[String], symbols: &[String]) -> Vec<Message> {
+    let symbols = Value::from(symbols).to_string();
+    templates
+        .iter()
+        .map(|template| Message::Text(template.replace("{symbols}", &symbols)))
+        .collect()
+}
+
+/// Type of an inferred column, ordered so that widening two types takes the largest one but for bools and numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Null,
+    Bool,
+    Int,
+    Float,
+    String,
+}
+
+impl Kind {
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Null => Kind::Null,
+            Value::Bool(_) => Kind::Bool,
+            Value::Number(number) if number.is_i64() => Kind::Int,
+            Value::Number(_) => Kind::Float,
+            _ => Kind::String,
+        }
+    }
+
+    fn widen(self, other: Kind) -> Kind {
+        match (self.min(other), self.max(other)) {
+            (Kind::Null, kind) => kind,
+            (low, high) if low == high => low,
+            (Kind::Int, Kind::Float) => Kind::Float,
+            _ => Kind::String,
+        }
+    }
+
+    fn column_type(self) -> ColumnType {
+        match self {
+            Kind::Bool => ColumnType::Bool,
+            Kind::Int => ColumnType::I64,
+            Kind::Float => ColumnType::F64,
+            // a column only seen null is kept as a string
+            Kind::Null | Kind::String => ColumnType::String,
+        }
+    }
+
+    /// The value as recorded in a column of this type, null if it does not fit.
+    fn value(self, value: &Value) -> DynamicValue {
+        match (self, value) {
+            (_, Value::Null) => DynamicValue::Null,
+            (Kind::Bool, Value::Bool(value)) => DynamicValue::Bool(*value),
+            (Kind::Int, Value::Number(number)) => number.as_i64().into(),
+            (Kind::Float, Value::Number(number)) => number.as_f64().into(),
+            (Kind::String, Value::String(value)) => DynamicValue::String(value.clone()),
+            (Kind::String, value) => DynamicValue::String(value.to_string()),
+            _ => DynamicValue::Null,
+        }
+    }
+}
+
+/// Columns of an inferred table, with their types.
+struct Schema {
+    /// leaked once per channel, as the tables of the other venues are static
+    table: &'static str,
+    descriptor: Arc<Descriptor>,
+    columns: Vec<(String, Kind)>,
+}
+
+impl Schema {
+    fn infer(table: &str, rows: &[Vec<(String, Value)>]) -> Result<Self> {
+        let mut columns: Vec<(String, Kind)> = vec![];
+        for row in rows {
+            for (name, value) in row {
+                match columns.iter_mut().find(|(column, _)| column == name) {
+                    Some((_, kind)) => *kind = kind.widen(Kind::of(value)),
+                    None => columns.push((name.clone(), Kind::of(value))),
+                }
+            }
+        }
+        let descriptor = columns
+            .iter()
+            .fold(SchemaBuilder::new(table), |builder, (name, kind)| {
+                builder.optional(name, kind.column_type())
+            })
+            .build()?;
+        Ok(Self {
+            table: String::leak(table.to_owned()),
+            descriptor: Arc::new(descriptor),
+            columns,
+        })
+    }
+
+    fn row(&self, values: Vec<(String, Value)>) -> Row {
+        let mut values = values.into_iter().collect::<HashMap<_, _>>();
+        let row = self
+            .columns
+            .iter()
+            .map(|(name, kind)| {
+                values
+                    .remove(name)
+                    .map_or(DynamicValue::Null, |value| kind.value(&value))
+            })
+            .collect::<Vec<_>>();
+        Row {
+            table: self.table,
+            descriptor: self.descriptor.clone(),
+            row: DynamicRow::new(row),
+        }
+    }
+}
+
+enum Channel {
+    /// flattened rows buffered until the schema is inferred
+    Inferring(Vec<Vec<(String, Value)>>),
+    Inferred(Schema),
+}
+
+/// A row of an inferred table.
+#[derive(Debug)]
+pub struct Row {
+    pub table: &'static str,
+    pub descriptor: Arc<Descriptor>,
+    pub row: DynamicRow,
+}
+
+impl Row {
+    pub fn persist(&self, writer: &mut TableWriter) -> Result<()> {
+        writer.begin()?.record_dyn(&self.descriptor, &self.row)?.end()?;
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        let object = self
+            .descriptor
+            .fields()
+            .iter()
+            .zip(self.row.values.iter())
+            .map(|(field, value)| {
+                let value = match value {
+                    DynamicValue::Null => Value::Null,
+                    DynamicValue::Bool(value) => Value::from(*value),
+                    DynamicValue::I32(value) => Value::from(*value),
+                    DynamicValue::I64(value) => Value::from(*value),
+                    DynamicValue::U64(value) => Value::from(*value),
+                    DynamicValue::F32(value) => Value::from(*value),
+                    DynamicValue::F64(value) => Value::from(*value),
+                    DynamicValue::String(value) => Value::from(value.as_str()),
+                    DynamicValue::Timestamp(value) => Value::from(value.to_rfc3339()),
+                };
+                (field.name().to_owned(), value)
+            })
+            .collect::<Map<_, _>>();
+        Ok(serde_json::to_vec(&object)?)
+    }
+}
+
+/// Stateful handler, inferring the schema of each channel from its first messages.
+pub struct Handler {
+    config: AutoConfig,
+    channels: HashMap<String, Channel>,
+}
+
+impl Handler {
+    pub fn new(config: AutoConfig) -> Self {
+        Self {
+            config,
+            channels: HashMap::new(),
+        }
+    }
+
+    pub fn handle(&mut self, message: Message) -> Vec<Record> {
+        let Message::Text(string) = message else {
+            return vec![Record::non_text()];
+        };
+        let Ok(message) = from_str::<Value>(&string) else {
+            return vec![Record::unhandled(string)];
+        };
+        let table = message
+            .pointer(&self.config.channel)
+            .and_then(Value::as_str)
+            .map_or(MESSAGES.to_owned(), table_name);
+        let payload = match &self.config.data {
+            Some(pointer) => match message.pointer(pointer) {
+                Some(payload) => payload,
+                // e.g. a subscription acknowledgment
+                None => return vec![Record::unhandled(string)],
+            },
+            None => &message,
+        };
+        let rows = match payload {
+            Value::Array(items) => items.iter().map(flatten).collect::<Vec<_>>(),
+            payload => vec![flatten(payload)],
+        };
+        self.rows(&table, rows)
+    }
+
+    fn rows(&mut self, table: &str, rows: Vec<Vec<(String, Value)>>) -> Vec<Record> {
+        let channel = self
+            .channels
+            .entry(table.to_owned())
+            .or_insert_with(|| Channel::Inferring(vec![]));
+        let rows = match channel {
+            Channel::Inferred(_) => rows,
+            Channel::Inferring(buffered) => {
+                buffered.extend(rows);
+                if buffered.len() < self.config.infer {
+                    return vec![];
+                }
+                let rows = std::mem::take(buffered);
+                match Schema::infer(table, &rows) {
+                    Ok(schema) => {
+                        info!("inferred table {table} {:?}", schema.columns);
+                        *channel = Channel::Inferred(schema);
+                    }
+                    Err(e) => {
+                        // inferred again from the next messages
+                        return vec![Record::skip(
+                            SkipReason::Parse,
+                            format!("cannot infer the schema of {table}: {e}"),
+                        )];
+                    }
+                }
+                rows
+            }
+        };
+        let Channel::Inferred(schema) = channel else {
+            unreachable!("schema inferred above");
+        };
+        rows.into_iter()
+            .map(|values| Record::data(EXCHANGE, table, "", VenueData::Auto(schema.row(values))))
+            .collect()
+    }
+}
+
+/// Flattens an object into `(column, value)` pairs, nested objects into `parent_child` columns and arrays into JSON
+/// strings, any other value into a `value` column.
+fn flatten(value: &Value) -> Vec<(String, Value)> {
+    fn flatten_into(prefix: &str, object: &Map<String, Value>, columns: &mut Vec<(String, Value)>) {
+        for (key, value) in object {
+            let name = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{prefix}_{key}")
+            };
+            match value {
+                Value::Object(object) => flatten_into(&name, object, columns),
+                Value::Array(_) => columns.push((name, Value::String(value.to_string()))),
+                value => columns.push((name, value.clone())),
+            }
+        }
+    }
+
+    let mut columns = vec![];
+    match value {
+        Value::Object(object) => flatten_into("", object, &mut columns),
+        value => flatten_into("", &Map::from_iter([("value".to_owned(), value.clone())]), &mut columns),
+    }
+    columns
+}
+
+/// The channel as a table name, anything but ascii alphanumerics replaced by underscores (e.g. `book.50` to
+/// `book_50`).
+fn table_name(channel: &str) -> String {
+    channel
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
@@ -0,0 +1,177 @@
+//! Aggregates captured trades into OHLCV bars.
+//!
+//! ## Features
+//! - Defines the [`Interval`] accepted on the command line and in the config file (e.g. `1s`, `1m`, `5m`).
+//! - Keeps one open [`Bar`] per symbol and interval, updated with every trade.
+//! - Closes bars when a later trade arrives or once the wall clock is past their end (plus some lateness).
+//! - Drops the trades arriving after their bar is closed, without keeping the closed bars: a trade is late once older
+//!   than the open bar of its symbol, or once its bar ended before the last close.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use dixit_persist_macros::Persist;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use tracing::debug;
+
+/// How long after its end a bar is kept open waiting for trades that arrive late.
+const LATENESS: TimeDelta = TimeDelta::seconds(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Interval(TimeDelta);
+
+impl Interval {
+    pub fn duration(&self) -> Duration {
+        self.0.to_std().unwrap_or_default()
+    }
+}
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (value, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+        let value = value.parse::<i64>().map_err(|_| format!("invalid interval {s}"))?;
+        let delta = match unit {
+            "ms" => TimeDelta::milliseconds(value),
+            "s" => TimeDelta::seconds(value),
+            "m" => TimeDelta::minutes(value),
+            "h" => TimeDelta::hours(value),
+            _ => return Err(format!("invalid interval unit {s}, expected ms, s, m or h")),
+        };
+        if delta <= TimeDelta::zero() {
+            return Err(format!("invalid interval {s}, must be positive"));
+        }
+        Ok(Self(delta))
+    }
+}
+
+impl TryFrom<String> for Interval {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.0.num_seconds();
+        if self.0.subsec_nanos() != 0 {
+            write!(f, "{}ms", self.0.num_milliseconds())
+        } else if seconds % 3600 == 0 {
+            write!(f, "{}h", seconds / 3600)
+        } else if seconds % 60 == 0 {
+            write!(f, "{}m", seconds / 60)
+        } else {
+            write!(f, "{}s", seconds)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Persist)]
+pub struct Bar {
+    pub symbol: String,
+    #[persist(intern)]
+    pub instrument: Option<String>,
+    pub interval: String,
+    pub start: DateTime<Utc>,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,
+    pub trade_count: u64,
+}
+
+#[derive(Default)]
+pub struct TradeAggregator {
+    intervals: Vec<Interval>,
+    open: HashMap<(String, Interval), Bar>,
+    /// time of the last close, every bar ending (plus the lateness) before it is closed
+    closed_until: Option<DateTime<Utc>>,
+}
+
+impl TradeAggregator {
+    pub fn new(intervals: Vec<Interval>) -> Self {
+        Self {
+            intervals,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.intervals.is_empty()
+    }
+
+    /// Returns the bars closed by this trade.
+    pub fn update(&mut self, symbol: &str, time: DateTime<Utc>, price: Decimal, size: Decimal) -> Vec<Bar> {
+        let mut bars = vec![];
+        for &interval in self.intervals.iter() {
+            let Ok(start) = time.duration_trunc(interval.0) else {
+                continue;
+            };
+            let deadline = start + interval.0 + LATENESS;
+            if self.closed_until.is_some_and(|closed| deadline <= closed) {
+                debug!("dropping late trade for {symbol} at {time}, {interval} bar already closed");
+                continue;
+            }
+            let key = (symbol.to_owned(), interval);
+
+            match self.open.get_mut(&key) {
+                Some(bar) if bar.start == start => {
+                    bar.high = bar.high.max(price);
+                    bar.low = bar.low.min(price);
+                    bar.close = price;
+                    bar.volume += size;
+                    bar.trade_count += 1;
+                }
+                Some(bar) if bar.start > start => {
+                    // older than the open bar, whose previous bar is already closed
+                    debug!("dropping out of order trade for {symbol} at {time}");
+                }
+                _ => {
+                    let bar = Bar {
+                        symbol: symbol.to_owned(),
+                        instrument: None,
+                        interval: interval.to_string(),
+                        start,
+                        open: price,
+                        high: price,
+                        low: price,
+                        close: price,
+                        volume: size,
+                        trade_count: 1,
+                    };
+                    if let Some(previous) = self.open.insert(key, bar) {
+                        bars.push(previous);
+                    }
+                }
+            }
+        }
+        bars
+    }
+
+    /// Returns the bars whose interval (plus the allowed lateness) ended before `now`.
+    pub fn close(&mut self, now: DateTime<Utc>) -> Vec<Bar> {
+        let expired = self
+            .open
+            .iter()
+            .filter(|((_, interval), bar)| bar.start + interval.0 + LATENESS <= now)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        let mut bars = expired
+            .into_iter()
+            .filter_map(|key| self.open.remove(&key))
+            .collect::<Vec<_>>();
+        self.closed_until = self.closed_until.max(Some(now));
+        bars.sort_by_key(|bar| bar.start);
+        bars
+    }
+}
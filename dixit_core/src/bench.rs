@@ -0,0 +1,329 @@
+//! Load tests the recorder with the `bench-feed` subcommand, a simulated venue generating synthetic trades and book
+//! updates at a fixed rate, for capacity planning.
+//!
+//! ## Features
+//! - Generates the JSON messages of `--symbols` symbols at `--rate` messages per second for `--duration`,
+//!   `--book-percent` of them being book updates and the others trades.
+//! - Parses every message as a venue feed would and pushes its records through the persister (routing, stages and
+//!   persist service) to the `trades` and `book` tables of the `bench` venue, purged on startup.
+//! - Reports the sustained throughput, the latency percentiles of appending the records to the pipeline and the
+//!   flush stalls, the appends blocked for longer than `--stall-threshold` while the service writes its files.
+//! - Paces the messages by batches of a millisecond, a rate above the capacity of the pipeline shows as a sustained
+//!   throughput below the target.
+//!
+//! ## Example
+//! ```sh
+//! dixit bench-feed --rate 500000 --duration 30s --format parquet
+//! ```
+
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dixit_persist::config::{Format, OverwriteMode, PersistConfig};
+use dixit_persist::service::ServiceOptions;
+use dixit_persist_macros::Persist;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::from_str;
+use tokio::sync::{mpsc, watch};
+use tokio::time;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::bars::TradeAggregator;
+use crate::clock::SharedClock;
+use crate::config::{self, Venue};
+use crate::conflation::Conflator;
+use crate::derived::Deriver;
+use crate::manifest::Manifest;
+use crate::model::{Record, Trade, VenueData};
+use crate::persister::{self, Stages};
+use crate::quality::Validator;
+use crate::route::Router;
+use crate::sampling::Sampler;
+use crate::skips::SkipLog;
+use crate::supervisor::Freshness;
+use crate::symbology::Symbology;
+
+pub const EXCHANGE: &str = "bench";
+
+/// Capacity of the record channel, as when recording a venue.
+const CHANNEL_CAPACITY: usize = 100;
+/// Interval between the batches of messages.
+const BATCH_INTERVAL: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// messages generated per second
+    pub rate: u64,
+    pub duration: Duration,
+    /// number of symbols the messages are spread over
+    pub symbols: usize,
+    /// share of book updates among the messages, in percent
+    pub book_percent: u8,
+    /// appends blocked for longer than this are reported as stalls
+    pub stall_threshold: Duration,
+    /// folder of the bench tables
+    pub directory: PathBuf,
+    pub format: Format,
+    /// clock of the messages and of the persister
+    pub clock: SharedClock,
+}
+
+/// A level of the simulated book, `size` being 0 for a removed level.
+#[derive(Debug, Persist)]
+pub struct BookUpdate {
+    pub symbol: String,
+    pub time: DateTime<Utc>,
+    pub side: String,
+    pub price: Decimal,
+    pub size: Decimal,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Push {
+    Trade(Update),
+    Book(Update),
+}
+
+#[derive(Deserialize, Debug)]
+struct Update {
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "t", with = "chrono::serde::ts_milliseconds")]
+    time: DateTime<Utc>,
+    #[serde(rename = "p")]
+    price: Decimal,
+    #[serde(rename = "q")]
+    size: Decimal,
+    side: String,
+}
+
+pub fn handle(message: Message) -> Vec<Record> {
+    match message {
+        Message::Text(string) => match from_str::<Push>(&string) {
+            Ok(Push::Trade(update)) => {
+                let trade = Trade {
+                    symbol: update.symbol.clone(),
+                    instrument: None,
+                    time: update.time,
+                    price: update.price,
+                    size: update.size,
+                    side: update.side,
+                };
+                vec![Record::data(
+                    EXCHANGE,
+                    "trades",
+                    &update.symbol,
+                    VenueData::Trade(trade),
+                )]
+            }
+            Ok(Push::Book(update)) => {
+                let book = BookUpdate {
+                    symbol: update.symbol.clone(),
+                    time: update.time,
+                    side: update.side,
+                    price: update.price,
+                    size: update.size,
+                };
+                vec![Record::data(
+                    EXCHANGE,
+                    "book",
+                    &update.symbol,
+                    VenueData::BenchBook(book),
+                )]
+            }
+            Err(_) => vec![Record::unhandled(string)],
+        },
+        _ => vec![Record::non_text()],
+    }
+}
+
+/// Generates the synthetic messages, reproducible as seeded.
+struct Generator {
+    symbols: Vec<String>,
+    book_percent: u64,
+    state: u64,
+}
+
+impl Generator {
+    fn new(symbols: usize, book_percent: u8) -> Self {
+        Self {
+            symbols: (0..symbols.max(1)).map(|i| format!("SYM{i}-USD")).collect(),
+            book_percent: book_percent.into(),
+            state: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+
+    /// xorshift64, enough to spread the messages without a random crate
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn message(&mut self, time: DateTime<Utc>) -> Message {
+        let index = (self.next() % self.symbols.len() as u64) as usize;
+        let kind = if self.next() % 100 < self.book_percent {
+            "book"
+        } else {
+            "trade"
+        };
+        let side = if self.next() & 1 == 0 { "buy" } else { "sell" };
+        let cents = 10_000_000 + self.next() % 100_000;
+        let lots = self.next() % 1_000;
+        let symbol = &self.symbols[index];
+        Message::Text(format!(
+            r#"{{"type":"{kind}","s":"{symbol}","t":{},"p":"{}.{:02}","q":"{}.{:03}","side":"{side}"}}"#,
+            time.timestamp_millis(),
+            cents / 100,
+            cents % 100,
+            lots / 1_000,
+            lots % 1_000
+        ))
+    }
+}
+
+/// Measures of a bench run.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub messages: u64,
+    pub records: u64,
+    pub target_rate: u64,
+    /// from the first message until every table is closed
+    pub elapsed: Duration,
+    /// append latencies, sorted
+    pub latencies: Vec<Duration>,
+    pub stall_threshold: Duration,
+}
+
+impl Report {
+    pub fn throughput(&self) -> f64 {
+        self.messages as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let index = ((self.latencies.len() - 1) as f64 * percentile).round() as usize;
+        self.latencies[index]
+    }
+
+    /// Appends blocked for longer than the stall threshold.
+    pub fn stalls(&self) -> usize {
+        self.latencies.len()
+            - self
+                .latencies
+                .partition_point(|latency| *latency <= self.stall_threshold)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "messages: {} ({} records) in {:.3}s",
+            self.messages,
+            self.records,
+            self.elapsed.as_secs_f64()
+        )?;
+        writeln!(
+            f,
+            "throughput: {:.0} msgs/s sustained, {} msgs/s targeted",
+            self.throughput(),
+            self.target_rate
+        )?;
+        writeln!(
+            f,
+            "append latency: p50 {:?}, p99 {:?}, p99.9 {:?}, max {:?}",
+            self.percentile(0.5),
+            self.percentile(0.99),
+            self.percentile(0.999),
+            self.latencies.last().copied().unwrap_or_default()
+        )?;
+        write!(
+            f,
+            "flush stalls: {} appends blocked over {:?}",
+            self.stalls(),
+            self.stall_threshold
+        )
+    }
+}
+
+/// Runs the simulated venue through the persister and prints the report once every table is closed.
+pub async fn run(config: BenchConfig) -> Result<()> {
+    let report = bench(config).await?;
+    println!("{report}");
+    Ok(())
+}
+
+pub async fn bench(config: BenchConfig) -> Result<Report> {
+    config::register(EXCHANGE)?;
+    let venue = Venue::Custom;
+    let persist_config = PersistConfig {
+        directory: config.directory.clone(),
+        format: config.format,
+        overwrite: OverwriteMode::Purge,
+        ..Default::default()
+    };
+    let service = persister::service(persist_config, ServiceOptions::default(), config.clock.clone());
+    let (_, sampling) = watch::channel(vec![]);
+    let stages = Stages {
+        bars: TradeAggregator::new(vec![]),
+        conflator: Conflator::new(vec![], Duration::from_millis(100)),
+        deriver: Deriver::new(venue, vec![]),
+        sampler: Sampler::new(vec![]),
+        sampling,
+        validator: Validator::default(),
+        catalog: None,
+        watermark: None,
+        symbology: Symbology::load(None)?,
+        router: Router::new(Default::default(), Default::default(), Default::default()),
+        skips: SkipLog::default(),
+        clock: config.clock.clone(),
+        freshness: Arc::new(Freshness::new(config.clock.clone())),
+    };
+    let mut generator = Generator::new(config.symbols, config.book_percent);
+    let manifest = Manifest::new(venue, generator.symbols.clone(), config.clock.now());
+    let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let persister = tokio::spawn(persister::run(venue, service, stages, manifest, vec![], rx));
+
+    let mut report = Report {
+        target_rate: config.rate,
+        stall_threshold: config.stall_threshold,
+        ..Default::default()
+    };
+    let start = Instant::now();
+    loop {
+        let elapsed = start.elapsed();
+        if elapsed >= config.duration {
+            break;
+        }
+        let due = (u128::from(config.rate) * elapsed.as_nanos() / 1_000_000_000) as u64;
+        if report.messages >= due {
+            time::sleep(BATCH_INTERVAL).await;
+            continue;
+        }
+        let now = config.clock.now();
+        while report.messages < due {
+            for record in handle(generator.message(now)) {
+                let append = Instant::now();
+                tx.send(record).await?;
+                report.latencies.push(append.elapsed());
+                report.records += 1;
+            }
+            report.messages += 1;
+        }
+    }
+    drop(tx);
+    persister.await??;
+    report.elapsed = start.elapsed();
+    report.latencies.sort_unstable();
+    Ok(report)
+}
@@ -0,0 +1,597 @@
+//! Contains Binance USDⓈ-M futures-specific WebSocket handling logic.
+//!
+//! ## Features
+//! - Subscribes to the mark price and force order streams for each symbol on the Binance futures WebSocket feed.
+//! - Splits each mark price update into funding rate and index price records.
+//! - Parses force orders into liquidation records.
+//! - Subscribes to 1 minute klines and forwards every update as a candle.
+//! - Polls open interest through the REST API, as Binance does not stream it.
+//! - Subscribes to the 100ms depth diffs with `--binance-depth`, synchronized with REST snapshots as documented by
+//!   Binance: the diffs are buffered until the snapshot is received, the diffs older than the snapshot are dropped, the
+//!   first diff applied spans its `lastUpdateId` and each following diff chains to the previous one through `pu`.
+//! - Persists every diff received to `depth_diffs` and the snapshots once a diff applies to them to
+//!   `depth_snapshots`, one row per price level, so the books can be rebuilt from the tables.
+//! - Reports the broken chains of diffs as gap events and synchronizes the book again from a new snapshot.
+//! - Parses the symbols of the exchange info into reference data (tick and lot sizes, status, delivery date).
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dixit_persist_macros::Persist;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{from_str, json};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::time;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{info, warn};
+
+use crate::candles::Candle;
+use crate::clock::SharedClock;
+use crate::events::{Event as RecorderEvent, EventKind};
+use crate::model::{FundingRate, IndexPrice, Liquidation, OpenInterest, Record, Severity, VenueData};
+use crate::reference::Instrument;
+
+pub const EXCHANGE: &str = "binance-futures";
+pub const WS_URL: &str = "wss://fstream.binance.com/ws";
+pub const REST_URL: &str = "https://fapi.binance.com";
+pub const SYMBOLS: &[&str] = &["BTCUSDT", "ETHUSDT"];
+
+/// Limit of the depth snapshots, the deepest served by the REST API.
+const SNAPSHOT_LIMIT: u32 = 1000;
+/// Delay before requesting a depth snapshot again after a failure.
+const SNAPSHOT_RETRY: Duration = Duration::from_secs(1);
+
+pub fn subscribe(depth: bool, symbols: &[String]) -> Vec<Message> {
+    request("SUBSCRIBE", depth, symbols)
+}
+
+pub fn unsubscribe(depth: bool, symbols: &[String]) -> Vec<Message> {
+    request("UNSUBSCRIBE", depth, symbols)
+}
+
+fn request(method: &str, depth: bool, symbols: &[String]) -> Vec<Message> {
+    if symbols.is_empty() {
+        return vec![];
+    }
+    let streams = symbols
+        .iter()
+        .flat_map(|symbol| {
+            let symbol = symbol.to_lowercase();
+            let mut streams = vec![
+                format!("{symbol}@markPrice@1s"),
+                format!("{symbol}@forceOrder"),
+                format!("{symbol}@kline_1m"),
+            ];
+            if depth {
+                streams.push(format!("{symbol}@depth@100ms"));
+            }
+            streams
+        })
+        .collect::<Vec<_>>();
+    let request = json!({
+        "method": method,
+        "params": streams,
+        "id": 1
+    });
+    vec![Message::Text(request.to_string())]
+}
+
+/// Stateful handler, synchronizing the books of the depth diffs with the snapshots requested to a [`Snapshots`] task.
+pub struct Handler {
+    books: HashMap<String, Book>,
+    requests: UnboundedSender<String>,
+    snapshots: UnboundedReceiver<DepthSnapshot>,
+    /// clock of the gap events
+    clock: SharedClock,
+}
+
+/// Synchronization of the book of a symbol.
+enum Book {
+    /// snapshot requested, the diffs received meanwhile are buffered
+    Requested(Vec<UpdateIds>),
+    /// snapshot received, waiting for a diff spanning its last update id
+    Received(DepthSnapshot),
+    /// last update id applied
+    Synced(u64),
+}
+
+/// Update ids of a depth diff, `previous` is the last update id of the previous diff.
+#[derive(Debug, Clone, Copy)]
+struct UpdateIds {
+    first: u64,
+    last: u64,
+    previous: u64,
+}
+
+impl Handler {
+    pub fn new(rest_url: &str, clock: SharedClock) -> (Self, Snapshots) {
+        let (requests, requests_rx) = mpsc::unbounded_channel();
+        let (snapshots_tx, snapshots) = mpsc::unbounded_channel();
+        let handler = Self {
+            books: HashMap::new(),
+            requests,
+            snapshots,
+            clock,
+        };
+        (
+            handler,
+            Snapshots {
+                rest_url: rest_url.to_owned(),
+                requests: requests_rx,
+                snapshots: snapshots_tx,
+            },
+        )
+    }
+
+    pub fn handle(&mut self, message: Message) -> Vec<Record> {
+        match message {
+            Message::Text(string) => {
+                if let Ok(update) = from_str::<DepthUpdate>(&string) {
+                    return self.depth(update);
+                } else if let Ok(event) = from_str::<Event>(&string) {
+                    return parse(event);
+                } else if let Ok(response) = from_str::<ErrorResponse>(&string) {
+                    return vec![Record::error(
+                        EXCHANGE,
+                        "error",
+                        Severity::Warning,
+                        response.error.msg,
+                        format!("code {}", response.error.code),
+                    )];
+                }
+                vec![Record::unhandled(string)]
+            }
+            _ => vec![Record::non_text()],
+        }
+    }
+
+    fn depth(&mut self, update: DepthUpdate) -> Vec<Record> {
+        let mut records = vec![];
+        while let Ok(snapshot) = self.snapshots.try_recv() {
+            self.receive(snapshot, &mut records);
+        }
+
+        let ids = UpdateIds {
+            first: update.first_update_id,
+            last: update.last_update_id,
+            previous: update.previous_update_id,
+        };
+        let symbol = update.symbol.clone();
+        records.extend(update.levels());
+        match self.books.remove(&symbol) {
+            None => self.request(&symbol, vec![ids]),
+            Some(book) => self.apply(&symbol, book, ids, &mut records),
+        }
+        records
+    }
+
+    /// Applies the snapshot to the diffs buffered, unless the book was synchronized meanwhile.
+    fn receive(&mut self, snapshot: DepthSnapshot, records: &mut Vec<Record>) {
+        let symbol = snapshot.symbol.clone();
+        let buffered = match self.books.remove(&symbol) {
+            Some(Book::Requested(buffered)) => buffered,
+            Some(book) => {
+                self.books.insert(symbol, book);
+                return;
+            }
+            None => return,
+        };
+        self.books.insert(symbol.clone(), Book::Received(snapshot));
+        for ids in buffered {
+            let book = self.books.remove(&symbol).expect("book of the snapshot");
+            self.apply(&symbol, book, ids, records);
+        }
+    }
+
+    fn apply(&mut self, symbol: &str, book: Book, ids: UpdateIds, records: &mut Vec<Record>) {
+        match book {
+            Book::Requested(mut buffered) => {
+                buffered.push(ids);
+                self.books.insert(symbol.to_owned(), Book::Requested(buffered));
+            }
+            // diff older than the snapshot, dropped
+            Book::Received(snapshot) if ids.last < snapshot.last_update_id => {
+                self.books.insert(symbol.to_owned(), Book::Received(snapshot));
+            }
+            Book::Received(snapshot) if ids.first <= snapshot.last_update_id => {
+                info!("synchronized the {symbol} book at {}", snapshot.last_update_id);
+                records.extend(snapshot.levels());
+                self.books.insert(symbol.to_owned(), Book::Synced(ids.last));
+            }
+            Book::Received(snapshot) => {
+                warn!(
+                    "{symbol} snapshot at {} older than the diffs from {}, requesting a new one",
+                    snapshot.last_update_id, ids.first
+                );
+                self.request(symbol, vec![ids]);
+            }
+            Book::Synced(last) if ids.previous == last => {
+                self.books.insert(symbol.to_owned(), Book::Synced(ids.last));
+            }
+            Book::Synced(last) => {
+                let source = format!("{EXCHANGE}/{symbol}");
+                let gap = format!("expected previous update {last} received {}", ids.previous);
+                records.push(RecorderEvent::new(self.clock.now(), EventKind::Gap, &source, gap).record());
+                self.request(symbol, vec![ids]);
+            }
+        }
+    }
+
+    fn request(&mut self, symbol: &str, buffered: Vec<UpdateIds>) {
+        // the task only stops once the handler is dropped
+        let _ = self.requests.send(symbol.to_owned());
+        self.books.insert(symbol.to_owned(), Book::Requested(buffered));
+    }
+}
+
+/// Fetches the depth snapshots requested by the [`Handler`] from the REST API, until the handler is dropped.
+pub struct Snapshots {
+    rest_url: String,
+    requests: UnboundedReceiver<String>,
+    snapshots: UnboundedSender<DepthSnapshot>,
+}
+
+impl Snapshots {
+    pub async fn run(mut self) {
+        let client = reqwest::Client::new();
+        while let Some(symbol) = self.requests.recv().await {
+            let url = format!("{}/fapi/v1/depth?symbol={symbol}&limit={SNAPSHOT_LIMIT}", self.rest_url);
+            let snapshot = loop {
+                match snapshot(&client, &url).await {
+                    Ok(snapshot) => break snapshot,
+                    Err(e) => {
+                        warn!("failed to fetch the depth snapshot {url}: {e}");
+                        time::sleep(SNAPSHOT_RETRY).await;
+                    }
+                }
+            };
+            let snapshot = DepthSnapshot { symbol, ..snapshot };
+            if self.snapshots.send(snapshot).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+async fn snapshot(client: &reqwest::Client, url: &str) -> Result<DepthSnapshot> {
+    let response = client.get(url).send().await?.error_for_status()?;
+    Ok(from_str(&response.text().await?)?)
+}
+
+pub fn open_interest_urls(rest_url: &str, symbols: &[String]) -> Vec<String> {
+    symbols
+        .iter()
+        .map(|symbol| format!("{rest_url}/fapi/v1/openInterest?symbol={symbol}"))
+        .collect()
+}
+
+pub fn open_interest_key(response: &OpenInterestResponse) -> (String, DateTime<Utc>) {
+    (response.symbol.clone(), response.time)
+}
+
+pub fn open_interest(response: OpenInterestResponse) -> Record {
+    Record::data(
+        EXCHANGE,
+        "openInterest",
+        &response.symbol,
+        VenueData::OpenInterest(OpenInterest {
+            symbol: response.symbol.clone(),
+            instrument: None,
+            time: response.time,
+            open_interest: response.open_interest,
+        }),
+    )
+}
+
+fn parse(event: Event) -> Vec<Record> {
+    match event {
+        Event::MarkPrice(update) => {
+            vec![
+                Record::data(
+                    EXCHANGE,
+                    "funding",
+                    &update.symbol,
+                    VenueData::Funding(FundingRate {
+                        symbol: update.symbol.clone(),
+                        instrument: None,
+                        time: update.event_time,
+                        funding_rate: update.funding_rate,
+                        next_funding_time: Some(update.next_funding_time),
+                    }),
+                ),
+                Record::data(
+                    EXCHANGE,
+                    "index",
+                    &update.symbol,
+                    VenueData::Index(IndexPrice {
+                        symbol: update.symbol.clone(),
+                        instrument: None,
+                        time: update.event_time,
+                        index_price: update.index_price,
+                    }),
+                ),
+            ]
+        }
+        Event::ForceOrder(ForceOrder { order }) => vec![Record::data(
+            EXCHANGE,
+            "forceOrder",
+            &order.symbol,
+            VenueData::Liquidation(Liquidation {
+                symbol: order.symbol.clone(),
+                instrument: None,
+                time: order.trade_time,
+                side: order.side.to_lowercase(),
+                price: order.average_price,
+                size: order.filled_quantity,
+            }),
+        )],
+        Event::Kline(Kline { symbol, kline }) => vec![Record::data(
+            EXCHANGE,
+            "kline",
+            &symbol,
+            VenueData::Candle(Candle {
+                symbol: symbol.clone(),
+                instrument: None,
+                start: kline.start_time,
+                open: kline.open,
+                high: kline.high,
+                low: kline.low,
+                close: kline.close,
+                volume: kline.volume,
+                trade_count: Some(kline.trade_count),
+                missing: false,
+            }),
+        )],
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "e")]
+enum Event {
+    #[serde(rename = "markPriceUpdate")]
+    MarkPrice(MarkPriceUpdate),
+    #[serde(rename = "forceOrder")]
+    ForceOrder(ForceOrder),
+    #[serde(rename = "kline")]
+    Kline(Kline),
+}
+
+#[derive(Deserialize, Debug)]
+struct MarkPriceUpdate {
+    #[serde(rename = "E", with = "chrono::serde::ts_milliseconds")]
+    event_time: DateTime<Utc>,
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "i")]
+    index_price: Decimal,
+    #[serde(rename = "r")]
+    funding_rate: Decimal,
+    #[serde(rename = "T", with = "chrono::serde::ts_milliseconds")]
+    next_funding_time: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ForceOrder {
+    #[serde(rename = "o")]
+    order: Order,
+}
+
+#[derive(Deserialize, Debug)]
+struct Order {
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "S")]
+    side: String,
+    #[serde(rename = "ap")]
+    average_price: Decimal,
+    #[serde(rename = "z")]
+    filled_quantity: Decimal,
+    #[serde(rename = "T", with = "chrono::serde::ts_milliseconds")]
+    trade_time: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Kline {
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "k")]
+    kline: KlineData,
+}
+
+#[derive(Deserialize, Debug)]
+struct KlineData {
+    #[serde(rename = "t", with = "chrono::serde::ts_milliseconds")]
+    start_time: DateTime<Utc>,
+    #[serde(rename = "o")]
+    open: Decimal,
+    #[serde(rename = "h")]
+    high: Decimal,
+    #[serde(rename = "l")]
+    low: Decimal,
+    #[serde(rename = "c")]
+    close: Decimal,
+    #[serde(rename = "v")]
+    volume: Decimal,
+    #[serde(rename = "n")]
+    trade_count: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct DepthUpdate {
+    #[serde(rename = "E", with = "chrono::serde::ts_milliseconds")]
+    event_time: DateTime<Utc>,
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "U")]
+    first_update_id: u64,
+    #[serde(rename = "u")]
+    last_update_id: u64,
+    #[serde(rename = "pu")]
+    previous_update_id: u64,
+    #[serde(rename = "b")]
+    bids: Vec<(Decimal, Decimal)>,
+    #[serde(rename = "a")]
+    asks: Vec<(Decimal, Decimal)>,
+}
+
+impl DepthUpdate {
+    fn levels(self) -> Vec<Record> {
+        let level = |side: &str, (price, size): (Decimal, Decimal)| {
+            Record::data(
+                EXCHANGE,
+                "depth",
+                &self.symbol,
+                VenueData::DepthDiff(DepthLevel {
+                    symbol: self.symbol.clone(),
+                    time: self.event_time,
+                    first_update_id: self.first_update_id,
+                    last_update_id: self.last_update_id,
+                    previous_update_id: Some(self.previous_update_id),
+                    side: side.to_owned(),
+                    price,
+                    size,
+                }),
+            )
+        };
+        let bids = self.bids.iter().map(|bid| level("bid", *bid));
+        let asks = self.asks.iter().map(|ask| level("ask", *ask));
+        bids.chain(asks).collect()
+    }
+}
+
+/// Depth snapshot of the REST API, its symbol is set once fetched.
+#[derive(Deserialize, Debug)]
+pub struct DepthSnapshot {
+    #[serde(skip)]
+    symbol: String,
+    #[serde(rename = "lastUpdateId")]
+    last_update_id: u64,
+    #[serde(rename = "E", with = "chrono::serde::ts_milliseconds")]
+    event_time: DateTime<Utc>,
+    bids: Vec<(Decimal, Decimal)>,
+    asks: Vec<(Decimal, Decimal)>,
+}
+
+impl DepthSnapshot {
+    fn levels(self) -> Vec<Record> {
+        let level = |side: &str, (price, size): (Decimal, Decimal)| {
+            Record::data(
+                EXCHANGE,
+                "depth",
+                &self.symbol,
+                VenueData::DepthSnapshot(DepthLevel {
+                    symbol: self.symbol.clone(),
+                    time: self.event_time,
+                    first_update_id: self.last_update_id,
+                    last_update_id: self.last_update_id,
+                    previous_update_id: None,
+                    side: side.to_owned(),
+                    price,
+                    size,
+                }),
+            )
+        };
+        let bids = self.bids.iter().map(|bid| level("bid", *bid));
+        let asks = self.asks.iter().map(|ask| level("ask", *ask));
+        bids.chain(asks).collect()
+    }
+}
+
+/// A price level of a depth diff or snapshot, a diff of size zero removes the level from the book.
+///
+/// The levels of a snapshot have both update ids set to its `lastUpdateId` and no previous update id.
+#[derive(Debug, Persist)]
+pub struct DepthLevel {
+    #[persist(intern)]
+    pub symbol: String,
+    pub time: DateTime<Utc>,
+    pub first_update_id: u64,
+    pub last_update_id: u64,
+    pub previous_update_id: Option<u64>,
+    #[persist(intern)]
+    pub side: String,
+    pub price: Decimal,
+    pub size: Decimal,
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorDetail {
+    code: i64,
+    msg: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenInterestResponse {
+    symbol: String,
+    open_interest: Decimal,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    time: DateTime<Utc>,
+}
+
+pub fn reference_urls(rest_url: &str) -> Vec<String> {
+    vec![format!("{rest_url}/fapi/v1/exchangeInfo")]
+}
+
+pub fn instruments(body: &str, as_of: DateTime<Utc>) -> Result<Vec<Instrument>> {
+    let response = from_str::<ExchangeInfo>(body)?;
+    Ok(response
+        .symbols
+        .into_iter()
+        .map(|data| {
+            let mut instrument = Instrument {
+                symbol: data.symbol,
+                instrument: None,
+                as_of,
+                tick_size: None,
+                lot_size: None,
+                status: data.status,
+                // the perpetuals are delivered in 2100
+                expiry: (data.contract_type != "PERPETUAL").then_some(data.delivery_date),
+            };
+            for filter in data.filters {
+                match filter {
+                    Filter::Price { tick_size } => instrument.tick_size = Some(tick_size),
+                    Filter::LotSize { step_size } => instrument.lot_size = Some(step_size),
+                    Filter::Other => {}
+                }
+            }
+            instrument
+        })
+        .collect())
+}
+
+#[derive(Deserialize, Debug)]
+struct ExchangeInfo {
+    symbols: Vec<SymbolInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SymbolInfo {
+    symbol: String,
+    status: String,
+    contract_type: String,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    delivery_date: DateTime<Utc>,
+    filters: Vec<Filter>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "filterType")]
+enum Filter {
+    #[serde(rename = "PRICE_FILTER", rename_all = "camelCase")]
+    Price { tick_size: Decimal },
+    #[serde(rename = "LOT_SIZE", rename_all = "camelCase")]
+    LotSize { step_size: Decimal },
+    #[serde(other)]
+    Other,
+}
@@ -0,0 +1,274 @@
+//! Contains Bitfinex-specific WebSocket handling logic.
+//!
+//! ## Features
+//! - Subscribes to the trades, ticker and 1 minute candles channels for each symbol on the Bitfinex WebSocket feed.
+//! - Tracks the numeric channel ids assigned at subscribe time to route array-style payloads.
+//! - Parses incoming trade, ticker and candle arrays into typed records.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use dixit_persist_macros::Persist;
+use rust_decimal::Decimal;
+use serde::{de::Error, Deserialize};
+use serde_json::{from_str, json, Value};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::candles::Candle;
+use crate::clock::SharedClock;
+use crate::model::{Record, Severity, VenueData};
+
+pub const EXCHANGE: &str = "bitfinex";
+pub const WS_URL: &str = "wss://api-pub.bitfinex.com/ws/2";
+pub const SYMBOLS: &[&str] = &["tBTCUSD", "tETHUSD"];
+
+const CHANNELS: &[&str] = &["trades", "ticker"];
+const CANDLES_KEY: &str = "trade:1m:";
+
+pub fn subscribe(symbols: &[String]) -> Vec<Message> {
+    symbols
+        .iter()
+        .flat_map(|symbol| {
+            CHANNELS
+                .iter()
+                .map(move |channel| {
+                    json!({
+                        "event": "subscribe",
+                        "channel": channel,
+                        "symbol": symbol
+                    })
+                })
+                .chain([json!({
+                    "event": "subscribe",
+                    "channel": "candles",
+                    "key": format!("{CANDLES_KEY}{symbol}")
+                })])
+        })
+        .map(|subscription| Message::Text(subscription.to_string()))
+        .collect()
+}
+
+/// Stateful handler, Bitfinex only tags data messages with the channel id it assigned on subscription.
+pub struct Handler {
+    channels: HashMap<u64, (String, String)>,
+    /// clock of the receive time of the tickers
+    clock: SharedClock,
+}
+
+impl Handler {
+    pub fn new(clock: SharedClock) -> Self {
+        Self {
+            channels: HashMap::new(),
+            clock,
+        }
+    }
+
+    /// Unsubscribes every channel of the symbols, by the channel ids Bitfinex assigned to them.
+    pub fn unsubscribe(&self, symbols: &[String]) -> Vec<Message> {
+        self.channels
+            .iter()
+            .filter(|(_, (_, symbol))| symbols.contains(symbol))
+            .map(|(chan_id, _)| {
+                let request = json!({
+                    "event": "unsubscribe",
+                    "chanId": chan_id
+                });
+                Message::Text(request.to_string())
+            })
+            .collect()
+    }
+
+    pub fn handle(&mut self, message: Message) -> Vec<Record> {
+        match message {
+            Message::Text(string) => match from_str::<Value>(&string) {
+                Ok(Value::Object(_)) => self.handle_event(string),
+                Ok(Value::Array(array)) => self
+                    .handle_array(&array)
+                    .unwrap_or_else(|| vec![Record::unhandled(string)]),
+                _ => vec![Record::unhandled(string)],
+            },
+            _ => vec![Record::non_text()],
+        }
+    }
+
+    fn handle_event(&mut self, string: String) -> Vec<Record> {
+        match from_str::<Event>(&string) {
+            Ok(Event::Subscribed {
+                channel,
+                chan_id,
+                symbol,
+                key,
+            }) => {
+                // candles are subscribed by key rather than symbol
+                let symbol = symbol.or_else(|| key.and_then(|key| key.strip_prefix(CANDLES_KEY).map(str::to_owned)));
+                self.channels.insert(chan_id, (channel, symbol.unwrap_or_default()));
+                vec![Record::unhandled(string)]
+            }
+            Ok(Event::Unsubscribed { chan_id }) => {
+                self.channels.remove(&chan_id);
+                vec![Record::unhandled(string)]
+            }
+            // error events answer a single request, e.g. a subscription to an unknown symbol
+            Ok(Event::Error { msg, code }) => vec![Record::error(
+                EXCHANGE,
+                "error",
+                Severity::Warning,
+                msg,
+                format!("code {code}"),
+            )],
+            _ => vec![Record::unhandled(string)],
+        }
+    }
+
+    fn handle_array(&self, array: &[Value]) -> Option<Vec<Record>> {
+        let (channel, symbol) = self.channels.get(&array.first()?.as_u64()?)?;
+
+        let data = match (channel.as_str(), &array[1..]) {
+            ("trades", [Value::Array(snapshot)]) => snapshot
+                .iter()
+                .map(|trade| Trade::parse(symbol, trade).map(VenueData::BitfinexTrade))
+                .collect::<Result<Vec<_>, _>>()
+                .ok()?,
+            ("trades", [Value::String(kind), trade]) if kind == "te" => {
+                vec![VenueData::BitfinexTrade(Trade::parse(symbol, trade).ok()?)]
+            }
+            ("ticker", [ticker @ Value::Array(_)]) => {
+                let ticker = Ticker::parse(symbol, ticker, self.clock.now()).ok()?;
+                vec![VenueData::BitfinexTicker(ticker)]
+            }
+            // the candles snapshot holds past candles (newest first), only live updates are recorded
+            ("candles", [candle @ Value::Array(values)]) if values.first().is_some_and(Value::is_number) => {
+                vec![VenueData::Candle(parse_candle(symbol, candle).ok()?)]
+            }
+            _ => return None,
+        };
+
+        Some(
+            data.into_iter()
+                .map(|data| Record::Data {
+                    exchange: EXCHANGE.to_string(),
+                    channel: channel.clone(),
+                    symbol: symbol.clone(),
+                    data: Box::new(data),
+                })
+                .collect(),
+        )
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum Event {
+    Subscribed {
+        channel: String,
+        #[serde(rename = "chanId")]
+        chan_id: u64,
+        symbol: Option<String>,
+        key: Option<String>,
+    },
+    Unsubscribed {
+        #[serde(rename = "chanId")]
+        chan_id: u64,
+    },
+    Error {
+        msg: String,
+        code: u64,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Persist)]
+pub struct Trade {
+    pub symbol: String,
+    pub trade_id: u64,
+    pub time: DateTime<Utc>,
+    pub size: Decimal,
+    pub price: Decimal,
+    pub side: String,
+}
+
+impl Trade {
+    /// Parses `[ID, MTS, AMOUNT, PRICE]`, where a negative amount means the taker sold.
+    fn parse(symbol: &str, value: &Value) -> Result<Self, serde_json::Error> {
+        let (trade_id, mts, amount, price) = <(u64, i64, Decimal, Decimal)>::deserialize(value)?;
+        Ok(Self {
+            symbol: symbol.to_owned(),
+            trade_id,
+            time: DateTime::from_timestamp_millis(mts)
+                .ok_or_else(|| serde_json::Error::custom(format!("invalid timestamp {mts}")))?,
+            size: amount.abs(),
+            price,
+            side: if amount.is_sign_negative() { "sell" } else { "buy" }.to_owned(),
+        })
+    }
+}
+
+#[derive(Debug, Persist)]
+pub struct Ticker {
+    pub symbol: String,
+    pub time: DateTime<Utc>,
+    pub bid: Decimal,
+    pub bid_size: Decimal,
+    pub ask: Decimal,
+    pub ask_size: Decimal,
+    pub daily_change: Decimal,
+    pub daily_change_relative: Decimal,
+    pub last_price: Decimal,
+    pub volume: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+}
+
+impl Ticker {
+    /// Parses `[BID, BID_SIZE, ASK, ASK_SIZE, DAILY_CHANGE, DAILY_CHANGE_RELATIVE, LAST_PRICE, VOLUME, HIGH, LOW]`,
+    /// tickers carry no timestamp so the receive `time` is used instead.
+    fn parse(symbol: &str, value: &Value, time: DateTime<Utc>) -> Result<Self, serde_json::Error> {
+        let (bid, bid_size, ask, ask_size, daily_change, daily_change_relative, last_price, volume, high, low) =
+            <(
+                Decimal,
+                Decimal,
+                Decimal,
+                Decimal,
+                Decimal,
+                Decimal,
+                Decimal,
+                Decimal,
+                Decimal,
+                Decimal,
+            )>::deserialize(value)?;
+        Ok(Self {
+            symbol: symbol.to_owned(),
+            time,
+            bid,
+            bid_size,
+            ask,
+            ask_size,
+            daily_change,
+            daily_change_relative,
+            last_price,
+            volume,
+            high,
+            low,
+        })
+    }
+}
+
+/// Parses `[MTS, OPEN, CLOSE, HIGH, LOW, VOLUME]`.
+fn parse_candle(symbol: &str, value: &Value) -> Result<Candle, serde_json::Error> {
+    let (mts, open, close, high, low, volume) =
+        <(i64, Decimal, Decimal, Decimal, Decimal, Decimal)>::deserialize(value)?;
+    Ok(Candle {
+        symbol: symbol.to_owned(),
+        instrument: None,
+        start: DateTime::from_timestamp_millis(mts)
+            .ok_or_else(|| serde_json::Error::custom(format!("invalid timestamp {mts}")))?,
+        open,
+        high,
+        low,
+        close,
+        volume,
+        trade_count: None,
+        missing: false,
+    })
+}
@@ -0,0 +1,150 @@
+//! Contains Bithumb-specific WebSocket handling logic.
+//!
+//! ## Features
+//! - Subscribes to the transactions of the KRW markets (e.g. `BTC_KRW`) on the Bithumb public feed, with a
+//!   `transaction` filter listing the symbols.
+//! - Parses the transactions into normalized trade records persisted to the `trades` table, for KRW premium analysis
+//!   against the venues quoting in USD.
+//! - Reports the rejected filters, answered with a status other than `0000`, as errors.
+//!
+//! A filter replaces the previous one of the connection, so the [`Subscriber`] keeps the symbols subscribed and every
+//! change registers all of them. Unsubscribing every symbol is not possible, they stay subscribed until the next
+//! connection.
+
+use std::sync::Mutex;
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{de, Deserialize, Deserializer};
+use serde_json::{from_str, json};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::model::{Record, Severity, Trade, VenueData};
+
+pub const EXCHANGE: &str = "bithumb";
+pub const WS_URL: &str = "wss://pubwss.bithumb.com/pub/ws";
+pub const SYMBOLS: &[&str] = &["BTC_KRW", "ETH_KRW"];
+
+/// Status of the successful answers.
+const OK: &str = "0000";
+
+/// Offset of the Korea Standard Time, in which Bithumb gives the transaction times.
+const KST_SECONDS: i32 = 9 * 3600;
+
+/// Symbols subscribed on the connection.
+#[derive(Default)]
+pub struct Subscriber {
+    symbols: Mutex<Vec<String>>,
+}
+
+impl Subscriber {
+    pub fn subscribe(&self, symbols: &[String]) -> Vec<Message> {
+        let mut subscribed = self.symbols.lock().expect("symbols poisoned");
+        for symbol in symbols {
+            if !subscribed.contains(symbol) {
+                subscribed.push(symbol.clone());
+            }
+        }
+        request(&subscribed)
+    }
+
+    pub fn unsubscribe(&self, symbols: &[String]) -> Vec<Message> {
+        let mut subscribed = self.symbols.lock().expect("symbols poisoned");
+        subscribed.retain(|symbol| !symbols.contains(symbol));
+        request(&subscribed)
+    }
+}
+
+fn request(symbols: &[String]) -> Vec<Message> {
+    if symbols.is_empty() {
+        return vec![];
+    }
+    let request = json!({
+        "type": "transaction",
+        "symbols": symbols
+    });
+    vec![Message::Text(request.to_string())]
+}
+
+pub fn handle(message: Message) -> Vec<Record> {
+    match message {
+        Message::Text(string) => {
+            if let Ok(push) = from_str::<Push>(&string) {
+                if push.kind == "transaction" {
+                    return push.content.list.into_iter().map(parse_transaction).collect();
+                }
+            } else if let Ok(status) = from_str::<Status>(&string) {
+                if status.status != OK {
+                    return vec![Record::error(
+                        EXCHANGE,
+                        "transaction",
+                        Severity::Warning,
+                        status.resmsg,
+                        format!("status {}", status.status),
+                    )];
+                }
+            }
+            vec![Record::unhandled(string)]
+        }
+        _ => vec![Record::non_text()],
+    }
+}
+
+/// Bithumb gives the side of the taker order as `1` (sell) or `2` (buy).
+fn parse_transaction(transaction: Transaction) -> Record {
+    let side = if transaction.buy_sell_gb == "2" { "buy" } else { "sell" };
+    Record::data(
+        EXCHANGE,
+        "trades",
+        &transaction.symbol,
+        VenueData::Trade(Trade {
+            symbol: transaction.symbol.clone(),
+            instrument: None,
+            time: transaction.cont_dtm,
+            price: transaction.cont_price,
+            size: transaction.cont_qty,
+            side: side.to_owned(),
+        }),
+    )
+}
+
+/// Parses a time of the Korea Standard Time without offset, e.g. `2024-03-01 19:40:03.830039`.
+fn kst<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    let string = String::deserialize(deserializer)?;
+    let kst = FixedOffset::east_opt(KST_SECONDS).expect("valid offset");
+    NaiveDateTime::parse_from_str(&string, "%Y-%m-%d %H:%M:%S%.f")
+        .ok()
+        .and_then(|time| time.and_local_timezone(kst).single())
+        .map(|time| time.to_utc())
+        .ok_or_else(|| de::Error::custom(format!("invalid time {string}")))
+}
+
+#[derive(Deserialize, Debug)]
+struct Push {
+    #[serde(rename = "type")]
+    kind: String,
+    content: Content,
+}
+
+#[derive(Deserialize, Debug)]
+struct Content {
+    list: Vec<Transaction>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Transaction {
+    symbol: String,
+    buy_sell_gb: String,
+    cont_price: Decimal,
+    cont_qty: Decimal,
+    #[serde(deserialize_with = "kst")]
+    cont_dtm: DateTime<Utc>,
+}
+
+/// Answer to the connection and to the filters, e.g. `Filter Registered Successfully`.
+#[derive(Deserialize, Debug)]
+struct Status {
+    status: String,
+    resmsg: String,
+}
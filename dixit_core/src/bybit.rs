@@ -0,0 +1,314 @@
+//! Contains Bybit-specific WebSocket handling logic.
+//!
+//! ## Features
+//! - Subscribes to the linear perpetual tickers and liquidation topics for each symbol on the Bybit v5 public feed.
+//! - Extracts funding rate, open interest and index price records from ticker snapshots and deltas.
+//! - Parses liquidation pushes into liquidation records.
+//! - Subscribes to 1 minute klines and forwards every update as a candle.
+//! - Parses the linear instruments of the REST API into reference data (tick and lot sizes, status, delivery time).
+//!
+//! Deltas only carry the fields that changed, so a single message yields between zero and three records.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{de, Deserialize, Deserializer};
+use serde_json::{from_str, json, Value};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::candles::Candle;
+use crate::model::{FundingRate, IndexPrice, Liquidation, OpenInterest, Record, Severity, SkipReason, VenueData};
+use crate::reference::Instrument;
+
+pub const EXCHANGE: &str = "bybit";
+pub const WS_URL: &str = "wss://stream.bybit.com/v5/public/linear";
+pub const REST_URL: &str = "https://api.bybit.com";
+pub const SYMBOLS: &[&str] = &["BTCUSDT", "ETHUSDT"];
+
+pub fn subscribe(symbols: &[String]) -> Vec<Message> {
+    request("subscribe", symbols)
+}
+
+pub fn unsubscribe(symbols: &[String]) -> Vec<Message> {
+    request("unsubscribe", symbols)
+}
+
+fn request(op: &str, symbols: &[String]) -> Vec<Message> {
+    if symbols.is_empty() {
+        return vec![];
+    }
+    let topics = symbols
+        .iter()
+        .flat_map(|symbol| {
+            [
+                format!("tickers.{symbol}"),
+                format!("liquidation.{symbol}"),
+                format!("kline.1.{symbol}"),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let request = json!({
+        "op": op,
+        "args": topics
+    });
+    vec![Message::Text(request.to_string())]
+}
+
+pub fn handle(message: Message) -> Vec<Record> {
+    match message {
+        Message::Text(string) => {
+            if let Ok(push) = from_str::<Push>(&string) {
+                let records = match push.topic.split_once('.') {
+                    Some(("tickers", _)) => TickerData::deserialize(&push.data)
+                        .ok()
+                        .map(|data| parse_ticker(push.ts, data)),
+                    Some(("kline", topic)) => Vec::<KlineData>::deserialize(&push.data)
+                        .ok()
+                        .and_then(|data| parse_klines(topic, data)),
+                    Some(("liquidation", _)) => LiquidationData::deserialize(&push.data).ok().map(parse_liquidation),
+                    _ => None,
+                };
+                if let Some(records) = records {
+                    return records;
+                }
+            } else if let Ok(response) = from_str::<Response>(&string) {
+                if !response.success {
+                    return vec![Record::error(
+                        EXCHANGE,
+                        &response.op,
+                        Severity::Warning,
+                        response.ret_msg,
+                        format!("{} rejected", response.op),
+                    )];
+                }
+            }
+            vec![Record::unhandled(string)]
+        }
+        _ => vec![Record::non_text()],
+    }
+}
+
+fn parse_ticker(time: DateTime<Utc>, data: TickerData) -> Vec<Record> {
+    let symbol = data.symbol;
+    let mut records = vec![];
+
+    if let Some(funding_rate) = data.funding_rate {
+        records.push(Record::data(
+            EXCHANGE,
+            "funding",
+            &symbol,
+            VenueData::Funding(FundingRate {
+                symbol: symbol.clone(),
+                instrument: None,
+                time,
+                funding_rate,
+                next_funding_time: data.next_funding_time,
+            }),
+        ));
+    }
+    if let Some(open_interest) = data.open_interest {
+        records.push(Record::data(
+            EXCHANGE,
+            "open_interest",
+            &symbol,
+            VenueData::OpenInterest(OpenInterest {
+                symbol: symbol.clone(),
+                instrument: None,
+                time,
+                open_interest,
+            }),
+        ));
+    }
+    if let Some(index_price) = data.index_price {
+        records.push(Record::data(
+            EXCHANGE,
+            "index",
+            &symbol,
+            VenueData::Index(IndexPrice {
+                symbol: symbol.clone(),
+                instrument: None,
+                time,
+                index_price,
+            }),
+        ));
+    }
+
+    if records.is_empty() {
+        records.push(Record::skip(
+            SkipReason::Other,
+            format!("no funding, open interest or index change for {symbol}"),
+        ));
+    }
+    records
+}
+
+/// Bybit reports the side of the liquidated position, flip it to get the side of the liquidation order.
+fn parse_liquidation(data: LiquidationData) -> Vec<Record> {
+    let side = if data.side == "Buy" { "sell" } else { "buy" };
+    vec![Record::data(
+        EXCHANGE,
+        "liquidation",
+        &data.symbol,
+        VenueData::Liquidation(Liquidation {
+            symbol: data.symbol.clone(),
+            instrument: None,
+            time: data.updated_time,
+            side: side.to_owned(),
+            price: data.price,
+            size: data.size,
+        }),
+    )]
+}
+
+/// The kline topic is `kline.{interval}.{symbol}`, the payload does not repeat the symbol.
+fn parse_klines(topic: &str, data: Vec<KlineData>) -> Option<Vec<Record>> {
+    let (_, symbol) = topic.split_once('.')?;
+    Some(
+        data.into_iter()
+            .map(|kline| {
+                Record::data(
+                    EXCHANGE,
+                    "kline",
+                    symbol,
+                    VenueData::Candle(Candle {
+                        symbol: symbol.to_owned(),
+                        instrument: None,
+                        start: kline.start,
+                        open: kline.open,
+                        high: kline.high,
+                        low: kline.low,
+                        close: kline.close,
+                        volume: kline.volume,
+                        trade_count: None,
+                        missing: false,
+                    }),
+                )
+            })
+            .collect(),
+    )
+}
+
+/// Bybit encodes epoch milliseconds as strings inside the ticker payload.
+fn millis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+    let Some(string) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    string
+        .parse::<i64>()
+        .ok()
+        .and_then(DateTime::from_timestamp_millis)
+        .map(Some)
+        .ok_or_else(|| de::Error::custom(format!("invalid timestamp {string}")))
+}
+
+#[derive(Deserialize, Debug)]
+struct Push {
+    topic: String,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    ts: DateTime<Utc>,
+    data: Value,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TickerData {
+    symbol: String,
+    funding_rate: Option<Decimal>,
+    #[serde(default, deserialize_with = "millis")]
+    next_funding_time: Option<DateTime<Utc>>,
+    open_interest: Option<Decimal>,
+    index_price: Option<Decimal>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LiquidationData {
+    symbol: String,
+    side: String,
+    price: Decimal,
+    size: Decimal,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    updated_time: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+struct KlineData {
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    start: DateTime<Utc>,
+    open: Decimal,
+    high: Decimal,
+    low: Decimal,
+    close: Decimal,
+    volume: Decimal,
+}
+
+#[derive(Deserialize, Debug)]
+struct Response {
+    success: bool,
+    #[serde(default)]
+    ret_msg: String,
+    #[serde(default)]
+    op: String,
+}
+
+pub fn reference_urls(rest_url: &str) -> Vec<String> {
+    vec![format!(
+        "{rest_url}/v5/market/instruments-info?category=linear&limit=1000"
+    )]
+}
+
+pub fn instruments(body: &str, as_of: DateTime<Utc>) -> Result<Vec<Instrument>> {
+    let response = from_str::<InstrumentsResponse>(body)?;
+    Ok(response
+        .result
+        .list
+        .into_iter()
+        .map(|data| Instrument {
+            symbol: data.symbol,
+            instrument: None,
+            as_of,
+            tick_size: Some(data.price_filter.tick_size),
+            lot_size: Some(data.lot_size_filter.qty_step),
+            status: data.status,
+            // "0" for the perpetuals
+            expiry: data
+                .delivery_time
+                .parse()
+                .ok()
+                .filter(|millis| *millis > 0)
+                .and_then(DateTime::from_timestamp_millis),
+        })
+        .collect())
+}
+
+#[derive(Deserialize, Debug)]
+struct InstrumentsResponse {
+    result: InstrumentsResult,
+}
+
+#[derive(Deserialize, Debug)]
+struct InstrumentsResult {
+    list: Vec<InstrumentData>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct InstrumentData {
+    symbol: String,
+    status: String,
+    delivery_time: String,
+    price_filter: PriceFilter,
+    lot_size_filter: LotSizeFilter,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PriceFilter {
+    tick_size: Decimal,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LotSizeFilter {
+    qty_step: Decimal,
+}
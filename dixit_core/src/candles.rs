@@ -0,0 +1,95 @@
+//! Closes and gap-fills 1 minute candles streamed by the venues.
+//!
+//! ## Features
+//! - Defines the normalized [`Candle`] persisted to the `candles` table.
+//! - Keeps the latest update of the open candle per symbol and only emits it once a later interval starts,
+//!   so venues that never flag candles as closed are handled the same way as those that do.
+//! - Emits candles flagged as `missing` for every interval skipped between two emitted candles, up to
+//!   [`MAX_FILLED`] of them, a longer gap (e.g. a symbol not traded for days) being only marked by its first missing
+//!   candle.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use dixit_persist_macros::Persist;
+use rust_decimal::Decimal;
+use tracing::warn;
+
+pub const INTERVAL: TimeDelta = TimeDelta::minutes(1);
+/// most missing candles emitted for a single gap, an hour of 1 minute candles
+pub const MAX_FILLED: i64 = 60;
+
+/// `missing` candles were not received from the venue, they repeat the previous close with no volume.
+#[derive(Debug, Clone, Persist)]
+pub struct Candle {
+    pub symbol: String,
+    #[persist(intern)]
+    pub instrument: Option<String>,
+    pub start: DateTime<Utc>,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,
+    pub trade_count: Option<u64>,
+    pub missing: bool,
+}
+
+impl Candle {
+    fn missing(previous: &Candle, start: DateTime<Utc>) -> Self {
+        Self {
+            symbol: previous.symbol.clone(),
+            instrument: previous.instrument.clone(),
+            start,
+            open: previous.close,
+            high: previous.close,
+            low: previous.close,
+            close: previous.close,
+            volume: Decimal::ZERO,
+            trade_count: previous.trade_count.map(|_| 0),
+            missing: true,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CandleAggregator {
+    open: HashMap<String, Candle>,
+}
+
+impl CandleAggregator {
+    /// Returns the candles closed by this update, in chronological order.
+    pub fn update(&mut self, candle: Candle) -> Vec<Candle> {
+        let Some(open) = self.open.get_mut(&candle.symbol) else {
+            self.open.insert(candle.symbol.clone(), candle);
+            return vec![];
+        };
+
+        if candle.start <= open.start {
+            // late or repeated update of the open candle, anything older is already persisted
+            if candle.start == open.start {
+                *open = candle;
+            }
+            return vec![];
+        }
+
+        let closed = std::mem::replace(open, candle);
+        let mut start = closed.start + INTERVAL;
+        let interval = INTERVAL.num_seconds();
+        let missing = ((open.start - start).num_seconds() + interval - 1).div_euclid(interval);
+        let mut candles = vec![closed];
+        if missing > MAX_FILLED {
+            warn!(
+                "{missing} candles missing for {} from {start}, only the first one is emitted",
+                open.symbol
+            );
+            candles.push(Candle::missing(&candles[0], start));
+            return candles;
+        }
+        while start < open.start {
+            candles.push(Candle::missing(&candles[0], start));
+            start += INTERVAL;
+        }
+        candles
+    }
+}
@@ -0,0 +1,502 @@
+//! Registers the recorded tables in the AWS Glue Data Catalog as they are written, so Athena or Presto see the new
+//! data without running crawlers (`glue` feature).
+//!
+//! ## Features
+//! - Creates the table once its first file is written (updating it if it already exists, e.g. on a schema change),
+//!   with the columns of the written schema and the location of the table under `--glue-location`, the S3 location
+//!   the output directory is uploaded to (e.g. with `--upload`).
+//! - Partitions the tables routed by `{date}` by a `date` string key, adding the partition of every new date.
+//! - Registers the parquet tables, and the JSON lines and CSV tables unless zstd compressed with a dictionary, the
+//!   delta and iceberg tables being left to their own catalogs.
+//! - Calls the Glue JSON API directly, signed with SigV4 from the `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`,
+//!   `AWS_SESSION_TOKEN` and `AWS_REGION` environment variables, `--glue-endpoint` overriding the regional endpoint
+//!   (e.g. for LocalStack).
+//! - Runs beside the persister, whose writes never wait on the catalog, the registrations being persisted to the
+//!   `_events` table and the failures logged.
+//!
+//! ## Example
+//! ```sh
+//! dixit coinbase --upload s3://bucket/dixit --glue-database markets --glue-location s3://bucket/dixit
+//! ```
+
+use std::collections::HashSet;
+use std::env;
+use std::fmt::Write as _;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use aws_lc_rs::{digest, hmac};
+use chrono::{DateTime, NaiveDate, Utc};
+use dixit_persist::config::Format;
+use dixit_persist::csv::CsvOptions;
+use dixit_persist::service::ServiceEvent;
+use parquet::basic::{LogicalType, Type as PhysicalType};
+use parquet::schema::types::Type;
+use serde_json::{json, Value};
+use tokio::sync::mpsc::{UnboundedReceiver, WeakSender};
+use tracing::{error, info, warn};
+
+use crate::clock::SharedClock;
+use crate::events::{Event, EventKind};
+use crate::model::Record;
+
+const SERVICE: &str = "glue";
+const PARTITION_KEY: &str = "date";
+
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub access_key: String,
+    pub secret_key: String,
+    pub session_token: Option<String>,
+}
+
+impl Credentials {
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            access_key: env::var("AWS_ACCESS_KEY_ID").context("AWS_ACCESS_KEY_ID is required by the glue catalog")?,
+            secret_key: env::var("AWS_SECRET_ACCESS_KEY")
+                .context("AWS_SECRET_ACCESS_KEY is required by the glue catalog")?,
+            session_token: env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GlueConfig {
+    pub database: String,
+    /// S3 location of the output directory, e.g. `s3://bucket/dixit`
+    pub location: String,
+    pub region: String,
+    /// endpoint of the Glue API, `https://glue.{region}.amazonaws.com` if unset
+    pub endpoint: Option<String>,
+    pub credentials: Credentials,
+    /// whether the tables are partitioned by date, see the `route` module
+    pub dated: bool,
+    pub csv: CsvOptions,
+    /// whether the JSON lines and CSV tables are zstd compressed with a dictionary, unreadable by Athena
+    pub zstd_dictionary: bool,
+    /// clock the requests are signed and the registrations reported with
+    pub clock: SharedClock,
+}
+
+/// A table whose first file was written, see [`ServiceEvent::TableCreated`].
+#[derive(Debug, Clone)]
+pub struct CreatedTable {
+    /// path of the table relative to the output directory, e.g. `coinbase/trades/2024-03-01`
+    pub table: String,
+    pub format: Format,
+    pub schema: Arc<Type>,
+}
+
+/// A request to the Glue API, signed with SigV4.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedRequest {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+pub struct Glue {
+    client: reqwest::Client,
+    config: GlueConfig,
+    /// tables created or updated by this run, by Glue name
+    tables: HashSet<String>,
+    /// partitions added by this run, by Glue name and value
+    partitions: HashSet<(String, String)>,
+}
+
+impl Glue {
+    pub fn new(config: GlueConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            config,
+            tables: HashSet::new(),
+            partitions: HashSet::new(),
+        }
+    }
+
+    /// Registers a table and its partition if not done yet by this run, returns what was registered.
+    pub async fn register(&mut self, created: &CreatedTable) -> Result<Option<String>> {
+        let Some(storage) = self.storage(created)? else {
+            return Ok(None);
+        };
+        let date = if self.config.dated { date(&created.table) } else { None };
+        let name = table_name(&created.table, date.as_ref());
+        let mut registered = vec![];
+        if self.tables.insert(name.clone()) {
+            let input = self.table_input(&name, created, &storage, date.as_ref());
+            let body = json!({"DatabaseName": self.config.database, "TableInput": input});
+            let result = match self.call("CreateTable", &body).await {
+                Err(GlueError::AlreadyExists) => self.call("UpdateTable", &body).await.map(|_| "updated"),
+                result => result.map(|_| "created"),
+            };
+            match result {
+                Ok(action) => registered.push(format!("{action} table {name}")),
+                Err(e) => {
+                    // registered again with the next file of the table
+                    self.tables.remove(&name);
+                    return Err(e.into());
+                }
+            }
+        }
+        if let Some((value, _)) = date {
+            if self.partitions.insert((name.clone(), value.clone())) {
+                let body = json!({
+                    "DatabaseName": self.config.database,
+                    "TableName": name,
+                    "PartitionInput": {
+                        "Values": [value],
+                        "StorageDescriptor": storage_descriptor(&storage, self.location(&created.table)),
+                    },
+                });
+                match self.call("CreatePartition", &body).await {
+                    Ok(_) => registered.push(format!("added partition {PARTITION_KEY}={value}")),
+                    Err(GlueError::AlreadyExists) => (),
+                    Err(e) => {
+                        self.partitions.remove(&(name, value));
+                        return Err(e.into());
+                    }
+                }
+            }
+        }
+        Ok((!registered.is_empty()).then(|| registered.join(", ")))
+    }
+
+    /// Storage of the table, `None` if the format cannot be read from the catalog.
+    fn storage(&self, created: &CreatedTable) -> Result<Option<Storage>> {
+        let (serde, input, output, parameters) = match created.format {
+            Format::Parquet => (
+                "org.apache.hadoop.hive.ql.io.parquet.serde.ParquetHiveSerDe",
+                "org.apache.hadoop.hive.ql.io.parquet.MapredParquetInputFormat",
+                "org.apache.hadoop.hive.ql.io.parquet.MapredParquetOutputFormat",
+                json!({}),
+            ),
+            Format::Jsonl | Format::Csv if self.config.zstd_dictionary => {
+                warn!("{} is zstd compressed with a dictionary, not registered", created.table);
+                return Ok(None);
+            }
+            Format::Jsonl => (
+                "org.openx.data.jsonserde.JsonSerDe",
+                "org.apache.hadoop.mapred.TextInputFormat",
+                "org.apache.hadoop.hive.ql.io.HiveIgnoreKeyTextOutputFormat",
+                json!({}),
+            ),
+            Format::Csv => (
+                "org.apache.hadoop.hive.serde2.lazy.LazySimpleSerDe",
+                "org.apache.hadoop.mapred.TextInputFormat",
+                "org.apache.hadoop.hive.ql.io.HiveIgnoreKeyTextOutputFormat",
+                json!({"field.delim": self.config.csv.delimiter.to_string(), "skip.header.line.count": "1"}),
+            ),
+            Format::Delta | Format::Iceberg => {
+                warn!("{} is a {:?} table, not registered", created.table, created.format);
+                return Ok(None);
+            }
+        };
+        let columns = created
+            .schema
+            .get_fields()
+            .iter()
+            .map(|field| {
+                Ok(json!({"Name": field.name().to_ascii_lowercase(), "Type": hive_type(field, created.format)?}))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(Storage {
+            columns,
+            serde,
+            input,
+            output,
+            parameters,
+            classification: match created.format {
+                Format::Parquet => "parquet",
+                Format::Jsonl => "json",
+                _ => "csv",
+            },
+        }))
+    }
+
+    fn table_input(
+        &self,
+        name: &str,
+        created: &CreatedTable,
+        storage: &Storage,
+        date: Option<&(String, usize)>,
+    ) -> Value {
+        // the dated tables are located at the directory holding their dates
+        let location = match date {
+            Some((_, start)) => self.location(created.table[..*start].trim_end_matches(['/', '_', '-'])),
+            None => self.location(&created.table),
+        };
+        let partition_keys = match date {
+            Some(_) => json!([{"Name": PARTITION_KEY, "Type": "string"}]),
+            None => json!([]),
+        };
+        json!({
+            "Name": name,
+            "TableType": "EXTERNAL_TABLE",
+            "Parameters": {"classification": storage.classification, "EXTERNAL": "TRUE"},
+            "PartitionKeys": partition_keys,
+            "StorageDescriptor": storage_descriptor(storage, location),
+        })
+    }
+
+    /// S3 location of a table path, a directory ending with `/`.
+    fn location(&self, table: &str) -> String {
+        let base = self.config.location.trim_end_matches('/');
+        if table.is_empty() {
+            format!("{base}/")
+        } else {
+            format!("{base}/{table}/")
+        }
+    }
+
+    async fn call(&self, action: &str, body: &Value) -> Result<Value, GlueError> {
+        let request = sign(&self.config, action, serde_json::to_vec(body)?, self.config.clock.now());
+        let mut builder = self.client.post(&request.url);
+        for (name, value) in request.headers.iter().filter(|(name, _)| name != "host") {
+            builder = builder.header(name, value);
+        }
+        let response = builder.body(request.body).send().await?;
+        let status = response.status();
+        let body = response.bytes().await?;
+        let value = serde_json::from_slice::<Value>(&body).unwrap_or(Value::Null);
+        if status.is_success() {
+            return Ok(value);
+        }
+        let kind = value["__type"].as_str().unwrap_or_default();
+        if kind.ends_with("AlreadyExistsException") {
+            return Err(GlueError::AlreadyExists);
+        }
+        Err(GlueError::Other(anyhow!(
+            "{action} failed with {status}: {}",
+            String::from_utf8_lossy(&body)
+        )))
+    }
+}
+
+struct Storage {
+    columns: Vec<Value>,
+    serde: &'static str,
+    input: &'static str,
+    output: &'static str,
+    parameters: Value,
+    classification: &'static str,
+}
+
+fn storage_descriptor(storage: &Storage, location: String) -> Value {
+    json!({
+        "Columns": storage.columns,
+        "Location": location,
+        "InputFormat": storage.input,
+        "OutputFormat": storage.output,
+        "SerdeInfo": {"SerializationLibrary": storage.serde, "Parameters": storage.parameters},
+    })
+}
+
+#[derive(Debug)]
+enum GlueError {
+    AlreadyExists,
+    Other(anyhow::Error),
+}
+
+impl std::fmt::Display for GlueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GlueError::AlreadyExists => write!(f, "already exists"),
+            GlueError::Other(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for GlueError {}
+
+impl From<reqwest::Error> for GlueError {
+    fn from(e: reqwest::Error) -> Self {
+        GlueError::Other(e.into())
+    }
+}
+
+impl From<serde_json::Error> for GlueError {
+    fn from(e: serde_json::Error) -> Self {
+        GlueError::Other(e.into())
+    }
+}
+
+/// The date of a dated table path with its position, e.g. `2024-03-01` in `coinbase/trades/2024-03-01`.
+pub fn date(table: &str) -> Option<(String, usize)> {
+    (0..table.len().saturating_sub(9)).find_map(|start| {
+        let candidate = table.get(start..start + 10)?;
+        NaiveDate::parse_from_str(candidate, "%Y-%m-%d")
+            .ok()
+            .map(|_| (candidate.to_owned(), start))
+    })
+}
+
+/// Name of the Glue table of a table path, its date left out, e.g. `coinbase_trades` for
+/// `coinbase/trades/2024-03-01`.
+pub fn table_name(table: &str, date: Option<&(String, usize)>) -> String {
+    let table = match date {
+        Some((value, start)) => format!("{}{}", &table[..*start], &table[start + value.len()..]),
+        None => table.to_owned(),
+    };
+    let name = table
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Hive type of a column, the timestamps of the JSON lines and CSV tables being RFC 3339 strings.
+pub fn hive_type(field: &Type, format: Format) -> Result<&'static str> {
+    let info = field.get_basic_info();
+    if field.is_group() {
+        bail!("column {} is a group, not supported by the catalog", field.name());
+    }
+    Ok(match (field.get_physical_type(), info.logical_type()) {
+        (PhysicalType::BOOLEAN, _) => "boolean",
+        (PhysicalType::INT32, _) => "int",
+        (PhysicalType::INT64, Some(LogicalType::Timestamp { .. })) | (PhysicalType::INT96, _) => match format {
+            Format::Parquet => "timestamp",
+            _ => "string",
+        },
+        (PhysicalType::INT64, _) => "bigint",
+        (PhysicalType::FLOAT, _) => "float",
+        (PhysicalType::DOUBLE, _) => "double",
+        (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)) => "string",
+        (PhysicalType::BYTE_ARRAY | PhysicalType::FIXED_LEN_BYTE_ARRAY, _) => "binary",
+    })
+}
+
+/// Signs a call of a Glue action with SigV4.
+pub fn sign(config: &GlueConfig, action: &str, body: Vec<u8>, now: DateTime<Utc>) -> SignedRequest {
+    let url = config
+        .endpoint
+        .clone()
+        .unwrap_or_else(|| format!("https://glue.{}.amazonaws.com/", config.region));
+    let host = url
+        .split("://")
+        .nth(1)
+        .and_then(|rest| rest.split('/').next())
+        .unwrap_or_default()
+        .to_owned();
+    let mut headers = vec![
+        ("content-type".to_owned(), "application/x-amz-json-1.1".to_owned()),
+        ("host".to_owned(), host),
+        ("x-amz-date".to_owned(), now.format("%Y%m%dT%H%M%SZ").to_string()),
+        ("x-amz-target".to_owned(), format!("AWSGlue.{action}")),
+    ];
+    if let Some(token) = &config.credentials.session_token {
+        headers.push(("x-amz-security-token".to_owned(), token.clone()));
+    }
+    let path = url
+        .split("://")
+        .nth(1)
+        .and_then(|rest| rest.find('/').map(|i| &rest[i..]));
+    let authorization = authorization(
+        &config.credentials,
+        &config.region,
+        SERVICE,
+        "POST",
+        path.unwrap_or("/"),
+        &headers,
+        &body,
+        now,
+    );
+    headers.push(("authorization".to_owned(), authorization));
+    SignedRequest { url, headers, body }
+}
+
+/// The SigV4 `Authorization` header of a request without query string, `headers` holding every signed header.
+#[allow(clippy::too_many_arguments)]
+pub fn authorization(
+    credentials: &Credentials,
+    region: &str,
+    service: &str,
+    method: &str,
+    path: &str,
+    headers: &[(String, String)],
+    body: &[u8],
+    now: DateTime<Utc>,
+) -> String {
+    let mut headers = headers
+        .iter()
+        .map(|(name, value)| (name.to_ascii_lowercase(), value.trim().to_owned()))
+        .collect::<Vec<_>>();
+    headers.sort();
+    let canonical_headers = headers.iter().fold(String::new(), |mut canonical, (name, value)| {
+        let _ = writeln!(canonical, "{name}:{value}");
+        canonical
+    });
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{method}\n{path}\n\n{canonical_headers}\n{signed_headers}\n{}",
+        hex(digest::digest(&digest::SHA256, body).as_ref())
+    );
+    let date = now.format("%Y%m%d").to_string();
+    let scope = format!("{date}/{region}/{service}/aws4_request");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{scope}\n{}",
+        now.format("%Y%m%dT%H%M%SZ"),
+        hex(digest::digest(&digest::SHA256, canonical_request.as_bytes()).as_ref())
+    );
+    let key = [date.as_str(), region, service, "aws4_request"].iter().fold(
+        format!("AWS4{}", credentials.secret_key).into_bytes(),
+        |key, part| {
+            hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &key), part.as_bytes())
+                .as_ref()
+                .to_vec()
+        },
+    );
+    let signature = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &key), string_to_sign.as_bytes());
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={}",
+        credentials.access_key,
+        hex(signature.as_ref())
+    )
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+/// Registers the tables of the [`ServiceEvent::TableCreated`] events until the persister stops, reporting the
+/// registrations as events while the persister still records.
+pub async fn run(config: GlueConfig, mut events: UnboundedReceiver<ServiceEvent>, tx: WeakSender<Record>) {
+    let clock = config.clock.clone();
+    let mut glue = Glue::new(config);
+    while let Some(event) = events.recv().await {
+        let ServiceEvent::TableCreated {
+            table, format, schema, ..
+        } = event
+        else {
+            continue;
+        };
+        let created = CreatedTable { table, format, schema };
+        match glue.register(&created).await {
+            Ok(Some(registered)) => {
+                info!("{}: {registered}", created.table);
+                let event = Event::new(clock.now(), EventKind::Catalog, &created.table, registered);
+                if let Some(tx) = tx.upgrade() {
+                    let _ = tx.send(event.record()).await;
+                }
+            }
+            Ok(None) => (),
+            Err(e) => error!("cannot register {} in the catalog: {e}", created.table),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Measures the offset of the local clock against an NTP server, so the local timestamps recorded by different hosts
+//! and venues can be compared (e.g. in cross-venue latency studies).
+//!
+//! ## Features
+//! - Queries the NTP server over SNTP (RFC 4330) at a fixed interval, persisting every measure to the `_clock` table.
+//! - Keeps the last measured offset, optionally applied to the local timestamps taken by the sources through the
+//!   [`NtpAdjustedClock`] (e.g. the receive time of the Bitfinex tickers).
+//! - A failed query is logged and retried on the next tick, the last offset is kept meanwhile.
+//! - Reads the local time from the clock of the arguments, the system clock unless replaced (e.g. by a
+//!   [`ManualClock`] in tests), passed to the sources, the stages of the persister and the supervisor.
+
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+pub use dixit_persist::clock::{system, Clock, ManualClock, SharedClock, SystemClock};
+use dixit_persist_macros::Persist;
+use tokio::net::{self, UdpSocket};
+use tokio::sync::mpsc::Sender;
+use tokio::time;
+use tracing::{info, warn};
+
+use crate::model::{Record, VenueData};
+
+pub const EXCHANGE: &str = "ntp";
+
+const PACKET_LEN: usize = 48;
+/// version 4, client mode
+const REQUEST_HEADER: u8 = (4 << 3) | 3;
+const MODE_SERVER: u8 = 4;
+/// seconds between the NTP epoch (1900) and the unix epoch (1970)
+const NTP_EPOCH_OFFSET: i64 = 2_208_988_800;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Persist)]
+pub struct ClockOffset {
+    pub server: String,
+    /// local time of the request
+    pub time: DateTime<Utc>,
+    /// to add to the local clock to get the server clock
+    pub offset_ns: i64,
+    pub round_trip_ns: i64,
+}
+
+/// The local clock adjusted with the last measured offset if enabled, read by the sources for their local timestamps.
+#[derive(Debug)]
+pub struct NtpAdjustedClock {
+    local: SharedClock,
+    adjust: bool,
+    /// last measured offset of the local clock, in nanoseconds
+    offset_ns: AtomicI64,
+}
+
+impl NtpAdjustedClock {
+    /// Adjusts the time of the `local` clock once an offset is measured, only if `adjust` is set.
+    pub fn new(local: SharedClock, adjust: bool) -> Self {
+        Self {
+            local,
+            adjust,
+            offset_ns: AtomicI64::new(0),
+        }
+    }
+
+    /// Keeps the last measured offset, to add to the local clock to get the server clock.
+    pub fn set_offset(&self, offset_ns: i64) {
+        self.offset_ns.store(offset_ns, Ordering::Relaxed);
+    }
+}
+
+impl Clock for NtpAdjustedClock {
+    fn now(&self) -> DateTime<Utc> {
+        let now = self.local.now();
+        if self.adjust {
+            now + TimeDelta::nanoseconds(self.offset_ns.load(Ordering::Relaxed))
+        } else {
+            now
+        }
+    }
+}
+
+/// Measures the offset of the local clock of `clock` against `server` (e.g. `pool.ntp.org:123`) every `interval`,
+/// until the channel is closed, keeping the last one in `clock`.
+pub async fn run(tx: Sender<Record>, server: String, interval: Duration, clock: Arc<NtpAdjustedClock>) -> Result<()> {
+    let mut timer = time::interval(interval);
+
+    loop {
+        timer.tick().await;
+        match measure(&server, &*clock.local).await {
+            Ok(offset) => {
+                info!(
+                    "clock offset against {server}: {}ns, round trip {}ns",
+                    offset.offset_ns, offset.round_trip_ns
+                );
+                clock.set_offset(offset.offset_ns);
+                tx.send(Record::data(EXCHANGE, "clock", &server, VenueData::Clock(offset)))
+                    .await?;
+            }
+            Err(e) => warn!("failed to query {server}: {e}"),
+        }
+    }
+}
+
+async fn measure(server: &str, local: &dyn Clock) -> Result<ClockOffset> {
+    let address = net::lookup_host(server)
+        .await?
+        .next()
+        .ok_or_else(|| anyhow!("cannot resolve {server}"))?;
+    let socket = UdpSocket::bind(if address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }).await?;
+    socket.connect(address).await?;
+
+    let mut request = [0u8; PACKET_LEN];
+    request[0] = REQUEST_HEADER;
+    let sent = local.now();
+    request[40..48].copy_from_slice(&to_ntp(sent));
+    socket.send(&request).await?;
+
+    let mut response = [0u8; PACKET_LEN];
+    let len = time::timeout(TIMEOUT, socket.recv(&mut response))
+        .await
+        .map_err(|_| anyhow!("no response within {TIMEOUT:?}"))??;
+    let received = local.now();
+    if len < PACKET_LEN {
+        bail!("response of {len} bytes, expected {PACKET_LEN}");
+    }
+    if response[0] & 0x7 != MODE_SERVER {
+        bail!("response is not in server mode");
+    }
+    if response[1] == 0 {
+        bail!("kiss-o'-death response {}", String::from_utf8_lossy(&response[12..16]));
+    }
+    // the server echoes the transmit timestamp of the request, anything else answers another request
+    if response[24..32] != request[40..48] {
+        bail!("response does not match the request");
+    }
+
+    let (offset, round_trip) = offset(sent, from_ntp(&response[32..40]), from_ntp(&response[40..48]), received);
+    Ok(ClockOffset {
+        server: server.to_owned(),
+        time: sent,
+        offset_ns: offset.num_nanoseconds().unwrap_or_default(),
+        round_trip_ns: round_trip.num_nanoseconds().unwrap_or_default(),
+    })
+}
+
+/// Offset and round trip from the request sent, received by the server, answered by the server and received back.
+fn offset(
+    sent: DateTime<Utc>,
+    server_received: DateTime<Utc>,
+    server_sent: DateTime<Utc>,
+    received: DateTime<Utc>,
+) -> (TimeDelta, TimeDelta) {
+    let offset = ((server_received - sent) + (server_sent - received)) / 2;
+    let round_trip = (received - sent) - (server_sent - server_received);
+    (offset, round_trip)
+}
+
+/// Seconds since 1900 (32 bits) and fraction of second (32 bits).
+fn to_ntp(time: DateTime<Utc>) -> [u8; 8] {
+    let seconds = (time.timestamp() + NTP_EPOCH_OFFSET) as u32;
+    let fraction = ((time.timestamp_subsec_nanos() as u64) << 32) / 1_000_000_000;
+    let mut bytes = [0u8; 8];
+    bytes[..4].copy_from_slice(&seconds.to_be_bytes());
+    bytes[4..].copy_from_slice(&(fraction as u32).to_be_bytes());
+    bytes
+}
+
+fn from_ntp(bytes: &[u8]) -> DateTime<Utc> {
+    let seconds = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64;
+    let fraction = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as u64;
+    let nanos = (fraction * 1_000_000_000) >> 32;
+    DateTime::from_timestamp(seconds - NTP_EPOCH_OFFSET, nanos as u32).unwrap_or_default()
+}
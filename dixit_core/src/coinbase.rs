@@ -0,0 +1,507 @@
+//! Contains Coinbase-specific WebSocket handling logic.
+//!
+//! ## Features
+//! - Records the exchange feed, or the Advanced Trade feed with `--coinbase-api advanced-trade` (see
+//!   [`crate::coinbase_advanced`]), both sharing the `coinbase` directory and the reference data.
+//! - Subscribes to the channels selected by `--coinbase-channels` on the Coinbase WebSocket feed: the RFQ matches
+//!   the `full` channel (level 3, order by order) and the `status` channel.
+//! - Parses incoming messages into RFQ match records or errors.
+//! - Parses the `received`, `open`, `done`, `match` and `change` messages of the `full` channel, each one to its own
+//!   table (e.g. `full_done`).
+//! - Verifies the sequence numbers of the `full` channel per product, reporting gaps as events and skipping the
+//!   messages older than the last one received.
+//! - Tracks the status of every product on the `status` channel, persisting its transitions only (e.g. from `online`
+//!   to `post_only`, or to `delisted`) to the `status` table, so the halted periods of a product can be excluded
+//!   downstream. The channel carrying no timestamp, a transition is timed when it is received.
+//! - Parses the products of the REST API into reference data (tick and lot sizes, status).
+//!
+//! The `full` channel requires the products (`--symbols`), the RFQ matches default to every product.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use dixit_persist::{jsonl::to_json, writer::TableWriter};
+use dixit_persist_macros::Persist;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{from_str, json};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::clock::SharedClock;
+use crate::events::{Event, EventKind};
+use crate::model::{Record, Severity, SkipReason, VenueData};
+use crate::reference::Instrument;
+
+pub const EXCHANGE: &str = "coinbase";
+pub const WS_URL: &str = "wss://ws-feed.exchange.coinbase.com";
+pub const REST_URL: &str = "https://api.exchange.coinbase.com";
+
+/// WebSocket feed recorded, each one with its own channels.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Api {
+    /// exchange feed: rfq_matches, full and status
+    Exchange,
+    /// Advanced Trade feed: market_trades and level2
+    AdvancedTrade,
+}
+
+impl Api {
+    /// Channels of the feed, the first one is recorded when none is given.
+    pub fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Api::Exchange => &["rfq_matches", "full", "status"],
+            Api::AdvancedTrade => &["market_trades", "level2"],
+        }
+    }
+}
+
+/// Subscribes to the channels of the products, or of every product if none is given.
+pub fn subscribe(channels: &[String], products: &[String]) -> Vec<Message> {
+    let subscription = if products.is_empty() {
+        json!({
+            "type": "subscribe",
+            "channels": channels
+        })
+    } else {
+        json!({
+            "type": "subscribe",
+            "product_ids": products,
+            "channels": channels
+        })
+    };
+    vec![Message::Text(subscription.to_string())]
+}
+
+pub fn unsubscribe(channels: &[String], products: &[String]) -> Vec<Message> {
+    if products.is_empty() {
+        return vec![];
+    }
+    let request = json!({
+        "type": "unsubscribe",
+        "product_ids": products,
+        "channels": channels
+    });
+    vec![Message::Text(request.to_string())]
+}
+
+/// Stateful handler, keeping the last sequence number of the `full` channel and the last status of each product.
+pub struct Handler {
+    sequences: HashMap<String, u64>,
+    statuses: HashMap<String, ProductStatus>,
+    /// clock of the status changes and the gap events
+    clock: SharedClock,
+}
+
+impl Handler {
+    pub fn new(clock: SharedClock) -> Self {
+        Self {
+            sequences: HashMap::new(),
+            statuses: HashMap::new(),
+            clock,
+        }
+    }
+
+    pub fn handle(&mut self, message: Message) -> Vec<Record> {
+        match message {
+            Message::Text(string) => match from_str::<Full>(&string) {
+                Ok(full) => self.handle_full(full),
+                Err(_) => match from_str::<Status>(&string) {
+                    Ok(status) if status.kind == "status" => self.handle_status(status),
+                    _ => vec![parse(Message::Text(string))],
+                },
+            },
+            message => vec![parse(message)],
+        }
+    }
+
+    fn handle_full(&mut self, full: Full) -> Vec<Record> {
+        let (product_id, sequence) = (full.product_id().to_owned(), full.sequence());
+        let mut records = vec![];
+        match self.sequences.get(&product_id) {
+            Some(last) if sequence <= *last => {
+                return vec![Record::skip(
+                    SkipReason::Other,
+                    format!("{product_id} sequence {sequence} already received, last {last}"),
+                )];
+            }
+            Some(last) if sequence > last + 1 => {
+                let source = format!("{EXCHANGE}/{product_id}");
+                let gap = format!("expected {} received {sequence}", last + 1);
+                records.push(Event::new(self.clock.now(), EventKind::Gap, &source, gap).record());
+            }
+            _ => {}
+        }
+        self.sequences.insert(product_id.clone(), sequence);
+        records.push(Record::data(
+            EXCHANGE,
+            "full",
+            &product_id,
+            VenueData::CoinbaseFull(full),
+        ));
+        records
+    }
+
+    /// Every status message lists all the products, only the ones whose status changed are recorded.
+    fn handle_status(&mut self, status: Status) -> Vec<Record> {
+        let time = self.clock.now();
+        let mut records = vec![];
+        for product in status.products {
+            let status = ProductStatus::new(product, time);
+            if self
+                .statuses
+                .get(&status.product_id)
+                .is_some_and(|last| last.is_same(&status))
+            {
+                continue;
+            }
+            let product_id = status.product_id.clone();
+            records.push(Record::data(
+                EXCHANGE,
+                "status",
+                &product_id,
+                VenueData::CoinbaseStatus(status.clone()),
+            ));
+            self.statuses.insert(product_id, status);
+        }
+        if records.is_empty() {
+            records.push(Record::skip(SkipReason::Other, "status unchanged"));
+        }
+        records
+    }
+}
+
+fn parse(message: Message) -> Record {
+    match message {
+        Message::Text(string) => {
+            if let Ok(rfq_match) = from_str::<RfqMatch>(&string) {
+                if rfq_match.channel == "rfq_match" {
+                    return Record::Data {
+                        exchange: EXCHANGE.to_string(),
+                        channel: rfq_match.channel.clone(),
+                        symbol: rfq_match.product_id.clone(),
+                        data: Box::new(VenueData::CoinbaseRfqMatch(rfq_match)),
+                    };
+                }
+            } else if let Ok(rfq_error) = from_str::<RfqError>(&string) {
+                if rfq_error.channel == "error" {
+                    // e.g. a rejected subscription, the other channels are not affected
+                    return Record::error(
+                        EXCHANGE,
+                        &rfq_error.channel,
+                        Severity::Warning,
+                        rfq_error.message,
+                        rfq_error.reason,
+                    );
+                }
+            }
+            Record::unhandled(string)
+        }
+        _ => Record::non_text(),
+    }
+}
+
+#[derive(Deserialize, Debug, Persist)]
+pub struct RfqMatch {
+    #[serde(rename = "type")]
+    #[persist(intern)]
+    pub channel: String,
+    pub maker_order_id: String,
+    pub taker_order_id: String,
+    pub time: DateTime<Utc>,
+    pub trade_id: u64,
+    #[persist(intern)]
+    pub product_id: String,
+    pub size: Decimal,
+    pub price: Decimal,
+    #[persist(intern)]
+    pub side: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RfqError {
+    #[serde(rename = "type")]
+    pub channel: String,
+    pub message: String,
+    pub reason: String,
+}
+
+/// Messages of the `full` channel, the `activate` messages of stop orders are skipped.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Full {
+    Received(Received),
+    Open(Open),
+    Done(Done),
+    Match(Match),
+    Change(Change),
+}
+
+impl Full {
+    pub fn table(&self) -> &'static str {
+        match self {
+            Full::Received(_) => "full_received",
+            Full::Open(_) => "full_open",
+            Full::Done(_) => "full_done",
+            Full::Match(_) => "full_match",
+            Full::Change(_) => "full_change",
+        }
+    }
+
+    pub fn product_id(&self) -> &str {
+        match self {
+            Full::Received(data) => &data.product_id,
+            Full::Open(data) => &data.product_id,
+            Full::Done(data) => &data.product_id,
+            Full::Match(data) => &data.product_id,
+            Full::Change(data) => &data.product_id,
+        }
+    }
+
+    pub fn time(&self) -> DateTime<Utc> {
+        match self {
+            Full::Received(data) => data.time,
+            Full::Open(data) => data.time,
+            Full::Done(data) => data.time,
+            Full::Match(data) => data.time,
+            Full::Change(data) => data.time,
+        }
+    }
+
+    pub fn sequence(&self) -> u64 {
+        match self {
+            Full::Received(data) => data.sequence,
+            Full::Open(data) => data.sequence,
+            Full::Done(data) => data.sequence,
+            Full::Match(data) => data.sequence,
+            Full::Change(data) => data.sequence,
+        }
+    }
+
+    pub fn persist(&self, writer: &mut TableWriter) -> Result<()> {
+        match self {
+            Full::Received(data) => writer.begin()?.record(data)?.end()?,
+            Full::Open(data) => writer.begin()?.record(data)?.end()?,
+            Full::Done(data) => writer.begin()?.record(data)?.end()?,
+            Full::Match(data) => writer.begin()?.record(data)?.end()?,
+            Full::Change(data) => writer.begin()?.record(data)?.end()?,
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        Ok(match self {
+            Full::Received(data) => to_json(data)?,
+            Full::Open(data) => to_json(data)?,
+            Full::Done(data) => to_json(data)?,
+            Full::Match(data) => to_json(data)?,
+            Full::Change(data) => to_json(data)?,
+        })
+    }
+}
+
+/// An order accepted by the matching engine, market orders carry `funds` and no price.
+#[derive(Deserialize, Debug, Persist)]
+pub struct Received {
+    pub time: DateTime<Utc>,
+    #[persist(intern)]
+    pub product_id: String,
+    pub sequence: u64,
+    pub order_id: String,
+    #[persist(intern)]
+    pub side: String,
+    #[persist(intern)]
+    pub order_type: String,
+    pub size: Option<Decimal>,
+    pub price: Option<Decimal>,
+    pub funds: Option<Decimal>,
+    pub client_oid: Option<String>,
+}
+
+/// An order resting on the book.
+#[derive(Deserialize, Debug, Persist)]
+pub struct Open {
+    pub time: DateTime<Utc>,
+    #[persist(intern)]
+    pub product_id: String,
+    pub sequence: u64,
+    pub order_id: String,
+    #[persist(intern)]
+    pub side: String,
+    pub price: Decimal,
+    pub remaining_size: Decimal,
+}
+
+/// An order off the book, `filled` or `canceled`, market orders carry no price.
+#[derive(Deserialize, Debug, Persist)]
+pub struct Done {
+    pub time: DateTime<Utc>,
+    #[persist(intern)]
+    pub product_id: String,
+    pub sequence: u64,
+    pub order_id: String,
+    #[persist(intern)]
+    pub side: String,
+    #[persist(intern)]
+    pub reason: String,
+    pub price: Option<Decimal>,
+    pub remaining_size: Option<Decimal>,
+}
+
+/// A trade between two orders, `side` is the side of the maker order.
+#[derive(Deserialize, Debug, Persist)]
+pub struct Match {
+    pub time: DateTime<Utc>,
+    #[persist(intern)]
+    pub product_id: String,
+    pub sequence: u64,
+    pub trade_id: u64,
+    pub maker_order_id: String,
+    pub taker_order_id: String,
+    #[persist(intern)]
+    pub side: String,
+    pub size: Decimal,
+    pub price: Decimal,
+}
+
+/// An order changed by self-trade prevention (`STP`) or modified (`modify_order`), with its new size and price.
+#[derive(Deserialize, Debug, Persist)]
+pub struct Change {
+    pub time: DateTime<Utc>,
+    #[persist(intern)]
+    pub product_id: String,
+    pub sequence: u64,
+    pub order_id: String,
+    #[persist(intern)]
+    pub side: String,
+    #[persist(intern)]
+    pub reason: Option<String>,
+    pub price: Option<Decimal>,
+    pub old_size: Option<Decimal>,
+    pub new_size: Option<Decimal>,
+    pub old_price: Option<Decimal>,
+    pub new_price: Option<Decimal>,
+}
+
+/// Message of the `status` channel, with the status of every product.
+#[derive(Deserialize, Debug)]
+struct Status {
+    #[serde(rename = "type")]
+    kind: String,
+    products: Vec<StatusProduct>,
+}
+
+#[derive(Deserialize, Debug)]
+struct StatusProduct {
+    id: String,
+    status: String,
+    #[serde(default)]
+    status_message: Option<String>,
+    #[serde(default)]
+    post_only: bool,
+    #[serde(default)]
+    limit_only: bool,
+    #[serde(default)]
+    cancel_only: bool,
+    #[serde(default)]
+    trading_disabled: bool,
+}
+
+/// A transition of the trading status of a product, `state` summing up the status and the flags: `online`,
+/// `post_only`, `limit_only`, `cancel_only`, `trading_disabled`, or the status itself when not online (e.g.
+/// `delisted`).
+#[derive(Debug, Clone, Persist)]
+pub struct ProductStatus {
+    /// when the transition was received
+    pub time: DateTime<Utc>,
+    #[persist(intern)]
+    pub product_id: String,
+    #[persist(intern)]
+    pub state: String,
+    #[persist(intern)]
+    pub status: String,
+    pub status_message: Option<String>,
+    pub post_only: bool,
+    pub limit_only: bool,
+    pub cancel_only: bool,
+    pub trading_disabled: bool,
+}
+
+impl ProductStatus {
+    fn new(product: StatusProduct, time: DateTime<Utc>) -> Self {
+        let state = if product.status != "online" {
+            product.status.clone()
+        } else if product.trading_disabled {
+            "trading_disabled".to_owned()
+        } else if product.cancel_only {
+            "cancel_only".to_owned()
+        } else if product.limit_only {
+            "limit_only".to_owned()
+        } else if product.post_only {
+            "post_only".to_owned()
+        } else {
+            "online".to_owned()
+        };
+        Self {
+            time,
+            product_id: product.id,
+            state,
+            status: product.status,
+            status_message: product.status_message.filter(|message| !message.is_empty()),
+            post_only: product.post_only,
+            limit_only: product.limit_only,
+            cancel_only: product.cancel_only,
+            trading_disabled: product.trading_disabled,
+        }
+    }
+
+    /// Whether both have the same status, their time aside.
+    fn is_same(&self, other: &Self) -> bool {
+        (
+            &self.state,
+            &self.status,
+            &self.status_message,
+            self.post_only,
+            self.limit_only,
+            self.cancel_only,
+            self.trading_disabled,
+        ) == (
+            &other.state,
+            &other.status,
+            &other.status_message,
+            other.post_only,
+            other.limit_only,
+            other.cancel_only,
+            other.trading_disabled,
+        )
+    }
+}
+
+pub fn reference_urls(rest_url: &str) -> Vec<String> {
+    vec![format!("{rest_url}/products")]
+}
+
+pub fn instruments(body: &str, as_of: DateTime<Utc>) -> Result<Vec<Instrument>> {
+    let products = from_str::<Vec<Product>>(body)?;
+    Ok(products
+        .into_iter()
+        .map(|product| Instrument {
+            symbol: product.id,
+            instrument: None,
+            as_of,
+            tick_size: Some(product.quote_increment),
+            lot_size: Some(product.base_increment),
+            status: product.status,
+            expiry: None,
+        })
+        .collect())
+}
+
+#[derive(Deserialize, Debug)]
+struct Product {
+    id: String,
+    base_increment: Decimal,
+    quote_increment: Decimal,
+    status: String,
+}
@@ -0,0 +1,315 @@
+//! Contains the Coinbase Advanced Trade WebSocket handling logic, selected by `--coinbase-api advanced-trade`.
+//!
+//! ## Features
+//! - Subscribes to the channels selected by `--coinbase-channels` on the Advanced Trade feed: `market_trades` and
+//!   `level2`, plus the `heartbeats` channel keeping the connection open while the products are quiet.
+//! - Authenticates the subscriptions with a JWT signed by the CDP API key of `--coinbase-key-name` and
+//!   `--coinbase-key-file`, a fresh one on every (re)subscription as they expire after two minutes.
+//! - Parses the trades of the `market_trades` channel to the `market_trades` table and the price levels of the
+//!   `level2` channel (snapshots and updates) to the `level2` table.
+//! - Verifies the sequence numbers of the connection, reporting gaps as events.
+//!
+//! Unlike the exchange feed, the Advanced Trade channels require the products (`--symbols`).
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use aws_lc_rs::rand::{SecureRandom, SystemRandom};
+use aws_lc_rs::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use dixit_persist_macros::Persist;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{from_str, json, Value};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::warn;
+
+use crate::clock::SharedClock;
+use crate::coinbase::EXCHANGE;
+use crate::events::{Event, EventKind};
+use crate::model::{Record, Severity, VenueData};
+
+pub const WS_URL: &str = "wss://advanced-trade-ws.coinbase.com";
+
+/// Lifetime of the JWTs, the longest accepted by Coinbase.
+const JWT_LIFETIME_SECONDS: i64 = 120;
+
+pub struct Config {
+    pub channels: Vec<String>,
+    pub credentials: Option<Credentials>,
+    /// clock the JWTs are issued by
+    pub clock: SharedClock,
+}
+
+/// CDP API key signing the JWTs of the subscriptions.
+pub struct Credentials {
+    key_name: String,
+    key_pair: EcdsaKeyPair,
+}
+
+impl Credentials {
+    /// Reads the EC private key of the API key from a PEM file, as downloaded from the CDP portal.
+    pub fn load(key_name: &str, key_file: &Path) -> Result<Self> {
+        let pem = fs::read_to_string(key_file).with_context(|| format!("cannot read {key_file:?}"))?;
+        Self::new(key_name, &pem).with_context(|| format!("invalid key {key_file:?}"))
+    }
+
+    /// Parses the EC private key (`EC PRIVATE KEY` or `PRIVATE KEY`) of a PEM, its new lines may be escaped.
+    pub fn new(key_name: &str, pem: &str) -> Result<Self> {
+        let base64 = pem
+            .replace("\\n", "\n")
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+            .collect::<String>();
+        let der = STANDARD.decode(base64).context("PEM is not base64")?;
+        let key_pair = EcdsaKeyPair::from_private_key_der(&ECDSA_P256_SHA256_FIXED_SIGNING, &der)
+            .map_err(|e| anyhow!("not a P-256 private key - {e}"))?;
+        Ok(Self {
+            key_name: key_name.to_owned(),
+            key_pair,
+        })
+    }
+
+    /// Signs a JWT valid for two minutes from `now`.
+    pub fn jwt(&self, now: DateTime<Utc>) -> Result<String> {
+        let random = SystemRandom::new();
+        let mut nonce = [0u8; 16];
+        random
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("cannot generate a nonce"))?;
+        let header = json!({
+            "alg": "ES256",
+            "kid": self.key_name,
+            "nonce": nonce.map(|byte| format!("{byte:02x}")).concat(),
+            "typ": "JWT",
+        });
+        let claims = json!({
+            "iss": "cdp",
+            "sub": self.key_name,
+            "nbf": now.timestamp(),
+            "exp": now.timestamp() + JWT_LIFETIME_SECONDS,
+        });
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let signature = self
+            .key_pair
+            .sign(&random, message.as_bytes())
+            .map_err(|_| anyhow!("cannot sign the JWT"))?;
+        Ok(format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature.as_ref())))
+    }
+}
+
+/// Subscribes to each channel of the products and to the heartbeats, one request per channel.
+pub fn subscribe(config: &Config, products: &[String]) -> Vec<Message> {
+    let jwt = config.jwt();
+    let mut messages = requests("subscribe", &config.channels, products, jwt.as_deref());
+    messages.extend(requests("subscribe", &["heartbeats".to_owned()], &[], jwt.as_deref()));
+    messages
+}
+
+pub fn unsubscribe(config: &Config, products: &[String]) -> Vec<Message> {
+    if products.is_empty() {
+        return vec![];
+    }
+    requests("unsubscribe", &config.channels, products, config.jwt().as_deref())
+}
+
+impl Config {
+    fn jwt(&self) -> Option<String> {
+        // a failed signature only loses the authentication, the market data channels are public
+        self.credentials
+            .as_ref()
+            .and_then(|credentials| match credentials.jwt(self.clock.now()) {
+                Ok(jwt) => Some(jwt),
+                Err(e) => {
+                    warn!("subscribing to {EXCHANGE} without a JWT: {e:#}");
+                    None
+                }
+            })
+    }
+}
+
+fn requests(kind: &str, channels: &[String], products: &[String], jwt: Option<&str>) -> Vec<Message> {
+    channels
+        .iter()
+        .map(|channel| {
+            let mut request = json!({
+                "type": kind,
+                "channel": channel,
+            });
+            if !products.is_empty() {
+                request["product_ids"] = json!(products);
+            }
+            if let Some(jwt) = jwt {
+                request["jwt"] = json!(jwt);
+            }
+            Message::Text(request.to_string())
+        })
+        .collect()
+}
+
+/// Stateful handler, keeping the last sequence number of the connection.
+pub struct Handler {
+    sequence: Option<u64>,
+    /// clock of the gap events
+    clock: SharedClock,
+}
+
+impl Handler {
+    pub fn new(clock: SharedClock) -> Self {
+        Self { sequence: None, clock }
+    }
+
+    pub fn handle(&mut self, message: Message) -> Vec<Record> {
+        let Message::Text(string) = message else {
+            return vec![Record::non_text()];
+        };
+        let envelope = match from_str::<Envelope>(&string) {
+            Ok(envelope) => envelope,
+            Err(_) => return vec![parse_error(string)],
+        };
+        let mut records = self.sequence(envelope.sequence_num);
+        match envelope.channel.as_str() {
+            "market_trades" => match Vec::<TradesEvent>::deserialize(&envelope.events) {
+                Ok(events) => records.extend(events.into_iter().flat_map(|event| event.trades).map(|trade| {
+                    let symbol = trade.product_id.clone();
+                    Record::data(
+                        EXCHANGE,
+                        "market_trades",
+                        &symbol,
+                        VenueData::CoinbaseMarketTrade(trade),
+                    )
+                })),
+                Err(e) => records.push(invalid(&envelope.channel, e)),
+            },
+            "l2_data" => match Vec::<Level2Event>::deserialize(&envelope.events) {
+                Ok(events) => {
+                    for event in events {
+                        let snapshot = event.kind == "snapshot";
+                        records.extend(event.updates.into_iter().map(|update| {
+                            let level = Level2 {
+                                time: envelope.timestamp,
+                                product_id: event.product_id.clone(),
+                                snapshot,
+                                side: update.side,
+                                event_time: update.event_time,
+                                price_level: update.price_level,
+                                new_quantity: update.new_quantity,
+                            };
+                            Record::data(EXCHANGE, "level2", &event.product_id, VenueData::CoinbaseLevel2(level))
+                        }));
+                    }
+                }
+                Err(e) => records.push(invalid(&envelope.channel, e)),
+            },
+            // heartbeats and subscription acknowledgments
+            _ => records.push(Record::unhandled(string)),
+        }
+        records
+    }
+
+    /// Reports a gap in the sequence numbers, they restart from zero on every connection.
+    fn sequence(&mut self, sequence: u64) -> Vec<Record> {
+        let last = self.sequence.replace(sequence);
+        match last {
+            Some(last) if sequence > last + 1 => {
+                let gap = format!("expected {} received {sequence}", last + 1);
+                vec![Event::new(self.clock.now(), EventKind::Gap, EXCHANGE, gap).record()]
+            }
+            _ => vec![],
+        }
+    }
+}
+
+fn parse_error(string: String) -> Record {
+    match from_str::<ErrorMessage>(&string) {
+        // e.g. a rejected subscription or an expired JWT, the other channels are not affected
+        Ok(error) if error.kind == "error" => {
+            Record::error(EXCHANGE, "error", Severity::Warning, error.message, String::new())
+        }
+        _ => Record::unhandled(string),
+    }
+}
+
+fn invalid(channel: &str, e: serde_json::Error) -> Record {
+    Record::error(
+        EXCHANGE,
+        channel,
+        Severity::Warning,
+        format!("invalid {channel} message"),
+        e.to_string(),
+    )
+}
+
+#[derive(Deserialize, Debug)]
+struct Envelope {
+    channel: String,
+    timestamp: DateTime<Utc>,
+    sequence_num: u64,
+    #[serde(default)]
+    events: Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorMessage {
+    #[serde(rename = "type")]
+    kind: String,
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct TradesEvent {
+    trades: Vec<MarketTrade>,
+}
+
+/// A trade of the `market_trades` channel, `side` is the side of the maker order (`BUY` or `SELL`).
+#[derive(Deserialize, Debug, Persist)]
+pub struct MarketTrade {
+    pub time: DateTime<Utc>,
+    #[persist(intern)]
+    pub product_id: String,
+    pub trade_id: String,
+    #[persist(intern)]
+    pub side: String,
+    pub price: Decimal,
+    pub size: Decimal,
+}
+
+#[derive(Deserialize, Debug)]
+struct Level2Event {
+    #[serde(rename = "type")]
+    kind: String,
+    product_id: String,
+    updates: Vec<Level2Update>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Level2Update {
+    side: String,
+    event_time: DateTime<Utc>,
+    price_level: Decimal,
+    new_quantity: Decimal,
+}
+
+/// A price level of the `level2` channel, a quantity of zero removes the level from the book.
+///
+/// The levels of the snapshot sent on subscription have `snapshot` set, `time` is the time of the message.
+#[derive(Debug, Persist)]
+pub struct Level2 {
+    pub time: DateTime<Utc>,
+    #[persist(intern)]
+    pub product_id: String,
+    pub snapshot: bool,
+    #[persist(intern)]
+    pub side: String,
+    pub event_time: DateTime<Utc>,
+    pub price_level: Decimal,
+    pub new_quantity: Decimal,
+}
@@ -0,0 +1,60 @@
+//! Conflates quote-style channels (e.g. tickers or best bid and offer), persisting only the latest record of each
+//! symbol per interval.
+//!
+//! ## Features
+//! - Keeps the latest record per table and symbol received within the conflation interval (e.g. 100ms).
+//! - Emits the pending records at the end of every interval, in the order their symbols first updated.
+//! - Passes the records of the other tables through, so trades keep their full fidelity.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+pub struct Conflator<T> {
+    tables: HashSet<String>,
+    interval: Duration,
+    /// latest record and its symbol, in first update order
+    pending: Vec<(String, T)>,
+    /// position of the pending records by table and symbol
+    index: HashMap<&'static str, HashMap<String, usize>>,
+}
+
+impl<T> Conflator<T> {
+    pub fn new(tables: impl IntoIterator<Item = String>, interval: Duration) -> Self {
+        Self {
+            tables: tables.into_iter().collect(),
+            interval,
+            pending: vec![],
+            index: HashMap::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.tables.is_empty()
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Returns the record back if its table is not conflated, otherwise keeps it until the next [`Conflator::drain`].
+    pub fn conflate(&mut self, table: &'static str, symbol: &str, record: T) -> Option<T> {
+        if !self.tables.contains(table) {
+            return Some(record);
+        }
+        let index = self.index.entry(table).or_default();
+        match index.get(symbol) {
+            Some(&position) => self.pending[position].1 = record,
+            None => {
+                index.insert(symbol.to_owned(), self.pending.len());
+                self.pending.push((symbol.to_owned(), record));
+            }
+        }
+        None
+    }
+
+    /// Returns the latest record of every symbol updated since the last call, with its symbol.
+    pub fn drain(&mut self) -> Vec<(String, T)> {
+        self.index.values_mut().for_each(HashMap::clear);
+        std::mem::take(&mut self.pending)
+    }
+}
@@ -0,0 +1,118 @@
+//! Changes the subscriptions of a running recorder, through its control socket (`--control-socket`) and the `ctl`
+//! subcommand.
+//!
+//! ## Features
+//! - Serves a line protocol on a unix socket: `subscribe <venue> <symbol>...`, `unsubscribe <venue> <symbol>...` and
+//!   `list <venue>`, each line answered by `ok <subscribed symbols>` or `error <reason>`.
+//! - Forwards the commands to the websocket as [`ControlCmd`]s, the websocket keeps the subscribed symbols so a
+//!   reconnection resubscribes to the current ones.
+//! - Subscribes to every channel the venue records for a symbol (e.g. trades, ticker and candles on Bitfinex).
+//!
+//! ## Example
+//! ```sh
+//! dixit --venue bitfinex --control-socket /tmp/dixit.ctl
+//! dixit ctl --socket /tmp/dixit.ctl subscribe bitfinex tSOLUSD
+//! ```
+
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+use tokio::sync::{mpsc::Sender, oneshot};
+
+use crate::config::Venue;
+
+/// Commands of the websocket, each replied with the symbols subscribed once applied.
+#[derive(Debug)]
+pub enum ControlCmd {
+    Subscribe(Vec<String>, oneshot::Sender<Vec<String>>),
+    Unsubscribe(Vec<String>, oneshot::Sender<Vec<String>>),
+    List(oneshot::Sender<Vec<String>>),
+}
+
+#[cfg(unix)]
+pub async fn serve(path: &Path, venue: Venue, tx: Sender<ControlCmd>) -> Result<()> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::UnixListener;
+    use tracing::{info, warn};
+
+    // a socket left by a previous run would fail the bind
+    let _ = std::fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+    info!("control socket listening on {}", path.display());
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let reply = match execute(&line, venue, &tx).await {
+                    Ok(symbols) => format!("ok {}\n", symbols.join(" ")),
+                    Err(e) => format!("error {e}\n"),
+                };
+                if let Err(e) = write.write_all(reply.as_bytes()).await {
+                    warn!("control socket error: {e}");
+                    break;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn serve(_path: &Path, _venue: Venue, _tx: Sender<ControlCmd>) -> Result<()> {
+    bail!("control sockets are only supported on unix")
+}
+
+async fn execute(line: &str, venue: Venue, tx: &Sender<ControlCmd>) -> Result<Vec<String>> {
+    let mut words = line.split_whitespace();
+    let (command, target) = (words.next(), words.next());
+    if let Some(target) = target.filter(|target| *target != venue.to_string()) {
+        bail!("recording {venue}, not {target}");
+    }
+    let symbols = words.map(str::to_owned).collect::<Vec<_>>();
+    match (command, target.is_some(), symbols.is_empty()) {
+        (Some("subscribe"), true, false) => apply(tx, venue, |reply| ControlCmd::Subscribe(symbols, reply)).await,
+        (Some("unsubscribe"), true, false) => apply(tx, venue, |reply| ControlCmd::Unsubscribe(symbols, reply)).await,
+        (Some("list"), true, true) => apply(tx, venue, ControlCmd::List).await,
+        _ => bail!("expected subscribe <venue> <symbol>..., unsubscribe <venue> <symbol>... or list <venue>"),
+    }
+}
+
+/// Sends a command to the websocket and returns the symbols subscribed once applied.
+pub async fn apply(
+    tx: &Sender<ControlCmd>,
+    venue: Venue,
+    command: impl FnOnce(oneshot::Sender<Vec<String>>) -> ControlCmd,
+) -> Result<Vec<String>> {
+    let (reply, symbols) = oneshot::channel();
+    tx.send(command(reply))
+        .await
+        .map_err(|_| anyhow!("the {venue} source does not take subscription commands"))?;
+    Ok(symbols.await?)
+}
+
+/// Sends a command to the control socket of a recorder and prints its reply, fails on an error reply.
+#[cfg(unix)]
+pub async fn send(path: &Path, command: &[String]) -> Result<()> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::UnixStream;
+
+    let mut stream = UnixStream::connect(path).await?;
+    stream.write_all(format!("{}\n", command.join(" ")).as_bytes()).await?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).await?;
+    match reply.trim_end().strip_prefix("error ") {
+        Some(reason) => bail!("{reason}"),
+        None => {
+            println!("{}", reply.trim_end());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn send(_path: &Path, _command: &[String]) -> Result<()> {
+    bail!("control sockets are only supported on unix")
+}
@@ -0,0 +1,242 @@
+//! Contains Deribit-specific WebSocket handling logic.
+//!
+//! ## Features
+//! - Subscribes to the ticker channel for each perpetual instrument through the Deribit JSON-RPC WebSocket API.
+//! - Extracts funding rate, open interest and index price records from each ticker notification.
+//! - Subscribes to 1 minute trade charts and forwards every update as a candle.
+//! - Parses the futures of the currencies of the symbols, listed by the REST API, into reference data (tick size,
+//!   minimum trade amount, expiry).
+//!
+//! Deribit perpetuals fund continuously, so `funding_8h` is recorded and there is no next funding time.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{from_str, json, Value};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::candles::Candle;
+use crate::model::{FundingRate, IndexPrice, OpenInterest, Record, Severity, VenueData};
+use crate::reference::Instrument;
+
+pub const EXCHANGE: &str = "deribit";
+pub const WS_URL: &str = "wss://www.deribit.com/ws/api/v2";
+pub const REST_URL: &str = "https://www.deribit.com";
+pub const SYMBOLS: &[&str] = &["BTC-PERPETUAL", "ETH-PERPETUAL"];
+
+pub fn subscribe(symbols: &[String]) -> Vec<Message> {
+    request("public/subscribe", symbols)
+}
+
+pub fn unsubscribe(symbols: &[String]) -> Vec<Message> {
+    request("public/unsubscribe", symbols)
+}
+
+fn request(method: &str, symbols: &[String]) -> Vec<Message> {
+    if symbols.is_empty() {
+        return vec![];
+    }
+    let channels = symbols
+        .iter()
+        .flat_map(|symbol| [format!("ticker.{symbol}.100ms"), format!("chart.trades.{symbol}.1")])
+        .collect::<Vec<_>>();
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": method,
+        "params": {
+            "channels": channels
+        }
+    });
+    vec![Message::Text(request.to_string())]
+}
+
+pub fn handle(message: Message) -> Vec<Record> {
+    match message {
+        Message::Text(string) => {
+            if let Ok(notification) = from_str::<Notification>(&string) {
+                if notification.method == "subscription" {
+                    let Params { channel, data } = notification.params;
+                    let records = if channel.starts_with("ticker.") {
+                        Ticker::deserialize(data).ok().map(parse_ticker)
+                    } else if let Some(chart) = channel.strip_prefix("chart.trades.") {
+                        Chart::deserialize(data).ok().and_then(|data| parse_chart(chart, data))
+                    } else {
+                        None
+                    };
+                    if let Some(records) = records {
+                        return records;
+                    }
+                }
+            } else if let Ok(response) = from_str::<ErrorResponse>(&string) {
+                return vec![Record::error(
+                    EXCHANGE,
+                    "error",
+                    Severity::Warning,
+                    response.error.message,
+                    format!("code {}", response.error.code),
+                )];
+            }
+            vec![Record::unhandled(string)]
+        }
+        _ => vec![Record::non_text()],
+    }
+}
+
+fn parse_ticker(ticker: Ticker) -> Vec<Record> {
+    let symbol = ticker.instrument_name;
+    let time = ticker.timestamp;
+    let mut records = vec![Record::data(
+        EXCHANGE,
+        "open_interest",
+        &symbol,
+        VenueData::OpenInterest(OpenInterest {
+            symbol: symbol.clone(),
+            instrument: None,
+            time,
+            open_interest: ticker.open_interest,
+        }),
+    )];
+    records.push(Record::data(
+        EXCHANGE,
+        "index",
+        &symbol,
+        VenueData::Index(IndexPrice {
+            symbol: symbol.clone(),
+            instrument: None,
+            time,
+            index_price: ticker.index_price,
+        }),
+    ));
+    if let Some(funding_rate) = ticker.funding_8h {
+        records.push(Record::data(
+            EXCHANGE,
+            "funding",
+            &symbol,
+            VenueData::Funding(FundingRate {
+                symbol: symbol.clone(),
+                instrument: None,
+                time,
+                funding_rate,
+                next_funding_time: None,
+            }),
+        ));
+    }
+    records
+}
+
+/// The chart channel is `chart.trades.{instrument}.{resolution}`, the payload does not repeat the instrument.
+fn parse_chart(chart: &str, data: Chart) -> Option<Vec<Record>> {
+    let (symbol, _) = chart.rsplit_once('.')?;
+    Some(vec![Record::data(
+        EXCHANGE,
+        "chart.trades",
+        symbol,
+        VenueData::Candle(Candle {
+            symbol: symbol.to_owned(),
+            instrument: None,
+            start: data.tick,
+            open: data.open,
+            high: data.high,
+            low: data.low,
+            close: data.close,
+            volume: data.volume,
+            trade_count: None,
+            missing: false,
+        }),
+    )])
+}
+
+#[derive(Deserialize, Debug)]
+struct Notification {
+    method: String,
+    params: Params,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    channel: String,
+    data: Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct Ticker {
+    instrument_name: String,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    timestamp: DateTime<Utc>,
+    open_interest: Decimal,
+    index_price: Decimal,
+    funding_8h: Option<Decimal>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Chart {
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    tick: DateTime<Utc>,
+    open: Decimal,
+    high: Decimal,
+    low: Decimal,
+    close: Decimal,
+    volume: Decimal,
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorDetail {
+    code: i64,
+    message: String,
+}
+
+/// One url by currency of the symbols (e.g. `BTC` for `BTC-PERPETUAL`).
+pub fn reference_urls(rest_url: &str, symbols: &[String]) -> Vec<String> {
+    let mut currencies = symbols
+        .iter()
+        .filter_map(|symbol| symbol.split('-').next())
+        .collect::<Vec<_>>();
+    currencies.sort();
+    currencies.dedup();
+    currencies
+        .into_iter()
+        .map(|currency| format!("{rest_url}/api/v2/public/get_instruments?currency={currency}&kind=future"))
+        .collect()
+}
+
+/// The lot size is the minimum trade amount, the amounts being multiples of it.
+pub fn instruments(body: &str, as_of: DateTime<Utc>) -> Result<Vec<Instrument>> {
+    let response = from_str::<InstrumentsResponse>(body)?;
+    Ok(response
+        .result
+        .into_iter()
+        .map(|data| Instrument {
+            symbol: data.instrument_name,
+            instrument: None,
+            as_of,
+            tick_size: Some(data.tick_size),
+            lot_size: Some(data.min_trade_amount),
+            status: if data.is_active { "active" } else { "inactive" }.to_owned(),
+            expiry: (data.settlement_period != "perpetual")
+                .then(|| DateTime::from_timestamp_millis(data.expiration_timestamp))
+                .flatten(),
+        })
+        .collect())
+}
+
+#[derive(Deserialize, Debug)]
+struct InstrumentsResponse {
+    result: Vec<InstrumentData>,
+}
+
+#[derive(Deserialize, Debug)]
+struct InstrumentData {
+    instrument_name: String,
+    tick_size: Decimal,
+    min_trade_amount: Decimal,
+    is_active: bool,
+    settlement_period: String,
+    expiration_timestamp: i64,
+}
@@ -0,0 +1,102 @@
+//! Derives metrics from the quotes of the venues (mid price, spread, microprice and imbalance), persisted to the
+//! `derived` table so the consumers do not need a post-processing pass.
+//!
+//! ## Features
+//! - Enabled per table with `--derive` (e.g. `ticker`), or per venue and table (e.g. `bitfinex/ticker`), the entries
+//!   of the other venues are ignored.
+//! - Derives from the records carrying the best bid and ask with their sizes, e.g. the Bitfinex tickers.
+//! - Runs on every record before conflation and sampling, so the metrics keep the full fidelity of the quotes.
+//! - Leaves the metrics undefined by the quote as nulls (e.g. the microprice and imbalance without sizes).
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use dixit_persist_macros::Persist;
+use rust_decimal::Decimal;
+
+use crate::config::Venue;
+use crate::model::VenueData;
+
+/// Basis points in a unit.
+const BPS: Decimal = Decimal::from_parts(10_000, 0, 0, false, 0);
+
+/// Best bid and ask of a symbol with their sizes, at `time`.
+pub struct Quote<'a> {
+    pub symbol: &'a str,
+    pub time: DateTime<Utc>,
+    pub bid: Decimal,
+    pub bid_size: Decimal,
+    pub ask: Decimal,
+    pub ask_size: Decimal,
+}
+
+/// `source` is the table of the quote, `microprice` weights each side with the size of the other one and
+/// `imbalance` goes from -1 (only asks) to 1 (only bids).
+#[derive(Debug, Persist)]
+pub struct Derived {
+    pub symbol: String,
+    #[persist(intern)]
+    pub instrument: Option<String>,
+    #[persist(intern)]
+    pub source: String,
+    pub time: DateTime<Utc>,
+    pub mid: Decimal,
+    pub spread_bps: Option<Decimal>,
+    pub microprice: Option<Decimal>,
+    pub imbalance: Option<Decimal>,
+}
+
+impl Derived {
+    pub fn new(source: &str, quote: Quote) -> Self {
+        let mid = (quote.bid + quote.ask) / Decimal::TWO;
+        let spread_bps = (!mid.is_zero()).then(|| (quote.ask - quote.bid) / mid * BPS);
+        let sizes = quote.bid_size + quote.ask_size;
+        let (microprice, imbalance) = if sizes.is_zero() {
+            (None, None)
+        } else {
+            (
+                Some((quote.bid * quote.ask_size + quote.ask * quote.bid_size) / sizes),
+                Some((quote.bid_size - quote.ask_size) / sizes),
+            )
+        };
+        Self {
+            symbol: quote.symbol.to_owned(),
+            instrument: None,
+            source: source.to_owned(),
+            time: quote.time,
+            mid,
+            spread_bps,
+            microprice,
+            imbalance,
+        }
+    }
+}
+
+/// Tables deriving metrics from their quotes.
+#[derive(Default)]
+pub struct Deriver {
+    tables: HashSet<String>,
+}
+
+impl Deriver {
+    /// Keeps the entries of the venue, given as `table` or `venue/table`.
+    pub fn new(venue: Venue, entries: impl IntoIterator<Item = String>) -> Self {
+        let venue = venue.to_string();
+        let tables = entries
+            .into_iter()
+            .filter_map(|entry| match entry.split_once('/') {
+                Some((entry_venue, table)) => (entry_venue == venue).then(|| table.to_owned()),
+                None => Some(entry),
+            })
+            .collect();
+        Self { tables }
+    }
+
+    pub fn derive(&self, data: &VenueData) -> Option<Derived> {
+        let table = data.table();
+        if !self.tables.contains(table) {
+            return None;
+        }
+        Some(Derived::new(table, data.quote()?))
+    }
+}
@@ -0,0 +1,83 @@
+//! Errors of the recorder shared by its sources and stages, so a failure keeps its cause and callers match on it
+//! instead of parsing a message.
+//!
+//! ## Features
+//! - Maps the errors reported by the venues as [`Record::Error`]s to [`RecorderError::Venue`], with their severity.
+//! - Keeps the websocket failures with their `tungstenite` source, and the aborts of the error policy with the venue
+//!   error causing them.
+//! - Wraps the [`PersistError`]s of the tables and the io errors, their sources preserved.
+//! - Converts into `anyhow::Error` as the other errors, read back with `downcast_ref::<RecorderError>()`.
+
+use std::io;
+
+use dixit_persist::error::PersistError;
+use thiserror::Error;
+use tokio_tungstenite::tungstenite;
+
+use crate::model::{Record, Severity};
+
+#[derive(Error, Debug)]
+pub enum RecorderError {
+    /// an error reported by a venue, e.g. a subscription to an unknown symbol
+    #[error("[{exchange}] [{channel}] {severity:?} {message}: {reason}")]
+    Venue {
+        exchange: String,
+        channel: String,
+        severity: Severity,
+        message: String,
+        reason: String,
+    },
+    /// the websocket connection to the venue failed
+    #[error("websocket {url} failed: {source}")]
+    Websocket {
+        url: String,
+        #[source]
+        source: tungstenite::Error,
+    },
+    /// the error policy aborted the connection after a venue error
+    #[error("aborting {url} after {cause}")]
+    Aborted {
+        url: String,
+        #[source]
+        cause: Box<RecorderError>,
+    },
+    /// the persist service stopped before the record was sent to it
+    #[error("persist service stopped")]
+    PersistStopped,
+    #[error(transparent)]
+    Persist(#[from] PersistError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl RecorderError {
+    /// The error reported by the record, none if it is not a [`Record::Error`].
+    pub fn venue(record: &Record) -> Option<Self> {
+        let Record::Error {
+            exchange,
+            channel,
+            severity,
+            message,
+            reason,
+        } = record
+        else {
+            return None;
+        };
+        Some(Self::Venue {
+            exchange: exchange.clone(),
+            channel: channel.clone(),
+            severity: *severity,
+            message: message.clone(),
+            reason: reason.clone(),
+        })
+    }
+
+    /// The severity of a venue error, or of the venue error an abort comes from.
+    pub fn severity(&self) -> Option<Severity> {
+        match self {
+            Self::Venue { severity, .. } => Some(*severity),
+            Self::Aborted { cause, .. } => cause.severity(),
+            _ => None,
+        }
+    }
+}
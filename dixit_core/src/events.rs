@@ -0,0 +1,104 @@
+//! Records the lifecycle events of the recorder to the `_events` table, so the data consumers can align data quality
+//! issues with operational events without reading the logs.
+//!
+//! ## Features
+//! - Connections, disconnections and resubscriptions of the sources, with the address or the reason.
+//! - Sequence gaps detected by the FIX and multicast sources, the Coinbase `full` channel and the Binance depth diffs.
+//! - Flushes and rotations of the tables by the persist service, and the errors reported by the venues.
+//! - Registrations of the tables in the Glue catalog, with the `catalog` module.
+//! - Events are timestamped by the clock of their source (the local clock adjusted by the `clock` module if enabled)
+//!   and kept while recording is paused.
+
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use dixit_persist::service::ServiceEvent;
+use dixit_persist_macros::Persist;
+
+use crate::model::{Record, VenueData};
+
+pub const EXCHANGE: &str = "dixit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Connect,
+    Disconnect,
+    Resubscribe,
+    Gap,
+    Flush,
+    Rotation,
+    Catalog,
+    Error,
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            EventKind::Connect => "connect",
+            EventKind::Disconnect => "disconnect",
+            EventKind::Resubscribe => "resubscribe",
+            EventKind::Gap => "gap",
+            EventKind::Flush => "flush",
+            EventKind::Rotation => "rotation",
+            EventKind::Catalog => "catalog",
+            EventKind::Error => "error",
+        };
+        write!(f, "{kind}")
+    }
+}
+
+#[derive(Debug, Persist)]
+pub struct Event {
+    pub time: DateTime<Utc>,
+    pub kind: String,
+    /// what the event is about, e.g. the websocket url, the FIX acceptor or the table
+    pub source: String,
+    pub detail: String,
+}
+
+impl Event {
+    pub fn new(time: DateTime<Utc>, kind: EventKind, source: &str, detail: impl Into<String>) -> Self {
+        Self {
+            time,
+            kind: kind.to_string(),
+            source: source.to_owned(),
+            detail: detail.into(),
+        }
+    }
+
+    /// The record of the event, keyed by its kind.
+    pub fn record(self) -> Record {
+        let kind = self.kind.clone();
+        Record::data(EXCHANGE, "events", &kind, VenueData::Event(self))
+    }
+
+    /// The event of a flush or rotation reported by the persist service at `time`.
+    pub fn service(event: ServiceEvent, time: DateTime<Utc>) -> Self {
+        match event {
+            ServiceEvent::Flush { table, rows } => Event::new(time, EventKind::Flush, &table, format!("{rows} rows")),
+            ServiceEvent::BudgetFlush { table, rows } => Event::new(
+                time,
+                EventKind::Flush,
+                &table,
+                format!("{rows} rows, over the buffered rows budget"),
+            ),
+            ServiceEvent::Rotation { tables } => Event::new(
+                time,
+                EventKind::Rotation,
+                &tables.join(","),
+                format!("{} tables", tables.len()),
+            ),
+            // persisted to the _stats table by the persister, see the quality module
+            ServiceEvent::ColumnStats { table, stats } => Event::new(
+                time,
+                EventKind::Flush,
+                &table,
+                format!("{} rows, {} columns measured", stats.rows, stats.columns.len()),
+            ),
+            // registered in the catalog by the persister, see the catalog module
+            ServiceEvent::TableCreated { table, format, .. } => {
+                Event::new(time, EventKind::Flush, &table, format!("first {format:?} file written"))
+            }
+        }
+    }
+}
@@ -0,0 +1,346 @@
+//! Serves the recorded Parquet tables over Arrow Flight, so they can be pulled without copying files.
+//!
+//! ## Features
+//! - Implements `DoGet` of the Flight service with `tonic`, every other Flight method is unimplemented.
+//! - Tickets are JSON queries naming a table (e.g. `{"table": "bitfinex/trades"}`) and optionally a time range
+//!   (`start` inclusive, `end` exclusive) applied to a timestamp column, `time` by default.
+//! - Streams the schema followed by the filtered record batches of every file of the table, in file order.
+//! - Streams the schema of the first file of the table: the columns of the other files are cast to its types and
+//!   their extra columns dropped, the table is rejected if a file misses a column or has a type that cannot be cast.
+//! - Skips the files outside of the time range without opening them if the table keeps a time index of the column
+//!   (recorded with `--time-index`), see [`dixit_persist::index`].
+//! - Skips the files not finalized yet of the tables written with `--finalize-markers`, see
+//!   [`dixit_persist::finalize`].
+//!
+//! The Flight messages are written by hand instead of depending on `arrow-flight`, as done for the gRPC ingest.
+//!
+//! ## Example
+//! ```python
+//! import json, pyarrow.flight as flight
+//!
+//! client = flight.connect("grpc://localhost:50052")
+//! ticket = flight.Ticket(json.dumps({"table": "bitfinex/trades", "start": "2024-09-01T00:00:00Z"}))
+//! df = client.do_get(ticket).read_pandas()
+//! ```
+
+// the helpers return the `tonic::Status` sent to the client as is
+#![allow(clippy::result_large_err)]
+
+use std::convert::Infallible;
+use std::fs::{self, File};
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use arrow::array::{AsArray, BooleanArray, RecordBatch};
+use arrow::compute::{can_cast_types, cast, filter_record_batch};
+use arrow::datatypes::{DataType, Int64Type, Schema, SchemaRef};
+use arrow::ipc::writer::{DictionaryTracker, EncodedData, IpcDataGenerator, IpcWriteOptions};
+use chrono::{DateTime, Utc};
+use dixit_persist::finalize;
+use dixit_persist::index::TableIndex;
+use futures::stream;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tonic::codec::ProstCodec;
+use tonic::codegen::{empty_body, http, Body, BoxFuture, BoxStream, Context, Poll, Service, StdError};
+use tonic::server::{Grpc, NamedService, ServerStreamingService};
+use tonic::transport::Server;
+use tonic::{Code, Status};
+use tracing::info;
+
+const DO_GET_PATH: &str = "/arrow.flight.protocol.FlightService/DoGet";
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Ticket {
+    #[prost(bytes = "vec", tag = "1")]
+    pub ticket: Vec<u8>,
+}
+
+/// Only the fields used by `DoGet` responses, the flight descriptor (tag 1) is never sent.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FlightData {
+    #[prost(bytes = "vec", tag = "2")]
+    pub data_header: Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub app_metadata: Vec<u8>,
+    #[prost(bytes = "vec", tag = "1000")]
+    pub data_body: Vec<u8>,
+}
+
+impl From<EncodedData> for FlightData {
+    fn from(data: EncodedData) -> Self {
+        Self {
+            data_header: data.ipc_message,
+            app_metadata: vec![],
+            data_body: data.arrow_data,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Query {
+    pub table: String,
+    #[serde(default = "Query::default_column")]
+    pub column: String,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl Query {
+    fn default_column() -> String {
+        "time".to_owned()
+    }
+
+    fn parse(ticket: &[u8]) -> Result<Self, Status> {
+        let query: Query =
+            serde_json::from_slice(ticket).map_err(|e| Status::invalid_argument(format!("invalid ticket: {e}")))?;
+        let relative = Path::new(&query.table);
+        if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(Status::invalid_argument(format!("invalid table {}", query.table)));
+        }
+        Ok(query)
+    }
+
+    fn is_filtered(&self) -> bool {
+        self.start.is_some() || self.end.is_some()
+    }
+
+    fn filter(&self, batch: &RecordBatch) -> Result<RecordBatch> {
+        if !self.is_filtered() {
+            return Ok(batch.clone());
+        }
+        let column = batch
+            .column_by_name(&self.column)
+            .ok_or_else(|| anyhow!("no column {} to filter by", self.column))?;
+        if !matches!(column.data_type(), DataType::Timestamp(_, _)) {
+            return Err(anyhow!("column {} is not a timestamp", self.column));
+        }
+        let nanos = cast(column, &DataType::Int64)?;
+        let start = self.start.and_then(|t| t.timestamp_nanos_opt()).unwrap_or(i64::MIN);
+        let end = self.end.and_then(|t| t.timestamp_nanos_opt()).unwrap_or(i64::MAX);
+        let predicate: BooleanArray = nanos
+            .as_primitive::<Int64Type>()
+            .iter()
+            .map(|time| time.map(|time| start <= time && time < end))
+            .collect();
+        Ok(filter_record_batch(batch, &predicate)?)
+    }
+}
+
+pub async fn run(address: SocketAddr, directory: PathBuf) -> Result<()> {
+    info!("serving {directory:?} over Arrow Flight on {address}");
+    Server::builder()
+        .add_service(FlightService { directory })
+        .serve(address)
+        .await?;
+    Ok(())
+}
+
+/// Returns the Parquet files of a table in the order they were written.
+fn files(directory: &Path, table: &str) -> Result<Vec<PathBuf>, Status> {
+    let entries = fs::read_dir(directory.join(table)).map_err(|_| Status::not_found(format!("no table {table}")))?;
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "parquet"))
+        .collect();
+    files.sort();
+    let files = finalize::finalized(&directory.join(table), files);
+    if files.is_empty() {
+        return Err(Status::not_found(format!("no files in table {table}")));
+    }
+    Ok(files)
+}
+
+/// Drops the files of the table outside of the time range of the query, according to the time index of the table.
+fn in_range(directory: &Path, query: &Query, files: Vec<PathBuf>) -> Result<Vec<PathBuf>, Status> {
+    if !query.is_filtered() {
+        return Ok(files);
+    }
+    let index = TableIndex::load(&directory.join(&query.table)).map_err(internal)?;
+    let files = files
+        .into_iter()
+        .filter(|file| {
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            !index.skips(&name, &query.column, query.start, query.end)
+        })
+        .collect();
+    Ok(files)
+}
+
+fn file_schema(file: &Path) -> Result<SchemaRef, Status> {
+    Ok(
+        ParquetRecordBatchReaderBuilder::try_new(File::open(file).map_err(internal)?)
+            .map_err(internal)?
+            .schema()
+            .clone(),
+    )
+}
+
+/// Checks that the batches of a file can be converted to the schema of the stream: every column of the stream has to
+/// be in the file, with the same type or a type that can be cast to it.
+fn check_schema(file: &Path, file_schema: &Schema, schema: &Schema) -> Result<(), Status> {
+    for field in schema.fields() {
+        let Ok(column) = file_schema.field_with_name(field.name()) else {
+            return Err(Status::failed_precondition(format!(
+                "{file:?} has no column {}",
+                field.name()
+            )));
+        };
+        if !can_cast_types(column.data_type(), field.data_type()) {
+            return Err(Status::failed_precondition(format!(
+                "{file:?}: cannot cast column {} from {} to {}",
+                field.name(),
+                column.data_type(),
+                field.data_type()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Converts a batch to the schema of the stream, see [`check_schema`].
+fn conform(batch: RecordBatch, schema: &SchemaRef) -> Result<RecordBatch, Status> {
+    if batch.schema() == *schema {
+        return Ok(batch);
+    }
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let column = batch
+                .column_by_name(field.name())
+                .ok_or_else(|| Status::internal(format!("no column {}", field.name())))?;
+            cast(column, field.data_type()).map_err(internal)
+        })
+        .collect::<Result<Vec<_>, Status>>()?;
+    RecordBatch::try_new(schema.clone(), columns).map_err(internal)
+}
+
+fn do_get(directory: &Path, query: Query) -> Result<BoxStream<FlightData>, Status> {
+    let files = files(directory, &query.table)?;
+    let schema = file_schema(&files[0])?;
+    let files = in_range(directory, &query, files)?;
+    for file in &files {
+        check_schema(file, &*file_schema(file)?, &schema)?;
+    }
+
+    let (tx, rx) = mpsc::channel(16);
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = stream_table(&tx, &schema, &files, &query) {
+            let _ = tx.blocking_send(Err(e));
+        }
+    });
+    Ok(Box::pin(stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|data| (data, rx))
+    })))
+}
+
+fn stream_table(
+    tx: &mpsc::Sender<Result<FlightData, Status>>,
+    schema: &SchemaRef,
+    files: &[PathBuf],
+    query: &Query,
+) -> Result<(), Status> {
+    let generator = IpcDataGenerator::default();
+    let options = IpcWriteOptions::default();
+    let mut tracker = DictionaryTracker::new(false);
+
+    let encoded = generator.schema_to_bytes_with_dictionary_tracker(schema, &mut tracker, &options);
+    if tx.blocking_send(Ok(encoded.into())).is_err() {
+        return Ok(());
+    }
+    for file in files {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(file).map_err(internal)?)
+            .map_err(internal)?
+            .build()
+            .map_err(internal)?;
+        for batch in reader {
+            let batch = conform(batch.map_err(internal)?, schema)?;
+            let batch = query
+                .filter(&batch)
+                .map_err(|e| Status::invalid_argument(e.to_string()))?;
+            if batch.num_rows() == 0 {
+                continue;
+            }
+            let (dictionaries, batch) = generator
+                .encoded_batch(&batch, &mut tracker, &options)
+                .map_err(internal)?;
+            for data in dictionaries.into_iter().chain([batch]) {
+                if tx.blocking_send(Ok(data.into())).is_err() {
+                    // the client went away
+                    return Ok(());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn internal(e: impl std::fmt::Display) -> Status {
+    Status::internal(e.to_string())
+}
+
+/// Routes requests to the `DoGet` method, what `tonic-build` would generate for the `FlightService`.
+#[derive(Clone)]
+struct FlightService {
+    directory: PathBuf,
+}
+
+impl NamedService for FlightService {
+    const NAME: &'static str = "arrow.flight.protocol.FlightService";
+}
+
+impl<B> Service<http::Request<B>> for FlightService
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        if request.uri().path() != DO_GET_PATH {
+            return Box::pin(async move {
+                Ok(http::Response::builder()
+                    .status(200)
+                    .header("grpc-status", (Code::Unimplemented as i32).to_string())
+                    .header(http::header::CONTENT_TYPE, "application/grpc")
+                    .body(empty_body())
+                    .expect("valid response"))
+            });
+        }
+        let method = DoGetMethod {
+            directory: self.directory.clone(),
+        };
+        Box::pin(async move {
+            let mut grpc = Grpc::new(ProstCodec::default());
+            Ok(grpc.server_streaming(method, request).await)
+        })
+    }
+}
+
+struct DoGetMethod {
+    directory: PathBuf,
+}
+
+impl ServerStreamingService<Ticket> for DoGetMethod {
+    type Response = FlightData;
+    type ResponseStream = BoxStream<FlightData>;
+    type Future = BoxFuture<tonic::Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, request: tonic::Request<Ticket>) -> Self::Future {
+        let directory = self.directory.clone();
+        Box::pin(async move {
+            let query = Query::parse(&request.into_inner().ticket)?;
+            info!("serving {query:?}");
+            do_get(&directory, query).map(tonic::Response::new)
+        })
+    }
+}
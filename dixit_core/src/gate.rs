@@ -0,0 +1,137 @@
+//! Contains Gate.io-specific WebSocket handling logic.
+//!
+//! ## Features
+//! - Subscribes to the spot trades (`spot.trades`) of each currency pair (e.g. `BTC_USDT`) on the Gate.io v4 feed.
+//! - Parses the trade updates into normalized trade records, persisted to the `trades` table.
+//! - Sends a `spot.ping` request every 20 seconds, as the feed expects application pings.
+//! - Reports the rejected requests (e.g. an unknown currency pair) as errors.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{de, Deserialize, Deserializer};
+use serde_json::{from_str, json, Value};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::model::{Record, Severity, Trade, VenueData};
+
+pub const EXCHANGE: &str = "gate";
+pub const WS_URL: &str = "wss://api.gateio.ws/ws/v4/";
+pub const SYMBOLS: &[&str] = &["BTC_USDT", "ETH_USDT"];
+
+/// Interval between the `spot.ping` requests keeping the connection open.
+pub const PING_INTERVAL: Duration = Duration::from_secs(20);
+
+const TRADES: &str = "spot.trades";
+
+/// Requests subscribing the symbols, at `now` as every request carries the time it was sent.
+pub fn subscribe(symbols: &[String], now: DateTime<Utc>) -> Vec<Message> {
+    request("subscribe", symbols, now)
+}
+
+pub fn unsubscribe(symbols: &[String], now: DateTime<Utc>) -> Vec<Message> {
+    request("unsubscribe", symbols, now)
+}
+
+pub fn ping(now: DateTime<Utc>) -> Message {
+    let request = json!({
+        "time": now.timestamp(),
+        "channel": "spot.ping"
+    });
+    Message::Text(request.to_string())
+}
+
+fn request(event: &str, symbols: &[String], now: DateTime<Utc>) -> Vec<Message> {
+    if symbols.is_empty() {
+        return vec![];
+    }
+    let request = json!({
+        "time": now.timestamp(),
+        "channel": TRADES,
+        "event": event,
+        "payload": symbols
+    });
+    vec![Message::Text(request.to_string())]
+}
+
+pub fn handle(message: Message) -> Vec<Record> {
+    match message {
+        Message::Text(string) => {
+            let Ok(push) = from_str::<Push>(&string) else {
+                return vec![Record::unhandled(string)];
+            };
+            if let Some(error) = push.error {
+                return vec![Record::error(
+                    EXCHANGE,
+                    &push.channel,
+                    Severity::Warning,
+                    error.message,
+                    format!("{} rejected with code {}", push.event, error.code),
+                )];
+            }
+            if push.channel == TRADES && push.event == "update" {
+                if let Ok(trade) = TradeData::deserialize(&push.result) {
+                    return vec![parse_trade(trade)];
+                }
+            }
+            vec![Record::unhandled(string)]
+        }
+        _ => vec![Record::non_text()],
+    }
+}
+
+/// `side` is the side of the taker order.
+fn parse_trade(trade: TradeData) -> Record {
+    Record::data(
+        EXCHANGE,
+        "trades",
+        &trade.currency_pair,
+        VenueData::Trade(Trade {
+            symbol: trade.currency_pair.clone(),
+            instrument: None,
+            time: trade.create_time_ms,
+            price: trade.price,
+            size: trade.amount,
+            side: trade.side,
+        }),
+    )
+}
+
+/// Gate.io encodes the trade time as a string of epoch milliseconds with a fractional part (e.g. `1606292218213.4578`).
+fn millis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    let string = String::deserialize(deserializer)?;
+    string
+        .parse::<Decimal>()
+        .ok()
+        .and_then(|millis| (millis * Decimal::ONE_THOUSAND).trunc().to_i64())
+        .and_then(DateTime::from_timestamp_micros)
+        .ok_or_else(|| de::Error::custom(format!("invalid timestamp {string}")))
+}
+
+#[derive(Deserialize, Debug)]
+struct Push {
+    channel: String,
+    #[serde(default)]
+    event: String,
+    error: Option<PushError>,
+    #[serde(default)]
+    result: Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct PushError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct TradeData {
+    currency_pair: String,
+    #[serde(deserialize_with = "millis")]
+    create_time_ms: DateTime<Utc>,
+    side: String,
+    amount: Decimal,
+    price: Decimal,
+}
@@ -0,0 +1,2850 @@
+//! # Recorder
+//! Collects market data (e.g. Coinbase RFQs, Bitfinex trades, derivatives funding) via WebSocket, processes the data,
+//! and stores it in Parquet files for further analysis.
+//!
+//! The `dixit` binary is a thin wrapper over [`run_recorder`], other services embed the recorder the same way, or
+//! record a venue of their own implementing [`CustomVenue`] with [`run_venue`].
+//!
+//! ## Overview
+//! - Configures and initializes the application settings using the `config` module, optionally overridden by a
+//!   YAML file reloaded while recording with the `settings` module.
+//! - Uses the `websocket` module to connect to the venue WebSocket feed and handle messages.
+//! - Records any JSON feed without a typed handler with the `auto` module, inferring the schema of each channel.
+//! - Optionally serves a control socket with the `control` module, to subscribe and unsubscribe symbols at runtime
+//!   through the `ctl` subcommand.
+//! - Optionally serves an admin HTTP API with the `admin` module (`admin` feature), to flush, rotate, enable or
+//!   disable the tables, change the subscriptions and pause recording.
+//! - Optionally measures the offset of the local clock against an NTP server with the `clock` module, persisted to
+//!   the `_clock` table.
+//! - Uses the `source` module to poll venue REST endpoints for data that is not streamed, or to serve a gRPC
+//!   ingest endpoint for internal producers (`grpc` feature).
+//! - Pulls the reference data of the venue instruments (tick and lot sizes, status, expiry) on startup and
+//!   periodically with the `reference` module.
+//! - Persists processed data into Parquet files using the `persister` module, optionally sampling some tables with
+//!   the `sampling` module, or conflating quote-style tables with the `conflation` module.
+//! - Optionally derives metrics from the quotes (mid, spread, microprice, imbalance) with the `derived` module.
+//! - Documents every capture run with a `_manifest.json` in the venue directory, using the `manifest` module.
+//! - Optionally mirrors the parsed records to Redis, NATS or a ZeroMQ PUB socket (`redis`, `nats` and `zmq`
+//!   features), or streams them as JSON lines to stdout or a unix socket, with the `publish` module.
+//! - Serves the recorded tables over Arrow Flight with the `flight` subcommand (`flight` feature).
+//! - Runs SQL over the recorded tables with the `query` subcommand (`query` feature).
+//! - Notifies systemd when ready and pings its watchdog while the feed is fresh, or runs as a Windows service, with
+//!   the `supervisor` module.
+//! - Checks the checksums and Parquet footers of the recorded tables with the `verify` subcommand.
+//! - Uploads the recorded tables to an object store after recording, or reconciles an output tree with it with the
+//!   `sync` subcommand, tracking the uploads in a manifest with the `upload` module (`s3` feature).
+//! - Registers the tables and their date partitions in the Glue catalog as their first files are written, with the
+//!   `catalog` module (`glue` feature).
+//! - Load tests the persister with synthetic trades and book updates with the `bench-feed` subcommand, using the
+//!   `bench` module.
+//! - Defines data structures in the `model` module to represent venue records and errors.
+//! - Records the lifecycle events of the recorder (connections, gaps, flushes, rotations, errors) to the `_events`
+//!   table with the `events` module.
+//! - Optionally acknowledges the records of the sequenced sources once persisted and saves their watermark with the
+//!   `watermark` module, so a restart backfills the gap.
+//! - Maps the venue symbols to canonical instruments with the `symbology` module, carried by the normalized tables.
+//! - Optionally persists the statistics of the columns of every flush to the `_stats` table with the `quality` module.
+//! - Optionally validates the records against data quality rules before persisting them, counting, persisting to the
+//!   `_violations` table or rejecting the violations, with the `quality` module.
+//!
+//! ## Workflow
+//! 1. Initialize the application and parse arguments (see [`config::init`]).
+//! 2. Set up a communication channel between the WebSocket handler and the persister.
+//! 3. Launch tasks to handle WebSocket connections, REST polling and data persistence.
+//! 4. Process and persist data until the application is stopped.
+
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{mpsc, watch};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{error, info};
+
+use bars::TradeAggregator;
+use clock::NtpAdjustedClock;
+use config::{Args, Venue};
+use conflation::Conflator;
+use derived::Deriver;
+use manifest::Manifest;
+use model::Record;
+use publish::Publisher;
+use sampling::Sampler;
+use settings::Settings;
+use skips::SkipLog;
+use supervisor::Freshness;
+use symbology::Symbology;
+use watermark::Tracker;
+use websocket::Subscriptions;
+
+#[cfg(feature = "admin")]
+pub mod admin;
+pub mod auto;
+pub mod bars;
+pub mod bench;
+#[cfg(feature = "binance")]
+pub mod binance_futures;
+#[cfg(feature = "bitfinex")]
+pub mod bitfinex;
+#[cfg(feature = "bithumb")]
+pub mod bithumb;
+#[cfg(feature = "bybit")]
+pub mod bybit;
+pub mod candles;
+#[cfg(feature = "glue")]
+pub mod catalog;
+pub mod clock;
+#[cfg(feature = "coinbase")]
+pub mod coinbase;
+#[cfg(feature = "coinbase")]
+pub mod coinbase_advanced;
+pub mod conflation;
+pub mod control;
+#[cfg(feature = "deribit")]
+pub mod deribit;
+pub mod derived;
+pub mod error;
+pub mod events;
+#[cfg(feature = "flight")]
+pub mod flight;
+#[cfg(feature = "gate")]
+pub mod gate;
+pub mod manifest;
+#[cfg(feature = "mexc")]
+pub mod mexc;
+#[cfg(feature = "okx")]
+pub mod okx;
+#[cfg(feature = "polygon")]
+pub mod polygon;
+pub mod publish;
+pub mod quality;
+#[cfg(feature = "query")]
+pub mod query;
+pub mod reference;
+pub mod route;
+pub mod sampling;
+pub mod settings;
+pub mod skips;
+pub mod source;
+pub mod supervisor;
+pub mod symbology;
+#[cfg(feature = "upbit")]
+pub mod upbit;
+#[cfg(feature = "s3")]
+pub mod upload;
+pub mod verify;
+pub mod watermark;
+
+/// A venue recorded by an application embedding the recorder, through its websocket feed.
+///
+/// Its records are persisted under the directory of its `name`, e.g. as the normalized data types of the `model`
+/// module, or as the rows of tables built at runtime (`auto::Row`).
+pub trait CustomVenue: Send {
+    /// name of the venue, naming its directory
+    fn name(&self) -> &'static str;
+
+    fn url(&self) -> String;
+
+    /// Extra headers of the upgrade request, e.g. an API key header, added before the ones of `--ws-header`.
+    fn headers(&self) -> Vec<(String, String)> {
+        vec![]
+    }
+
+    /// Requests subscribing the symbols, on (re)connection and on subscription commands.
+    fn subscribe(&self, symbols: &[String]) -> Vec<Message>;
+
+    fn unsubscribe(&self, _symbols: &[String]) -> Vec<Message> {
+        vec![]
+    }
+
+    fn handle(&mut self, message: Message) -> Vec<Record>;
+}
+
+/// Runs a subcommand if any, otherwise records the venue of the arguments until its feed ends.
+pub async fn run_recorder(args: Args) -> Result<()> {
+    if let Some(command) = args.command.clone() {
+        return command.run(args.clock).await;
+    }
+    record(args, None).await
+}
+
+/// Records a venue of the application with the settings of the arguments, their venue being ignored.
+pub async fn run_venue(mut args: Args, venue: impl CustomVenue + 'static) -> Result<()> {
+    config::register(venue.name())?;
+    args.venue = Some(Venue::Custom);
+    record(args, Some(Box::new(venue))).await
+}
+
+async fn record(mut args: Args, custom: Option<Box<dyn CustomVenue>>) -> Result<()> {
+    // the command line arguments are overridden by the config file if any
+    let settings = args.config.as_deref().map(Settings::load).transpose()?;
+    if let Some(settings) = &settings {
+        settings.apply(&mut args);
+    }
+    let venue = args.venue();
+
+    // the sources and the stages read the local clock adjusted by the clock offset if enabled, the persist service
+    // and the supervisor the local clock
+    let local = args.clock.clone();
+    let adjusted = Arc::new(NtpAdjustedClock::new(local.clone(), args.ntp_adjust));
+    args.clock = adjusted.clone();
+
+    // create a channel to send data from the websocket to the persister
+    let (tx, rx) = mpsc::channel::<Record>(100);
+
+    // connect the publishers before recording, so a wrong url fails fast
+    let mut publishers = vec![];
+    if let Some(url) = &args.publish {
+        publishers.push(Publisher::connect(url).await?);
+    }
+    #[cfg(feature = "zmq")]
+    if let Some(endpoint) = &args.zmq_bind {
+        publishers.push(Publisher::bind_zmq(endpoint, args.zmq_encoding).await?);
+    }
+    if let Some(url) = &args.stream {
+        publishers.push(Publisher::stream(url).await?);
+    }
+
+    // launch the persister
+    let service = persister::service(args.persist_config(venue), args.service_options(), local.clone());
+    let persist_tx = service.1.downgrade();
+    let (watermark, acks) = if args.watermark {
+        let (tracker, acks) = Tracker::new(args.watermark_interval.duration(), args.max_unacked);
+        (Some(tracker), Some(acks))
+    } else {
+        (None, None)
+    };
+    let (sampling_tx, sampling_rx) = watch::channel(args.sample.clone());
+    // register the tables in the catalog, only if set
+    #[cfg(feature = "glue")]
+    let (catalog_tx, catalog) = match args.glue_config()? {
+        Some(config) => {
+            let (catalog_tx, catalog_rx) = mpsc::unbounded_channel();
+            let catalog = tokio::spawn(catalog::run(config, catalog_rx, tx.downgrade()));
+            (Some(catalog_tx), Some(catalog))
+        }
+        None => (None, None),
+    };
+    #[cfg(not(feature = "glue"))]
+    let catalog_tx = None;
+    let freshness = Arc::new(Freshness::new(local.clone()));
+    let stages = persister::Stages {
+        bars: TradeAggregator::new(args.bars.clone()),
+        sampler: Sampler::new(args.sample.clone()),
+        sampling: sampling_rx,
+        validator: args.validator(),
+        catalog: catalog_tx,
+        conflator: Conflator::new(args.conflate.clone(), args.conflate_interval.duration()),
+        deriver: Deriver::new(venue, args.derive.clone()),
+        watermark,
+        symbology: Symbology::load(args.symbology.as_deref())?,
+        router: args.router(),
+        skips: SkipLog::new(args.skip_log_first, args.skip_log_every),
+        clock: args.clock.clone(),
+        freshness: freshness.clone(),
+    };
+    let manifest = Manifest::new(venue, args.symbols(venue.default_symbols()), args.clock.now());
+    let persister = tokio::spawn(async move {
+        if let Err(e) = persister::run(venue, service, stages, manifest, publishers, rx).await {
+            error!("persisted error: {e}");
+        }
+    });
+
+    // launch the rest polling, only for the venues that do not stream everything
+    let rest_args = args.clone();
+    #[cfg(feature = "s3")]
+    let (upload, directory) = (args.upload.clone(), args.directory.clone());
+    #[cfg_attr(not(feature = "binance"), allow(unused_variables))]
+    let rest_tx = tx.clone();
+    let rest = tokio::spawn(async move {
+        #[cfg_attr(not(feature = "binance"), allow(unused_variables))]
+        let interval = rest_args.poll_interval.duration();
+        let polled: Result<()> = match venue {
+            #[cfg(feature = "binance")]
+            Venue::BinanceFutures => {
+                let rest_url = rest_args.rest_url(binance_futures::REST_URL);
+                let urls = binance_futures::open_interest_urls(&rest_url, &rest_args.symbols(binance_futures::SYMBOLS));
+                let key = binance_futures::open_interest_key;
+                source::rest::run(rest_tx, urls, interval, key, binance_futures::open_interest).await
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = polled {
+            error!("rest error: {e}");
+        }
+    });
+
+    // pull the reference data of the instruments, only for the venues with a REST API listing them
+    let reference = (!args.no_reference)
+        .then(|| args.reference())
+        .flatten()
+        .map(|(urls, parse)| {
+            let (tx, interval, clock) = (tx.clone(), args.reference_interval.duration(), args.clock.clone());
+            tokio::spawn(async move {
+                if let Err(e) = reference::run(tx, venue.to_string(), urls, interval, parse, clock).await {
+                    error!("reference error: {e}");
+                }
+            })
+        });
+
+    // measure the clock offset, only if an NTP server is set
+    let ntp = args.ntp_server.clone().map(|server| {
+        let (tx, interval) = (tx.clone(), args.ntp_interval.duration());
+        tokio::spawn(async move {
+            if let Err(e) = clock::run(tx, server, interval, adjusted).await {
+                error!("clock error: {e}");
+            }
+        })
+    });
+
+    // serve the control socket and the admin API, only if set, the websocket applies the subscription commands
+    let (control_tx, control_rx) = mpsc::channel(16);
+    #[cfg(feature = "admin")]
+    let admin = args.admin_address.map(|address| {
+        let admin = admin::Admin {
+            venue,
+            persist: persist_tx.clone(),
+            control: control_tx.clone(),
+        };
+        tokio::spawn(async move {
+            if let Err(e) = admin::serve(address, admin).await {
+                error!("admin API error: {e}");
+            }
+        })
+    });
+    let control = args.control_socket.clone().map(|path| {
+        let control_tx = control_tx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = control::serve(&path, venue, control_tx).await {
+                error!("control socket error: {e}");
+            }
+        })
+    });
+
+    // reload the config file on changes, only if set
+    let reload = args.config.clone().zip(settings).map(|(path, settings)| {
+        let targets = settings::Targets {
+            venue,
+            control: control_tx.clone(),
+            persist: persist_tx,
+            options: args.service_options(),
+            sampling: sampling_tx,
+        };
+        tokio::spawn(settings::run(path, settings, targets))
+    });
+
+    // notify the service manager and watch the feed staleness while recording
+    let supervisor = tokio::spawn(supervisor::run(freshness, args.stale_after.duration()));
+
+    // launch the websocket
+    let policy = args.error_policy();
+    let mut websocket = tokio::spawn(async move {
+        let ws_url = |url: &str| args.ws_url.clone().unwrap_or_else(|| url.to_owned());
+        let headers = args.ws_headers();
+        let clock = args.clock.clone();
+        if let Err(e) = match venue {
+            #[cfg(feature = "coinbase")]
+            Venue::Coinbase if args.coinbase_api == coinbase::Api::AdvancedTrade => {
+                match args.coinbase_advanced_config() {
+                    Ok(config) => {
+                        let subscribe = |products: &[String]| coinbase_advanced::subscribe(&config, products);
+                        let unsubscribe = |products: &[String]| coinbase_advanced::unsubscribe(&config, products);
+                        let subscriptions = Subscriptions::new(args.symbols(&[]), subscribe, unsubscribe)
+                            .with_headers(headers)
+                            .with_clock(clock.clone());
+                        // the handler verifies the sequence numbers of the connection
+                        let mut handler = coinbase_advanced::Handler::new(clock);
+                        let handle = |message| handler.handle(message);
+                        let url = ws_url(coinbase_advanced::WS_URL);
+                        websocket::run(tx, &url, policy, subscriptions, control_rx, handle).await
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            #[cfg(feature = "coinbase")]
+            Venue::Coinbase => match args.coinbase_channels() {
+                Ok(channels) => {
+                    let subscribe = |products: &[String]| coinbase::subscribe(&channels, products);
+                    let unsubscribe = |products: &[String]| coinbase::unsubscribe(&channels, products);
+                    let subscriptions = Subscriptions::new(args.symbols(&[]), subscribe, unsubscribe)
+                        .with_headers(headers)
+                        .with_clock(clock.clone());
+                    // the handler verifies the sequence numbers of the full channel
+                    let mut handler = coinbase::Handler::new(clock);
+                    let handle = |message| handler.handle(message);
+                    websocket::run(tx, &ws_url(coinbase::WS_URL), policy, subscriptions, control_rx, handle).await
+                }
+                Err(e) => Err(e),
+            },
+            #[cfg(feature = "bitfinex")]
+            Venue::Bitfinex => {
+                // the handler tracks the channels, both to route the messages and to unsubscribe them
+                let handler = Mutex::new(bitfinex::Handler::new(clock.clone()));
+                let unsubscribe = |symbols: &[String]| handler.lock().expect("handler poisoned").unsubscribe(symbols);
+                let subscriptions =
+                    Subscriptions::new(args.symbols(bitfinex::SYMBOLS), bitfinex::subscribe, unsubscribe)
+                        .with_headers(headers)
+                        .with_clock(clock);
+                let handle = |message| handler.lock().expect("handler poisoned").handle(message);
+                websocket::run(tx, &ws_url(bitfinex::WS_URL), policy, subscriptions, control_rx, handle).await
+            }
+            #[cfg(feature = "binance")]
+            Venue::BinanceFutures => {
+                let depth = args.binance_depth;
+                let subscribe = |symbols: &[String]| binance_futures::subscribe(depth, symbols);
+                let unsubscribe = |symbols: &[String]| binance_futures::unsubscribe(depth, symbols);
+                let subscriptions = Subscriptions::new(args.symbols(binance_futures::SYMBOLS), subscribe, unsubscribe)
+                    .with_headers(headers)
+                    .with_clock(clock.clone());
+                // the handler synchronizes the depth diffs with the snapshots fetched by a separate task
+                let rest_url = args.rest_url(binance_futures::REST_URL);
+                let (mut handler, snapshots) = binance_futures::Handler::new(&rest_url, clock);
+                tokio::spawn(snapshots.run());
+                let handle = |message| handler.handle(message);
+                websocket::run(
+                    tx,
+                    &ws_url(binance_futures::WS_URL),
+                    policy,
+                    subscriptions,
+                    control_rx,
+                    handle,
+                )
+                .await
+            }
+            #[cfg(feature = "okx")]
+            Venue::Okx => {
+                // candles are only served on the business endpoint, recorded over a connection of their own
+                let candles = args.okx_candles_url().map(|url| {
+                    let subscriptions = Subscriptions::new(
+                        args.symbols(okx::SYMBOLS),
+                        okx::subscribe_candles,
+                        okx::unsubscribe_candles,
+                    )
+                    .with_headers(headers.clone())
+                    .with_clock(clock.clone());
+                    let tx = tx.clone();
+                    // no subscription commands, the symbols subscribed at runtime only get the public channels
+                    let (_, control_rx) = mpsc::channel(1);
+                    tokio::spawn(async move {
+                        if let Err(e) = websocket::run(tx, &url, policy, subscriptions, control_rx, okx::handle).await {
+                            error!("candles websocket error: {e}");
+                        }
+                    })
+                });
+                let families = &args.okx_option_families;
+                let subscribe = |symbols: &[String]| okx::subscribe(families, symbols);
+                let subscriptions = Subscriptions::new(args.symbols(okx::SYMBOLS), subscribe, okx::unsubscribe)
+                    .with_headers(headers)
+                    .with_clock(clock);
+                let recorded =
+                    websocket::run(tx, &ws_url(okx::WS_URL), policy, subscriptions, control_rx, okx::handle).await;
+                if let Some(candles) = candles {
+                    candles.abort();
+                }
+                recorded
+            }
+            #[cfg(feature = "bybit")]
+            Venue::Bybit => {
+                let subscriptions =
+                    Subscriptions::new(args.symbols(bybit::SYMBOLS), bybit::subscribe, bybit::unsubscribe)
+                        .with_headers(headers)
+                        .with_clock(clock);
+                websocket::run(
+                    tx,
+                    &ws_url(bybit::WS_URL),
+                    policy,
+                    subscriptions,
+                    control_rx,
+                    bybit::handle,
+                )
+                .await
+            }
+            #[cfg(feature = "deribit")]
+            Venue::Deribit => {
+                let symbols = args.symbols(deribit::SYMBOLS);
+                let subscriptions = Subscriptions::new(symbols, deribit::subscribe, deribit::unsubscribe)
+                    .with_headers(headers)
+                    .with_clock(clock);
+                websocket::run(
+                    tx,
+                    &ws_url(deribit::WS_URL),
+                    policy,
+                    subscriptions,
+                    control_rx,
+                    deribit::handle,
+                )
+                .await
+            }
+            #[cfg(feature = "mexc")]
+            Venue::Mexc => {
+                let subscriptions = Subscriptions::new(args.symbols(mexc::SYMBOLS), mexc::subscribe, mexc::unsubscribe)
+                    .with_keepalive(mexc::PING_INTERVAL, mexc::ping)
+                    .with_headers(headers)
+                    .with_clock(clock);
+                websocket::run(
+                    tx,
+                    &ws_url(mexc::WS_URL),
+                    policy,
+                    subscriptions,
+                    control_rx,
+                    mexc::handle,
+                )
+                .await
+            }
+            #[cfg(feature = "gate")]
+            Venue::Gate => {
+                // every request carries the time it was sent
+                let subscribe = |symbols: &[String]| gate::subscribe(symbols, clock.now());
+                let unsubscribe = |symbols: &[String]| gate::unsubscribe(symbols, clock.now());
+                let subscriptions = Subscriptions::new(args.symbols(gate::SYMBOLS), subscribe, unsubscribe)
+                    .with_keepalive(gate::PING_INTERVAL, gate::ping)
+                    .with_headers(headers)
+                    .with_clock(clock.clone());
+                websocket::run(
+                    tx,
+                    &ws_url(gate::WS_URL),
+                    policy,
+                    subscriptions,
+                    control_rx,
+                    gate::handle,
+                )
+                .await
+            }
+            #[cfg(feature = "upbit")]
+            Venue::Upbit => {
+                // a request replaces the subscriptions, the subscriber requests every symbol on each change
+                let subscriber = upbit::Subscriber::default();
+                let subscribe = |symbols: &[String]| subscriber.subscribe(symbols);
+                let unsubscribe = |symbols: &[String]| subscriber.unsubscribe(symbols);
+                let subscriptions = Subscriptions::new(args.symbols(upbit::SYMBOLS), subscribe, unsubscribe)
+                    .with_keepalive(upbit::PING_INTERVAL, upbit::ping)
+                    .with_headers(headers)
+                    .with_clock(clock);
+                websocket::run(
+                    tx,
+                    &ws_url(upbit::WS_URL),
+                    policy,
+                    subscriptions,
+                    control_rx,
+                    upbit::handle,
+                )
+                .await
+            }
+            #[cfg(feature = "bithumb")]
+            Venue::Bithumb => {
+                // a filter replaces the previous one, the subscriber registers every symbol on each change
+                let subscriber = bithumb::Subscriber::default();
+                let subscribe = |symbols: &[String]| subscriber.subscribe(symbols);
+                let unsubscribe = |symbols: &[String]| subscriber.unsubscribe(symbols);
+                let subscriptions = Subscriptions::new(args.symbols(bithumb::SYMBOLS), subscribe, unsubscribe)
+                    .with_headers(headers)
+                    .with_clock(clock);
+                let url = ws_url(bithumb::WS_URL);
+                websocket::run(tx, &url, policy, subscriptions, control_rx, bithumb::handle).await
+            }
+            #[cfg(feature = "polygon")]
+            Venue::Polygon => match args.polygon_api_key() {
+                Ok(api_key) => {
+                    // every connection is authenticated before subscribing
+                    let subscriptions =
+                        Subscriptions::new(args.symbols(polygon::SYMBOLS), polygon::subscribe, polygon::unsubscribe)
+                            .with_login(polygon::auth(&api_key))
+                            .with_headers(headers)
+                            .with_clock(clock);
+                    let url = ws_url(args.polygon_feed.url());
+                    websocket::run(tx, &url, policy, subscriptions, control_rx, polygon::handle).await
+                }
+                Err(e) => Err(e),
+            },
+            Venue::Auto => match args.auto_config() {
+                Ok(config) => {
+                    let templates = config.subscribe.clone();
+                    let subscribe = |symbols: &[String]| auto::subscribe(&templates, symbols);
+                    let subscriptions = Subscriptions::new(args.symbols(&[]), subscribe, |_: &[String]| vec![])
+                        .with_headers(headers)
+                        .with_clock(clock);
+                    let url = ws_url(&config.url);
+                    // the handler infers the schema of each channel from its first messages
+                    let mut handler = auto::Handler::new(config);
+                    let handle = |message| handler.handle(message);
+                    websocket::run(tx, &url, policy, subscriptions, control_rx, handle).await
+                }
+                Err(e) => Err(e),
+            },
+            // the other sources do not take subscription commands, closing the channel fails them at once
+            Venue::Fix => {
+                drop(control_rx);
+                match args.fix_config() {
+                    Ok(config) => source::fix::run(tx, config, args.symbols(&[]), acks).await,
+                    Err(e) => Err(e),
+                }
+            }
+            Venue::Multicast => {
+                drop(control_rx);
+                match args.multicast_config() {
+                    Ok(config) => source::multicast::run(tx, config, source::multicast::ItchDecoder).await,
+                    Err(e) => Err(e),
+                }
+            }
+            #[cfg(feature = "grpc")]
+            Venue::Grpc => {
+                drop(control_rx);
+                source::grpc::run(tx, args.grpc_address).await
+            }
+            Venue::Custom => {
+                let venue = Mutex::new(custom.expect("custom venue registered"));
+                let url = ws_url(&venue.lock().expect("venue poisoned").url());
+                let venue_headers = venue.lock().expect("venue poisoned").headers();
+                let subscribe = |symbols: &[String]| venue.lock().expect("venue poisoned").subscribe(symbols);
+                let unsubscribe = |symbols: &[String]| venue.lock().expect("venue poisoned").unsubscribe(symbols);
+                let subscriptions = Subscriptions::new(args.symbols(&[]), subscribe, unsubscribe)
+                    .with_headers(venue_headers)
+                    .with_headers(headers)
+                    .with_clock(clock);
+                let handle = |message| venue.lock().expect("venue poisoned").handle(message);
+                websocket::run(tx, &url, policy, subscriptions, control_rx, handle).await
+            }
+        } {
+            error!("websocket error: {e}");
+        }
+    });
+
+    // the rest polling never ends on its own, stop it with the websocket so the persister can finish, the service
+    // manager ends the feed too when stopping the recorder
+    tokio::select! {
+        _ = &mut websocket => {}
+        _ = supervisor::shutdown_requested() => {
+            info!("shutdown requested, ending the feed");
+            websocket.abort();
+            let _ = websocket.await;
+        }
+    }
+    supervisor.abort();
+    supervisor::stopping();
+    rest.abort();
+    if let Some(ntp) = ntp {
+        ntp.abort();
+    }
+    if let Some(reference) = reference {
+        reference.abort();
+    }
+    if let Some(control) = control {
+        control.abort();
+    }
+    if let Some(reload) = reload {
+        reload.abort();
+    }
+    #[cfg(feature = "admin")]
+    if let Some(admin) = admin {
+        admin.abort();
+    }
+    let _ = persister.await;
+    // the tables of the last files are registered once the persister is done
+    #[cfg(feature = "glue")]
+    if let Some(catalog) = catalog {
+        let _ = catalog.await;
+    }
+
+    // upload the venue directory once its tables are closed, only if set
+    #[cfg(feature = "s3")]
+    if let Some(url) = &upload {
+        upload::upload(url, &directory, &venue.to_string()).await?;
+    }
+
+    Ok(())
+}
+
+pub mod config {
+    //! Handles application configuration and initialization.
+    //!
+    //! ## Features
+    //! - Defines the [`Venue`] enum to specify supported venues (e.g., Coinbase, Bitfinex, Bybit).
+    //! - Parses command-line arguments using [`clap`], including the FIX session settings.
+    //! - Defines the [`Command`] subcommands run instead of recording a venue (e.g. `flight`).
+    //! - Configures logging to stderr with environment-based filtering, keeping stdout free for `--stream`.
+    //!
+    //! ## Example
+    //! ```no_run
+    //! use dixit_core::config::init;
+    //!
+    //! let args = init();
+    //! println!("Selected venue: {}", args.venue());
+    //! ```
+
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::sync::OnceLock;
+
+    use clap::{Parser, Subcommand, ValueEnum};
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+    use tracing_subscriber::EnvFilter;
+
+    use dixit_persist::config::{FileNaming, Format, OverwriteMode, PersistConfig};
+    use dixit_persist::csv::{CsvOptions, Quoting};
+    use dixit_persist::retry::RetryPolicy;
+    use dixit_persist::service::ServiceOptions;
+
+    use crate::bars::Interval;
+    use crate::clock::SharedClock;
+    use crate::model::Severity;
+    use crate::quality::{Rule, Validator, ViolationPolicy};
+    use crate::route::{LatePolicy, Router, TableRoute};
+    use crate::sampling::Sampling;
+    use crate::websocket::{ErrorAction, ErrorPolicy};
+    use std::net::{Ipv4Addr, SocketAddrV4};
+
+    use crate::auto::AutoConfig;
+    use crate::source::fix::FixConfig;
+    use crate::source::multicast::MulticastConfig;
+    use crate::watermark::Watermark;
+
+    #[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+    pub enum Venue {
+        #[cfg(feature = "coinbase")]
+        Coinbase,
+        #[cfg(feature = "bitfinex")]
+        Bitfinex,
+        #[cfg(feature = "binance")]
+        BinanceFutures,
+        #[cfg(feature = "okx")]
+        Okx,
+        #[cfg(feature = "bybit")]
+        Bybit,
+        #[cfg(feature = "deribit")]
+        Deribit,
+        #[cfg(feature = "mexc")]
+        Mexc,
+        #[cfg(feature = "gate")]
+        Gate,
+        #[cfg(feature = "upbit")]
+        Upbit,
+        #[cfg(feature = "bithumb")]
+        Bithumb,
+        /// US equities trades and quotes
+        #[cfg(feature = "polygon")]
+        Polygon,
+        /// any JSON websocket feed, the schema of each channel being inferred
+        Auto,
+        Fix,
+        Multicast,
+        #[cfg(feature = "grpc")]
+        Grpc,
+        /// venue of an application embedding the recorder, see [`crate::run_venue`]
+        #[value(skip)]
+        Custom,
+    }
+
+    impl fmt::Display for Venue {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let status_str = match self {
+                #[cfg(feature = "coinbase")]
+                Venue::Coinbase => "coinbase",
+                #[cfg(feature = "bitfinex")]
+                Venue::Bitfinex => "bitfinex",
+                #[cfg(feature = "binance")]
+                Venue::BinanceFutures => "binance-futures",
+                #[cfg(feature = "okx")]
+                Venue::Okx => "okx",
+                #[cfg(feature = "bybit")]
+                Venue::Bybit => "bybit",
+                #[cfg(feature = "deribit")]
+                Venue::Deribit => "deribit",
+                #[cfg(feature = "mexc")]
+                Venue::Mexc => "mexc",
+                #[cfg(feature = "gate")]
+                Venue::Gate => "gate",
+                #[cfg(feature = "upbit")]
+                Venue::Upbit => "upbit",
+                #[cfg(feature = "bithumb")]
+                Venue::Bithumb => "bithumb",
+                #[cfg(feature = "polygon")]
+                Venue::Polygon => "polygon",
+                Venue::Auto => "auto",
+                Venue::Fix => "fix",
+                Venue::Multicast => "multicast",
+                #[cfg(feature = "grpc")]
+                Venue::Grpc => "grpc",
+                Venue::Custom => CUSTOM.get().copied().unwrap_or("custom"),
+            };
+            write!(f, "{}", status_str)
+        }
+    }
+
+    /// Name of the custom venue, set once as a process records a single venue.
+    static CUSTOM: OnceLock<&'static str> = OnceLock::new();
+
+    /// Registers the name of the custom venue, fails if another one is registered already.
+    pub fn register(name: &'static str) -> anyhow::Result<()> {
+        let registered = *CUSTOM.get_or_init(|| name);
+        if registered != name {
+            anyhow::bail!("cannot register venue {name}, {registered} is registered already");
+        }
+        Ok(())
+    }
+
+    impl Venue {
+        /// Symbols recorded when none are given on the command line.
+        pub fn default_symbols(&self) -> &'static [&'static str] {
+            match self {
+                #[cfg(feature = "bitfinex")]
+                Venue::Bitfinex => crate::bitfinex::SYMBOLS,
+                #[cfg(feature = "binance")]
+                Venue::BinanceFutures => crate::binance_futures::SYMBOLS,
+                #[cfg(feature = "okx")]
+                Venue::Okx => crate::okx::SYMBOLS,
+                #[cfg(feature = "bybit")]
+                Venue::Bybit => crate::bybit::SYMBOLS,
+                #[cfg(feature = "deribit")]
+                Venue::Deribit => crate::deribit::SYMBOLS,
+                #[cfg(feature = "mexc")]
+                Venue::Mexc => crate::mexc::SYMBOLS,
+                #[cfg(feature = "gate")]
+                Venue::Gate => crate::gate::SYMBOLS,
+                #[cfg(feature = "upbit")]
+                Venue::Upbit => crate::upbit::SYMBOLS,
+                #[cfg(feature = "bithumb")]
+                Venue::Bithumb => crate::bithumb::SYMBOLS,
+                #[cfg(feature = "polygon")]
+                Venue::Polygon => crate::polygon::SYMBOLS,
+                _ => &[],
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Parser)]
+    #[clap(author, version, about, long_about = None, subcommand_negates_reqs = true)]
+    pub struct Args {
+        #[clap(subcommand)]
+        pub command: Option<Command>,
+        /// YAML file overriding the symbols, rotation interval, sampling rules, output directory and table route,
+        /// reloaded on changes except for the output directory and the table route
+        #[clap(long)]
+        pub config: Option<std::path::PathBuf>,
+        /// folder of the recorded tables
+        #[clap(long, default_value = "output")]
+        pub directory: std::path::PathBuf,
+        /// venue to record, required unless running a subcommand
+        #[clap(short, long, value_enum, required = true)]
+        pub venue: Option<Venue>,
+        /// websocket url replacing the venue one, e.g. to record from a mock exchange in tests
+        #[clap(long, hide = true)]
+        pub ws_url: Option<String>,
+        /// extra header of the websocket upgrade requests as name=value (e.g. X-Api-Key=...), repeat for several
+        #[clap(long, value_parser = parse_header)]
+        pub ws_header: Vec<(String, String)>,
+        /// user agent of the websocket upgrade requests, none is sent by default
+        #[clap(long)]
+        pub user_agent: Option<String>,
+        /// REST url replacing the venue one, e.g. to fetch from a mock exchange in tests
+        #[clap(long, hide = true)]
+        pub rest_url: Option<String>,
+        /// comma separated list of symbols, if empty will use the venue defaults
+        #[clap(short, long, value_delimiter = ',')]
+        pub symbols: Vec<String>,
+        /// coinbase websocket feed: exchange or advanced-trade (requires --symbols)
+        #[cfg(feature = "coinbase")]
+        #[clap(long, value_enum, default_value = "exchange")]
+        pub coinbase_api: crate::coinbase::Api,
+        /// comma separated list of coinbase channels, of the exchange feed: rfq_matches (default), full (level 3,
+        /// requires --symbols) or status (product status transitions), of the advanced-trade feed: market_trades
+        /// (default) or level2
+        #[cfg(feature = "coinbase")]
+        #[clap(long, value_delimiter = ',', value_parser = ["rfq_matches", "full", "status", "market_trades", "level2"])]
+        pub coinbase_channels: Vec<String>,
+        /// name of the CDP API key (organizations/{org_id}/apiKeys/{key_id}) authenticating the advanced-trade
+        /// subscriptions
+        #[cfg(feature = "coinbase")]
+        #[clap(long, requires = "coinbase_key_file")]
+        pub coinbase_key_name: Option<String>,
+        /// PEM file of the EC private key of --coinbase-key-name
+        #[cfg(feature = "coinbase")]
+        #[clap(long, requires = "coinbase_key_name")]
+        pub coinbase_key_file: Option<std::path::PathBuf>,
+        /// API key of polygon, read from the POLYGON_API_KEY variable if not set
+        #[cfg(feature = "polygon")]
+        #[clap(long)]
+        pub polygon_api_key: Option<String>,
+        /// polygon stocks cluster: realtime or delayed (by 15 minutes)
+        #[cfg(feature = "polygon")]
+        #[clap(long, value_enum, default_value = "realtime")]
+        pub polygon_feed: crate::polygon::Feed,
+        /// comma separated list of okx option families (e.g. BTC-USD) recording the greeks of their options
+        #[cfg(feature = "okx")]
+        #[clap(long, value_delimiter = ',')]
+        pub okx_option_families: Vec<String>,
+        /// websocket url replacing the okx business endpoint of the candles, e.g. to record from a mock exchange in
+        /// tests, the candles are not recorded if --ws-url is set without it
+        #[cfg(feature = "okx")]
+        #[clap(long, hide = true)]
+        pub okx_business_url: Option<String>,
+        /// record the 100ms depth diffs of the binance-futures symbols, synchronized with REST snapshots
+        #[cfg(feature = "binance")]
+        #[clap(long)]
+        pub binance_depth: bool,
+        /// comma separated list of intervals (e.g. 1s,1m,5m) to aggregate trades into bars, disabled if empty
+        #[clap(short, long, value_delimiter = ',')]
+        pub bars: Vec<Interval>,
+        /// interval between polls of the venue REST endpoints
+        #[clap(short, long, default_value = "10s")]
+        pub poll_interval: Interval,
+        /// layout of the recorded tables: parquet, delta (Delta Lake log), iceberg (Iceberg snapshots), jsonl or csv
+        #[clap(long, default_value = "parquet")]
+        pub format: Format,
+        /// with the delta format, write a checkpoint of the transaction log of every table every this many commits
+        /// (e.g. 10), 0 never checkpoints
+        #[clap(long, default_value = "0")]
+        pub delta_checkpoint_interval: u64,
+        /// template of the table names, from the {venue}, {channel} and {date} placeholders (e.g.
+        /// {venue}_{channel}_{date}), each / separated segment being a folder
+        #[clap(long, default_value_t)]
+        pub table_route: TableRoute,
+        /// tables of the records of a day arriving after its --lateness-window, with {date} in the table route:
+        /// reopen (the table of their date) or late-table (the {channel}_late table of the current date)
+        #[clap(long, value_enum, default_value = "reopen")]
+        pub late_data: LatePolicy,
+        /// time after midnight the tables of the previous day stay open for late records, then are completed
+        #[clap(long, default_value = "5m")]
+        pub lateness_window: Interval,
+        /// comma separated list of table=format (e.g. trades=jsonl) overriding the format of specific tables
+        #[clap(long, value_delimiter = ',', value_parser = parse_table_format)]
+        pub table_formats: Vec<(String, Format)>,
+        /// comma separated list of table=column (e.g. trades=instrument,level2=tob_*) persisting only these columns of
+        /// specific tables, a * matching any characters
+        #[clap(long, value_delimiter = ',', value_parser = parse_table_column)]
+        pub include_columns: Vec<(String, String)>,
+        /// comma separated list of table=column (e.g. events=detail) dropping these columns from specific tables, a *
+        /// matching any characters
+        #[clap(long, value_delimiter = ',', value_parser = parse_table_column)]
+        pub exclude_columns: Vec<(String, String)>,
+        /// comma separated list of table=N (every Nth record) or table=N/s (N records per second) sampling the records
+        /// of each symbol persisted to specific tables (e.g. ticker=10,funding=1/s), the other tables are persisted in full
+        #[clap(long, value_delimiter = ',', value_parser = parse_table_sampling)]
+        pub sample: Vec<(String, Sampling)>,
+        /// comma separated list of tables (e.g. ticker) persisting only the latest record of each symbol per
+        /// --conflate-interval
+        #[clap(long, value_delimiter = ',')]
+        pub conflate: Vec<String>,
+        /// comma separated list of tables (e.g. ticker), or of venue/table (e.g. bitfinex/ticker), deriving the mid,
+        /// spread, microprice and imbalance of their quotes to the derived table
+        #[clap(long, value_delimiter = ',')]
+        pub derive: Vec<String>,
+        /// interval of the conflated tables
+        #[clap(long, default_value = "100ms")]
+        pub conflate_interval: Interval,
+        /// skipped messages of each reason (e.g. heartbeats of unsubscribed channels) logged at info before sampling
+        #[clap(long, default_value_t = crate::skips::FIRST)]
+        pub skip_log_first: u64,
+        /// past the first ones, only every Nth skipped message of each reason is logged at info, none if 0, every
+        /// one being logged at debug
+        #[clap(long, default_value_t = crate::skips::EVERY)]
+        pub skip_log_every: u64,
+        /// delimiter of the csv fields, must be an ascii character
+        #[clap(long, default_value = ",")]
+        pub csv_delimiter: char,
+        /// quoting of the csv fields: necessary, always, nonnumeric or never
+        #[clap(long, default_value = "necessary")]
+        pub csv_quoting: Quoting,
+        /// gzip compress the csv tables
+        #[clap(long)]
+        pub csv_gzip: bool,
+        /// zstd compress the jsonl and csv tables with a dictionary trained from the first flush of each table,
+        /// stored as _dictionary.zstd in the table directory
+        #[clap(long)]
+        pub zstd_dictionary: bool,
+        /// persist the null counts, distinct counts and bounds of the columns of every flush to the _stats table
+        #[clap(long)]
+        pub column_stats: bool,
+        /// keep the time range of this timestamp column (e.g. time) in every file of the tables in their _index.json,
+        /// so the readers of a time range skip the other files without opening them
+        #[clap(long)]
+        pub time_index: Option<String>,
+        /// comma separated list of table=check (e.g. trades=price>0,ticker=bid<=ask,*=time~5s) validating the records
+        /// before persisting them, a check compares a column to a number or another column (<, <=, >, >=, =, !=) or
+        /// bounds the distance of a timestamp column to the local clock (~), * applying to every table
+        #[clap(long, value_delimiter = ',')]
+        pub validate: Vec<Rule>,
+        /// persist the records violating a --validate rule to the _violations table
+        #[clap(long)]
+        pub persist_violations: bool,
+        /// drop the records violating a --validate rule instead of persisting them
+        #[clap(long)]
+        pub reject_violations: bool,
+        /// maximum number of rows of the parquet row groups, 0 writes a single row group per flush
+        #[clap(long, default_value = "0")]
+        pub rows_per_row_group: usize,
+        /// keep the parquet files open across flushes and close them once they hold this many rows, 0 writes a file
+        /// per flush
+        #[clap(long, default_value = "0")]
+        pub rows_per_file: usize,
+        /// interval (e.g. 1h) at whose boundaries (e.g. on the hour) every table is closed, completing the files kept
+        /// open by --rows-per-file
+        #[clap(long)]
+        pub rotation_interval: Option<Interval>,
+        /// files already in the table directories: append (keeps them), fail-if-exists or purge (deletes them)
+        #[clap(long, default_value = "append")]
+        pub overwrite: OverwriteMode,
+        /// naming of the table files: index (sequential), timestamp (creation time and process id) or uuid
+        #[clap(long, default_value = "index")]
+        pub file_naming: FileNaming,
+        /// comma separated list of severity=action (e.g. warning=continue,error=reconnect) setting how the websocket
+        /// reacts to the venue errors: continue, resubscribe, reconnect or abort
+        #[clap(long, value_delimiter = ',', value_parser = parse_error_action)]
+        pub on_error: Vec<(Severity, ErrorAction)>,
+        /// how long to wait for a table directory locked by another recorder, fails at once if not set
+        #[clap(long)]
+        pub lock_timeout: Option<Interval>,
+        /// retry the flushes failing on io errors (e.g. a network filesystem blip) this many times, the rows being
+        /// kept buffered in between, 0 stops the recorder on the first failed flush
+        #[clap(long, default_value = "0")]
+        pub flush_retries: u32,
+        /// wait before the first retry of a failed flush, doubled for every next retry up to 5s
+        #[clap(long, default_value = "100ms")]
+        pub flush_backoff: Interval,
+        /// directory the buffered rows of a table are dumped to as JSON lines once its flush retries are exhausted,
+        /// before the recorder stops
+        #[clap(long)]
+        pub recovery_directory: Option<std::path::PathBuf>,
+        /// local directory the rows of a failed flush are spilled to (once its retries are exhausted) while the
+        /// output is unavailable, written to their table once it recovers, including by the next run
+        #[clap(long)]
+        pub spill_directory: Option<std::path::PathBuf>,
+        /// write a .sha256 sidecar next to every completed file, checked by the verify subcommand
+        #[clap(long)]
+        pub checksum: bool,
+        /// write a .success marker next to every file once completed, the query, flight and sync subcommands and the
+        /// replay skipping the files without one, so they never read a file still written
+        #[clap(long)]
+        pub finalize_markers: bool,
+        /// maximum number of rows buffered by all the tables, the largest table is flushed when exceeded, 0 for no limit
+        #[clap(long, default_value = "0")]
+        pub max_buffered_rows: usize,
+        /// host:port of an NTP server (e.g. pool.ntp.org:123) to measure the offset of the local clock against, the
+        /// measures are persisted to the _clock table
+        #[clap(long)]
+        pub ntp_server: Option<String>,
+        /// interval between the measures of the clock offset
+        #[clap(long, default_value = "1m")]
+        pub ntp_interval: Interval,
+        /// adjust the local timestamps (e.g. the receive time of the tickers) with the last measured clock offset
+        #[clap(long, requires = "ntp_server")]
+        pub ntp_adjust: bool,
+        /// clock the local time is read from, the system clock unless replaced by the application embedding the
+        /// recorder (e.g. a `ManualClock` in tests)
+        #[clap(skip = crate::clock::system())]
+        pub clock: SharedClock,
+        /// time without any venue record after which the feed is stale, the systemd watchdog is no longer pinged
+        /// so the service manager restarts the recorder
+        #[clap(long, default_value = "1m")]
+        pub stale_after: Interval,
+        /// run as a Windows service, reporting the state of the recorder to the service control manager
+        #[cfg(windows)]
+        #[clap(long)]
+        pub windows_service: bool,
+        /// acknowledge the records of the sequenced sources (fix) once persisted and save their watermark to
+        /// _watermark.json, a restart resumes the session after it so the source resends the gap
+        #[clap(long)]
+        pub watermark: bool,
+        /// interval at which the tables are flushed and the watermark saved
+        #[clap(long, default_value = "10s")]
+        pub watermark_interval: Interval,
+        /// sequence numbers the source reads ahead of the persister acknowledgments before waiting, 0 for no limit
+        #[clap(long, default_value = "1000")]
+        pub max_unacked: u64,
+        /// interval between pulls of the reference data of the instruments (tick and lot sizes, status, expiry)
+        #[clap(long, default_value = "1h")]
+        pub reference_interval: Interval,
+        /// do not pull the reference data of the instruments
+        #[clap(long)]
+        pub no_reference: bool,
+        /// YAML file mapping the symbols of each venue to canonical instruments, extending the built-in mappings
+        #[clap(long)]
+        pub symbology: Option<std::path::PathBuf>,
+        /// path of a unix socket taking subscription commands at runtime, sent with the ctl subcommand
+        #[clap(long)]
+        pub control_socket: Option<std::path::PathBuf>,
+        /// address the admin HTTP API listens on (e.g. 127.0.0.1:8080), to flush or rotate the tables, change the
+        /// subscriptions and pause recording
+        #[cfg(feature = "admin")]
+        #[clap(long)]
+        pub admin_address: Option<std::net::SocketAddr>,
+        /// url of a redis:// or nats:// server to mirror the parsed records to, as JSON on `{venue}.{channel}.{symbol}`
+        #[clap(long)]
+        pub publish: Option<String>,
+        /// jsonl://stdout or jsonl:///path/to/unix.sock to stream the records as JSON lines, logs go to stderr
+        #[clap(long)]
+        pub stream: Option<String>,
+        /// url of an object store (e.g. s3://bucket/prefix) the venue directory is uploaded to once recording ends,
+        /// skipping the files already uploaded, configured by the AWS_* environment variables
+        #[cfg(feature = "s3")]
+        #[clap(long)]
+        pub upload: Option<String>,
+        /// Glue database the tables are registered to as their first files are written, with their date partitions
+        #[cfg(feature = "glue")]
+        #[clap(long, requires = "glue_location")]
+        pub glue_database: Option<String>,
+        /// S3 location of the output directory the tables are registered at (e.g. s3://bucket/prefix, as uploaded
+        /// with --upload)
+        #[cfg(feature = "glue")]
+        #[clap(long)]
+        pub glue_location: Option<String>,
+        /// endpoint of the Glue API, the regional endpoint of AWS_REGION by default
+        #[cfg(feature = "glue")]
+        #[clap(long)]
+        pub glue_endpoint: Option<String>,
+        /// endpoint of a ZeroMQ PUB socket to bind (e.g. tcp://0.0.0.0:5556), publishing the records by topic
+        #[cfg(feature = "zmq")]
+        #[clap(long)]
+        pub zmq_bind: Option<String>,
+        /// encoding of the ZeroMQ payload frames
+        #[cfg(feature = "zmq")]
+        #[clap(long, value_enum, default_value = "json")]
+        pub zmq_encoding: crate::publish::Encoding,
+        /// websocket url of the feed, required by the auto venue
+        #[clap(long)]
+        pub auto_url: Option<String>,
+        /// JSON messages sent on connection by the auto venue, {symbols} being replaced by the JSON array of the
+        /// symbols
+        #[clap(long)]
+        pub auto_subscribe: Vec<String>,
+        /// JSON pointer to the channel of the messages, naming their table
+        #[clap(long, default_value = "/channel")]
+        pub auto_channel: String,
+        /// JSON pointer to the payload of the messages (e.g. /data), the whole message if not set
+        #[clap(long)]
+        pub auto_data: Option<String>,
+        /// number of messages of each channel the schema of its table is inferred from
+        #[clap(long, default_value = "100")]
+        pub auto_infer: usize,
+        /// host:port of the FIX acceptor, required by the fix venue
+        #[clap(long)]
+        pub fix_address: Option<String>,
+        #[clap(long, default_value = "DIXIT")]
+        pub fix_sender_comp_id: String,
+        #[clap(long, default_value = "")]
+        pub fix_target_comp_id: String,
+        #[clap(long, default_value = "30s")]
+        pub fix_heartbeat: Interval,
+        /// group:port to join, required by the multicast venue
+        #[clap(long)]
+        pub multicast_group: Option<SocketAddrV4>,
+        /// address of the local interface used to join the multicast group
+        #[clap(long, default_value = "0.0.0.0")]
+        pub multicast_interface: Ipv4Addr,
+        /// address the gRPC ingest endpoint listens on, used by the grpc venue
+        #[cfg(feature = "grpc")]
+        #[clap(long, default_value = "0.0.0.0:50051")]
+        pub grpc_address: std::net::SocketAddr,
+    }
+
+    #[derive(Debug, Clone, Subcommand)]
+    pub enum Command {
+        /// serve the recorded tables over Arrow Flight
+        #[cfg(feature = "flight")]
+        Flight {
+            #[clap(long, default_value = "0.0.0.0:50052")]
+            address: std::net::SocketAddr,
+            /// folder holding the recorded tables
+            #[clap(long, default_value = "output")]
+            directory: std::path::PathBuf,
+        },
+        /// run SQL over the recorded tables, registered as `<venue>_<table>` views
+        #[cfg(feature = "query")]
+        Query {
+            sql: String,
+            /// folder holding the recorded tables
+            #[clap(long, default_value = "output")]
+            directory: std::path::PathBuf,
+        },
+        /// check the checksum sidecars and Parquet footers of the recorded tables, reporting corrupted files
+        Verify {
+            /// folder holding the recorded tables
+            #[clap(long, default_value = "output")]
+            directory: std::path::PathBuf,
+        },
+        /// upload the new and changed files of the recorded tables to an object store, reporting the objects without
+        /// a local file
+        #[cfg(feature = "s3")]
+        Sync {
+            /// url of the object store (e.g. s3://bucket/prefix), configured by the AWS_* environment variables
+            url: String,
+            /// folder holding the recorded tables
+            #[clap(long, default_value = "output")]
+            directory: std::path::PathBuf,
+        },
+        /// send a command to the control socket of a recorder: subscribe <venue> <symbol>...,
+        /// unsubscribe <venue> <symbol>... or list <venue>
+        Ctl {
+            /// control socket of the recorder, as set by --control-socket
+            #[clap(long)]
+            socket: std::path::PathBuf,
+            #[clap(required = true, trailing_var_arg = true)]
+            command: Vec<String>,
+        },
+        /// push synthetic trades and book updates through the persister at a fixed rate, reporting the sustained
+        /// throughput, the append latencies and the flush stalls
+        BenchFeed {
+            /// messages generated per second
+            #[clap(long, default_value = "100000")]
+            rate: u64,
+            #[clap(long, default_value = "10s")]
+            duration: Interval,
+            /// number of symbols the messages are spread over
+            #[clap(long, default_value = "10")]
+            symbols: usize,
+            /// share of book updates among the messages, in percent
+            #[clap(long, default_value = "80", value_parser = clap::value_parser!(u8).range(0..=100))]
+            book_percent: u8,
+            /// appends blocked for longer than this are reported as flush stalls
+            #[clap(long, default_value = "10ms")]
+            stall_threshold: Interval,
+            /// folder of the bench tables, purged on startup
+            #[clap(long, default_value = "bench")]
+            directory: std::path::PathBuf,
+            #[clap(long, default_value = "parquet")]
+            format: Format,
+        },
+    }
+
+    impl Command {
+        /// Runs the subcommand, the `bench-feed` one dating its trades by `clock`.
+        pub async fn run(self, clock: SharedClock) -> anyhow::Result<()> {
+            match self {
+                #[cfg(feature = "flight")]
+                Command::Flight { address, directory } => crate::flight::run(address, directory).await,
+                #[cfg(feature = "query")]
+                Command::Query { sql, directory } => crate::query::run(&directory, &sql),
+                Command::Verify { directory } => crate::verify::run(&directory),
+                #[cfg(feature = "s3")]
+                Command::Sync { url, directory } => crate::upload::run(&url, &directory).await,
+                Command::Ctl { socket, command } => crate::control::send(&socket, &command).await,
+                Command::BenchFeed {
+                    rate,
+                    duration,
+                    symbols,
+                    book_percent,
+                    stall_threshold,
+                    directory,
+                    format,
+                } => {
+                    crate::bench::run(crate::bench::BenchConfig {
+                        rate,
+                        duration: duration.duration(),
+                        symbols,
+                        book_percent,
+                        stall_threshold: stall_threshold.duration(),
+                        directory,
+                        format,
+                        clock,
+                    })
+                    .await
+                }
+            }
+        }
+    }
+
+    impl Args {
+        pub fn venue(&self) -> Venue {
+            self.venue.expect("venue is required without a subcommand")
+        }
+
+        /// Persistence settings selected on the command line, the output directory is made absolute by the persister.
+        pub fn persist_config(&self, venue: Venue) -> PersistConfig {
+            PersistConfig {
+                format: self.format,
+                table_formats: self
+                    .table_formats
+                    .iter()
+                    .map(|(table, format)| (self.table_route.pattern(&venue.to_string(), table), *format))
+                    .collect(),
+                csv: CsvOptions {
+                    delimiter: self.csv_delimiter,
+                    quoting: self.csv_quoting,
+                    gzip: self.csv_gzip,
+                },
+                rows_per_row_group: self.rows_per_row_group,
+                rows_per_file: self.rows_per_file,
+                delta_checkpoint_interval: self.delta_checkpoint_interval,
+                overwrite: self.overwrite,
+                checksum: self.checksum,
+                finalize_markers: self.finalize_markers,
+                file_naming: self.file_naming,
+                directory: self.directory.clone(),
+                lock_timeout: self.lock_timeout.as_ref().map(Interval::duration),
+                zstd_dictionary: self.zstd_dictionary,
+                column_stats: self.column_stats,
+                time_index: self.time_index.clone(),
+                flush_retry: RetryPolicy {
+                    recovery_directory: self.recovery_directory.clone(),
+                    ..RetryPolicy::new(self.flush_retries, self.flush_backoff.duration())
+                },
+                spill_directory: self.spill_directory.clone(),
+                include_columns: self.table_columns(venue, &self.include_columns),
+                exclude_columns: self.table_columns(venue, &self.exclude_columns),
+                ..Default::default()
+            }
+        }
+
+        /// Catalog of the tables, if --glue-database is set, the credentials and region being read from the AWS_*
+        /// environment variables.
+        #[cfg(feature = "glue")]
+        pub fn glue_config(&self) -> anyhow::Result<Option<crate::catalog::GlueConfig>> {
+            let Some(database) = self.glue_database.clone() else {
+                return Ok(None);
+            };
+            let region = std::env::var("AWS_REGION")
+                .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+                .map_err(|_| anyhow::anyhow!("AWS_REGION is required by the glue catalog"))?;
+            Ok(Some(crate::catalog::GlueConfig {
+                database,
+                location: self.glue_location.clone().unwrap_or_default(),
+                region,
+                endpoint: self.glue_endpoint.clone(),
+                credentials: crate::catalog::Credentials::from_env()?,
+                dated: self.table_route.is_dated(),
+                csv: CsvOptions {
+                    delimiter: self.csv_delimiter,
+                    quoting: self.csv_quoting,
+                    gzip: self.csv_gzip,
+                },
+                zstd_dictionary: self.zstd_dictionary,
+                clock: self.clock.clone(),
+            }))
+        }
+
+        /// Validator of the records against the --validate rules.
+        pub fn validator(&self) -> Validator {
+            let policy = ViolationPolicy {
+                persist: self.persist_violations,
+                reject: self.reject_violations,
+            };
+            Validator::new(self.validate.clone(), policy)
+        }
+
+        /// Columns of the tables of the venue by table pattern, from the table=column pairs of the command line.
+        fn table_columns(&self, venue: Venue, columns: &[(String, String)]) -> HashMap<String, Vec<String>> {
+            let mut tables = HashMap::<String, Vec<String>>::new();
+            for (table, column) in columns {
+                tables
+                    .entry(self.table_route.pattern(&venue.to_string(), table))
+                    .or_default()
+                    .push(column.clone());
+            }
+            tables
+        }
+
+        /// Reaction of the websocket to the venue errors, the default policy overridden by `--on-error`.
+        pub fn error_policy(&self) -> ErrorPolicy {
+            let mut policy = ErrorPolicy::default();
+            for (severity, action) in self.on_error.iter() {
+                policy.set(*severity, *action);
+            }
+            policy
+        }
+
+        /// Names of the tables the records are persisted to, with the lateness policy of the dated tables.
+        pub fn router(&self) -> Router {
+            let window = chrono::TimeDelta::from_std(self.lateness_window.duration()).unwrap_or_default();
+            Router::new(self.table_route.clone(), self.late_data, window)
+        }
+
+        pub fn service_options(&self) -> ServiceOptions {
+            ServiceOptions {
+                rotation_interval: self.rotation_interval.as_ref().map(Interval::duration),
+                max_buffered_rows: self.max_buffered_rows,
+                // the created tables are registered in the catalog
+                #[cfg(feature = "glue")]
+                report_tables: self.glue_database.is_some(),
+                #[cfg(not(feature = "glue"))]
+                report_tables: false,
+            }
+        }
+
+        pub fn rest_url(&self, url: &str) -> String {
+            self.rest_url.clone().unwrap_or_else(|| url.to_owned())
+        }
+
+        /// Url of the okx candles, none if only the public endpoint is replaced (e.g. by a mock exchange).
+        #[cfg(feature = "okx")]
+        pub fn okx_candles_url(&self) -> Option<String> {
+            match (&self.okx_business_url, &self.ws_url) {
+                (Some(url), _) => Some(url.clone()),
+                (None, Some(_)) => None,
+                (None, None) => Some(crate::okx::BUSINESS_URL.to_owned()),
+            }
+        }
+
+        /// Urls listing the instruments of the venue and their parser, for the venues with a REST API listing them.
+        pub fn reference(&self) -> Option<(Vec<String>, crate::reference::Parse)> {
+            #[cfg(feature = "binance")]
+            use crate::binance_futures;
+            #[cfg(feature = "bybit")]
+            use crate::bybit;
+            #[cfg(feature = "coinbase")]
+            use crate::coinbase;
+            #[cfg(feature = "deribit")]
+            use crate::deribit;
+            #[cfg(feature = "okx")]
+            use crate::okx;
+            match self.venue() {
+                #[cfg(feature = "coinbase")]
+                Venue::Coinbase => Some((
+                    coinbase::reference_urls(&self.rest_url(coinbase::REST_URL)),
+                    coinbase::instruments,
+                )),
+                #[cfg(feature = "binance")]
+                Venue::BinanceFutures => Some((
+                    binance_futures::reference_urls(&self.rest_url(binance_futures::REST_URL)),
+                    binance_futures::instruments,
+                )),
+                #[cfg(feature = "okx")]
+                Venue::Okx => Some((okx::reference_urls(&self.rest_url(okx::REST_URL)), okx::instruments)),
+                #[cfg(feature = "bybit")]
+                Venue::Bybit => Some((
+                    bybit::reference_urls(&self.rest_url(bybit::REST_URL)),
+                    bybit::instruments,
+                )),
+                #[cfg(feature = "deribit")]
+                Venue::Deribit => {
+                    let symbols = self.symbols(deribit::SYMBOLS);
+                    Some((
+                        deribit::reference_urls(&self.rest_url(deribit::REST_URL), &symbols),
+                        deribit::instruments,
+                    ))
+                }
+                _ => None,
+            }
+        }
+
+        pub fn symbols(&self, defaults: &[&str]) -> Vec<String> {
+            if self.symbols.is_empty() {
+                defaults.iter().map(|s| s.to_string()).collect()
+            } else {
+                self.symbols.clone()
+            }
+        }
+
+        /// Extra headers of the websocket upgrade requests, the user agent first.
+        pub fn ws_headers(&self) -> Vec<(String, String)> {
+            let user_agent = self
+                .user_agent
+                .iter()
+                .map(|user_agent| ("User-Agent".to_owned(), user_agent.clone()));
+            user_agent.chain(self.ws_header.iter().cloned()).collect()
+        }
+
+        /// Channels of the coinbase feed, its default one if none is given.
+        #[cfg(feature = "coinbase")]
+        pub fn coinbase_channels(&self) -> anyhow::Result<Vec<String>> {
+            let channels = self.coinbase_api.channels();
+            if self.coinbase_channels.is_empty() {
+                return Ok(vec![channels[0].to_owned()]);
+            }
+            if let Some(channel) = self
+                .coinbase_channels
+                .iter()
+                .find(|channel| !channels.contains(&channel.as_str()))
+            {
+                let api = self.coinbase_api.to_possible_value().expect("api not skipped");
+                anyhow::bail!(
+                    "coinbase channel {channel} is not a channel of the {} api ({})",
+                    api.get_name(),
+                    channels.join(", ")
+                );
+            }
+            Ok(self.coinbase_channels.clone())
+        }
+
+        #[cfg(feature = "coinbase")]
+        pub fn coinbase_advanced_config(&self) -> anyhow::Result<crate::coinbase_advanced::Config> {
+            use crate::coinbase_advanced::{Config, Credentials};
+            if self.symbols.is_empty() {
+                anyhow::bail!("--symbols is required by the coinbase advanced-trade api");
+            }
+            let credentials = match (&self.coinbase_key_name, &self.coinbase_key_file) {
+                (Some(name), Some(file)) => Some(Credentials::load(name, file)?),
+                _ => None,
+            };
+            Ok(Config {
+                channels: self.coinbase_channels()?,
+                credentials,
+                clock: self.clock.clone(),
+            })
+        }
+
+        #[cfg(feature = "polygon")]
+        pub fn polygon_api_key(&self) -> anyhow::Result<String> {
+            use crate::polygon::API_KEY_VARIABLE;
+            match &self.polygon_api_key {
+                Some(api_key) => Ok(api_key.clone()),
+                None => std::env::var(API_KEY_VARIABLE).map_err(|_| {
+                    anyhow::anyhow!("--polygon-api-key or {API_KEY_VARIABLE} is required by the polygon venue")
+                }),
+            }
+        }
+
+        pub fn fix_config(&self) -> anyhow::Result<FixConfig> {
+            let address = self
+                .fix_address
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("--fix-address is required by the fix venue"))?;
+            Ok(FixConfig {
+                address,
+                sender_comp_id: self.fix_sender_comp_id.clone(),
+                target_comp_id: self.fix_target_comp_id.clone(),
+                heartbeat: self.fix_heartbeat.duration(),
+                resume: if self.watermark {
+                    Watermark::load(&self.directory.join(Venue::Fix.to_string()))?
+                } else {
+                    None
+                },
+                clock: self.clock.clone(),
+            })
+        }
+
+        pub fn auto_config(&self) -> anyhow::Result<AutoConfig> {
+            let url = self
+                .auto_url
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("--auto-url is required by the auto venue"))?;
+            Ok(AutoConfig {
+                url,
+                subscribe: self.auto_subscribe.clone(),
+                channel: self.auto_channel.clone(),
+                data: self.auto_data.clone(),
+                infer: self.auto_infer.max(1),
+            })
+        }
+
+        pub fn multicast_config(&self) -> anyhow::Result<MulticastConfig> {
+            let group = self
+                .multicast_group
+                .ok_or_else(|| anyhow::anyhow!("--multicast-group is required by the multicast venue"))?;
+            Ok(MulticastConfig {
+                group,
+                interface: self.multicast_interface,
+                clock: self.clock.clone(),
+            })
+        }
+    }
+
+    fn parse_table_sampling(value: &str) -> Result<(String, Sampling), String> {
+        let (table, sampling) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected table=sampling, got {value}"))?;
+        Ok((table.to_owned(), sampling.parse()?))
+    }
+
+    fn parse_error_action(value: &str) -> Result<(Severity, ErrorAction), String> {
+        let (severity, action) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected severity=action, got {value}"))?;
+        Ok((severity.parse()?, action.parse()?))
+    }
+
+    fn parse_table_format(value: &str) -> Result<(String, Format), String> {
+        let (table, format) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected table=format, got {value}"))?;
+        Ok((table.to_owned(), format.parse()?))
+    }
+
+    fn parse_header(value: &str) -> Result<(String, String), String> {
+        use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+        let (name, header) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected name=value, got {value}"))?;
+        HeaderName::from_bytes(name.as_bytes()).map_err(|e| format!("invalid header name {name}: {e}"))?;
+        HeaderValue::from_str(header).map_err(|e| format!("invalid value of header {name}: {e}"))?;
+        Ok((name.to_owned(), header.to_owned()))
+    }
+
+    fn parse_table_column(value: &str) -> Result<(String, String), String> {
+        match value.split_once('=') {
+            Some((table, column)) if !table.is_empty() && !column.is_empty() => {
+                Ok((table.to_owned(), column.to_owned()))
+            }
+            _ => Err(format!("expected table=column, got {value}")),
+        }
+    }
+
+    pub fn init() -> Args {
+        tracing_subscriber::registry()
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_line_number(true)
+                    .with_writer(std::io::stderr),
+            )
+            .with(EnvFilter::from_default_env())
+            .init();
+
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+
+        Args::parse()
+    }
+}
+
+pub mod model {
+    //! Defines data structures for RFQ records and venue-specific data.
+    //!
+    //! ## Features
+    //! - `Record`: Represents a single RFQ record, which could be valid data, skipped messages, or errors.
+    //! - `SkipReason`: Why a message was skipped, counted and sampled per reason by the `skips` module.
+    //! - `Severity`: How much of the capture a venue error affects, from a single request to the whole session.
+    //! - `VenueData`: Wraps venue-specific data types and maps each of them to its table.
+    //! - `FundingRate`, `OpenInterest`, `IndexPrice`, `Liquidation`: Normalized derivative data shared across venues.
+    //! - `Trade`: Normalized trade for venues and sources without a venue-specific trade type (e.g. MEXC, gRPC ingest).
+    //! - `ClockOffset`: Offset of the local clock measured against an NTP server.
+    //! - `auto::Row`: Row of a table inferred by the `auto` venue, whose table is named after its channel.
+
+    use std::str::FromStr;
+
+    use anyhow::Result;
+    use chrono::{DateTime, Utc};
+    use dixit_persist::{jsonl::to_json, writer::TableWriter};
+    use dixit_persist_macros::Persist;
+    use rust_decimal::Decimal;
+
+    #[cfg(feature = "binance")]
+    use crate::binance_futures;
+    #[cfg(feature = "bitfinex")]
+    use crate::bitfinex;
+    #[cfg(feature = "coinbase")]
+    use crate::coinbase;
+    #[cfg(feature = "coinbase")]
+    use crate::coinbase_advanced;
+    #[cfg(feature = "okx")]
+    use crate::okx;
+    #[cfg(feature = "polygon")]
+    use crate::polygon;
+    use crate::{
+        auto,
+        bars::Bar,
+        bench,
+        candles::Candle,
+        clock::ClockOffset,
+        derived::{Derived, Quote},
+        events::Event,
+        quality,
+        reference::Instrument,
+        source::fix::MarketDataEntry,
+        source::multicast::ItchTrade,
+        symbology::{Mapping, Symbology},
+        watermark::Watermark,
+    };
+
+    #[derive(Debug)]
+    pub enum VenueData {
+        #[cfg(feature = "coinbase")]
+        CoinbaseRfqMatch(coinbase::RfqMatch),
+        #[cfg(feature = "coinbase")]
+        CoinbaseFull(coinbase::Full),
+        #[cfg(feature = "coinbase")]
+        CoinbaseStatus(coinbase::ProductStatus),
+        #[cfg(feature = "coinbase")]
+        CoinbaseMarketTrade(coinbase_advanced::MarketTrade),
+        #[cfg(feature = "coinbase")]
+        CoinbaseLevel2(coinbase_advanced::Level2),
+        #[cfg(feature = "okx")]
+        OkxOptionSummary(okx::OptionSummary),
+        #[cfg(feature = "binance")]
+        DepthDiff(binance_futures::DepthLevel),
+        #[cfg(feature = "binance")]
+        DepthSnapshot(binance_futures::DepthLevel),
+        #[cfg(feature = "bitfinex")]
+        BitfinexTrade(bitfinex::Trade),
+        #[cfg(feature = "bitfinex")]
+        BitfinexTicker(bitfinex::Ticker),
+        #[cfg(feature = "polygon")]
+        StockTrade(polygon::StockTrade),
+        #[cfg(feature = "polygon")]
+        StockQuote(polygon::StockQuote),
+        Funding(FundingRate),
+        OpenInterest(OpenInterest),
+        Index(IndexPrice),
+        Liquidation(Liquidation),
+        Candle(Candle),
+        Bar(Bar),
+        FixMarketData(MarketDataEntry),
+        ItchTrade(ItchTrade),
+        Clock(ClockOffset),
+        Event(Event),
+        Symbology(Mapping),
+        Reference(Instrument),
+        Derived(Derived),
+        Auto(auto::Row),
+        Trade(Trade),
+        BenchBook(bench::BookUpdate),
+        ColumnStat(quality::ColumnStat),
+        Violation(quality::Violation),
+    }
+
+    impl VenueData {
+        pub fn table(&self) -> &'static str {
+            match self {
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseRfqMatch(_) => "rfq_match",
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseFull(data) => data.table(),
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseStatus(_) => "status",
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseMarketTrade(_) => "market_trades",
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseLevel2(_) => "level2",
+                #[cfg(feature = "okx")]
+                VenueData::OkxOptionSummary(_) => "option_greeks",
+                #[cfg(feature = "binance")]
+                VenueData::DepthDiff(_) => "depth_diffs",
+                #[cfg(feature = "binance")]
+                VenueData::DepthSnapshot(_) => "depth_snapshots",
+                #[cfg(feature = "bitfinex")]
+                VenueData::BitfinexTrade(_) => "trades",
+                #[cfg(feature = "bitfinex")]
+                VenueData::BitfinexTicker(_) => "ticker",
+                #[cfg(feature = "polygon")]
+                VenueData::StockTrade(_) => "stock_trades",
+                #[cfg(feature = "polygon")]
+                VenueData::StockQuote(_) => "stock_quotes",
+                VenueData::Funding(_) => "funding",
+                VenueData::OpenInterest(_) => "open_interest",
+                VenueData::Index(_) => "index",
+                VenueData::Liquidation(_) => "liquidations",
+                VenueData::Candle(_) => "candles",
+                VenueData::Bar(_) => "bars",
+                VenueData::FixMarketData(_) => "market_data",
+                VenueData::ItchTrade(_) => "trades",
+                VenueData::Clock(_) => "_clock",
+                VenueData::Event(_) => "_events",
+                VenueData::Symbology(_) => "_symbology",
+                VenueData::Reference(_) => "reference",
+                VenueData::Derived(_) => "derived",
+                VenueData::Auto(data) => data.table,
+                VenueData::Trade(_) => "trades",
+                VenueData::BenchBook(_) => "book",
+                VenueData::ColumnStat(_) => "_stats",
+                VenueData::Violation(_) => "_violations",
+            }
+        }
+
+        pub fn persist(&self, writer: &mut TableWriter) -> Result<()> {
+            match self {
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseRfqMatch(data) => writer.begin()?.record(data)?.end()?,
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseFull(data) => data.persist(writer)?,
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseStatus(data) => writer.begin()?.record(data)?.end()?,
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseMarketTrade(data) => writer.begin()?.record(data)?.end()?,
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseLevel2(data) => writer.begin()?.record(data)?.end()?,
+                #[cfg(feature = "okx")]
+                VenueData::OkxOptionSummary(data) => writer.begin()?.record(data)?.end()?,
+                #[cfg(feature = "binance")]
+                VenueData::DepthDiff(data) => writer.begin()?.record(data)?.end()?,
+                #[cfg(feature = "binance")]
+                VenueData::DepthSnapshot(data) => writer.begin()?.record(data)?.end()?,
+                #[cfg(feature = "bitfinex")]
+                VenueData::BitfinexTrade(data) => writer.begin()?.record(data)?.end()?,
+                #[cfg(feature = "bitfinex")]
+                VenueData::BitfinexTicker(data) => writer.begin()?.record(data)?.end()?,
+                #[cfg(feature = "polygon")]
+                VenueData::StockTrade(data) => writer.begin()?.record(data)?.end()?,
+                #[cfg(feature = "polygon")]
+                VenueData::StockQuote(data) => writer.begin()?.record(data)?.end()?,
+                VenueData::Funding(data) => writer.begin()?.record(data)?.end()?,
+                VenueData::OpenInterest(data) => writer.begin()?.record(data)?.end()?,
+                VenueData::Index(data) => writer.begin()?.record(data)?.end()?,
+                VenueData::Liquidation(data) => writer.begin()?.record(data)?.end()?,
+                VenueData::Candle(data) => writer.begin()?.record(data)?.end()?,
+                VenueData::Bar(data) => writer.begin()?.record(data)?.end()?,
+                VenueData::FixMarketData(data) => writer.begin()?.record(data)?.end()?,
+                VenueData::ItchTrade(data) => writer.begin()?.record(data)?.end()?,
+                VenueData::Clock(data) => writer.begin()?.record(data)?.end()?,
+                VenueData::Event(data) => writer.begin()?.record(data)?.end()?,
+                VenueData::Symbology(data) => writer.begin()?.record(data)?.end()?,
+                VenueData::Reference(data) => writer.begin()?.record(data)?.end()?,
+                VenueData::Derived(data) => writer.begin()?.record(data)?.end()?,
+                VenueData::Auto(data) => data.persist(writer)?,
+                VenueData::Trade(data) => writer.begin()?.record(data)?.end()?,
+                VenueData::BenchBook(data) => writer.begin()?.record(data)?.end()?,
+                VenueData::ColumnStat(data) => writer.begin()?.record(data)?.end()?,
+                VenueData::Violation(data) => writer.begin()?.record(data)?.end()?,
+            }
+            Ok(())
+        }
+
+        /// Serializes the data as a JSON object, with the columns of its table as keys.
+        pub fn to_json(&self) -> Result<Vec<u8>> {
+            Ok(match self {
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseRfqMatch(data) => to_json(data)?,
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseFull(data) => data.to_json()?,
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseStatus(data) => to_json(data)?,
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseMarketTrade(data) => to_json(data)?,
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseLevel2(data) => to_json(data)?,
+                #[cfg(feature = "okx")]
+                VenueData::OkxOptionSummary(data) => to_json(data)?,
+                #[cfg(feature = "binance")]
+                VenueData::DepthDiff(data) => to_json(data)?,
+                #[cfg(feature = "binance")]
+                VenueData::DepthSnapshot(data) => to_json(data)?,
+                #[cfg(feature = "bitfinex")]
+                VenueData::BitfinexTrade(data) => to_json(data)?,
+                #[cfg(feature = "bitfinex")]
+                VenueData::BitfinexTicker(data) => to_json(data)?,
+                #[cfg(feature = "polygon")]
+                VenueData::StockTrade(data) => to_json(data)?,
+                #[cfg(feature = "polygon")]
+                VenueData::StockQuote(data) => to_json(data)?,
+                VenueData::Funding(data) => to_json(data)?,
+                VenueData::OpenInterest(data) => to_json(data)?,
+                VenueData::Index(data) => to_json(data)?,
+                VenueData::Liquidation(data) => to_json(data)?,
+                VenueData::Candle(data) => to_json(data)?,
+                VenueData::Bar(data) => to_json(data)?,
+                VenueData::FixMarketData(data) => to_json(data)?,
+                VenueData::ItchTrade(data) => to_json(data)?,
+                VenueData::Clock(data) => to_json(data)?,
+                VenueData::Event(data) => to_json(data)?,
+                VenueData::Symbology(data) => to_json(data)?,
+                VenueData::Reference(data) => to_json(data)?,
+                VenueData::Derived(data) => to_json(data)?,
+                VenueData::Auto(data) => data.to_json()?,
+                VenueData::Trade(data) => to_json(data)?,
+                VenueData::BenchBook(data) => to_json(data)?,
+                VenueData::ColumnStat(data) => to_json(data)?,
+                VenueData::Violation(data) => to_json(data)?,
+            })
+        }
+
+        /// Sets the canonical instrument of the normalized data types, from their symbol on the venue.
+        pub fn normalize(&mut self, venue: &str, symbology: &Symbology) {
+            let (symbol, instrument) = match self {
+                VenueData::Funding(data) => (&data.symbol, &mut data.instrument),
+                VenueData::OpenInterest(data) => (&data.symbol, &mut data.instrument),
+                VenueData::Index(data) => (&data.symbol, &mut data.instrument),
+                VenueData::Liquidation(data) => (&data.symbol, &mut data.instrument),
+                VenueData::Candle(data) => (&data.symbol, &mut data.instrument),
+                VenueData::Bar(data) => (&data.symbol, &mut data.instrument),
+                VenueData::Reference(data) => (&data.symbol, &mut data.instrument),
+                VenueData::Derived(data) => (&data.symbol, &mut data.instrument),
+                VenueData::Trade(data) => (&data.symbol, &mut data.instrument),
+                #[cfg(feature = "polygon")]
+                VenueData::StockTrade(data) => (&data.symbol, &mut data.instrument),
+                #[cfg(feature = "polygon")]
+                VenueData::StockQuote(data) => (&data.symbol, &mut data.instrument),
+                _ => return,
+            };
+            *instrument = symbology.instrument(venue, symbol);
+        }
+
+        /// Returns `(symbol, time, price, size)` for the data types that represent trades.
+        pub fn trade(&self) -> Option<(&str, DateTime<Utc>, Decimal, Decimal)> {
+            match self {
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseRfqMatch(data) => Some((&data.product_id, data.time, data.price, data.size)),
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseFull(coinbase::Full::Match(data)) => {
+                    Some((&data.product_id, data.time, data.price, data.size))
+                }
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseMarketTrade(data) => Some((&data.product_id, data.time, data.price, data.size)),
+                #[cfg(feature = "bitfinex")]
+                VenueData::BitfinexTrade(data) => Some((&data.symbol, data.time, data.price, data.size)),
+                VenueData::Trade(data) => Some((&data.symbol, data.time, data.price, data.size)),
+                #[cfg(feature = "polygon")]
+                VenueData::StockTrade(data) => Some((&data.symbol, data.time, data.price, data.size)),
+                _ => None,
+            }
+        }
+
+        /// Returns the best bid and ask with their sizes for the data types that carry them.
+        pub fn quote(&self) -> Option<Quote<'_>> {
+            match self {
+                #[cfg(feature = "bitfinex")]
+                VenueData::BitfinexTicker(data) => Some(Quote {
+                    symbol: &data.symbol,
+                    time: data.time,
+                    bid: data.bid,
+                    bid_size: data.bid_size,
+                    ask: data.ask,
+                    ask_size: data.ask_size,
+                }),
+                #[cfg(feature = "polygon")]
+                VenueData::StockQuote(data) => Some(Quote {
+                    symbol: &data.symbol,
+                    time: data.time,
+                    bid: data.bid,
+                    bid_size: data.bid_size,
+                    ask: data.ask,
+                    ask_size: data.ask_size,
+                }),
+                _ => None,
+            }
+        }
+
+        /// Returns the event time of the data types that carry one, partitioning their tables by date.
+        pub fn time(&self) -> Option<DateTime<Utc>> {
+            match self {
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseRfqMatch(data) => Some(data.time),
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseFull(data) => Some(data.time()),
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseStatus(data) => Some(data.time),
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseMarketTrade(data) => Some(data.time),
+                #[cfg(feature = "coinbase")]
+                VenueData::CoinbaseLevel2(data) => Some(data.time),
+                #[cfg(feature = "okx")]
+                VenueData::OkxOptionSummary(data) => Some(data.time),
+                #[cfg(feature = "binance")]
+                VenueData::DepthDiff(data) | VenueData::DepthSnapshot(data) => Some(data.time),
+                #[cfg(feature = "bitfinex")]
+                VenueData::BitfinexTrade(data) => Some(data.time),
+                #[cfg(feature = "bitfinex")]
+                VenueData::BitfinexTicker(data) => Some(data.time),
+                #[cfg(feature = "polygon")]
+                VenueData::StockTrade(data) => Some(data.time),
+                #[cfg(feature = "polygon")]
+                VenueData::StockQuote(data) => Some(data.time),
+                VenueData::Funding(data) => Some(data.time),
+                VenueData::OpenInterest(data) => Some(data.time),
+                VenueData::Index(data) => Some(data.time),
+                VenueData::Liquidation(data) => Some(data.time),
+                VenueData::Candle(data) => Some(data.start),
+                VenueData::Bar(data) => Some(data.start),
+                VenueData::FixMarketData(data) => Some(data.time),
+                VenueData::Clock(data) => Some(data.time),
+                VenueData::Event(data) => Some(data.time),
+                VenueData::Reference(data) => Some(data.as_of),
+                VenueData::Derived(data) => Some(data.time),
+                VenueData::Trade(data) => Some(data.time),
+                VenueData::BenchBook(data) => Some(data.time),
+                VenueData::ColumnStat(data) => Some(data.time),
+                VenueData::Violation(data) => Some(data.time),
+                _ => None,
+            }
+        }
+    }
+
+    #[derive(Debug, Persist)]
+    pub struct FundingRate {
+        pub symbol: String,
+        #[persist(intern)]
+        pub instrument: Option<String>,
+        pub time: DateTime<Utc>,
+        pub funding_rate: Decimal,
+        pub next_funding_time: Option<DateTime<Utc>>,
+    }
+
+    #[derive(Debug, Persist)]
+    pub struct OpenInterest {
+        pub symbol: String,
+        #[persist(intern)]
+        pub instrument: Option<String>,
+        pub time: DateTime<Utc>,
+        pub open_interest: Decimal,
+    }
+
+    #[derive(Debug, Persist)]
+    pub struct IndexPrice {
+        pub symbol: String,
+        #[persist(intern)]
+        pub instrument: Option<String>,
+        pub time: DateTime<Utc>,
+        pub index_price: Decimal,
+    }
+
+    /// `side` is the side of the taker order.
+    #[derive(Debug, Persist)]
+    pub struct Trade {
+        pub symbol: String,
+        #[persist(intern)]
+        pub instrument: Option<String>,
+        pub time: DateTime<Utc>,
+        pub price: Decimal,
+        pub size: Decimal,
+        #[persist(intern)]
+        pub side: String,
+    }
+
+    /// `side` is the side of the liquidation order, `sell` means a long position was liquidated.
+    #[derive(Debug, Persist)]
+    pub struct Liquidation {
+        pub symbol: String,
+        #[persist(intern)]
+        pub instrument: Option<String>,
+        pub time: DateTime<Utc>,
+        #[persist(intern)]
+        pub side: String,
+        pub price: Decimal,
+        pub size: Decimal,
+    }
+
+    pub enum Record {
+        Data {
+            exchange: String,
+            channel: String,
+            symbol: String,
+            data: Box<VenueData>,
+        },
+        Skip {
+            reason: SkipReason,
+            message: String,
+        },
+        Error {
+            exchange: String,
+            channel: String,
+            severity: Severity,
+            message: String,
+            reason: String,
+        },
+        /// sent by a sequenced source once the records of `seq` are sent, if `--watermark` is set
+        Checkpoint {
+            seq: u64,
+            watermark: Watermark,
+        },
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub enum SkipReason {
+        /// a message of a channel or event not recorded, e.g. heartbeats, subscription acks or statuses
+        Unsubscribed,
+        /// a message that could not be parsed, e.g. malformed JSON or a payload not matching its channel
+        Parse,
+        /// a frame without text, e.g. pings, pongs or binary frames of a text venue
+        NonText,
+        /// a message parsed but not recorded, e.g. a duplicate or a failed poll
+        Other,
+    }
+
+    impl std::fmt::Display for SkipReason {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(match self {
+                SkipReason::Unsubscribed => "unsubscribed",
+                SkipReason::Parse => "parse",
+                SkipReason::NonText => "non_text",
+                SkipReason::Other => "other",
+            })
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Severity {
+        /// a single request failed (e.g. a subscription to an unknown product), the other channels keep flowing
+        Warning,
+        /// the connection is in a bad state (e.g. rate limited), the channels may have stopped
+        Error,
+        /// the session cannot go on (e.g. logged out)
+        Fatal,
+    }
+
+    impl FromStr for Severity {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "warning" => Ok(Severity::Warning),
+                "error" => Ok(Severity::Error),
+                "fatal" => Ok(Severity::Fatal),
+                _ => Err(format!("unknown severity {s}, expected warning, error or fatal")),
+            }
+        }
+    }
+
+    impl Record {
+        pub fn data(exchange: &str, channel: &str, symbol: &str, data: VenueData) -> Self {
+            Record::Data {
+                exchange: exchange.to_owned(),
+                channel: channel.to_owned(),
+                symbol: symbol.to_owned(),
+                data: Box::new(data),
+            }
+        }
+
+        pub fn skip(reason: SkipReason, message: impl Into<String>) -> Self {
+            Record::Skip {
+                reason,
+                message: message.into(),
+            }
+        }
+
+        /// Skips a text message matching none of the messages of the venue, as a message of a channel not recorded if
+        /// it is JSON, as a parse failure otherwise.
+        pub fn unhandled(text: String) -> Self {
+            let reason = match serde_json::from_str::<serde::de::IgnoredAny>(&text) {
+                Ok(_) => SkipReason::Unsubscribed,
+                Err(_) => SkipReason::Parse,
+            };
+            Record::skip(reason, text)
+        }
+
+        pub fn non_text() -> Self {
+            Record::skip(SkipReason::NonText, "no text")
+        }
+
+        pub fn error(exchange: &str, channel: &str, severity: Severity, message: String, reason: String) -> Self {
+            Record::Error {
+                exchange: exchange.to_owned(),
+                channel: channel.to_owned(),
+                severity,
+                message,
+                reason,
+            }
+        }
+    }
+}
+
+pub mod persister {
+    //! Persists RFQ data into Parquet files for long-term storage and analysis.
+    //!
+    //! ## Features
+    //! - Configures output directories and files using the `dixit_persist` crate.
+    //! - Processes incoming records from an `mpsc::Receiver`, routing them to one table per data type of the
+    //!   `PersistService`, which owns the table writers, named by the `Router` (`{venue}/{channel}` by default).
+    //! - Completes the tables of a day once its lateness window has passed, when the tables are partitioned by date.
+    //! - Closes and gap-fills candles through the `candles` aggregator before persisting them.
+    //! - Optionally aggregates trades into bars through the `bars` aggregator, closing them on a timer.
+    //! - Optionally derives metrics from the quotes of some tables through the `Deriver`, before conflation.
+    //! - Conflates the records of quote-style tables through the `Conflator`, keeping the latest record of each
+    //!   symbol per interval.
+    //! - Samples the records of each symbol persisted to the tables with a sampling rule, through the `Sampler`,
+    //!   whose rules are replaced when the config file changes.
+    //! - Handles valid data, skips irrelevant records, and logs errors, the skipped messages being counted per
+    //!   reason and their logs sampled by the `SkipLog`.
+    //! - Persists the venue errors and the flushes and rotations of the service to the `_events` table, with the
+    //!   events of the sources, the events are kept while paused.
+    //! - Persists the column statistics of the flushes of the venue tables reported by the service to the `_stats`
+    //!   table, with the `quality` module.
+    //! - Forwards the tables whose first file is written to the catalog, if set, including the ones completed on
+    //!   shutdown.
+    //! - Validates the venue records against the data quality rules of the `Validator` after conflation, persisting
+    //!   their violations to the `_violations` table and dropping the rejected records, the counts of violations
+    //!   are logged on shutdown.
+    //! - Writes the `_manifest.json` of the run in the venue directory on startup, completed with the tables written
+    //!   on shutdown.
+    //! - Mirrors every parsed record to the publishers, publishing errors are logged and do not stop recording.
+    //! - Drops the parsed records while paused (e.g. by the admin API), neither persisting nor publishing them.
+    //! - Marks the feed as fresh on every venue record, for the staleness detector of the `supervisor` module.
+    //! - Sets the canonical instrument of the normalized records and persists the mappings of the venue to the
+    //!   `_symbology` table on startup, with the `symbology` module.
+    //! - Acknowledges the checkpoints of the sequenced sources and periodically saves their watermark once flushed,
+    //!   with the `watermark` module.
+    //! - Writes Parquet, Delta Lake, Iceberg, JSON lines or CSV tables depending on the selected format of each table.
+
+    use std::env;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use anyhow::{Ok, Result};
+    use dixit_persist::config::PersistConfig;
+    use dixit_persist::path::table_path;
+    use dixit_persist::service::{PersistCmd, PersistService, ServiceEvent, ServiceOptions};
+    use dixit_persist::writer::TableWriter;
+    use tokio::sync::mpsc::{self, Receiver, Sender};
+    use tokio::sync::{oneshot, watch};
+    use tokio::time;
+    use tracing::{error, info, warn};
+
+    use crate::{
+        bars::TradeAggregator,
+        candles::CandleAggregator,
+        clock::SharedClock,
+        config::Venue,
+        conflation::Conflator,
+        derived::Deriver,
+        error::RecorderError,
+        events::{Event, EventKind},
+        manifest::Manifest,
+        model::{Record, Severity, VenueData},
+        publish::{self, Publisher},
+        quality::{self, Validator},
+        route::Router,
+        sampling::{Sampler, Sampling},
+        skips::SkipLog,
+        supervisor::Freshness,
+        symbology::Symbology,
+        watermark::Tracker,
+    };
+
+    static PAUSED: AtomicBool = AtomicBool::new(false);
+
+    #[cfg(feature = "admin")]
+    pub fn set_paused(paused: bool) {
+        if PAUSED.swap(paused, Ordering::Relaxed) != paused {
+            info!("recording {}", if paused { "paused" } else { "resumed" });
+        }
+    }
+
+    impl dixit_persist::service::Writable for VenueData {
+        fn write(&self, writer: &mut TableWriter) -> Result<()> {
+            self.persist(writer)
+        }
+    }
+
+    /// Stages the records go through before being persisted, in order.
+    pub struct Stages {
+        pub bars: TradeAggregator,
+        pub conflator: Conflator<VenueData>,
+        /// tables deriving metrics from their quotes
+        pub deriver: Deriver,
+        pub sampler: Sampler,
+        /// sampling rules replacing the ones of the sampler on changes
+        pub sampling: watch::Receiver<Vec<(String, Sampling)>>,
+        /// data quality rules the records are validated against before being persisted
+        pub validator: Validator,
+        /// catalog the tables are registered to once their first file is written, e.g. Glue
+        pub catalog: Option<mpsc::UnboundedSender<ServiceEvent>>,
+        /// checkpoints of the source acknowledged and saved, if `--watermark` is set
+        pub watermark: Option<Tracker>,
+        /// canonical instruments of the symbols
+        pub symbology: Symbology,
+        /// names of the tables the records are persisted to
+        pub router: Router,
+        /// counts and sampled logs of the skipped messages
+        pub skips: SkipLog,
+        /// clock of the stages, dating the tables, the events, the statistics and the bars closed on the timer
+        pub clock: SharedClock,
+        /// marked on every venue record, for the staleness detector of the `supervisor` module
+        pub freshness: Arc<Freshness>,
+    }
+
+    /// Creates the service writing the tables under the output directory, relative to the working directory, run
+    /// by [`run`], rotating the tables by the local `clock`.
+    pub fn service(
+        config: PersistConfig,
+        options: ServiceOptions,
+        clock: SharedClock,
+    ) -> (PersistService, Sender<PersistCmd>) {
+        let config = PersistConfig {
+            directory: env::current_dir().unwrap().join(&config.directory),
+            ..config
+        };
+        let (service, tx) = PersistService::new(config, options);
+        (service.with_clock(clock), tx)
+    }
+
+    pub async fn run(
+        venue: Venue,
+        (service, tx): (PersistService, Sender<PersistCmd>),
+        mut stages: Stages,
+        mut manifest: Manifest,
+        mut publishers: Vec<Publisher>,
+        mut rx: Receiver<Record>,
+    ) -> Result<()> {
+        let venue_directory = table_path(&service.config().directory, &venue.to_string());
+        manifest.write(&venue_directory)?;
+        let column_stats = service.config().column_stats;
+        let (events_tx, mut events) = mpsc::unbounded_channel();
+        let service = tokio::spawn(service.with_events(events_tx).run());
+        let mut candles = CandleAggregator::default();
+        let mut timer = time::interval(Duration::from_secs(1));
+        let mut conflation = time::interval(stages.conflator.interval());
+        let mut partitions = time::interval(Duration::from_secs(1));
+        let venue_name = venue.to_string();
+        for mapping in stages.symbology.mappings(&venue_name) {
+            let symbol = mapping.symbol.clone();
+            persist(&tx, venue, &mut stages, &symbol, VenueData::Symbology(mapping)).await?;
+        }
+        let watermark_interval = stages
+            .watermark
+            .as_ref()
+            .map_or(Duration::from_secs(10), Tracker::interval);
+        let mut watermark = time::interval_at(time::Instant::now() + watermark_interval, watermark_interval);
+
+        let routed = async {
+            loop {
+                tokio::select! {
+                    record = rx.recv() => match record.inspect(|record| fresh(&stages.freshness, record)) {
+                        Some(Record::Data { data, .. })
+                            if PAUSED.load(Ordering::Relaxed) && !matches!(*data, VenueData::Event(_)) => {}
+                        Some(Record::Data {
+                            data,
+                            exchange,
+                            channel,
+                            symbol,
+                        }) => {
+                            info!("[{exchange}] [{channel}] [{symbol}]: {:?}", data);
+                            if !publishers.is_empty() {
+                                publish(&mut publishers, publish::subject(venue, &channel, &symbol), &data).await;
+                            }
+                            let mut data = match *data {
+                                VenueData::Candle(candle) => {
+                                    candles.update(candle).into_iter().map(VenueData::Candle).collect()
+                                }
+                                data => vec![data],
+                            };
+                            if let Some((symbol, time, price, size)) = data.first().and_then(VenueData::trade) {
+                                let closed = stages.bars.update(symbol, time, price, size);
+                                data.extend(closed.into_iter().map(VenueData::Bar));
+                            }
+                            let derived = data.iter().filter_map(|data| stages.deriver.derive(data)).collect::<Vec<_>>();
+                            data.extend(derived.into_iter().map(VenueData::Derived));
+                            for mut data in data {
+                                data.normalize(&venue_name, &stages.symbology);
+                                if let Some(data) = stages.conflator.conflate(data.table(), &symbol, data) {
+                                    validate(&tx, venue, &mut stages, &symbol, data).await?;
+                                }
+                            }
+                        }
+                        Some(Record::Skip { reason, message }) => {
+                            stages.skips.skip(reason, &message);
+                        }
+                        // the source reacts to its errors as set by its error policy, stopping on its own if needed
+                        Some(Record::Error {
+                            exchange,
+                            channel,
+                            severity,
+                            message,
+                            reason,
+                        }) => {
+                            match severity {
+                                Severity::Warning => warn!("[{exchange}] [{channel}] {message}: {reason}"),
+                                _ => error!("[{exchange}] [{channel}] {severity:?} {message}: {reason}"),
+                            }
+                            let source = format!("{exchange}/{channel}");
+                            let detail = format!("{severity:?} {message}: {reason}");
+                            let event = Event::new(stages.clock.now(), EventKind::Error, &source, detail);
+                            persist_event(&tx, venue, &mut stages, event).await?;
+                        }
+                        Some(Record::Checkpoint { seq, watermark }) => {
+                            if let Some(tracker) = &mut stages.watermark {
+                                tracker.append(seq, watermark);
+                            }
+                        }
+                        None => break,
+                    },
+                    Some(event) = events.recv() => {
+                        service_event(&tx, venue, &mut stages, event).await?;
+                    }
+                    std::result::Result::Ok(()) = stages.sampling.changed() => {
+                        stages.sampler = Sampler::new(stages.sampling.borrow_and_update().clone());
+                    }
+                    _ = timer.tick(), if stages.bars.is_enabled() => {
+                        for bar in stages.bars.close(stages.clock.now()) {
+                            let symbol = bar.symbol.clone();
+                            let mut data = VenueData::Bar(bar);
+                            data.normalize(&venue_name, &stages.symbology);
+                            persist(&tx, venue, &mut stages, &symbol, data).await?;
+                        }
+                    }
+                    _ = conflation.tick(), if stages.conflator.is_enabled() => {
+                        for (symbol, data) in stages.conflator.drain() {
+                            validate(&tx, venue, &mut stages, &symbol, data).await?;
+                        }
+                    }
+                    _ = partitions.tick(), if stages.router.is_dated() => {
+                        for table in stages.router.expired(stages.clock.now()) {
+                            complete(&tx, table).await?;
+                        }
+                    }
+                    _ = watermark.tick(), if stages.watermark.is_some() => {
+                        if let Some(tracker) = &mut stages.watermark {
+                            tracker.flush(&tx, &venue_directory).await?;
+                        }
+                    }
+                }
+            }
+            for (symbol, data) in stages.conflator.drain() {
+                validate(&tx, venue, &mut stages, &symbol, data).await?;
+            }
+            if stages.validator.is_enabled() {
+                stages.validator.report();
+            }
+            stages.skips.report();
+            if column_stats {
+                // the statistics of the last flushes are persisted before the service closes the tables
+                let (reply, flushed) = oneshot::channel();
+                tx.send(PersistCmd::Flush(reply))
+                    .await
+                    .map_err(|_| RecorderError::PersistStopped)?;
+                flushed.await??;
+                while let std::result::Result::Ok(event) = events.try_recv() {
+                    service_event(&tx, venue, &mut stages, event).await?;
+                }
+            }
+            Ok(())
+        }
+        .await;
+
+        // the service closes every table once the sender is dropped, a failed send means the service stopped on an
+        // error, which is returned first
+        drop(tx);
+        let stats = service.await??;
+        if let Some(catalog) = &stages.catalog {
+            while let std::result::Result::Ok(event) = events.try_recv() {
+                if matches!(event, ServiceEvent::TableCreated { .. }) {
+                    let _ = catalog.send(event);
+                }
+            }
+        }
+        if let Some(tracker) = &mut stages.watermark {
+            tracker.finish(&venue_directory)?;
+        }
+        manifest.finish(&stats, stages.clock.now());
+        manifest.write(&venue_directory)?;
+        routed
+    }
+
+    /// Marks the feed as fresh on the venue data, the clock measures and the events do not come from the venue.
+    fn fresh(freshness: &Freshness, record: &Record) {
+        if let Record::Data { data, .. } = record {
+            if !matches!(**data, VenueData::Clock(_) | VenueData::Event(_)) {
+                freshness.record_received();
+            }
+        }
+    }
+
+    async fn publish(publishers: &mut [Publisher], subject: String, data: &VenueData) {
+        let Some(payload) = data
+            .to_json()
+            .inspect_err(|e| error!("cannot serialize {subject}: {e}"))
+            .ok()
+        else {
+            return;
+        };
+        for publisher in publishers.iter_mut() {
+            if let Err(e) = publisher.publish(subject.clone(), payload.clone()).await {
+                error!("publish error: {e}");
+            }
+        }
+    }
+
+    /// Persists an event of the service, the column statistics of the venue tables to the `_stats` table and the
+    /// other events to the `_events` table, but the created tables forwarded to the catalog.
+    async fn service_event(
+        tx: &Sender<PersistCmd>,
+        venue: Venue,
+        stages: &mut Stages,
+        event: ServiceEvent,
+    ) -> Result<()> {
+        match event {
+            ServiceEvent::ColumnStats { table, stats } => {
+                if quality::is_measured(&table) {
+                    for stat in quality::column_stats(&table, stats, stages.clock.now()) {
+                        let data = VenueData::ColumnStat(stat);
+                        persist(tx, venue, stages, &table, data).await?;
+                    }
+                }
+                Ok(())
+            }
+            ServiceEvent::TableCreated { .. } => {
+                if let Some(catalog) = &stages.catalog {
+                    let _ = catalog.send(event);
+                }
+                Ok(())
+            }
+            event => {
+                let event = Event::service(event, stages.clock.now());
+                persist_event(tx, venue, stages, event).await
+            }
+        }
+    }
+
+    /// Persists a venue record unless rejected by the validator, with its violations to the `_violations` table.
+    async fn validate(
+        tx: &Sender<PersistCmd>,
+        venue: Venue,
+        stages: &mut Stages,
+        symbol: &str,
+        data: VenueData,
+    ) -> Result<()> {
+        let (keep, violations) = stages.validator.validate(symbol, &data, stages.clock.now());
+        for violation in violations {
+            let violation = VenueData::Violation(violation);
+            persist(tx, venue, stages, symbol, violation).await?;
+        }
+        if keep {
+            persist(tx, venue, stages, symbol, data).await?;
+        }
+        Ok(())
+    }
+
+    /// Persists an event of the recorder to the `_events` table, keyed by its kind.
+    async fn persist_event(tx: &Sender<PersistCmd>, venue: Venue, stages: &mut Stages, event: Event) -> Result<()> {
+        let kind = event.kind.clone();
+        persist(tx, venue, stages, &kind, VenueData::Event(event)).await
+    }
+
+    /// Closes a table whose lateness window has passed, completing its open file without waiting for it.
+    async fn complete(tx: &Sender<PersistCmd>, table: String) -> Result<()> {
+        info!("completing {table}");
+        let (reply, _) = oneshot::channel();
+        tx.send(PersistCmd::Rotate(table, reply))
+            .await
+            .map_err(|_| RecorderError::PersistStopped.into())
+    }
+
+    /// Sends the record to the table of its route, unless dropped by the sampler.
+    async fn persist(
+        tx: &Sender<PersistCmd>,
+        venue: Venue,
+        stages: &mut Stages,
+        symbol: &str,
+        data: VenueData,
+    ) -> Result<()> {
+        if !stages.sampler.keep(data.table(), symbol) {
+            return Ok(());
+        }
+        let now = stages.clock.now();
+        let table = stages.router.table(&venue.to_string(), data.table(), data.time(), now);
+        tx.send(PersistCmd::Write {
+            table,
+            record: Box::new(data),
+        })
+        .await
+        .map_err(|_| RecorderError::PersistStopped.into())
+    }
+}
+
+pub mod websocket {
+    //! Manages WebSocket connections to receive RFQ data in real time.
+    //!
+    //! ## Features
+    //! - Establishes a WebSocket connection using `tokio-tungstenite`.
+    //! - Sends subscription messages to start receiving data.
+    //! - Processes incoming messages and forwards them to the data channel.
+    //! - Reacts to the venue errors as set by the [`ErrorPolicy`] for their severity: continues, resubscribes,
+    //!   reconnects (resubscribing) or aborts.
+    //! - Fails with a [`RecorderError`] keeping its cause: the websocket error, or the venue error aborting it.
+    //! - Subscribes and unsubscribes symbols at runtime on [`ControlCmd`]s, tracking the [`Subscriptions`] so a
+    //!   reconnection resubscribes to the current symbols.
+    //! - Reports its connections, disconnections and resubscriptions as events, persisted to the `_events` table.
+    //! - Sends the keepalive requests of the venues expecting application pings (e.g. MEXC) at their interval, and
+    //!   the login requests of the venues authenticating connections (e.g. Polygon) before subscribing.
+    //! - Adds the extra headers of the venue and of `--ws-header` / `--user-agent` to the upgrade request of every
+    //!   connection (e.g. an API key header, an `Origin` expected by the venue).
+
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use chrono::{DateTime, Utc};
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::TcpStream;
+    use tokio::sync::mpsc::{Receiver, Sender};
+    use tokio::time;
+    use tokio_tungstenite::{
+        connect_async_tls_with_config,
+        tungstenite::{
+            client::IntoClientRequest,
+            http::{HeaderName, HeaderValue},
+            Message,
+        },
+        MaybeTlsStream, WebSocketStream,
+    };
+    use tracing::{info, warn};
+
+    use crate::clock::{self, Clock, SharedClock};
+    use crate::control::ControlCmd;
+    use crate::error::RecorderError;
+    use crate::events::{Event, EventKind};
+    use crate::model::{Record, Severity};
+
+    /// Delay before reconnecting, so a venue failing right after the connection is not hammered.
+    const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+    /// Reaction to a venue error, ordered from the mildest.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum ErrorAction {
+        Continue,
+        Resubscribe,
+        Reconnect,
+        Abort,
+    }
+
+    impl FromStr for ErrorAction {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "continue" => Ok(ErrorAction::Continue),
+                "resubscribe" => Ok(ErrorAction::Resubscribe),
+                "reconnect" => Ok(ErrorAction::Reconnect),
+                "abort" => Ok(ErrorAction::Abort),
+                _ => Err(format!(
+                    "unknown error action {s}, expected continue, resubscribe, reconnect or abort"
+                )),
+            }
+        }
+    }
+
+    /// Action taken for the errors of each severity.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct ErrorPolicy {
+        pub warning: ErrorAction,
+        pub error: ErrorAction,
+        pub fatal: ErrorAction,
+    }
+
+    impl Default for ErrorPolicy {
+        fn default() -> Self {
+            Self {
+                warning: ErrorAction::Continue,
+                error: ErrorAction::Reconnect,
+                fatal: ErrorAction::Abort,
+            }
+        }
+    }
+
+    impl ErrorPolicy {
+        pub fn action(&self, severity: Severity) -> ErrorAction {
+            match severity {
+                Severity::Warning => self.warning,
+                Severity::Error => self.error,
+                Severity::Fatal => self.fatal,
+            }
+        }
+
+        pub fn set(&mut self, severity: Severity, action: ErrorAction) {
+            match severity {
+                Severity::Warning => self.warning = action,
+                Severity::Error => self.error = action,
+                Severity::Fatal => self.fatal = action,
+            }
+        }
+    }
+
+    /// A keepalive request, built when sent as some carry the current time.
+    type Keepalive = fn(DateTime<Utc>) -> Message;
+
+    /// Symbols subscribed and the venue requests (un)subscribing them.
+    pub struct Subscriptions<S, U> {
+        symbols: Vec<String>,
+        subscribe_fn: S,
+        unsubscribe_fn: U,
+        login: Vec<Message>,
+        keepalive: Option<(Duration, Keepalive)>,
+        headers: Vec<(String, String)>,
+        /// clock of the keepalive requests and the connection events
+        clock: SharedClock,
+    }
+
+    impl<S, U> Subscriptions<S, U>
+    where
+        S: Fn(&[String]) -> Vec<Message>,
+        U: Fn(&[String]) -> Vec<Message>,
+    {
+        pub fn new(symbols: Vec<String>, subscribe_fn: S, unsubscribe_fn: U) -> Self {
+            Self {
+                symbols,
+                subscribe_fn,
+                unsubscribe_fn,
+                login: vec![],
+                keepalive: None,
+                headers: vec![],
+                clock: clock::system(),
+            }
+        }
+
+        /// Reads the time of the keepalive requests and the connection events from the clock, the system clock if
+        /// not set.
+        pub fn with_clock(mut self, clock: SharedClock) -> Self {
+            self.clock = clock;
+            self
+        }
+
+        /// Sends the requests on every connection before subscribing, for the venues authenticating connections.
+        pub fn with_login(mut self, requests: Vec<Message>) -> Self {
+            self.login = requests;
+            self
+        }
+
+        /// Adds the headers to the upgrade request of every connection, after the ones already added.
+        pub fn with_headers(mut self, headers: Vec<(String, String)>) -> Self {
+            self.headers.extend(headers);
+            self
+        }
+
+        /// Sends the request at every interval while connected, for the venues expecting application pings.
+        pub fn with_keepalive(mut self, interval: Duration, request: Keepalive) -> Self {
+            self.keepalive = Some((interval, request));
+            self
+        }
+
+        /// Requests subscribing every symbol, on (re)connection.
+        fn subscribe_all(&self) -> Vec<Message> {
+            (self.subscribe_fn)(&self.symbols)
+        }
+
+        /// Updates the symbols and returns the venue requests, the command is replied with the symbols subscribed.
+        fn apply(&mut self, command: ControlCmd) -> Vec<Message> {
+            let (requests, reply) = match command {
+                ControlCmd::Subscribe(symbols, reply) => {
+                    let added = symbols
+                        .into_iter()
+                        .filter(|symbol| !self.symbols.contains(symbol))
+                        .collect::<Vec<_>>();
+                    info!("subscribing to {added:?}");
+                    self.symbols.extend(added.iter().cloned());
+                    let requests = if added.is_empty() {
+                        vec![]
+                    } else {
+                        (self.subscribe_fn)(&added)
+                    };
+                    (requests, reply)
+                }
+                ControlCmd::Unsubscribe(symbols, reply) => {
+                    let removed = symbols
+                        .into_iter()
+                        .filter(|symbol| self.symbols.contains(symbol))
+                        .collect::<Vec<_>>();
+                    info!("unsubscribing from {removed:?}");
+                    self.symbols.retain(|symbol| !removed.contains(symbol));
+                    ((self.unsubscribe_fn)(&removed), reply)
+                }
+                ControlCmd::List(reply) => (vec![], reply),
+            };
+            let _ = reply.send(self.symbols.clone());
+            requests
+        }
+    }
+
+    pub async fn run<S, U>(
+        tx: Sender<Record>,
+        ws_url: &str,
+        policy: ErrorPolicy,
+        mut subscriptions: Subscriptions<S, U>,
+        mut control: Receiver<ControlCmd>,
+        mut handle_fn: impl FnMut(Message) -> Vec<Record>,
+    ) -> Result<()>
+    where
+        S: Fn(&[String]) -> Vec<Message>,
+        U: Fn(&[String]) -> Vec<Message>,
+    {
+        let clock = subscriptions.clock.clone();
+        let mut controlled = true;
+        loop {
+            let mut stream = connect(ws_url, &subscriptions.headers).await?;
+            send(&mut stream, subscriptions.login.clone()).await?;
+            send(&mut stream, subscriptions.subscribe_all()).await?;
+            let subscribed = format!("subscribed to {} symbols", subscriptions.symbols.len());
+            event(&tx, &*clock, EventKind::Connect, ws_url, subscribed).await?;
+            let mut keepalive = subscriptions.keepalive.map(|(period, request)| {
+                let interval = time::interval_at(time::Instant::now() + period, period);
+                (interval, request)
+            });
+
+            loop {
+                let message = tokio::select! {
+                    message = stream.next() => message,
+                    request = tick(&mut keepalive, &*clock) => {
+                        send(&mut stream, vec![request]).await?;
+                        continue;
+                    }
+                    command = control.recv(), if controlled => {
+                        match command {
+                            Some(command) => send(&mut stream, subscriptions.apply(command)).await?,
+                            None => controlled = false,
+                        }
+                        continue;
+                    }
+                };
+                let message = match message {
+                    Some(Ok(message)) => message,
+                    Some(Err(source)) => {
+                        event(&tx, &*clock, EventKind::Disconnect, ws_url, source.to_string()).await?;
+                        return Err(RecorderError::Websocket {
+                            url: ws_url.to_owned(),
+                            source,
+                        }
+                        .into());
+                    }
+                    None => {
+                        event(&tx, &*clock, EventKind::Disconnect, ws_url, "stream ended").await?;
+                        return Ok(());
+                    }
+                };
+                let mut action = (ErrorAction::Continue, None);
+                for record in handle_fn(message) {
+                    if let Record::Error { severity, .. } = &record {
+                        let reaction = policy.action(*severity);
+                        if reaction > action.0 {
+                            action = (reaction, RecorderError::venue(&record));
+                        }
+                    }
+                    tx.send(record).await?;
+                }
+                match action {
+                    (ErrorAction::Resubscribe, Some(error)) => {
+                        warn!("resubscribing to {ws_url} after {error}");
+                        send(&mut stream, subscriptions.subscribe_all()).await?;
+                        event(&tx, &*clock, EventKind::Resubscribe, ws_url, error.to_string()).await?;
+                    }
+                    (ErrorAction::Reconnect, Some(error)) => {
+                        warn!("reconnecting to {ws_url} after {error}");
+                        event(
+                            &tx,
+                            &*clock,
+                            EventKind::Disconnect,
+                            ws_url,
+                            format!("reconnecting after {error}"),
+                        )
+                        .await?;
+                        break;
+                    }
+                    (ErrorAction::Abort, Some(error)) => {
+                        let aborting = format!("aborting after {error}");
+                        event(&tx, &*clock, EventKind::Disconnect, ws_url, aborting).await?;
+                        return Err(RecorderError::Aborted {
+                            url: ws_url.to_owned(),
+                            cause: Box::new(error),
+                        }
+                        .into());
+                    }
+                    _ => (),
+                }
+            }
+            let _ = stream.close(None).await;
+            time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    /// Waits for the next keepalive request, forever if the venue has none.
+    async fn tick(keepalive: &mut Option<(time::Interval, Keepalive)>, clock: &dyn Clock) -> Message {
+        match keepalive {
+            Some((interval, request)) => {
+                interval.tick().await;
+                request(clock.now())
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    async fn event(
+        tx: &Sender<Record>,
+        clock: &dyn Clock,
+        kind: EventKind,
+        ws_url: &str,
+        detail: impl Into<String>,
+    ) -> Result<()> {
+        tx.send(Event::new(clock.now(), kind, ws_url, detail).record()).await?;
+        Ok(())
+    }
+
+    async fn send(stream: &mut WebSocketStream<MaybeTlsStream<TcpStream>>, messages: Vec<Message>) -> Result<()> {
+        for message in messages {
+            stream.send(message).await?;
+        }
+        Ok(())
+    }
+
+    async fn connect(ws_url: &str, headers: &[(String, String)]) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+        let mut request = ws_url.into_client_request()?;
+        for (name, value) in headers {
+            // a header given twice keeps its last value, e.g. --user-agent overridden by a --ws-header
+            request
+                .headers_mut()
+                .insert(HeaderName::from_str(name)?, HeaderValue::from_str(value)?);
+        }
+        let (stream, _) = connect_async_tls_with_config(request, None, true, None).await?;
+        Ok(stream)
+    }
+}
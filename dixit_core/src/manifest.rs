@@ -0,0 +1,82 @@
+//! Documents how every dataset was produced, with a `_manifest.json` describing the capture run in the directory
+//! of the venue tables (e.g. `output/coinbase/_manifest.json`).
+//!
+//! ## Features
+//! - Written on startup with the run id, start time, command line, crate version and git commit, venue and symbols.
+//! - Updated on shutdown with the end time and the rows and files written to every table.
+//! - Replaced atomically (written aside then renamed), so readers never see a partial manifest.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dixit_persist::service::ServiceStats;
+use serde::Serialize;
+
+use crate::config::Venue;
+
+pub const FILE_NAME: &str = "_manifest.json";
+
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    pub run_id: String,
+    pub version: &'static str,
+    pub git_commit: Option<&'static str>,
+    pub start_time: DateTime<Utc>,
+    /// set on shutdown, a manifest without end time belongs to a run still going or that crashed
+    pub end_time: Option<DateTime<Utc>>,
+    pub venues: Vec<String>,
+    pub symbols: Vec<String>,
+    /// command line arguments of the run, the options left out took their default value
+    pub arguments: Vec<String>,
+    pub tables: BTreeMap<String, TableSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableSummary {
+    pub rows: u64,
+    pub files: u64,
+}
+
+impl Manifest {
+    pub fn new(venue: Venue, symbols: Vec<String>, start_time: DateTime<Utc>) -> Self {
+        Self {
+            run_id: format!("{}-{}", start_time.format("%Y%m%dT%H%M%S%.3fZ"), std::process::id()),
+            version: env!("CARGO_PKG_VERSION"),
+            git_commit: option_env!("DIXIT_GIT_COMMIT"),
+            start_time,
+            end_time: None,
+            venues: vec![venue.to_string()],
+            symbols,
+            arguments: std::env::args().skip(1).collect(),
+            tables: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the end time and the tables written, from the final stats of the persist service.
+    pub fn finish(&mut self, stats: &ServiceStats, end_time: DateTime<Utc>) {
+        self.end_time = Some(end_time);
+        self.tables = stats
+            .tables
+            .iter()
+            .map(|(table, stats)| {
+                let summary = TableSummary {
+                    rows: stats.rows_written,
+                    files: stats.files_written,
+                };
+                (table.clone(), summary)
+            })
+            .collect();
+    }
+
+    pub fn write(&self, directory: &Path) -> Result<()> {
+        fs::create_dir_all(directory)?;
+        let path = directory.join(FILE_NAME);
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(temp, path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+//! Contains MEXC-specific WebSocket handling logic.
+//!
+//! ## Features
+//! - Subscribes to the spot trades (`spot@public.deals.v3.api@{symbol}`) of each symbol on the MEXC JSON feed.
+//! - Parses the deals of each push into normalized trade records, persisted to the `trades` table.
+//! - Sends a `PING` request every 20 seconds, MEXC closing the connections silent for a minute.
+//! - Reports the rejected subscriptions, which MEXC acknowledges with a zero code and an explanation, as errors.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{from_str, json};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::model::{Record, Severity, Trade, VenueData};
+
+pub const EXCHANGE: &str = "mexc";
+pub const WS_URL: &str = "wss://wbs.mexc.com/ws";
+pub const SYMBOLS: &[&str] = &["BTCUSDT", "ETHUSDT"];
+
+/// Interval between the `PING` requests keeping the connection open.
+pub const PING_INTERVAL: Duration = Duration::from_secs(20);
+
+const DEALS: &str = "spot@public.deals.v3.api";
+
+pub fn subscribe(symbols: &[String]) -> Vec<Message> {
+    request("SUBSCRIPTION", symbols)
+}
+
+pub fn unsubscribe(symbols: &[String]) -> Vec<Message> {
+    request("UNSUBSCRIPTION", symbols)
+}
+
+pub fn ping(_now: DateTime<Utc>) -> Message {
+    Message::Text(json!({ "method": "PING" }).to_string())
+}
+
+fn request(method: &str, symbols: &[String]) -> Vec<Message> {
+    if symbols.is_empty() {
+        return vec![];
+    }
+    let params = symbols
+        .iter()
+        .map(|symbol| format!("{DEALS}@{symbol}"))
+        .collect::<Vec<_>>();
+    let request = json!({
+        "method": method,
+        "params": params
+    });
+    vec![Message::Text(request.to_string())]
+}
+
+pub fn handle(message: Message) -> Vec<Record> {
+    match message {
+        Message::Text(string) => {
+            if let Ok(push) = from_str::<Push>(&string) {
+                if push.channel.starts_with(DEALS) {
+                    return parse_deals(push);
+                }
+            } else if let Ok(response) = from_str::<Response>(&string) {
+                // e.g. "Not Subscribed successfully! [spot@public.deals.v3.api@BTCUSDT]. Reason: Blocked!"
+                if response.code != 0 || response.msg.starts_with("Not Subscribed") {
+                    return vec![Record::error(
+                        EXCHANGE,
+                        "subscription",
+                        Severity::Warning,
+                        response.msg,
+                        format!("code {}", response.code),
+                    )];
+                }
+            }
+            vec![Record::unhandled(string)]
+        }
+        _ => vec![Record::non_text()],
+    }
+}
+
+/// MEXC gives the side of the taker order as `1` (buy) or `2` (sell).
+fn parse_deals(push: Push) -> Vec<Record> {
+    push.data
+        .deals
+        .into_iter()
+        .map(|deal| {
+            let side = if deal.side == 1 { "buy" } else { "sell" };
+            Record::data(
+                EXCHANGE,
+                "trades",
+                &push.symbol,
+                VenueData::Trade(Trade {
+                    symbol: push.symbol.clone(),
+                    instrument: None,
+                    time: deal.time,
+                    price: deal.price,
+                    size: deal.size,
+                    side: side.to_owned(),
+                }),
+            )
+        })
+        .collect()
+}
+
+#[derive(Deserialize, Debug)]
+struct Push {
+    #[serde(rename = "c")]
+    channel: String,
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "d")]
+    data: Deals,
+}
+
+#[derive(Deserialize, Debug)]
+struct Deals {
+    deals: Vec<Deal>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Deal {
+    #[serde(rename = "S")]
+    side: u8,
+    #[serde(rename = "p")]
+    price: Decimal,
+    #[serde(rename = "v")]
+    size: Decimal,
+    #[serde(rename = "t", with = "chrono::serde::ts_milliseconds")]
+    time: DateTime<Utc>,
+}
+
+/// Answer to a request, `msg` is `PONG` for the pings and the topics for the subscriptions.
+#[derive(Deserialize, Debug)]
+struct Response {
+    code: i64,
+    msg: String,
+}
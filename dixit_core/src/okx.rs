@@ -0,0 +1,404 @@
+//! Contains OKX-specific WebSocket handling logic.
+//!
+//! ## Features
+//! - Subscribes to the funding rate, open interest and index ticker channels on the OKX public WebSocket feed.
+//! - Subscribes to the liquidation orders channel, which covers every swap instrument rather than a single symbol.
+//! - Subscribes to the option summary channel of the option families in `--okx-option-families` (e.g. `BTC-USD`),
+//!   recording the greeks and volatilities of every option of the family to the `option_greeks` table.
+//! - Parses the `arg`/`data` envelope into normalized funding, open interest, index and liquidation records.
+//! - Subscribes to the 1 minute candles of the symbols on the business endpoint, over a connection of its own, the
+//!   volume of the candles being in the base currency (`volCcy`) rather than in contracts.
+//! - Parses the swap instruments of the REST API into reference data (tick and lot sizes, state).
+//!
+//! Symbols are swap instruments (e.g. `BTC-USDT-SWAP`), the index is derived by dropping the `-SWAP` suffix.
+//! The greeks are both in coin terms (e.g. `delta`) and in Black-Scholes terms (e.g. `delta_bs`), the volatilities
+//! the venue leaves empty (e.g. no bid) are recorded as nulls.
+//! The candles connection follows the symbols of the command line, not the ones subscribed at runtime.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dixit_persist_macros::Persist;
+use rust_decimal::Decimal;
+use serde::{de, Deserialize, Deserializer};
+use serde_json::{from_str, json, Value};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::candles::Candle;
+use crate::model::{FundingRate, IndexPrice, Liquidation, OpenInterest, Record, Severity, VenueData};
+use crate::reference::Instrument;
+
+pub const EXCHANGE: &str = "okx";
+pub const WS_URL: &str = "wss://ws.okx.com:8443/ws/v5/public";
+/// endpoint of the candles
+pub const BUSINESS_URL: &str = "wss://ws.okx.com:8443/ws/v5/business";
+pub const REST_URL: &str = "https://www.okx.com";
+pub const SYMBOLS: &[&str] = &["BTC-USDT-SWAP", "ETH-USDT-SWAP"];
+
+pub fn subscribe(families: &[String], symbols: &[String]) -> Vec<Message> {
+    let mut args = args(symbols);
+    args.push(json!({"channel": "liquidation-orders", "instType": "SWAP"}));
+    args.extend(
+        families
+            .iter()
+            .map(|family| json!({"channel": "opt-summary", "instFamily": family})),
+    );
+    request("subscribe", args)
+}
+
+/// Unsubscribes the channels of the symbols, the liquidation orders and option summary channels covering every symbol
+/// are kept.
+pub fn unsubscribe(symbols: &[String]) -> Vec<Message> {
+    if symbols.is_empty() {
+        return vec![];
+    }
+    request("unsubscribe", args(symbols))
+}
+
+/// Subscribes the 1 minute candles of the symbols, on the business endpoint.
+pub fn subscribe_candles(symbols: &[String]) -> Vec<Message> {
+    request("subscribe", candles_args(symbols))
+}
+
+pub fn unsubscribe_candles(symbols: &[String]) -> Vec<Message> {
+    if symbols.is_empty() {
+        return vec![];
+    }
+    request("unsubscribe", candles_args(symbols))
+}
+
+fn candles_args(symbols: &[String]) -> Vec<Value> {
+    symbols
+        .iter()
+        .map(|symbol| json!({"channel": "candle1m", "instId": symbol}))
+        .collect()
+}
+
+fn args(symbols: &[String]) -> Vec<Value> {
+    symbols
+        .iter()
+        .flat_map(|symbol| {
+            [
+                json!({"channel": "funding-rate", "instId": symbol}),
+                json!({"channel": "open-interest", "instId": symbol}),
+                json!({"channel": "index-tickers", "instId": index_of(symbol)}),
+            ]
+        })
+        .collect()
+}
+
+fn request(op: &str, args: Vec<Value>) -> Vec<Message> {
+    let request = json!({
+        "op": op,
+        "args": args
+    });
+    vec![Message::Text(request.to_string())]
+}
+
+pub fn handle(message: Message) -> Vec<Record> {
+    match message {
+        Message::Text(string) => {
+            if let Ok(push) = from_str::<Push>(&string) {
+                if let Some(records) = parse(&push) {
+                    return records;
+                }
+            } else if let Ok(event) = from_str::<Event>(&string) {
+                if event.event == "error" {
+                    return vec![Record::error(
+                        EXCHANGE,
+                        "error",
+                        Severity::Warning,
+                        event.msg,
+                        format!("code {}", event.code),
+                    )];
+                }
+            }
+            vec![Record::unhandled(string)]
+        }
+        _ => vec![Record::non_text()],
+    }
+}
+
+fn index_of(symbol: &str) -> &str {
+    symbol.strip_suffix("-SWAP").unwrap_or(symbol)
+}
+
+fn parse(push: &Push) -> Option<Vec<Record>> {
+    let channel = push.arg.channel.as_str();
+    let mut records = vec![];
+    for data in push.data.iter() {
+        match channel {
+            "funding-rate" => {
+                let funding = FundingRateData::deserialize(data).ok()?;
+                records.push(Record::data(
+                    EXCHANGE,
+                    channel,
+                    &funding.inst_id,
+                    VenueData::Funding(FundingRate {
+                        symbol: funding.inst_id.clone(),
+                        instrument: None,
+                        time: funding.ts,
+                        funding_rate: funding.funding_rate,
+                        next_funding_time: Some(funding.funding_time),
+                    }),
+                ));
+            }
+            "open-interest" => {
+                let open_interest = OpenInterestData::deserialize(data).ok()?;
+                records.push(Record::data(
+                    EXCHANGE,
+                    channel,
+                    &open_interest.inst_id,
+                    VenueData::OpenInterest(OpenInterest {
+                        symbol: open_interest.inst_id.clone(),
+                        instrument: None,
+                        time: open_interest.ts,
+                        open_interest: open_interest.oi,
+                    }),
+                ));
+            }
+            "index-tickers" => {
+                let index = IndexTickerData::deserialize(data).ok()?;
+                records.push(Record::data(
+                    EXCHANGE,
+                    channel,
+                    &index.inst_id,
+                    VenueData::Index(IndexPrice {
+                        symbol: index.inst_id.clone(),
+                        instrument: None,
+                        time: index.ts,
+                        index_price: index.idx_px,
+                    }),
+                ));
+            }
+            "liquidation-orders" => {
+                let liquidation = LiquidationData::deserialize(data).ok()?;
+                records.extend(liquidation.details.into_iter().map(|detail| {
+                    Record::data(
+                        EXCHANGE,
+                        channel,
+                        &liquidation.inst_id,
+                        VenueData::Liquidation(Liquidation {
+                            symbol: liquidation.inst_id.clone(),
+                            instrument: None,
+                            time: detail.ts,
+                            side: detail.side,
+                            price: detail.bk_px,
+                            size: detail.sz,
+                        }),
+                    )
+                }));
+            }
+            "candle1m" => {
+                let candle = parse_candle(&push.arg.inst_id, data)?;
+                records.push(Record::data(
+                    EXCHANGE,
+                    channel,
+                    &push.arg.inst_id,
+                    VenueData::Candle(candle),
+                ));
+            }
+            "opt-summary" => {
+                let summary = OptionSummary::deserialize(data).ok()?;
+                let symbol = summary.symbol.clone();
+                records.push(Record::data(
+                    EXCHANGE,
+                    channel,
+                    &symbol,
+                    VenueData::OkxOptionSummary(summary),
+                ));
+            }
+            _ => return None,
+        }
+    }
+    Some(records)
+}
+
+/// Candles are arrays of strings: start, open, high, low, close, then the volumes in contracts, base and quote
+/// currencies and the confirm flag.
+fn parse_candle(symbol: &str, data: &Value) -> Option<Candle> {
+    let fields = Vec::<String>::deserialize(data).ok()?;
+    let [start, open, high, low, close, _, volume, ..] = fields.as_slice() else {
+        return None;
+    };
+    Some(Candle {
+        symbol: symbol.to_owned(),
+        instrument: None,
+        start: start.parse().ok().and_then(DateTime::from_timestamp_millis)?,
+        open: open.parse().ok()?,
+        high: high.parse().ok()?,
+        low: low.parse().ok()?,
+        close: close.parse().ok()?,
+        volume: volume.parse().ok()?,
+        trade_count: None,
+        missing: false,
+    })
+}
+
+/// OKX encodes epoch milliseconds as strings.
+fn millis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    let string = String::deserialize(deserializer)?;
+    string
+        .parse::<i64>()
+        .ok()
+        .and_then(DateTime::from_timestamp_millis)
+        .ok_or_else(|| de::Error::custom(format!("invalid timestamp {string}")))
+}
+
+/// OKX encodes the values it has not computed yet as empty strings.
+fn optional<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Decimal>, D::Error> {
+    let string = String::deserialize(deserializer)?;
+    if string.is_empty() {
+        return Ok(None);
+    }
+    string.parse().map(Some).map_err(de::Error::custom)
+}
+
+#[derive(Deserialize, Debug)]
+struct Push {
+    arg: Arg,
+    data: Vec<Value>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Arg {
+    channel: String,
+    /// instrument of the channels subscribed by instrument, the candles payload not repeating it
+    #[serde(default)]
+    inst_id: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Event {
+    event: String,
+    #[serde(default)]
+    code: String,
+    #[serde(default)]
+    msg: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct FundingRateData {
+    inst_id: String,
+    funding_rate: Decimal,
+    #[serde(deserialize_with = "millis")]
+    funding_time: DateTime<Utc>,
+    #[serde(deserialize_with = "millis")]
+    ts: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct OpenInterestData {
+    inst_id: String,
+    oi: Decimal,
+    #[serde(deserialize_with = "millis")]
+    ts: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct IndexTickerData {
+    inst_id: String,
+    idx_px: Decimal,
+    #[serde(deserialize_with = "millis")]
+    ts: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LiquidationData {
+    inst_id: String,
+    details: Vec<LiquidationDetail>,
+}
+
+/// `sz` is expressed in contracts, `bkPx` is the bankruptcy price of the liquidated position.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LiquidationDetail {
+    side: String,
+    bk_px: Decimal,
+    sz: Decimal,
+    #[serde(deserialize_with = "millis")]
+    ts: DateTime<Utc>,
+}
+
+/// Greeks and volatilities of an option, `forward_price` is the forward price of its underlying at expiry.
+#[derive(Deserialize, Debug, Persist)]
+#[serde(rename_all = "camelCase")]
+pub struct OptionSummary {
+    #[serde(rename = "instId")]
+    #[persist(intern)]
+    pub symbol: String,
+    #[serde(rename = "uly")]
+    #[persist(intern)]
+    pub underlying: String,
+    #[serde(rename = "ts", deserialize_with = "millis")]
+    pub time: DateTime<Utc>,
+    #[serde(deserialize_with = "optional")]
+    pub delta: Option<Decimal>,
+    #[serde(deserialize_with = "optional")]
+    pub gamma: Option<Decimal>,
+    #[serde(deserialize_with = "optional")]
+    pub vega: Option<Decimal>,
+    #[serde(deserialize_with = "optional")]
+    pub theta: Option<Decimal>,
+    #[serde(rename = "deltaBS", deserialize_with = "optional")]
+    pub delta_bs: Option<Decimal>,
+    #[serde(rename = "gammaBS", deserialize_with = "optional")]
+    pub gamma_bs: Option<Decimal>,
+    #[serde(rename = "vegaBS", deserialize_with = "optional")]
+    pub vega_bs: Option<Decimal>,
+    #[serde(rename = "thetaBS", deserialize_with = "optional")]
+    pub theta_bs: Option<Decimal>,
+    #[serde(deserialize_with = "optional")]
+    pub mark_vol: Option<Decimal>,
+    #[serde(deserialize_with = "optional")]
+    pub bid_vol: Option<Decimal>,
+    #[serde(deserialize_with = "optional")]
+    pub ask_vol: Option<Decimal>,
+    #[serde(deserialize_with = "optional")]
+    pub real_vol: Option<Decimal>,
+    #[serde(rename = "fwdPx", deserialize_with = "optional")]
+    pub forward_price: Option<Decimal>,
+}
+
+pub fn reference_urls(rest_url: &str) -> Vec<String> {
+    vec![format!("{rest_url}/api/v5/public/instruments?instType=SWAP")]
+}
+
+pub fn instruments(body: &str, as_of: DateTime<Utc>) -> Result<Vec<Instrument>> {
+    let response = from_str::<InstrumentsResponse>(body)?;
+    Ok(response
+        .data
+        .into_iter()
+        .map(|data| Instrument {
+            symbol: data.inst_id,
+            instrument: None,
+            as_of,
+            tick_size: data.tick_sz,
+            lot_size: data.lot_sz,
+            status: data.state,
+            // empty for the swaps
+            expiry: data.exp_time.parse().ok().and_then(DateTime::from_timestamp_millis),
+        })
+        .collect())
+}
+
+#[derive(Deserialize, Debug)]
+struct InstrumentsResponse {
+    data: Vec<InstrumentData>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct InstrumentData {
+    inst_id: String,
+    #[serde(deserialize_with = "optional")]
+    tick_sz: Option<Decimal>,
+    #[serde(deserialize_with = "optional")]
+    lot_sz: Option<Decimal>,
+    state: String,
+    exp_time: String,
+}
@@ -0,0 +1,192 @@
+//! Contains Polygon.io-specific WebSocket handling logic, recording US equities.
+//!
+//! ## Features
+//! - Authenticates every connection with the API key (`--polygon-api-key` or the `POLYGON_API_KEY` variable) before
+//!   subscribing, on the real-time or the 15 minutes delayed stocks cluster (`--polygon-feed`).
+//! - Subscribes to the trades (`T.{ticker}`) and quotes (`Q.{ticker}`) of each ticker, upper cased as Polygon
+//!   expects them.
+//! - Parses the arrays of events of each message into the `stock_trades` and `stock_quotes` tables, the symbols
+//!   being mapped to their canonical instrument like the normalized tables of the crypto venues.
+//! - Reports a failed authentication as fatal, as retrying with the same key cannot succeed, and the other errors
+//!   (e.g. too many connections) as errors.
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use dixit_persist_macros::Persist;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{from_str, json, Value};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::model::{Record, Severity, SkipReason, VenueData};
+
+pub const EXCHANGE: &str = "polygon";
+pub const SYMBOLS: &[&str] = &["AAPL", "MSFT"];
+
+/// Variable holding the API key when `--polygon-api-key` is not given.
+pub const API_KEY_VARIABLE: &str = "POLYGON_API_KEY";
+
+/// Cluster serving the stock events.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Feed {
+    Realtime,
+    /// delayed by 15 minutes, for the plans without real-time data
+    Delayed,
+}
+
+impl Feed {
+    pub fn url(&self) -> &'static str {
+        match self {
+            Feed::Realtime => "wss://socket.polygon.io/stocks",
+            Feed::Delayed => "wss://delayed.polygon.io/stocks",
+        }
+    }
+}
+
+/// Authenticates the connection, sent before the subscriptions.
+pub fn auth(api_key: &str) -> Vec<Message> {
+    let request = json!({
+        "action": "auth",
+        "params": api_key
+    });
+    vec![Message::Text(request.to_string())]
+}
+
+pub fn subscribe(symbols: &[String]) -> Vec<Message> {
+    request("subscribe", symbols)
+}
+
+pub fn unsubscribe(symbols: &[String]) -> Vec<Message> {
+    request("unsubscribe", symbols)
+}
+
+fn request(action: &str, symbols: &[String]) -> Vec<Message> {
+    if symbols.is_empty() {
+        return vec![];
+    }
+    let params = symbols
+        .iter()
+        .map(|symbol| symbol.to_uppercase())
+        .flat_map(|ticker| [format!("T.{ticker}"), format!("Q.{ticker}")])
+        .collect::<Vec<_>>()
+        .join(",");
+    let request = json!({
+        "action": action,
+        "params": params
+    });
+    vec![Message::Text(request.to_string())]
+}
+
+pub fn handle(message: Message) -> Vec<Record> {
+    match message {
+        Message::Text(string) => match from_str::<Vec<Value>>(&string) {
+            // a malformed event is skipped without losing the others of the message
+            Ok(events) => events
+                .into_iter()
+                .map(|event| match Event::deserialize(&event) {
+                    Ok(event) => parse(event),
+                    Err(_) => Record::skip(SkipReason::Parse, event.to_string()),
+                })
+                .collect(),
+            Err(_) => vec![Record::unhandled(string)],
+        },
+        _ => vec![Record::non_text()],
+    }
+}
+
+fn parse(event: Event) -> Record {
+    match event {
+        Event::Trade(trade) => {
+            let symbol = trade.symbol.clone();
+            Record::data(EXCHANGE, "trades", &symbol, VenueData::StockTrade(trade))
+        }
+        Event::Quote(quote) => {
+            let symbol = quote.symbol.clone();
+            Record::data(EXCHANGE, "quotes", &symbol, VenueData::StockQuote(quote))
+        }
+        Event::Status(status) => {
+            let severity = match status.status.as_str() {
+                "auth_failed" => Severity::Fatal,
+                "error" | "max_connections" => Severity::Error,
+                _ => {
+                    return Record::skip(
+                        SkipReason::Unsubscribed,
+                        format!("{}: {}", status.status, status.message),
+                    )
+                }
+            };
+            Record::error(EXCHANGE, "status", severity, status.message, status.status)
+        }
+        Event::Other => Record::skip(SkipReason::Unsubscribed, "unknown event"),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "ev")]
+enum Event {
+    #[serde(rename = "T")]
+    Trade(StockTrade),
+    #[serde(rename = "Q")]
+    Quote(StockQuote),
+    #[serde(rename = "status")]
+    Status(Status),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug)]
+struct Status {
+    status: String,
+    message: String,
+}
+
+/// A trade of a ticker, `exchange` is the Polygon id of the exchange and `tape` the SIP tape (1 for NYSE, 2 for
+/// NYSE American and the regional exchanges, 3 for Nasdaq).
+#[derive(Deserialize, Debug, Persist)]
+pub struct StockTrade {
+    #[serde(rename = "sym")]
+    pub symbol: String,
+    #[serde(default)]
+    #[persist(intern)]
+    pub instrument: Option<String>,
+    #[serde(rename = "t", with = "chrono::serde::ts_milliseconds")]
+    pub time: DateTime<Utc>,
+    #[serde(rename = "i")]
+    pub trade_id: String,
+    #[serde(rename = "x")]
+    pub exchange: i32,
+    #[serde(rename = "z")]
+    pub tape: i32,
+    #[serde(rename = "p")]
+    pub price: Decimal,
+    #[serde(rename = "s")]
+    pub size: Decimal,
+    #[serde(rename = "q")]
+    pub sequence: u64,
+}
+
+/// The national best bid and offer of a ticker, with the exchange of each side.
+#[derive(Deserialize, Debug, Persist)]
+pub struct StockQuote {
+    #[serde(rename = "sym")]
+    pub symbol: String,
+    #[serde(default)]
+    #[persist(intern)]
+    pub instrument: Option<String>,
+    #[serde(rename = "t", with = "chrono::serde::ts_milliseconds")]
+    pub time: DateTime<Utc>,
+    #[serde(rename = "bx")]
+    pub bid_exchange: i32,
+    #[serde(rename = "bp")]
+    pub bid: Decimal,
+    #[serde(rename = "bs")]
+    pub bid_size: Decimal,
+    #[serde(rename = "ax")]
+    pub ask_exchange: i32,
+    #[serde(rename = "ap")]
+    pub ask: Decimal,
+    #[serde(rename = "as")]
+    pub ask_size: Decimal,
+    #[serde(rename = "q")]
+    pub sequence: u64,
+}
@@ -0,0 +1,118 @@
+//! Mirrors the parsed records to a message bus or a local stream as JSON, so live consumers (alerting, dashboards,
+//! `jq`) can tap the stream while the records are still persisted.
+//!
+//! ## Features
+//! - Publishes to Redis pub/sub channels (`redis` feature) or NATS subjects (`nats` feature), selected by the scheme
+//!   of the `--publish` URL (e.g. `redis://localhost:6379` or `nats://localhost:4222`).
+//! - Names channels and subjects `{venue}.{channel}.{symbol}`, with dots and spaces in the names replaced by
+//!   underscores.
+//! - Publishes on a bound ZeroMQ PUB socket (`zmq` feature, `--zmq-bind`), one `[topic, payload]` multipart message
+//!   per record, as JSON or msgpack.
+//! - Streams JSON lines to stdout or a listening unix socket (`--stream jsonl://stdout` or
+//!   `--stream jsonl:///tmp/dixit.sock`), each line being `{"subject": ..., "data": {...}}`.
+//! - Serializes the records as the JSON lines tables do, so published and recorded data have the same fields.
+
+use anyhow::{anyhow, Result};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::config::Venue;
+
+/// Encoding of the payload frames of the ZeroMQ publisher, msgpack maps are keyed in alphabetical order.
+#[cfg(feature = "zmq")]
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Encoding {
+    Json,
+    Msgpack,
+}
+
+pub enum Publisher {
+    #[cfg(feature = "redis")]
+    Redis(redis::aio::MultiplexedConnection),
+    #[cfg(feature = "nats")]
+    Nats(async_nats::Client),
+    #[cfg(feature = "zmq")]
+    Zmq(zeromq::PubSocket, Encoding),
+    Stream(Box<dyn AsyncWrite + Send + Unpin>),
+}
+
+impl Publisher {
+    pub async fn connect(url: &str) -> Result<Self> {
+        match url.split_once("://").map(|(scheme, _)| scheme) {
+            #[cfg(feature = "redis")]
+            Some("redis" | "rediss") => {
+                let client = redis::Client::open(url)?;
+                Ok(Publisher::Redis(client.get_multiplexed_async_connection().await?))
+            }
+            #[cfg(feature = "nats")]
+            Some("nats") => Ok(Publisher::Nats(async_nats::connect(url).await?)),
+            _ => Err(anyhow!(
+                "cannot publish to {url}, expected a redis:// (redis feature) or nats:// (nats feature) url"
+            )),
+        }
+    }
+
+    #[cfg(feature = "zmq")]
+    pub async fn bind_zmq(endpoint: &str, encoding: Encoding) -> Result<Self> {
+        use zeromq::Socket;
+
+        let mut socket = zeromq::PubSocket::new();
+        socket.bind(endpoint).await?;
+        Ok(Publisher::Zmq(socket, encoding))
+    }
+
+    pub async fn stream(url: &str) -> Result<Self> {
+        let writer: Box<dyn AsyncWrite + Send + Unpin> = match url.strip_prefix("jsonl://") {
+            Some("stdout") => Box::new(tokio::io::stdout()),
+            #[cfg(unix)]
+            Some(path) if path.starts_with('/') => Box::new(tokio::net::UnixStream::connect(path).await?),
+            #[cfg(not(unix))]
+            Some(path) if path.starts_with('/') => {
+                anyhow::bail!("cannot stream to {url}, unix sockets are only supported on unix")
+            }
+            _ => {
+                return Err(anyhow!(
+                    "cannot stream to {url}, expected jsonl://stdout or jsonl:///path/to/unix.sock"
+                ))
+            }
+        };
+        Ok(Publisher::Stream(writer))
+    }
+
+    pub async fn publish(&mut self, subject: String, payload: Vec<u8>) -> Result<()> {
+        match *self {
+            #[cfg(feature = "redis")]
+            Publisher::Redis(ref mut connection) => {
+                Ok(redis::AsyncCommands::publish(connection, subject, payload).await?)
+            }
+            #[cfg(feature = "nats")]
+            Publisher::Nats(ref client) => Ok(client.publish(subject, payload.into()).await?),
+            #[cfg(feature = "zmq")]
+            Publisher::Zmq(ref mut socket, encoding) => {
+                use zeromq::SocketSend;
+
+                let payload = match encoding {
+                    Encoding::Json => payload,
+                    Encoding::Msgpack => {
+                        rmp_serde::to_vec_named(&serde_json::from_slice::<serde_json::Value>(&payload)?)?
+                    }
+                };
+                let mut message = zeromq::ZmqMessage::from(subject);
+                message.push_back(payload.into());
+                Ok(socket.send(message).await?)
+            }
+            Publisher::Stream(ref mut writer) => {
+                let mut line = format!(r#"{{"subject":{},"data":"#, serde_json::to_string(&subject)?).into_bytes();
+                line.extend(payload);
+                line.extend(b"}\n");
+                writer.write_all(&line).await?;
+                Ok(writer.flush().await?)
+            }
+        }
+    }
+}
+
+pub fn subject(venue: Venue, channel: &str, symbol: &str) -> String {
+    [venue.to_string().as_str(), channel, symbol]
+        .map(|name| name.replace(['.', ' '], "_"))
+        .join(".")
+}
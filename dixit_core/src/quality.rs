@@ -0,0 +1,385 @@
+//! Monitors the data quality of the captures, persisting the statistics of the columns of every flush to the `_stats`
+//! table when recording with `--column-stats`, and validating the records against the rules of `--validate`.
+//!
+//! ## Features
+//! - One row per column of every flush of the venue tables: the rows of the flush, the null count and rate, the
+//!   estimated distinct values and the bounds of the numeric and timestamp columns.
+//! - The statistics are computed by the persist service on every flush, whatever triggered it (full buffer, flush
+//!   on demand, rotation or shutdown), see `dixit_persist::stats`.
+//! - The rules compare a column to a number (e.g. `trades=price>0`) or to another column (e.g. `ticker=bid<=ask`),
+//!   or bound the distance of a timestamp column to the local clock (e.g. `trades=time~5s`), `*` applying a rule to
+//!   every venue table.
+//! - Every record is validated before being persisted, a rule whose column is missing or null does not apply.
+//! - The violations are counted by rule, optionally persisted to the `_violations` table and optionally rejected,
+//!   the rejected records are not persisted.
+//! - The recorder tables (e.g. `_events`, `_stats`) are neither measured nor validated.
+//!
+//! ## Example
+//! ```
+//! use dixit_core::quality::Rule;
+//!
+//! let rule: Rule = "ticker=bid<=ask".parse().unwrap();
+//! assert_eq!(rule.to_string(), "ticker=bid<=ask");
+//! assert!("ticker=bid".parse::<Rule>().is_err());
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use dixit_persist::stats::FlushStats;
+use dixit_persist_macros::Persist;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tracing::{info, warn};
+
+use crate::bars::Interval;
+use crate::model::VenueData;
+
+#[derive(Debug, Persist)]
+pub struct ColumnStat {
+    /// local time of the flush
+    pub time: DateTime<Utc>,
+    pub table: String,
+    /// rows of the flush
+    pub rows: u64,
+    pub column: String,
+    pub nulls: u64,
+    pub null_rate: f64,
+    /// estimated, nulls excluded
+    pub distinct: u64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub min_time: Option<DateTime<Utc>>,
+    pub max_time: Option<DateTime<Utc>>,
+}
+
+/// Whether the flushes of the table are measured, the recorder tables are not.
+pub fn is_measured(table: &str) -> bool {
+    !table.rsplit('/').next().unwrap_or(table).starts_with('_')
+}
+
+/// The rows of the statistics of a flush of `table` reported at `time`, one per column.
+pub fn column_stats(table: &str, stats: FlushStats, time: DateTime<Utc>) -> Vec<ColumnStat> {
+    let rows = stats.rows;
+    stats
+        .columns
+        .into_iter()
+        .map(|column| ColumnStat {
+            time,
+            table: table.to_owned(),
+            rows: rows as u64,
+            null_rate: column.null_rate(rows),
+            column: column.column,
+            nulls: column.nulls as u64,
+            distinct: column.distinct,
+            min: column.min,
+            max: column.max,
+            min_time: column.min_time,
+            max_time: column.max_time,
+        })
+        .collect()
+}
+
+/// A record of a venue table violating a rule.
+#[derive(Debug, Persist)]
+pub struct Violation {
+    /// local time of the validation
+    pub time: DateTime<Utc>,
+    pub table: String,
+    pub symbol: String,
+    pub rule: String,
+    /// value of the column of the rule, as in the JSON lines tables
+    pub value: Option<String>,
+    pub rejected: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Operator {
+    /// Operators by symbol, the two character ones first.
+    const SYMBOLS: [(&'static str, Operator); 6] = [
+        ("<=", Operator::Le),
+        (">=", Operator::Ge),
+        ("!=", Operator::Ne),
+        ("<", Operator::Lt),
+        (">", Operator::Gt),
+        ("=", Operator::Eq),
+    ];
+
+    fn holds(&self, left: f64, right: f64) -> bool {
+        match self {
+            Operator::Lt => left < right,
+            Operator::Le => left <= right,
+            Operator::Gt => left > right,
+            Operator::Ge => left >= right,
+            Operator::Eq => left == right,
+            Operator::Ne => left != right,
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (symbol, _) = Self::SYMBOLS
+            .iter()
+            .find(|(_, operator)| operator == self)
+            .expect("every operator has a symbol");
+        write!(f, "{symbol}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Number(f64),
+    Column(String),
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Number(number) => write!(f, "{number}"),
+            Operand::Column(column) => write!(f, "{column}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Check {
+    /// `column<op>operand`, e.g. `price>0` or `bid<=ask`
+    Compare {
+        column: String,
+        operator: Operator,
+        operand: Operand,
+    },
+    /// `column~interval`, the timestamp of the column within the interval of the local clock, e.g. `time~5s`
+    Fresh { column: String, within: Interval },
+}
+
+impl Check {
+    fn column(&self) -> &str {
+        match self {
+            Check::Compare { column, .. } | Check::Fresh { column, .. } => column,
+        }
+    }
+
+    /// Whether the record holds, `None` if the rule does not apply to it.
+    fn holds(&self, record: &Map<String, Value>, now: DateTime<Utc>) -> Option<bool> {
+        match self {
+            Check::Compare {
+                column,
+                operator,
+                operand,
+            } => {
+                let left = number(record.get(column)?)?;
+                let right = match operand {
+                    Operand::Number(number) => *number,
+                    Operand::Column(column) => number(record.get(column)?)?,
+                };
+                Some(operator.holds(left, right))
+            }
+            Check::Fresh { column, within } => {
+                let time = match record.get(column)? {
+                    Value::String(time) => DateTime::parse_from_rfc3339(time).ok()?.to_utc(),
+                    _ => return None,
+                };
+                let distance = (now - time).abs().to_std().ok()?;
+                Some(distance <= within.duration())
+            }
+        }
+    }
+}
+
+impl FromStr for Check {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("invalid check {s}, expected column<op>value, column<op>column or column~interval");
+        let start = s.find(['<', '>', '=', '!', '~']).ok_or_else(error)?;
+        let column = s[..start].trim();
+        let rest = &s[start..];
+        if column.is_empty() {
+            return Err(error());
+        }
+        if let Some(within) = rest.strip_prefix('~') {
+            return Ok(Check::Fresh {
+                column: column.to_owned(),
+                within: within.trim().parse()?,
+            });
+        }
+        let (symbol, operator) = Operator::SYMBOLS
+            .iter()
+            .find(|(symbol, _)| rest.starts_with(symbol))
+            .ok_or_else(error)?;
+        let operand = rest[symbol.len()..].trim();
+        let operand = match operand.parse::<f64>() {
+            Ok(number) => Operand::Number(number),
+            Err(_) if !operand.is_empty() && operand.chars().all(|c| c.is_alphanumeric() || c == '_') => {
+                Operand::Column(operand.to_owned())
+            }
+            Err(_) => return Err(error()),
+        };
+        Ok(Check::Compare {
+            column: column.to_owned(),
+            operator: *operator,
+            operand,
+        })
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Check::Compare {
+                column,
+                operator,
+                operand,
+            } => write!(f, "{column}{operator}{operand}"),
+            Check::Fresh { column, within } => write!(f, "{column}~{within}"),
+        }
+    }
+}
+
+/// A check of the records of a table, or of every venue table with `*`, as `table=check`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Rule {
+    pub table: String,
+    pub check: Check,
+}
+
+impl Rule {
+    fn applies(&self, table: &str) -> bool {
+        self.table == "*" || self.table == table
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((table, check)) if !table.is_empty() => Ok(Rule {
+                table: table.to_owned(),
+                check: check.parse()?,
+            }),
+            _ => Err(format!("expected table=check, got {s}")),
+        }
+    }
+}
+
+impl TryFrom<String> for Rule {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.table, self.check)
+    }
+}
+
+/// What is done with the records violating a rule, besides counting them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ViolationPolicy {
+    /// persist the violations to the `_violations` table
+    pub persist: bool,
+    /// drop the violating records instead of persisting them
+    pub reject: bool,
+}
+
+/// Validates the records of the venue tables against the rules, counting the violations of each rule.
+#[derive(Default)]
+pub struct Validator {
+    rules: Vec<Rule>,
+    policy: ViolationPolicy,
+    /// violations by rule, in the order of the rules
+    counts: Vec<u64>,
+}
+
+impl Validator {
+    pub fn new(rules: Vec<Rule>, policy: ViolationPolicy) -> Self {
+        Self {
+            counts: vec![0; rules.len()],
+            rules,
+            policy,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.rules.is_empty()
+    }
+
+    /// Validates a record of `symbol` at `now`, returns whether it must be persisted with the violations to persist.
+    pub fn validate(&mut self, symbol: &str, data: &VenueData, now: DateTime<Utc>) -> (bool, Vec<Violation>) {
+        let table = data.table();
+        if !is_measured(table) || !self.rules.iter().any(|rule| rule.applies(table)) {
+            return (true, vec![]);
+        }
+        let record = match data.to_json().map(|json| serde_json::from_slice::<Value>(&json)) {
+            Ok(Ok(Value::Object(record))) => record,
+            _ => return (true, vec![]),
+        };
+        let mut violations = vec![];
+        for (rule, count) in self.rules.iter().zip(self.counts.iter_mut()) {
+            if !rule.applies(table) || rule.check.holds(&record, now) != Some(false) {
+                continue;
+            }
+            *count += 1;
+            if *count == 1 {
+                warn!("[{table}] [{symbol}] first violation of {rule}");
+            }
+            violations.push(Violation {
+                time: now,
+                table: table.to_owned(),
+                symbol: symbol.to_owned(),
+                rule: rule.to_string(),
+                value: record.get(rule.check.column()).map(|value| match value {
+                    Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                }),
+                rejected: self.policy.reject,
+            });
+        }
+        let keep = violations.is_empty() || !self.policy.reject;
+        if !self.policy.persist {
+            violations.clear();
+        }
+        (keep, violations)
+    }
+
+    /// Violations counted by rule.
+    pub fn counts(&self) -> impl Iterator<Item = (&Rule, u64)> {
+        self.rules.iter().zip(self.counts.iter().copied())
+    }
+
+    /// Logs the violations counted by rule.
+    pub fn report(&self) {
+        for (rule, count) in self.counts() {
+            if count > 0 {
+                warn!("{count} violations of {rule}");
+            } else {
+                info!("no violation of {rule}");
+            }
+        }
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(number) => number.parse().ok(),
+        _ => None,
+    }
+}
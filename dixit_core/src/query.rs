@@ -0,0 +1,95 @@
+//! Runs SQL over the recorded tables for quick ad-hoc checks, using the embedded `polars` SQL engine.
+//!
+//! ## Features
+//! - Registers every table folder (`<venue>/<table>`) of the output directory as a view named `<venue>_<table>`,
+//!   with dashes replaced by underscores (e.g. `binance_futures_funding`).
+//! - Reads the Parquet files of the subfolders of a table too, so the tables routed by date (e.g.
+//!   `{venue}/{channel}/{date}`) are queried as a single view, the table metadata folders (e.g. `_delta_log`) being
+//!   skipped.
+//! - Scans the Parquet files lazily, so only the columns needed by the query are read, skipping the files not
+//!   finalized yet of the tables written with `--finalize-markers`.
+//! - Prints the result as a table, the number of rows shown can be raised with `POLARS_FMT_MAX_ROWS`.
+//!
+//! ## Example
+//! ```sh
+//! dixit query "SELECT symbol, count(*) FROM bitfinex_trades GROUP BY symbol"
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use dixit_persist::finalize;
+use polars::prelude::{DataFrame, LazyFrame, ScanArgsParquet};
+use polars::sql::SQLContext;
+use tracing::info;
+
+pub fn run(directory: &Path, sql: &str) -> Result<()> {
+    let result = execute(directory, sql)?;
+    println!("{result}");
+    Ok(())
+}
+
+/// Runs the query over the tables of `directory`, returning its result.
+pub fn execute(directory: &Path, sql: &str) -> Result<DataFrame> {
+    let mut context = SQLContext::new();
+    for (name, files) in tables(directory)? {
+        info!("registering {name} with {} files", files.len());
+        context.register(&name, LazyFrame::scan_parquet_files(files, ScanArgsParquet::default())?);
+    }
+    Ok(context.execute(sql)?.collect()?)
+}
+
+/// Returns the view name and the Parquet files of every table with at least one file.
+fn tables(directory: &Path) -> Result<Vec<(String, Arc<[PathBuf]>)>> {
+    let venues = fs::read_dir(directory).map_err(|e| anyhow!("cannot read {directory:?}: {e}"))?;
+    let mut tables = vec![];
+    for venue in venues
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+    {
+        for table in fs::read_dir(venue.path())?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+        {
+            let mut files = vec![];
+            parquet_files(&table.path(), &mut files)?;
+            if files.is_empty() {
+                continue;
+            }
+            let name = format!(
+                "{}_{}",
+                venue.file_name().to_string_lossy(),
+                table.file_name().to_string_lossy()
+            )
+            .replace('-', "_");
+            tables.push((name, files.into()));
+        }
+    }
+    Ok(tables)
+}
+
+/// Adds the finalized Parquet files of `directory` and of its subfolders to `files`, skipping the metadata folders
+/// (e.g. `_delta_log`, holding the Parquet checkpoints of the log).
+fn parquet_files(directory: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut own = vec![];
+    let mut subfolders = vec![];
+    for path in fs::read_dir(directory)?.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        let metadata = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with(['_', '.']));
+        if path.is_dir() && !metadata {
+            subfolders.push(path);
+        } else if path.extension().is_some_and(|extension| extension == "parquet") {
+            own.push(path);
+        }
+    }
+    own.sort();
+    files.extend(finalize::finalized(directory, own));
+    subfolders.sort();
+    for subfolder in subfolders {
+        parquet_files(&subfolder, files)?;
+    }
+    Ok(())
+}
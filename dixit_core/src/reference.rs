@@ -0,0 +1,88 @@
+//! Pulls the reference data of the venue instruments (tick size, lot size, status and expiry) through the REST API of
+//! the venue, so the recorded prices and sizes can be interpreted with the right precision.
+//!
+//! ## Features
+//! - Pulls on startup then every `--reference-interval`, persisting a snapshot of every instrument listed by the venue
+//!   to the `reference` table, with the time of the pull (`as_of`).
+//! - Each venue parses its own responses into the normalized [`Instrument`] (e.g. `okx::instruments`).
+//! - A failed pull is logged and retried on the next tick, the previous snapshot still holds meanwhile.
+//!
+//! Disabled with `--no-reference`. Bitfinex publishes neither tick nor lot sizes (its prices have 5 significant
+//! digits) and the sequenced sources have no REST API, so their reference data is not pulled.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dixit_persist_macros::Persist;
+use rust_decimal::Decimal;
+use tokio::sync::mpsc::Sender;
+use tokio::time;
+use tracing::{info, warn};
+
+use crate::clock::SharedClock;
+use crate::model::{Record, VenueData};
+
+/// Parses a response of the venue, pulled at `as_of`.
+pub type Parse = fn(&str, DateTime<Utc>) -> Result<Vec<Instrument>>;
+
+/// `tick_size` is the price increment and `lot_size` the size increment, `expiry` is not set for perpetuals.
+#[derive(Debug, Persist)]
+pub struct Instrument {
+    pub symbol: String,
+    #[persist(intern)]
+    pub instrument: Option<String>,
+    pub as_of: DateTime<Utc>,
+    pub tick_size: Option<Decimal>,
+    pub lot_size: Option<Decimal>,
+    #[persist(intern)]
+    pub status: String,
+    pub expiry: Option<DateTime<Utc>>,
+}
+
+/// Pulls the urls every `interval`, the first time right away, until the channel is closed, the snapshots dated by
+/// `clock`.
+pub async fn run(
+    tx: Sender<Record>,
+    exchange: String,
+    urls: Vec<String>,
+    interval: Duration,
+    parse: Parse,
+    clock: SharedClock,
+) -> Result<()> {
+    let client = reqwest::Client::new();
+    let mut timer = time::interval(interval);
+
+    loop {
+        timer.tick().await;
+        for url in urls.iter() {
+            let as_of = clock.now();
+            let instruments = match pull(&client, url, as_of, parse).await {
+                Ok(instruments) => instruments,
+                Err(e) => {
+                    warn!("failed to pull the reference data {url}: {e}");
+                    continue;
+                }
+            };
+            info!(
+                "pulled the reference data of {} instruments from {url}",
+                instruments.len()
+            );
+            for instrument in instruments {
+                let symbol = instrument.symbol.clone();
+                tx.send(Record::data(
+                    &exchange,
+                    "reference",
+                    &symbol,
+                    VenueData::Reference(instrument),
+                ))
+                .await?;
+            }
+        }
+    }
+}
+
+async fn pull(client: &reqwest::Client, url: &str, as_of: DateTime<Utc>, parse: Parse) -> Result<Vec<Instrument>> {
+    let response = client.get(url).send().await?.error_for_status()?;
+    parse(&response.text().await?, as_of)
+}
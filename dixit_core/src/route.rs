@@ -0,0 +1,217 @@
+//! Names the table each record is persisted to, so the output layout can match an existing data lake.
+//!
+//! ## Features
+//! - A [`TableRoute`] is a template of `/` separated segments (each segment being a folder) with the `{venue}`,
+//!   `{channel}` (the table of the data, e.g. `trades`) and `{date}` (the UTC date of the event, e.g. `2024-03-01`,
+//!   or of the write for the data without event time) placeholders, `{venue}/{channel}` by default.
+//! - Set with `--table-route` or `table_route` in the config file, e.g. `{venue}_{channel}_{date}`, only read on
+//!   startup.
+//! - The tables of the recorder (e.g. `_events`) stay in the venue directory, beside its manifest and watermark.
+//! - With `{date}`, the [`Router`] completes the tables of a day once its lateness window (`--lateness-window`) has
+//!   passed after midnight. The records of that day arriving later are late, and by the [`LatePolicy`]
+//!   (`--late-data`) either reopen the table of their date, or go to the `{channel}_late` table of the current date.
+//! - A reopened table is completed again a lateness window after its first late record, so the late records of the
+//!   window are added to it as a single file.
+//!
+//! ## Example
+//! ```yaml
+//! table_route: "{venue}_{channel}_{date}"
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use clap::ValueEnum;
+use serde::Deserialize;
+use tracing::info;
+
+const VENUE: &str = "{venue}";
+const CHANNEL: &str = "{channel}";
+const DATE: &str = "{date}";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TableRoute(String);
+
+impl Default for TableRoute {
+    fn default() -> Self {
+        Self(format!("{VENUE}/{CHANNEL}"))
+    }
+}
+
+impl TableRoute {
+    /// Whether the tables are partitioned by date.
+    pub fn is_dated(&self) -> bool {
+        self.0.contains(DATE)
+    }
+
+    /// Table of the data of a channel, the tables of the recorder (starting with `_`) being kept in the venue
+    /// directory.
+    pub fn table(&self, venue: &str, channel: &str, date: NaiveDate) -> String {
+        if channel.starts_with('_') {
+            return format!("{venue}/{channel}");
+        }
+        self.render(venue, channel)
+            .replace(DATE, &date.format("%Y-%m-%d").to_string())
+    }
+
+    /// Tables of a channel whatever their date, `{date}` being replaced by a `*` wildcard.
+    pub fn pattern(&self, venue: &str, channel: &str) -> String {
+        if channel.starts_with('_') {
+            return format!("{venue}/{channel}");
+        }
+        self.render(venue, channel).replace(DATE, "*")
+    }
+
+    fn render(&self, venue: &str, channel: &str) -> String {
+        self.0.replace(VENUE, venue).replace(CHANNEL, channel)
+    }
+}
+
+impl FromStr for TableRoute {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.contains(CHANNEL) {
+            return Err(format!(
+                "invalid table route {s}, {CHANNEL} is required to tell the tables apart"
+            ));
+        }
+        let unknown = [VENUE, CHANNEL, DATE]
+            .iter()
+            .fold(s.to_owned(), |rest, placeholder| rest.replace(placeholder, ""));
+        if unknown.contains(['{', '}']) {
+            return Err(format!(
+                "invalid table route {s}, expected the {VENUE}, {CHANNEL} and {DATE} placeholders only"
+            ));
+        }
+        let segments = s.split('/').collect::<Vec<_>>();
+        if segments
+            .iter()
+            .any(|segment| segment.is_empty() || *segment == "." || *segment == "..")
+        {
+            return Err(format!(
+                "invalid table route {s}, expected a relative path without empty, . or .. segments"
+            ));
+        }
+        Ok(Self(s.to_owned()))
+    }
+}
+
+impl TryFrom<String> for TableRoute {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for TableRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Where the records of a day arriving after its lateness window go.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum LatePolicy {
+    /// to the table of their date, reopened
+    #[default]
+    Reopen,
+    /// to the `{channel}_late` table of the current date
+    LateTable,
+}
+
+/// Names the tables of the records and tracks the dated tables to complete.
+pub struct Router {
+    route: TableRoute,
+    policy: LatePolicy,
+    window: TimeDelta,
+    /// dated tables written, by date
+    open: BTreeMap<NaiveDate, BTreeSet<String>>,
+    /// tables reopened by late records, by the time of their first late record
+    reopened: BTreeMap<String, DateTime<Utc>>,
+    /// late records, logged when their tables are completed
+    late: u64,
+}
+
+impl Router {
+    pub fn new(route: TableRoute, policy: LatePolicy, window: TimeDelta) -> Self {
+        Self {
+            route,
+            policy,
+            window,
+            open: BTreeMap::new(),
+            reopened: BTreeMap::new(),
+            late: 0,
+        }
+    }
+
+    pub fn is_dated(&self) -> bool {
+        self.route.is_dated()
+    }
+
+    /// Table of the data of a channel with its event time if any, `now` dating the data without event time.
+    pub fn table(&mut self, venue: &str, channel: &str, time: Option<DateTime<Utc>>, now: DateTime<Utc>) -> String {
+        let today = now.date_naive();
+        if !self.is_dated() || channel.starts_with('_') {
+            return self.route.table(venue, channel, today);
+        }
+        let date = time.map_or(today, |time| time.date_naive());
+        let (channel, date) = if self.is_late(date, now) {
+            self.late += 1;
+            match self.policy {
+                LatePolicy::Reopen => {
+                    let table = self.route.table(venue, channel, date);
+                    self.reopened.entry(table.clone()).or_insert(now);
+                    return table;
+                }
+                LatePolicy::LateTable => (format!("{channel}_late"), today),
+            }
+        } else {
+            (channel.to_owned(), date)
+        };
+        let table = self.route.table(venue, &channel, date);
+        self.open.entry(date).or_default().insert(table.clone());
+        table
+    }
+
+    /// Removes the tables of the days whose lateness window has passed and the tables reopened a lateness window
+    /// ago, to be completed.
+    pub fn expired(&mut self, now: DateTime<Utc>) -> Vec<String> {
+        let expired = self
+            .open
+            .keys()
+            .copied()
+            .take_while(|date| self.is_late(*date, now))
+            .collect::<Vec<_>>();
+        let mut tables = expired
+            .iter()
+            .filter_map(|date| self.open.remove(date))
+            .flatten()
+            .collect::<Vec<_>>();
+        let reopened = self
+            .reopened
+            .iter()
+            .filter(|(_, since)| now > **since + self.window)
+            .map(|(table, _)| table.clone())
+            .collect::<Vec<_>>();
+        for table in reopened {
+            self.reopened.remove(&table);
+            tables.push(table);
+        }
+        if !tables.is_empty() && self.late > 0 {
+            info!("{} late records since the last completed day", self.late);
+            self.late = 0;
+        }
+        tables
+    }
+
+    /// Whether the lateness window of the date has passed.
+    fn is_late(&self, date: NaiveDate, now: DateTime<Utc>) -> bool {
+        let end = date.succ_opt().and_then(|next| next.and_hms_opt(0, 0, 0));
+        end.is_some_and(|end| now.naive_utc() > end + self.window)
+    }
+}
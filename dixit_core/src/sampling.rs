@@ -0,0 +1,110 @@
+//! Bounds the number of records persisted per table and symbol, e.g. to record a full depth feed at a lower rate
+//! while keeping the trades at full fidelity.
+//!
+//! ## Features
+//! - `N` keeps every Nth record of a symbol (e.g. `ticker=10`), starting with the first one.
+//! - `N/s` keeps at most N records of a symbol per second (e.g. `ticker=5/s`), the others are dropped.
+//! - Tables without a sampling rule are persisted in full, sampling does not apply to the published records.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Sampling {
+    Every(u32),
+    PerSecond(u32),
+}
+
+impl FromStr for Sampling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (count, sampling): (_, fn(u32) -> Sampling) = match s.strip_suffix("/s") {
+            Some(count) => (count, Sampling::PerSecond),
+            None => (s, Sampling::Every),
+        };
+        match count.parse::<u32>() {
+            Ok(count) if count > 0 => Ok(sampling(count)),
+            _ => Err(format!(
+                "invalid sampling {s}, expected N (every Nth record) or N/s (N records per second)"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Sampling {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Sampling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sampling::Every(count) => write!(f, "{count}"),
+            Sampling::PerSecond(count) => write!(f, "{count}/s"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Window {
+    /// records seen since the last kept one
+    seen: u64,
+    start: Option<Instant>,
+    kept: u32,
+}
+
+#[derive(Default)]
+pub struct Sampler {
+    rules: HashMap<String, Sampling>,
+    /// windows by table and symbol
+    windows: HashMap<&'static str, HashMap<String, Window>>,
+}
+
+impl Sampler {
+    pub fn new(rules: impl IntoIterator<Item = (String, Sampling)>) -> Self {
+        Self {
+            rules: rules.into_iter().collect(),
+            windows: HashMap::new(),
+        }
+    }
+
+    /// Returns true if the record of `symbol` must be persisted to `table`.
+    pub fn keep(&mut self, table: &'static str, symbol: &str) -> bool {
+        let Some(sampling) = self.rules.get(table) else {
+            return true;
+        };
+        let windows = self.windows.entry(table).or_default();
+        if !windows.contains_key(symbol) {
+            windows.insert(symbol.to_owned(), Window::default());
+        }
+        let window = windows.get_mut(symbol).expect("window just inserted");
+        match *sampling {
+            Sampling::Every(count) => {
+                let keep = window.seen == 0;
+                window.seen = (window.seen + 1) % count as u64;
+                keep
+            }
+            Sampling::PerSecond(count) => {
+                let now = Instant::now();
+                match window.start {
+                    Some(start) if now.duration_since(start) < Duration::from_secs(1) => (),
+                    _ => {
+                        window.start = Some(now);
+                        window.kept = 0;
+                    }
+                }
+                window.kept += 1;
+                window.kept <= count
+            }
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//! Loads the recorder settings from a YAML file (`--config`) and reloads them while recording.
+//!
+//! ## Features
+//! - Overrides the command line arguments with the settings of the file on startup.
+//! - Watches the file and applies the safe changes at runtime: the symbols (subscribed and unsubscribed as with the
+//!   control socket), the rotation interval and the sampling rules. The folder of the file is watched, so a file
+//!   replaced by a rename (e.g. by an editor or a config management tool) is reloaded too.
+//! - Adds the `headers` to the websocket upgrade requests, after the `--ws-header` ones, so API keys can be kept out
+//!   of the command line.
+//! - Rejects the changes requiring a restart (the output directory, the table route and the headers), keeping the
+//!   running value.
+//! - Logs every change applied or rejected, an invalid file is logged and ignored until fixed.
+//!
+//! ## Example
+//! ```yaml
+//! symbols: [BTC-USDT-SWAP, SOL-USDT-SWAP]
+//! rotation_interval: 1h
+//! sample:
+//!   ticker: 5/s
+//! directory: output
+//! table_route: "{venue}_{channel}_{date}"
+//! headers:
+//!   Origin: https://example.com
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use dixit_persist::service::{PersistCmd, ServiceOptions};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Deserialize;
+use tokio::sync::mpsc::{self, Sender, UnboundedSender, WeakSender};
+use tokio::sync::watch;
+use tokio::time;
+use tracing::{info, warn};
+
+use crate::bars::Interval;
+use crate::config::{Args, Venue};
+use crate::control::{self, ControlCmd};
+use crate::route::TableRoute;
+use crate::sampling::Sampling;
+
+/// time for the events of a single write (e.g. truncate then write) to settle before reloading
+const SETTLE_DELAY: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// symbols to record, the venue defaults if empty
+    pub symbols: Vec<String>,
+    pub rotation_interval: Option<Interval>,
+    /// sampling rule by table
+    pub sample: BTreeMap<String, Sampling>,
+    /// folder of the recorded tables, only read on startup
+    pub directory: Option<PathBuf>,
+    /// names of the recorded tables, only read on startup
+    pub table_route: Option<TableRoute>,
+    /// extra headers of the websocket upgrade requests, only read on startup
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Where the changes of the settings are applied.
+pub struct Targets {
+    pub venue: Venue,
+    pub control: Sender<ControlCmd>,
+    pub persist: WeakSender<PersistCmd>,
+    /// options of the persist service, the rotation interval replaced on changes
+    pub options: ServiceOptions,
+    pub sampling: watch::Sender<Vec<(String, Sampling)>>,
+}
+
+impl Settings {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).with_context(|| format!("cannot read {}", path.display()))?;
+        serde_yaml::from_str(&content).with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    /// Overrides the arguments with the settings given in the file.
+    pub fn apply(&self, args: &mut Args) {
+        if !self.symbols.is_empty() {
+            args.symbols = self.symbols.clone();
+        }
+        if self.rotation_interval.is_some() {
+            args.rotation_interval = self.rotation_interval;
+        }
+        if !self.sample.is_empty() {
+            args.sample = self.sample.clone().into_iter().collect();
+        }
+        if let Some(directory) = &self.directory {
+            args.directory = directory.clone();
+        }
+        if let Some(table_route) = &self.table_route {
+            args.table_route = table_route.clone();
+        }
+        args.ws_header.extend(self.headers.clone());
+    }
+}
+
+/// Reloads the settings whenever the file is written or replaced, until aborted.
+pub async fn run(path: PathBuf, mut settings: Settings, mut targets: Targets) {
+    let (changes_tx, mut changes) = mpsc::unbounded_channel();
+    let _watcher = match watch(&path, changes_tx) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            warn!("config not watched, its changes are not reloaded: {e:#}");
+            return;
+        }
+    };
+    while changes.recv().await.is_some() {
+        time::sleep(SETTLE_DELAY).await;
+        while changes.try_recv().is_ok() {}
+        match Settings::load(&path) {
+            Ok(reloaded) => settings = reload(settings, reloaded, &mut targets).await,
+            Err(e) => warn!("config not reloaded: {e:#}"),
+        }
+    }
+}
+
+/// Watches the folder of the file rather than the file itself, so the file is still watched once replaced.
+fn watch(path: &Path, changes: UnboundedSender<()>) -> Result<RecommendedWatcher> {
+    let name = path
+        .file_name()
+        .with_context(|| format!("invalid config path {}", path.display()))?
+        .to_owned();
+    let folder = match path.parent() {
+        Some(folder) if !folder.as_os_str().is_empty() => folder,
+        _ => Path::new("."),
+    };
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+        Ok(event) => {
+            let written = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_));
+            let name = Some(name.as_os_str());
+            if written && event.paths.iter().any(|path| path.file_name() == name) {
+                let _ = changes.send(());
+            }
+        }
+        Err(e) => warn!("config watch error: {e}"),
+    })?;
+    watcher.watch(folder, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
+
+/// Applies the changes from `settings` to `reloaded`, returns the settings in effect.
+async fn reload(settings: Settings, mut reloaded: Settings, targets: &mut Targets) -> Settings {
+    if reloaded == settings {
+        return settings;
+    }
+    if reloaded.directory != settings.directory {
+        warn!(
+            "config directory changed from {:?} to {:?}, not applied as it requires a restart",
+            settings.directory, reloaded.directory
+        );
+        reloaded.directory = settings.directory.clone();
+    }
+    if reloaded.table_route != settings.table_route {
+        warn!(
+            "config table_route changed from {} to {}, not applied as it requires a restart",
+            or_default(&settings.table_route),
+            or_default(&reloaded.table_route)
+        );
+        reloaded.table_route = settings.table_route.clone();
+    }
+    if reloaded.headers != settings.headers {
+        warn!("config headers changed, not applied as they require a restart");
+        reloaded.headers = settings.headers.clone();
+    }
+
+    if reloaded.symbols != settings.symbols {
+        if let Err(e) = resubscribe(&reloaded, targets).await {
+            warn!("config symbols not applied: {e}");
+            reloaded.symbols = settings.symbols.clone();
+        }
+    }
+
+    if reloaded.rotation_interval != settings.rotation_interval {
+        targets.options.rotation_interval = reloaded.rotation_interval.as_ref().map(Interval::duration);
+        let applied = match targets.persist.upgrade() {
+            Some(persist) => persist.send(PersistCmd::SetOptions(targets.options)).await.is_ok(),
+            None => false,
+        };
+        if applied {
+            info!(
+                "config rotation_interval changed from {} to {}",
+                or_none(settings.rotation_interval),
+                or_none(reloaded.rotation_interval)
+            );
+        } else {
+            warn!("config rotation_interval not applied, the persist service stopped");
+        }
+    }
+
+    if reloaded.sample != settings.sample {
+        let rules = |sample: &BTreeMap<String, Sampling>| {
+            sample
+                .iter()
+                .map(|(table, sampling)| format!("{table}={sampling}"))
+                .collect::<Vec<_>>()
+        };
+        info!(
+            "config sample changed from {:?} to {:?}",
+            rules(&settings.sample),
+            rules(&reloaded.sample)
+        );
+        targets
+            .sampling
+            .send_replace(reloaded.sample.clone().into_iter().collect());
+    }
+
+    reloaded
+}
+
+/// Subscribes the symbols added and unsubscribes the ones removed, compared to the symbols subscribed.
+async fn resubscribe(reloaded: &Settings, targets: &Targets) -> Result<()> {
+    let symbols = if reloaded.symbols.is_empty() {
+        targets.venue.default_symbols().iter().map(|s| s.to_string()).collect()
+    } else {
+        reloaded.symbols.clone()
+    };
+    let subscribed = control::apply(&targets.control, targets.venue, ControlCmd::List).await?;
+    let removed = subscribed
+        .iter()
+        .filter(|symbol| !symbols.contains(symbol))
+        .cloned()
+        .collect::<Vec<_>>();
+    let added = symbols
+        .iter()
+        .filter(|symbol| !subscribed.contains(symbol))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !removed.is_empty() {
+        control::apply(&targets.control, targets.venue, |reply| {
+            ControlCmd::Unsubscribe(removed.clone(), reply)
+        })
+        .await?;
+    }
+    if !added.is_empty() {
+        control::apply(&targets.control, targets.venue, |reply| {
+            ControlCmd::Subscribe(added.clone(), reply)
+        })
+        .await?;
+    }
+    info!("config symbols changed, subscribed {added:?} and unsubscribed {removed:?}");
+    Ok(())
+}
+
+fn or_none(interval: Option<Interval>) -> String {
+    interval.map_or_else(|| "none".to_owned(), |interval| interval.to_string())
+}
+
+fn or_default(route: &Option<TableRoute>) -> String {
+    route.clone().unwrap_or_default().to_string()
+}
@@ -0,0 +1,80 @@
+//! Logs the messages skipped by the venues sampled per reason, so venues sending many heartbeats or statuses do not
+//! flood the logs.
+//!
+//! ## Features
+//! - Counts the skipped messages per [`SkipReason`]: unsubscribed channel, parse failure, frame without text or other.
+//! - Logs at info the first `--skip-log-first` skips of each reason then every `--skip-log-every`th one, with the
+//!   count of the reason and the start of the message.
+//! - Logs every skipped message in full at debug.
+//! - Logs the counts per reason on shutdown.
+//!
+//! ## Example
+//! ```sh
+//! dixit --venue okx --skip-log-first 5 --skip-log-every 10000
+//! ```
+
+use std::collections::BTreeMap;
+
+use tracing::{debug, info};
+
+use crate::model::SkipReason;
+
+/// Characters of a skipped message logged at info, the whole message being logged at debug.
+const PREVIEW: usize = 200;
+/// Skips of each reason logged before sampling, by default.
+pub const FIRST: u64 = 10;
+/// Sampling of the skips past the first ones, by default.
+pub const EVERY: u64 = 1000;
+
+/// Counts and sampled logs of the skipped messages, see the module documentation.
+#[derive(Debug, Clone)]
+pub struct SkipLog {
+    first: u64,
+    every: u64,
+    counts: BTreeMap<SkipReason, u64>,
+}
+
+impl Default for SkipLog {
+    fn default() -> Self {
+        Self::new(FIRST, EVERY)
+    }
+}
+
+impl SkipLog {
+    /// Logs the first `first` skips of each reason, then every `every`th one, none past the first ones if 0.
+    pub fn new(first: u64, every: u64) -> Self {
+        Self {
+            first,
+            every,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Counts a skipped message, logging it if sampled, returns whether it was.
+    pub fn skip(&mut self, reason: SkipReason, message: &str) -> bool {
+        let count = self.counts.entry(reason).or_default();
+        *count += 1;
+        let count = *count;
+        debug!("skip data ({reason}): {message}");
+        let sampled = count <= self.first || (count - self.first).checked_rem(self.every) == Some(0);
+        if sampled {
+            match message.char_indices().nth(PREVIEW) {
+                Some((end, _)) => info!("skip data ({reason}, {count} so far): {}...", &message[..end]),
+                None => info!("skip data ({reason}, {count} so far): {message}"),
+            }
+        }
+        sampled
+    }
+
+    /// Skipped messages of a reason.
+    pub fn count(&self, reason: SkipReason) -> u64 {
+        self.counts.get(&reason).copied().unwrap_or_default()
+    }
+
+    /// Logs the counts per reason.
+    pub fn report(&self) {
+        for (reason, count) in &self.counts {
+            info!("{count} messages skipped ({reason})");
+        }
+    }
+}
@@ -0,0 +1,15 @@
+//! Data sources feeding the record channel besides the venue WebSockets.
+//!
+//! ## Features
+//! - `rest`: Polls HTTP endpoints for slower datasets (tickers, funding, reference data).
+//! - `fix`: Experimental FIX 4.4 market data session for venues that only offer FIX.
+//! - `multicast`: UDP multicast feeds (e.g. colocation ITCH feeds) decoded through a pluggable decoder.
+//! - `grpc`: Ingest endpoint where internal producers push records, behind the `grpc` feature.
+//! - `replay`: Recorded tables replayed in chronological order, to backtest on recorded data.
+
+pub mod fix;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod multicast;
+pub mod replay;
+pub mod rest;
@@ -0,0 +1,528 @@
+//! Experimental FIX 4.4 market data source, for venues that only offer FIX.
+//!
+//! ## Features
+//! - Frames and validates messages (`BodyLength`, `CheckSum`) read from a plain TCP connection.
+//! - Handles the session layer: logon, heartbeats, test requests, sequence gaps and logout.
+//! - Reports the connection, the sequence gaps and the connection closed by the acceptor as events.
+//! - Subscribes to bids, offers and trades through a `MarketDataRequest` for each symbol.
+//! - Parses full and incremental refreshes into market data entry records.
+//! - With `--watermark`, checkpoints the sequence numbers received and resumes the session after the saved watermark
+//!   on restart, without resetting the sequence numbers, so the acceptor resends the gap.
+//!
+//! TLS is not supported, our own messages are not resent, a `ResendRequest` is answered by a gap fill. Sequence numbers
+//! are reset on logon unless resuming. Messages resent by the counterparty after a gap are processed as they arrive, so
+//! records may be out of order.
+
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use dixit_persist_macros::Persist;
+use rust_decimal::Decimal;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::Sender;
+use tokio::time;
+use tracing::{info, warn};
+
+use crate::clock::{Clock, SharedClock};
+use crate::events::{Event, EventKind};
+use crate::model::{Record, Severity, SkipReason, VenueData};
+use crate::watermark::{Acks, Watermark};
+
+pub const EXCHANGE: &str = "fix";
+
+const BEGIN_STRING: &str = "FIX.4.4";
+const SOH: u8 = 0x01;
+const TIME_FORMAT: &str = "%Y%m%d-%H:%M:%S%.3f";
+/// Skipped on resume, our messages sent after the watermark was saved are not known, the acceptor asks for the gap
+/// and gets a gap fill.
+const RESUME_SEQ_GAP: u64 = 100;
+
+mod tag {
+    pub const BEGIN_STRING: u32 = 8;
+    pub const BEGIN_SEQ_NO: u32 = 7;
+    pub const BODY_LENGTH: u32 = 9;
+    pub const CHECKSUM: u32 = 10;
+    pub const END_SEQ_NO: u32 = 16;
+    pub const MSG_SEQ_NUM: u32 = 34;
+    pub const MSG_TYPE: u32 = 35;
+    pub const NEW_SEQ_NO: u32 = 36;
+    pub const POSS_DUP_FLAG: u32 = 43;
+    pub const SENDER_COMP_ID: u32 = 49;
+    pub const SENDING_TIME: u32 = 52;
+    pub const SYMBOL: u32 = 55;
+    pub const TARGET_COMP_ID: u32 = 56;
+    pub const TEXT: u32 = 58;
+    pub const ENCRYPT_METHOD: u32 = 98;
+    pub const HEART_BT_INT: u32 = 108;
+    pub const TEST_REQ_ID: u32 = 112;
+    pub const GAP_FILL_FLAG: u32 = 123;
+    pub const RESET_SEQ_NUM_FLAG: u32 = 141;
+    pub const NO_RELATED_SYM: u32 = 146;
+    pub const MD_REQ_ID: u32 = 262;
+    pub const SUBSCRIPTION_REQUEST_TYPE: u32 = 263;
+    pub const MARKET_DEPTH: u32 = 264;
+    pub const MD_UPDATE_TYPE: u32 = 265;
+    pub const NO_MD_ENTRY_TYPES: u32 = 267;
+    pub const NO_MD_ENTRIES: u32 = 268;
+    pub const MD_ENTRY_TYPE: u32 = 269;
+    pub const MD_ENTRY_PX: u32 = 270;
+    pub const MD_ENTRY_SIZE: u32 = 271;
+    pub const MD_UPDATE_ACTION: u32 = 279;
+}
+
+#[derive(Debug, Clone)]
+pub struct FixConfig {
+    pub address: String,
+    pub sender_comp_id: String,
+    pub target_comp_id: String,
+    pub heartbeat: Duration,
+    /// watermark of a previous run to resume the session after
+    pub resume: Option<Watermark>,
+    /// clock of the sending times and of the refreshes without one
+    pub clock: SharedClock,
+}
+
+/// A single bid, offer or trade from a market data refresh.
+#[derive(Debug, Persist)]
+pub struct MarketDataEntry {
+    pub symbol: String,
+    pub time: DateTime<Utc>,
+    pub seq_num: u64,
+    pub action: String,
+    pub entry_type: String,
+    pub price: Option<Decimal>,
+    pub size: Option<Decimal>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct FixMessage {
+    fields: Vec<(u32, String)>,
+}
+
+impl FixMessage {
+    pub fn new(msg_type: &str) -> Self {
+        Self {
+            fields: vec![(tag::MSG_TYPE, msg_type.to_owned())],
+        }
+    }
+
+    pub fn with(mut self, tag: u32, value: impl ToString) -> Self {
+        self.fields.push((tag, value.to_string()));
+        self
+    }
+
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields.iter().find(|(t, _)| *t == tag).map(|(_, v)| v.as_str())
+    }
+
+    pub fn msg_type(&self) -> &str {
+        self.get(tag::MSG_TYPE).unwrap_or_default()
+    }
+
+    fn seq_num(&self) -> Option<u64> {
+        self.get(tag::MSG_SEQ_NUM)?.parse().ok()
+    }
+
+    /// Encodes the message, adding the header fields, `BodyLength` and `CheckSum`.
+    pub fn encode(&self, seq_num: u64, sender: &str, target: &str, time: DateTime<Utc>) -> Vec<u8> {
+        let mut body = Vec::new();
+        let msg_type = (tag::MSG_TYPE, self.msg_type().to_owned());
+        let header = [
+            (tag::SENDER_COMP_ID, sender.to_owned()),
+            (tag::TARGET_COMP_ID, target.to_owned()),
+            (tag::MSG_SEQ_NUM, seq_num.to_string()),
+            (tag::SENDING_TIME, time.format(TIME_FORMAT).to_string()),
+        ];
+        let fields = self.fields.iter().filter(|(t, _)| *t != tag::MSG_TYPE);
+        for (tag, value) in [&msg_type].into_iter().chain(header.iter()).chain(fields) {
+            body.extend_from_slice(format!("{tag}={value}").as_bytes());
+            body.push(SOH);
+        }
+
+        let mut message = format!("8={BEGIN_STRING}\x019={}\x01", body.len()).into_bytes();
+        message.extend_from_slice(&body);
+        let checksum = checksum(&message);
+        message.extend_from_slice(format!("10={checksum:03}\x01").as_bytes());
+        message
+    }
+
+    /// Decodes the first complete message in the buffer, removing it (and any garbage before it) from the buffer.
+    pub fn decode(buffer: &mut Vec<u8>) -> Result<Option<Self>> {
+        let Some(start) = buffer.windows(2).position(|w| w == b"8=") else {
+            // the next message may start with the last byte read
+            let garbage = buffer.len() - usize::from(buffer.last() == Some(&b'8'));
+            buffer.drain(..garbage);
+            return Ok(None);
+        };
+        buffer.drain(..start);
+
+        // 8=FIX.4.4|9=len| then the body, then 10=xxx|
+        let Some(begin_end) = buffer.iter().position(|b| *b == SOH) else {
+            return Ok(None);
+        };
+        let Some(length_end) = buffer[begin_end + 1..].iter().position(|b| *b == SOH) else {
+            return Ok(None);
+        };
+        let length_end = begin_end + 1 + length_end;
+        let length_field = std::str::from_utf8(&buffer[begin_end + 1..length_end])?;
+        let body_length: usize = length_field
+            .strip_prefix("9=")
+            .and_then(|length| length.parse().ok())
+            .ok_or_else(|| anyhow!("invalid body length {length_field}"))?;
+
+        let body_end = length_end + 1 + body_length;
+        let message_end = body_end + 7;
+        if buffer.len() < message_end {
+            return Ok(None);
+        }
+
+        let expected = checksum(&buffer[..body_end]);
+        let raw = buffer.drain(..message_end).collect::<Vec<_>>();
+        let mut message = Self::default();
+        for field in raw.split(|b| *b == SOH).filter(|field| !field.is_empty()) {
+            let field = std::str::from_utf8(field)?;
+            let (tag, value) = field.split_once('=').ok_or_else(|| anyhow!("invalid field {field}"))?;
+            let tag = tag.parse::<u32>()?;
+            match tag {
+                tag::CHECKSUM if value.parse::<u32>().ok() != Some(expected) => {
+                    bail!("invalid checksum {value}, expected {expected:03}")
+                }
+                tag::BEGIN_STRING | tag::BODY_LENGTH | tag::CHECKSUM => {}
+                _ => message.fields.push((tag, value.to_owned())),
+            }
+        }
+        Ok(Some(message))
+    }
+
+    /// Splits a refresh into its entries, each one starting with `first_tag`, keeping the fields before the group.
+    fn entries(&self, first_tag: u32) -> Vec<Vec<(u32, &str)>> {
+        let Some(group) = self.fields.iter().position(|(t, _)| *t == tag::NO_MD_ENTRIES) else {
+            return vec![];
+        };
+        let mut entries: Vec<Vec<(u32, &str)>> = vec![];
+        for (tag, value) in self.fields[group + 1..].iter() {
+            if *tag == first_tag || entries.is_empty() {
+                entries.push(vec![]);
+            }
+            if let Some(entry) = entries.last_mut() {
+                entry.push((*tag, value.as_str()));
+            }
+        }
+        entries
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().map(|b| *b as u32).sum::<u32>() % 256
+}
+
+struct Session {
+    config: FixConfig,
+    writer: OwnedWriteHalf,
+    out_seq: u64,
+    in_seq: u64,
+    /// sequence numbers requested through a `ResendRequest` and not received yet
+    missing: BTreeSet<u64>,
+}
+
+impl Session {
+    async fn send(&mut self, message: FixMessage) -> Result<()> {
+        self.out_seq += 1;
+        self.send_at(self.out_seq, message).await
+    }
+
+    async fn send_at(&mut self, seq_num: u64, message: FixMessage) -> Result<()> {
+        let bytes = message.encode(
+            seq_num,
+            &self.config.sender_comp_id,
+            &self.config.target_comp_id,
+            self.config.clock.now(),
+        );
+        self.writer.write_all(&bytes).await?;
+        Ok(())
+    }
+
+    /// Highest sequence number received, the messages missing before it may still be resent.
+    fn last_received(&self) -> u64 {
+        self.in_seq - 1
+    }
+
+    fn watermark(&self) -> Watermark {
+        let next = self.missing.first().copied().unwrap_or(self.in_seq);
+        Watermark {
+            received: next - 1,
+            sent: self.out_seq,
+        }
+    }
+
+    /// Sends a checkpoint if the sequence numbers moved since the last one, only if acknowledged.
+    async fn checkpoint(&self, tx: &Sender<Record>, acks: &Option<Acks>, last: &mut (u64, Watermark)) -> Result<()> {
+        let checkpoint = (self.last_received(), self.watermark());
+        if acks.is_none() || checkpoint == *last {
+            return Ok(());
+        }
+        *last = checkpoint;
+        let (seq, watermark) = checkpoint;
+        tx.send(Record::Checkpoint { seq, watermark }).await?;
+        Ok(())
+    }
+}
+
+async fn acked(acks: &mut Option<Acks>) {
+    match acks {
+        Some(acks) => acks.changed().await,
+        None => std::future::pending().await,
+    }
+}
+
+pub async fn run(tx: Sender<Record>, config: FixConfig, symbols: Vec<String>, mut acks: Option<Acks>) -> Result<()> {
+    let (mut reader, writer) = TcpStream::connect(&config.address).await?.into_split();
+    let heartbeat = config.heartbeat;
+    let resume = config.resume;
+    let mut session = Session {
+        config,
+        writer,
+        out_seq: resume.map_or(0, |watermark| watermark.sent + RESUME_SEQ_GAP),
+        in_seq: resume.map_or(1, |watermark| watermark.received + 1),
+        missing: BTreeSet::new(),
+    };
+
+    session
+        .send(
+            FixMessage::new("A")
+                .with(tag::ENCRYPT_METHOD, 0)
+                .with(tag::HEART_BT_INT, heartbeat.as_secs())
+                .with(tag::RESET_SEQ_NUM_FLAG, if resume.is_some() { "N" } else { "Y" }),
+        )
+        .await?;
+    let address = session.config.address.clone();
+    let logon = match resume {
+        Some(watermark) => format!("logon sent, resuming after {}", watermark.received),
+        None => "logon sent".to_owned(),
+    };
+    tx.send(Event::new(session.config.clock.now(), EventKind::Connect, &address, logon).record())
+        .await?;
+    let mut checkpoint = (session.last_received(), session.watermark());
+
+    let mut timer = time::interval_at(time::Instant::now() + heartbeat, heartbeat);
+    let mut buffer = Vec::new();
+    let mut read = [0u8; 4096];
+    let mut idle_ticks = 0;
+
+    loop {
+        // a source ahead of the persister only keeps the session alive until acknowledged
+        let throttled = acks
+            .as_ref()
+            .is_some_and(|acks| acks.throttled(session.last_received()));
+        tokio::select! {
+            _ = acked(&mut acks), if throttled => {}
+            n = reader.read(&mut read), if !throttled => {
+                let n = n?;
+                if n == 0 {
+                    let closed = Event::new(session.config.clock.now(), EventKind::Disconnect, &address, "connection closed");
+                    tx.send(closed.record()).await?;
+                    bail!("connection closed by {address}");
+                }
+                idle_ticks = 0;
+                buffer.extend_from_slice(&read[..n]);
+                while let Some(message) = FixMessage::decode(&mut buffer)? {
+                    let more = handle(&mut session, &tx, &symbols, message).await?;
+                    session.checkpoint(&tx, &acks, &mut checkpoint).await?;
+                    if !more {
+                        return Ok(());
+                    }
+                }
+            }
+            _ = timer.tick() => {
+                session.send(FixMessage::new("0")).await?;
+                idle_ticks += 1;
+                match idle_ticks {
+                    0..=1 => {}
+                    2 => {
+                        let test_request = FixMessage::new("1").with(tag::TEST_REQ_ID, session.config.clock.now().timestamp());
+                        session.send(test_request).await?;
+                    }
+                    _ => bail!("no response to test request from {}", session.config.address),
+                }
+                session.checkpoint(&tx, &acks, &mut checkpoint).await?;
+            }
+        }
+    }
+}
+
+/// Returns `false` once the session is over.
+async fn handle(session: &mut Session, tx: &Sender<Record>, symbols: &[String], message: FixMessage) -> Result<bool> {
+    let seq_num = message.seq_num().unwrap_or_default();
+    let msg_type = message.msg_type().to_owned();
+
+    // a sequence reset applies regardless of the sequence number it carries
+    if msg_type == "4" {
+        if let Some(new_seq_no) = message.get(tag::NEW_SEQ_NO).and_then(|s| s.parse::<u64>().ok()) {
+            if message.get(tag::GAP_FILL_FLAG) == Some("Y") {
+                // admin messages are not resent, the gap fill covers them
+                session.missing.retain(|seq| *seq < seq_num || *seq >= new_seq_no);
+                session.in_seq = session.in_seq.max(new_seq_no);
+            } else {
+                info!("sequence reset to {new_seq_no}");
+                session.missing.clear();
+                session.in_seq = new_seq_no;
+            }
+        }
+        return Ok(true);
+    }
+
+    match seq_num.cmp(&session.in_seq) {
+        Ordering::Greater => {
+            warn!("sequence gap, expected {} received {seq_num}", session.in_seq);
+            let gap = format!("expected {} received {seq_num}", session.in_seq);
+            tx.send(Event::new(session.config.clock.now(), EventKind::Gap, &session.config.address, gap).record())
+                .await?;
+            let resend = FixMessage::new("2")
+                .with(tag::BEGIN_SEQ_NO, session.in_seq)
+                .with(tag::END_SEQ_NO, seq_num - 1);
+            session.missing.extend(session.in_seq..seq_num);
+            session.in_seq = seq_num + 1;
+            session.send(resend).await?;
+        }
+        Ordering::Less if !session.missing.remove(&seq_num) => {
+            if message.get(tag::POSS_DUP_FLAG) != Some("Y") {
+                bail!("sequence too low, expected {} received {seq_num}", session.in_seq);
+            }
+            // duplicates were already processed
+            return Ok(true);
+        }
+        Ordering::Less => {}
+        Ordering::Equal => session.in_seq += 1,
+    }
+
+    match msg_type.as_str() {
+        "A" => {
+            info!("logged on to {}", session.config.address);
+            for (i, symbol) in symbols.iter().enumerate() {
+                session.send(market_data_request(i, symbol)).await?;
+            }
+        }
+        "2" => {
+            // our messages are not resent, the gap is filled up to the next sequence number
+            let begin = message
+                .get(tag::BEGIN_SEQ_NO)
+                .and_then(|s| s.parse::<u64>().ok())
+                .unwrap_or(1);
+            let gap_fill = FixMessage::new("4")
+                .with(tag::POSS_DUP_FLAG, "Y")
+                .with(tag::GAP_FILL_FLAG, "Y")
+                .with(tag::NEW_SEQ_NO, session.out_seq + 1);
+            session.send_at(begin, gap_fill).await?;
+        }
+        "1" => {
+            let heartbeat =
+                FixMessage::new("0").with(tag::TEST_REQ_ID, message.get(tag::TEST_REQ_ID).unwrap_or_default());
+            session.send(heartbeat).await?;
+        }
+        "5" => {
+            tx.send(Record::error(
+                EXCHANGE,
+                "session",
+                Severity::Fatal,
+                "logout".to_owned(),
+                message.get(tag::TEXT).unwrap_or_default().to_owned(),
+            ))
+            .await?;
+            return Ok(false);
+        }
+        "3" | "j" | "Y" => {
+            // a rejected request (e.g. market data for an unknown symbol) leaves the other requests running
+            tx.send(Record::error(
+                EXCHANGE,
+                "market_data",
+                Severity::Warning,
+                format!("rejected ({msg_type})"),
+                message.get(tag::TEXT).unwrap_or_default().to_owned(),
+            ))
+            .await?;
+        }
+        "W" | "X" => {
+            for record in parse_refresh(&message, seq_num, &*session.config.clock) {
+                tx.send(record).await?;
+            }
+        }
+        "0" => {}
+        _ => {
+            tx.send(Record::skip(SkipReason::Unsubscribed, format!("{:?}", message)))
+                .await?
+        }
+    }
+    Ok(true)
+}
+
+fn market_data_request(id: usize, symbol: &str) -> FixMessage {
+    FixMessage::new("V")
+        .with(tag::MD_REQ_ID, format!("dixit-{id}"))
+        .with(tag::SUBSCRIPTION_REQUEST_TYPE, 1)
+        .with(tag::MARKET_DEPTH, 0)
+        .with(tag::MD_UPDATE_TYPE, 1)
+        .with(tag::NO_MD_ENTRY_TYPES, 3)
+        .with(tag::MD_ENTRY_TYPE, 0)
+        .with(tag::MD_ENTRY_TYPE, 1)
+        .with(tag::MD_ENTRY_TYPE, 2)
+        .with(tag::NO_RELATED_SYM, 1)
+        .with(tag::SYMBOL, symbol)
+}
+
+/// Full refreshes (`W`) carry the symbol once and entries start at `MDEntryType`,
+/// incremental refreshes (`X`) start entries at `MDUpdateAction` and repeat the symbol in each of them.
+fn parse_refresh(message: &FixMessage, seq_num: u64, clock: &dyn Clock) -> Vec<Record> {
+    let full = message.msg_type() == "W";
+    let time = message
+        .get(tag::SENDING_TIME)
+        .and_then(|time| NaiveDateTime::parse_from_str(time, TIME_FORMAT).ok())
+        .map(|time| time.and_utc())
+        .unwrap_or_else(|| clock.now());
+    let first_tag = if full {
+        tag::MD_ENTRY_TYPE
+    } else {
+        tag::MD_UPDATE_ACTION
+    };
+
+    message
+        .entries(first_tag)
+        .into_iter()
+        .map(|entry| {
+            let get = |tag: u32| entry.iter().find(|(t, _)| *t == tag).map(|(_, v)| *v);
+            let symbol = get(tag::SYMBOL).or(message.get(tag::SYMBOL)).unwrap_or_default();
+            let action = match (full, get(tag::MD_UPDATE_ACTION)) {
+                (true, _) => "snapshot",
+                (false, Some("0")) => "new",
+                (false, Some("1")) => "change",
+                (false, Some("2")) => "delete",
+                (false, _) => "unknown",
+            };
+            let entry_type = match get(tag::MD_ENTRY_TYPE) {
+                Some("0") => "bid",
+                Some("1") => "offer",
+                Some("2") => "trade",
+                Some(other) => other,
+                None => "unknown",
+            };
+            Record::data(
+                EXCHANGE,
+                "market_data",
+                symbol,
+                VenueData::FixMarketData(MarketDataEntry {
+                    symbol: symbol.to_owned(),
+                    time,
+                    seq_num,
+                    action: action.to_owned(),
+                    entry_type: entry_type.to_owned(),
+                    price: get(tag::MD_ENTRY_PX).and_then(|px| px.parse().ok()),
+                    size: get(tag::MD_ENTRY_SIZE).and_then(|size| size.parse().ok()),
+                }),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,246 @@
+//! gRPC ingest endpoint, so internal systems that do not speak WebSocket can reuse the Parquet persistence.
+//!
+//! ## Features
+//! - Serves the client streaming `dixit.Ingest/Push` method described in `proto/ingest.proto` with `tonic`.
+//! - Maps the hand-written `prost` messages onto the normalized records (trades, funding, open interest, index
+//!   and liquidations), rejecting records with missing data or invalid decimals.
+//! - Replies with the number of accepted and rejected records once the producer closes its stream.
+//!
+//! The service is written by hand instead of generated by `tonic-build`, so building does not require `protoc`.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use tokio::sync::mpsc::Sender;
+use tonic::codec::ProstCodec;
+use tonic::codegen::{empty_body, http, Body, BoxFuture, Context, Poll, Service, StdError};
+use tonic::server::{ClientStreamingService, Grpc, NamedService};
+use tonic::transport::Server;
+use tonic::{Code, Status, Streaming};
+use tracing::info;
+
+use crate::model::{FundingRate, IndexPrice, Liquidation, OpenInterest, Record, SkipReason, Trade, VenueData};
+
+const PUSH_PATH: &str = "/dixit.Ingest/Push";
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct IngestRecord {
+    #[prost(string, tag = "1")]
+    pub exchange: String,
+    #[prost(string, tag = "2")]
+    pub channel: String,
+    #[prost(string, tag = "3")]
+    pub symbol: String,
+    #[prost(int64, tag = "4")]
+    pub time_nanos: i64,
+    #[prost(oneof = "ingest_record::Data", tags = "5, 6, 7, 8, 9")]
+    pub data: Option<ingest_record::Data>,
+}
+
+pub mod ingest_record {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Data {
+        #[prost(message, tag = "5")]
+        Trade(super::TradeMessage),
+        #[prost(message, tag = "6")]
+        FundingRate(super::FundingRateMessage),
+        #[prost(message, tag = "7")]
+        OpenInterest(super::OpenInterestMessage),
+        #[prost(message, tag = "8")]
+        IndexPrice(super::IndexPriceMessage),
+        #[prost(message, tag = "9")]
+        Liquidation(super::LiquidationMessage),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TradeMessage {
+    #[prost(string, tag = "1")]
+    pub price: String,
+    #[prost(string, tag = "2")]
+    pub size: String,
+    #[prost(string, tag = "3")]
+    pub side: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FundingRateMessage {
+    #[prost(string, tag = "1")]
+    pub funding_rate: String,
+    #[prost(int64, optional, tag = "2")]
+    pub next_funding_time_nanos: Option<i64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct OpenInterestMessage {
+    #[prost(string, tag = "1")]
+    pub open_interest: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct IndexPriceMessage {
+    #[prost(string, tag = "1")]
+    pub index_price: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct LiquidationMessage {
+    #[prost(string, tag = "1")]
+    pub side: String,
+    #[prost(string, tag = "2")]
+    pub price: String,
+    #[prost(string, tag = "3")]
+    pub size: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct IngestReply {
+    #[prost(uint64, tag = "1")]
+    pub accepted: u64,
+    #[prost(uint64, tag = "2")]
+    pub rejected: u64,
+}
+
+pub async fn run(tx: Sender<Record>, address: SocketAddr) -> Result<()> {
+    info!("serving gRPC ingest on {address}");
+    Server::builder()
+        .add_service(IngestService { tx })
+        .serve(address)
+        .await?;
+    Ok(())
+}
+
+async fn push(tx: Sender<Record>, mut stream: Streaming<IngestRecord>) -> Result<IngestReply, Status> {
+    let mut reply = IngestReply::default();
+    while let Some(message) = stream.message().await? {
+        let record = match parse(message) {
+            Ok(record) => {
+                reply.accepted += 1;
+                record
+            }
+            Err(message) => {
+                reply.rejected += 1;
+                Record::skip(SkipReason::Parse, message)
+            }
+        };
+        tx.send(record)
+            .await
+            .map_err(|_| Status::unavailable("persister is not running"))?;
+    }
+    Ok(reply)
+}
+
+fn parse(message: IngestRecord) -> Result<Record, String> {
+    let IngestRecord {
+        exchange,
+        channel,
+        symbol,
+        time_nanos,
+        data,
+    } = message;
+    let time = DateTime::from_timestamp_nanos(time_nanos);
+    let data = match data.ok_or_else(|| format!("no data in record for {symbol}"))? {
+        ingest_record::Data::Trade(trade) => VenueData::Trade(Trade {
+            symbol: symbol.clone(),
+            instrument: None,
+            time,
+            price: decimal("price", &trade.price)?,
+            size: decimal("size", &trade.size)?,
+            side: trade.side,
+        }),
+        ingest_record::Data::FundingRate(funding) => VenueData::Funding(FundingRate {
+            symbol: symbol.clone(),
+            instrument: None,
+            time,
+            funding_rate: decimal("funding_rate", &funding.funding_rate)?,
+            next_funding_time: funding
+                .next_funding_time_nanos
+                .map(DateTime::<Utc>::from_timestamp_nanos),
+        }),
+        ingest_record::Data::OpenInterest(open_interest) => VenueData::OpenInterest(OpenInterest {
+            symbol: symbol.clone(),
+            instrument: None,
+            time,
+            open_interest: decimal("open_interest", &open_interest.open_interest)?,
+        }),
+        ingest_record::Data::IndexPrice(index) => VenueData::Index(IndexPrice {
+            symbol: symbol.clone(),
+            instrument: None,
+            time,
+            index_price: decimal("index_price", &index.index_price)?,
+        }),
+        ingest_record::Data::Liquidation(liquidation) => VenueData::Liquidation(Liquidation {
+            symbol: symbol.clone(),
+            instrument: None,
+            time,
+            side: liquidation.side,
+            price: decimal("price", &liquidation.price)?,
+            size: decimal("size", &liquidation.size)?,
+        }),
+    };
+    Ok(Record::data(&exchange, &channel, &symbol, data))
+}
+
+fn decimal(field: &str, value: &str) -> Result<Decimal, String> {
+    Decimal::from_str(value).map_err(|e| format!("invalid {field} {value:?}: {e}"))
+}
+
+/// Routes requests to the `Push` method, what `tonic-build` would generate for the `Ingest` service.
+#[derive(Clone)]
+struct IngestService {
+    tx: Sender<Record>,
+}
+
+impl NamedService for IngestService {
+    const NAME: &'static str = "dixit.Ingest";
+}
+
+impl<B> Service<http::Request<B>> for IngestService
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        if request.uri().path() != PUSH_PATH {
+            return Box::pin(async move {
+                Ok(http::Response::builder()
+                    .status(200)
+                    .header("grpc-status", (Code::Unimplemented as i32).to_string())
+                    .header(http::header::CONTENT_TYPE, "application/grpc")
+                    .body(empty_body())
+                    .expect("valid response"))
+            });
+        }
+        let method = PushMethod { tx: self.tx.clone() };
+        Box::pin(async move {
+            let mut grpc = Grpc::new(ProstCodec::default());
+            Ok(grpc.client_streaming(method, request).await)
+        })
+    }
+}
+
+struct PushMethod {
+    tx: Sender<Record>,
+}
+
+impl ClientStreamingService<IngestRecord> for PushMethod {
+    type Response = IngestReply;
+    type Future = BoxFuture<tonic::Response<IngestReply>, Status>;
+
+    fn call(&mut self, request: tonic::Request<Streaming<IngestRecord>>) -> Self::Future {
+        let tx = self.tx.clone();
+        Box::pin(async move { push(tx, request.into_inner()).await.map(tonic::Response::new) })
+    }
+}
@@ -0,0 +1,224 @@
+//! Records exchange colocation feeds delivered over UDP multicast.
+//!
+//! ## Features
+//! - Joins an IPv4 multicast group and reads MoldUDP64 packets.
+//! - Reassembles packets in sequence order, buffering out of order packets and reporting gaps as events.
+//! - Decodes every message through a pluggable [`Decoder`], an ITCH 5.0 trade decoder is provided.
+
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+use anyhow::Result;
+use dixit_persist_macros::Persist;
+use rust_decimal::Decimal;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+
+use crate::clock::SharedClock;
+use crate::events::{self, EventKind};
+use crate::model::{Record, SkipReason, VenueData};
+
+pub const EXCHANGE: &str = "multicast";
+
+/// Packets buffered while waiting for a missing one, the gap is skipped once the buffer is full.
+const MAX_PENDING: usize = 1_000;
+const HEADER_LEN: usize = 20;
+const END_OF_SESSION: u16 = 0xFFFF;
+
+/// Turns a single message (without its length prefix) into records.
+pub trait Decoder: Send {
+    fn decode(&mut self, message: &[u8]) -> Vec<Record>;
+}
+
+#[derive(Debug, Clone)]
+pub struct MulticastConfig {
+    pub group: SocketAddrV4,
+    pub interface: Ipv4Addr,
+    /// clock of the gap events
+    pub clock: SharedClock,
+}
+
+pub async fn run(tx: Sender<Record>, config: MulticastConfig, mut decoder: impl Decoder) -> Result<()> {
+    let socket = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, config.group.port())).await?;
+    socket.join_multicast_v4(*config.group.ip(), config.interface)?;
+    info!("joined multicast group {} on {}", config.group, config.interface);
+
+    let mut reassembler = Reassembler::default();
+    let mut buffer = vec![0u8; 65_536];
+    loop {
+        let n = socket.recv(&mut buffer).await?;
+        let Some(packet) = Packet::parse(&buffer[..n]) else {
+            tx.send(Record::skip(SkipReason::Parse, format!("invalid packet of {n} bytes")))
+                .await?;
+            continue;
+        };
+        if packet.count == END_OF_SESSION {
+            info!("end of session {}", packet.session);
+            return Ok(());
+        }
+        for event in reassembler.push(packet) {
+            match event {
+                Event::Message(message) => {
+                    for record in decoder.decode(&message) {
+                        tx.send(record).await?;
+                    }
+                }
+                Event::Gap { from, to } => {
+                    warn!("skipping sequence gap {from}..{to}");
+                    let gap = format!("skipped {from}..{to}");
+                    let gap = events::Event::new(config.clock.now(), EventKind::Gap, &config.group.to_string(), gap);
+                    tx.send(gap.record()).await?;
+                }
+            }
+        }
+    }
+}
+
+/// A MoldUDP64 packet: session (10 bytes), sequence number (u64), message count (u16),
+/// then each message prefixed by its length (u16), all big endian.
+#[derive(Debug, PartialEq)]
+pub struct Packet {
+    pub session: String,
+    pub sequence: u64,
+    pub count: u16,
+    pub messages: Vec<Vec<u8>>,
+}
+
+impl Packet {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let header = bytes.get(..HEADER_LEN)?;
+        let session = String::from_utf8_lossy(&header[..10]).trim().to_owned();
+        let sequence = u64::from_be_bytes(header[10..18].try_into().ok()?);
+        let count = u16::from_be_bytes(header[18..20].try_into().ok()?);
+
+        let mut messages = vec![];
+        let mut offset = HEADER_LEN;
+        if count != END_OF_SESSION {
+            for _ in 0..count {
+                let length = u16::from_be_bytes(bytes.get(offset..offset + 2)?.try_into().ok()?) as usize;
+                offset += 2;
+                messages.push(bytes.get(offset..offset + length)?.to_vec());
+                offset += length;
+            }
+        }
+        Some(Self {
+            session,
+            sequence,
+            count,
+            messages,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Message(Vec<u8>),
+    Gap { from: u64, to: u64 },
+}
+
+/// Releases messages in sequence order, the sequence number of a packet is the one of its first message.
+#[derive(Default)]
+pub struct Reassembler {
+    next: Option<u64>,
+    pending: BTreeMap<u64, Vec<Vec<u8>>>,
+}
+
+impl Reassembler {
+    pub fn push(&mut self, packet: Packet) -> Vec<Event> {
+        let next = *self.next.get_or_insert(packet.sequence);
+        let end = packet.sequence + packet.messages.len() as u64;
+        if end <= next {
+            // duplicate or already released
+            return vec![];
+        }
+
+        // a packet straddling the next sequence number only has its newer messages released
+        let skip = next.saturating_sub(packet.sequence) as usize;
+        let sequence = packet.sequence.max(next);
+        self.pending
+            .insert(sequence, packet.messages.into_iter().skip(skip).collect());
+
+        let mut events = vec![];
+        self.release(&mut events);
+        if self.pending.len() > MAX_PENDING {
+            if let Some((&first, _)) = self.pending.first_key_value() {
+                let from = self.next.unwrap_or(first);
+                events.push(Event::Gap { from, to: first });
+                self.next = Some(first);
+                self.release(&mut events);
+            }
+        }
+        events
+    }
+
+    fn release(&mut self, events: &mut Vec<Event>) {
+        while let Some(entry) = self.pending.first_entry() {
+            let next = self.next.unwrap_or(*entry.key());
+            if *entry.key() > next {
+                break;
+            }
+            let sequence = *entry.key();
+            let messages = entry.remove();
+            let skip = next - sequence;
+            self.next = Some(next.max(sequence + messages.len() as u64));
+            events.extend(messages.into_iter().skip(skip as usize).map(Event::Message));
+        }
+    }
+}
+
+/// An ITCH 5.0 non-cross trade (`P`) message.
+#[derive(Debug, Persist)]
+pub struct ItchTrade {
+    pub stock: String,
+    pub nanos_since_midnight: u64,
+    pub order_reference: u64,
+    pub side: String,
+    pub shares: u32,
+    pub price: Decimal,
+    pub match_number: u64,
+}
+
+/// Decodes ITCH 5.0 trade messages, every other message type is ignored.
+#[derive(Default)]
+pub struct ItchDecoder;
+
+impl Decoder for ItchDecoder {
+    fn decode(&mut self, message: &[u8]) -> Vec<Record> {
+        match message.first() {
+            Some(b'P') => match ItchTrade::parse(message) {
+                Some(trade) => {
+                    let stock = trade.stock.clone();
+                    vec![Record::data(EXCHANGE, "itch", &stock, VenueData::ItchTrade(trade))]
+                }
+                None => vec![Record::skip(
+                    SkipReason::Parse,
+                    format!("invalid trade message of {} bytes", message.len()),
+                )],
+            },
+            _ => vec![],
+        }
+    }
+}
+
+impl ItchTrade {
+    fn parse(message: &[u8]) -> Option<Self> {
+        if message.len() < 44 {
+            return None;
+        }
+        let be_u64 = |range: std::ops::Range<usize>| {
+            message[range]
+                .iter()
+                .fold(0u64, |value, byte| (value << 8) | *byte as u64)
+        };
+        Some(Self {
+            stock: String::from_utf8_lossy(&message[24..32]).trim().to_owned(),
+            nanos_since_midnight: be_u64(5..11),
+            order_reference: be_u64(11..19),
+            side: if message[19] == b'B' { "buy" } else { "sell" }.to_owned(),
+            shares: be_u64(20..24) as u32,
+            price: Decimal::new(be_u64(32..36) as i64, 4),
+            match_number: be_u64(36..44),
+        })
+    }
+}
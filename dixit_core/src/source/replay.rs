@@ -0,0 +1,286 @@
+//! Replays recorded tables as the records of a live source, so a strategy reading the record channel runs on
+//! recorded data by swapping its source for a [`ReplayFeed`].
+//!
+//! ## Features
+//! - Subscribes by venue, channel and symbols as the live venues, reading the `{venue}/{channel}` table of the
+//!   recording directory and its `{date}` partitions, all the symbols of the table if none is given.
+//! - Streams the rows of every subscription merged by their `time` column, restricted to a time range with
+//!   [`ReplayFeed::between`], the files outside of the range being skipped by their time index and statistics.
+//! - Sends every row as a [`Record::Data`] of its venue, channel and symbol holding an [`auto::Row`] with the columns
+//!   of the table, so the records are persisted or published as the `auto` venue ones.
+//! - Keeps the columns of the first file of each table, nested columns (e.g. lists) being sent as strings and the
+//!   values of the later files not fitting their column as nulls.
+//! - Reads the files on a blocking thread, sending the records as fast as the receiver takes them.
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use dixit_persist::dynamic::{ColumnType, Descriptor, DynamicRow, DynamicValue, SchemaBuilder};
+use dixit_persist::merge::MergeReader;
+use dixit_persist::reader::{Comparison, Predicate, TableReader};
+use parquet::basic::{LogicalType, TimeUnit, Type as PhysicalType};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::{Field, Row};
+use parquet::schema::types::Type;
+use tokio::sync::mpsc::Sender;
+use tokio::task;
+use tracing::{info, warn};
+
+use crate::auto;
+use crate::model::{Record, VenueData};
+
+/// Column holding the symbol of the rows.
+const SYMBOL: &str = "symbol";
+
+/// Recorded tables to replay, see the module documentation.
+#[derive(Debug, Clone)]
+pub struct ReplayFeed {
+    directory: PathBuf,
+    subscriptions: Vec<Subscription>,
+    range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+}
+
+#[derive(Debug, Clone)]
+struct Subscription {
+    venue: String,
+    channel: String,
+    symbols: Vec<String>,
+}
+
+impl ReplayFeed {
+    /// Replays the tables recorded in `directory` (the `--directory` of the recorder).
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            subscriptions: vec![],
+            range: None,
+        }
+    }
+
+    /// Replays the rows of the channel of the venue (e.g. `trades`) of the symbols, of every symbol if empty.
+    pub fn subscribe(mut self, venue: &str, channel: &str, symbols: &[&str]) -> Self {
+        self.subscriptions.push(Subscription {
+            venue: venue.to_owned(),
+            channel: channel.to_owned(),
+            symbols: symbols.iter().map(|symbol| (*symbol).to_owned()).collect(),
+        });
+        self
+    }
+
+    /// Replays the rows whose time is in `[start, end)` only.
+    pub fn between(mut self, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        self.range = Some((start, end));
+        self
+    }
+
+    /// Sends the records of the subscriptions in chronological order, returning once every row is sent.
+    pub async fn run(self, tx: Sender<Record>) -> Result<()> {
+        task::spawn_blocking(move || {
+            let mut count = 0;
+            for record in self.records()? {
+                tx.blocking_send(record?)?;
+                count += 1;
+            }
+            info!("replayed {count} records");
+            Ok(())
+        })
+        .await?
+    }
+
+    /// The records of the subscriptions in chronological order, read as iterating.
+    pub fn records(self) -> Result<Replay> {
+        let (mut tables, mut readers, mut owners) = (vec![], vec![], vec![]);
+        for subscription in self.subscriptions {
+            let directory = self.directory.join(&subscription.venue).join(&subscription.channel);
+            let mut table_readers = vec![];
+            for partition in partitions(&directory)? {
+                let mut reader: TableReader = TableReader::open(&partition)?;
+                if let Some((start, end)) = self.range {
+                    reader = reader.between(start, end);
+                }
+                if let [symbol] = subscription.symbols.as_slice() {
+                    reader = reader.with_predicate(Predicate::new(SYMBOL, Comparison::Eq, symbol.as_str()));
+                }
+                table_readers.push(reader);
+            }
+            let Some(first) = table_readers.iter().find_map(|reader| reader.files().first()) else {
+                warn!("no file to replay in {directory:?}");
+                continue;
+            };
+            let table = Table::read(first, subscription)?;
+            owners.extend(table_readers.iter().map(|_| tables.len()));
+            readers.extend(table_readers);
+            tables.push(table);
+        }
+        Ok(Replay {
+            tables,
+            owners,
+            rows: MergeReader::new(readers),
+        })
+    }
+}
+
+/// The table directory followed by its partitions (e.g. the `{date}` folders), in name order.
+fn partitions(directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut partitions = vec![];
+    for entry in fs::read_dir(directory).with_context(|| format!("cannot read table {directory:?}"))? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            partitions.push(entry.path());
+        }
+    }
+    partitions.sort();
+    partitions.insert(0, directory.to_owned());
+    Ok(partitions)
+}
+
+/// Records of a [`ReplayFeed`], in chronological order.
+pub struct Replay {
+    tables: Vec<Table>,
+    /// table of every reader of `rows`
+    owners: Vec<usize>,
+    rows: MergeReader,
+}
+
+impl Iterator for Replay {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (reader, row) = match self.rows.next()? {
+                Ok(row) => row,
+                Err(e) => return Some(Err(e.into())),
+            };
+            let table = &self.tables[self.owners[reader]];
+            if let Some(record) = table.record(row) {
+                return Some(Ok(record));
+            }
+        }
+    }
+}
+
+/// Columns of a replayed table.
+struct Table {
+    subscription: Subscription,
+    /// leaked once per subscription, as the channel of the `auto` venue
+    table: &'static str,
+    descriptor: Arc<Descriptor>,
+    columns: Vec<Column>,
+}
+
+/// Type of a replayed column, with the unit of the timestamps stored as plain integers.
+#[derive(Debug, Clone)]
+struct Column {
+    column_type: ColumnType,
+    unit: Option<TimeUnit>,
+}
+
+impl Table {
+    /// The columns of the table as in its first file.
+    fn read(file: &Path, subscription: Subscription) -> Result<Self> {
+        let reader = SerializedFileReader::new(File::open(file)?).with_context(|| format!("cannot read {file:?}"))?;
+        let fields = reader
+            .metadata()
+            .file_metadata()
+            .schema_descr()
+            .root_schema()
+            .get_fields()
+            .to_vec();
+        let columns = fields.iter().map(|field| Column::of(field)).collect::<Vec<_>>();
+        let descriptor = fields
+            .iter()
+            .zip(columns.iter())
+            .fold(SchemaBuilder::new(&subscription.channel), |builder, (field, column)| {
+                builder.optional(field.name(), column.column_type)
+            })
+            .build()?;
+        Ok(Self {
+            table: String::leak(subscription.channel.clone()),
+            subscription,
+            descriptor: Arc::new(descriptor),
+            columns,
+        })
+    }
+
+    /// The row as a record, none if its symbol is not subscribed.
+    fn record(&self, row: Row) -> Option<Record> {
+        let mut symbol = "";
+        let mut values = Vec::with_capacity(self.columns.len());
+        for ((name, field), column) in row.get_column_iter().zip(self.columns.iter()) {
+            if let (SYMBOL, Field::Str(value)) = (name.as_str(), field) {
+                symbol = value;
+            }
+            values.push(column.value(field));
+        }
+        let symbols = &self.subscription.symbols;
+        if !symbols.is_empty() && !symbols.iter().any(|subscribed| subscribed == symbol) {
+            return None;
+        }
+        let data = auto::Row {
+            table: self.table,
+            descriptor: self.descriptor.clone(),
+            row: DynamicRow::new(values),
+        };
+        Some(Record::data(
+            &self.subscription.venue,
+            &self.subscription.channel,
+            symbol,
+            VenueData::Auto(data),
+        ))
+    }
+}
+
+impl Column {
+    fn of(field: &Type) -> Self {
+        let column_type = |column_type| Self {
+            column_type,
+            unit: None,
+        };
+        if !field.is_primitive() {
+            return column_type(ColumnType::String);
+        }
+        match (field.get_physical_type(), field.get_basic_info().logical_type()) {
+            (PhysicalType::BOOLEAN, _) => column_type(ColumnType::Bool),
+            (PhysicalType::INT32, None | Some(LogicalType::Integer { is_signed: true, .. })) => {
+                column_type(ColumnType::I32)
+            }
+            (PhysicalType::INT64, Some(LogicalType::Timestamp { unit, .. })) => Self {
+                column_type: ColumnType::Timestamp,
+                unit: Some(unit),
+            },
+            (PhysicalType::INT64, Some(LogicalType::Integer { is_signed: false, .. })) => column_type(ColumnType::U64),
+            (PhysicalType::INT64, None | Some(LogicalType::Integer { .. })) => column_type(ColumnType::I64),
+            (PhysicalType::FLOAT, _) => column_type(ColumnType::F32),
+            (PhysicalType::DOUBLE, _) => column_type(ColumnType::F64),
+            _ => column_type(ColumnType::String),
+        }
+    }
+
+    /// The value as sent in the column, null if it does not fit.
+    fn value(&self, field: &Field) -> DynamicValue {
+        match (self.column_type, field) {
+            (_, Field::Null) => DynamicValue::Null,
+            (ColumnType::Bool, Field::Bool(value)) => DynamicValue::Bool(*value),
+            (ColumnType::I32, Field::Byte(value)) => DynamicValue::I32((*value).into()),
+            (ColumnType::I32, Field::Short(value)) => DynamicValue::I32((*value).into()),
+            (ColumnType::I32, Field::Int(value)) => DynamicValue::I32(*value),
+            (ColumnType::I64, Field::Long(value)) => DynamicValue::I64(*value),
+            (ColumnType::U64, Field::ULong(value)) => DynamicValue::U64(*value),
+            (ColumnType::F32, Field::Float(value)) => DynamicValue::F32(*value),
+            (ColumnType::F64, Field::Double(value)) => DynamicValue::F64(*value),
+            (ColumnType::Timestamp, Field::TimestampMillis(epoch)) => DateTime::from_timestamp_millis(*epoch).into(),
+            (ColumnType::Timestamp, Field::TimestampMicros(epoch)) => DateTime::from_timestamp_micros(*epoch).into(),
+            (ColumnType::Timestamp, Field::Long(epoch)) => match self.unit {
+                Some(TimeUnit::NANOS(_)) => DynamicValue::Timestamp(DateTime::from_timestamp_nanos(*epoch)),
+                Some(TimeUnit::MICROS(_)) => DateTime::from_timestamp_micros(*epoch).into(),
+                Some(TimeUnit::MILLIS(_)) | None => DateTime::from_timestamp_millis(*epoch).into(),
+            },
+            (ColumnType::String, Field::Str(value)) => DynamicValue::String(value.clone()),
+            (ColumnType::String, field) => DynamicValue::String(field.to_string()),
+            _ => DynamicValue::Null,
+        }
+    }
+}
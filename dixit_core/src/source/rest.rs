@@ -0,0 +1,104 @@
+//! Polls HTTP endpoints at a fixed interval and forwards the responses as records.
+//!
+//! ## Features
+//! - Requests every configured URL on each tick using `reqwest`.
+//! - Deserializes responses holding either a single item or a list of items.
+//! - Drops items whose key was already seen, so unchanged snapshots are only persisted once.
+
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tokio::sync::mpsc::Sender;
+use tokio::time;
+use tracing::warn;
+
+use crate::model::{Record, SkipReason};
+
+/// Number of keys remembered for deduplication, oldest keys are forgotten first.
+const SEEN_CAPACITY: usize = 10_000;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+pub async fn run<T, K>(
+    tx: Sender<Record>,
+    urls: Vec<String>,
+    interval: Duration,
+    key_fn: impl Fn(&T) -> K,
+    record_fn: impl Fn(T) -> Record,
+) -> Result<()>
+where
+    T: DeserializeOwned,
+    K: Eq + Hash + Clone,
+{
+    let client = reqwest::Client::new();
+    let mut timer = time::interval(interval);
+    let mut seen = Seen::default();
+
+    loop {
+        timer.tick().await;
+        for url in urls.iter() {
+            let items = match poll::<T>(&client, url).await {
+                Ok(items) => items,
+                Err(e) => {
+                    // a failed poll is retried on the next tick rather than stopping the source
+                    warn!("failed to poll {url}: {e}");
+                    tx.send(Record::skip(SkipReason::Other, format!("failed to poll {url}")))
+                        .await?;
+                    continue;
+                }
+            };
+            for item in items {
+                if seen.insert(key_fn(&item)) {
+                    tx.send(record_fn(item)).await?;
+                }
+            }
+        }
+    }
+}
+
+async fn poll<T: DeserializeOwned>(client: &reqwest::Client, url: &str) -> Result<Vec<T>> {
+    let response = client.get(url).send().await?.error_for_status()?;
+    Ok(match serde_json::from_str::<OneOrMany<T>>(&response.text().await?)? {
+        OneOrMany::One(item) => vec![item],
+        OneOrMany::Many(items) => items,
+    })
+}
+
+struct Seen<K> {
+    keys: HashSet<K>,
+    order: VecDeque<K>,
+}
+
+impl<K> Default for Seen<K> {
+    fn default() -> Self {
+        Self {
+            keys: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone> Seen<K> {
+    /// Returns `true` if the key was not seen before.
+    fn insert(&mut self, key: K) -> bool {
+        if !self.keys.insert(key.clone()) {
+            return false;
+        }
+        self.order.push_back(key);
+        if self.order.len() > SEEN_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.keys.remove(&oldest);
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,265 @@
+//! Integrates the recorder with systemd and the Windows service control manager, so the service manager knows when
+//! it is ready and restarts it once the feed goes stale.
+//!
+//! ## Features
+//! - Sends `READY=1` once the sources are launched and `STOPPING=1` once the feed ends, over the `sd_notify`
+//!   protocol (a datagram to `$NOTIFY_SOCKET`, abstract sockets included).
+//! - Detects a stale feed, no record received for `--stale-after` by the local clock of the [`Freshness`] marked by
+//!   the persister, logging when it goes stale and recovers.
+//! - Pings the watchdog (`WATCHDOG=1`) at half the `WatchdogSec` of the unit while the feed is fresh, so systemd
+//!   restarts a recorder whose feed is stale as well as a hung one.
+//! - Does nothing without `$NOTIFY_SOCKET` (e.g. not started by systemd), beyond logging.
+//! - Runs the recorder as a Windows service with `--windows-service` (see [`windows::run`]), reporting it running
+//!   once ready and ending the recording as if the feed ended when the service is stopped.
+//!
+//! ## Example
+//! ```ini
+//! [Service]
+//! Type=notify
+//! WatchdogSec=2min
+//! Restart=on-failure
+//! ExecStart=/usr/local/bin/dixit --venue okx --stale-after 1m
+//! ```
+
+use std::env;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tokio::time;
+use tracing::{info, warn};
+
+use crate::clock::SharedClock;
+
+/// requested by the service manager, ends the recording once notified
+static SHUTDOWN: Notify = Notify::const_new();
+
+/// Time of the last record received from the venue, shared by the persister marking it and the supervisor.
+#[derive(Debug)]
+pub struct Freshness {
+    clock: SharedClock,
+    /// local time of the last record received, in milliseconds since the epoch
+    last_record: AtomicI64,
+}
+
+impl Freshness {
+    pub fn new(clock: SharedClock) -> Self {
+        Self {
+            clock,
+            last_record: AtomicI64::new(0),
+        }
+    }
+
+    /// Marks the feed as fresh, called for every record received from the venue.
+    pub fn record_received(&self) {
+        self.last_record
+            .store(self.clock.now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    /// Time since the last record received, by the local clock.
+    pub fn staleness(&self) -> Duration {
+        let elapsed = self.clock.now().timestamp_millis() - self.last_record.load(Ordering::Relaxed);
+        Duration::from_millis(elapsed.max(0) as u64)
+    }
+}
+
+/// Notifies the service manager that the recorder is ready, then checks the feed until aborted, pinging the
+/// watchdog while it is fresh.
+pub async fn run(freshness: Arc<Freshness>, stale_after: Duration) {
+    // the feed is fresh until it had the time to deliver its first record
+    freshness.record_received();
+    notify("READY=1");
+
+    let watchdog = watchdog_interval();
+    let period = watchdog.map_or(stale_after, |watchdog| watchdog.min(stale_after));
+    let mut timer = time::interval(period);
+    let mut stale = false;
+    loop {
+        timer.tick().await;
+        let staleness = freshness.staleness();
+        if staleness < stale_after {
+            if stale {
+                info!("feed recovered");
+                notify("STATUS=recording");
+            }
+            stale = false;
+            if watchdog.is_some() {
+                notify("WATCHDOG=1");
+            }
+        } else if !stale {
+            warn!("feed stale, no record for {}s", staleness.as_secs());
+            notify(&format!("STATUS=feed stale, no record for {}s", staleness.as_secs()));
+            stale = true;
+        }
+    }
+}
+
+/// Notifies the service manager that the recorder is stopping, called once the feed ended, the persister still
+/// flushing the last files.
+pub fn stopping() {
+    notify("STOPPING=1");
+}
+
+/// Asks the recorder to end the recording, as if the feed ended, e.g. when the Windows service is stopped.
+pub fn request_shutdown() {
+    SHUTDOWN.notify_one();
+}
+
+/// Completes once [`request_shutdown`] is called, even if called before.
+pub async fn shutdown_requested() {
+    SHUTDOWN.notified().await;
+}
+
+/// Half the watchdog timeout set by systemd, if the watchdog is enabled for this process.
+fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid != std::process::id().to_string() {
+            return None;
+        }
+    }
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    Some(Duration::from_micros(usec / 2)).filter(|interval| !interval.is_zero())
+}
+
+#[cfg(unix)]
+fn notify(state: &str) {
+    use std::os::unix::net::UnixDatagram;
+
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let sent = UnixDatagram::unbound().and_then(|socket| {
+        let path = path.to_string_lossy();
+        match path.strip_prefix('@') {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                socket.send_to_addr(state.as_bytes(), &address)
+            }
+            _ => socket.send_to(state.as_bytes(), &*path),
+        }
+    });
+    if let Err(e) = sent {
+        warn!("failed to notify {state} to the service manager: {e}");
+    }
+}
+
+#[cfg(windows)]
+fn notify(state: &str) {
+    windows::notify(state);
+}
+
+#[cfg(not(any(unix, windows)))]
+fn notify(_state: &str) {}
+
+/// Runs the recorder under the Windows service control manager.
+///
+/// ## Example
+/// ```text
+/// sc create dixit binPath= "C:\dixit\dixit.exe --windows-service --venue okx --stale-after 1m"
+/// sc failure dixit reset= 86400 actions= restart/5000
+/// ```
+#[cfg(windows)]
+pub mod windows {
+    use std::ffi::OsString;
+    use std::sync::OnceLock;
+    use std::time::Duration;
+
+    use anyhow::{anyhow, Result};
+    use tracing::{error, info, warn};
+    use windows_service::service::{
+        ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType,
+    };
+    use windows_service::service_control_handler::{self, ServiceControlHandlerResult, ServiceStatusHandle};
+    use windows_service::{define_windows_service, service_dispatcher};
+
+    use crate::config::Args;
+
+    /// name the service is registered with, ignored by the manager for a service running in its own process
+    const SERVICE_NAME: &str = "dixit";
+    /// time the manager waits for the recorder to start or stop before considering it hung
+    const WAIT_HINT: Duration = Duration::from_secs(60);
+
+    /// arguments of the recorder, the manager only passes the start parameters to the service
+    static ARGS: OnceLock<Args> = OnceLock::new();
+    static STATUS: OnceLock<ServiceStatusHandle> = OnceLock::new();
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    /// Records with `args` once the manager starts the service, blocking until it is stopped.
+    pub fn run(args: Args) -> Result<()> {
+        ARGS.set(args).map_err(|_| anyhow!("windows service already running"))?;
+        service_dispatcher::start(SERVICE_NAME, ffi_service_main)?;
+        Ok(())
+    }
+
+    fn service_main(_arguments: Vec<OsString>) {
+        let exit_code = match serve() {
+            Ok(()) => 0,
+            Err(e) => {
+                error!("windows service error: {e}");
+                1
+            }
+        };
+        set_state(ServiceState::Stopped, exit_code);
+    }
+
+    fn serve() -> Result<()> {
+        let args = ARGS
+            .get()
+            .cloned()
+            .ok_or_else(|| anyhow!("windows service started without arguments"))?;
+        let handler = |control: ServiceControl| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                info!("windows service stop requested");
+                super::request_shutdown();
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        };
+        let status = service_control_handler::register(SERVICE_NAME, handler)?;
+        let _ = STATUS.set(status);
+        set_state(ServiceState::StartPending, 0);
+
+        tokio::runtime::Runtime::new()?.block_on(crate::run_recorder(args))
+    }
+
+    /// Reports the `sd_notify` states the manager has an equivalent for.
+    pub(super) fn notify(state: &str) {
+        match state {
+            "READY=1" => set_state(ServiceState::Running, 0),
+            "STOPPING=1" => set_state(ServiceState::StopPending, 0),
+            _ => {}
+        }
+    }
+
+    fn set_state(state: ServiceState, exit_code: u32) {
+        // not started by the manager (e.g. run from a console)
+        let Some(status) = STATUS.get() else {
+            return;
+        };
+        let controls_accepted = match state {
+            ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            _ => ServiceControlAccept::empty(),
+        };
+        let wait_hint = match state {
+            ServiceState::StartPending | ServiceState::StopPending => WAIT_HINT,
+            _ => Duration::ZERO,
+        };
+        let reported = status.set_service_status(ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted,
+            exit_code: ServiceExitCode::Win32(exit_code),
+            checkpoint: 0,
+            wait_hint,
+            process_id: None,
+        });
+        if let Err(e) = reported {
+            warn!("failed to report {state:?} to the service control manager: {e}");
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Maps the symbols of the venues (e.g. `BTC-USD`, `XBT/USD`, `BTCUSDT`) to canonical instruments, so the normalized
+//! tables of different venues can be joined on the same instrument.
+//!
+//! ## Features
+//! - Built-in mappings for the default symbols of every venue, extended or overridden by a YAML file
+//!   (`--symbology`) keyed by venue.
+//! - Canonical instruments are `BASE-QUOTE` for spot pairs and indices, suffixed with `-PERP` for perpetual swaps,
+//!   and the ticker for equities.
+//! - Sets the `instrument` column of the normalized tables (funding, open interest, index, liquidations, candles,
+//!   bars, reference data and the stock tables), left null for the symbols without mapping.
+//! - Persists the mappings of the venue recorded to the `_symbology` table on startup, alongside the data.
+//!
+//! ## Example
+//! ```yaml
+//! okx:
+//!   SOL-USDT-SWAP: SOL-USDT-PERP
+//! binance-futures:
+//!   SOLUSDT: SOL-USDT-PERP
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use dixit_persist_macros::Persist;
+
+/// Mappings of the default symbols, as `(venue, symbol, instrument)`.
+const DEFAULTS: &[(&str, &str, &str)] = &[
+    ("coinbase", "BTC-USD", "BTC-USD"),
+    ("coinbase", "ETH-USD", "ETH-USD"),
+    ("bitfinex", "tBTCUSD", "BTC-USD"),
+    ("bitfinex", "tETHUSD", "ETH-USD"),
+    ("binance-futures", "BTCUSDT", "BTC-USDT-PERP"),
+    ("binance-futures", "ETHUSDT", "ETH-USDT-PERP"),
+    ("okx", "BTC-USDT-SWAP", "BTC-USDT-PERP"),
+    ("okx", "ETH-USDT-SWAP", "ETH-USDT-PERP"),
+    ("okx", "BTC-USDT", "BTC-USDT"),
+    ("okx", "ETH-USDT", "ETH-USDT"),
+    ("bybit", "BTCUSDT", "BTC-USDT-PERP"),
+    ("bybit", "ETHUSDT", "ETH-USDT-PERP"),
+    ("deribit", "BTC-PERPETUAL", "BTC-USD-PERP"),
+    ("deribit", "ETH-PERPETUAL", "ETH-USD-PERP"),
+    ("mexc", "BTCUSDT", "BTC-USDT"),
+    ("mexc", "ETHUSDT", "ETH-USDT"),
+    ("gate", "BTC_USDT", "BTC-USDT"),
+    ("gate", "ETH_USDT", "ETH-USDT"),
+    ("upbit", "KRW-BTC", "BTC-KRW"),
+    ("upbit", "KRW-ETH", "ETH-KRW"),
+    ("bithumb", "BTC_KRW", "BTC-KRW"),
+    ("bithumb", "ETH_KRW", "ETH-KRW"),
+    ("polygon", "AAPL", "AAPL"),
+    ("polygon", "MSFT", "MSFT"),
+    ("fix", "XBT/USD", "BTC-USD"),
+    ("fix", "ETH/USD", "ETH-USD"),
+];
+
+/// Canonical instrument of each symbol, by venue.
+#[derive(Debug, Clone)]
+pub struct Symbology {
+    venues: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// A symbol of a venue and its canonical instrument, persisted to the `_symbology` table.
+#[derive(Debug, Persist)]
+pub struct Mapping {
+    #[persist(intern)]
+    pub venue: String,
+    pub symbol: String,
+    pub instrument: String,
+}
+
+impl Default for Symbology {
+    fn default() -> Self {
+        let mut venues = BTreeMap::<String, BTreeMap<String, String>>::new();
+        for (venue, symbol, instrument) in DEFAULTS {
+            venues
+                .entry(venue.to_string())
+                .or_default()
+                .insert(symbol.to_string(), instrument.to_string());
+        }
+        Self { venues }
+    }
+}
+
+impl Symbology {
+    /// The built-in mappings, extended or overridden by the mappings of the file if any.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut symbology = Self::default();
+        let Some(path) = path else {
+            return Ok(symbology);
+        };
+        let content = fs::read_to_string(path).with_context(|| format!("cannot read {}", path.display()))?;
+        let venues = serde_yaml::from_str::<BTreeMap<String, BTreeMap<String, String>>>(&content)
+            .with_context(|| format!("invalid symbology {}", path.display()))?;
+        for (venue, mappings) in venues {
+            symbology.venues.entry(venue).or_default().extend(mappings);
+        }
+        Ok(symbology)
+    }
+
+    pub fn instrument(&self, venue: &str, symbol: &str) -> Option<String> {
+        self.venues.get(venue)?.get(symbol).cloned()
+    }
+
+    /// The mappings of the venue, ordered by symbol.
+    pub fn mappings(&self, venue: &str) -> Vec<Mapping> {
+        self.venues
+            .get(venue)
+            .into_iter()
+            .flatten()
+            .map(|(symbol, instrument)| Mapping {
+                venue: venue.to_owned(),
+                symbol: symbol.clone(),
+                instrument: instrument.clone(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,135 @@
+//! Contains Upbit-specific WebSocket handling logic.
+//!
+//! ## Features
+//! - Subscribes to the trades of the KRW markets (e.g. `KRW-BTC`) on the Upbit feed, whose requests are a JSON array
+//!   of a ticket, the subscribed types and the format.
+//! - Parses the trades, sent as binary frames holding JSON, into normalized trade records persisted to the `trades`
+//!   table, for KRW premium analysis against the venues quoting in USD.
+//! - Sends a `PING` request every minute, Upbit closing the connections idle for two minutes.
+//!
+//! A request replaces the subscriptions of the connection, so the [`Subscriber`] keeps the symbols subscribed and
+//! every change requests all of them. Unsubscribing every symbol is not possible, they stay subscribed until the next
+//! connection.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{from_slice, json};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::model::{Record, Severity, SkipReason, Trade, VenueData};
+
+pub const EXCHANGE: &str = "upbit";
+pub const WS_URL: &str = "wss://api.upbit.com/websocket/v1";
+pub const SYMBOLS: &[&str] = &["KRW-BTC", "KRW-ETH"];
+
+/// Interval between the `PING` requests keeping the connection open.
+pub const PING_INTERVAL: Duration = Duration::from_secs(60);
+
+pub fn ping(_now: DateTime<Utc>) -> Message {
+    Message::Text("PING".to_owned())
+}
+
+/// Symbols subscribed on the connection.
+#[derive(Default)]
+pub struct Subscriber {
+    symbols: Mutex<Vec<String>>,
+}
+
+impl Subscriber {
+    pub fn subscribe(&self, symbols: &[String]) -> Vec<Message> {
+        let mut subscribed = self.symbols.lock().expect("symbols poisoned");
+        for symbol in symbols {
+            if !subscribed.contains(symbol) {
+                subscribed.push(symbol.clone());
+            }
+        }
+        request(&subscribed)
+    }
+
+    pub fn unsubscribe(&self, symbols: &[String]) -> Vec<Message> {
+        let mut subscribed = self.symbols.lock().expect("symbols poisoned");
+        subscribed.retain(|symbol| !symbols.contains(symbol));
+        request(&subscribed)
+    }
+}
+
+fn request(symbols: &[String]) -> Vec<Message> {
+    if symbols.is_empty() {
+        return vec![];
+    }
+    let request = json!([
+        { "ticket": format!("dixit-{}", std::process::id()) },
+        { "type": "trade", "codes": symbols },
+        { "format": "DEFAULT" }
+    ]);
+    vec![Message::Text(request.to_string())]
+}
+
+pub fn handle(message: Message) -> Vec<Record> {
+    let bytes = match message {
+        Message::Binary(bytes) => bytes,
+        Message::Text(string) => string.into_bytes(),
+        _ => return vec![Record::skip(SkipReason::NonText, "no data")],
+    };
+    if let Ok(trade) = from_slice::<TradeData>(&bytes) {
+        if trade.kind == "trade" {
+            return vec![parse_trade(trade)];
+        }
+    } else if let Ok(response) = from_slice::<ErrorResponse>(&bytes) {
+        // e.g. an invalid request, the connection is kept
+        return vec![Record::error(
+            EXCHANGE,
+            &response.error.name,
+            Severity::Warning,
+            response.error.message,
+            "request rejected".to_owned(),
+        )];
+    }
+    // e.g. the {"status":"UP"} answer to the pings
+    vec![Record::unhandled(String::from_utf8_lossy(&bytes).into_owned())]
+}
+
+/// Upbit gives the side of the taker order as `ASK` (sell) or `BID` (buy).
+fn parse_trade(trade: TradeData) -> Record {
+    let side = if trade.ask_bid == "BID" { "buy" } else { "sell" };
+    Record::data(
+        EXCHANGE,
+        "trades",
+        &trade.code,
+        VenueData::Trade(Trade {
+            symbol: trade.code.clone(),
+            instrument: None,
+            time: trade.trade_timestamp,
+            price: trade.trade_price,
+            size: trade.trade_volume,
+            side: side.to_owned(),
+        }),
+    )
+}
+
+#[derive(Deserialize, Debug)]
+struct TradeData {
+    #[serde(rename = "type")]
+    kind: String,
+    code: String,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    trade_timestamp: DateTime<Utc>,
+    trade_price: Decimal,
+    trade_volume: Decimal,
+    ask_bid: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorDetail {
+    name: String,
+    message: String,
+}
@@ -0,0 +1,262 @@
+//! Uploads the recorded tables to an object store (e.g. `s3://bucket/prefix`), after recording with `--upload` or
+//! with the `sync` subcommand reconciling an output tree with the bucket (`s3` feature).
+//!
+//! ## Features
+//! - Mirrors the files of every venue directory under the prefix, keyed by their path relative to the output
+//!   directory (e.g. `prefix/coinbase/trades/000000000.parquet`), uploading the same file always to the same key.
+//! - Records every upload in the `_uploads.json` manifest of the venue directory: the size, SHA-256 checksum and
+//!   etag of the file, saved after each upload so a run failing halfway keeps the files already uploaded.
+//! - Skips the files whose size and checksum match the manifest and whose object still has the recorded size and
+//!   etag, uploads the new or changed files and the ones missing or replaced in the bucket.
+//! - Leaves out the files not finalized yet of the tables written with `--finalize-markers`, uploading a file before
+//!   its marker.
+//! - Reports the objects of the bucket without a local file, which are never deleted.
+//! - Takes the S3 credentials and region from the `AWS_*` environment variables (e.g. `AWS_ACCESS_KEY_ID`,
+//!   `AWS_REGION`, `AWS_ENDPOINT`), `file://` and `memory://` urls are supported too.
+//!
+//! ## Example
+//! ```sh
+//! dixit sync --directory output s3://bucket/dixit
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use dixit_persist::checksum;
+use dixit_persist::finalize;
+use dixit_persist::lock::LOCK_FILE;
+use futures::TryStreamExt;
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectMeta, ObjectStore, PutPayload};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+use url::Url;
+
+pub const MANIFEST_FILE: &str = "_uploads.json";
+
+/// Files uploaded from a venue directory, by path relative to the directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UploadManifest {
+    pub files: BTreeMap<String, Uploaded>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Uploaded {
+    pub size: u64,
+    pub sha256: String,
+    /// etag of the object, if returned by the store
+    pub etag: Option<String>,
+}
+
+impl UploadManifest {
+    pub fn load(directory: &Path) -> Result<Self> {
+        let path = directory.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        serde_json::from_slice(&fs::read(&path)?).with_context(|| format!("invalid {}", path.display()))
+    }
+
+    /// Replaces the manifest atomically, written aside then renamed.
+    pub fn save(&self, directory: &Path) -> Result<()> {
+        let path = directory.join(MANIFEST_FILE);
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(temp, path)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SyncReport {
+    pub uploaded: usize,
+    /// bytes of the uploaded files
+    pub bytes: u64,
+    /// files already in the bucket
+    pub skipped: usize,
+    /// files failing to upload, with the reason
+    pub failed: Vec<(String, String)>,
+    /// keys of the objects without a local file
+    pub remote_only: Vec<String>,
+}
+
+impl SyncReport {
+    fn merge(&mut self, other: SyncReport) {
+        self.uploaded += other.uploaded;
+        self.bytes += other.bytes;
+        self.skipped += other.skipped;
+        self.failed.extend(other.failed);
+        self.remote_only.extend(other.remote_only);
+    }
+}
+
+pub struct Uploader {
+    store: Arc<dyn ObjectStore>,
+    prefix: ObjectPath,
+    /// output directory holding the venue directories
+    directory: PathBuf,
+}
+
+impl Uploader {
+    /// Opens the store of the url, configured from the `AWS_*` environment variables for `s3://` urls.
+    pub fn new(url: &str, directory: &Path) -> Result<Self> {
+        let url = Url::parse(url).with_context(|| format!("invalid upload url {url}"))?;
+        let options = std::env::vars()
+            .filter(|(key, _)| key.starts_with("AWS_"))
+            .map(|(key, value)| (key.to_ascii_lowercase(), value));
+        let (store, prefix) = object_store::parse_url_opts(&url, options)?;
+        Ok(Self::with_store(Arc::from(store), prefix, directory))
+    }
+
+    pub fn with_store(store: Arc<dyn ObjectStore>, prefix: ObjectPath, directory: &Path) -> Self {
+        Self {
+            store,
+            prefix,
+            directory: directory.to_owned(),
+        }
+    }
+
+    /// Reconciles every venue directory of the output directory with the bucket.
+    pub async fn sync(&self) -> Result<SyncReport> {
+        let mut report = SyncReport::default();
+        let mut entries = fs::read_dir(&self.directory)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            if entry.file_type()?.is_dir() {
+                report.merge(self.sync_venue(&entry.file_name().to_string_lossy()).await?);
+            }
+        }
+        Ok(report)
+    }
+
+    /// Reconciles the directory of a venue with the bucket, uploading its new and changed files.
+    pub async fn sync_venue(&self, venue: &str) -> Result<SyncReport> {
+        let directory = self.directory.join(venue);
+        let mut manifest = UploadManifest::load(&directory)?;
+        let prefix = self.prefix.child(venue);
+        let mut remote = self
+            .store
+            .list(Some(&prefix))
+            .map_ok(|meta| (meta.location.to_string(), meta))
+            .try_collect::<HashMap<_, _>>()
+            .await?;
+
+        let mut report = SyncReport::default();
+        for file in files(&directory)? {
+            let key = ObjectPath::from_iter(prefix.parts().chain(file.split('/').map(Into::into)));
+            let object = remote.remove(key.as_ref());
+            let path = directory.join(&file);
+            let local = Uploaded {
+                size: fs::metadata(&path)?.len(),
+                sha256: checksum::sha256(&path)?,
+                etag: None,
+            };
+            if manifest
+                .files
+                .get(&file)
+                .is_some_and(|uploaded| is_uploaded(uploaded, &local, object.as_ref()))
+            {
+                report.skipped += 1;
+                continue;
+            }
+            match self.upload(&path, &key).await {
+                Ok(etag) => {
+                    info!("uploaded {key}");
+                    report.uploaded += 1;
+                    report.bytes += local.size;
+                    manifest.files.insert(file, Uploaded { etag, ..local });
+                    manifest.save(&directory)?;
+                }
+                Err(e) => {
+                    error!("cannot upload {key}: {e}");
+                    report.failed.push((file, e.to_string()));
+                }
+            }
+        }
+        for key in remote.into_keys() {
+            warn!("{key} has no local file");
+            report.remote_only.push(key);
+        }
+        Ok(report)
+    }
+
+    async fn upload(&self, path: &Path, key: &ObjectPath) -> Result<Option<String>> {
+        let payload = PutPayload::from(fs::read(path)?);
+        Ok(self.store.put(key, payload).await?.e_tag)
+    }
+}
+
+/// Whether the file recorded in the manifest is unchanged and its object still the uploaded one.
+fn is_uploaded(uploaded: &Uploaded, local: &Uploaded, object: Option<&ObjectMeta>) -> bool {
+    let Some(object) = object else {
+        return false;
+    };
+    uploaded.size == local.size
+        && uploaded.sha256 == local.sha256
+        && object.size == uploaded.size
+        && (uploaded.etag.is_none() || object.e_tag == uploaded.etag)
+}
+
+/// Files of a venue directory relative to it, with `/` separators, the lock and upload manifest left out.
+fn files(directory: &Path) -> Result<Vec<String>> {
+    let mut files = vec![];
+    if directory.exists() {
+        collect(directory, "", &mut files)?;
+    }
+    Ok(files)
+}
+
+fn collect(directory: &Path, relative: &str, files: &mut Vec<String>) -> Result<()> {
+    let mut entries = fs::read_dir(directory)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = if relative.is_empty() {
+            name.clone()
+        } else {
+            format!("{relative}/{name}")
+        };
+        if entry.file_type()?.is_dir() {
+            collect(&entry.path(), &path, files)?;
+        } else if name != LOCK_FILE && name != MANIFEST_FILE && !name.ends_with(".tmp") {
+            // the files being written are uploaded by a later sync, once finalized
+            if finalize::is_finalized(&entry.path()) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn log(report: &SyncReport) {
+    info!(
+        "uploaded {} files ({} bytes), {} already in the bucket, {} objects without a local file",
+        report.uploaded,
+        report.bytes,
+        report.skipped,
+        report.remote_only.len()
+    );
+}
+
+/// Uploads the directory of a venue after recording, with `--upload`.
+pub async fn upload(url: &str, directory: &Path, venue: &str) -> Result<()> {
+    let report = Uploader::new(url, directory)?.sync_venue(venue).await?;
+    log(&report);
+    if !report.failed.is_empty() {
+        bail!("{} files failed to upload", report.failed.len());
+    }
+    Ok(())
+}
+
+/// Reconciles an output tree with the bucket, with the `sync` subcommand.
+pub async fn run(url: &str, directory: &Path) -> Result<()> {
+    let report = Uploader::new(url, directory)?.sync().await?;
+    log(&report);
+    if !report.failed.is_empty() {
+        bail!("{} files failed to upload", report.failed.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,33 @@
+//! Verifies the recorded tables with the `verify` subcommand, e.g. before archiving or after copying an output tree.
+//!
+//! ## Features
+//! - Checks the `.sha256` sidecars written with `--checksum` and the footers of every Parquet file.
+//! - Reports every corrupted or truncated file and fails if there is any.
+//!
+//! ## Example
+//! ```sh
+//! dixit verify --directory output
+//! ```
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use dixit_persist::checksum;
+use tracing::{error, info};
+
+pub fn run(directory: &Path) -> Result<()> {
+    let report = checksum::verify(directory)?;
+    for corrupted in report.corrupted.iter() {
+        error!("{}: {}", corrupted.path.display(), corrupted.reason);
+    }
+    info!(
+        "verified {} files under {}, {} with a matching checksum",
+        report.files,
+        directory.display(),
+        report.checksums
+    );
+    if !report.is_valid() {
+        bail!("{} corrupted files", report.corrupted.len());
+    }
+    Ok(())
+}
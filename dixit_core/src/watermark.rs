@@ -0,0 +1,134 @@
+//! Tracks how far the records of a sequenced source are persisted, so a restart backfills the gap from there
+//! (`--watermark`).
+//!
+//! ## Features
+//! - The source sends a checkpoint once the records of a sequence number are sent, the persister acknowledges it once
+//!   those records are appended to the persist service.
+//! - The source stops reading once `--max-unacked` sequence numbers wait for their acknowledgment, so a slow persister
+//!   slows the source down rather than piling up records.
+//! - Flushes every table at `--watermark-interval`, then saves the [`Watermark`] of the records flushed to
+//!   `_watermark.json` in the venue directory, through a temporary file renamed over the previous one.
+//! - Supported by the FIX source, which logs on without resetting the sequence numbers after a restart, so the acceptor
+//!   resends the messages after the watermark.
+
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use dixit_persist::service::PersistCmd;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tokio::sync::{oneshot, watch};
+use tracing::info;
+
+pub const FILE: &str = "_watermark.json";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Watermark {
+    /// every record up to this sequence number included is persisted
+    pub received: u64,
+    /// last sequence number sent by the source, to resume its session
+    pub sent: u64,
+}
+
+impl Watermark {
+    /// The watermark saved by a previous run, if any.
+    pub fn load(venue_directory: &Path) -> Result<Option<Self>> {
+        let path = venue_directory.join(FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)?;
+        let watermark = serde_json::from_str(&content).with_context(|| format!("invalid {}", path.display()))?;
+        Ok(Some(watermark))
+    }
+
+    fn save(&self, venue_directory: &Path) -> Result<()> {
+        let path = venue_directory.join(FILE);
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&temporary, &path)?;
+        Ok(())
+    }
+}
+
+/// Acknowledgments of the checkpoints, held by the source.
+pub struct Acks {
+    appended: watch::Receiver<u64>,
+    max_unacked: u64,
+}
+
+impl Acks {
+    /// Whether the source waits for acknowledgments before reading past the sequence number `seq`.
+    pub fn throttled(&self, seq: u64) -> bool {
+        self.max_unacked > 0 && seq.saturating_sub(*self.appended.borrow()) >= self.max_unacked
+    }
+
+    /// Waits for the next acknowledgment, forever once the persister stopped.
+    pub async fn changed(&mut self) {
+        if self.appended.changed().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// Checkpoints appended and saved, held by the persister.
+pub struct Tracker {
+    interval: Duration,
+    appended: Watermark,
+    saved: Watermark,
+    acks: watch::Sender<u64>,
+}
+
+impl Tracker {
+    pub fn new(interval: Duration, max_unacked: u64) -> (Self, Acks) {
+        let (acks, appended) = watch::channel(0);
+        let tracker = Self {
+            interval,
+            appended: Watermark::default(),
+            saved: Watermark::default(),
+            acks,
+        };
+        (tracker, Acks { appended, max_unacked })
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Acknowledges a checkpoint, the records sent before it are appended to the persist service.
+    pub fn append(&mut self, seq: u64, watermark: Watermark) {
+        self.appended = watermark;
+        self.acks.send_replace(seq);
+    }
+
+    /// Flushes every table and saves the watermark appended before the flush, unless already saved.
+    pub async fn flush(&mut self, tx: &Sender<PersistCmd>, venue_directory: &Path) -> Result<()> {
+        if self.appended == self.saved {
+            return Ok(());
+        }
+        let watermark = self.appended;
+        let (reply, flushed) = oneshot::channel();
+        tx.send(PersistCmd::Flush(reply))
+            .await
+            .map_err(|_| anyhow!("persist service stopped"))?;
+        flushed.await??;
+        self.save(watermark, venue_directory)
+    }
+
+    /// Saves the last watermark appended, once the persist service closed every table.
+    pub fn finish(&mut self, venue_directory: &Path) -> Result<()> {
+        if self.appended == self.saved {
+            return Ok(());
+        }
+        self.save(self.appended, venue_directory)
+    }
+
+    fn save(&mut self, watermark: Watermark, venue_directory: &Path) -> Result<()> {
+        watermark.save(venue_directory)?;
+        info!("watermark saved at {}", watermark.received);
+        self.saved = watermark;
+        Ok(())
+    }
+}
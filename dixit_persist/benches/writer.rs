@@ -0,0 +1,138 @@
+//! Benchmarks of the append and flush paths, run with `cargo bench -p dixit_persist`.
+//!
+//! ## Features
+//! - `append`: appending a narrow record (a trade) and a wide one (an order book) to a `RowBuffer`.
+//! - `table_writer`: recording order books through a `TableWriter`, flushing to Parquet files every 100 rows.
+//! - `flush`: writing 10k buffered order books as a Parquet file in memory, for each compression codec.
+
+use std::sync::Arc;
+use std::{env, fs};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use dixit_persist::config::PersistConfig;
+use dixit_persist::row::RowBuffer;
+use dixit_persist::writer::TableWriter;
+use dixit_persist::Persistable;
+use dixit_persist_macros::Persist;
+use parquet::basic::{BrotliLevel, Compression, GzipLevel, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type;
+
+#[path = "../tests/orderbook.rs"]
+#[allow(dead_code)]
+mod orderbook;
+
+use orderbook::OrderBook;
+
+const FLUSHED_ROWS: usize = 10_000;
+
+#[derive(Debug, Clone, Persist)]
+struct Trade {
+    symbol: String,
+    #[persist_timestamp(unit = "ns")]
+    time: u64,
+    price: f64,
+    size: f64,
+    buy: bool,
+}
+
+fn append(c: &mut Criterion) {
+    let mut group = c.benchmark_group("append");
+    group.throughput(Throughput::Elements(1));
+
+    let trade = Trade {
+        symbol: "BTC-USD".to_owned(),
+        time: 1_700_000_000_000_000_000,
+        price: 50_000.0,
+        size: 0.1,
+        buy: true,
+    };
+    group.bench_function("trade", |b| append_record(b, &trade));
+    let book = OrderBook::random_instance(100, 200, 50.0);
+    group.bench_function("orderbook", |b| append_record(b, &book));
+
+    group.finish();
+}
+
+fn append_record<T: Persistable>(b: &mut criterion::Bencher, record: &T) {
+    let mut buffer = RowBuffer::default();
+    b.iter(|| {
+        // cleared as the table writer does on flush, so the buffer does not grow for the whole run
+        if buffer.len() >= FLUSHED_ROWS {
+            buffer.clear();
+        }
+        buffer.begin();
+        record.append(&mut buffer).unwrap();
+    });
+}
+
+fn table_writer(c: &mut Criterion) {
+    let mut directory = env::current_dir().unwrap();
+    directory.push("target");
+    directory.push("bench");
+    let config = PersistConfig::new(&directory, "orderbook");
+    let mut writer = TableWriter::new("orderbook", &config).unwrap();
+    let book = OrderBook::random_instance(100, 200, 50.0);
+
+    let mut group = c.benchmark_group("table_writer");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("orderbook", |b| {
+        b.iter(|| writer.begin().unwrap().record(&book).unwrap().end().unwrap())
+    });
+    group.finish();
+
+    drop(writer);
+    let _ = fs::remove_dir_all(directory);
+}
+
+fn flush(c: &mut Criterion) {
+    let mut fields = vec![];
+    OrderBook::schema(&mut fields, None, None, None);
+    let schema = Arc::new(Type::group_type_builder("schema").with_fields(fields).build().unwrap());
+    let books = (0..FLUSHED_ROWS)
+        .scan(OrderBook::random_instance(100, 200, 50.0), |book, _| {
+            *book = book.tick();
+            Some(book.clone())
+        })
+        .collect::<Vec<_>>();
+
+    let codecs = [
+        ("uncompressed", Compression::UNCOMPRESSED),
+        ("snappy", Compression::SNAPPY),
+        ("lz4_raw", Compression::LZ4_RAW),
+        ("gzip_6", Compression::GZIP(GzipLevel::default())),
+        ("brotli_1", Compression::BROTLI(BrotliLevel::try_new(1).unwrap())),
+        ("zstd_1", Compression::ZSTD(ZstdLevel::try_new(1).unwrap())),
+        ("zstd_9", Compression::ZSTD(ZstdLevel::try_new(9).unwrap())),
+    ];
+
+    let mut group = c.benchmark_group("flush");
+    group.throughput(Throughput::Elements(FLUSHED_ROWS as u64));
+    group.sample_size(20);
+    for (name, compression) in codecs {
+        let props = Arc::new(WriterProperties::builder().set_compression(compression).build());
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || {
+                    let mut buffer = RowBuffer::default();
+                    for book in &books {
+                        buffer.begin();
+                        book.append(&mut buffer).unwrap();
+                    }
+                    buffer
+                },
+                |mut buffer| {
+                    let mut writer = SerializedFileWriter::new(vec![], schema.clone(), props.clone()).unwrap();
+                    buffer.record(&mut writer).unwrap();
+                    writer.into_inner().unwrap()
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, append, table_writer, flush);
+criterion_main!(benches);
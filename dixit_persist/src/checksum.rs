@@ -0,0 +1,163 @@
+//! Writes `.sha256` sidecars next to the completed table files and verifies them, with the Parquet footers, across
+//! an output tree.
+//!
+//! Sidecars hold a single `sha256sum` line (`<hex digest>  <file name>`), so they can also be checked with
+//! `sha256sum -c`. Verification reports the files whose checksum does not match, the Parquet files whose footer
+//! cannot be read (e.g. truncated by a crash) and the sidecars left without their file, as well as the files of the
+//! marked tables without their finalization marker, see [`crate::finalize`].
+
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use sha2::{Digest, Sha256};
+
+use crate::error::PersistError;
+use crate::finalize;
+use crate::lock::LOCK_FILE;
+
+pub const EXTENSION: &str = "sha256";
+
+/// A file failing verification.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Corrupted {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct VerifyReport {
+    /// files checked, sidecars excluded
+    pub files: usize,
+    /// files whose checksum matched their sidecar
+    pub checksums: usize,
+    pub corrupted: Vec<Corrupted>,
+}
+
+impl VerifyReport {
+    pub fn is_valid(&self) -> bool {
+        self.corrupted.is_empty()
+    }
+}
+
+/// Path of the sidecar of `path`, e.g. `000000000.parquet.sha256`.
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".");
+    sidecar.push(EXTENSION);
+    PathBuf::from(sidecar)
+}
+
+/// Hex encoded SHA-256 digest of the file content.
+pub fn sha256(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    }))
+}
+
+/// Writes the sidecar of a completed file.
+pub fn write_sidecar(path: &Path) -> Result<(), PersistError> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    fs::write(sidecar_path(path), format!("{}  {name}\n", sha256(path)?))?;
+    Ok(())
+}
+
+/// Verifies every file under `directory`: sidecar checksums when present, and footers of the Parquet files.
+pub fn verify(directory: &Path) -> Result<VerifyReport, PersistError> {
+    let mut report = VerifyReport::default();
+    verify_directory(directory, &mut report)?;
+    Ok(report)
+}
+
+fn verify_directory(directory: &Path, report: &mut VerifyReport) -> Result<(), PersistError> {
+    let mut entries = fs::read_dir(directory)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            verify_directory(&path, report)?;
+            continue;
+        }
+        if entry.file_name() == LOCK_FILE || entry.file_name() == finalize::MARKERS_FILE {
+            continue;
+        }
+        let mut corrupted = |reason: String| {
+            report.corrupted.push(Corrupted {
+                path: path.clone(),
+                reason,
+            })
+        };
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(EXTENSION) => {
+                // the sidecar is checked with its file, only the ones left alone are reported here
+                if !path.with_extension("").exists() {
+                    corrupted("sidecar without its file".to_owned());
+                }
+            }
+            Some(finalize::EXTENSION) => {
+                if !path.with_extension("").exists() {
+                    corrupted("marker without its file".to_owned());
+                }
+            }
+            extension => {
+                if !finalize::is_finalized(&path) {
+                    corrupted("not finalized, no marker".to_owned());
+                }
+                if extension == Some("parquet") {
+                    if let Err(reason) = parquet_footer(&path) {
+                        corrupted(reason);
+                    }
+                }
+                if let Some(reason) = checksum(&path, report)? {
+                    report.corrupted.push(Corrupted { path, reason });
+                }
+                report.files += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the footer and the metadata of every row group.
+fn parquet_footer(path: &Path) -> Result<(), String> {
+    let reader = SerializedFileReader::new(File::open(path).map_err(|e| e.to_string())?)
+        .map_err(|e| format!("invalid parquet footer: {e}"))?;
+    let metadata = reader.metadata();
+    let rows: i64 = metadata.row_groups().iter().map(|group| group.num_rows()).sum();
+    if rows != metadata.file_metadata().num_rows() {
+        return Err(format!(
+            "row groups hold {rows} rows, the footer expects {}",
+            metadata.file_metadata().num_rows()
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the reason of a mismatch, if the file has a sidecar.
+fn checksum(path: &Path, report: &mut VerifyReport) -> Result<Option<String>, PersistError> {
+    let sidecar = sidecar_path(path);
+    if !sidecar.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&sidecar)?;
+    let expected = content.split_whitespace().next().unwrap_or_default();
+    let actual = sha256(path)?;
+    if expected != actual {
+        return Ok(Some(format!("checksum {actual} does not match the sidecar {expected}")));
+    }
+    report.checksums += 1;
+    Ok(None)
+}
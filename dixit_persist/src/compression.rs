@@ -0,0 +1,49 @@
+//! Compression of every value of the string and bytes columns marked `#[persist(compress = "zstd")]`, for large
+//! payloads (e.g. the raw messages archived) that page compression alone leaves too large.
+//!
+//! ## Features
+//! - Compresses each value on its own as a zstd frame, so a value is read back without the other values of its page.
+//! - Stores the compressed columns as plain `BYTE_ARRAY`, without the `String` logical type, as the frames are not
+//!   UTF-8, the nulls of the optional columns being kept as nulls.
+//! - Reads the values back with [`decompress`] (or `zstd -d` on a value dumped to a file).
+
+use parquet::basic::{Repetition, Type as PhysicalType};
+use parquet::data_type::ByteArray;
+use parquet::errors::ParquetError;
+use parquet::schema::types::{Type, TypePtr};
+
+use crate::error::PersistError;
+
+const LEVEL: i32 = 3;
+
+/// Compresses a value of a compressed column.
+pub fn compress(value: &[u8]) -> Result<ByteArray, ParquetError> {
+    zstd::bulk::compress(value, LEVEL)
+        .map(ByteArray::from)
+        .map_err(|e| ParquetError::External(Box::new(e)))
+}
+
+/// Decompresses a value of a compressed column.
+pub fn decompress(value: &[u8]) -> Result<Vec<u8>, PersistError> {
+    Ok(zstd::stream::decode_all(value)?)
+}
+
+/// Turns the string columns from `start` on into plain `BYTE_ARRAY` columns, for the columns of a compressed field.
+pub fn binary_columns(fields: &mut [TypePtr], start: usize) {
+    for field in fields[start..].iter_mut() {
+        if !field.is_primitive() || field.get_physical_type() != PhysicalType::BYTE_ARRAY {
+            continue;
+        }
+        let info = field.get_basic_info();
+        let repetition = if info.has_repetition() {
+            info.repetition()
+        } else {
+            Repetition::REQUIRED
+        };
+        *field = Type::primitive_type_builder(field.name(), PhysicalType::BYTE_ARRAY)
+            .with_repetition(repetition)
+            .build()
+            .expect("a plain BYTE_ARRAY column is valid")
+            .into();
+    }
+}
@@ -1,39 +1,355 @@
-use std::{collections::HashSet, fmt};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
 use serde::{Deserialize, Serialize};
 
+use crate::csv::CsvOptions;
+use crate::retry::RetryPolicy;
+
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct PersistConfig {
     #[serde(default)]
-    pub directory: String,
-    /// if set to true will append to existing files, when false will remove all existing parquet files
+    pub directory: PathBuf,
+    /// what to do with the files already in the table directories, appending to them by default
     #[serde(default)]
-    pub keep: bool,
+    pub overwrite: OverwriteMode,
     /// if set will only record tables matching that name, if empty will assume you want to persist everything
     #[serde(default)]
     pub tables: HashSet<String>,
+    /// layout of the table directories, plain parquet files by default
+    #[serde(default)]
+    pub format: Format,
+    /// format of specific tables, overriding the default format, a `*` matching any characters (e.g. `okx/trades_*`)
+    #[serde(default)]
+    pub table_formats: HashMap<String, Format>,
+    /// delimiter, quoting and compression of the csv tables
+    #[serde(default)]
+    pub csv: CsvOptions,
+    /// if set every recorded value is checked against the schema, reporting the column and row of a mismatch
+    #[serde(default)]
+    pub validate: bool,
+    /// maximum number of rows of the parquet row groups, if 0 every flush writes a single row group
+    #[serde(default)]
+    pub rows_per_row_group: usize,
+    /// if set parquet files are kept open across flushes, every flush appending row groups, and closed once they
+    /// hold this many rows (or when the writer is closed), if 0 every flush writes a new file
+    #[serde(default)]
+    pub rows_per_file: usize,
+    /// if set a `.sha256` sidecar is written next to every completed file, see [`crate::checksum`]
+    #[serde(default)]
+    pub checksum: bool,
+    /// if set a `.success` marker is written next to every file once completed, after its checksum and table log
+    /// commit, the readers of the table skipping the files without one, see [`crate::finalize`]
+    #[serde(default)]
+    pub finalize_markers: bool,
+    /// if set a checkpoint of the transaction log of the delta tables is written every this many commits (e.g. 10),
+    /// see [`crate::delta`]
+    #[serde(default)]
+    pub delta_checkpoint_interval: u64,
+    /// naming of the table files, sequential indexes by default
+    #[serde(default)]
+    pub file_naming: FileNaming,
+    /// if set a table directory locked by another writer is waited for up to this duration, otherwise creating its
+    /// writer fails at once
+    #[serde(default)]
+    pub lock_timeout: Option<Duration>,
+    /// if set the JSON lines and CSV files are zstd compressed (`.jsonl.zst` and `.csv.zst`, overriding `csv.gzip`)
+    /// with a dictionary trained from the first flush of each table, see [`crate::dictionary`], parquet pages are
+    /// compressed without
+    #[serde(default)]
+    pub zstd_dictionary: bool,
+    /// columns persisted of specific tables, the other columns being dropped, every column by default, a `*`
+    /// matching any characters in the table and column names (e.g. `okx/trades_*` or `tob_*`)
+    #[serde(default)]
+    pub include_columns: HashMap<String, Vec<String>>,
+    /// columns dropped from specific tables (e.g. the raw payloads), after `include_columns`
+    #[serde(default)]
+    pub exclude_columns: HashMap<String, Vec<String>>,
+    /// if set the null counts, distinct counts and bounds of the columns of every flush are logged, see
+    /// [`crate::stats`]
+    #[serde(default)]
+    pub column_stats: bool,
+    /// if set the time range of this timestamp column (e.g. `time`) in every file is kept in the `_index.json` of
+    /// the table directory, see [`crate::index`]
+    #[serde(default)]
+    pub time_index: Option<String>,
+    /// retries of the flushes failing on io errors, and where their rows are dumped once exhausted, see
+    /// [`crate::retry`]
+    #[serde(default)]
+    pub flush_retry: RetryPolicy,
+    /// if set the rows of a flush failing on io errors once its retries are exhausted are spilled to this local
+    /// directory and written to the table once its output recovers, see [`crate::spill`]
+    #[serde(default)]
+    pub spill_directory: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// parquet files only
+    #[default]
+    Parquet,
+    /// parquet files committed to a Delta Lake transaction log
+    Delta,
+    /// parquet files committed as Apache Iceberg snapshots
+    Iceberg,
+    /// JSON lines files, one object per row
+    Jsonl,
+    /// CSV files with a header, see [`CsvOptions`]
+    Csv,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Parquet | Format::Delta | Format::Iceberg => "parquet",
+            Format::Jsonl => "jsonl",
+            Format::Csv => "csv",
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Parquet => write!(f, "parquet"),
+            Format::Delta => write!(f, "delta"),
+            Format::Iceberg => write!(f, "iceberg"),
+            Format::Jsonl => write!(f, "jsonl"),
+            Format::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "parquet" => Ok(Format::Parquet),
+            "delta" => Ok(Format::Delta),
+            "iceberg" => Ok(Format::Iceberg),
+            "jsonl" => Ok(Format::Jsonl),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!(
+                "unknown format {s}, expected parquet, delta, iceberg, jsonl or csv"
+            )),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverwriteMode {
+    /// keeps the files already in the table directory, the new files are added next to them
+    #[default]
+    Append,
+    /// fails if the table directory already holds files
+    FailIfExists,
+    /// deletes the content of the table directory first, logging every deleted entry
+    Purge,
+}
+
+impl fmt::Display for OverwriteMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverwriteMode::Append => write!(f, "append"),
+            OverwriteMode::FailIfExists => write!(f, "fail-if-exists"),
+            OverwriteMode::Purge => write!(f, "purge"),
+        }
+    }
+}
+
+impl FromStr for OverwriteMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "append" => Ok(OverwriteMode::Append),
+            "fail-if-exists" => Ok(OverwriteMode::FailIfExists),
+            "purge" => Ok(OverwriteMode::Purge),
+            _ => Err(format!(
+                "unknown overwrite mode {s}, expected append, fail-if-exists or purge"
+            )),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileNaming {
+    /// sequential indexes (e.g. `000000042.parquet`), continuing after the files already in the table directory
+    #[default]
+    Index,
+    /// UTC time of the file creation and process id (e.g. `20240101T120000.123456789Z-4242.parquet`), sorting
+    /// chronologically
+    Timestamp,
+    /// random UUID v4 (e.g. `67e55044-10b1-426f-9247-bb680e5fe0c8.parquet`), unique across hosts
+    Uuid,
+}
+
+impl fmt::Display for FileNaming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileNaming::Index => write!(f, "index"),
+            FileNaming::Timestamp => write!(f, "timestamp"),
+            FileNaming::Uuid => write!(f, "uuid"),
+        }
+    }
+}
+
+impl FromStr for FileNaming {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "index" => Ok(FileNaming::Index),
+            "timestamp" => Ok(FileNaming::Timestamp),
+            "uuid" => Ok(FileNaming::Uuid),
+            _ => Err(format!("unknown file naming {s}, expected index, timestamp or uuid")),
+        }
+    }
 }
 
 impl PersistConfig {
-    pub fn new(directory: &str, table: &str) -> Self {
+    pub fn new(directory: &Path, table: &str) -> Self {
         let mut tables = HashSet::new();
         tables.insert(table.to_owned());
 
         Self {
-            directory: directory.to_owned(),
-            keep: false,
+            directory: directory.to_path_buf(),
+            overwrite: OverwriteMode::default(),
             tables,
+            format: Format::default(),
+            table_formats: HashMap::new(),
+            csv: CsvOptions::default(),
+            validate: false,
+            rows_per_row_group: 0,
+            rows_per_file: 0,
+            checksum: false,
+            finalize_markers: false,
+            delta_checkpoint_interval: 0,
+            file_naming: FileNaming::default(),
+            lock_timeout: None,
+            zstd_dictionary: false,
+            include_columns: HashMap::new(),
+            exclude_columns: HashMap::new(),
+            column_stats: false,
+            time_index: None,
+            flush_retry: RetryPolicy::default(),
+            spill_directory: None,
+        }
+    }
+
+    /// Whether the table is written from the start, i.e. `tables` is empty or names it, whatever the directory.
+    pub fn is_table_enabled(&self, table: &str) -> bool {
+        self.tables.is_empty() || self.tables.contains(table)
+    }
+
+    pub fn format(&self, table: &str) -> Format {
+        table_entry(&self.table_formats, table).copied().unwrap_or(self.format)
+    }
+
+    /// Columns of the table selected by `include_columns` and `exclude_columns`.
+    pub fn columns(&self, table: &str) -> ColumnFilter {
+        ColumnFilter {
+            include: table_entry(&self.include_columns, table).cloned(),
+            exclude: table_entry(&self.exclude_columns, table).cloned().unwrap_or_default(),
+        }
+    }
+}
+
+/// Columns persisted of a table, see [`PersistConfig::columns`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ColumnFilter {
+    /// every column if unset
+    pub include: Option<Vec<String>>,
+    pub exclude: Vec<String>,
+}
+
+impl ColumnFilter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_none() && self.exclude.is_empty()
+    }
+
+    /// Flags the columns kept, in the order of `columns`.
+    pub fn kept(&self, columns: &[&str]) -> Vec<bool> {
+        columns
+            .iter()
+            .map(|column| {
+                let included = match &self.include {
+                    Some(include) => selects(include, column),
+                    None => true,
+                };
+                included && !selects(&self.exclude, column)
+            })
+            .collect()
+    }
+
+    /// Patterns of the filter matching none of `columns`, e.g. a misspelled column.
+    pub fn unmatched<'a>(&'a self, columns: &[&str]) -> Vec<&'a str> {
+        self.include
+            .iter()
+            .flatten()
+            .chain(&self.exclude)
+            .filter(|pattern| {
+                !columns
+                    .iter()
+                    .any(|column| selects(std::slice::from_ref(*pattern), column))
+            })
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// Entry of the table, by name or else by the first pattern matching it.
+fn table_entry<'a, T>(entries: &'a HashMap<String, T>, table: &str) -> Option<&'a T> {
+    entries.get(table).or_else(|| {
+        entries
+            .iter()
+            .find(|(pattern, _)| pattern.contains('*') && matches(pattern, table))
+            .map(|(_, entry)| entry)
+    })
+}
+
+/// Whether any of the patterns is the name or matches it.
+fn selects(patterns: &[String], name: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| pattern == name || (pattern.contains('*') && matches(pattern, name)))
+}
+
+/// Whether the table matches the pattern, each `*` of the pattern matching any characters.
+fn matches(pattern: &str, table: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = table.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts = parts.collect::<Vec<_>>();
+    let last = parts.pop().unwrap_or_default();
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
         }
     }
+    rest.len() >= last.len() && rest.ends_with(last)
 }
 
 impl fmt::Display for PersistConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "PersistConfig {{ directory: \"{}\", keep: {}, tables: {:?} }}",
-            self.directory,
-            self.keep,
+            "PersistConfig {{ directory: \"{}\", overwrite: {}, format: {}, tables: {:?} }}",
+            self.directory.display(),
+            self.overwrite,
+            self.format,
             if self.tables.is_empty() {
                 "all".to_string()
             } else {
@@ -0,0 +1,149 @@
+//! Writes buffered rows as CSV, with a header generated from the `Persistable` schema.
+//!
+//! Values are formatted as in the JSON lines output (timestamps as RFC 3339 strings), nulls are empty fields.
+//! The delimiter, the quoting and the gzip compression are set through [`CsvOptions`].
+
+use std::io::Write;
+use std::str::FromStr;
+
+use csv::{QuoteStyle, WriterBuilder};
+use parquet::schema::types::TypePtr;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::PersistError;
+use crate::jsonl;
+use crate::row::RowBuffer;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CsvOptions {
+    #[serde(default = "CsvOptions::default_delimiter")]
+    pub delimiter: char,
+    #[serde(default)]
+    pub quoting: Quoting,
+    /// if set to true files are gzip compressed and get a `.csv.gz` extension
+    #[serde(default)]
+    pub gzip: bool,
+}
+
+impl CsvOptions {
+    fn default_delimiter() -> char {
+        ','
+    }
+
+    pub fn extension(&self) -> &'static str {
+        if self.gzip {
+            "csv.gz"
+        } else {
+            "csv"
+        }
+    }
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: Self::default_delimiter(),
+            quoting: Quoting::default(),
+            gzip: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Quoting {
+    /// quote fields holding the delimiter, quotes or new lines
+    #[default]
+    Necessary,
+    /// quote every field
+    Always,
+    /// quote every field that is not a number
+    NonNumeric,
+    /// never quote, the output may not be parseable
+    Never,
+}
+
+impl FromStr for Quoting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "necessary" => Ok(Quoting::Necessary),
+            "always" => Ok(Quoting::Always),
+            "nonnumeric" => Ok(Quoting::NonNumeric),
+            "never" => Ok(Quoting::Never),
+            _ => Err(format!(
+                "unknown quoting {s}, expected necessary, always, nonnumeric or never"
+            )),
+        }
+    }
+}
+
+impl From<Quoting> for QuoteStyle {
+    fn from(quoting: Quoting) -> Self {
+        match quoting {
+            Quoting::Necessary => QuoteStyle::Necessary,
+            Quoting::Always => QuoteStyle::Always,
+            Quoting::NonNumeric => QuoteStyle::NonNumeric,
+            Quoting::Never => QuoteStyle::Never,
+        }
+    }
+}
+
+pub struct CsvWriter<W: Write> {
+    writer: csv::Writer<W>,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(writer: W, options: &CsvOptions) -> Result<Self, PersistError> {
+        let delimiter = u8::try_from(options.delimiter)
+            .map_err(|_| PersistError::Other(format!("csv delimiter {:?} is not ascii", options.delimiter)))?;
+        Ok(Self {
+            writer: WriterBuilder::new()
+                .delimiter(delimiter)
+                .quote_style(options.quoting.into())
+                .from_writer(writer),
+        })
+    }
+
+    /// Writes the header and the buffered rows then clears them, returns the number of rows written.
+    /// Meant to be called once per file, as the header is written on every call.
+    pub fn write(&mut self, fields: &[TypePtr], buffer: &mut RowBuffer) -> Result<usize, PersistError> {
+        let size = buffer.len();
+        let columns = buffer.columns();
+        if columns.len() != fields.len() {
+            return Err(PersistError::SchemaMismatch {
+                expected: format!("{} fields", fields.len()),
+                found: format!("{} columns buffered", columns.len()),
+            });
+        }
+        self.writer
+            .write_record(fields.iter().map(|field| field.name()))
+            .map_err(csv_error)?;
+        for row in 0..size {
+            let record = fields
+                .iter()
+                .zip(columns)
+                .map(|(field, column)| match jsonl::value(field, &column[row]) {
+                    Value::Null => String::new(),
+                    Value::String(value) => value,
+                    value => value.to_string(),
+                });
+            self.writer.write_record(record).map_err(csv_error)?;
+        }
+        self.writer.flush()?;
+        buffer.clear();
+        Ok(size)
+    }
+
+    pub fn into_inner(self) -> Result<W, PersistError> {
+        self.writer
+            .into_inner()
+            .map_err(|e| PersistError::Other(format!("failed to flush csv - {e}")))
+    }
+}
+
+fn csv_error(e: csv::Error) -> PersistError {
+    PersistError::Other(format!("failed to write csv - {e}"))
+}
@@ -0,0 +1,419 @@
+//! Maintains a Delta Lake transaction log next to the Parquet files of a table.
+//!
+//! ## Features
+//! - Commits every flushed file as an `add` action in a new `_delta_log/<version>.json` entry, the first commit also
+//!   holds the protocol and the table metadata (with the schema converted from the Parquet schema).
+//! - Commits with the next version when another writer committed the same version first, without the protocol and
+//!   the metadata if the other writer created the table.
+//! - Writes the nanosecond timestamps in microseconds, the unit of the Delta `timestamp` type (see
+//!   [`micros_columns`]).
+//! - Optionally writes a checkpoint every `delta_checkpoint_interval` commits: a `<version>.checkpoint.parquet` with
+//!   the protocol, the metadata and the files of the table at that version, pointed to by `_last_checkpoint`, so
+//!   readers do not replay every JSON commit.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow::array::{
+    ArrayRef, BooleanArray, Int32Array, Int64Array, ListBuilder, MapBuilder, MapFieldNames, RecordBatch, StringArray,
+    StringBuilder, StructArray,
+};
+use arrow::buffer::NullBuffer;
+use arrow::datatypes::{Field, Schema};
+use chrono::{DateTime, Utc};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{LogicalType, Repetition, TimeUnit, Type as PhysicalType};
+use parquet::format::MicroSeconds;
+use parquet::schema::types::{Type, TypePtr};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::error::PersistError;
+use crate::path::{relative_path, table_id};
+
+const LOG_FOLDER: &str = "_delta_log";
+const LAST_CHECKPOINT: &str = "_last_checkpoint";
+
+/// Commits a flushed Parquet file at `now`, `schema` is the Parquet schema of the table. A checkpoint is written once
+/// the version is a multiple of `checkpoint_interval`, if not 0.
+pub fn commit(
+    table: &Path,
+    file: &Path,
+    rows: i64,
+    schema: &Type,
+    checkpoint_interval: u64,
+    now: DateTime<Utc>,
+) -> Result<(), PersistError> {
+    let log = table.join(LOG_FOLDER);
+    fs::create_dir_all(&log)?;
+
+    let now = now.timestamp_millis();
+    let path = file
+        .strip_prefix(table)
+        .map_err(|_| PersistError::Other(format!("{file:?} is not in table {table:?}")))?;
+
+    let created = [
+        json!({"protocol": {"minReaderVersion": 1, "minWriterVersion": 2}}),
+        json!({"metaData": {
+            "id": table_id(),
+            "format": {"provider": "parquet", "options": {}},
+            "schemaString": delta_schema(schema)?.to_string(),
+            "partitionColumns": [],
+            "configuration": {},
+            "createdTime": now,
+        }}),
+    ];
+    let appended = vec![
+        json!({"add": {
+            "path": relative_path(path),
+            "partitionValues": {},
+            "size": fs::metadata(file)?.len(),
+            "modificationTime": now,
+            "dataChange": true,
+            "stats": json!({"numRecords": rows}).to_string(),
+        }}),
+        json!({"commitInfo": {"timestamp": now, "operation": "WRITE"}}),
+    ];
+
+    // another writer may commit the same version first, commits are retried with the next one
+    let mut version = next_version(&log)?;
+    loop {
+        let actions = match version {
+            0 => created.iter().chain(&appended).collect::<Vec<_>>(),
+            _ => appended.iter().collect(),
+        };
+        let mut content = actions
+            .iter()
+            .map(|action| action.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        content.push('\n');
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(log.join(format!("{version:020}.json")))
+        {
+            Ok(mut file) => {
+                write(&mut file, &content)?;
+                break;
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => version += 1,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    if checkpoint_interval > 0 && version > 0 && version % checkpoint_interval == 0 {
+        // the commit is done, readers replay the JSON commits until the next checkpoint
+        if let Err(e) = checkpoint(&log, version) {
+            warn!("cannot checkpoint version {version} of {table:?} - {e}");
+        }
+    }
+    Ok(())
+}
+
+fn write(file: &mut File, content: &str) -> Result<(), PersistError> {
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+fn next_version(log: &Path) -> Result<u64, PersistError> {
+    let mut next = 0;
+    for entry in fs::read_dir(log)? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+        if let Some(version) = name.strip_suffix(".json").and_then(|v| v.parse::<u64>().ok()) {
+            next = next.max(version + 1);
+        }
+    }
+    Ok(next)
+}
+
+/// Rewrites the nanosecond timestamp columns in microseconds, returning their indexes so their values are divided by
+/// 1000 as they are buffered.
+pub fn micros_columns(columns: &mut [TypePtr]) -> Result<Vec<usize>, PersistError> {
+    let mut micros = vec![];
+    for (i, column) in columns.iter_mut().enumerate() {
+        let info = column.get_basic_info();
+        let Some(LogicalType::Timestamp {
+            is_adjusted_to_u_t_c,
+            unit: TimeUnit::NANOS(_),
+        }) = info.logical_type()
+        else {
+            continue;
+        };
+        *column = Arc::new(
+            Type::primitive_type_builder(column.name(), column.get_physical_type())
+                .with_repetition(info.repetition())
+                .with_logical_type(Some(LogicalType::Timestamp {
+                    is_adjusted_to_u_t_c,
+                    unit: TimeUnit::MICROS(MicroSeconds::new()),
+                }))
+                .build()?,
+        );
+        micros.push(i);
+    }
+    Ok(micros)
+}
+
+/// Writes the checkpoint of `version` and points `_last_checkpoint` to it, unless another writer wrote it first.
+fn checkpoint(log: &Path, version: u64) -> Result<(), PersistError> {
+    let actions = replay(log, version)?;
+    let batch = checkpoint_batch(&actions).map_err(|e| PersistError::Other(e.to_string()))?;
+    let path = log.join(format!("{version:020}.checkpoint.parquet"));
+    let file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut writer = ArrowWriter::try_new(file, batch.schema(), None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+
+    // replaced at once, readers never see a partial pointer
+    let pointer = log.join(format!("{LAST_CHECKPOINT}.{version}.tmp"));
+    fs::write(&pointer, json!({"version": version, "size": actions.len()}).to_string())?;
+    fs::rename(&pointer, log.join(LAST_CHECKPOINT))?;
+    info!(
+        "checkpointed version {version} of {log:?} with {} actions",
+        actions.len()
+    );
+    Ok(())
+}
+
+/// Replays the commits up to `version`: the last protocol and metadata, then the files added and not removed.
+fn replay(log: &Path, version: u64) -> Result<Vec<Value>, PersistError> {
+    let mut protocol = None;
+    let mut metadata = None;
+    let mut files = BTreeMap::new();
+    for commit in 0..=version {
+        let path = log.join(format!("{commit:020}.json"));
+        for line in fs::read_to_string(&path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+        {
+            let action: Value = serde_json::from_str(line)
+                .map_err(|e| PersistError::Other(format!("invalid action in {path:?} - {e}")))?;
+            if action.get("protocol").is_some() {
+                protocol = Some(action);
+            } else if action.get("metaData").is_some() {
+                metadata = Some(action);
+            } else if let Some(file) = action["add"]["path"].as_str() {
+                files.insert(file.to_owned(), action);
+            } else if let Some(file) = action["remove"]["path"].as_str() {
+                files.remove(file);
+            }
+        }
+    }
+    Ok(protocol
+        .into_iter()
+        .chain(metadata)
+        .chain(files.into_values())
+        .collect())
+}
+
+/// Lays out the actions of a checkpoint as the columns of the Delta checkpoint schema, one action per row, the
+/// columns of the other actions being null.
+fn checkpoint_batch(actions: &[Value]) -> Result<RecordBatch, arrow::error::ArrowError> {
+    let valid = |action: &str| actions.iter().map(|row| row.get(action).is_some()).collect::<Vec<_>>();
+    let value = |action: &str, path: &[&str]| {
+        actions
+            .iter()
+            .map(|row| {
+                path.iter()
+                    .try_fold(row.get(action)?, |value, key| value.get(key))
+                    .cloned()
+            })
+            .collect::<Vec<_>>()
+    };
+    let strings = |action: &str, path: &[&str]| -> ArrayRef {
+        Arc::new(StringArray::from_iter(
+            value(action, path)
+                .iter()
+                .map(|value| value.as_ref().and_then(Value::as_str)),
+        ))
+    };
+    let longs = |action: &str, path: &[&str]| -> ArrayRef {
+        Arc::new(Int64Array::from_iter(
+            value(action, path)
+                .iter()
+                .map(|value| value.as_ref().and_then(Value::as_i64)),
+        ))
+    };
+    let ints = |action: &str, path: &[&str]| -> ArrayRef {
+        Arc::new(Int32Array::from_iter(value(action, path).iter().map(|value| {
+            value.as_ref().and_then(Value::as_i64).map(|value| value as i32)
+        })))
+    };
+    let bools = |action: &str, path: &[&str]| -> ArrayRef {
+        Arc::new(BooleanArray::from_iter(
+            value(action, path)
+                .iter()
+                .map(|value| value.as_ref().and_then(Value::as_bool)),
+        ))
+    };
+
+    let protocol = valid("protocol");
+    let metadata = valid("metaData");
+    let add = valid("add");
+    let remove = vec![false; actions.len()];
+    let columns = [
+        (
+            "protocol",
+            structs(
+                vec![
+                    ("minReaderVersion", ints("protocol", &["minReaderVersion"])),
+                    ("minWriterVersion", ints("protocol", &["minWriterVersion"])),
+                ],
+                &protocol,
+            )?,
+        ),
+        (
+            "metaData",
+            structs(
+                vec![
+                    ("id", strings("metaData", &["id"])),
+                    ("name", strings("metaData", &["name"])),
+                    ("description", strings("metaData", &["description"])),
+                    (
+                        "format",
+                        structs(
+                            vec![
+                                ("provider", strings("metaData", &["format", "provider"])),
+                                ("options", empty_maps(&metadata)?),
+                            ],
+                            &metadata,
+                        )?,
+                    ),
+                    ("schemaString", strings("metaData", &["schemaString"])),
+                    ("partitionColumns", empty_lists(&metadata)),
+                    ("configuration", empty_maps(&metadata)?),
+                    ("createdTime", longs("metaData", &["createdTime"])),
+                ],
+                &metadata,
+            )?,
+        ),
+        (
+            "add",
+            structs(
+                vec![
+                    ("path", strings("add", &["path"])),
+                    ("partitionValues", empty_maps(&add)?),
+                    ("size", longs("add", &["size"])),
+                    ("modificationTime", longs("add", &["modificationTime"])),
+                    ("dataChange", bools("add", &["dataChange"])),
+                    ("stats", strings("add", &["stats"])),
+                ],
+                &add,
+            )?,
+        ),
+        (
+            "remove",
+            structs(
+                vec![
+                    ("path", strings("remove", &["path"])),
+                    ("deletionTimestamp", longs("remove", &["deletionTimestamp"])),
+                    ("dataChange", bools("remove", &["dataChange"])),
+                ],
+                &remove,
+            )?,
+        ),
+    ];
+    let schema = Schema::new(
+        columns
+            .iter()
+            .map(|(name, column)| Field::new(*name, column.data_type().clone(), true))
+            .collect::<Vec<_>>(),
+    );
+    RecordBatch::try_new(
+        Arc::new(schema),
+        columns.into_iter().map(|(_, column)| column).collect(),
+    )
+}
+
+/// A struct column of nullable fields, null in the rows not `valid`.
+fn structs(fields: Vec<(&str, ArrayRef)>, valid: &[bool]) -> Result<ArrayRef, arrow::error::ArrowError> {
+    let (fields, arrays): (Vec<_>, Vec<_>) = fields
+        .into_iter()
+        .map(|(name, array)| (Field::new(name, array.data_type().clone(), true), array))
+        .unzip();
+    let nulls = NullBuffer::from(valid.to_vec());
+    Ok(Arc::new(StructArray::try_new(fields.into(), arrays, Some(nulls))?))
+}
+
+/// A `map<string, string>` column, empty in the rows `valid` and null in the others.
+fn empty_maps(valid: &[bool]) -> Result<ArrayRef, arrow::error::ArrowError> {
+    let names = MapFieldNames {
+        entry: "key_value".to_owned(),
+        key: "key".to_owned(),
+        value: "value".to_owned(),
+    };
+    let mut maps = MapBuilder::new(Some(names), StringBuilder::new(), StringBuilder::new());
+    for valid in valid {
+        maps.append(*valid)?;
+    }
+    Ok(Arc::new(maps.finish()))
+}
+
+/// An `array<string>` column, empty in the rows `valid` and null in the others.
+fn empty_lists(valid: &[bool]) -> ArrayRef {
+    let mut lists = ListBuilder::new(StringBuilder::new());
+    for valid in valid {
+        lists.append(*valid);
+    }
+    Arc::new(lists.finish())
+}
+
+fn delta_schema(schema: &Type) -> Result<Value, PersistError> {
+    let fields = schema
+        .get_fields()
+        .iter()
+        .map(|field| {
+            Ok(json!({
+                "name": field.name(),
+                "type": delta_type(field)?,
+                "nullable": field.get_basic_info().repetition() != Repetition::REQUIRED,
+                "metadata": {},
+            }))
+        })
+        .collect::<Result<Vec<_>, PersistError>>()?;
+    Ok(json!({"type": "struct", "fields": fields}))
+}
+
+fn delta_type(field: &Type) -> Result<&'static str, PersistError> {
+    let info = field.get_basic_info();
+    Ok(match (field.get_physical_type(), info.logical_type()) {
+        (PhysicalType::BOOLEAN, _) => "boolean",
+        (PhysicalType::INT32, _) => "integer",
+        // the nanosecond columns are written in microseconds, see `micros_columns`
+        (
+            PhysicalType::INT64,
+            Some(LogicalType::Timestamp {
+                unit: TimeUnit::MICROS(_) | TimeUnit::MILLIS(_),
+                ..
+            }),
+        ) => "timestamp",
+        (PhysicalType::INT64, Some(LogicalType::Timestamp { .. })) => {
+            return Err(PersistError::ColumnTypeMismatch {
+                column: field.name().to_owned(),
+                expected: "a timestamp in microseconds or milliseconds".to_owned(),
+                found: "a timestamp in nanoseconds".to_owned(),
+            })
+        }
+        (PhysicalType::INT64, _) => "long",
+        (PhysicalType::FLOAT, _) => "float",
+        (PhysicalType::DOUBLE, _) => "double",
+        (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)) => "string",
+        (PhysicalType::BYTE_ARRAY, _) => "binary",
+        (physical_type, _) => {
+            return Err(PersistError::ColumnTypeMismatch {
+                column: field.name().to_owned(),
+                expected: "a type supported by delta".to_owned(),
+                found: physical_type.to_string(),
+            })
+        }
+    })
+}
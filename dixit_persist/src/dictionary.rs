@@ -0,0 +1,89 @@
+//! Zstd compression of the JSON lines and CSV tables with a dictionary shared by the files of a table, see
+//! `PersistConfig::zstd_dictionary`.
+//!
+//! ## Features
+//! - Trains the dictionary from the first flush of the table, every line being a sample, and stores it as
+//!   `_dictionary.zstd` in the table directory before the first file compressed with it.
+//! - Reuses the dictionary of the table directory when appending to a table, so every file of the table is read with
+//!   the same dictionary (e.g. `zstd -D _dictionary.zstd -d 000000000.jsonl.zst`).
+//! - Compresses the flushes too small to train a dictionary without one, training again on the next flush.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use tracing::{info, warn};
+
+/// Name of the dictionary in the table directory.
+pub const DICTIONARY_FILE: &str = "_dictionary.zstd";
+
+const LEVEL: i32 = 3;
+/// Maximum size of the trained dictionaries.
+const MAX_SIZE: usize = 16 * 1024;
+/// Below this many bytes of samples the dictionary is not trained, zstd needs about a hundred times its size.
+const MIN_SAMPLES: usize = 8 * 1024;
+
+/// Dictionary of a table, trained on the first flush large enough.
+#[derive(Debug, Default)]
+pub struct ZstdDictionary {
+    dictionary: Option<Vec<u8>>,
+}
+
+impl ZstdDictionary {
+    /// Loads the dictionary stored in the table directory, if any.
+    pub fn load(directory: &Path) -> io::Result<Self> {
+        let dictionary = match fs::read(directory.join(DICTIONARY_FILE)) {
+            Ok(dictionary) => Some(dictionary),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        Ok(Self { dictionary })
+    }
+
+    pub fn get(&self) -> Option<&[u8]> {
+        self.dictionary.as_deref()
+    }
+
+    /// Trains the dictionary from the lines of `content` if the table has none yet, returns the new dictionary to
+    /// store, or none if trained already or if `content` is too small to train one.
+    pub fn train(&mut self, content: &[u8]) -> Option<&[u8]> {
+        if self.dictionary.is_some() || content.len() < MIN_SAMPLES {
+            return None;
+        }
+        let sizes = content
+            .split_inclusive(|byte| *byte == b'\n')
+            .map(<[u8]>::len)
+            .collect::<Vec<_>>();
+        match zstd::dict::from_continuous(content, &sizes, MAX_SIZE) {
+            Ok(dictionary) => {
+                info!(
+                    "trained a zstd dictionary of {} bytes from {} lines",
+                    dictionary.len(),
+                    sizes.len()
+                );
+                Some(self.dictionary.insert(dictionary))
+            }
+            Err(e) => {
+                warn!(
+                    "cannot train a zstd dictionary from {} lines, compressing without - {e}",
+                    sizes.len()
+                );
+                None
+            }
+        }
+    }
+
+    /// Compresses the content of a file, with the dictionary if trained.
+    pub fn compress(&self, content: &[u8]) -> io::Result<Vec<u8>> {
+        let mut compressor = zstd::bulk::Compressor::with_dictionary(LEVEL, self.get().unwrap_or_default())?;
+        compressor.compress(content)
+    }
+}
+
+/// Decompresses a file of a table compressed with `dictionary`, or without one.
+pub fn decompress(content: &[u8], dictionary: Option<&[u8]>) -> io::Result<Vec<u8>> {
+    let mut decoder = zstd::Decoder::with_dictionary(content, dictionary.unwrap_or_default())?;
+    let mut decompressed = vec![];
+    decoder.read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
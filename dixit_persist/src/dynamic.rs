@@ -0,0 +1,331 @@
+//! Persists records whose type is only known at runtime (e.g. the records of a plugin venue loaded as a library),
+//! behind a `dyn DynPersistable` instead of a generic `Persistable`.
+//!
+//! ## Features
+//! - The columns of the table come from a [`Descriptor`] rather than from the record type, built by hand or from a
+//!   `Persistable` type with [`Descriptor::of`].
+//! - Every `Persistable` is a [`DynPersistable`], so typed and plugin records can be boxed together.
+//! - Fails a record that appends more or fewer values than the columns of its descriptor, or a value not matching
+//!   its column, the row is dropped.
+//! - Tables whose shape is only known at runtime (e.g. generic JSON feeds) are described with a [`SchemaBuilder`] and
+//!   recorded as [`DynamicRow`]s.
+//! - Writes through a [`DynTableWriter`], or through the persist service as a [`DynRecord`] (`tokio` feature).
+
+use std::any::type_name;
+use std::str::FromStr;
+#[cfg(feature = "tokio")]
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use parquet::basic::Repetition;
+use parquet::errors::ParquetError;
+use parquet::record::Field;
+use parquet::schema::types::TypePtr;
+
+use crate::config::PersistConfig;
+use crate::error::PersistError;
+use crate::row::RowBuffer;
+use crate::writer::{TableStats, TableWriter};
+use crate::Persistable;
+
+/// Object safe part of [`Persistable`], the schema being provided by a [`Descriptor`].
+pub trait DynPersistable {
+    /// Appends one value per column of the descriptor, in its order.
+    fn append(&self, row: &mut RowBuffer) -> Result<(), ParquetError>;
+
+    /// Returns true if the record must not be persisted.
+    fn ignored(&self) -> bool {
+        false
+    }
+}
+
+impl<T: Persistable> DynPersistable for T {
+    fn append(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
+        Persistable::append(self, row)
+    }
+
+    fn ignored(&self) -> bool {
+        Persistable::ignored(self)
+    }
+}
+
+/// Columns of the records of a table, `name` only shows in errors (e.g. the name of the plugin record).
+#[derive(Debug, Clone)]
+pub struct Descriptor {
+    name: String,
+    fields: Vec<TypePtr>,
+}
+
+impl Descriptor {
+    pub fn new(name: impl Into<String>, fields: Vec<TypePtr>) -> Self {
+        Self {
+            name: name.into(),
+            fields,
+        }
+    }
+
+    /// The columns of a `Persistable` type, as it would be recorded by a [`TableWriter`].
+    pub fn of<T: Persistable>() -> Self {
+        let mut fields = Vec::new();
+        T::schema(&mut fields, None, None, None);
+        Self::new(type_name::<T>(), fields)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn fields(&self) -> &[TypePtr] {
+        &self.fields
+    }
+}
+
+/// Type of a column built by a [`SchemaBuilder`], stored as the Rust type of the same name (e.g. `I64` as an `i64`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Bool,
+    I32,
+    I64,
+    U64,
+    F32,
+    F64,
+    String,
+    /// nanoseconds since the epoch, as `DateTime<Utc>`
+    Timestamp,
+}
+
+impl FromStr for ColumnType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bool" => Ok(ColumnType::Bool),
+            "i32" => Ok(ColumnType::I32),
+            "i64" => Ok(ColumnType::I64),
+            "u64" => Ok(ColumnType::U64),
+            "f32" => Ok(ColumnType::F32),
+            "f64" => Ok(ColumnType::F64),
+            "string" => Ok(ColumnType::String),
+            "timestamp" => Ok(ColumnType::Timestamp),
+            _ => Err(format!(
+                "unknown column type {s}, expected bool, i32, i64, u64, f32, f64, string or timestamp"
+            )),
+        }
+    }
+}
+
+impl ColumnType {
+    fn schema(self, fields: &mut Vec<TypePtr>, name: &str, repetition: Repetition) {
+        let (name, repetition) = (Some(name), Some(repetition));
+        match self {
+            ColumnType::Bool => bool::schema(fields, name, repetition, None),
+            ColumnType::I32 => i32::schema(fields, name, repetition, None),
+            ColumnType::I64 => i64::schema(fields, name, repetition, None),
+            ColumnType::U64 => u64::schema(fields, name, repetition, None),
+            ColumnType::F32 => f32::schema(fields, name, repetition, None),
+            ColumnType::F64 => f64::schema(fields, name, repetition, None),
+            ColumnType::String => String::schema(fields, name, repetition, None),
+            ColumnType::Timestamp => DateTime::<Utc>::schema(fields, name, repetition, None),
+        }
+    }
+}
+
+/// Builds the descriptor of a table whose columns are only known at runtime.
+///
+/// ```ignore
+/// let descriptor = SchemaBuilder::new("ticks")
+///     .column("symbol", ColumnType::String)
+///     .column("price", ColumnType::F64)
+///     .optional("size", ColumnType::F64)
+///     .build()?;
+/// ```
+pub struct SchemaBuilder {
+    name: String,
+    fields: Vec<TypePtr>,
+}
+
+impl SchemaBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            fields: vec![],
+        }
+    }
+
+    /// Adds a column always set.
+    pub fn column(mut self, name: &str, column_type: ColumnType) -> Self {
+        column_type.schema(&mut self.fields, name, Repetition::REQUIRED);
+        self
+    }
+
+    /// Adds a column which can be null.
+    pub fn optional(mut self, name: &str, column_type: ColumnType) -> Self {
+        column_type.schema(&mut self.fields, name, Repetition::OPTIONAL);
+        self
+    }
+
+    /// Fails if the schema has no column or two columns with the same name.
+    pub fn build(self) -> Result<Descriptor, PersistError> {
+        if self.fields.is_empty() {
+            return Err(PersistError::Other(format!("schema {} has no column", self.name)));
+        }
+        for (j, field) in self.fields.iter().enumerate() {
+            if let Some(i) = self.fields[..j].iter().position(|other| other.name() == field.name()) {
+                return Err(PersistError::Other(format!(
+                    "duplicate column {} in {}: columns {i} and {j} have the same name",
+                    field.name(),
+                    self.name
+                )));
+            }
+        }
+        Ok(Descriptor::new(self.name, self.fields))
+    }
+}
+
+/// A value of a [`DynamicRow`], matching the [`ColumnType`] of its column or `Null` for an optional column.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DynamicValue {
+    Null,
+    Bool(bool),
+    I32(i32),
+    I64(i64),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    String(String),
+    Timestamp(DateTime<Utc>),
+}
+
+macro_rules! impl_from_for_dynamic_value {
+    ($($type:ty => $variant:ident),*) => {
+        $(
+            impl From<$type> for DynamicValue {
+                fn from(value: $type) -> Self {
+                    DynamicValue::$variant(value.into())
+                }
+            }
+        )*
+    };
+}
+
+impl_from_for_dynamic_value!(
+    bool => Bool,
+    i32 => I32,
+    i64 => I64,
+    u64 => U64,
+    f32 => F32,
+    f64 => F64,
+    String => String,
+    &str => String,
+    DateTime<Utc> => Timestamp
+);
+
+impl<T: Into<DynamicValue>> From<Option<T>> for DynamicValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(DynamicValue::Null, Into::into)
+    }
+}
+
+/// A row of a table built by a [`SchemaBuilder`], one value per column in the order of the columns.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DynamicRow {
+    pub values: Vec<DynamicValue>,
+}
+
+impl DynamicRow {
+    pub fn new(values: impl IntoIterator<Item = DynamicValue>) -> Self {
+        Self {
+            values: values.into_iter().collect(),
+        }
+    }
+
+    pub fn push(&mut self, value: impl Into<DynamicValue>) -> &mut Self {
+        self.values.push(value.into());
+        self
+    }
+}
+
+impl DynPersistable for DynamicRow {
+    fn append(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
+        for value in self.values.iter() {
+            match value {
+                DynamicValue::Null => row.push(Field::Null),
+                DynamicValue::Bool(value) => row.push(Field::Bool(*value)),
+                DynamicValue::I32(value) => row.push(Field::Int(*value)),
+                DynamicValue::I64(value) => row.push(Field::Long(*value)),
+                DynamicValue::U64(value) => row.push(Field::ULong(*value)),
+                DynamicValue::F32(value) => row.push(Field::Float(*value)),
+                DynamicValue::F64(value) => row.push(Field::Double(*value)),
+                DynamicValue::String(value) => row.push_str(value),
+                DynamicValue::Timestamp(value) => Persistable::append(value, row)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes the dynamic records of a table, all sharing the columns of the descriptor.
+pub struct DynTableWriter {
+    writer: TableWriter,
+    descriptor: Descriptor,
+}
+
+impl DynTableWriter {
+    /// Creates the writer as [`TableWriter::new`].
+    pub fn new(path_prefix: &str, persist_config: &PersistConfig, descriptor: Descriptor) -> Result<Self> {
+        Ok(Self {
+            writer: TableWriter::new(path_prefix, persist_config)?,
+            descriptor,
+        })
+    }
+
+    pub fn descriptor(&self) -> &Descriptor {
+        &self.descriptor
+    }
+
+    /// Records a row, see [`crate::writer::RowBuilder::record_dyn`].
+    pub fn write(&mut self, record: &dyn DynPersistable) -> Result<()> {
+        self.writer.begin()?.record_dyn(&self.descriptor, record)?.end()?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), PersistError> {
+        self.writer.flush()
+    }
+
+    pub fn close(&mut self) -> Result<(), PersistError> {
+        self.writer.close()
+    }
+
+    pub fn stats(&self) -> TableStats {
+        self.writer.stats()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.writer.is_enabled()
+    }
+
+    /// Enables or disables the table at runtime, see [`TableWriter::set_enabled`].
+    pub fn set_enabled(&mut self, enabled: bool) -> Result<(), PersistError> {
+        self.writer.set_enabled(enabled)
+    }
+}
+
+/// A dynamic record sent to the persist service, the first record of a table sets its columns.
+#[cfg(feature = "tokio")]
+pub struct DynRecord {
+    pub descriptor: Arc<Descriptor>,
+    pub record: Box<dyn DynPersistable + Send + Sync>,
+}
+
+#[cfg(feature = "tokio")]
+impl crate::service::Writable for DynRecord {
+    fn write(&self, writer: &mut TableWriter) -> Result<()> {
+        writer
+            .begin()?
+            .record_dyn(&self.descriptor, self.record.as_ref())?
+            .end()?;
+        Ok(())
+    }
+}
@@ -1,18 +1,91 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
 use parquet::errors::ParquetError;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum PersistError {
+    /// rows flushed or a file completed before the first row set the schema of the table
+    #[error("schema has not been created")]
+    SchemaMissing,
+    /// rows not matching the columns of their table, e.g. a record appending fewer values than its descriptor
+    #[error("schema mismatch: expected {expected}, found {found}")]
+    SchemaMismatch { expected: String, found: String },
+    /// a column whose type is not the one expected, e.g. a time range on a string column
+    #[error("column {column} is {found}, expected {expected}")]
+    ColumnTypeMismatch {
+        column: String,
+        expected: String,
+        found: String,
+    },
+    /// a column read into a struct field without default (see [`crate::reader::column`]) but missing from the row
+    #[error("column {0} is missing")]
+    ColumnMissing(String),
+    #[error("io error: {0}")]
+    Io(#[source] io::Error),
+    #[error("parquet error: {0}")]
+    Parquet(#[source] ParquetError),
+    /// the table directory is locked by another writer, after waiting for it if set
+    #[error("table directory {directory:?} is locked by another writer{}", waited.map(|t| format!(" (waited {t:?})")).unwrap_or_default())]
+    DirectoryLocked {
+        directory: PathBuf,
+        waited: Option<Duration>,
+    },
+    /// no space left on the device of the table, the rows not written being kept buffered
+    #[error("disk full: {0}")]
+    DiskFull(#[source] io::Error),
+    /// a flush still failing once its retries are exhausted, see [`crate::retry`]
+    #[error(
+        "flush failed after {attempts} attempts{}: {source}",
+        recovery.as_ref().map(|path| format!(", rows dumped to {path:?}")).unwrap_or_default()
+    )]
+    FlushFailed {
+        attempts: u32,
+        /// file the buffered rows were dumped to, if any, the rows being kept buffered otherwise
+        recovery: Option<PathBuf>,
+        #[source]
+        source: Box<PersistError>,
+    },
     #[error("other error: {0}")]
     Other(String),
-    #[error("parquet error: {0}")]
-    ParquetError(String),
-    #[error("io error: {0}")]
-    IOError(#[from] std::io::Error),
+}
+
+impl From<io::Error> for PersistError {
+    fn from(err: io::Error) -> Self {
+        if is_disk_full(&err) {
+            Self::DiskFull(err)
+        } else {
+            Self::Io(err)
+        }
+    }
 }
 
 impl From<ParquetError> for PersistError {
     fn from(err: ParquetError) -> Self {
-        Self::ParquetError(err.to_string())
+        // the parquet writers wrap the errors of their output
+        match err {
+            ParquetError::External(source) => match source.downcast::<io::Error>() {
+                Ok(err) => Self::from(*err),
+                Err(source) => Self::Parquet(ParquetError::External(source)),
+            },
+            err => Self::Parquet(err),
+        }
+    }
+}
+
+impl PersistError {
+    /// Whether the error may go away on its own (e.g. a network filesystem blip, a disk briefly full), so the
+    /// operation failing is worth retrying.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Io(_) | Self::DiskFull(_))
     }
 }
+
+/// Whether the error is the device running out of space, `io::ErrorKind::StorageFull` being too recent.
+fn is_disk_full(err: &io::Error) -> bool {
+    // ENOSPC on unix, ERROR_HANDLE_DISK_FULL and ERROR_DISK_FULL on windows
+    let codes: &[i32] = if cfg!(windows) { &[39, 112] } else { &[28] };
+    err.raw_os_error().is_some_and(|code| codes.contains(&code))
+}
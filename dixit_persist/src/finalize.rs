@@ -0,0 +1,77 @@
+//! Markers of the table files completely written, so the readers of a table being written (e.g. a query, an upload)
+//! never read a file still open or left partial by a crash, see `PersistConfig::finalize_markers`.
+//!
+//! ## Features
+//! - Writes an empty `.success` marker next to every completed file (e.g. `000000042.parquet.success`), once the file
+//!   is closed and its checksum sidecar, time index entry and table log commit are written.
+//! - Flags the table directory as marked with a `_markers` file when its writer is created, before its first file,
+//!   so the readers of a marked table skip the files without a marker and read the other tables as before.
+//! - Writes the markers through the output of the writer, so they are mirrored by the outputs writing elsewhere
+//!   (e.g. an object store), the marker being written after its file.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use tracing::debug;
+
+use crate::checksum;
+use crate::error::PersistError;
+use crate::output::Output;
+
+/// Extension of the markers, after the extension of their file.
+pub const EXTENSION: &str = "success";
+/// Flags a table directory whose files are readable once marked only.
+pub const MARKERS_FILE: &str = "_markers";
+
+/// Path of the marker of `path`, e.g. `000000000.parquet.success`.
+pub fn marker_path(path: &Path) -> PathBuf {
+    let mut marker = path.as_os_str().to_owned();
+    marker.push(".");
+    marker.push(EXTENSION);
+    PathBuf::from(marker)
+}
+
+/// Whether the files of the table directory are readable once marked only.
+pub fn is_marked(directory: &Path) -> bool {
+    directory.join(MARKERS_FILE).exists()
+}
+
+/// Whether the file of a table directory is completely written: marked, of a table without markers or metadata of
+/// the table (e.g. a marker, a checksum sidecar or the `_index.json`).
+pub fn is_finalized(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let metadata = name.starts_with(['_', '.'])
+        || [EXTENSION, checksum::EXTENSION]
+            .iter()
+            .any(|extension| path.extension().is_some_and(|ext| ext == *extension));
+    metadata || !path.parent().is_some_and(is_marked) || marker_path(path).exists()
+}
+
+/// The files of the table directory completely written, in their order.
+pub fn finalized(directory: &Path, mut files: Vec<PathBuf>) -> Vec<PathBuf> {
+    if is_marked(directory) {
+        files.retain(|file| {
+            let marked = marker_path(file).exists();
+            if !marked {
+                debug!("skipping {file:?}, not finalized");
+            }
+            marked
+        });
+    }
+    files
+}
+
+/// Flags the table directory as marked, unless it is already.
+pub fn mark_table(output: &mut dyn Output, directory: &Path) -> Result<(), PersistError> {
+    let path = directory.join(MARKERS_FILE);
+    if !output.exists(&path) {
+        output.create(&path)?.flush()?;
+    }
+    Ok(())
+}
+
+/// Writes the marker of a completed file.
+pub fn mark(output: &mut dyn Output, path: &Path) -> Result<(), PersistError> {
+    output.create(&marker_path(path))?.flush()?;
+    Ok(())
+}
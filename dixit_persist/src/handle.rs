@@ -0,0 +1,48 @@
+//! Shares a [`TableWriter`] between tasks, so several producers can persist to the same table (`tokio` feature).
+//!
+//! ## Features
+//! - Cheaply cloneable handle, every clone writing to the same table.
+//! - Records are written whole: a row is never interleaved with the row of another producer.
+//! - Flushes run on the task holding the handle, as they do with a `TableWriter`, the other producers wait for them.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::Mutex;
+
+use crate::error::PersistError;
+use crate::writer::{TableStats, TableWriter};
+use crate::Persistable;
+
+#[derive(Clone)]
+pub struct TableWriterHandle {
+    writer: Arc<Mutex<TableWriter>>,
+}
+
+impl TableWriterHandle {
+    pub fn new(writer: TableWriter) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(writer)),
+        }
+    }
+
+    /// Records a row holding a single record.
+    pub async fn write<T: Persistable>(&self, record: &T) -> Result<()> {
+        let mut writer = self.writer.lock().await;
+        writer.begin()?.record(record)?.end()?;
+        Ok(())
+    }
+
+    pub async fn flush(&self) -> Result<(), PersistError> {
+        self.writer.lock().await.flush()
+    }
+
+    /// Flushes the buffered rows and closes the open file, the table can still be written to afterwards.
+    pub async fn close(&self) -> Result<(), PersistError> {
+        self.writer.lock().await.close()
+    }
+
+    pub async fn stats(&self) -> TableStats {
+        self.writer.lock().await.stats()
+    }
+}
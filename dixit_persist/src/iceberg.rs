@@ -0,0 +1,333 @@
+//! Maintains Apache Iceberg (format version 1) metadata next to the Parquet files of a table.
+//!
+//! Every flushed file is committed as a new `append` snapshot: a manifest holding the file, a manifest list holding
+//! every manifest of the table and a new `v<N>.metadata.json`, pointed to by `version-hint.text`.
+//! The table can then be registered in a catalog (e.g. with the `register_table` procedure of the REST or Glue
+//! catalogs) from the location of its latest metadata file.
+//!
+//! The Parquet files have no field ids, columns are resolved through the `schema.name-mapping.default` property.
+//! Timestamps are written in microseconds, the unit of the Iceberg `timestamp` and `timestamptz` types of the format
+//! version 1 (see [`crate::delta::micros_columns`]).
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use parquet::basic::{LogicalType, Repetition, TimeUnit, Type as PhysicalType};
+use parquet::schema::types::Type;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::error::PersistError;
+use crate::path::{file_uri as uri, table_id};
+
+const METADATA_FOLDER: &str = "metadata";
+const VERSION_HINT: &str = "version-hint.text";
+const MANIFEST_SUFFIX: &str = "-m0.avro";
+const BLOCK_SIZE: i64 = 64 * 1024 * 1024;
+
+const MANIFEST_SCHEMA: &str = r#"{"type":"record","name":"manifest_entry","fields":[
+{"name":"status","type":"int","field-id":0},
+{"name":"snapshot_id","type":"long","field-id":1},
+{"name":"data_file","type":{"type":"record","name":"r2","fields":[
+{"name":"file_path","type":"string","field-id":100},
+{"name":"file_format","type":"string","field-id":101},
+{"name":"partition","type":{"type":"record","name":"r102","fields":[]},"field-id":102},
+{"name":"record_count","type":"long","field-id":103},
+{"name":"file_size_in_bytes","type":"long","field-id":104},
+{"name":"block_size_in_bytes","type":"long","field-id":105}]},"field-id":2}]}"#;
+
+const MANIFEST_LIST_SCHEMA: &str = r#"{"type":"record","name":"manifest_file","fields":[
+{"name":"manifest_path","type":"string","field-id":500},
+{"name":"manifest_length","type":"long","field-id":501},
+{"name":"partition_spec_id","type":"int","field-id":502},
+{"name":"added_snapshot_id","type":"long","field-id":503}]}"#;
+
+/// Commits a flushed Parquet file as a new snapshot taken at `now`, `schema` is the Parquet schema of the table.
+pub fn commit(table: &Path, file: &Path, rows: i64, schema: &Type, now: DateTime<Utc>) -> Result<(), PersistError> {
+    let table = fs::canonicalize(table)?;
+    let file = fs::canonicalize(file)?;
+    let metadata = table.join(METADATA_FOLDER);
+    fs::create_dir_all(&metadata)?;
+
+    let now = now.timestamp_millis();
+
+    let (version, previous) = match fs::read_to_string(metadata.join(VERSION_HINT)) {
+        Ok(hint) => {
+            let version = hint
+                .trim()
+                .parse::<u64>()
+                .map_err(|e| PersistError::Other(format!("invalid version hint {hint:?} - {e}")))?;
+            let content = fs::read_to_string(metadata.join(format!("v{version}.metadata.json")))?;
+            let previous: Value = serde_json::from_str(&content).map_err(|e| PersistError::Other(e.to_string()))?;
+            (version, Some(previous))
+        }
+        Err(_) => (0, None),
+    };
+    let parent = previous
+        .as_ref()
+        .and_then(|previous| previous["current-snapshot-id"].as_i64())
+        .filter(|id| *id >= 0);
+    let snapshot = parent.unwrap_or(0) + 1;
+
+    let manifest = metadata.join(format!("{snapshot:020}{MANIFEST_SUFFIX}"));
+    let entry = Avro::default()
+        .int(1) // added
+        .long(snapshot)
+        .string(&uri(&file))
+        .string("PARQUET")
+        .long(rows)
+        .long(fs::metadata(&file)?.len() as i64)
+        .long(BLOCK_SIZE)
+        .into_bytes();
+    let table_schema = table_schema(schema)?;
+    let manifest_metadata = [
+        ("schema", table_schema.to_string()),
+        ("partition-spec", "[]".to_owned()),
+        ("partition-spec-id", "0".to_owned()),
+        ("format-version", "1".to_owned()),
+    ];
+    write_avro(&manifest, MANIFEST_SCHEMA, &manifest_metadata, &[entry])?;
+
+    let manifests = manifests(&metadata)?
+        .into_iter()
+        .map(|(path, id)| {
+            Ok(Avro::default()
+                .string(&uri(&path))
+                .long(fs::metadata(&path)?.len() as i64)
+                .int(0)
+                .long(id)
+                .into_bytes())
+        })
+        .collect::<Result<Vec<_>, PersistError>>()?;
+    let manifest_list = metadata.join(format!("snap-{snapshot:020}.avro"));
+    write_avro(&manifest_list, MANIFEST_LIST_SCHEMA, &[], &manifests)?;
+
+    let snapshot_entry = json!({
+        "snapshot-id": snapshot,
+        "parent-snapshot-id": parent,
+        "timestamp-ms": now,
+        "manifest-list": uri(&manifest_list),
+        "summary": {
+            "operation": "append",
+            "added-data-files": "1",
+            "added-records": rows.to_string(),
+        },
+        "schema-id": 0,
+    });
+    let mut table_metadata = match previous {
+        Some(previous) => previous,
+        None => new_table(&table, &table_schema, now),
+    };
+    table_metadata["last-updated-ms"] = json!(now);
+    table_metadata["current-snapshot-id"] = json!(snapshot);
+    push(&mut table_metadata["snapshots"], snapshot_entry);
+    push(
+        &mut table_metadata["snapshot-log"],
+        json!({"timestamp-ms": now, "snapshot-id": snapshot}),
+    );
+    if version > 0 {
+        let file = uri(&metadata.join(format!("v{version}.metadata.json")));
+        push(
+            &mut table_metadata["metadata-log"],
+            json!({"timestamp-ms": now, "metadata-file": file}),
+        );
+    }
+
+    // creating the metadata file fails if another writer committed this version first
+    let version = version + 1;
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(metadata.join(format!("v{version}.metadata.json")))?
+        .write_all(table_metadata.to_string().as_bytes())?;
+    fs::write(metadata.join(VERSION_HINT), version.to_string())?;
+    Ok(())
+}
+
+fn new_table(table: &Path, schema: &Value, now: i64) -> Value {
+    let mapping: Vec<Value> = schema["fields"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|field| json!({"field-id": field["id"], "names": [field["name"]]}))
+        .collect();
+    json!({
+        "format-version": 1,
+        "table-uuid": table_id(),
+        "location": uri(table),
+        "last-updated-ms": now,
+        "last-column-id": schema["fields"].as_array().map(Vec::len).unwrap_or_default(),
+        "schema": schema,
+        "schemas": [schema],
+        "current-schema-id": 0,
+        "partition-spec": [],
+        "partition-specs": [{"spec-id": 0, "fields": []}],
+        "default-spec-id": 0,
+        "last-partition-id": 999,
+        "properties": {"schema.name-mapping.default": Value::Array(mapping).to_string()},
+        "current-snapshot-id": -1,
+        "snapshots": [],
+        "snapshot-log": [],
+        "metadata-log": [],
+        "sort-orders": [{"order-id": 0, "fields": []}],
+        "default-sort-order-id": 0,
+    })
+}
+
+fn push(array: &mut Value, value: Value) {
+    match array {
+        Value::Array(array) => array.push(value),
+        _ => *array = json!([value]),
+    }
+}
+
+/// Returns the path and snapshot id of every manifest of the table, one per snapshot.
+fn manifests(metadata: &Path) -> Result<Vec<(PathBuf, i64)>, PersistError> {
+    let mut manifests = vec![];
+    for entry in fs::read_dir(metadata)? {
+        let path = entry?.path();
+        let name = path.file_name().map(|name| name.to_string_lossy().to_string());
+        if let Some(id) = name
+            .as_deref()
+            .and_then(|name| name.strip_suffix(MANIFEST_SUFFIX))
+            .and_then(|id| id.parse::<i64>().ok())
+        {
+            manifests.push((path, id));
+        }
+    }
+    manifests.sort_by_key(|(_, id)| *id);
+    Ok(manifests)
+}
+
+fn table_schema(schema: &Type) -> Result<Value, PersistError> {
+    let fields = schema
+        .get_fields()
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            Ok(json!({
+                "id": i + 1,
+                "name": field.name(),
+                "required": field.get_basic_info().repetition() == Repetition::REQUIRED,
+                "type": iceberg_type(field)?,
+            }))
+        })
+        .collect::<Result<Vec<_>, PersistError>>()?;
+    Ok(json!({"type": "struct", "schema-id": 0, "fields": fields}))
+}
+
+fn iceberg_type(field: &Type) -> Result<&'static str, PersistError> {
+    Ok(
+        match (field.get_physical_type(), field.get_basic_info().logical_type()) {
+            (PhysicalType::BOOLEAN, _) => "boolean",
+            (PhysicalType::INT32, _) => "int",
+            // the nanosecond columns are written in microseconds, see `delta::micros_columns`
+            (
+                PhysicalType::INT64,
+                Some(LogicalType::Timestamp {
+                    is_adjusted_to_u_t_c,
+                    unit: TimeUnit::MICROS(_),
+                }),
+            ) => {
+                if is_adjusted_to_u_t_c {
+                    "timestamptz"
+                } else {
+                    "timestamp"
+                }
+            }
+            (PhysicalType::INT64, Some(LogicalType::Timestamp { unit, .. })) => {
+                return Err(PersistError::ColumnTypeMismatch {
+                    column: field.name().to_owned(),
+                    expected: "a timestamp in microseconds".to_owned(),
+                    found: match unit {
+                        TimeUnit::MILLIS(_) => "a timestamp in milliseconds",
+                        _ => "a timestamp in nanoseconds",
+                    }
+                    .to_owned(),
+                })
+            }
+            (PhysicalType::INT64, _) => "long",
+            (PhysicalType::FLOAT, _) => "float",
+            (PhysicalType::DOUBLE, _) => "double",
+            (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)) => "string",
+            (PhysicalType::BYTE_ARRAY, _) => "binary",
+            (physical_type, _) => {
+                return Err(PersistError::ColumnTypeMismatch {
+                    column: field.name().to_owned(),
+                    expected: "a type supported by iceberg".to_owned(),
+                    found: physical_type.to_string(),
+                })
+            }
+        },
+    )
+}
+
+/// Writes an uncompressed Avro object container file with a single block of already encoded records.
+fn write_avro(path: &Path, schema: &str, metadata: &[(&str, String)], records: &[Vec<u8>]) -> Result<(), PersistError> {
+    let sync = *Uuid::new_v4().as_bytes();
+
+    let schema: String = schema.lines().collect();
+    let mut header = Avro::default()
+        .long(metadata.len() as i64 + 2)
+        .string("avro.schema")
+        .string(&schema)
+        .string("avro.codec")
+        .string("null");
+    for (key, value) in metadata {
+        header = header.string(key).string(value);
+    }
+    let mut bytes = b"Obj\x01".to_vec();
+    bytes.extend(header.long(0).into_bytes());
+    bytes.extend(sync);
+
+    let data = records.concat();
+    bytes.extend(
+        Avro::default()
+            .long(records.len() as i64)
+            .long(data.len() as i64)
+            .into_bytes(),
+    );
+    bytes.extend(data);
+    bytes.extend(sync);
+
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?
+        .write_all(&bytes)?;
+    Ok(())
+}
+
+/// Avro binary encoding of the primitive types used by the manifests.
+#[derive(Default)]
+struct Avro {
+    bytes: Vec<u8>,
+}
+
+impl Avro {
+    fn long(mut self, value: i64) -> Self {
+        let mut zigzag = ((value << 1) ^ (value >> 63)) as u64;
+        while zigzag >= 0x80 {
+            self.bytes.push((zigzag as u8 & 0x7f) | 0x80);
+            zigzag >>= 7;
+        }
+        self.bytes.push(zigzag as u8);
+        self
+    }
+
+    fn int(self, value: i32) -> Self {
+        self.long(value as i64)
+    }
+
+    fn string(self, value: &str) -> Self {
+        let mut avro = self.long(value.len() as i64);
+        avro.bytes.extend_from_slice(value.as_bytes());
+        avro
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
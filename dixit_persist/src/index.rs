@@ -0,0 +1,118 @@
+//! Index of the event-time range of every file of a table, so readers locate the files of a time range without
+//! opening every footer, see `PersistConfig::time_index`.
+//!
+//! ## Features
+//! - Kept as `_index.json` in the table directory, listing the first and last time of a timestamp column and the row
+//!   count of every completed file, by file name.
+//! - Updated by the writer of the table as every file is completed, after the file and its checksum sidecar, the
+//!   index being replaced atomically (written aside then renamed).
+//! - Files missing from the index (e.g. written before it or by a crashed writer), indexed by another column or
+//!   without any time are never skipped by [`TableIndex::skips`].
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use parquet::record::Field;
+use parquet::schema::types::TypePtr;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::error::PersistError;
+use crate::stats;
+
+/// Name of the index in the table directory.
+pub const INDEX_FILE: &str = "_index.json";
+
+/// Files of a table with their time range, see the module documentation.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableIndex {
+    /// timestamp column the ranges are taken from
+    pub column: String,
+    pub files: BTreeMap<String, FileRange>,
+}
+
+/// Time range of the rows of a file, inclusive, unset if every time of the file is null.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FileRange {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub rows: u64,
+}
+
+impl FileRange {
+    /// Extends the range with the times of `column` among the buffered values, one vector per field of `fields`.
+    pub fn extend(&mut self, column: &str, fields: &[TypePtr], values: &[Vec<Field>]) {
+        let Some((field, values)) = fields.iter().zip(values).find(|(field, _)| field.name() == column) else {
+            return;
+        };
+        if let Some((start, end)) = stats::time_bounds(field, values) {
+            self.start = Some(self.start.map_or(start, |current| current.min(start)));
+            self.end = Some(self.end.map_or(end, |current| current.max(end)));
+        }
+    }
+
+    /// Whether some time of the range is in `[start, end)`, unbounded sides being unset.
+    pub fn overlaps(&self, start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>) -> bool {
+        let (Some(first), Some(last)) = (self.start, self.end) else {
+            return true;
+        };
+        let start = start.unwrap_or(DateTime::<Utc>::MIN_UTC);
+        start <= last && end.unwrap_or(DateTime::<Utc>::MAX_UTC) > first
+    }
+}
+
+impl TableIndex {
+    pub fn new(column: &str) -> Self {
+        Self {
+            column: column.to_owned(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Loads the index of the table directory, empty if there is none.
+    pub fn load(directory: &Path) -> Result<Self, PersistError> {
+        let path = directory.join(INDEX_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        serde_json::from_slice(&fs::read(&path)?)
+            .map_err(|e| PersistError::Other(format!("invalid index {path:?} - {e}")))
+    }
+
+    /// Loads the index of the table directory to extend it with the files of `column`, a new index if the files
+    /// were indexed by another column.
+    pub fn open(directory: &Path, column: &str) -> Result<Self, PersistError> {
+        let index = Self::load(directory)?;
+        if index.files.is_empty() || index.column == column {
+            return Ok(Self {
+                column: column.to_owned(),
+                ..index
+            });
+        }
+        warn!(
+            "the files of {directory:?} are indexed by {}, indexing them by {column} from now on",
+            index.column
+        );
+        Ok(Self::new(column))
+    }
+
+    /// Replaces the index of the table directory atomically, written aside then renamed.
+    pub fn save(&self, directory: &Path) -> Result<(), PersistError> {
+        let path = directory.join(INDEX_FILE);
+        let temp = path.with_extension("json.tmp");
+        let content = serde_json::to_vec_pretty(self).map_err(|e| PersistError::Other(e.to_string()))?;
+        fs::write(&temp, content)?;
+        fs::rename(temp, path)?;
+        Ok(())
+    }
+
+    /// Whether the file named `name` cannot hold rows of `column` in `[start, end)`, according to the index.
+    pub fn skips(&self, name: &str, column: &str, start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>) -> bool {
+        if self.column != column {
+            return false;
+        }
+        self.files.get(name).is_some_and(|range| !range.overlaps(start, end))
+    }
+}
@@ -0,0 +1,110 @@
+//! Writes buffered rows as JSON lines, for greppable output or tools that do not read Parquet.
+//!
+//! Every row is a JSON object keyed by the column names of the `Persistable` schema, timestamps are written as
+//! RFC 3339 strings and nulls are kept so every line has the same keys.
+
+use std::io::Write;
+
+use chrono::DateTime;
+use parquet::basic::{LogicalType, TimeUnit};
+use parquet::record::Field;
+use parquet::schema::types::TypePtr;
+use serde_json::Value;
+
+use crate::error::PersistError;
+use crate::row::RowBuffer;
+use crate::Persistable;
+
+pub struct JsonlWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonlWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Writes and clears the buffered rows, returns the number of rows written.
+    pub fn write(&mut self, fields: &[TypePtr], buffer: &mut RowBuffer) -> Result<usize, PersistError> {
+        let size = buffer.len();
+        let columns = buffer.columns();
+        if columns.len() != fields.len() {
+            return Err(PersistError::SchemaMismatch {
+                expected: format!("{} fields", fields.len()),
+                found: format!("{} columns buffered", columns.len()),
+            });
+        }
+        for row in 0..size {
+            // written field by field to keep the column order of the schema
+            self.writer.write_all(b"{")?;
+            for (i, (field, column)) in fields.iter().zip(columns).enumerate() {
+                if i > 0 {
+                    self.writer.write_all(b",")?;
+                }
+                serde_json::to_writer(&mut self.writer, field.name()).map_err(json_error)?;
+                self.writer.write_all(b":")?;
+                serde_json::to_writer(&mut self.writer, &value(field, &column[row])).map_err(json_error)?;
+            }
+            self.writer.write_all(b"}\n")?;
+        }
+        self.writer.flush()?;
+        buffer.clear();
+        Ok(size)
+    }
+}
+
+/// Serializes a single record as a JSON object, with the same keys and values as the lines of a JSON lines table.
+pub fn to_json<T: Persistable>(record: &T) -> Result<Vec<u8>, PersistError> {
+    let mut fields = vec![];
+    T::schema(&mut fields, None, None, None);
+    let mut buffer = RowBuffer::default();
+    buffer.begin();
+    record.append(&mut buffer)?;
+
+    let mut line = vec![];
+    JsonlWriter::new(&mut line).write(&fields, &mut buffer)?;
+    line.pop(); // trailing new line
+    Ok(line)
+}
+
+/// Converts a buffered value to JSON, using the logical type of the field for timestamps.
+pub(crate) fn value(field: &TypePtr, value: &Field) -> Value {
+    let timestamp = match field.get_basic_info().logical_type() {
+        Some(LogicalType::Timestamp { unit, .. }) => Some(unit),
+        _ => None,
+    };
+    match (value, timestamp) {
+        (Field::Long(epoch), Some(unit)) => epoch_timestamp(*epoch, &unit),
+        (Field::ULong(epoch), Some(unit)) => epoch_timestamp(*epoch as i64, &unit),
+        (Field::Null, _) => Value::Null,
+        (Field::Bool(value), _) => Value::from(*value),
+        (Field::Int(value), _) => Value::from(*value),
+        (Field::UInt(value), _) => Value::from(*value),
+        (Field::Long(value), _) => Value::from(*value),
+        (Field::ULong(value), _) => Value::from(*value),
+        (Field::Float(value), _) => Value::from(*value),
+        (Field::Double(value), _) => Value::from(*value),
+        (Field::Str(value), _) => Value::from(value.as_str()),
+        (Field::Bytes(value), _) => match value.as_utf8() {
+            Ok(value) => Value::from(value),
+            Err(_) => Value::from(value.to_string()),
+        },
+        (value, _) => Value::from(value.to_string()),
+    }
+}
+
+fn epoch_timestamp(epoch: i64, unit: &TimeUnit) -> Value {
+    let time = match unit {
+        TimeUnit::NANOS(_) => Some(DateTime::from_timestamp_nanos(epoch)),
+        TimeUnit::MICROS(_) => DateTime::from_timestamp_micros(epoch),
+        TimeUnit::MILLIS(_) => DateTime::from_timestamp_millis(epoch),
+    };
+    match time {
+        Some(time) => Value::from(time.to_rfc3339()),
+        None => Value::from(epoch),
+    }
+}
+
+fn json_error(e: serde_json::Error) -> PersistError {
+    PersistError::Other(e.to_string())
+}
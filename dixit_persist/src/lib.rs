@@ -1,26 +1,57 @@
+pub mod checksum;
+pub mod clock;
+pub mod compression;
 pub mod config;
+pub mod csv;
+pub mod delta;
+pub mod dictionary;
+pub mod dynamic;
 pub mod error;
+pub mod finalize;
+#[cfg(feature = "tokio")]
+pub mod handle;
+pub mod iceberg;
+pub mod index;
+pub mod jsonl;
+pub mod lock;
+pub mod memory;
+pub mod merge;
+pub mod output;
+pub mod path;
+pub mod pool;
+pub mod reader;
+pub mod retry;
 pub mod row;
+#[cfg(feature = "tokio")]
+pub mod service;
+pub mod spill;
+pub mod stats;
 pub mod writer;
 
 use crate::row::RowBuffer;
 
+use bytes::Bytes;
 use chrono::{DateTime, TimeZone};
 use compact_str::CompactString;
+use itertools::Itertools;
 use parquet::basic::Type as PhysicalType;
 use parquet::basic::{LogicalType, Repetition, TimeUnit};
+use parquet::data_type::ByteArray;
 use parquet::errors::ParquetError;
 use parquet::format::NanoSeconds;
 use parquet::record::Field;
 use parquet::schema::types::{Type, TypePtr};
 use rust_decimal::prelude::ToPrimitive;
-use std::any::type_name;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
-use std::sync::LazyLock;
-use std::sync::Mutex;
 use std::time::Duration;
 
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` cannot be persisted as it does not implement `Persistable`",
+    label = "not persistable",
+    note = "derive `Persist` for it, wrap it in a `#[persist(transparent)]` newtype implementing `Persistable`, \
+            or skip the field with `#[persist(ignore)]`"
+)]
 pub trait Persistable {
     fn schema(
         fields: &mut Vec<TypePtr>,
@@ -31,29 +62,91 @@ pub trait Persistable {
 
     fn append(&self, row: &mut RowBuffer) -> Result<(), ParquetError>;
 
-    fn field_count() -> usize
+    /// Appends the value as `append`, string values being interned, see `#[persist(intern)]` and
+    /// [`RowBuffer::push_interned`].
+    fn append_interned(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
+        self.append(row)
+    }
+
+    /// Appends the value as `append`, string and bytes values being compressed, see `#[persist(compress = "zstd")]`
+    /// and [`compression`].
+    fn append_compressed(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
+        self.append(row)
+    }
+
+    /// Returns true if the record must not be persisted, see `#[persist(ignore_if = "...")]`.
+    fn ignored(&self) -> bool {
+        false
+    }
+
+    /// Describes every column as `name physical_type logical_type repetition`, in schema order.
+    fn schema_columns() -> Vec<String>
     where
         Self: Sized,
     {
-        static FIELD_COUNT: LazyLock<Mutex<HashMap<String, usize>>> = LazyLock::new(|| Mutex::new(Default::default()));
+        let mut fields = Vec::new();
+        Self::schema(&mut fields, None, None, None);
+        fields
+            .iter()
+            .map(|field| {
+                let info = field.get_basic_info();
+                format!(
+                    "{} {} {:?} {}",
+                    field.name(),
+                    field.get_physical_type(),
+                    info.logical_type(),
+                    info.repetition()
+                )
+            })
+            .collect()
+    }
 
-        let mut map = FIELD_COUNT.lock().unwrap();
+    /// Stable (FNV-1a) hash of the [`Persistable::schema_columns`], changes whenever a column is added, removed,
+    /// renamed or retyped. Meant to be pinned in tests with [`assert_schema_fingerprint`].
+    fn schema_fingerprint() -> u64
+    where
+        Self: Sized,
+    {
+        Self::schema_columns()
+            .join("\n")
+            .bytes()
+            .fold(0xcbf29ce484222325, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+    }
 
-        let name = type_name::<Self>();
-        let count = match map.get(name) {
-            None => {
-                let mut fields = Vec::new();
-                Self::schema(&mut fields, Some("any"), None, None);
-                let count = fields.len();
-                map.insert(name.to_string(), count);
-                count
-            }
-            Some(count) => *count,
-        };
-        count
+    /// Number of columns of the type, counted from its schema unless overridden. Called for every null value
+    /// written, so the derive and the types of this crate return it without building the schema.
+    fn field_count() -> usize
+    where
+        Self: Sized,
+    {
+        let mut fields = Vec::new();
+        Self::schema(&mut fields, Some("any"), None, None);
+        fields.len()
     }
 }
 
+/// Fails when the schema fingerprint of a type is not the expected one, printing the new fingerprint and columns.
+///
+/// ```ignore
+/// assert_schema_fingerprint!(Trade, 0x5a1e3d2c4b6f7081);
+/// ```
+#[macro_export]
+macro_rules! assert_schema_fingerprint {
+    ($type:ty, $expected:expr) => {{
+        let actual = <$type as $crate::Persistable>::schema_fingerprint();
+        assert_eq!(
+            $expected,
+            actual,
+            "schema of {} changed, fingerprint is now {:#018x}:\n{}",
+            stringify!($type),
+            actual,
+            <$type as $crate::Persistable>::schema_columns().join("\n")
+        );
+    }};
+}
+
 impl Persistable for String {
     fn schema(
         fields: &mut Vec<TypePtr>,
@@ -74,11 +167,32 @@ impl Persistable for String {
 
     #[inline]
     fn append(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
-        row.push(parquet::record::Field::Str(self.clone()));
+        row.push_str(self);
+        Ok(())
+    }
+
+    #[inline]
+    fn append_interned(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
+        row.push_interned(self);
         Ok(())
     }
+
+    fn append_compressed(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
+        row.push(Field::Bytes(compression::compress(self.as_bytes())?));
+        Ok(())
+    }
+
+    #[inline]
+    fn field_count() -> usize {
+        1
+    }
 }
 
+/// The columns of `T`, all optional, a `None` being null in every column.
+///
+/// Nested options collapse: `Some(None)` of an `Option<Option<T>>` is written as `None`, as is `Some` of a struct
+/// whose columns are all null. A `None` container (e.g. `Option<Vec<T>>` or `Option<HashMap<K, V>>`) is null while
+/// an empty container is `[]` or `{}`.
 impl<T: Persistable> Persistable for Option<T> {
     fn schema(
         fields: &mut Vec<TypePtr>,
@@ -100,6 +214,69 @@ impl<T: Persistable> Persistable for Option<T> {
         }
         Ok(())
     }
+
+    #[inline]
+    fn append_interned(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
+        if let Some(ref value) = *self {
+            T::append_interned(value, row)?;
+        } else {
+            for _ in 0..T::field_count() {
+                row.push(Field::Null);
+            }
+        }
+        Ok(())
+    }
+
+    fn append_compressed(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
+        if let Some(ref value) = *self {
+            T::append_compressed(value, row)?;
+        } else {
+            for _ in 0..T::field_count() {
+                row.push(Field::Null);
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn field_count() -> usize {
+        T::field_count()
+    }
+}
+
+/// Raw bytes, as a `BYTE_ARRAY` column without logical type.
+impl Persistable for Bytes {
+    fn schema(
+        fields: &mut Vec<TypePtr>,
+        prefix: Option<&str>,
+        repetition_override: Option<Repetition>,
+        _logical_type: Option<LogicalType>,
+    ) {
+        let prefix = prefix.expect("name must be set");
+        fields.push(
+            Type::primitive_type_builder(prefix, PhysicalType::BYTE_ARRAY)
+                .with_repetition(repetition_override.unwrap_or(Repetition::REQUIRED))
+                .build()
+                .unwrap()
+                .into(),
+        );
+    }
+
+    #[inline]
+    fn append(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
+        row.push(Field::Bytes(ByteArray::from(self.clone())));
+        Ok(())
+    }
+
+    fn append_compressed(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
+        row.push(Field::Bytes(compression::compress(self)?));
+        Ok(())
+    }
+
+    #[inline]
+    fn field_count() -> usize {
+        1
+    }
 }
 
 impl Persistable for &str {
@@ -122,11 +299,28 @@ impl Persistable for &str {
 
     #[inline]
     fn append(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
-        row.push(parquet::record::Field::Str(self.to_string()));
+        row.push_str(self);
         Ok(())
     }
+
+    #[inline]
+    fn append_interned(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
+        row.push_interned(self);
+        Ok(())
+    }
+
+    fn append_compressed(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
+        row.push(Field::Bytes(compression::compress(self.as_bytes())?));
+        Ok(())
+    }
+
+    #[inline]
+    fn field_count() -> usize {
+        1
+    }
 }
 
+/// A string column holding the `Debug` string of the items (e.g. `[1, 2]`), `[]` if empty.
 impl<T: Persistable + Debug> Persistable for Vec<T> {
     fn schema(
         fields: &mut Vec<TypePtr>,
@@ -150,8 +344,15 @@ impl<T: Persistable + Debug> Persistable for Vec<T> {
         row.push(Field::Str(format!("{:?}", self)));
         Ok(())
     }
+
+    #[inline]
+    fn field_count() -> usize {
+        1
+    }
 }
 
+/// A string column holding the `Debug` string of the items (e.g. `{1, 2}`), `{}` if empty. The items are sorted by
+/// their `Debug` string, so equal sets are written the same.
 impl<T: Persistable + Debug> Persistable for HashSet<T> {
     fn schema(
         fields: &mut Vec<TypePtr>,
@@ -170,13 +371,20 @@ impl<T: Persistable + Debug> Persistable for HashSet<T> {
         );
     }
 
-    #[inline]
     fn append(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
-        row.push(Field::Str(format!("{:?}", self)));
+        let items = self.iter().map(|item| format!("{item:?}")).sorted().join(", ");
+        row.push(Field::Str(format!("{{{items}}}")));
         Ok(())
     }
+
+    #[inline]
+    fn field_count() -> usize {
+        1
+    }
 }
 
+/// A string column holding the `Debug` string of the entries (e.g. `{"a": 1}`), `{}` if empty. The entries are sorted
+/// by the `Debug` string of their key, so equal maps are written the same.
 impl<K: Persistable + Debug, V: Persistable + Debug> Persistable for HashMap<K, V> {
     fn schema(
         fields: &mut Vec<TypePtr>,
@@ -195,11 +403,21 @@ impl<K: Persistable + Debug, V: Persistable + Debug> Persistable for HashMap<K,
         );
     }
 
-    #[inline]
     fn append(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
-        row.push(Field::Str(format!("{:?}", self)));
+        let entries = self
+            .iter()
+            .map(|(key, value)| (format!("{key:?}"), format!("{value:?}")))
+            .sorted()
+            .map(|(key, value)| format!("{key}: {value}"))
+            .join(", ");
+        row.push(Field::Str(format!("{{{entries}}}")));
         Ok(())
     }
+
+    #[inline]
+    fn field_count() -> usize {
+        1
+    }
 }
 
 impl<X: Persistable, Y: Persistable> Persistable for (X, Y) {
@@ -237,6 +455,11 @@ impl<X: Persistable, Y: Persistable> Persistable for (X, Y) {
         self.1.append(row)?;
         Ok(())
     }
+
+    #[inline]
+    fn field_count() -> usize {
+        X::field_count() + Y::field_count()
+    }
 }
 
 macro_rules! define_schema {
@@ -257,6 +480,11 @@ macro_rules! define_schema {
                     .into(),
             );
         }
+
+        #[inline]
+        fn field_count() -> usize {
+            1
+        }
     };
 }
 
@@ -325,6 +553,11 @@ impl<Tz: TimeZone> Persistable for DateTime<Tz> {
         ));
         Ok(())
     }
+
+    #[inline]
+    fn field_count() -> usize {
+        1
+    }
 }
 
 impl Persistable for Duration {
@@ -348,6 +581,11 @@ impl Persistable for Duration {
         ns.append(row)?;
         Ok(())
     }
+
+    #[inline]
+    fn field_count() -> usize {
+        1
+    }
 }
 
 macro_rules! impl_persistable_for_arrays {
@@ -371,6 +609,11 @@ macro_rules! impl_persistable_for_arrays {
                     }
                     Ok(())
                 }
+
+                #[inline]
+                fn field_count() -> usize {
+                    $len * T::field_count()
+                }
             }
         )*
     }
@@ -401,9 +644,25 @@ impl Persistable for CompactString {
 
     #[inline]
     fn append(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
-        row.push(parquet::record::Field::Str(self.to_string()));
+        row.push_str(self);
+        Ok(())
+    }
+
+    #[inline]
+    fn append_interned(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
+        row.push_interned(self);
+        Ok(())
+    }
+
+    fn append_compressed(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
+        row.push(Field::Bytes(compression::compress(self.as_bytes())?));
         Ok(())
     }
+
+    #[inline]
+    fn field_count() -> usize {
+        1
+    }
 }
 
 impl Persistable for rust_decimal::Decimal {
@@ -430,4 +689,9 @@ impl Persistable for rust_decimal::Decimal {
         row.push(parquet::record::Field::Double(self.to_f64().unwrap()));
         Ok(())
     }
+
+    #[inline]
+    fn field_count() -> usize {
+        1
+    }
 }
@@ -0,0 +1,53 @@
+//! Advisory lock of a table directory, so two writers (e.g. two recorders pointed at the same output directory) never
+//! interleave their files or delete each other's data.
+//!
+//! ## Features
+//! - Locks a `.lock` file in the table directory, held until the writer is dropped (or its process dies).
+//! - Fails at once when the table is locked by another writer, or waits up to `PersistConfig::lock_timeout` (the
+//!   persist service sleeps between its own attempts instead, so as not to block its runtime).
+//! - Uses `flock` on Unix and `LockFileEx` on Windows through `fs4`, other writers must lock the table too.
+
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use fs4::fs_std::FileExt;
+
+use crate::error::PersistError;
+
+pub const LOCK_FILE: &str = ".lock";
+
+/// Interval between two attempts to lock a table locked by another writer.
+pub(crate) const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+pub struct TableLock {
+    // the lock is released when the file is closed
+    _file: File,
+}
+
+impl TableLock {
+    /// Locks the table `directory`, waiting up to `timeout` (if any) for the writer holding it.
+    pub fn acquire(directory: &Path, timeout: Option<Duration>) -> Result<Self, PersistError> {
+        let path = directory.join(LOCK_FILE);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+        let start = Instant::now();
+        while !file.try_lock_exclusive()? {
+            match timeout {
+                Some(timeout) if start.elapsed() < timeout => thread::sleep(RETRY_INTERVAL),
+                _ => {
+                    return Err(PersistError::DirectoryLocked {
+                        directory: directory.to_owned(),
+                        waited: timeout,
+                    })
+                }
+            }
+        }
+        Ok(Self { _file: file })
+    }
+}
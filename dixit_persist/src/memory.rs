@@ -0,0 +1,98 @@
+//! Encodes a table as a parquet file in memory, for tests without temporary directories or services serving freshly
+//! encoded parquet (e.g. over HTTP).
+//!
+//! ## Features
+//! - Rows are recorded as with a [`TableWriter`], typed or dynamic, until [`MemoryTableWriter::flush_to_bytes`].
+//! - Every flush appends row groups to the same file, so the bytes hold a single parquet file whatever the number of
+//!   rows, written as plain parquet whatever the format of the config.
+//! - Nothing is written to the filesystem, the directory and the tables of the config are ignored.
+
+use std::io::{self, Write};
+use std::mem;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use bytes::Bytes;
+
+use crate::config::{Format, PersistConfig};
+use crate::error::PersistError;
+use crate::writer::{RowBuilder, TableStats, TableWriter};
+
+/// Parquet files completed by the writer, taken by `flush_to_bytes`.
+type Files = Arc<Mutex<Vec<Bytes>>>;
+
+pub struct MemoryTableWriter {
+    writer: TableWriter,
+    files: Files,
+}
+
+impl MemoryTableWriter {
+    /// Creates the writer, using the row group size and the validation of the config.
+    pub fn new(path_prefix: &str, persist_config: &PersistConfig) -> Result<Self> {
+        let config = PersistConfig {
+            tables: Default::default(),
+            format: Format::Parquet,
+            table_formats: Default::default(),
+            rows_per_file: usize::MAX,
+            checksum: false,
+            ..persist_config.clone()
+        };
+        let files = Files::default();
+        let output = {
+            let files = files.clone();
+            move |_: &Path| -> io::Result<Box<dyn Write + Send>> {
+                Ok(Box::new(MemoryFile {
+                    bytes: vec![],
+                    files: files.clone(),
+                }))
+            }
+        };
+        Ok(Self {
+            writer: TableWriter::with_output(path_prefix, &config, output)?,
+            files,
+        })
+    }
+
+    pub fn begin(&mut self) -> Result<RowBuilder> {
+        self.writer.begin()
+    }
+
+    /// Encodes the rows recorded since the previous call as a parquet file, `None` if there are none.
+    pub fn flush_to_bytes(&mut self) -> Result<Option<Bytes>, PersistError> {
+        let closed = self.writer.close();
+        // a file failing to close is incomplete, it is dropped either way
+        let file = self.files.lock().expect("memory files lock poisoned").pop();
+        closed?;
+        Ok(file)
+    }
+
+    pub fn stats(&self) -> TableStats {
+        self.writer.stats()
+    }
+}
+
+/// Parquet file being written, handed over to the files of its writer once complete.
+struct MemoryFile {
+    bytes: Vec<u8>,
+    files: Files,
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.bytes.flush()
+    }
+}
+
+impl Drop for MemoryFile {
+    fn drop(&mut self) {
+        let bytes = Bytes::from(mem::take(&mut self.bytes));
+        if let Ok(mut files) = self.files.lock() {
+            files.push(bytes);
+        }
+    }
+}
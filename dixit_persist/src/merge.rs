@@ -0,0 +1,115 @@
+//! Merges the rows of several tables (e.g. the trades of three venues) into a single chronological stream, for
+//! backtests.
+//!
+//! ## Features
+//! - K-way merge of [`TableReader`]s by the time of their rows, holding a single row per table in memory.
+//! - Each table is read in its own order, chronological for the tables recorded live, the merge only ordering the
+//!   rows across the tables.
+//! - The rows of the same time are yielded in the order of the tables, the rows without a time as soon as read.
+//! - Every row is returned with the index of its table, the readers keeping their own time column, time range and
+//!   predicates (see [`TableReader::between`] and [`TableReader::with_predicate`]).
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use parquet::record::Row;
+
+use crate::error::PersistError;
+use crate::reader::{Readable, TableReader};
+
+/// Merges the rows of several tables by time, see the module documentation.
+pub struct MergeReader<T = Row> {
+    readers: Vec<TableReader>,
+    /// next row of every table not exhausted yet, earliest first
+    heads: BinaryHeap<Reverse<Head>>,
+    started: bool,
+    _record: PhantomData<T>,
+}
+
+/// Next row of a table.
+struct Head {
+    time: Option<DateTime<Utc>>,
+    table: usize,
+    row: Row,
+}
+
+impl Head {
+    fn key(&self) -> (Option<DateTime<Utc>>, usize) {
+        (self.time, self.table)
+    }
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Head {}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl<T: Readable> MergeReader<T> {
+    /// Merges the rows of the readers, the index of a table being its index in `readers`.
+    pub fn new(readers: Vec<TableReader>) -> Self {
+        Self {
+            heads: BinaryHeap::with_capacity(readers.len()),
+            readers,
+            started: false,
+            _record: PhantomData,
+        }
+    }
+
+    /// Merges every row of the table directories by their `time` column.
+    pub fn open<P: AsRef<Path>>(directories: impl IntoIterator<Item = P>) -> Result<Self, PersistError> {
+        let readers = directories
+            .into_iter()
+            .map(|directory| TableReader::open(directory.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(readers))
+    }
+
+    /// Reads the next row of a table into the heads, if any.
+    fn pull(&mut self, table: usize) -> Result<(), PersistError> {
+        let reader = &mut self.readers[table];
+        if let Some(row) = reader.next_row() {
+            let row = row?;
+            let time = reader.time(&row);
+            self.heads.push(Reverse(Head { time, table, row }));
+        }
+        Ok(())
+    }
+}
+
+impl<T: Readable> Iterator for MergeReader<T> {
+    type Item = Result<(usize, T), PersistError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            for table in 0..self.readers.len() {
+                if let Err(e) = self.pull(table) {
+                    return Some(Err(e));
+                }
+            }
+        }
+        let Reverse(head) = self.heads.pop()?;
+        if let Err(e) = self.pull(head.table) {
+            return Some(Err(e));
+        }
+        Some(T::from_row(head.row).map(|record| (head.table, record)))
+    }
+}
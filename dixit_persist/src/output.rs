@@ -0,0 +1,49 @@
+//! Where a [`TableWriter`](crate::writer::TableWriter) writes its files, the filesystem by default, any `Write`
+//! otherwise (e.g. an in-memory buffer, an HTTP body, an object store upload).
+//!
+//! ## Features
+//! - Every file of the table is opened through [`Output::create`], parquet, CSV and JSON lines alike.
+//! - A file is complete once its writer is dropped, after the last byte of the file has been written and flushed.
+//! - [`FileOutput`] is the output of [`TableWriter::new`](crate::writer::TableWriter::new), creating the files in
+//!   the table directory and failing if one already exists.
+//! - Writers over another output (see [`TableWriter::with_output`](crate::writer::TableWriter::with_output)) do not
+//!   touch the filesystem: no directory, no lock and no table log.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Opens the files of a table.
+pub trait Output: Send {
+    /// Opens a new file, `path` being the table path (e.g. `coinbase/trades`) joined with the file name.
+    fn create(&mut self, path: &Path) -> io::Result<Box<dyn Write + Send>>;
+
+    /// Returns true if a file already exists at `path`, so files named by index are not overwritten.
+    fn exists(&self, _path: &Path) -> bool {
+        false
+    }
+}
+
+/// Creates the files on the filesystem.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileOutput;
+
+impl Output for FileOutput {
+    fn create(&mut self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(File::create_new(path)?))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+}
+
+/// Any function opening a writer is an output, e.g. `|path: &Path| Ok(Box::new(upload(path)?))`.
+impl<F> Output for F
+where
+    F: FnMut(&Path) -> io::Result<Box<dyn Write + Send>> + Send,
+{
+    fn create(&mut self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        self(path)
+    }
+}
@@ -0,0 +1,89 @@
+//! Path handling shared by the table writers and logs, so tables are laid out the same way on Unix and Windows.
+//!
+//! ## Features
+//! - Table names are `/` separated on every platform (e.g. `coinbase/trades`), each segment being a folder.
+//! - Paths stay `Path`s (non UTF-8 paths included), they are only converted to strings in the table logs, as `file:`
+//!   URIs or `/` separated relative paths.
+//! - Windows drive, UNC (`\\server\share`) and verbatim (`\\?\C:\`) paths are written as `file:` URIs, table
+//!   directories are opened through verbatim paths so they are not limited to 260 characters.
+
+use std::io;
+use std::path::{Component, Path, PathBuf, Prefix};
+
+use uuid::Uuid;
+
+/// Id of a new table in its log, the Delta `metaData.id` or the Iceberg `table-uuid`.
+pub fn table_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+/// Directory of a table in the persist directory, `table` being `/` separated.
+pub fn table_path(directory: &Path, table: &str) -> PathBuf {
+    let mut path = directory.to_path_buf();
+    path.extend(table.split('/').filter(|segment| !segment.is_empty()));
+    path
+}
+
+/// Joins the components of a relative path with `/`, whatever the platform separator.
+pub fn relative_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Formats an absolute path as a `file:` URI, e.g. `file:///data/trades`, `file:///C:/data/trades` or
+/// `file://server/share/trades`.
+pub fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => match prefix.kind() {
+                Prefix::Disk(disk) | Prefix::VerbatimDisk(disk) => uri.push_str(&format!("/{}:", disk as char)),
+                Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => {
+                    uri.push_str(&format!("{}/{}", server.to_string_lossy(), share.to_string_lossy()))
+                }
+                Prefix::Verbatim(name) | Prefix::DeviceNS(name) => {
+                    uri.push('/');
+                    uri.push_str(&name.to_string_lossy());
+                }
+            },
+            Component::RootDir => (),
+            component => {
+                uri.push('/');
+                uri.push_str(&component.as_os_str().to_string_lossy());
+            }
+        }
+    }
+    if uri.len() == "file://".len() {
+        uri.push('/');
+    }
+    uri
+}
+
+/// Returns the absolute verbatim form of a Windows path (`\\?\C:\...` or `\\?\UNC\server\share\...`), which is not
+/// limited to 260 characters, other platforms have no such limit and get the path back.
+pub fn long_path(path: &Path) -> io::Result<PathBuf> {
+    if !cfg!(windows) {
+        return Ok(path.to_path_buf());
+    }
+    let path = std::path::absolute(path)?;
+    let mut components = path.components();
+    let mut long = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(disk) => PathBuf::from(format!(r"\\?\{}:", disk as char)),
+            Prefix::UNC(server, share) => {
+                let mut long = PathBuf::from(r"\\?\UNC");
+                long.push(server);
+                long.push(share);
+                long
+            }
+            // already verbatim or a device path
+            _ => return Ok(path),
+        },
+        _ => return Ok(path),
+    };
+    long.push(r"\");
+    long.extend(components.filter(|component| !matches!(component, Component::RootDir)));
+    Ok(long)
+}
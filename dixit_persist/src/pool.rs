@@ -0,0 +1,101 @@
+//! Recycles the buffers of the writers, so the writers created and dropped over a long run (e.g. per session or per
+//! table rotated out) reuse the column vectors of the previous ones instead of growing new ones.
+//!
+//! ## Features
+//! - Cheaply cloneable pool shared by the writers, set with
+//!   [`TableWriter::with_buffer_pool`](crate::writer::TableWriter::with_buffer_pool).
+//! - A writer takes a buffer from the pool when created and hands it back when dropped, once its rows are flushed.
+//! - Keeps at most `max_buffers` buffers, the buffers recycled beyond that are freed.
+//! - Reports the buffers created, reused, recycled and freed, with high-water marks of the rows buffered at once by a
+//!   writer and of the values held by the pool, see [`BufferPool::stats`].
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::row::RowBuffer;
+
+/// Buffers recycled by the writers, see the module documentation.
+#[derive(Clone)]
+pub struct BufferPool {
+    pool: Arc<Mutex<Pool>>,
+}
+
+struct Pool {
+    buffers: Vec<RowBuffer>,
+    max_buffers: usize,
+    stats: PoolStats,
+}
+
+/// Counters of a pool, see [`BufferPool::stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PoolStats {
+    /// buffers created as the pool had none to hand out
+    pub created: u64,
+    /// buffers handed out again after being recycled
+    pub reused: u64,
+    /// buffers handed back to the pool
+    pub recycled: u64,
+    /// buffers freed as the pool already held `max_buffers`
+    pub freed: u64,
+    /// buffers held by the pool
+    pub pooled: usize,
+    /// values the buffers held by the pool store without reallocating
+    pub capacity: usize,
+    /// most values held by the pool at once
+    pub high_water_capacity: usize,
+    /// most rows buffered at once by a writer, over the buffers recycled
+    pub high_water_rows: usize,
+}
+
+impl BufferPool {
+    /// Creates a pool holding at most `max_buffers` buffers, e.g. the number of tables written at once.
+    pub fn new(max_buffers: usize) -> Self {
+        Self {
+            pool: Arc::new(Mutex::new(Pool {
+                buffers: vec![],
+                max_buffers,
+                stats: PoolStats::default(),
+            })),
+        }
+    }
+
+    /// A recycled buffer if any, an empty one otherwise.
+    pub fn take(&self) -> RowBuffer {
+        let mut pool = self.lock();
+        match pool.buffers.pop() {
+            Some(buffer) => {
+                pool.stats.reused += 1;
+                pool.stats.pooled -= 1;
+                pool.stats.capacity -= buffer.capacity();
+                buffer
+            }
+            None => {
+                pool.stats.created += 1;
+                RowBuffer::default()
+            }
+        }
+    }
+
+    /// Hands a buffer back to the pool, its rows are dropped.
+    pub fn recycle(&self, mut buffer: RowBuffer) {
+        let mut pool = self.lock();
+        pool.stats.recycled += 1;
+        pool.stats.high_water_rows = pool.stats.high_water_rows.max(buffer.peak());
+        if pool.buffers.len() >= pool.max_buffers {
+            pool.stats.freed += 1;
+            return;
+        }
+        buffer.reset();
+        pool.stats.pooled += 1;
+        pool.stats.capacity += buffer.capacity();
+        pool.stats.high_water_capacity = pool.stats.high_water_capacity.max(pool.stats.capacity);
+        pool.buffers.push(buffer);
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.lock().stats
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Pool> {
+        self.pool.lock().expect("buffer pool lock poisoned")
+    }
+}
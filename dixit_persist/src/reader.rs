@@ -0,0 +1,585 @@
+//! Reads back the parquet files of a table recorded by a [`TableWriter`](crate::writer::TableWriter).
+//!
+//! ## Features
+//! - Streams the rows of every parquet file of the table directory, in file name order (chronological for files named
+//!   by index or timestamp), one file open at a time.
+//! - Skips the other entries of the directory: the lock file, checksum sidecars, CSV or JSON lines files and the
+//!   delta or iceberg metadata folders.
+//! - Rows are read as parquet [`Row`]s, or as any type implementing [`Readable`].
+//! - Optionally reads the rows of a time range ([`TableReader::between`]) and the rows matching simple predicates
+//!   comparing a column to a number, a time or a string ([`TableReader::with_predicate`]).
+//! - Skips the files outside of the time range according to the time index of the table, if kept (see
+//!   [`crate::index`]), and the row groups whose column statistics exclude every match, without decoding them.
+//! - Rows with a null or without the column of a predicate never match it.
+//! - `#[derive(Readable)]` builds a struct from the columns named after its fields, the columns missing from older
+//!   files being filled by the fields marked `#[persist(default)]` or `#[persist(default = "expr")]`, so the files
+//!   written before a column was added can still be read into the current struct.
+
+use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::vec;
+
+use chrono::{DateTime, Utc};
+use compact_str::CompactString;
+use parquet::basic::{LogicalType, TimeUnit};
+use parquet::file::metadata::RowGroupMetaData;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::serialized_reader::ReadOptionsBuilder;
+use parquet::file::statistics::Statistics;
+use parquet::record::reader::RowIter;
+use parquet::record::{Field, Row};
+use parquet::schema::types::SchemaDescriptor;
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+
+use crate::config::Format;
+use crate::error::PersistError;
+use crate::finalize;
+use crate::index::TableIndex;
+
+/// A record built from a row read by a [`TableReader`].
+pub trait Readable: Sized {
+    fn from_row(row: Row) -> Result<Self, PersistError>;
+}
+
+impl Readable for Row {
+    fn from_row(row: Row) -> Result<Self, PersistError> {
+        Ok(row)
+    }
+}
+
+/// A field of a [`Readable`] struct, read from the value of its column.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` cannot be read from a column as it does not implement `FromField`",
+    label = "not readable",
+    note = "skip the field with `#[persist(ignore)]`, it is then set to its default"
+)]
+pub trait FromField: Sized {
+    /// Converts the value of the column, none if of another type.
+    fn from_field(field: &Field) -> Option<Self>;
+}
+
+impl<T: FromField> FromField for Option<T> {
+    fn from_field(field: &Field) -> Option<Self> {
+        match field {
+            Field::Null => Some(None),
+            field => T::from_field(field).map(Some),
+        }
+    }
+}
+
+impl FromField for String {
+    fn from_field(field: &Field) -> Option<Self> {
+        match field {
+            Field::Str(text) => Some(text.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl FromField for CompactString {
+    fn from_field(field: &Field) -> Option<Self> {
+        match field {
+            Field::Str(text) => Some(CompactString::from(text.as_str())),
+            _ => None,
+        }
+    }
+}
+
+impl FromField for bool {
+    fn from_field(field: &Field) -> Option<Self> {
+        match field {
+            Field::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromField for f64 {
+    fn from_field(field: &Field) -> Option<Self> {
+        match field {
+            Field::Double(value) => Some(*value),
+            Field::Float(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+}
+
+impl FromField for f32 {
+    fn from_field(field: &Field) -> Option<Self> {
+        match field {
+            Field::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromField for Decimal {
+    fn from_field(field: &Field) -> Option<Self> {
+        match field {
+            Field::Double(value) => Decimal::from_f64(*value),
+            _ => None,
+        }
+    }
+}
+
+/// Integers are read from the integer columns they fit in, the epochs of the timestamp columns in their own unit.
+macro_rules! integer_from_field {
+    ($($type:ty),*) => {
+        $(
+            impl FromField for $type {
+                fn from_field(field: &Field) -> Option<Self> {
+                    let value = match *field {
+                        Field::Byte(value) => i128::from(value),
+                        Field::Short(value) => i128::from(value),
+                        Field::Int(value) => i128::from(value),
+                        Field::Long(value) | Field::TimestampMillis(value) | Field::TimestampMicros(value) => {
+                            i128::from(value)
+                        }
+                        Field::UByte(value) => i128::from(value),
+                        Field::UShort(value) => i128::from(value),
+                        Field::UInt(value) => i128::from(value),
+                        Field::ULong(value) => i128::from(value),
+                        _ => return None,
+                    };
+                    <$type>::try_from(value).ok()
+                }
+            }
+        )*
+    };
+}
+
+integer_from_field!(i16, u16, i32, u32, i64, u64, isize, usize);
+
+/// Times are written in nanoseconds, read as epochs of 64 bits.
+impl FromField for DateTime<Utc> {
+    fn from_field(field: &Field) -> Option<Self> {
+        match *field {
+            Field::Long(epoch) => Some(DateTime::from_timestamp_nanos(epoch)),
+            Field::TimestampMicros(epoch) => DateTime::from_timestamp_micros(epoch),
+            Field::TimestampMillis(epoch) => DateTime::from_timestamp_millis(epoch),
+            _ => None,
+        }
+    }
+}
+
+/// Reads a field of a [`Readable`] struct from its column, used by `#[derive(Readable)]`.
+pub fn column<T: FromField>(row: &Row, column: &str) -> Result<T, PersistError> {
+    column_or(row, column, || Err(PersistError::ColumnMissing(column.to_owned())))
+}
+
+/// Reads a field as [`column`], the default being used when the row has no such column (e.g. a file written before
+/// the column was added), not when its value is null.
+pub fn column_or<T: FromField>(
+    row: &Row,
+    column: &str,
+    default: impl FnOnce() -> Result<T, PersistError>,
+) -> Result<T, PersistError> {
+    let Some((_, field)) = row.get_column_iter().find(|(name, _)| **name == column) else {
+        return default();
+    };
+    T::from_field(field).ok_or_else(|| PersistError::ColumnTypeMismatch {
+        column: column.to_owned(),
+        expected: std::any::type_name::<T>().to_owned(),
+        found: field.to_string(),
+    })
+}
+
+/// Comparison of a column to the value of a [`Predicate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+}
+
+impl Comparison {
+    /// Whether the comparison holds for a value ordered as `ordering` against the value of the predicate.
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Lt => ordering.is_lt(),
+            Comparison::Le => ordering.is_le(),
+            Comparison::Gt => ordering.is_gt(),
+            Comparison::Ge => ordering.is_ge(),
+            Comparison::Eq => ordering.is_eq(),
+        }
+    }
+
+    /// Whether the comparison may hold for a value between a min and a max ordered as `min` and `max`.
+    fn may_hold(self, min: Ordering, max: Ordering) -> bool {
+        match self {
+            Comparison::Lt | Comparison::Le => self.holds(min),
+            Comparison::Gt | Comparison::Ge => self.holds(max),
+            Comparison::Eq => min.is_le() && max.is_ge(),
+        }
+    }
+}
+
+/// Value a column is compared to by a [`Predicate`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    /// compared to the numeric columns, decimals included
+    Number(f64),
+    /// compared to the timestamp columns
+    Time(DateTime<Utc>),
+    /// compared to the string columns, byte by byte
+    Text(String),
+}
+
+impl From<f64> for Literal {
+    fn from(value: f64) -> Self {
+        Literal::Number(value)
+    }
+}
+
+impl From<i64> for Literal {
+    fn from(value: i64) -> Self {
+        Literal::Number(value as f64)
+    }
+}
+
+impl From<DateTime<Utc>> for Literal {
+    fn from(value: DateTime<Utc>) -> Self {
+        Literal::Time(value)
+    }
+}
+
+impl From<&str> for Literal {
+    fn from(value: &str) -> Self {
+        Literal::Text(value.to_owned())
+    }
+}
+
+impl From<String> for Literal {
+    fn from(value: String) -> Self {
+        Literal::Text(value)
+    }
+}
+
+/// Comparison of a top level column to a value, e.g. `Predicate::new("price", Comparison::Gt, 100.0)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Predicate {
+    pub column: String,
+    pub comparison: Comparison,
+    pub value: Literal,
+}
+
+impl Predicate {
+    pub fn new(column: &str, comparison: Comparison, value: impl Into<Literal>) -> Self {
+        Self {
+            column: column.to_owned(),
+            comparison,
+            value: value.into(),
+        }
+    }
+
+    /// Resolves the column in the schema of a file, none if the file has no such column.
+    fn resolve(&self, schema: &SchemaDescriptor) -> Result<Option<Bound>, PersistError> {
+        let leaf = (0..schema.num_columns()).find(|i| schema.column(*i).path().parts() == [self.column.as_str()]);
+        let Some(leaf) = leaf else {
+            return Ok(None);
+        };
+        let value = match &self.value {
+            Literal::Number(value) => Scalar::Number(*value),
+            Literal::Text(value) => Scalar::Bytes(value.as_bytes().to_vec()),
+            Literal::Time(time) => {
+                let Some(LogicalType::Timestamp { unit, .. }) = schema.column(leaf).logical_type() else {
+                    return Err(PersistError::ColumnTypeMismatch {
+                        column: self.column.clone(),
+                        expected: "a timestamp".to_owned(),
+                        found: schema.column(leaf).physical_type().to_string(),
+                    });
+                };
+                let epoch = match unit {
+                    TimeUnit::MILLIS(_) => Some(time.timestamp_millis()),
+                    TimeUnit::MICROS(_) => Some(time.timestamp_micros()),
+                    TimeUnit::NANOS(_) => time.timestamp_nanos_opt(),
+                };
+                Scalar::Epoch(epoch.ok_or_else(|| PersistError::Other(format!("time {time} out of range")))?)
+            }
+        };
+        Ok(Some(Bound {
+            column: self.column.clone(),
+            leaf,
+            comparison: self.comparison,
+            value,
+        }))
+    }
+}
+
+/// A predicate resolved in the schema of a file, the times converted to the unit of the column.
+#[derive(Debug, Clone)]
+struct Bound {
+    column: String,
+    /// index of the column among the leaves of the schema
+    leaf: usize,
+    comparison: Comparison,
+    value: Scalar,
+}
+
+#[derive(Debug, Clone)]
+enum Scalar {
+    Number(f64),
+    Epoch(i64),
+    Bytes(Vec<u8>),
+}
+
+impl Bound {
+    /// Whether the row group may hold matching rows, according to the min and max of its column chunk.
+    fn may_match(&self, row_group: &RowGroupMetaData) -> bool {
+        let Some(statistics) = row_group.column(self.leaf).statistics() else {
+            return true;
+        };
+        let bounds = match (&self.value, statistics) {
+            (Scalar::Number(value), Statistics::Int32(stats)) => {
+                orderings(stats.min_opt(), stats.max_opt(), |v| (v as f64).partial_cmp(value))
+            }
+            (Scalar::Number(value), Statistics::Int64(stats)) => {
+                orderings(stats.min_opt(), stats.max_opt(), |v| (v as f64).partial_cmp(value))
+            }
+            (Scalar::Number(value), Statistics::Float(stats)) => {
+                orderings(stats.min_opt(), stats.max_opt(), |v| (v as f64).partial_cmp(value))
+            }
+            (Scalar::Number(value), Statistics::Double(stats)) => {
+                orderings(stats.min_opt(), stats.max_opt(), |v| v.partial_cmp(value))
+            }
+            (Scalar::Epoch(value), Statistics::Int64(stats)) => {
+                orderings(stats.min_opt(), stats.max_opt(), |v| Some(v.cmp(value)))
+            }
+            (Scalar::Bytes(value), Statistics::ByteArray(_)) => statistics
+                .min_bytes_opt()
+                .zip(statistics.max_bytes_opt())
+                .map(|(min, max)| (min.cmp(value), max.cmp(value))),
+            _ => None,
+        };
+        match bounds {
+            Some((min, max)) => self.comparison.may_hold(min, max),
+            None => true,
+        }
+    }
+
+    fn matches(&self, row: &Row) -> bool {
+        let Some((_, field)) = row.get_column_iter().find(|(name, _)| **name == self.column) else {
+            return false;
+        };
+        let ordering = match (&self.value, field) {
+            (Scalar::Number(value), field) => number(field).and_then(|number| number.partial_cmp(value)),
+            (
+                Scalar::Epoch(value),
+                Field::Long(epoch) | Field::TimestampMillis(epoch) | Field::TimestampMicros(epoch),
+            ) => Some(epoch.cmp(value)),
+            (Scalar::Bytes(value), Field::Str(text)) => Some(text.as_bytes().cmp(value)),
+            (Scalar::Bytes(value), Field::Bytes(bytes)) => Some(bytes.data().cmp(value)),
+            _ => None,
+        };
+        ordering.is_some_and(|ordering| self.comparison.holds(ordering))
+    }
+}
+
+/// Orders the min and max of the statistics against a value, none if either is unknown or not comparable.
+fn orderings<T: Copy>(
+    min: Option<&T>,
+    max: Option<&T>,
+    compare: impl Fn(T) -> Option<Ordering>,
+) -> Option<(Ordering, Ordering)> {
+    Some((compare(*min?)?, compare(*max?)?))
+}
+
+fn number(field: &Field) -> Option<f64> {
+    match field {
+        Field::Byte(value) => Some(*value as f64),
+        Field::Short(value) => Some(*value as f64),
+        Field::Int(value) => Some(*value as f64),
+        Field::Long(value) => Some(*value as f64),
+        Field::UByte(value) => Some(*value as f64),
+        Field::UShort(value) => Some(*value as f64),
+        Field::UInt(value) => Some(*value as f64),
+        Field::ULong(value) => Some(*value as f64),
+        Field::Float(value) => Some(*value as f64),
+        Field::Double(value) => Some(*value),
+        _ => None,
+    }
+}
+
+/// Iterates over the rows of a table directory, see the module documentation.
+pub struct TableReader<T = Row> {
+    files: vec::IntoIter<PathBuf>,
+    rows: Option<RowIter<'static>>,
+    /// time index of the table, empty if not kept
+    index: TableIndex,
+    /// timestamp column of [`TableReader::between`], `time` by default
+    time_column: String,
+    range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    predicates: Vec<Predicate>,
+    /// predicates resolved in the schema of the open file
+    bounds: Vec<Bound>,
+    /// unit of the time column in the open file, if a timestamp
+    time_unit: Option<TimeUnit>,
+    _record: PhantomData<T>,
+}
+
+impl<T: Readable> TableReader<T> {
+    /// Lists the parquet files of the table directory, the ones not finalized being skipped if the table is marked
+    /// (see [`crate::finalize`]), they are opened while iterating.
+    pub fn open(directory: &Path) -> Result<Self, PersistError> {
+        let extension = format!(".{}", Format::Parquet.extension());
+        let mut files = vec![];
+        for entry in fs::read_dir(directory)? {
+            let entry = entry?;
+            let is_parquet = entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.ends_with(&extension));
+            if is_parquet && entry.file_type()?.is_file() {
+                files.push(entry.path());
+            }
+        }
+        files.sort();
+        let files = finalize::finalized(directory, files);
+        Ok(Self {
+            files: files.into_iter(),
+            rows: None,
+            index: TableIndex::load(directory)?,
+            time_column: "time".to_owned(),
+            range: None,
+            predicates: vec![],
+            bounds: vec![],
+            time_unit: None,
+            _record: PhantomData,
+        })
+    }
+
+    /// Reads the rows whose time is in `[start, end)` only, see [`TableReader::time_column`].
+    pub fn between(mut self, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        self.range = Some((start, end));
+        self
+    }
+
+    /// Sets the timestamp column of [`TableReader::between`], `time` by default.
+    pub fn time_column(mut self, column: &str) -> Self {
+        self.time_column = column.to_owned();
+        self
+    }
+
+    /// Reads the rows matching the predicate only, on top of the other predicates.
+    pub fn with_predicate(mut self, predicate: Predicate) -> Self {
+        self.predicates.push(predicate);
+        self
+    }
+
+    /// The files not opened yet.
+    pub fn files(&self) -> &[PathBuf] {
+        self.files.as_slice()
+    }
+
+    /// The predicates of the rows read, the time range included.
+    fn predicates(&self) -> Vec<Predicate> {
+        let mut predicates = self.predicates.clone();
+        if let Some((start, end)) = self.range {
+            predicates.push(Predicate::new(&self.time_column, Comparison::Ge, start));
+            predicates.push(Predicate::new(&self.time_column, Comparison::Lt, end));
+        }
+        predicates
+    }
+
+    /// Whether the time index of the table excludes the file from the time range.
+    fn is_skipped(&self, path: &Path) -> bool {
+        let Some((start, end)) = self.range else {
+            return false;
+        };
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        self.index.skips(&name, &self.time_column, Some(start), Some(end))
+    }
+
+    fn next_file(&mut self) -> Result<bool, PersistError> {
+        let predicates = self.predicates();
+        'files: loop {
+            let Some(path) = self.files.next() else {
+                return Ok(false);
+            };
+            if self.is_skipped(&path) {
+                continue;
+            }
+            let mut options = ReadOptionsBuilder::new();
+            if !predicates.is_empty() {
+                let pruned = predicates.clone();
+                options = options.with_predicate(Box::new(move |row_group, _| {
+                    pruned
+                        .iter()
+                        .all(|predicate| match predicate.resolve(row_group.schema_descr()) {
+                            Ok(Some(bound)) => bound.may_match(row_group),
+                            Ok(None) => false,
+                            // reported once the file is open
+                            Err(_) => true,
+                        })
+                }));
+            }
+            let reader = SerializedFileReader::new_with_options(File::open(&path)?, options.build())?;
+            let schema = reader.metadata().file_metadata().schema_descr();
+            let mut bounds = vec![];
+            for predicate in predicates.iter() {
+                match predicate.resolve(schema)? {
+                    Some(bound) => bounds.push(bound),
+                    // no row of the file has the column
+                    None => continue 'files,
+                }
+            }
+            self.bounds = bounds;
+            self.time_unit = (0..schema.num_columns())
+                .map(|i| schema.column(i))
+                .find(|column| column.path().parts() == [self.time_column.as_str()])
+                .and_then(|column| match column.logical_type() {
+                    Some(LogicalType::Timestamp { unit, .. }) => Some(unit),
+                    _ => None,
+                });
+            self.rows = Some(reader.into_iter());
+            return Ok(true);
+        }
+    }
+
+    /// Reads the next row matching the predicates, opening the next files as needed.
+    pub(crate) fn next_row(&mut self) -> Option<Result<Row, PersistError>> {
+        loop {
+            if let Some(row) = self.rows.as_mut().and_then(Iterator::next) {
+                let row = match row {
+                    Ok(row) => row,
+                    Err(e) => return Some(Err(e.into())),
+                };
+                if self.bounds.iter().all(|bound| bound.matches(&row)) {
+                    return Some(Ok(row));
+                }
+                continue;
+            }
+            match self.next_file() {
+                Ok(true) => continue,
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+
+    /// Time of a row of the open file, unset if null or if the time column is not a timestamp.
+    pub(crate) fn time(&self, row: &Row) -> Option<DateTime<Utc>> {
+        let (_, field) = row.get_column_iter().find(|(name, _)| **name == self.time_column)?;
+        match (field, &self.time_unit) {
+            (Field::TimestampMillis(epoch), _) => DateTime::from_timestamp_millis(*epoch),
+            (Field::TimestampMicros(epoch), _) => DateTime::from_timestamp_micros(*epoch),
+            (Field::Long(epoch), Some(TimeUnit::NANOS(_))) => Some(DateTime::from_timestamp_nanos(*epoch)),
+            (Field::Long(epoch), Some(TimeUnit::MICROS(_))) => DateTime::from_timestamp_micros(*epoch),
+            (Field::Long(epoch), Some(TimeUnit::MILLIS(_))) => DateTime::from_timestamp_millis(*epoch),
+            _ => None,
+        }
+    }
+}
+
+impl<T: Readable> Iterator for TableReader<T> {
+    type Item = Result<T, PersistError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_row().map(|row| row.and_then(T::from_row))
+    }
+}
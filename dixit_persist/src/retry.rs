@@ -0,0 +1,72 @@
+//! Retries of the flushes failing on a transient io error (e.g. an NFS blip, a disk briefly full), so the buffered
+//! rows outlive it instead of being lost with the flush.
+//!
+//! ## Features
+//! - Keeps the rows buffered when a flush fails, removing the partial file it left, and flushes them again after a
+//!   backoff doubling from `backoff` up to `max_backoff`, up to `retries` times.
+//! - Retries the io errors only (see [`PersistError::is_transient`](crate::error::PersistError::is_transient)), a
+//!   schema or parquet error failing at once.
+//! - Escalates to [`PersistError::FlushFailed`](crate::error::PersistError::FlushFailed) once the retries are
+//!   exhausted, the buffered rows being dumped first as a JSON lines file of the table in `recovery_directory` if set
+//!   (e.g. `recovery/okx/trades/{time}-{pid}.jsonl`), so they can be loaded back once the storage is fixed.
+//! - Spills the rows to the `spill_directory` of the config rather than escalating, if set, see [`crate::spill`].
+//! - Disabled by default, a failed flush failing at once with its rows kept buffered, as without a policy.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Retries of the failed flushes of a table, see the module documentation.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// flushes retried after the first failure, 0 to fail at once
+    pub retries: u32,
+    /// wait before the first retry, doubled for every next one
+    pub backoff: Duration,
+    /// longest wait between two retries
+    pub max_backoff: Duration,
+    /// if set the buffered rows are dumped there once the retries are exhausted, kept buffered otherwise
+    pub recovery_directory: Option<PathBuf>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            recovery_directory: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Retries the failed flushes `retries` times, waiting `backoff` doubled for every retry.
+    pub fn new(retries: u32, backoff: Duration) -> Self {
+        Self {
+            retries,
+            backoff,
+            ..Default::default()
+        }
+    }
+
+    /// Dumps the buffered rows to the directory once the retries are exhausted.
+    pub fn with_recovery_directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.recovery_directory = Some(directory.into());
+        self
+    }
+
+    /// Wait before the retry (from 0), capped by `max_backoff`.
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+
+    /// Whether a failed flush is retried or dumped rather than failing at once.
+    pub fn is_enabled(&self) -> bool {
+        self.retries > 0 || self.recovery_directory.is_some()
+    }
+}
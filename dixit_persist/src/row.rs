@@ -1,10 +1,19 @@
+use bytes::BytesMut;
 use itertools::Itertools;
+use parquet::basic::{Repetition, Type as PhysicalType};
 use parquet::data_type::ByteArray;
 use parquet::errors::ParquetError;
 use parquet::file::writer::SerializedFileWriter;
 use parquet::record::Field;
+use parquet::schema::types::TypePtr;
+use std::collections::HashMap;
 use std::io::Write;
 
+/// Maximum number of distinct interned values per buffer, values pushed once the limit is reached are not interned.
+const MAX_INTERNED: usize = 4096;
+/// Size of the blocks of the arena holding the values pushed with `push_str`.
+const ARENA_BLOCK: usize = 64 * 1024;
+
 #[derive(Debug, Default)]
 pub struct RowBuffer {
     rows: Vec<Vec<Field>>,
@@ -16,29 +25,219 @@ pub struct RowBuffer {
     f32s: Vec<f32>,
     f64s: Vec<f64>,
     strs: Vec<ByteArray>,
+    /// columns of the table, used to name the columns in errors
+    schema: Vec<TypePtr>,
+    /// if set every pushed value is checked against the schema, the first mismatch is kept in `error`
+    validate: bool,
+    error: Option<ParquetError>,
+    /// maximum number of rows of the row groups written by `record`, 0 for a single row group
+    rows_per_row_group: usize,
+    /// values pushed with `push_interned`, shared by every row holding them
+    interned: HashMap<String, ByteArray>,
+    /// values pushed with `push_str`, a block is freed once the rows referencing it are flushed
+    arena: BytesMut,
+    /// buffered column of each column of the schema, `None` for the pruned columns, empty if none is pruned
+    slots: Vec<Option<usize>>,
+    /// number of pruned columns
+    pruned: usize,
+    /// emptied columns of a recycled buffer, reused by the columns buffered next
+    spare: Vec<Vec<Field>>,
+    /// most rows buffered at once since the buffer was created or last reset
+    peak: usize,
 }
 
 impl RowBuffer {
     pub fn begin(&mut self) {
-        debug_assert_eq!(self.current, self.rows.len());
+        debug_assert_eq!(self.current, self.rows.len() + self.pruned);
         self.current = 0;
     }
 
     pub fn push(&mut self, field: Field) {
-        if self.rows.len() <= self.current {
-            self.rows.resize_with(self.current + 1, Vec::new);
-        }
-        self.rows[self.current].push(field);
+        let column = self.current;
         self.current += 1;
+        let Some(slot) = self.slot(column) else {
+            return;
+        };
+        if self.rows.len() <= slot {
+            self.rows.resize_with(slot + 1, || self.spare.pop().unwrap_or_default());
+        }
+        if self.validate && self.error.is_none() {
+            self.error = self.check(column, self.rows[slot].len(), &field).err();
+        }
+        self.rows[slot].push(field);
+    }
+
+    /// Buffered column of a column of the schema, if not pruned.
+    fn slot(&self, column: usize) -> Option<usize> {
+        match self.slots.get(column) {
+            Some(slot) => *slot,
+            None => Some(column - self.pruned),
+        }
+    }
+
+    /// Column of the schema of a buffered column, to name it in errors.
+    fn column(&self, slot: usize) -> usize {
+        self.slots
+            .iter()
+            .position(|column| *column == Some(slot))
+            .unwrap_or(slot + self.pruned)
+    }
+
+    /// Drops the columns of the schema not kept, their buffered values and the values pushed to them next are
+    /// discarded, `kept` flagging every column of the schema. Meant to be called once the first row is complete.
+    pub fn prune(&mut self, kept: &[bool]) {
+        let mut slot = 0;
+        self.slots = kept
+            .iter()
+            .map(|kept| {
+                kept.then(|| {
+                    slot += 1;
+                    slot - 1
+                })
+            })
+            .collect();
+        self.pruned = kept.len() - slot;
+        if self.rows.len() < kept.len() {
+            self.rows
+                .resize_with(kept.len(), || self.spare.pop().unwrap_or_default());
+        }
+        let mut column = 0;
+        self.rows.retain(|_| {
+            column += 1;
+            kept.get(column - 1).copied().unwrap_or(true)
+        });
+        self.current = self.rows.len() + self.pruned;
+    }
+
+    /// Pushes a string column value copied into an arena shared by the buffered rows, instead of allocating a
+    /// `String` per value.
+    pub fn push_str(&mut self, value: &str) {
+        if self.arena.capacity() < value.len() {
+            self.arena.reserve(value.len().max(ARENA_BLOCK));
+        }
+        self.arena.extend_from_slice(value.as_bytes());
+        let bytes = self.arena.split().freeze();
+        self.push(Field::Bytes(ByteArray::from(bytes)));
+    }
+
+    /// Pushes a string column value stored once for all the rows holding it, meant for low cardinality columns
+    /// (e.g. sides, symbols or channels) repeated in many rows.
+    pub fn push_interned(&mut self, value: &str) {
+        let field = match self.interned.get(value) {
+            Some(bytes) => Field::Bytes(bytes.clone()),
+            None if self.interned.len() < MAX_INTERNED => {
+                let bytes = ByteArray::from(value);
+                self.interned.insert(value.to_owned(), bytes.clone());
+                Field::Bytes(bytes)
+            }
+            None => Field::Str(value.to_owned()),
+        };
+        self.push(field);
+    }
+
+    /// Divides the values of the last row in the buffered columns `slots` by `divisor`, rounding down, e.g. to write
+    /// nanosecond timestamps in microseconds.
+    pub fn scale_last(&mut self, slots: &[usize], divisor: i64) {
+        for slot in slots {
+            match self.rows.get_mut(*slot).and_then(|column| column.last_mut()) {
+                Some(Field::Long(value)) => *value = value.div_euclid(divisor),
+                Some(Field::ULong(value)) => *value /= divisor as u64,
+                _ => (),
+            }
+        }
+    }
+
+    /// Number of values pushed to the row begun last.
+    pub fn pushed(&self) -> usize {
+        self.current
+    }
+
+    pub fn set_schema(&mut self, schema: &[TypePtr]) {
+        self.schema = schema.to_vec();
+    }
+
+    /// Enables checking every pushed value against the schema, meant for debugging new `Persistable` types.
+    pub fn set_validate(&mut self, validate: bool) {
+        self.validate = validate;
+    }
+
+    /// Splits the rows written by `record` into row groups of at most `rows` rows, 0 writes a single row group.
+    pub fn set_rows_per_row_group(&mut self, rows: usize) {
+        self.rows_per_row_group = rows;
+    }
+
+    pub fn validate(&self) -> bool {
+        self.validate
+    }
+
+    pub fn rows_per_row_group(&self) -> usize {
+        self.rows_per_row_group
+    }
+
+    /// Returns the first value pushed since the last call that did not match its column, if validating.
+    pub fn take_error(&mut self) -> Option<ParquetError> {
+        self.error.take()
+    }
+
+    /// Checks the values pushed to the current row from the column `start` against the schema, whether validating or
+    /// not, e.g. the values of records without a compile time schema.
+    pub fn check_pushed(&self, start: usize) -> Result<(), ParquetError> {
+        for column in start..self.current {
+            let Some(slot) = self.slot(column) else {
+                continue;
+            };
+            let values = &self.rows[slot];
+            let row = values.len() - 1;
+            self.check(column, row, &values[row])?;
+        }
+        Ok(())
+    }
+
+    fn check(&self, column: usize, row: usize, field: &Field) -> Result<(), ParquetError> {
+        let Some(schema) = self.schema.get(column) else {
+            return if self.schema.is_empty() {
+                Ok(())
+            } else {
+                Err(ParquetError::General(format!(
+                    "row {row} has more values than the {} columns of the schema - {field:?}",
+                    self.schema.len()
+                )))
+            };
+        };
+        let valid = match (schema.get_physical_type(), field) {
+            (_, Field::Null) => schema.get_basic_info().repetition() != Repetition::REQUIRED,
+            (PhysicalType::BOOLEAN, Field::Bool(_)) => true,
+            (PhysicalType::INT32, Field::Int(_) | Field::UInt(_)) => true,
+            (PhysicalType::INT64, Field::Long(_) | Field::ULong(_)) => true,
+            (PhysicalType::FLOAT, Field::Float(_)) => true,
+            (PhysicalType::DOUBLE, Field::Double(_)) => true,
+            (PhysicalType::BYTE_ARRAY, Field::Str(_) | Field::Bytes(_)) => true,
+            _ => false,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(invalid_value(
+                &self.schema,
+                column,
+                row,
+                &format!(
+                    "{} {}",
+                    schema.get_basic_info().repetition(),
+                    schema.get_physical_type()
+                ),
+                field,
+            ))
+        }
     }
 
     pub fn record<W: Write + Send>(&mut self, writer: &mut SerializedFileWriter<W>) -> Result<usize, ParquetError> {
         debug_assert_eq!(
             self.current,
-            self.rows.len(),
+            self.rows.len() + self.pruned,
             "current {} actual {}",
             self.current,
-            self.rows.len()
+            self.rows.len() + self.pruned
         );
         debug_assert_eq!(1, self.rows.iter().map(|c| c.len()).sorted().dedup().count());
 
@@ -47,103 +246,194 @@ impl RowBuffer {
             return Ok(0);
         }
 
-        let mut row_group_writer = writer.next_row_group()?;
+        let rows_per_row_group = match self.rows_per_row_group {
+            0 => size,
+            rows => rows,
+        };
         let not_null = &mut self.not_null;
 
-        for column in self.rows.iter_mut() {
-            let mut column_writer = row_group_writer.next_column()?.unwrap();
-
-            not_null.clear();
-            not_null.extend(column.iter().map(|f| if matches!(f, Field::Null) { 0 } else { 1 }));
-
-            match column_writer.untyped() {
-                parquet::column::writer::ColumnWriter::BoolColumnWriter(ref mut typed_writer) => {
-                    self.bools.clear();
-                    for f in column.iter() {
-                        match f {
-                            Field::Bool(val) => self.bools.push(*val),
-                            Field::Null => (),
-                            _ => return Err(ParquetError::General(format!("invalid type, expected bool - {:?}", f))),
+        for start in (0..size).step_by(rows_per_row_group) {
+            let end = (start + rows_per_row_group).min(size);
+            let mut row_group_writer = writer.next_row_group()?;
+
+            for (index, column) in self.rows.iter().enumerate() {
+                let mut column_writer = row_group_writer.next_column()?.unwrap();
+
+                not_null.clear();
+                not_null.extend(
+                    column[start..end]
+                        .iter()
+                        .map(|f| if matches!(f, Field::Null) { 0 } else { 1 }),
+                );
+
+                match column_writer.untyped() {
+                    parquet::column::writer::ColumnWriter::BoolColumnWriter(ref mut typed_writer) => {
+                        self.bools.clear();
+                        for (row, f) in column[start..end].iter().enumerate() {
+                            match f {
+                                Field::Bool(val) => self.bools.push(*val),
+                                Field::Null => (),
+                                _ => {
+                                    return Err(invalid_value(&self.schema, self.column(index), start + row, "bool", f))
+                                }
+                            }
                         }
+                        typed_writer.write_batch(&self.bools, Some(&not_null[..]), None)?;
                     }
-                    typed_writer.write_batch(&self.bools, Some(&not_null[..]), None)?;
-                }
-                parquet::column::writer::ColumnWriter::Int32ColumnWriter(ref mut typed_writer) => {
-                    self.i32s.clear();
-                    for f in column.iter() {
-                        match f {
-                            Field::Int(val) => self.i32s.push(*val),
-                            Field::UInt(val) => self.i32s.push(*val as i32),
-                            Field::Null => (),
-                            _ => return Err(ParquetError::General(format!("invalid type, expected int32 - {:?}", f))),
+                    parquet::column::writer::ColumnWriter::Int32ColumnWriter(ref mut typed_writer) => {
+                        self.i32s.clear();
+                        for (row, f) in column[start..end].iter().enumerate() {
+                            match f {
+                                Field::Int(val) => self.i32s.push(*val),
+                                Field::UInt(val) => self.i32s.push(*val as i32),
+                                Field::Null => (),
+                                _ => {
+                                    return Err(invalid_value(
+                                        &self.schema,
+                                        self.column(index),
+                                        start + row,
+                                        "int32",
+                                        f,
+                                    ))
+                                }
+                            }
                         }
+                        typed_writer.write_batch(&self.i32s, Some(&not_null[..]), None)?;
                     }
-                    typed_writer.write_batch(&self.i32s, Some(&not_null[..]), None)?;
-                }
-                parquet::column::writer::ColumnWriter::Int64ColumnWriter(ref mut typed_writer) => {
-                    self.i64s.clear();
-                    for f in column.iter() {
-                        match f {
-                            Field::Long(val) => self.i64s.push(*val),
-                            Field::ULong(val) => self.i64s.push(*val as i64),
-                            Field::Null => (),
-                            _ => return Err(ParquetError::General(format!("invalid type, expected int64 - {:?}", f))),
+                    parquet::column::writer::ColumnWriter::Int64ColumnWriter(ref mut typed_writer) => {
+                        self.i64s.clear();
+                        for (row, f) in column[start..end].iter().enumerate() {
+                            match f {
+                                Field::Long(val) => self.i64s.push(*val),
+                                Field::ULong(val) => self.i64s.push(*val as i64),
+                                Field::Null => (),
+                                _ => {
+                                    return Err(invalid_value(
+                                        &self.schema,
+                                        self.column(index),
+                                        start + row,
+                                        "int64",
+                                        f,
+                                    ))
+                                }
+                            }
                         }
+                        typed_writer.write_batch(&self.i64s, Some(&not_null[..]), None)?;
                     }
-                    typed_writer.write_batch(&self.i64s, Some(&not_null[..]), None)?;
-                }
-                parquet::column::writer::ColumnWriter::FloatColumnWriter(ref mut typed_writer) => {
-                    self.f32s.clear();
-                    for f in column.iter() {
-                        match f {
-                            Field::Float(val) => self.f32s.push(*val),
-                            Field::Null => (),
-                            _ => return Err(ParquetError::General(format!("invalid type, expected float - {:?}", f))),
+                    parquet::column::writer::ColumnWriter::FloatColumnWriter(ref mut typed_writer) => {
+                        self.f32s.clear();
+                        for (row, f) in column[start..end].iter().enumerate() {
+                            match f {
+                                Field::Float(val) => self.f32s.push(*val),
+                                Field::Null => (),
+                                _ => {
+                                    return Err(invalid_value(
+                                        &self.schema,
+                                        self.column(index),
+                                        start + row,
+                                        "float",
+                                        f,
+                                    ))
+                                }
+                            }
                         }
+                        typed_writer.write_batch(&self.f32s, Some(&not_null[..]), None)?;
                     }
-                    typed_writer.write_batch(&self.f32s, Some(&not_null[..]), None)?;
-                }
-                parquet::column::writer::ColumnWriter::DoubleColumnWriter(ref mut typed_writer) => {
-                    self.f64s.clear();
-                    for f in column.iter() {
-                        match f {
-                            Field::Double(val) => self.f64s.push(*val),
-                            Field::Null => (),
-                            _ => {
-                                return Err(ParquetError::General(format!(
-                                    "invalid type, expected double - {:?}",
-                                    f
-                                )))
+                    parquet::column::writer::ColumnWriter::DoubleColumnWriter(ref mut typed_writer) => {
+                        self.f64s.clear();
+                        for (row, f) in column[start..end].iter().enumerate() {
+                            match f {
+                                Field::Double(val) => self.f64s.push(*val),
+                                Field::Null => (),
+                                _ => {
+                                    return Err(invalid_value(
+                                        &self.schema,
+                                        self.column(index),
+                                        start + row,
+                                        "double",
+                                        f,
+                                    ))
+                                }
                             }
                         }
+                        typed_writer.write_batch(&self.f64s, Some(&not_null[..]), None)?;
                     }
-                    typed_writer.write_batch(&self.f64s, Some(&not_null[..]), None)?;
-                }
-                parquet::column::writer::ColumnWriter::ByteArrayColumnWriter(ref mut typed_writer) => {
-                    self.strs.clear();
-                    for f in column.iter() {
-                        match f {
-                            Field::Str(ref val) => self.strs.push(ByteArray::from(val.as_str())),
-                            Field::Null => (),
-                            _ => {
-                                return Err(ParquetError::General(format!(
-                                    "invalid type, expected byte array - {:?}",
-                                    f
-                                )))
+                    parquet::column::writer::ColumnWriter::ByteArrayColumnWriter(ref mut typed_writer) => {
+                        self.strs.clear();
+                        for (row, f) in column[start..end].iter().enumerate() {
+                            match f {
+                                Field::Str(ref val) => self.strs.push(ByteArray::from(val.as_str())),
+                                Field::Bytes(ref val) => self.strs.push(val.clone()),
+                                Field::Null => (),
+                                _ => {
+                                    return Err(invalid_value(
+                                        &self.schema,
+                                        self.column(index),
+                                        start + row,
+                                        "byte array",
+                                        f,
+                                    ))
+                                }
                             }
                         }
+                        typed_writer.write_batch(&self.strs, Some(&not_null[..]), None)?;
                     }
-                    typed_writer.write_batch(&self.strs, Some(&not_null[..]), None)?;
+                    _ => return Err(ParquetError::General("unsupported column writer type".to_string())),
                 }
-                _ => return Err(ParquetError::General("unsupported column writer type".to_string())),
+                column_writer.close()?;
             }
-            column_writer.close()?;
-            column.clear();
+            row_group_writer.close()?;
         }
-        row_group_writer.close()?;
+        self.clear();
         Ok(size)
     }
 
+    /// Buffered values, one vector per column not pruned.
+    pub fn columns(&self) -> &[Vec<Field>] {
+        &self.rows
+    }
+
+    /// Drops the values pushed after the first `len` rows, e.g. the partial row of an ignored record.
+    pub fn truncate(&mut self, len: usize) {
+        for column in self.rows.iter_mut() {
+            column.truncate(len);
+        }
+        self.current = self.rows.len() + self.pruned;
+    }
+
+    pub fn clear(&mut self) {
+        self.peak = self.peak.max(self.len());
+        for column in self.rows.iter_mut() {
+            column.clear();
+        }
+    }
+
+    /// Drops the buffered rows and the settings of the buffer, keeping its allocations for the next table it buffers,
+    /// see [`BufferPool`](crate::pool::BufferPool).
+    pub fn reset(&mut self) {
+        self.clear();
+        self.spare.append(&mut self.rows);
+        self.current = 0;
+        self.schema.clear();
+        self.validate = false;
+        self.error = None;
+        self.rows_per_row_group = 0;
+        self.interned.clear();
+        self.slots.clear();
+        self.pruned = 0;
+        self.peak = 0;
+    }
+
+    /// Number of values the columns of the buffer hold without reallocating, its spare columns included.
+    pub fn capacity(&self) -> usize {
+        self.rows.iter().chain(self.spare.iter()).map(Vec::capacity).sum()
+    }
+
+    /// Most rows buffered at once since the buffer was created or last reset, the rows buffered now included.
+    pub fn peak(&self) -> usize {
+        self.peak.max(self.len())
+    }
+
     pub fn len(&self) -> usize {
         self.rows.first().map(|c| c.len()).unwrap_or_default()
     }
@@ -152,3 +442,10 @@ impl RowBuffer {
         self.len() == 0
     }
 }
+
+fn invalid_value(schema: &[TypePtr], column: usize, row: usize, expected: &str, field: &Field) -> ParquetError {
+    let name = schema.get(column).map(|field| field.name()).unwrap_or("?");
+    ParquetError::General(format!(
+        "invalid value in column {name} (index {column}) at row {row}, expected {expected} - {field:?}"
+    ))
+}
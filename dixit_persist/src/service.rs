@@ -0,0 +1,477 @@
+//! Owns the writers of every table behind a single command channel, so applications only route records to it
+//! (`tokio` feature).
+//!
+//! ## Features
+//! - Creates the writer of a table on its first record, named as with [`TableWriter::new`] (e.g. `coinbase/trades`),
+//!   waiting for a table locked by another writer up to the `lock_timeout` of the config without blocking its
+//!   runtime.
+//! - Closes every table at the boundaries of the `rotation_interval` (e.g. on the hour for `1h`), so the files of the
+//!   quiet tables are completed too, the boundaries being read from its [`Clock`] (see [`PersistService::with_clock`]),
+//!   as are the timestamped file names and the commit times of its writers.
+//! - Replaces its options at runtime through [`PersistCmd::SetOptions`], rotating at the boundaries of the new interval.
+//! - Flushes the table holding the most rows whenever the rows buffered by all the tables exceed `max_buffered_rows`,
+//!   the rows of a table whose output is unavailable being spilled to the `spill_directory` of the config if set.
+//! - Retries the failed flushes of a table from its own loop once their backoff is over (see
+//!   [`TableWriter::with_deferred_retries`]), so a table whose output is unavailable never blocks the other tables,
+//!   and waits for the pending retries before returning.
+//! - Flushes or rotates a single table on demand (e.g. before a maintenance), through [`PersistCmd::FlushTable`]
+//!   and [`PersistCmd::Rotate`].
+//! - Logs the tables it writes on start, and enables or disables a table at runtime through
+//!   [`PersistCmd::SetEnabled`], whatever the `tables` of the config, including the tables not written yet.
+//! - Reports the stats of every table and its own counters through [`PersistCmd::Stats`].
+//! - Takes the buffers of its writers from a [`BufferPool`] shared with other services or writers, if set with
+//!   [`PersistService::with_buffer_pool`], reporting the stats of the pool with its own.
+//! - Reports its flushes and rotations as [`ServiceEvent`]s, if a sender is set with [`PersistService::with_events`],
+//!   with the column statistics of every flush of the tables if `column_stats` is set in the config, and the schema
+//!   of every table once its first file is written if `report_tables` is set in the options.
+//! - Closes every table and returns the final stats once all the senders are dropped, or on the first write error.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
+use parquet::schema::types::Type;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time;
+use tracing::{info, warn};
+
+use crate::clock::{self, SharedClock};
+use crate::config::{Format, PersistConfig};
+use crate::error::PersistError;
+use crate::lock::RETRY_INTERVAL;
+use crate::pool::{BufferPool, PoolStats};
+use crate::stats::FlushStats;
+use crate::writer::{TableStats, TableWriter};
+use crate::Persistable;
+
+const COMMAND_CAPACITY: usize = 1024;
+/// Longest interval between two readings of the clock checking whether a rotation is due.
+const ROTATION_POLL: Duration = Duration::from_millis(100);
+
+/// A record sent to the service, written as a row of its table.
+pub trait Writable: Send + Sync {
+    fn write(&self, writer: &mut TableWriter) -> Result<()>;
+}
+
+impl<T: Persistable + Send + Sync> Writable for T {
+    fn write(&self, writer: &mut TableWriter) -> Result<()> {
+        writer.begin()?.record(self)?.end()?;
+        Ok(())
+    }
+}
+
+pub enum PersistCmd {
+    /// Records a row in `table`, creating the table writer first if needed.
+    Write {
+        table: String,
+        record: Box<dyn Writable>,
+    },
+    /// Flushes the buffered rows of every table.
+    Flush(oneshot::Sender<Result<(), PersistError>>),
+    /// Flushes the buffered rows of a table, fails if the table has no writer yet.
+    FlushTable(String, oneshot::Sender<Result<(), PersistError>>),
+    /// Closes a table, completing its open file, the next row of the table opens a new file.
+    Rotate(String, oneshot::Sender<Result<(), PersistError>>),
+    /// Enables or disables a table, applied to its writer once created if it has none yet. A disabled table is
+    /// flushed and its open file completed, fails to enable a table if the config has no directory.
+    SetEnabled(String, bool, oneshot::Sender<Result<(), PersistError>>),
+    Stats(oneshot::Sender<ServiceStats>),
+    /// Replaces the options, the next rotation is a full `rotation_interval` away.
+    SetOptions(ServiceOptions),
+}
+
+/// Operational events of the service, e.g. to align data quality issues with them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServiceEvent {
+    /// rows of a table flushed on demand
+    Flush { table: String, rows: usize },
+    /// rows of a table flushed because the rows buffered by all the tables exceeded `max_buffered_rows`
+    BudgetFlush { table: String, rows: usize },
+    /// tables closed on demand or by the rotation timer, completing their open file
+    Rotation { tables: Vec<String> },
+    /// statistics of the columns of a flush of a table, whatever triggered it
+    ColumnStats { table: String, stats: FlushStats },
+    /// first file written by the writer of a table, with the directory, format and schema of the table
+    TableCreated {
+        table: String,
+        directory: PathBuf,
+        format: Format,
+        schema: Arc<Type>,
+    },
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ServiceOptions {
+    /// if set every table is closed at this interval, completing its open file
+    pub rotation_interval: Option<Duration>,
+    /// if set the rows buffered by all the tables are kept under this limit, flushing the largest table first
+    pub max_buffered_rows: usize,
+    /// if set the schema of every table is reported once its first file is written, as a
+    /// [`ServiceEvent::TableCreated`]
+    pub report_tables: bool,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ServiceStats {
+    pub tables: HashMap<String, TableStats>,
+    /// records written by the service
+    pub records: u64,
+    /// flushes triggered by `max_buffered_rows`
+    pub budget_flushes: u64,
+    /// rotations triggered by `rotation_interval`
+    pub rotations: u64,
+    /// stats of the buffer pool of the writers, if set
+    pub buffer_pool: Option<PoolStats>,
+}
+
+pub struct PersistService {
+    config: PersistConfig,
+    options: ServiceOptions,
+    writers: HashMap<String, TableWriter>,
+    stats: ServiceStats,
+    rx: mpsc::Receiver<PersistCmd>,
+    events: Option<mpsc::UnboundedSender<ServiceEvent>>,
+    clock: SharedClock,
+    pool: Option<BufferPool>,
+    /// tables enabled or disabled at runtime, applied to the writers created next
+    enabled: HashMap<String, bool>,
+}
+
+impl PersistService {
+    /// Creates the service and the sender of its commands, the service does nothing until [`PersistService::run`].
+    pub fn new(config: PersistConfig, options: ServiceOptions) -> (Self, mpsc::Sender<PersistCmd>) {
+        let (tx, rx) = mpsc::channel(COMMAND_CAPACITY);
+        let service = Self {
+            config,
+            options,
+            writers: HashMap::new(),
+            stats: ServiceStats::default(),
+            rx,
+            events: None,
+            clock: clock::system(),
+            pool: None,
+            enabled: HashMap::new(),
+        };
+        (service, tx)
+    }
+
+    /// Reads the rotation boundaries and the time of the writers from `clock` instead of the system clock, e.g. a
+    /// [`clock::ManualClock`] in tests.
+    pub fn with_clock(self, clock: SharedClock) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Reports the events of the service to `events`, unbounded so the service never waits on its listener.
+    pub fn with_events(self, events: mpsc::UnboundedSender<ServiceEvent>) -> Self {
+        Self {
+            events: Some(events),
+            ..self
+        }
+    }
+
+    /// Takes the buffers of the writers from `pool`, handing them back once the service returns.
+    pub fn with_buffer_pool(self, pool: BufferPool) -> Self {
+        Self {
+            pool: Some(pool),
+            ..self
+        }
+    }
+
+    pub fn config(&self) -> &PersistConfig {
+        &self.config
+    }
+
+    pub async fn run(mut self) -> Result<ServiceStats> {
+        self.log_tables();
+        let mut rotation = self.rotation();
+        let mut due = self.next_rotation();
+
+        loop {
+            let retry = self.next_retry();
+            tokio::select! {
+                command = self.rx.recv() => match command {
+                    Some(PersistCmd::Write { table, record }) => self.write(table, record.as_ref()).await?,
+                    Some(PersistCmd::Flush(reply)) => {
+                        let tables = self.writers.keys().cloned().collect::<Vec<_>>();
+                        let _ = reply.send(tables.iter().try_for_each(|table| self.flush_table(table)));
+                    }
+                    Some(PersistCmd::FlushTable(table, reply)) => {
+                        let _ = reply.send(self.flush_table(&table));
+                    }
+                    Some(PersistCmd::Rotate(table, reply)) => {
+                        info!("rotating {table}");
+                        let result = self.writer(&table).and_then(TableWriter::close);
+                        self.report(&table);
+                        if result.is_ok() {
+                            self.event(ServiceEvent::Rotation { tables: vec![table] });
+                        }
+                        let _ = reply.send(result);
+                    }
+                    Some(PersistCmd::SetEnabled(table, enabled, reply)) => {
+                        let _ = reply.send(self.set_enabled(table, enabled));
+                    }
+                    Some(PersistCmd::Stats(reply)) => {
+                        let _ = reply.send(self.stats());
+                    }
+                    Some(PersistCmd::SetOptions(options)) => {
+                        self.options = options;
+                        rotation = self.rotation();
+                        due = self.next_rotation();
+                    }
+                    None => break,
+                },
+                _ = time::sleep_until(retry.unwrap_or_else(Instant::now).into()), if retry.is_some() => {
+                    self.retry_flushes()?;
+                }
+                _ = rotation.tick(), if due.is_some() => {
+                    if due.is_some_and(|due| self.clock.now() < due) {
+                        continue;
+                    }
+                    due = self.next_rotation();
+                    info!("rotating {} tables", self.writers.len());
+                    self.stats.rotations += 1;
+                    for writer in self.writers.values_mut() {
+                        writer.close()?;
+                    }
+                    let tables = self.writers.keys().cloned().collect::<Vec<_>>();
+                    tables.iter().for_each(|table| self.report(table));
+                    if !tables.is_empty() {
+                        self.event(ServiceEvent::Rotation { tables });
+                    }
+                }
+            }
+        }
+
+        for writer in self.writers.values_mut() {
+            writer.close()?;
+        }
+        // the rows of the failed flushes are written once retried, or the service fails with the last retry
+        while let Some(retry) = self.next_retry() {
+            time::sleep_until(retry.into()).await;
+            for writer in self.writers.values_mut() {
+                writer.close()?;
+            }
+        }
+        let tables = self.writers.keys().cloned().collect::<Vec<_>>();
+        tables.iter().for_each(|table| self.report(table));
+        let mut stats = self.stats();
+        // the buffers are handed back to the pool before reporting it
+        self.writers.clear();
+        stats.buffer_pool = self.pool.as_ref().map(BufferPool::stats);
+        Ok(stats)
+    }
+
+    /// Logs which tables are written, the others being disabled until enabled at runtime.
+    fn log_tables(&self) {
+        let directory = &self.config.directory;
+        if directory.as_os_str().is_empty() {
+            warn!("no directory set, no table is persisted");
+        } else if self.config.tables.is_empty() {
+            info!("persisting every table to {directory:?}");
+        } else {
+            let tables = self.config.tables.iter().sorted().join(", ");
+            info!("persisting the tables {tables} to {directory:?}, the other tables are disabled");
+        }
+    }
+
+    /// Timer checking whether the next rotation is due, only polled if a rotation interval is set.
+    fn rotation(&self) -> time::Interval {
+        let period = self
+            .options
+            .rotation_interval
+            .unwrap_or(ROTATION_POLL)
+            .min(ROTATION_POLL);
+        let mut timer = time::interval(period);
+        timer.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        timer
+    }
+
+    /// Next boundary of the rotation interval after the current time, if set.
+    fn next_rotation(&self) -> Option<DateTime<Utc>> {
+        let period = i64::try_from(self.options.rotation_interval?.as_nanos()).ok()?.max(1);
+        let now = self.clock.now().timestamp_nanos_opt()?;
+        Some(DateTime::from_timestamp_nanos((now.div_euclid(period) + 1) * period))
+    }
+
+    async fn write(&mut self, table: String, record: &dyn Writable) -> Result<()> {
+        let writer = match self.writers.get_mut(&table) {
+            Some(writer) => writer,
+            None => {
+                let mut writer = Self::create_writer(&table, &self.config)
+                    .await?
+                    .with_clock(self.clock.clone())
+                    .with_deferred_retries();
+                if let Some(pool) = &self.pool {
+                    writer = writer.with_buffer_pool(pool.clone());
+                }
+                if let Some(enabled) = self.enabled.get(&table) {
+                    writer.set_enabled(*enabled)?;
+                }
+                self.writers.entry(table.clone()).or_insert(writer)
+            }
+        };
+        let written = record.write(writer);
+        self.report(&table);
+        written?;
+        self.stats.records += 1;
+
+        if self.options.max_buffered_rows > 0 {
+            let buffered: usize = self.writers.values().map(|w| w.stats().rows_buffered).sum();
+            if buffered > self.options.max_buffered_rows {
+                // the tables whose flush failed are flushed again once their retry is due
+                if let Some((table, writer)) = self
+                    .writers
+                    .iter_mut()
+                    .filter(|(_, writer)| writer.retry_due().is_none())
+                    .max_by_key(|(_, writer)| writer.stats().rows_buffered)
+                {
+                    warn!(
+                        "{buffered} rows buffered, over the budget of {}, flushing {table}",
+                        self.options.max_buffered_rows
+                    );
+                    self.stats.budget_flushes += 1;
+                    let rows = writer.stats().rows_buffered;
+                    writer.flush()?;
+                    let table = table.clone();
+                    self.report(&table);
+                    self.event(ServiceEvent::BudgetFlush { table, rows });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Enables or disables a table, its writer now or the one created for its first record.
+    fn set_enabled(&mut self, table: String, enabled: bool) -> Result<(), PersistError> {
+        if enabled && self.config.directory.as_os_str().is_empty() {
+            return Err(PersistError::Other(format!(
+                "table {table} cannot be enabled as the persist config has no directory"
+            )));
+        }
+        if let Some(writer) = self.writers.get_mut(&table) {
+            writer.set_enabled(enabled)?;
+            self.report(&table);
+        }
+        info!("{} {table}", if enabled { "enabled" } else { "disabled" });
+        self.enabled.insert(table, enabled);
+        Ok(())
+    }
+
+    /// Flushes a table on demand, reported only if rows were written, the rows of a failed flush being written once
+    /// retried.
+    fn flush_table(&mut self, table: &str) -> Result<(), PersistError> {
+        let writer = self.writer(table)?;
+        let buffered = writer.stats().rows_buffered;
+        let flushed = writer.flush();
+        let rows = buffered - writer.stats().rows_buffered;
+        self.report(table);
+        flushed?;
+        if rows > 0 {
+            let table = table.to_owned();
+            self.event(ServiceEvent::Flush { table, rows });
+        }
+        Ok(())
+    }
+
+    /// When the first of the pending retries of the failed flushes is due, if any.
+    fn next_retry(&self) -> Option<Instant> {
+        self.writers.values().filter_map(TableWriter::retry_due).min()
+    }
+
+    /// Flushes again the tables whose retry is due.
+    fn retry_flushes(&mut self) -> Result<(), PersistError> {
+        let now = Instant::now();
+        let due = self
+            .writers
+            .iter()
+            .filter(|(_, writer)| writer.retry_due().is_some_and(|due| due <= now))
+            .map(|(table, _)| table.clone())
+            .collect::<Vec<_>>();
+        for table in due {
+            let flushed = self.writer(&table)?.flush();
+            self.report(&table);
+            flushed?;
+        }
+        Ok(())
+    }
+
+    /// Reports the column statistics of the last flush of a table, if computed, and its schema once its first file
+    /// is written.
+    fn report(&mut self, table: &str) {
+        let Some(writer) = self.writers.get_mut(table) else {
+            return;
+        };
+        let stats = writer.take_column_stats();
+        let schema = self.options.report_tables.then(|| writer.take_created()).flatten();
+        let created = schema.map(|schema| ServiceEvent::TableCreated {
+            table: table.to_owned(),
+            directory: writer.current_file_path.clone(),
+            format: writer.format(),
+            schema,
+        });
+        if let Some(stats) = stats {
+            let table = table.to_owned();
+            self.event(ServiceEvent::ColumnStats { table, stats });
+        }
+        if let Some(created) = created {
+            self.event(created);
+        }
+    }
+
+    fn event(&self, event: ServiceEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+
+    /// Creates the writer of a table, trying to lock its directory once per attempt and sleeping between the attempts
+    /// up to the `lock_timeout` of the config, rather than in the writer.
+    async fn create_writer(table: &str, config: &PersistConfig) -> Result<TableWriter> {
+        let deadline = config.lock_timeout.map(|timeout| Instant::now() + timeout);
+        let once = PersistConfig {
+            lock_timeout: None,
+            ..config.clone()
+        };
+        loop {
+            let e = match TableWriter::new(table, &once) {
+                Err(e) => e,
+                created => return created,
+            };
+            match (e.downcast_ref(), deadline) {
+                (Some(PersistError::DirectoryLocked { .. }), Some(deadline)) if Instant::now() < deadline => {
+                    time::sleep(RETRY_INTERVAL).await
+                }
+                (Some(PersistError::DirectoryLocked { directory, .. }), _) => {
+                    return Err(PersistError::DirectoryLocked {
+                        directory: directory.clone(),
+                        waited: config.lock_timeout,
+                    }
+                    .into())
+                }
+                _ => return Err(e),
+            }
+        }
+    }
+
+    fn writer(&mut self, table: &str) -> Result<&mut TableWriter, PersistError> {
+        self.writers
+            .get_mut(table)
+            .ok_or_else(|| PersistError::Other(format!("unknown table {table}")))
+    }
+
+    fn stats(&self) -> ServiceStats {
+        ServiceStats {
+            tables: self
+                .writers
+                .iter()
+                .map(|(table, writer)| (table.clone(), writer.stats()))
+                .collect(),
+            buffer_pool: self.pool.as_ref().map(BufferPool::stats),
+            ..self.stats.clone()
+        }
+    }
+}
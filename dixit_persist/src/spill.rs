@@ -0,0 +1,124 @@
+//! Local scratch files of the rows a table cannot write while its output is unavailable (e.g. an object store
+//! outage), so they are neither dropped nor held in memory until it recovers, see `PersistConfig::spill_directory`.
+//!
+//! ## Features
+//! - Spills the buffered rows of a flush still failing on an io error once its retries are exhausted, as a file of
+//!   the table format in the directory of the table in the spill directory (e.g.
+//!   `spill/okx/trades/000000003-500.parquet` for 500 rows).
+//! - Drains the spilled files to the output in the order they were spilled, before the rows of the next flush, each
+//!   becoming a file of the table (committed to its log, indexed and checksummed as any other file), then deleted.
+//! - Keeps the spilled files not drained when the writer is dropped, a writer of the table draining them once it has
+//!   written its first row (e.g. after a restart), the files left incomplete by a crash being ignored.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+use crate::error::PersistError;
+use crate::index::FileRange;
+
+/// A batch of rows spilled to the scratch directory.
+#[derive(Debug, Clone)]
+pub struct Spilled {
+    pub path: PathBuf,
+    pub rows: i64,
+    /// time range of the rows, unbounded for the files spilled by a previous writer
+    pub range: FileRange,
+}
+
+/// Spilled files of a table, oldest first.
+#[derive(Debug)]
+pub struct SpillQueue {
+    directory: PathBuf,
+    files: VecDeque<Spilled>,
+    next: usize,
+}
+
+impl SpillQueue {
+    /// Opens the spill directory of a table, queuing the files spilled and not drained by a previous writer.
+    pub fn open(directory: PathBuf) -> Result<Self, PersistError> {
+        let (mut files, mut next) = (vec![], 0);
+        if directory.exists() {
+            for entry in fs::read_dir(&directory)? {
+                let path = entry?.path();
+                let (index, rows) = spilled_name(&path);
+                // numbered after every file, incomplete ones included
+                if let Some(index) = index {
+                    next = next.max(index + 1);
+                }
+                match (index, rows) {
+                    (Some(index), Some(rows)) => files.push((index, rows, path)),
+                    _ => warn!("ignoring {path:?}, not a complete spilled file"),
+                }
+            }
+        }
+        files.sort();
+        if !files.is_empty() {
+            warn!("{} spilled files to drain in {directory:?}", files.len());
+        }
+        let files = files
+            .into_iter()
+            .map(|(_, rows, path)| Spilled {
+                path,
+                rows,
+                range: FileRange::default(),
+            })
+            .collect();
+        Ok(Self { directory, files, next })
+    }
+
+    /// Path of the next spilled file while it is written, see [`SpillQueue::push`].
+    pub fn next_path(&mut self, extension: &str) -> Result<PathBuf, PersistError> {
+        fs::create_dir_all(&self.directory)?;
+        let path = self.directory.join(format!("{:0>9}.{extension}", self.next));
+        self.next += 1;
+        Ok(path)
+    }
+
+    /// Queues a file written to a path of [`SpillQueue::next_path`], named after its rows so it is complete.
+    pub fn push(&mut self, path: &Path, rows: i64, range: FileRange) -> Result<PathBuf, PersistError> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let (index, extension) = name.split_once('.').unwrap_or((&name, ""));
+        let spilled = path.with_file_name(format!("{index}-{rows}.{extension}"));
+        fs::rename(path, &spilled)?;
+        self.files.push_back(Spilled {
+            path: spilled.clone(),
+            rows,
+            range,
+        });
+        Ok(spilled)
+    }
+
+    /// The oldest file not drained yet.
+    pub fn front(&self) -> Option<&Spilled> {
+        self.files.front()
+    }
+
+    /// Removes the oldest file once drained.
+    pub fn pop(&mut self) -> Option<Spilled> {
+        let spilled = self.files.pop_front()?;
+        if let Err(e) = fs::remove_file(&spilled.path) {
+            warn!("cannot remove drained {:?} - {e}", spilled.path);
+        }
+        Some(spilled)
+    }
+
+    /// Rows spilled and not drained yet.
+    pub fn rows(&self) -> u64 {
+        self.files.iter().map(|spilled| spilled.rows as u64).sum()
+    }
+}
+
+/// Index and rows of a spilled file, named `{index}-{rows}.{extension}` once complete and `{index}.{extension}`
+/// while written.
+fn spilled_name(path: &Path) -> (Option<usize>, Option<i64>) {
+    let stem = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split_once('.'))
+        .map_or("", |(stem, _)| stem);
+    let (index, rows) = stem.split_once('-').unwrap_or((stem, ""));
+    (index.parse().ok(), rows.parse().ok())
+}
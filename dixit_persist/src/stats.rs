@@ -0,0 +1,232 @@
+//! Statistics of the columns of every flush of a table, for data quality monitoring, see
+//! `PersistConfig::column_stats`.
+//!
+//! ## Features
+//! - Counts the nulls of every column and estimates its distinct values with a HyperLogLog sketch, close to exact for
+//!   the few distinct values of a flush.
+//! - Keeps the min and max of the numeric columns, and of the timestamp columns as times.
+//! - Computed from the buffered rows before they are written, whatever the format of the table, the pruned columns
+//!   excluded.
+
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use chrono::{DateTime, Utc};
+use parquet::basic::{LogicalType, TimeUnit};
+use parquet::record::Field;
+use parquet::schema::types::TypePtr;
+
+/// Bits of the hash selecting the register of the sketch, 1024 registers estimating within about 3%.
+const PRECISION: u32 = 10;
+const REGISTERS: usize = 1 << PRECISION;
+
+/// Statistics of the rows of a flush.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlushStats {
+    pub rows: usize,
+    pub columns: Vec<ColumnStats>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStats {
+    pub column: String,
+    pub nulls: usize,
+    /// estimated number of distinct values, nulls excluded
+    pub distinct: u64,
+    /// bounds of the numeric columns
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// bounds of the timestamp columns
+    pub min_time: Option<DateTime<Utc>>,
+    pub max_time: Option<DateTime<Utc>>,
+}
+
+impl FlushStats {
+    /// Computes the statistics of the buffered values, one vector per column of `fields`.
+    pub fn compute(fields: &[TypePtr], columns: &[Vec<Field>]) -> Self {
+        Self {
+            rows: columns.first().map(Vec::len).unwrap_or_default(),
+            columns: fields
+                .iter()
+                .zip(columns)
+                .map(|(field, values)| ColumnStats::compute(field, values))
+                .collect(),
+        }
+    }
+}
+
+impl ColumnStats {
+    fn compute(field: &TypePtr, values: &[Field]) -> Self {
+        let unit = match field.get_basic_info().logical_type() {
+            Some(LogicalType::Timestamp { unit, .. }) => Some(unit),
+            _ => None,
+        };
+        let mut nulls = 0;
+        let mut sketch = HyperLogLog::default();
+        let mut bounds: Option<(f64, f64)> = None;
+        let mut time_bounds: Option<(i64, i64)> = None;
+        for value in values {
+            if matches!(value, Field::Null) {
+                nulls += 1;
+                continue;
+            }
+            sketch.insert(value);
+            match (value, &unit) {
+                (Field::Long(epoch), Some(_)) => time_bounds = Some(extend(time_bounds, *epoch)),
+                (Field::ULong(epoch), Some(_)) => time_bounds = Some(extend(time_bounds, *epoch as i64)),
+                _ => {
+                    if let Some(number) = number(value) {
+                        bounds = Some(extend(bounds, number));
+                    }
+                }
+            }
+        }
+        let time = |epoch: i64| unit.as_ref().and_then(|unit| timestamp(epoch, unit));
+        Self {
+            column: field.name().to_owned(),
+            nulls,
+            distinct: sketch.estimate(),
+            min: bounds.map(|(min, _)| min),
+            max: bounds.map(|(_, max)| max),
+            min_time: time_bounds.and_then(|(min, _)| time(min)),
+            max_time: time_bounds.and_then(|(_, max)| time(max)),
+        }
+    }
+
+    pub fn null_rate(&self, rows: usize) -> f64 {
+        if rows == 0 {
+            0.0
+        } else {
+            self.nulls as f64 / rows as f64
+        }
+    }
+}
+
+impl fmt::Display for FlushStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} rows", self.rows)?;
+        for column in self.columns.iter() {
+            write!(
+                f,
+                ", {}: {} nulls ~{} distinct",
+                column.column, column.nulls, column.distinct
+            )?;
+            if let (Some(min), Some(max)) = (column.min, column.max) {
+                write!(f, " [{min}, {max}]")?;
+            }
+            if let (Some(min), Some(max)) = (column.min_time, column.max_time) {
+                write!(f, " [{}, {}]", min.to_rfc3339(), max.to_rfc3339())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Bounds of the values of a timestamp column as times, unset for the other columns or if every value is null.
+pub fn time_bounds(field: &TypePtr, values: &[Field]) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let Some(LogicalType::Timestamp { unit, .. }) = field.get_basic_info().logical_type() else {
+        return None;
+    };
+    let mut bounds = None;
+    for value in values {
+        match value {
+            Field::Long(epoch) => bounds = Some(extend(bounds, *epoch)),
+            Field::ULong(epoch) => bounds = Some(extend(bounds, *epoch as i64)),
+            _ => (),
+        }
+    }
+    let (min, max) = bounds?;
+    Some((timestamp(min, &unit)?, timestamp(max, &unit)?))
+}
+
+fn extend<T: PartialOrd + Copy>(bounds: Option<(T, T)>, value: T) -> (T, T) {
+    match bounds {
+        Some((min, max)) => (
+            if value < min { value } else { min },
+            if value > max { value } else { max },
+        ),
+        None => (value, value),
+    }
+}
+
+fn number(value: &Field) -> Option<f64> {
+    match value {
+        Field::Byte(value) => Some(*value as f64),
+        Field::Short(value) => Some(*value as f64),
+        Field::Int(value) => Some(*value as f64),
+        Field::Long(value) => Some(*value as f64),
+        Field::UByte(value) => Some(*value as f64),
+        Field::UShort(value) => Some(*value as f64),
+        Field::UInt(value) => Some(*value as f64),
+        Field::ULong(value) => Some(*value as f64),
+        Field::Float(value) if !value.is_nan() => Some(*value as f64),
+        Field::Double(value) if !value.is_nan() => Some(*value),
+        _ => None,
+    }
+}
+
+fn timestamp(epoch: i64, unit: &TimeUnit) -> Option<DateTime<Utc>> {
+    match unit {
+        TimeUnit::NANOS(_) => Some(DateTime::from_timestamp_nanos(epoch)),
+        TimeUnit::MICROS(_) => DateTime::from_timestamp_micros(epoch),
+        TimeUnit::MILLIS(_) => DateTime::from_timestamp_millis(epoch),
+    }
+}
+
+/// Sketch of the distinct values of a column.
+struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self {
+            registers: vec![0; REGISTERS],
+        }
+    }
+}
+
+impl HyperLogLog {
+    fn insert(&mut self, value: &Field) {
+        let hash = hash(value);
+        let register = (hash >> (64 - PRECISION)) as usize;
+        // the bit set below the register bits bounds the rank
+        let rank = ((hash << PRECISION) | (1 << (PRECISION - 1))).leading_zeros() as u8 + 1;
+        self.registers[register] = self.registers[register].max(rank);
+    }
+
+    fn estimate(&self) -> u64 {
+        let registers = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / registers);
+        let sum: f64 = self.registers.iter().map(|rank| 2f64.powi(-i32::from(*rank))).sum();
+        let estimate = alpha * registers * registers / sum;
+        let empty = self.registers.iter().filter(|rank| **rank == 0).count();
+        if estimate <= 2.5 * registers && empty > 0 {
+            // linear counting, more accurate for the small cardinalities
+            (registers * (registers / empty as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+fn hash(value: &Field) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    match value {
+        Field::Bool(value) => value.hash(&mut hasher),
+        Field::Byte(value) => i64::from(*value).hash(&mut hasher),
+        Field::Short(value) => i64::from(*value).hash(&mut hasher),
+        Field::Int(value) => i64::from(*value).hash(&mut hasher),
+        Field::Long(value) => value.hash(&mut hasher),
+        Field::UByte(value) => u64::from(*value).hash(&mut hasher),
+        Field::UShort(value) => u64::from(*value).hash(&mut hasher),
+        Field::UInt(value) => u64::from(*value).hash(&mut hasher),
+        Field::ULong(value) => value.hash(&mut hasher),
+        Field::Float(value) => value.to_bits().hash(&mut hasher),
+        Field::Double(value) => value.to_bits().hash(&mut hasher),
+        Field::Str(value) => value.as_bytes().hash(&mut hasher),
+        Field::Bytes(value) => value.data().hash(&mut hasher),
+        value => value.to_string().hash(&mut hasher),
+    }
+    hasher.finish()
+}
@@ -1,19 +1,41 @@
-use crate::config::PersistConfig;
+use crate::checksum;
+use crate::clock::{self, SharedClock};
+use crate::config::{ColumnFilter, FileNaming, Format, OverwriteMode, PersistConfig};
+use crate::csv::{CsvOptions, CsvWriter};
+use crate::dictionary::{self, ZstdDictionary};
+use crate::dynamic::{Descriptor, DynPersistable};
 use crate::error::PersistError;
+use crate::finalize;
+use crate::index::{FileRange, TableIndex};
+use crate::jsonl::JsonlWriter;
+use crate::lock::{TableLock, LOCK_FILE};
+use crate::output::{FileOutput, Output};
+use crate::path::{long_path, table_path};
+use crate::pool::BufferPool;
+use crate::retry::RetryPolicy;
 use crate::row::RowBuffer;
+use crate::spill::SpillQueue;
+use crate::stats::FlushStats;
 use crate::Persistable;
+use crate::{delta, iceberg};
 
 use anyhow::Result;
+use flate2::write::GzEncoder;
 use itertools::Itertools;
 use parquet::basic::{Compression, ZstdLevel};
 use parquet::file::properties::WriterProperties;
 use parquet::file::writer::SerializedFileWriter;
 use parquet::schema::types::{Type, TypePtr};
+use std::any::type_name;
 use std::fs;
-use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::mem;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{io, process, thread};
 use tracing::{error, info, warn};
+use uuid::Uuid;
 
 const BUFFERED_ROWS: usize = 100;
 
@@ -21,44 +43,271 @@ pub struct TableWriter {
     flush_size: usize,
     pub current_file_path: PathBuf,
     file_index: usize,
+    file_naming: FileNaming,
     buffer: RowBuffer,
     enabled: bool,
+    /// whether the writer has a directory or an output to write to, it cannot be enabled otherwise
+    writable: bool,
+    /// columns of the records, pruned columns included
     fields: Vec<TypePtr>,
+    /// columns written to the files, the fields kept by the column filter
+    columns: Vec<TypePtr>,
+    column_filter: ColumnFilter,
     schema: Option<Arc<Type>>,
+    format: Format,
+    csv: CsvOptions,
+    rows_per_file: usize,
+    /// commits between the checkpoints of a delta table, none if 0
+    delta_checkpoint_interval: u64,
+    /// nanosecond timestamp columns of a delta or iceberg table, buffered in microseconds, see
+    /// [`delta::micros_columns`]
+    micros: Vec<usize>,
+    checksum: bool,
+    /// whether every completed file is marked, see [`crate::finalize`]
+    finalize_markers: bool,
+    /// dictionary of the JSON lines and CSV files, if zstd compressed
+    zstd: Option<ZstdDictionary>,
+    open_file: Option<OpenFile>,
+    output: Box<dyn Output>,
+    rows_written: u64,
+    files_written: u64,
+    /// statistics of the last flush, if `PersistConfig::column_stats` is set
+    column_stats: Option<FlushStats>,
+    compute_stats: bool,
+    /// whether the schema was taken once the first file was written, see [`TableWriter::take_created`]
+    created: bool,
+    /// time ranges of the completed files, if `PersistConfig::time_index` is set and the writer has a directory
+    index: Option<TableIndex>,
+    /// time range of the rows flushed to the next completed file
+    range: FileRange,
     pub auto_flush: bool,
+    /// retries of the failed flushes, see [`crate::retry`]
+    retry: RetryPolicy,
+    /// directory of the table in the recovery directory of the retry policy, if set
+    recovery: Option<PathBuf>,
+    flush_retries: u64,
+    /// retries of the failing flush done so far and when the next one is due, unset once the flush succeeds or fails
+    pending_retry: Option<(u32, Instant)>,
+    /// whether a failing flush returns at once rather than sleeping between its retries, see
+    /// [`TableWriter::with_deferred_retries`]
+    deferred_retries: bool,
+    /// files spilled while the output is unavailable, if `PersistConfig::spill_directory` is set
+    spill: Option<SpillQueue>,
+    /// pool the buffer is taken from and handed back to once the writer is dropped, if any
+    pool: Option<BufferPool>,
+    /// names the timestamped files and dumps, and stamps the table format commits
+    clock: SharedClock,
+    /// held while the writer is alive, unset if the writer has no directory
+    _lock: Option<TableLock>,
+}
+
+/// Counters of a table, see [`TableWriter::stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TableStats {
+    /// rows recorded but not flushed yet
+    pub rows_buffered: usize,
+    /// rows flushed to the table files, including the open file
+    pub rows_written: u64,
+    /// files completed, the open file is not counted until closed
+    pub files_written: u64,
+    /// whether the rows recorded are written, see [`TableWriter::set_enabled`]
+    pub enabled: bool,
+    /// failed flushes retried, see [`crate::retry`]
+    pub flush_retries: u64,
+    /// rows spilled to the spill directory and not drained to the table yet, see [`crate::spill`]
+    pub rows_spilled: u64,
 }
 
 impl TableWriter {
     pub fn new(path_prefix: &str, persist_config: &PersistConfig) -> Result<Self> {
-        let enabled = (persist_config.tables.is_empty() || persist_config.tables.contains(path_prefix))
-            && !persist_config.directory.is_empty();
+        let writable = !persist_config.directory.as_os_str().is_empty();
+        let enabled = persist_config.is_table_enabled(path_prefix) && writable;
 
-        if !enabled {
+        if !writable {
+            info!("ignoring parquet persistence for {path_prefix} as persist config {persist_config} has no directory");
+        } else if !enabled {
             info!("ignoring parquet persistence for {path_prefix} as its not mentioned in persist config {persist_config}");
         }
 
-        let mut path = PathBuf::from(&persist_config.directory);
-        path.push(path_prefix);
-        if !persist_config.directory.is_empty() {
-            if !persist_config.keep {
-                warn!("deleting directory {:?}", &path);
-                let _ = fs::remove_dir_all(Path::new(&path));
-            }
+        let mut path = table_path(&persist_config.directory, path_prefix);
+        let mut file_index = 0;
+        let mut lock = None;
+        if !persist_config.directory.as_os_str().is_empty() {
+            path = long_path(&path)?;
             fs::create_dir_all(&path)?;
+            // locked before deleting anything, the data of another writer is never deleted
+            lock = Some(TableLock::acquire(&path, persist_config.lock_timeout)?);
+            match persist_config.overwrite {
+                OverwriteMode::Append => (),
+                OverwriteMode::FailIfExists => {
+                    if let Some(entry) = entries(&path)?.first() {
+                        return Err(PersistError::Other(format!(
+                            "table directory {path:?} is not empty (e.g. {entry:?}), append or purge it explicitly"
+                        ))
+                        .into());
+                    }
+                }
+                OverwriteMode::Purge => purge(&path)?,
+            }
+            if persist_config.finalize_markers {
+                finalize::mark_table(&mut FileOutput, &path)?;
+            }
+            // the directory is listed once, the next files are numbered from there
+            if persist_config.file_naming == FileNaming::Index {
+                file_index = next_index(&path)?;
+            }
         }
 
+        let index = match &persist_config.time_index {
+            Some(column) if lock.is_some() => Some(TableIndex::open(&path, column)?),
+            _ => None,
+        };
+
+        let format = persist_config.format(path_prefix);
+        let zstd = match zstd_compressed(persist_config, format) {
+            true if enabled => Some(ZstdDictionary::load(&path)?),
+            true => Some(ZstdDictionary::default()),
+            false => None,
+        };
+
+        let mut buffer = RowBuffer::default();
+        buffer.set_validate(persist_config.validate);
+        buffer.set_rows_per_row_group(persist_config.rows_per_row_group);
+
+        Ok(TableWriter {
+            flush_size: BUFFERED_ROWS,
+            current_file_path: path,
+            file_index,
+            file_naming: persist_config.file_naming,
+            enabled,
+            writable,
+            buffer,
+            fields: vec![],
+            columns: vec![],
+            column_filter: persist_config.columns(path_prefix),
+            schema: None,
+            format,
+            csv: persist_config.csv,
+            rows_per_file: persist_config.rows_per_file,
+            delta_checkpoint_interval: persist_config.delta_checkpoint_interval,
+            micros: vec![],
+            checksum: persist_config.checksum,
+            finalize_markers: persist_config.finalize_markers,
+            zstd,
+            open_file: None,
+            output: Box::new(FileOutput),
+            rows_written: 0,
+            files_written: 0,
+            column_stats: None,
+            compute_stats: persist_config.column_stats,
+            created: false,
+            index,
+            range: FileRange::default(),
+            auto_flush: true,
+            retry: persist_config.flush_retry.clone(),
+            recovery: recovery_path(persist_config, path_prefix),
+            flush_retries: 0,
+            pending_retry: None,
+            deferred_retries: false,
+            spill: spill_queue(persist_config, path_prefix)?,
+            pool: None,
+            clock: clock::system(),
+            _lock: lock,
+        })
+    }
+
+    /// Creates a writer opening its files through `output` rather than on the filesystem, the table path being the
+    /// directory of the config joined with the path prefix, as with [`TableWriter::new`], whether it exists or not.
+    ///
+    /// Nothing is read from or written to the filesystem, so the overwrite mode is not applied and files named by
+    /// index start from 0. Fails for the delta and iceberg formats or with checksums, which read their files back.
+    pub fn with_output(
+        path_prefix: &str,
+        persist_config: &PersistConfig,
+        output: impl Output + 'static,
+    ) -> Result<Self> {
+        let format = persist_config.format(path_prefix);
+        if matches!(format, Format::Delta | Format::Iceberg) || persist_config.checksum {
+            return Err(PersistError::Other(format!(
+                "table {path_prefix} cannot be written to a custom output as {format:?}{}, only plain files can",
+                if persist_config.checksum { " with checksums" } else { "" }
+            ))
+            .into());
+        }
+        let enabled = persist_config.is_table_enabled(path_prefix);
+        if !enabled {
+            info!("ignoring parquet persistence for {path_prefix} as its not mentioned in persist config {persist_config}");
+        }
+
+        let mut output = output;
+        let path = table_path(&persist_config.directory, path_prefix);
+        if persist_config.finalize_markers {
+            finalize::mark_table(&mut output, &path)?;
+        }
+
+        let mut buffer = RowBuffer::default();
+        buffer.set_validate(persist_config.validate);
+        buffer.set_rows_per_row_group(persist_config.rows_per_row_group);
+
         Ok(TableWriter {
             flush_size: BUFFERED_ROWS,
             current_file_path: path,
             file_index: 0,
+            file_naming: persist_config.file_naming,
             enabled,
-            buffer: RowBuffer::default(),
+            writable: true,
+            buffer,
             fields: vec![],
+            columns: vec![],
+            column_filter: persist_config.columns(path_prefix),
             schema: None,
+            format,
+            csv: persist_config.csv,
+            rows_per_file: persist_config.rows_per_file,
+            delta_checkpoint_interval: persist_config.delta_checkpoint_interval,
+            micros: vec![],
+            checksum: false,
+            finalize_markers: persist_config.finalize_markers,
+            zstd: zstd_compressed(persist_config, format).then(ZstdDictionary::default),
+            open_file: None,
+            output: Box::new(output),
+            rows_written: 0,
+            files_written: 0,
+            column_stats: None,
+            compute_stats: persist_config.column_stats,
+            created: false,
+            index: None,
+            range: FileRange::default(),
             auto_flush: true,
+            retry: persist_config.flush_retry.clone(),
+            recovery: recovery_path(persist_config, path_prefix),
+            flush_retries: 0,
+            pending_retry: None,
+            deferred_retries: false,
+            spill: spill_queue(persist_config, path_prefix)?,
+            pool: None,
+            clock: clock::system(),
+            _lock: None,
         })
     }
 
+    /// Buffers the rows in a buffer taken from `pool`, handed back to it once the writer is dropped, so the writers
+    /// created one after the other reuse the allocations of the previous ones. Meant to be called before any row.
+    pub fn with_buffer_pool(mut self, pool: BufferPool) -> Self {
+        let mut buffer = pool.take();
+        buffer.set_validate(self.buffer.validate());
+        buffer.set_rows_per_row_group(self.buffer.rows_per_row_group());
+        self.buffer = buffer;
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Reads the time from `clock` instead of the system clock, e.g. a [`clock::ManualClock`] in tests.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
     pub fn begin(&mut self) -> Result<RowBuilder> {
         if self.enabled {
             if self.buffer.len() >= self.flush_size {
@@ -70,54 +319,496 @@ impl TableWriter {
         Ok(RowBuilder::new(self))
     }
 
+    /// Retries the failed flushes without sleeping, for the writers driven by an async loop (e.g. the
+    /// [`crate::service`]): a flush failing on an io error returns at once with its rows kept buffered, the flushes
+    /// called before the retry is due (see [`TableWriter::retry_due`]) do nothing and the first one called after it
+    /// retries, until the retries are exhausted.
+    pub fn with_deferred_retries(mut self) -> Self {
+        self.deferred_retries = true;
+        self
+    }
+
+    /// When the retry of the failing flush is due, if any, see [`TableWriter::with_deferred_retries`].
+    pub fn retry_due(&self) -> Option<Instant> {
+        self.pending_retry.map(|(_, due)| due)
+    }
+
+    /// Writes the buffered rows, retrying the io errors as set by the `flush_retry` of the config, then spilling the
+    /// rows to the `spill_directory` if set, the rows being kept buffered until written (or dumped to the recovery
+    /// directory). Sleeps between the retries, unless they are deferred with [`TableWriter::with_deferred_retries`].
     pub fn flush(&mut self) -> Result<(), PersistError> {
-        if self.buffer.is_empty() || !self.enabled {
-            return Ok(());
+        if self.deferred_retries {
+            if self.retry_due().is_some_and(|due| Instant::now() < due) {
+                return Ok(());
+            }
+            return self.flush_attempt().map(|_| ());
         }
+        while let Some(delay) = self.flush_attempt()? {
+            thread::sleep(delay);
+        }
+        Ok(())
+    }
 
-        let schema = self
-            .schema
-            .as_ref()
-            .ok_or_else(|| PersistError::Other("schema has not been created".to_string()))?
-            .clone();
+    /// Flushes once, returning the delay before the next retry if the flush failed on an io error with retries left.
+    fn flush_attempt(&mut self) -> Result<Option<Duration>, PersistError> {
+        let (retry, _) = self.pending_retry.take().unwrap_or((0, Instant::now()));
+        let e = match self.try_flush() {
+            Ok(()) => return Ok(None),
+            Err(e) if !e.is_transient() => return Err(e),
+            Err(e) => e,
+        };
+        if retry < self.retry.retries {
+            let delay = self.retry.delay(retry);
+            self.flush_retries += 1;
+            warn!(
+                "flush of {:?} failed, retry {}/{} in {delay:?} - {e}",
+                self.current_file_path,
+                retry + 1,
+                self.retry.retries
+            );
+            self.pending_retry = Some((retry + 1, Instant::now() + delay));
+            return Ok(Some(delay));
+        }
+        if self.spill.is_some() {
+            match self.spill() {
+                Ok(()) => return Ok(None),
+                Err(spill) => error!("cannot spill the rows of {:?} - {spill}", self.current_file_path),
+            }
+        }
+        if !self.retry.is_enabled() {
+            return Err(e);
+        }
+        Err(self.escalate(retry + 1, e))
+    }
 
-        let level = ZstdLevel::try_new(1)
-            .map_err(|e| PersistError::Other(format!("cannot select correct parquet compression level - {:?}", e)))?;
-        let props = Arc::new(
-            WriterProperties::builder()
-                .set_compression(Compression::ZSTD(level))
-                .build(),
+    /// Writes the buffered rows to a new file of the spill directory, clearing them once written.
+    fn spill(&mut self) -> Result<(), PersistError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let schema = self.schema.clone().ok_or(PersistError::SchemaMissing)?;
+        let extension = self.extension();
+        let spill = self.spill.as_mut().expect("spill directory set");
+        let path = spill.next_path(extension)?;
+        let rows = fs::File::create_new(&path)
+            .map_err(PersistError::from)
+            .and_then(|file| self.write_rows(Box::new(file), schema));
+        let rows = match rows {
+            Ok(rows) => rows,
+            Err(e) => {
+                let _ = fs::remove_file(&path);
+                return Err(e);
+            }
+        };
+        let range = mem::take(&mut self.range);
+        let spill = self.spill.as_mut().expect("spill directory set");
+        let spilled = spill.push(&path, rows, range)?;
+        warn!(
+            "spilled {rows} rows of {:?} to {spilled:?}, {} rows to drain",
+            self.current_file_path,
+            spill.rows()
         );
+        Ok(())
+    }
 
-        let mut buf;
-        loop {
-            buf = PathBuf::from(&self.current_file_path);
-            buf.push(format!("{:0>9}.parquet", self.file_index));
-            self.file_index += 1;
-            if !buf.as_path().exists() {
-                break;
+    /// Writes the spilled files to the output, oldest first, as files of the table.
+    fn drain(&mut self, schema: &Type) -> Result<(), PersistError> {
+        while let Some(spilled) = self.spill.as_ref().and_then(SpillQueue::front).cloned() {
+            let path = self.next_path();
+            let copied = self.output.create(&path).and_then(|mut file| {
+                io::copy(&mut fs::File::open(&spilled.path)?, &mut file)?;
+                file.flush()
+            });
+            if let Err(e) = copied {
+                let e = PersistError::from(e);
+                self.remove_partial(&path, &e);
+                return Err(e);
+            }
+            info!("drained {:?} to {:?}", spilled.path, path);
+            self.rows_written += spilled.rows as u64;
+            self.files_written += 1;
+            self.spill.as_mut().and_then(SpillQueue::pop);
+            let range = mem::replace(&mut self.range, spilled.range);
+            let completed = self.completed(&path, spilled.rows, schema);
+            self.range = range;
+            completed?;
+        }
+        Ok(())
+    }
+
+    /// Fails a flush whose retries are exhausted, dumping its rows to the recovery directory first if set.
+    fn escalate(&mut self, attempts: u32, source: PersistError) -> PersistError {
+        let recovery = self.recovery.clone().and_then(|directory| match self.dump(&directory) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                error!(
+                    "cannot dump the rows of {:?} to {directory:?} - {e}",
+                    self.current_file_path
+                );
+                None
             }
+        });
+        let e = PersistError::FlushFailed {
+            attempts,
+            recovery,
+            source: Box::new(source),
+        };
+        error!("{:?}: {e}", self.current_file_path);
+        e
+    }
+
+    /// Writes the buffered rows as JSON lines to a new file of `directory`, clearing them once written.
+    fn dump(&mut self, directory: &Path) -> Result<PathBuf, PersistError> {
+        fs::create_dir_all(directory)?;
+        let path = directory.join(format!(
+            "{}-{}.jsonl",
+            self.clock.now().format("%Y%m%dT%H%M%S%.9fZ"),
+            process::id()
+        ));
+        let file = BufWriter::new(fs::File::create_new(&path)?);
+        let rows = JsonlWriter::new(file).write(&self.columns, &mut self.buffer)?;
+        warn!("dumped {rows} rows of {:?} to {path:?}", self.current_file_path);
+        Ok(path)
+    }
+
+    fn try_flush(&mut self) -> Result<(), PersistError> {
+        if !self.enabled {
+            return Ok(());
+        }
+        // the rows spilled first are written first, once the schema is known
+        if let Some(schema) = self.schema.clone() {
+            self.drain(&schema)?;
         }
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let schema = self.schema.as_ref().ok_or(PersistError::SchemaMissing)?.clone();
+
+        if self.compute_stats {
+            let stats = FlushStats::compute(&self.columns, self.buffer.columns());
+            info!("flushing {:?}: {stats}", self.current_file_path);
+            self.column_stats = Some(stats);
+        }
+        if let Some(index) = &self.index {
+            self.range.extend(&index.column, &self.columns, self.buffer.columns());
+        }
+
+        if self.rows_per_file > 0 && matches!(self.format, Format::Parquet | Format::Delta | Format::Iceberg) {
+            return self.append_row_groups(schema);
+        }
+
+        let buf = self.next_path();
         info!("saving {:?}", buf);
-        let mut writer = SerializedFileWriter::new(File::create_new(buf)?, schema, props)
-            .map_err(|e| PersistError::Other(format!("cannot create parquet serialiser - {:?}", e)))?;
+        let rows = self
+            .output
+            .create(&buf)
+            .map_err(PersistError::from)
+            .and_then(|file| self.write_rows(file, schema.clone()));
+        let rows = match rows {
+            Ok(rows) => rows,
+            Err(e) => {
+                self.remove_partial(&buf, &e);
+                return Err(e);
+            }
+        };
 
-        self.buffer.record(&mut writer).map_err(|e| {
-            PersistError::Other(format!(
-                "failed to write to parquet {:?} - {:?}",
-                self.current_file_path, e
-            ))
-        })?;
+        info!("written {} rows", rows);
+        self.rows_written += rows as u64;
+        self.files_written += 1;
+        self.completed(&buf, rows, &schema)
+    }
 
-        let result = writer
-            .close()
-            .map_err(|e| PersistError::Other(format!("failed to close parquet writer - {:?}", e)))?;
+    /// Removes the file left on the filesystem by a failed flush, its rows being still buffered, unless the flush
+    /// failed as the file existed already (e.g. created by another writer).
+    fn remove_partial(&self, path: &Path, e: &PersistError) {
+        let taken = matches!(e, PersistError::Io(e) if e.kind() == io::ErrorKind::AlreadyExists);
+        if taken || !self.output.exists(path) {
+            return;
+        }
+        match fs::remove_file(path) {
+            Ok(()) => warn!("removed {path:?} left by a failed flush"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => error!("cannot remove {path:?} left by a failed flush - {e}"),
+        }
+    }
 
-        info!("written {} rows", result.num_rows);
+    /// Flushes the buffered rows and closes the open parquet file, if any.
+    pub fn close(&mut self) -> Result<(), PersistError> {
+        self.flush()?;
+        self.close_file()
+    }
 
+    fn next_path(&mut self) -> PathBuf {
+        let name = match self.file_naming {
+            FileNaming::Index => {
+                let mut path = self.index_path();
+                if self.output.exists(&path) {
+                    // another writer is adding files to the table, continue after its files
+                    self.file_index = next_index(&self.current_file_path).unwrap_or(self.file_index + 1);
+                    path = self.index_path();
+                }
+                self.file_index += 1;
+                return path;
+            }
+            FileNaming::Timestamp => format!("{}-{}", self.clock.now().format("%Y%m%dT%H%M%S%.9fZ"), process::id()),
+            FileNaming::Uuid => Uuid::new_v4().to_string(),
+        };
+        self.current_file_path.join(format!("{name}.{}", self.extension()))
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.current_file_path
+            .join(format!("{:0>9}.{}", self.file_index, self.extension()))
+    }
+
+    /// Writes the checksum sidecar of a completed file, if enabled, adds it to the time index, if kept, commits it to
+    /// the table log and marks it as finalized, if enabled.
+    fn completed(&mut self, path: &Path, rows: i64, schema: &Type) -> Result<(), PersistError> {
+        if self.checksum {
+            checksum::write_sidecar(path)?;
+        }
+        let range = mem::take(&mut self.range);
+        if let Some(index) = self.index.as_mut() {
+            let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            index.files.insert(
+                name,
+                FileRange {
+                    rows: rows as u64,
+                    ..range
+                },
+            );
+            index.save(&self.current_file_path)?;
+        }
+        self.commit(path, rows, schema)?;
+        if self.finalize_markers {
+            finalize::mark(self.output.as_mut(), path)?;
+        }
         Ok(())
     }
 
+    fn commit(&self, path: &Path, rows: i64, schema: &Type) -> Result<(), PersistError> {
+        let now = self.clock.now();
+        match self.format {
+            Format::Parquet | Format::Jsonl | Format::Csv => Ok(()),
+            Format::Delta => delta::commit(
+                &self.current_file_path,
+                path,
+                rows,
+                schema,
+                self.delta_checkpoint_interval,
+                now,
+            ),
+            Format::Iceberg => iceberg::commit(&self.current_file_path, path, rows, schema, now),
+        }
+    }
+
+    /// Appends the buffered rows as new row groups of the open file, opening it first if needed, the file is closed
+    /// once it holds `rows_per_file` rows.
+    fn append_row_groups(&mut self, schema: Arc<Type>) -> Result<(), PersistError> {
+        let mut file = match self.open_file.take() {
+            Some(file) => file,
+            None => {
+                let path = self.next_path();
+                info!("opening {:?}", path);
+                let writer = parquet_writer(self.output.create(&path)?, schema)?;
+                OpenFile { path, writer, rows: 0 }
+            }
+        };
+        let rows = self.buffer.record(&mut file.writer).map_err(PersistError::from);
+        // the file is kept open on errors, its row groups written so far are still valid
+        self.open_file = Some(file);
+        let rows = rows?;
+        info!("appended {} rows", rows);
+        self.rows_written += rows as u64;
+
+        let file = self.open_file.as_mut().expect("file just opened");
+        file.rows += rows;
+        if file.rows >= self.rows_per_file {
+            self.close_file()?;
+        }
+        Ok(())
+    }
+
+    fn close_file(&mut self) -> Result<(), PersistError> {
+        let Some(file) = self.open_file.take() else {
+            return Ok(());
+        };
+        let result = file.writer.close()?;
+        info!("saved {:?} with {} rows", file.path, result.num_rows);
+        self.files_written += 1;
+        let schema = self.schema.clone().ok_or(PersistError::SchemaMissing)?;
+        self.completed(&file.path, result.num_rows, &schema)
+    }
+
+    fn extension(&self) -> &'static str {
+        match self.format {
+            Format::Jsonl if self.zstd.is_some() => "jsonl.zst",
+            Format::Csv if self.zstd.is_some() => "csv.zst",
+            Format::Csv => self.csv.extension(),
+            format => format.extension(),
+        }
+    }
+
+    /// Writes the buffered rows to `file` in the format of the table, clearing them once written.
+    fn write_rows(&mut self, file: Box<dyn Write + Send>, schema: Arc<Type>) -> Result<i64, PersistError> {
+        let rows = match self.format {
+            Format::Jsonl | Format::Csv if self.zstd.is_some() => self.write_zstd(file)? as i64,
+            Format::Jsonl => JsonlWriter::new(BufWriter::new(file)).write(&self.columns, &mut self.buffer)? as i64,
+            Format::Csv => self.write_csv(file)? as i64,
+            _ => self.write_parquet(file, schema)?,
+        };
+        Ok(rows)
+    }
+
+    /// Writes the buffered rows as a zstd compressed file, storing the dictionary of the table first if trained by
+    /// this flush.
+    fn write_zstd(&mut self, file: Box<dyn Write + Send>) -> Result<usize, PersistError> {
+        let mut content = vec![];
+        let rows = match self.format {
+            Format::Csv => CsvWriter::new(&mut content, &self.csv)?.write(&self.columns, &mut self.buffer)?,
+            _ => JsonlWriter::new(&mut content).write(&self.columns, &mut self.buffer)?,
+        };
+        let zstd = self.zstd.as_mut().expect("zstd compressed table");
+        if let Some(trained) = zstd.train(&content) {
+            let mut file = self
+                .output
+                .create(&self.current_file_path.join(dictionary::DICTIONARY_FILE))?;
+            file.write_all(trained)?;
+            file.flush()?;
+        }
+        let mut file = BufWriter::new(file);
+        file.write_all(&zstd.compress(&content)?)?;
+        file.flush()?;
+        Ok(rows)
+    }
+
+    fn write_csv(&mut self, file: Box<dyn Write + Send>) -> Result<usize, PersistError> {
+        let file = BufWriter::new(file);
+        if self.csv.gzip {
+            let mut writer = CsvWriter::new(GzEncoder::new(file, flate2::Compression::fast()), &self.csv)?;
+            let rows = writer.write(&self.columns, &mut self.buffer)?;
+            writer.into_inner()?.finish()?;
+            Ok(rows)
+        } else {
+            CsvWriter::new(file, &self.csv)?.write(&self.columns, &mut self.buffer)
+        }
+    }
+
+    fn write_parquet(&mut self, file: Box<dyn Write + Send>, schema: Arc<Type>) -> Result<i64, PersistError> {
+        let mut writer = parquet_writer(file, schema)?;
+
+        self.buffer.record(&mut writer)?;
+
+        let result = writer.close()?;
+        Ok(result.num_rows)
+    }
+
+    pub fn stats(&self) -> TableStats {
+        TableStats {
+            rows_buffered: self.buffer.len(),
+            rows_written: self.rows_written,
+            files_written: self.files_written,
+            enabled: self.enabled,
+            flush_retries: self.flush_retries,
+            rows_spilled: self.spill.as_ref().map_or(0, SpillQueue::rows),
+        }
+    }
+
+    /// Whether the rows recorded are written, unset if the table is not in the `tables` of the config or the config
+    /// has no directory, the records of a disabled table being dropped.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enables or disables the table at runtime, whatever the `tables` of the config. Disabling a table flushes its
+    /// buffered rows and completes its open file, the rows recorded next are dropped until it is enabled again.
+    /// Fails to enable a table without a directory.
+    pub fn set_enabled(&mut self, enabled: bool) -> Result<(), PersistError> {
+        if enabled == self.enabled {
+            return Ok(());
+        }
+        if !enabled {
+            self.close()?;
+            self.enabled = false;
+            info!("disabled parquet persistence for {:?}", self.current_file_path);
+            return Ok(());
+        }
+        if !self.writable {
+            return Err(PersistError::Other(format!(
+                "table {:?} cannot be enabled as the persist config has no directory",
+                self.current_file_path
+            )));
+        }
+        // the dictionary of a table disabled from the start is not loaded
+        if self._lock.is_some() && self.zstd.as_ref().is_some_and(|zstd| zstd.get().is_none()) {
+            self.zstd = Some(ZstdDictionary::load(&self.current_file_path)?);
+        }
+        self.enabled = true;
+        info!("enabled parquet persistence for {:?}", self.current_file_path);
+        Ok(())
+    }
+
+    /// Selects the columns written to the files from the fields of the first row, through the column filter of the
+    /// table, the values of the other columns being discarded by the buffer.
+    fn prune(&mut self) -> Result<(), PersistError> {
+        if self.column_filter.is_empty() {
+            self.columns = self.fields.clone();
+            return Ok(());
+        }
+        let names = self.fields.iter().map(|field| field.name()).collect_vec();
+        for pattern in self.column_filter.unmatched(&names) {
+            warn!(
+                "column {pattern} of the column filter matches no column of {:?}",
+                self.current_file_path
+            );
+        }
+        let kept = self.column_filter.kept(&names);
+        if !kept.contains(&true) {
+            return Err(PersistError::Other(format!(
+                "every column of {:?} is pruned by its column filter",
+                self.current_file_path
+            )));
+        }
+        let pruned = names
+            .iter()
+            .zip(&kept)
+            .filter(|(_, kept)| !**kept)
+            .map(|(name, _)| name)
+            .join(", ");
+        if !pruned.is_empty() {
+            info!("pruning columns {pruned} of {:?}", self.current_file_path);
+        }
+        self.buffer.prune(&kept);
+        self.columns = self
+            .fields
+            .iter()
+            .zip(kept)
+            .filter(|(_, kept)| *kept)
+            .map(|(field, _)| field.clone())
+            .collect();
+        Ok(())
+    }
+
+    /// Takes the column statistics of the last flush, if computed since the last call.
+    pub fn take_column_stats(&mut self) -> Option<FlushStats> {
+        self.column_stats.take()
+    }
+
+    /// Takes the schema of the written columns once the first file of the writer is completed, a single time, e.g.
+    /// to register the table in a catalog.
+    pub fn take_created(&mut self) -> Option<Arc<Type>> {
+        if self.created || self.files_written == 0 {
+            return None;
+        }
+        self.created = true;
+        self.schema.clone()
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
     pub fn flush_if_needed(&mut self) -> Result<(), PersistError> {
         if self.buffer.len() >= self.flush_size {
             self.flush()?
@@ -128,42 +819,267 @@ impl TableWriter {
 
 impl Drop for TableWriter {
     fn drop(&mut self) {
-        if !self.buffer.is_empty() {
-            if let Err(e) = self.flush() {
+        // nothing flushes the writer afterwards, so the retries left are waited for
+        self.deferred_retries = false;
+        if !self.buffer.is_empty() || self.open_file.is_some() {
+            if let Err(e) = self.close() {
                 error!("failed to flush file {:?}", e)
             }
         }
+        if let Some(pool) = self.pool.take() {
+            pool.recycle(mem::take(&mut self.buffer));
+        }
     }
 }
 
+/// Directory of the table in the recovery directory of the retry policy, if set.
+fn recovery_path(persist_config: &PersistConfig, path_prefix: &str) -> Option<PathBuf> {
+    let directory = persist_config.flush_retry.recovery_directory.as_ref()?;
+    Some(table_path(directory, path_prefix))
+}
+
+/// Spilled files of the table, if the config has a spill directory.
+fn spill_queue(persist_config: &PersistConfig, path_prefix: &str) -> Result<Option<SpillQueue>, PersistError> {
+    let Some(directory) = &persist_config.spill_directory else {
+        return Ok(None);
+    };
+    Ok(Some(SpillQueue::open(table_path(directory, path_prefix))?))
+}
+
+/// Whether the files of the table are zstd compressed with a dictionary, only JSON lines and CSV files are.
+fn zstd_compressed(persist_config: &PersistConfig, format: Format) -> bool {
+    persist_config.zstd_dictionary && matches!(format, Format::Jsonl | Format::Csv)
+}
+
+/// Entries of the table directory but its lock file, sorted by name.
+fn entries(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries = vec![];
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        if entry.file_name() != LOCK_FILE {
+            entries.push(entry.path());
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+/// Deletes everything in the table directory but its lock file, logging what is deleted first.
+fn purge(directory: &Path) -> io::Result<()> {
+    let entries = entries(directory)?;
+    if entries.is_empty() {
+        return Ok(());
+    }
+    warn!("purging {} entries of directory {:?}", entries.len(), directory);
+    for entry in entries.iter() {
+        warn!("deleting {:?}", entry);
+    }
+    for entry in entries {
+        if fs::symlink_metadata(&entry)?.is_dir() {
+            fs::remove_dir_all(entry)?;
+        } else {
+            fs::remove_file(entry)?;
+        }
+    }
+    Ok(())
+}
+
+/// Index following the files of the table directory named by index, whatever their extension.
+fn next_index(directory: &Path) -> io::Result<usize> {
+    let mut next = 0;
+    for entry in fs::read_dir(directory)? {
+        let name = entry?.file_name();
+        let index = name
+            .to_str()
+            .and_then(|name| name.split('.').next())
+            .filter(|stem| !stem.is_empty() && stem.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|stem| stem.parse::<usize>().ok());
+        if let Some(index) = index {
+            next = next.max(index + 1);
+        }
+    }
+    Ok(next)
+}
+
+fn parquet_writer<W: Write + Send>(file: W, schema: Arc<Type>) -> Result<SerializedFileWriter<W>, PersistError> {
+    let level = ZstdLevel::try_new(1)?;
+    let props = Arc::new(
+        WriterProperties::builder()
+            .set_compression(Compression::ZSTD(level))
+            .build(),
+    );
+
+    Ok(SerializedFileWriter::new(file, schema, props)?)
+}
+
+/// Parquet file kept open across flushes, see `PersistConfig::rows_per_file`.
+struct OpenFile {
+    path: PathBuf,
+    writer: SerializedFileWriter<Box<dyn Write + Send>>,
+    rows: usize,
+}
+
 pub struct RowBuilder<'a> {
     writer: &'a mut TableWriter,
+    rows: usize,
+    ignored: bool,
 }
 
 impl<'a> RowBuilder<'a> {
     pub fn new(writer: &'a mut TableWriter) -> Self {
-        Self { writer }
+        let rows = writer.buffer.len();
+        Self {
+            writer,
+            rows,
+            ignored: false,
+        }
     }
 
-    pub fn record<T: Persistable>(self, record: &T) -> Result<Self, PersistError> {
+    /// Appends the record to the row, the whole row is dropped at `end` if any of its records is ignored.
+    pub fn record<T: Persistable>(mut self, record: &T) -> Result<Self, PersistError> {
         if self.writer.enabled {
             if self.writer.schema.is_none() {
+                let start = self.writer.fields.len();
                 T::schema(&mut self.writer.fields, None, None, None);
+                if let Err(e) = check_columns(&self.writer.fields, start, type_name::<T>()) {
+                    // the schema is built again by the next row
+                    self.writer.fields.clear();
+                    self.writer.buffer.truncate(self.rows);
+                    return Err(e);
+                }
+                self.writer.buffer.set_schema(&self.writer.fields);
+            }
+
+            self.ignored |= record.ignored();
+            if !self.ignored {
+                record.append(&mut self.writer.buffer)?;
+                if let Some(e) = self.writer.buffer.take_error() {
+                    self.writer.buffer.truncate(self.rows);
+                    return Err(e.into());
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    /// Appends a record whose type is only known at runtime as `record`, the columns of the table being set by the
+    /// descriptor of its first row. Fails if the record does not append one value per column of the descriptor, or a
+    /// value not matching its column.
+    pub fn record_dyn(mut self, descriptor: &Descriptor, record: &dyn DynPersistable) -> Result<Self, PersistError> {
+        if self.writer.enabled {
+            if self.writer.schema.is_none() {
+                let start = self.writer.fields.len();
+                self.writer.fields.extend(descriptor.fields().iter().cloned());
+                if let Err(e) = check_columns(&self.writer.fields, start, descriptor.name()) {
+                    self.writer.fields.clear();
+                    self.writer.buffer.truncate(self.rows);
+                    return Err(e);
+                }
+                self.writer.buffer.set_schema(&self.writer.fields);
             }
 
-            record.append(&mut self.writer.buffer)?;
+            self.ignored |= record.ignored();
+            if !self.ignored {
+                let start = self.writer.buffer.pushed();
+                record.append(&mut self.writer.buffer)?;
+                let appended = self.writer.buffer.pushed() - start;
+                if appended != descriptor.fields().len() {
+                    self.writer.buffer.truncate(self.rows);
+                    return Err(PersistError::SchemaMismatch {
+                        expected: format!("{} columns of {}", descriptor.fields().len(), descriptor.name()),
+                        found: format!("{appended} values appended"),
+                    });
+                }
+                if let Err(e) = self.writer.buffer.check_pushed(start) {
+                    self.writer.buffer.truncate(self.rows);
+                    return Err(e.into());
+                }
+                if let Some(e) = self.writer.buffer.take_error() {
+                    self.writer.buffer.truncate(self.rows);
+                    return Err(e.into());
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    /// Appends a value given by the caller as the column `key`, or the columns prefixed by `key` for a struct, e.g.
+    /// the exchange of a venue struct not holding it. The context columns are declared by the first row, the next
+    /// rows set them in the same order.
+    pub fn context<T: Persistable>(self, key: &str, value: &T) -> Result<Self, PersistError> {
+        self.append_context(key, value, false)
+    }
+
+    /// Appends the symbol of the row as an interned `symbol` column, see [`RowBuilder::context`].
+    pub fn tag(self, symbol: &str) -> Result<Self, PersistError> {
+        self.append_context("symbol", &symbol, true)
+    }
+
+    fn append_context<T: Persistable>(self, key: &str, value: &T, interned: bool) -> Result<Self, PersistError> {
+        if self.writer.enabled {
+            let start = self.writer.buffer.pushed();
+            if self.writer.schema.is_none() {
+                T::schema(&mut self.writer.fields, Some(key), None, None);
+                if let Err(e) = check_columns(&self.writer.fields, start, key) {
+                    self.writer.fields.clear();
+                    self.writer.buffer.truncate(self.rows);
+                    return Err(e);
+                }
+                self.writer.buffer.set_schema(&self.writer.fields);
+            } else {
+                let column = self.writer.fields.get(start).map(|field| field.name());
+                let declared = column.is_some_and(|name| {
+                    name == key || name.strip_prefix(key).is_some_and(|name| name.starts_with('_'))
+                });
+                if !declared {
+                    self.writer.buffer.truncate(self.rows);
+                    return Err(PersistError::SchemaMismatch {
+                        expected: format!(
+                            "column {start} ({}) of {:?}, set the contexts in the order of the first row",
+                            column.unwrap_or("none"),
+                            self.writer.current_file_path
+                        ),
+                        found: format!("context {key}"),
+                    });
+                }
+            }
+
+            if !self.ignored {
+                if interned {
+                    value.append_interned(&mut self.writer.buffer)?;
+                } else {
+                    value.append(&mut self.writer.buffer)?;
+                }
+                if let Some(e) = self.writer.buffer.take_error() {
+                    self.writer.buffer.truncate(self.rows);
+                    return Err(e.into());
+                }
+            }
         }
         Ok(self)
     }
 
     pub fn end(&mut self) -> Result<(), PersistError> {
         if self.writer.enabled {
+            if self.ignored {
+                self.writer.buffer.truncate(self.rows);
+            }
+
             if self.writer.schema.is_none() {
+                if let Err(e) = self.writer.prune() {
+                    // the schema is built again by the next row
+                    self.writer.fields.clear();
+                    self.writer.buffer.truncate(self.rows);
+                    return Err(e);
+                }
+                if matches!(self.writer.format, Format::Delta | Format::Iceberg) {
+                    self.writer.micros = delta::micros_columns(&mut self.writer.columns)?;
+                }
                 info!(
                     "created table {:?} {:?}",
                     self.writer.current_file_path,
                     self.writer
-                        .fields
+                        .columns
                         .iter()
                         .map(|f| format!("{}:{:?}", f.name(), f.get_physical_type()))
                         .collect_vec()
@@ -171,11 +1087,15 @@ impl<'a> RowBuilder<'a> {
 
                 self.writer.schema.replace(Arc::new(
                     Type::group_type_builder("schema")
-                        .with_fields(self.writer.fields.clone())
+                        .with_fields(self.writer.columns.clone())
                         .build()?,
                 ));
             }
 
+            if !self.writer.micros.is_empty() && self.writer.buffer.len() > self.rows {
+                self.writer.buffer.scale_last(&self.writer.micros, 1000);
+            }
+
             if self.writer.auto_flush {
                 self.writer.flush_if_needed()?;
             }
@@ -183,3 +1103,18 @@ impl<'a> RowBuilder<'a> {
         Ok(())
     }
 }
+
+/// Fails if a column added by the type `name` (from `start`) has the name of another column, e.g. a `foo_bar` field
+/// next to a `foo` field flattened into `foo_bar`.
+fn check_columns(fields: &[TypePtr], start: usize, name: &str) -> Result<(), PersistError> {
+    for (j, field) in fields.iter().enumerate().skip(start) {
+        if let Some(i) = fields[..j].iter().position(|other| other.name() == field.name()) {
+            return Err(PersistError::Other(format!(
+                "duplicate column {} in {}: columns {i} and {j} have the same name, rename one of the fields",
+                field.name(),
+                name,
+            )));
+        }
+    }
+    Ok(())
+}
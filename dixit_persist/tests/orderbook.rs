@@ -33,7 +33,9 @@ pub struct OrderBook {
     pub healthy: bool,
     pub stale: bool,
     pub ignore: bool,
+    #[persist_timestamp(unit = "ns")]
     pub exchange_ts: u64,
+    #[persist_timestamp(unit = "ns")]
     pub internal_ts: u64,
 }
 
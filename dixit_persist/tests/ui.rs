@@ -0,0 +1,6 @@
+/// Compile errors of the `Persist` derive, refresh the expected output with `TRYBUILD=overwrite`.
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
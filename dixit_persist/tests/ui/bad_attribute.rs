@@ -0,0 +1,11 @@
+use dixit_persist_macros::Persist;
+
+#[derive(Persist)]
+struct Trade {
+    #[persist(skip)]
+    price: f64,
+    #[persist_timestamp(unit = "s")]
+    time: i64,
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use dixit_persist_macros::Persist;
+
+#[derive(Persist)]
+struct Message {
+    #[persist(compress = "zstd")]
+    size: Option<f64>,
+    #[persist(compress = "gzip")]
+    raw: String,
+    #[persist(intern, compress = "zstd")]
+    topic: String,
+}
+
+fn main() {}
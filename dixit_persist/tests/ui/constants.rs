@@ -0,0 +1,18 @@
+use dixit_persist_macros::Persist;
+
+#[derive(Persist)]
+#[persist(const(exchange = "coinbase", price = "0"))]
+struct Trade {
+    price: f64,
+    #[persist(const(channel = "trades"))]
+    size: f64,
+}
+
+#[derive(Persist)]
+#[persist(const(exchange = "coinbase"))]
+enum Side {
+    Buy,
+    Sell,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use dixit_persist_macros::Persist;
+
+#[derive(Persist)]
+#[persist(flatten)]
+struct Header {
+    exchange: String,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use dixit_persist_macros::Persist;
+
+#[derive(Persist)]
+struct Level<T> {
+    price: T,
+}
+
+fn main() {}
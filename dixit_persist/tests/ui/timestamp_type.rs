@@ -0,0 +1,10 @@
+use dixit_persist_macros::Persist;
+
+#[derive(Persist)]
+struct Trade {
+    price: f64,
+    #[persist_timestamp(unit = "ms")]
+    time: Option<u32>,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use dixit_persist_macros::Persist;
+
+#[derive(Persist)]
+union Price {
+    bid: f64,
+    ask: f64,
+}
+
+fn main() {}
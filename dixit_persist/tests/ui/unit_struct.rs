@@ -0,0 +1,6 @@
+use dixit_persist_macros::Persist;
+
+#[derive(Persist)]
+struct Heartbeat;
+
+fn main() {}
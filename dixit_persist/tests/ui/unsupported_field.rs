@@ -0,0 +1,13 @@
+use dixit_persist_macros::Persist;
+
+struct Venue {
+    name: String,
+}
+
+#[derive(Persist)]
+struct Trade {
+    price: f64,
+    venue: Venue,
+}
+
+fn main() {}
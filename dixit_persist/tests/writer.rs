@@ -1,24 +1,61 @@
-use std::{env, sync::LazyLock};
+use std::{env, path::PathBuf, sync::LazyLock};
 
 mod orderbook;
 
-static TMP_FOLDER: LazyLock<String> = LazyLock::new(|| {
+static TMP_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| {
     let mut path_buf = env::current_dir().unwrap();
     path_buf.push("target");
     //path_buf.push("debug");
     path_buf.push("test");
-    path_buf.into_os_string().into_string().expect("invalid path")
+    path_buf
 });
 
 #[cfg(test)]
 mod tests {
     use anyhow::{Ok, Result};
 
-    use dixit_persist::{config::PersistConfig, writer::TableWriter};
+    use std::collections::{HashMap, HashSet};
+    use std::io::Read;
+
+    use dixit_persist::{
+        compression,
+        config::{Format, OverwriteMode, PersistConfig},
+        csv::{CsvOptions, Quoting},
+        dictionary::{self, ZstdDictionary},
+        dynamic::{ColumnType, Descriptor, DynPersistable, DynTableWriter, DynamicRow, DynamicValue, SchemaBuilder},
+        error::PersistError,
+        index::{FileRange, TableIndex},
+        memory::MemoryTableWriter,
+        merge::MergeReader,
+        output::FileOutput,
+        pool::BufferPool,
+        reader::{Comparison, Predicate, TableReader},
+        retry::RetryPolicy,
+        row::RowBuffer,
+        writer::TableWriter,
+        Persistable,
+    };
+    use dixit_persist_macros::{Persist, Readable};
+    use parquet::{
+        basic::{LogicalType, Repetition},
+        errors::ParquetError,
+        file::reader::{FileReader, SerializedFileReader},
+        record::Field,
+        record::RowAccessor,
+        schema::types::TypePtr,
+    };
 
     use crate::orderbook::OrderBook;
     use crate::TMP_FOLDER;
 
+    /// Config of a table starting empty, whatever the previous runs left in it.
+    fn purged(table: &str) -> PersistConfig {
+        PersistConfig {
+            overwrite: OverwriteMode::Purge,
+            ..PersistConfig::new(&TMP_FOLDER, table)
+        }
+    }
+
     #[test]
     fn test_random_simple_instance() {
         let instance = OrderBook::random_instance(100, 200, 50.0);
@@ -31,7 +68,7 @@ mod tests {
 
     #[test]
     fn test_persist_macros() -> Result<()> {
-        let config = PersistConfig::new(&TMP_FOLDER, "orderbook");
+        let config = purged("orderbook");
         let mut writer = TableWriter::new("orderbook", &config)?;
 
         let mut instance = OrderBook::random_instance(100, 200, 50.0);
@@ -46,4 +83,2675 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_rows_per_row_group() -> Result<()> {
+        let config = PersistConfig {
+            rows_per_row_group: 4,
+            ..purged("orderbook_row_groups")
+        };
+        let mut writer = TableWriter::new("orderbook_row_groups", &config)?;
+
+        let mut instance = OrderBook::random_instance(100, 200, 50.0);
+        for _ in 0..10 {
+            instance = instance.tick();
+            writer.begin()?.record(&instance)?.end()?;
+        }
+        writer.flush()?;
+
+        let file = std::fs::File::open(writer.current_file_path.join("000000000.parquet"))?;
+        let metadata = SerializedFileReader::new(file)?.metadata().clone();
+        let rows = metadata
+            .row_groups()
+            .iter()
+            .map(|group| group.num_rows())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![4, 4, 2], rows);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rows_per_file() -> Result<()> {
+        let config = PersistConfig {
+            format: Format::Delta,
+            rows_per_file: 25,
+            ..purged("orderbook_open_file")
+        };
+        let mut writer = TableWriter::new("orderbook_open_file", &config)?;
+
+        let mut instance = OrderBook::random_instance(100, 200, 50.0);
+        for _ in 0..5 {
+            for _ in 0..10 {
+                instance = instance.tick();
+                writer.begin()?.record(&instance)?.end()?;
+            }
+            writer.flush()?;
+        }
+        // the second file is still open, only the first one is committed
+        let log = writer.current_file_path.join("_delta_log");
+        assert!(!log.join("00000000000000000001.json").exists());
+        writer.close()?;
+
+        let row_groups = |name: &str| -> Result<Vec<i64>> {
+            let file = std::fs::File::open(writer.current_file_path.join(name))?;
+            let metadata = SerializedFileReader::new(file)?.metadata().clone();
+            Ok(metadata.row_groups().iter().map(|group| group.num_rows()).collect())
+        };
+        assert_eq!(vec![10, 10, 10], row_groups("000000000.parquet")?);
+        assert_eq!(vec![10, 10], row_groups("000000001.parquet")?);
+        let second = std::fs::read_to_string(log.join("00000000000000000001.json"))?;
+        assert!(second.contains("\\\"numRecords\\\":20"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksum() -> Result<()> {
+        use dixit_persist::checksum::{sidecar_path, verify};
+
+        let config = PersistConfig {
+            checksum: true,
+            ..purged("orderbook_checksum")
+        };
+        let mut writer = TableWriter::new("orderbook_checksum", &config)?;
+        let mut instance = OrderBook::random_instance(100, 200, 50.0);
+        for _ in 0..2 {
+            for _ in 0..10 {
+                instance = instance.tick();
+                writer.begin()?.record(&instance)?.end()?;
+            }
+            writer.flush()?;
+        }
+        let first = writer.current_file_path.join("000000000.parquet");
+        let second = writer.current_file_path.join("000000001.parquet");
+        let sidecar = std::fs::read_to_string(sidecar_path(&first))?;
+        assert!(sidecar.ends_with("  000000000.parquet\n"), "{sidecar}");
+
+        let report = verify(&writer.current_file_path)?;
+        assert!(report.is_valid(), "{:?}", report.corrupted);
+        assert_eq!((2, 2), (report.files, report.checksums));
+
+        // a truncated file fails both its footer and its checksum, a sidecar alone is reported too
+        let content = std::fs::read(&second)?;
+        std::fs::write(&second, &content[..content.len() / 2])?;
+        std::fs::write(
+            writer.current_file_path.join("000000002.parquet.sha256"),
+            "0  000000002.parquet\n",
+        )?;
+        let report = verify(&writer.current_file_path)?;
+        let corrupted: Vec<_> = report.corrupted.iter().map(|c| c.path.clone()).collect();
+        assert_eq!(
+            vec![
+                second.clone(),
+                second,
+                writer.current_file_path.join("000000002.parquet.sha256")
+            ],
+            corrupted
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_finalize_markers() -> Result<()> {
+        use dixit_persist::checksum::verify;
+        use dixit_persist::finalize::{is_finalized, marker_path};
+
+        let config = PersistConfig {
+            finalize_markers: true,
+            checksum: true,
+            rows_per_file: 20,
+            ..purged("orderbook_finalize")
+        };
+        let mut writer = TableWriter::new("orderbook_finalize", &config)?;
+        let directory = writer.current_file_path.clone();
+        let first = directory.join("000000000.parquet");
+        let record = |writer: &mut TableWriter| -> Result<()> {
+            for _ in 0..10 {
+                writer
+                    .begin()?
+                    .record(&OrderBook::random_instance(100, 200, 50.0))?
+                    .end()?;
+            }
+            writer.flush()?;
+            Ok(())
+        };
+        let files = |directory: &std::path::Path| -> Result<Vec<std::path::PathBuf>> {
+            let reader: TableReader = TableReader::open(directory)?;
+            Ok(reader.files().to_vec())
+        };
+
+        // the open file is not read until it is closed
+        record(&mut writer)?;
+        assert!(first.exists() && !is_finalized(&first));
+        assert!(files(&directory)?.is_empty());
+
+        // then marked after its checksum
+        record(&mut writer)?;
+        assert!(marker_path(&first).exists());
+        assert_eq!(vec![first.clone()], files(&directory)?);
+        let reader: TableReader = TableReader::open(&directory)?;
+        assert_eq!(20, reader.count());
+
+        // a file left partial by a crash is skipped, and reported by the verification
+        let partial = directory.join("000000009.parquet");
+        std::fs::write(&partial, b"PAR1")?;
+        assert_eq!(vec![first], files(&directory)?);
+        let report = verify(&directory)?;
+        let corrupted = report.corrupted.iter().map(|c| c.reason.as_str()).collect::<Vec<_>>();
+        assert_eq!(partial, report.corrupted[0].path);
+        assert_eq!("not finalized, no marker", corrupted[0]);
+        drop(writer);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_naming() -> Result<()> {
+        use dixit_persist::config::FileNaming;
+
+        use std::path::PathBuf;
+
+        let write = |config: &PersistConfig| -> Result<PathBuf> {
+            let mut writer = TableWriter::new("orderbook_naming", config)?;
+            writer
+                .begin()?
+                .record(&OrderBook::random_instance(100, 200, 50.0))?
+                .end()?;
+            writer.flush()?;
+            Ok(writer.current_file_path.clone())
+        };
+        let files = |directory: &PathBuf| -> Result<Vec<String>> {
+            let mut files = std::fs::read_dir(directory)?
+                .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                .collect::<Result<Vec<_>>>()?;
+            files.retain(|file| file != ".lock");
+            files.sort();
+            Ok(files)
+        };
+
+        // kept tables continue after their last file, gaps included
+        let directory = write(&purged("orderbook_naming"))?;
+        std::fs::rename(directory.join("000000000.parquet"), directory.join("000000007.parquet"))?;
+        let keep = PersistConfig {
+            overwrite: OverwriteMode::Append,
+            ..purged("orderbook_naming")
+        };
+        write(&keep)?;
+        assert_eq!(vec!["000000007.parquet", "000000008.parquet"], files(&directory)?);
+
+        for file_naming in [FileNaming::Timestamp, FileNaming::Uuid] {
+            write(&PersistConfig {
+                file_naming,
+                ..keep.clone()
+            })?;
+        }
+        let files = files(&directory)?;
+        assert_eq!(4, files.len());
+        let timestamp = format!("Z-{}.parquet", std::process::id());
+        assert_eq!(1, files.iter().filter(|file| file.ends_with(&timestamp)).count());
+        // uuids are 36 characters long, hyphens included
+        assert_eq!(
+            1,
+            files.iter().filter(|file| file.len() == 36 + ".parquet".len()).count()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_overwrite() -> Result<()> {
+        let write = |overwrite: OverwriteMode| -> Result<TableWriter> {
+            let config = PersistConfig {
+                overwrite,
+                ..PersistConfig::new(&TMP_FOLDER, "orderbook_overwrite")
+            };
+            let mut writer = TableWriter::new("orderbook_overwrite", &config)?;
+            writer
+                .begin()?
+                .record(&OrderBook::random_instance(100, 200, 50.0))?
+                .end()?;
+            writer.flush()?;
+            Ok(writer)
+        };
+
+        let directory = write(OverwriteMode::Purge)?.current_file_path.clone();
+        write(OverwriteMode::Append)?;
+        assert!(directory.join("000000001.parquet").exists());
+
+        let e = write(OverwriteMode::FailIfExists).err().unwrap();
+        assert!(e.to_string().contains("is not empty"), "{e}");
+        assert!(directory.join("000000001.parquet").exists());
+
+        write(OverwriteMode::Purge)?;
+        assert!(!directory.join("000000001.parquet").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock() -> Result<()> {
+        use std::time::Duration;
+
+        let config = purged("orderbook_lock");
+        let mut writer = TableWriter::new("orderbook_lock", &config)?;
+        writer
+            .begin()?
+            .record(&OrderBook::random_instance(100, 200, 50.0))?
+            .end()?;
+        writer.flush()?;
+
+        // a second writer fails without deleting the files of the first one
+        let e = TableWriter::new("orderbook_lock", &config).err().unwrap();
+        assert!(e.to_string().contains("is locked by another writer"), "{e}");
+        assert!(
+            matches!(
+                e.downcast_ref(),
+                Some(PersistError::DirectoryLocked { waited: None, .. })
+            ),
+            "{e}"
+        );
+        assert!(writer.current_file_path.join("000000000.parquet").exists());
+
+        let waiting = PersistConfig {
+            overwrite: OverwriteMode::Append,
+            lock_timeout: Some(Duration::from_secs(10)),
+            ..config
+        };
+        let release = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            drop(writer);
+        });
+        let writer = TableWriter::new("orderbook_lock", &waiting)?;
+        release.join().unwrap();
+        assert!(writer.current_file_path.join("000000000.parquet").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_sources() {
+        // the io errors of the parquet writers are kept as io errors, a full disk told apart
+        let full = std::io::Error::from_raw_os_error(if cfg!(windows) { 112 } else { 28 });
+        let e = PersistError::from(ParquetError::External(Box::new(full)));
+        assert!(matches!(e, PersistError::DiskFull(_)), "{e}");
+        let e = PersistError::from(std::io::Error::from(std::io::ErrorKind::PermissionDenied));
+        assert!(matches!(e, PersistError::Io(_)), "{e}");
+        let e = PersistError::from(ParquetError::General("corrupt".to_owned()));
+        assert!(matches!(e, PersistError::Parquet(_)), "{e}");
+        assert!(std::error::Error::source(&e).is_some());
+    }
+
+    #[test]
+    fn test_delta_log() -> Result<()> {
+        let config = PersistConfig {
+            format: Format::Delta,
+            delta_checkpoint_interval: 2,
+            ..purged("orderbook_delta")
+        };
+        let mut writer = TableWriter::new("orderbook_delta", &config)?;
+
+        let mut instance = OrderBook::random_instance(100, 200, 50.0);
+        let mut first_ts = None;
+        for _ in 0..3 {
+            for _ in 0..5 {
+                instance = instance.tick();
+                first_ts.get_or_insert(instance.exchange_ts);
+                writer.begin()?.record(&instance)?.end()?;
+            }
+            writer.flush()?;
+        }
+
+        let log = writer.current_file_path.join("_delta_log");
+        let first = std::fs::read_to_string(log.join("00000000000000000000.json"))?;
+        let second = std::fs::read_to_string(log.join("00000000000000000001.json"))?;
+        assert!(first.contains("\"protocol\"") && first.contains("\"metaData\""));
+        assert!(first.contains("\"path\":\"000000000.parquet\""));
+        assert!(!second.contains("\"metaData\""));
+        assert!(second.contains("\"path\":\"000000001.parquet\""));
+        assert!(second.contains("\\\"numRecords\\\":5"));
+
+        // the nanosecond timestamps are written in microseconds
+        let file = SerializedFileReader::new(std::fs::File::open(writer.current_file_path.join("000000000.parquet"))?)?;
+        let column = file
+            .metadata()
+            .file_metadata()
+            .schema_descr()
+            .columns()
+            .iter()
+            .find(|column| column.name() == "exchange_ts")
+            .map(|column| column.logical_type());
+        assert!(
+            matches!(
+                column,
+                Some(Some(LogicalType::Timestamp {
+                    unit: parquet::basic::TimeUnit::MICROS(_),
+                    ..
+                }))
+            ),
+            "{column:?}"
+        );
+        let row = file.get_row_iter(None)?.next().unwrap()?;
+        let (_, exchange_ts) = row.get_column_iter().find(|(name, _)| *name == "exchange_ts").unwrap();
+        assert_eq!(&Field::TimestampMicros((first_ts.unwrap() / 1000) as i64), exchange_ts);
+
+        // every second commit is checkpointed with the protocol, the metadata and the three files
+        assert!(!log.join("00000000000000000001.checkpoint.parquet").exists());
+        let last = std::fs::read_to_string(log.join("_last_checkpoint"))?;
+        assert_eq!(
+            serde_json::json!({"version": 2, "size": 5}),
+            serde_json::from_str::<serde_json::Value>(&last)?
+        );
+        let checkpoint = std::fs::File::open(log.join("00000000000000000002.checkpoint.parquet"))?;
+        let checkpoint = SerializedFileReader::new(checkpoint)?;
+        assert_eq!(5, checkpoint.metadata().file_metadata().num_rows());
+        let paths = checkpoint
+            .get_row_iter(None)?
+            .map(|row| {
+                let row = row?;
+                let add = row
+                    .get_column_iter()
+                    .find(|(name, _)| *name == "add")
+                    .map(|(_, add)| add.clone());
+                Ok(match add {
+                    Some(Field::Group(add)) => Some(add.get_string(0)?.clone()),
+                    _ => None,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let expected = vec![
+            None,
+            None,
+            Some("000000000.parquet".to_owned()),
+            Some("000000001.parquet".to_owned()),
+            Some("000000002.parquet".to_owned()),
+        ];
+        assert_eq!(expected, paths);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_writer_clock() -> Result<()> {
+        use dixit_persist::clock::ManualClock;
+        use dixit_persist::config::FileNaming;
+        use std::sync::Arc;
+
+        let config = PersistConfig {
+            format: Format::Delta,
+            file_naming: FileNaming::Timestamp,
+            ..purged("orderbook_clock")
+        };
+        let clock = Arc::new(ManualClock::new("2024-03-01T10:00:00Z".parse()?));
+        let mut writer = TableWriter::new("orderbook_clock", &config)?.with_clock(clock);
+        writer
+            .begin()?
+            .record(&OrderBook::random_instance(100, 200, 50.0))?
+            .end()?;
+        writer.flush()?;
+
+        // both the file name and the commit are stamped with the time of the clock
+        let file = format!("20240301T100000.000000000Z-{}.parquet", std::process::id());
+        assert!(writer.current_file_path.join(&file).exists());
+        let log = std::fs::read_to_string(writer.current_file_path.join("_delta_log/00000000000000000000.json"))?;
+        assert!(log.contains(&format!("\"path\":\"{file}\"")));
+        assert!(log.contains("\"timestamp\":1709287200000"));
+        assert!(log.contains("\"createdTime\":1709287200000"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_iceberg_metadata() -> Result<()> {
+        let config = PersistConfig {
+            format: Format::Iceberg,
+            ..purged("orderbook_iceberg")
+        };
+        let mut writer = TableWriter::new("orderbook_iceberg", &config)?;
+
+        let mut instance = OrderBook::random_instance(100, 200, 50.0);
+        for _ in 0..2 {
+            for _ in 0..5 {
+                instance = instance.tick();
+                writer.begin()?.record(&instance)?.end()?;
+            }
+            writer.flush()?;
+        }
+
+        let metadata = writer.current_file_path.join("metadata");
+        assert_eq!("2", std::fs::read_to_string(metadata.join("version-hint.text"))?);
+        let table = std::fs::read_to_string(metadata.join("v2.metadata.json"))?;
+        assert!(table.contains("\"current-snapshot-id\":2"));
+        assert!(table.contains("\"parent-snapshot-id\":1"));
+        assert!(metadata.join("00000000000000000001-m0.avro").exists());
+        assert!(std::fs::read(metadata.join("snap-00000000000000000002.avro"))?.starts_with(b"Obj\x01"));
+
+        // the nanosecond timestamps are written in microseconds, as declared in the table schema
+        let table: serde_json::Value = serde_json::from_str(&table)?;
+        let field = table["schema"]["fields"]
+            .as_array()
+            .and_then(|fields| fields.iter().find(|field| field["name"] == "exchange_ts"));
+        assert_eq!(Some("timestamptz"), field.and_then(|field| field["type"].as_str()));
+        let file = SerializedFileReader::new(std::fs::File::open(writer.current_file_path.join("000000000.parquet"))?)?;
+        let column = file
+            .metadata()
+            .file_metadata()
+            .schema_descr()
+            .columns()
+            .iter()
+            .find(|column| column.name() == "exchange_ts")
+            .map(|column| column.logical_type());
+        assert!(
+            matches!(
+                column,
+                Some(Some(LogicalType::Timestamp {
+                    is_adjusted_to_u_t_c: true,
+                    unit: parquet::basic::TimeUnit::MICROS(_),
+                }))
+            ),
+            "{column:?}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jsonl_table_format() -> Result<()> {
+        let mut config = purged("orderbook_jsonl");
+        config.table_formats.insert("orderbook_jsonl".to_owned(), Format::Jsonl);
+        let mut writer = TableWriter::new("orderbook_jsonl", &config)?;
+
+        let mut instance = OrderBook::random_instance(100, 200, 50.0);
+        for _ in 0..3 {
+            instance = instance.tick();
+            writer.begin()?.record(&instance)?.end()?;
+        }
+        writer.flush()?;
+
+        let lines = std::fs::read_to_string(writer.current_file_path.join("000000000.jsonl"))?;
+        assert_eq!(3, lines.lines().count());
+        for line in lines.lines() {
+            let row: serde_json::Value = serde_json::from_str(line)?;
+            assert_eq!(Some(100), row["exchange_id"].as_u64());
+            assert!(row["tob_0_price"].is_f64());
+            assert!(row.get("ignore").is_some());
+            assert!(row["exchange_ts"].as_str().is_some_and(|time| time.ends_with("+00:00")));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_format_pattern() {
+        let mut config = PersistConfig::default();
+        config.table_formats.insert("okx/trades_*".to_owned(), Format::Jsonl);
+        config
+            .table_formats
+            .insert("okx/*/ticker_*.csv".to_owned(), Format::Csv);
+        config.table_formats.insert("okx/funding".to_owned(), Format::Delta);
+
+        assert_eq!(Format::Jsonl, config.format("okx/trades_2024-03-01"));
+        assert_eq!(Format::Csv, config.format("okx/spot/ticker_2024-03-01.csv"));
+        assert_eq!(Format::Delta, config.format("okx/funding"));
+        assert_eq!(Format::Parquet, config.format("okx/funding_2024-03-01"));
+        assert_eq!(Format::Parquet, config.format("bybit/trades_2024-03-01"));
+        assert_eq!(Format::Parquet, config.format("okx/spot/ticker_2024-03-01"));
+    }
+
+    #[test]
+    fn test_to_json() -> Result<()> {
+        let instance = OrderBook::random_instance(100, 200, 50.0);
+        let json = dixit_persist::jsonl::to_json(&instance)?;
+        let row: serde_json::Value = serde_json::from_slice(&json)?;
+        assert_eq!(Some(100), row["exchange_id"].as_u64());
+        assert_eq!(Some(200), row["symbol_id"].as_u64());
+        assert!(!json.ends_with(b"\n"));
+
+        Ok(())
+    }
+
+    #[derive(Persist)]
+    #[persist(transparent)]
+    struct Price(f64);
+
+    #[derive(Persist)]
+    #[persist(transparent)]
+    struct Venue {
+        name: String,
+    }
+
+    #[derive(Persist)]
+    struct Quote {
+        venue: Venue,
+        bid: Price,
+        ask: Option<Price>,
+    }
+
+    #[test]
+    fn test_transparent() -> Result<()> {
+        let mut fields = vec![];
+        Quote::schema(&mut fields, None, None, None);
+        let names: Vec<_> = fields.iter().map(|field| field.name()).collect();
+        assert_eq!(vec!["venue", "bid", "ask"], names);
+
+        let quote = Quote {
+            venue: Venue {
+                name: "test".to_owned(),
+            },
+            bid: Price(99.5),
+            ask: None,
+        };
+        let row: serde_json::Value = serde_json::from_slice(&dixit_persist::jsonl::to_json(&quote)?)?;
+        assert_eq!(Some("test"), row["venue"].as_str());
+        assert_eq!(Some(99.5), row["bid"].as_f64());
+        assert!(row["ask"].is_null());
+
+        Ok(())
+    }
+
+    #[derive(Persist)]
+    struct Header {
+        exchange: String,
+        channel: String,
+        recv_ts: u64,
+    }
+
+    #[derive(Persist)]
+    struct Fill {
+        #[persist(flatten)]
+        header: Header,
+        price: f64,
+    }
+
+    #[derive(Persist)]
+    struct Book {
+        #[persist(flatten)]
+        header: Header,
+        bid: Price,
+        #[persist(flatten)]
+        quote: Quote,
+    }
+
+    #[test]
+    fn test_flatten() -> Result<()> {
+        let mut fields = vec![];
+        Fill::schema(&mut fields, None, None, None);
+        let names: Vec<_> = fields.iter().map(|field| field.name()).collect();
+        assert_eq!(vec!["exchange", "channel", "recv_ts", "price"], names);
+
+        // nested in a prefixed field, the flattened columns take the prefix of the field
+        let mut fields = vec![];
+        Option::<Fill>::schema(&mut fields, Some("fill"), None, None);
+        let names: Vec<_> = fields.iter().map(|field| field.name()).collect();
+        assert_eq!(
+            vec!["fill_exchange", "fill_channel", "fill_recv_ts", "fill_price"],
+            names
+        );
+
+        // the flattened columns are checked against the other columns when the table is created
+        let config = purged("books_flatten");
+        let mut writer = TableWriter::new("books_flatten", &config)?;
+        let header = Header {
+            exchange: "test".to_owned(),
+            channel: "book".to_owned(),
+            recv_ts: 1,
+        };
+        let quote = Quote {
+            venue: Venue {
+                name: "test".to_owned(),
+            },
+            bid: Price(99.5),
+            ask: None,
+        };
+        let book = Book {
+            header,
+            bid: Price(99.0),
+            quote,
+        };
+        let e = writer.begin()?.record(&book).err().unwrap();
+        assert!(e.to_string().contains("duplicate column bid"), "{e}");
+
+        let fill = Fill {
+            header: book.header,
+            price: 99.5,
+        };
+        let row: serde_json::Value = serde_json::from_slice(&dixit_persist::jsonl::to_json(&fill)?)?;
+        assert_eq!(Some("book"), row["channel"].as_str());
+        assert_eq!(Some(99.5), row["price"].as_f64());
+
+        Ok(())
+    }
+
+    #[derive(Persist)]
+    #[persist(ignore_if = "Level::is_stale")]
+    struct Level {
+        #[persist(ignore_if = "Level::is_empty")]
+        price: f64,
+        quantity: f64,
+        stale: bool,
+    }
+
+    impl Level {
+        fn is_stale(&self) -> bool {
+            self.stale
+        }
+
+        fn is_empty(price: &f64) -> bool {
+            *price == 0.0
+        }
+    }
+
+    #[test]
+    fn test_ignore_if() -> Result<()> {
+        let mut config = purged("levels_jsonl");
+        config.format = Format::Jsonl;
+        let mut writer = TableWriter::new("levels_jsonl", &config)?;
+
+        for (price, stale) in [(1.0, false), (2.0, true), (0.0, false), (4.0, true)] {
+            let level = Level {
+                price,
+                quantity: 1.0,
+                stale,
+            };
+            writer.begin()?.record(&level)?.end()?;
+        }
+        writer.flush()?;
+
+        let lines = std::fs::read_to_string(writer.current_file_path.join("000000000.jsonl"))?;
+        let rows: Vec<serde_json::Value> = lines.lines().map(serde_json::from_str).collect::<Result<_, _>>()?;
+        assert_eq!(2, rows.len());
+        assert_eq!(Some(1.0), rows[0]["price"].as_f64());
+        assert!(rows[1]["price"].is_null());
+        assert!(rows.iter().all(|row| row["stale"] == false));
+
+        Ok(())
+    }
+
+    #[derive(Debug, Persist)]
+    enum Side {
+        Buy,
+        Sell,
+    }
+
+    #[derive(Debug, Persist)]
+    struct Order {
+        #[persist(intern)]
+        symbol: String,
+        side: Side,
+        #[persist(intern)]
+        venue: Option<String>,
+    }
+
+    #[test]
+    fn test_intern() -> Result<()> {
+        let config = purged("interned");
+        let mut writer = TableWriter::new("interned", &config)?;
+        for i in 0..10 {
+            let order = Order {
+                symbol: "BTC-USD".to_owned(),
+                side: if i % 2 == 0 { Side::Buy } else { Side::Sell },
+                venue: (i % 3 == 0).then(|| "coinbase".to_owned()),
+            };
+            writer.begin()?.record(&order)?.end()?;
+        }
+        writer.flush()?;
+
+        let file = std::fs::File::open(writer.current_file_path.join("000000000.parquet"))?;
+        let rows = SerializedFileReader::new(file)?
+            .get_row_iter(None)?
+            .map(|row| Ok(row?.to_string()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(10, rows.len());
+        assert_eq!(r#"{symbol: "BTC-USD", side: "Buy", venue: "coinbase"}"#, rows[0]);
+        assert_eq!(r#"{symbol: "BTC-USD", side: "Sell", venue: null}"#, rows[1]);
+
+        let json = dixit_persist::jsonl::to_json(&Order {
+            symbol: "ETH-USD".to_owned(),
+            side: Side::Sell,
+            venue: None,
+        })?;
+        assert_eq!(br#"{"symbol":"ETH-USD","side":"Sell","venue":null}"#, &json[..]);
+
+        Ok(())
+    }
+
+    #[derive(Debug, Persist)]
+    struct Leg {
+        size: Option<u32>,
+        tags: Vec<String>,
+    }
+
+    #[derive(Debug, Persist)]
+    struct Nullable {
+        nested: Option<Option<u32>>,
+        list: Option<Vec<u32>>,
+        map: Option<HashMap<String, u32>>,
+        set: HashSet<u32>,
+        items: Vec<Option<u32>>,
+        pair: Option<[Option<u32>; 2]>,
+        leg: Option<Option<Leg>>,
+    }
+
+    #[test]
+    fn test_nullability() -> Result<()> {
+        assert_eq!(
+            vec![
+                "nested INT32 None OPTIONAL",
+                "list BYTE_ARRAY Some(String) OPTIONAL",
+                "map BYTE_ARRAY Some(String) OPTIONAL",
+                "set BYTE_ARRAY Some(String) REQUIRED",
+                "items BYTE_ARRAY Some(String) REQUIRED",
+                "pair_0 INT32 None OPTIONAL",
+                "pair_1 INT32 None OPTIONAL",
+                "leg_size INT32 None OPTIONAL",
+                "leg_tags BYTE_ARRAY Some(String) OPTIONAL",
+            ],
+            Nullable::schema_columns()
+        );
+        assert_eq!(9, Nullable::field_count());
+
+        let rows = [
+            Nullable {
+                nested: None,
+                list: None,
+                map: None,
+                set: HashSet::new(),
+                items: vec![],
+                pair: None,
+                leg: None,
+            },
+            Nullable {
+                nested: Some(None),
+                list: Some(vec![]),
+                map: Some(HashMap::new()),
+                set: HashSet::from([3]),
+                items: vec![None, Some(1)],
+                pair: Some([None, Some(2)]),
+                leg: Some(None),
+            },
+            Nullable {
+                nested: Some(Some(3)),
+                list: Some(vec![1, 2]),
+                map: Some(HashMap::from_iter((0..20).map(|i| (format!("k{i:02}"), i)))),
+                set: HashSet::from_iter(0..20),
+                items: vec![Some(1)],
+                pair: Some([Some(1), Some(2)]),
+                leg: Some(Some(Leg {
+                    size: None,
+                    tags: vec![],
+                })),
+            },
+        ];
+        let config = purged("nullable");
+        let mut writer = TableWriter::new("nullable", &config)?;
+        for row in rows.iter() {
+            writer.begin()?.record(row)?.end()?;
+        }
+        writer.flush()?;
+
+        let file = std::fs::File::open(writer.current_file_path.join("000000000.parquet"))?;
+        let rows = SerializedFileReader::new(file)?
+            .get_row_iter(None)?
+            .map(|row| Ok(row?.to_string()))
+            .collect::<Result<Vec<_>>>()?;
+        // a None is null in every column, the empty containers are not
+        assert_eq!(
+            r#"{nested: null, list: null, map: null, set: "{}", items: "[]", pair_0: null, pair_1: null, leg_size: null, leg_tags: null}"#,
+            rows[0]
+        );
+        // the nested options collapse
+        assert_eq!(
+            r#"{nested: null, list: "[]", map: "{}", set: "{3}", items: "[None, Some(1)]", pair_0: null, pair_1: 2, leg_size: null, leg_tags: null}"#,
+            rows[1]
+        );
+        // the maps and sets are sorted
+        let keys = (0..20)
+            .map(|i| format!(r#""k{i:02}": {i}"#))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut items = (0..20).map(|i| i.to_string()).collect::<Vec<_>>();
+        items.sort();
+        assert_eq!(
+            format!(
+                r#"{{nested: 3, list: "[1, 2]", map: "{{{keys}}}", set: "{{{}}}", items: "[Some(1)]", pair_0: 1, pair_1: 2, leg_size: null, leg_tags: "[]"}}"#,
+                items.join(", ")
+            ),
+            rows[2]
+        );
+
+        Ok(())
+    }
+
+    #[derive(Debug, Persist)]
+    #[persist(const(exchange = "coinbase", channel = "rfq_match"))]
+    struct RfqMatch {
+        price: f64,
+        size: f64,
+    }
+
+    #[derive(Debug, Persist)]
+    struct RfqQuote {
+        id: u32,
+        last: Option<RfqMatch>,
+    }
+
+    #[test]
+    fn test_constants() -> Result<()> {
+        assert_eq!(
+            vec![
+                "exchange BYTE_ARRAY Some(String) REQUIRED",
+                "channel BYTE_ARRAY Some(String) REQUIRED",
+                "price DOUBLE None REQUIRED",
+                "size DOUBLE None REQUIRED",
+            ],
+            RfqMatch::schema_columns()
+        );
+        assert_eq!(
+            "last_exchange BYTE_ARRAY Some(String) OPTIONAL",
+            RfqQuote::schema_columns()[1]
+        );
+
+        let config = purged("constants");
+        let mut writer = TableWriter::new("constants", &config)?;
+        for id in 0..3 {
+            let quote = RfqQuote {
+                id,
+                last: (id > 0).then_some(RfqMatch {
+                    price: 37000.0,
+                    size: id as f64,
+                }),
+            };
+            writer.begin()?.record(&quote)?.end()?;
+        }
+        writer.flush()?;
+
+        let file = std::fs::File::open(writer.current_file_path.join("000000000.parquet"))?;
+        let rows = SerializedFileReader::new(file)?
+            .get_row_iter(None)?
+            .map(|row| Ok(row?.to_string()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            r#"{id: 0, last_exchange: null, last_channel: null, last_price: null, last_size: null}"#,
+            rows[0]
+        );
+        assert_eq!(
+            r#"{id: 2, last_exchange: "coinbase", last_channel: "rfq_match", last_price: 37000.0, last_size: 2.0}"#,
+            rows[2]
+        );
+
+        let json = dixit_persist::jsonl::to_json(&RfqMatch { price: 1.5, size: 2.0 })?;
+        assert_eq!(
+            br#"{"exchange":"coinbase","channel":"rfq_match","price":1.5,"size":2.0}"#,
+            &json[..]
+        );
+
+        Ok(())
+    }
+
+    #[derive(Debug, Persist)]
+    struct Note {
+        id: u32,
+        text: String,
+    }
+
+    #[test]
+    fn test_strings() -> Result<()> {
+        let config = purged("strings");
+        let mut writer = TableWriter::new("strings", &config)?;
+        // values larger than the arena blocks and values spanning two blocks
+        let notes = (0..8)
+            .map(|id| Note {
+                id,
+                text: id.to_string().repeat(20_000 * (id as usize % 3) + 1),
+            })
+            .collect::<Vec<_>>();
+        for note in &notes {
+            writer.begin()?.record(note)?.end()?;
+        }
+        writer.flush()?;
+
+        let file = std::fs::File::open(writer.current_file_path.join("000000000.parquet"))?;
+        let texts = SerializedFileReader::new(file)?
+            .get_row_iter(None)?
+            .map(|row| Ok(row?.get_string(1)?.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(notes.into_iter().map(|note| note.text).collect::<Vec<_>>(), texts);
+
+        Ok(())
+    }
+
+    #[derive(Debug, Persist)]
+    struct Archived {
+        id: u32,
+        #[persist(compress = "zstd")]
+        raw: String,
+        #[persist(compress = "zstd")]
+        reason: Option<String>,
+        #[persist(compress = "zstd")]
+        frame: bytes::Bytes,
+    }
+
+    #[test]
+    fn test_compress() -> Result<()> {
+        let columns = Archived::schema_columns();
+        assert_eq!("raw BYTE_ARRAY None REQUIRED", columns[1]);
+        assert_eq!("reason BYTE_ARRAY None OPTIONAL", columns[2]);
+        assert_eq!("frame BYTE_ARRAY None REQUIRED", columns[3]);
+
+        let config = purged("compressed");
+        let mut writer = TableWriter::new("compressed", &config)?;
+        let raw = r#"{"channel":"level2","events":[{"type":"update","price":"37000.10"}]}"#.repeat(100);
+        for id in 0..4 {
+            let archived = Archived {
+                id,
+                raw: raw.clone(),
+                reason: (id % 2 == 0).then(|| "unknown message".to_owned()),
+                frame: bytes::Bytes::from(vec![id as u8; 1_000]),
+            };
+            writer.begin()?.record(&archived)?.end()?;
+        }
+        writer.flush()?;
+
+        let reader: TableReader = TableReader::open(&writer.current_file_path)?;
+        let rows = reader.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(4, rows.len());
+        for (id, row) in rows.iter().enumerate() {
+            let stored = row.get_bytes(1)?.data();
+            assert!(stored.len() < raw.len() / 10, "{} bytes stored", stored.len());
+            assert_eq!(raw.as_bytes(), compression::decompress(stored)?);
+            match row.get_bytes(2) {
+                std::result::Result::Ok(reason) => {
+                    assert_eq!(b"unknown message".to_vec(), compression::decompress(reason.data())?)
+                }
+                Err(_) => assert_eq!(1, id % 2),
+            }
+            assert_eq!(
+                vec![id as u8; 1_000],
+                compression::decompress(row.get_bytes(3)?.data())?
+            );
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_handle() -> Result<()> {
+        use dixit_persist::handle::TableWriterHandle;
+
+        let config = purged("handle");
+        let mut writer = TableWriter::new("handle", &config)?;
+        writer.auto_flush = false;
+        let handle = TableWriterHandle::new(writer);
+
+        let producers = (0..4)
+            .map(|id| {
+                let handle = handle.clone();
+                tokio::spawn(async move {
+                    for _ in 0..25 {
+                        let note = Note {
+                            id,
+                            text: id.to_string(),
+                        };
+                        handle.write(&note).await?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+        for producer in producers {
+            producer.await??;
+        }
+        assert_eq!(100, handle.stats().await.rows_buffered);
+
+        handle.flush().await?;
+        let stats = handle.stats().await;
+        assert_eq!(
+            (0, 100, 1),
+            (stats.rows_buffered, stats.rows_written, stats.files_written)
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_service() -> Result<()> {
+        use dixit_persist::service::{PersistCmd, PersistService, ServiceEvent, ServiceOptions};
+        use tokio::sync::{mpsc, oneshot};
+
+        let mut config = purged("service/a");
+        config.tables.insert("service/b".to_owned());
+        let options = ServiceOptions {
+            max_buffered_rows: 30,
+            ..Default::default()
+        };
+        let (service, tx) = PersistService::new(config, options);
+        let (events_tx, mut events) = mpsc::unbounded_channel();
+        let pool = BufferPool::new(2);
+        let service = tokio::spawn(service.with_events(events_tx).with_buffer_pool(pool).run());
+
+        for (table, rows) in [("service/a", 20), ("service/b", 15)] {
+            for id in 0..rows {
+                let record = Box::new(Note {
+                    id,
+                    text: table.to_owned(),
+                });
+                tx.send(PersistCmd::Write {
+                    table: table.to_owned(),
+                    record,
+                })
+                .await?;
+            }
+        }
+        let (reply, stats) = oneshot::channel();
+        tx.send(PersistCmd::Stats(reply)).await?;
+        let stats = stats.await?;
+        assert_eq!(35, stats.records);
+        // the 31st row is over the budget, flushing the 20 rows of the first table
+        assert_eq!(1, stats.budget_flushes);
+        assert_eq!(20, stats.tables["service/a"].rows_written);
+        assert_eq!(15, stats.tables["service/b"].rows_buffered);
+
+        let (reply, flushed) = oneshot::channel();
+        tx.send(PersistCmd::FlushTable("service/b".to_owned(), reply)).await?;
+        flushed.await??;
+        let (reply, rotated) = oneshot::channel();
+        tx.send(PersistCmd::Rotate("service/a".to_owned(), reply)).await?;
+        rotated.await??;
+        let (reply, unknown) = oneshot::channel();
+        tx.send(PersistCmd::Rotate("service/c".to_owned(), reply)).await?;
+        assert!(unknown.await?.is_err());
+        let (reply, stats) = oneshot::channel();
+        tx.send(PersistCmd::Stats(reply)).await?;
+        let stats = stats.await?;
+        assert_eq!(15, stats.tables["service/b"].rows_written);
+        assert_eq!(1, stats.tables["service/a"].files_written);
+        assert!(TMP_FOLDER.join("service/a/000000000.parquet").exists());
+
+        let expected = [
+            ServiceEvent::BudgetFlush {
+                table: "service/a".to_owned(),
+                rows: 20,
+            },
+            ServiceEvent::Flush {
+                table: "service/b".to_owned(),
+                rows: 15,
+            },
+            ServiceEvent::Rotation {
+                tables: vec!["service/a".to_owned()],
+            },
+        ];
+        for event in expected {
+            assert_eq!(Some(event), events.recv().await);
+        }
+
+        drop(tx);
+        let stats = service.await??;
+        assert_eq!(15, stats.tables["service/b"].rows_written);
+        assert!(TMP_FOLDER.join("service/b/000000000.parquet").exists());
+        // the buffers of both tables are handed back to the pool once the service returns
+        let pool = stats.buffer_pool.unwrap_or_default();
+        assert_eq!((2, 2, 20), (pool.recycled, pool.pooled, pool.high_water_rows));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_service_set_enabled() -> Result<()> {
+        use dixit_persist::service::{PersistCmd, PersistService, ServiceOptions};
+        use tokio::sync::{mpsc, oneshot};
+
+        let mut config = purged("service_enabled/a");
+        config.tables.insert("service_enabled/b".to_owned());
+        let (service, tx) = PersistService::new(config, ServiceOptions::default());
+        let service = tokio::spawn(service.run());
+        let set_enabled = |tx: mpsc::Sender<PersistCmd>, table: &str, enabled| {
+            let table = table.to_owned();
+            async move {
+                let (reply, result) = oneshot::channel();
+                tx.send(PersistCmd::SetEnabled(table, enabled, reply)).await?;
+                Ok(result.await??)
+            }
+        };
+        let write = |tx: mpsc::Sender<PersistCmd>, table: &str, id| {
+            let table = table.to_owned();
+            async move {
+                let record = Box::new(Note {
+                    id,
+                    text: table.clone(),
+                });
+                Ok(tx.send(PersistCmd::Write { table, record }).await?)
+            }
+        };
+
+        // disabled before its first record, and a table not in the config enabled
+        set_enabled(tx.clone(), "service_enabled/a", false).await?;
+        set_enabled(tx.clone(), "service_enabled/c", true).await?;
+        for table in [
+            "service_enabled/a",
+            "service_enabled/b",
+            "service_enabled/c",
+            "service_enabled/d",
+        ] {
+            write(tx.clone(), table, 0).await?;
+        }
+        // disabled once written
+        set_enabled(tx.clone(), "service_enabled/b", false).await?;
+        write(tx.clone(), "service_enabled/b", 1).await?;
+
+        let (reply, stats) = oneshot::channel();
+        tx.send(PersistCmd::Stats(reply)).await?;
+        let stats = stats.await?;
+        let tables = ["a", "b", "c", "d"].map(|table| {
+            let stats = stats.tables[&format!("service_enabled/{table}")];
+            (stats.enabled, stats.rows_buffered as u64 + stats.rows_written)
+        });
+        assert_eq!([(false, 0), (false, 1), (true, 1), (false, 0)], tables);
+
+        drop(tx);
+        service.await??;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_service_options() -> Result<()> {
+        use dixit_persist::service::{PersistCmd, PersistService, ServiceOptions};
+        use std::time::Duration;
+        use tokio::sync::oneshot;
+
+        let config = purged("service_options/a");
+        let (service, tx) = PersistService::new(config, ServiceOptions::default());
+        let service = tokio::spawn(service.run());
+
+        let record = Box::new(Note {
+            id: 0,
+            text: "rotated".to_owned(),
+        });
+        tx.send(PersistCmd::Write {
+            table: "service_options/a".to_owned(),
+            record,
+        })
+        .await?;
+        let options = ServiceOptions {
+            rotation_interval: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        tx.send(PersistCmd::SetOptions(options)).await?;
+        tokio::time::sleep(Duration::from_millis(120)).await;
+
+        let (reply, stats) = oneshot::channel();
+        tx.send(PersistCmd::Stats(reply)).await?;
+        let stats = stats.await?;
+        assert!(stats.rotations >= 1);
+        assert_eq!(1, stats.tables["service_options/a"].files_written);
+
+        drop(tx);
+        service.await??;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_service_column_stats() -> Result<()> {
+        use dixit_persist::service::{PersistCmd, PersistService, ServiceEvent, ServiceOptions};
+        use tokio::sync::{mpsc, oneshot};
+
+        let mut config = purged("service_stats/a");
+        config.column_stats = true;
+        let (events_tx, mut events) = mpsc::unbounded_channel();
+        let (service, tx) = PersistService::new(config, ServiceOptions::default());
+        let service = tokio::spawn(service.with_events(events_tx).run());
+
+        for id in 0..3 {
+            let record = Box::new(Note {
+                id,
+                text: "measured".to_owned(),
+            });
+            tx.send(PersistCmd::Write {
+                table: "service_stats/a".to_owned(),
+                record,
+            })
+            .await?;
+        }
+        let (reply, flushed) = oneshot::channel();
+        tx.send(PersistCmd::FlushTable("service_stats/a".to_owned(), reply))
+            .await?;
+        flushed.await??;
+
+        let Some(ServiceEvent::ColumnStats { table, stats }) = events.recv().await else {
+            panic!("column stats not reported first");
+        };
+        assert_eq!("service_stats/a", table);
+        assert_eq!(3, stats.rows);
+        assert_eq!((Some(0.0), Some(2.0)), (stats.columns[0].min, stats.columns[0].max));
+        assert_eq!(1, stats.columns[1].distinct);
+        assert!(matches!(events.recv().await, Some(ServiceEvent::Flush { rows: 3, .. })));
+
+        drop(tx);
+        service.await??;
+        // nothing left to flush on shutdown
+        assert_eq!(None, events.recv().await);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_service_clock() -> Result<()> {
+        use dixit_persist::clock::ManualClock;
+        use dixit_persist::service::{PersistCmd, PersistService, ServiceEvent, ServiceOptions};
+        use std::sync::Arc;
+        use std::time::Duration;
+        use tokio::sync::{mpsc, oneshot};
+
+        let clock = Arc::new(ManualClock::new("2024-03-01T10:59:00Z".parse()?));
+        let options = ServiceOptions {
+            rotation_interval: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        let (service, tx) = PersistService::new(purged("service_clock/a"), options);
+        let (events_tx, mut events) = mpsc::unbounded_channel();
+        let service = tokio::spawn(service.with_clock(clock.clone()).with_events(events_tx).run());
+
+        let record = Box::new(Note {
+            id: 0,
+            text: "rotated on the hour".to_owned(),
+        });
+        tx.send(PersistCmd::Write {
+            table: "service_clock/a".to_owned(),
+            record,
+        })
+        .await?;
+        // the clock stands still, whatever the time spent
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let (reply, stats) = oneshot::channel();
+        tx.send(PersistCmd::Stats(reply)).await?;
+        assert_eq!(0, stats.await?.rotations);
+
+        clock.advance(Duration::from_secs(60));
+        let rotation = tokio::time::timeout(Duration::from_secs(5), events.recv()).await?;
+        let expected = ServiceEvent::Rotation {
+            tables: vec!["service_clock/a".to_owned()],
+        };
+        assert_eq!(Some(expected), rotation);
+
+        // the next rotation is on the next hour
+        clock.advance(Duration::from_secs(1800));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        drop(tx);
+        let stats = service.await??;
+        assert_eq!(1, stats.rotations);
+        assert_eq!(1, stats.tables["service_clock/a"].files_written);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_service_flush_retry() -> Result<()> {
+        use dixit_persist::service::{PersistCmd, PersistService, ServiceOptions, ServiceStats};
+        use std::time::Duration;
+        use tokio::sync::{mpsc, oneshot};
+
+        async fn stats(tx: &mpsc::Sender<PersistCmd>) -> Result<ServiceStats> {
+            let (reply, stats) = oneshot::channel();
+            tx.send(PersistCmd::Stats(reply)).await?;
+            Ok(stats.await?)
+        }
+
+        let config = PersistConfig {
+            flush_retry: RetryPolicy::new(5, Duration::from_millis(200)),
+            ..purged("service_retry/a")
+        };
+        let (service, tx) = PersistService::new(config, ServiceOptions::default());
+        let service = tokio::spawn(service.run());
+        for id in 0..5 {
+            let record = Box::new(Note {
+                id,
+                text: "retried".to_owned(),
+            });
+            tx.send(PersistCmd::Write {
+                table: "service_retry/a".to_owned(),
+                record,
+            })
+            .await?;
+        }
+        assert_eq!(5, stats(&tx).await?.tables["service_retry/a"].rows_buffered);
+
+        // the table directory is gone, the flush fails without blocking the service
+        let directory = TMP_FOLDER.join("service_retry/a");
+        std::fs::remove_dir_all(&directory)?;
+        let (reply, flushed) = oneshot::channel();
+        tx.send(PersistCmd::FlushTable("service_retry/a".to_owned(), reply))
+            .await?;
+        flushed.await??;
+        let table = stats(&tx).await?.tables["service_retry/a"];
+        assert_eq!(
+            (5, 0, 1),
+            (table.rows_buffered, table.rows_written, table.flush_retries)
+        );
+
+        // the service retries on its own once the directory is back
+        std::fs::create_dir_all(&directory)?;
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        let table = stats(&tx).await?.tables["service_retry/a"];
+        assert_eq!((0, 5), (table.rows_buffered, table.rows_written));
+
+        drop(tx);
+        service.await??;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_service_lock_wait() -> Result<()> {
+        use dixit_persist::service::{PersistCmd, PersistService, ServiceOptions};
+        use std::time::Duration;
+        use tokio::sync::{mpsc, oneshot};
+
+        async fn rows_written(tx: &mpsc::Sender<PersistCmd>) -> Result<u64> {
+            let (reply, stats) = oneshot::channel();
+            tx.send(PersistCmd::Stats(reply)).await?;
+            Ok(stats.await?.tables["service_lock/a"].rows_written)
+        }
+
+        let writer = TableWriter::new("service_lock/a", &purged("service_lock/a"))?;
+        let config = PersistConfig {
+            overwrite: OverwriteMode::Append,
+            lock_timeout: Some(Duration::from_secs(10)),
+            ..purged("service_lock/a")
+        };
+        let (service, tx) = PersistService::new(config, ServiceOptions::default());
+        let service = tokio::spawn(service.run());
+        let record = Box::new(Note {
+            id: 1,
+            text: "waited".to_owned(),
+        });
+        tx.send(PersistCmd::Write {
+            table: "service_lock/a".to_owned(),
+            record,
+        })
+        .await?;
+
+        // the service waits for the lock without blocking the single thread of the runtime
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        drop(writer);
+        let (reply, flushed) = oneshot::channel();
+        tx.send(PersistCmd::FlushTable("service_lock/a".to_owned(), reply))
+            .await?;
+        flushed.await??;
+        assert_eq!(1, rows_written(&tx).await?);
+
+        drop(tx);
+        service.await??;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_paths() {
+        use dixit_persist::path::{file_uri, relative_path, table_path};
+        use std::path::Path;
+
+        let table = table_path(&TMP_FOLDER, "coinbase/rfq_match");
+        assert_eq!(TMP_FOLDER.join("coinbase").join("rfq_match"), table);
+        assert_eq!(
+            "coinbase/rfq_match",
+            relative_path(table.strip_prefix(&*TMP_FOLDER).unwrap())
+        );
+        if cfg!(unix) {
+            assert_eq!("file:///data/trades", file_uri(Path::new("/data/trades")));
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_windows_paths() -> Result<()> {
+        use dixit_persist::path::{file_uri, long_path};
+        use std::path::Path;
+
+        assert_eq!("file:///C:/data/trades", file_uri(Path::new(r"C:\data\trades")));
+        assert_eq!("file:///C:/data/trades", file_uri(Path::new(r"\\?\C:\data\trades")));
+        assert_eq!(
+            "file://server/share/trades",
+            file_uri(Path::new(r"\\server\share\trades"))
+        );
+        assert_eq!(
+            "file://server/share/trades",
+            file_uri(Path::new(r"\\?\UNC\server\share\trades"))
+        );
+        assert_eq!(
+            Path::new(r"\\?\C:\data\trades"),
+            long_path(Path::new(r"C:\data\trades"))?
+        );
+        assert_eq!(
+            Path::new(r"\\?\UNC\server\share\trades"),
+            long_path(Path::new(r"\\server\share\trades"))?
+        );
+
+        // a table deeper than the 260 characters of the legacy paths
+        let table = format!("long/{}", "x".repeat(250));
+        let mut writer = TableWriter::new(&table, &purged(&table))?;
+        writer
+            .begin()?
+            .record(&Note {
+                id: 1,
+                text: table.clone(),
+            })?
+            .end()?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_directory() -> Result<()> {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let directory = TMP_FOLDER.join(OsStr::from_bytes(b"non_utf8_\xff"));
+        let config = PersistConfig {
+            format: Format::Iceberg,
+            ..PersistConfig::new(&directory, "table")
+        };
+        let mut writer = TableWriter::new("table", &config)?;
+        writer
+            .begin()?
+            .record(&Note {
+                id: 1,
+                text: "text".to_owned(),
+            })?
+            .end()?;
+        writer.flush()?;
+        assert!(directory.join("table").join("000000000.parquet").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_fingerprint() {
+        dixit_persist::assert_schema_fingerprint!(OrderBook, 0xcce3cf33472e98f5);
+    }
+
+    #[derive(Persist)]
+    struct Inner {
+        bar: f64,
+    }
+
+    #[derive(Persist)]
+    struct Outer {
+        foo: Inner,
+        foo_bar: f64,
+    }
+
+    #[test]
+    fn test_duplicate_columns() -> Result<()> {
+        let config = purged("duplicate_columns");
+        let mut writer = TableWriter::new("duplicate_columns", &config)?;
+
+        let outer = Outer {
+            foo: Inner { bar: 1.0 },
+            foo_bar: 2.0,
+        };
+        let Err(e) = writer.begin()?.record(&outer) else {
+            panic!("duplicate column not detected");
+        };
+        assert!(e.to_string().contains("duplicate column foo_bar"), "{e}");
+
+        Ok(())
+    }
+
+    /// Number of columns of the schema of a type, as counted before `field_count` was derived.
+    fn schema_len<T: Persistable>() -> usize {
+        let mut fields = vec![];
+        T::schema(&mut fields, Some("any"), None, None);
+        fields.len()
+    }
+
+    #[test]
+    fn test_field_count() {
+        // derived from the fields, flattened, transparent, ignored or constant
+        assert_eq!(schema_len::<Quote>(), Quote::field_count());
+        assert_eq!(schema_len::<Book>(), Book::field_count());
+        assert_eq!(schema_len::<Level>(), Level::field_count());
+        assert_eq!(schema_len::<Order>(), Order::field_count());
+        assert_eq!(schema_len::<RfqQuote>(), RfqQuote::field_count());
+        assert_eq!(schema_len::<Archived>(), Archived::field_count());
+        assert_eq!(schema_len::<OrderBook>(), OrderBook::field_count());
+        assert_eq!(
+            schema_len::<Option<[(Price, std::time::Duration); 3]>>(),
+            Option::<[(Price, std::time::Duration); 3]>::field_count()
+        );
+        // counted from the schema when not overridden
+        assert_eq!(2, Mistyped::field_count());
+    }
+
+    /// Declares an int64 `size` column but records it as a string.
+    struct Mistyped;
+
+    impl Persistable for Mistyped {
+        fn schema(
+            fields: &mut Vec<TypePtr>,
+            _prefix: Option<&str>,
+            _repetition_override: Option<Repetition>,
+            _logical_type: Option<LogicalType>,
+        ) {
+            f64::schema(fields, Some("price"), None, None);
+            u64::schema(fields, Some("size"), None, None);
+        }
+
+        fn append(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
+            row.push(Field::Double(1.0));
+            row.push(Field::Str("1".to_owned()));
+            std::result::Result::Ok(())
+        }
+    }
+
+    #[test]
+    fn test_validate() -> Result<()> {
+        let mut config = purged("mistyped");
+        config.validate = true;
+        let mut writer = TableWriter::new("mistyped", &config)?;
+
+        let Err(e) = writer.begin()?.record(&Mistyped) else {
+            panic!("invalid value not detected");
+        };
+        assert!(e.to_string().contains("column size (index 1) at row 0"), "{e}");
+
+        // without validation the mismatch is only found when writing the parquet file
+        drop(writer);
+        let mut writer = TableWriter::new("mistyped", &purged("mistyped"))?;
+        writer.begin()?.record(&Mistyped)?.end()?;
+        let e = writer.flush().unwrap_err();
+        assert!(e.to_string().contains("column size (index 1) at row 0"), "{e}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_buffer_pool() -> Result<()> {
+        let pool = BufferPool::new(1);
+        let mut writer = TableWriter::new("pooled/a", &purged("pooled/a"))?.with_buffer_pool(pool.clone());
+        for id in 0..150 {
+            let text = format!("note {id}");
+            writer.begin()?.record(&Note { id, text })?.end()?;
+        }
+        drop(writer);
+        let stats = pool.stats();
+        assert_eq!(
+            (1, 0, 1, 1),
+            (stats.created, stats.reused, stats.recycled, stats.pooled)
+        );
+        // flushed every 100 rows
+        assert_eq!(100, stats.high_water_rows);
+        assert!(stats.capacity >= 200, "{stats:?}");
+        assert_eq!(stats.capacity, stats.high_water_capacity);
+
+        // the recycled buffer takes the settings of the next writer, not the ones of the previous writer
+        let config = PersistConfig {
+            validate: true,
+            ..purged("pooled/mistyped")
+        };
+        let mut writer = TableWriter::new("pooled/mistyped", &config)?.with_buffer_pool(pool.clone());
+        assert!(writer.begin()?.record(&Mistyped).is_err());
+        drop(writer);
+
+        let mut writer = TableWriter::new("pooled/b", &purged("pooled/b"))?.with_buffer_pool(pool.clone());
+        let other = TableWriter::new("pooled/c", &purged("pooled/c"))?.with_buffer_pool(pool.clone());
+        for id in 0..3 {
+            let text = format!("note {id}");
+            writer.begin()?.record(&Note { id, text })?.end()?;
+        }
+        drop(writer);
+        drop(other);
+        let stats = pool.stats();
+        assert_eq!(
+            (2, 2, 4, 1, 1),
+            (stats.created, stats.reused, stats.recycled, stats.freed, stats.pooled)
+        );
+        assert_eq!(100, stats.high_water_rows);
+
+        let reader: TableReader = TableReader::open(&TMP_FOLDER.join("pooled/b"))?;
+        let rows = reader.collect::<Result<Vec<_>, _>>()?;
+        let texts = rows
+            .iter()
+            .map(|row| row.get_string(1).cloned())
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(vec!["note 0", "note 1", "note 2"], texts);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_enabled() -> Result<()> {
+        // not in the tables of the config
+        let mut writer = TableWriter::new("toggled/b", &purged("toggled/a"))?;
+        assert!(!writer.is_enabled());
+        let note = |id| Note {
+            id,
+            text: "toggled".to_owned(),
+        };
+        writer.begin()?.record(&note(0))?.end()?;
+        assert_eq!(0, writer.stats().rows_buffered);
+
+        writer.set_enabled(true)?;
+        writer.begin()?.record(&note(1))?.end()?;
+        assert!(writer.stats().enabled);
+        assert_eq!(1, writer.stats().rows_buffered);
+
+        // disabling completes the open file, the next rows are dropped
+        writer.set_enabled(false)?;
+        writer.begin()?.record(&note(2))?.end()?;
+        let stats = writer.stats();
+        assert_eq!(
+            (0, 1, 1, false),
+            (
+                stats.rows_buffered,
+                stats.rows_written,
+                stats.files_written,
+                stats.enabled
+            )
+        );
+        drop(writer);
+        let reader: TableReader = TableReader::open(&TMP_FOLDER.join("toggled/b"))?;
+        let ids = reader
+            .map(|row| std::result::Result::Ok(row?.get_int(0)?))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(vec![1], ids);
+
+        // nowhere to write to without a directory
+        let mut writer = TableWriter::new("toggled/c", &PersistConfig::default())?;
+        assert!(!writer.is_enabled());
+        assert!(writer.set_enabled(true).is_err());
+        writer.set_enabled(false)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_gzip() -> Result<()> {
+        let mut config = purged("orderbook_csv");
+        config.format = Format::Csv;
+        config.csv = CsvOptions {
+            delimiter: ';',
+            quoting: Quoting::Always,
+            gzip: true,
+        };
+        let mut writer = TableWriter::new("orderbook_csv", &config)?;
+
+        let mut instance = OrderBook::random_instance(100, 200, 50.0);
+        for _ in 0..3 {
+            instance = instance.tick();
+            writer.begin()?.record(&instance)?.end()?;
+        }
+        writer.flush()?;
+
+        let file = std::fs::File::open(writer.current_file_path.join("000000000.csv.gz"))?;
+        let mut content = String::new();
+        flate2::read::GzDecoder::new(file).read_to_string(&mut content)?;
+        let mut lines = content.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("\"exchange_id\";\"symbol_id\";\"buys\""));
+        assert_eq!(3, lines.filter(|line| line.starts_with("\"100\";\"200\";")).count());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zstd_dictionary() -> Result<()> {
+        let mut config = purged("orderbook_zstd");
+        config.format = Format::Jsonl;
+        config.zstd_dictionary = true;
+        let mut writer = TableWriter::new("orderbook_zstd", &config)?;
+        let directory = writer.current_file_path.clone();
+        let mut instance = OrderBook::random_instance(100, 200, 50.0);
+
+        // too small to train a dictionary, compressed without
+        writer.begin()?.record(&instance)?.end()?;
+        writer.flush()?;
+        assert!(!directory.join(dictionary::DICTIONARY_FILE).exists());
+        let content = std::fs::read(directory.join("000000000.jsonl.zst"))?;
+        assert_eq!(
+            1,
+            String::from_utf8(dictionary::decompress(&content, None)?)?
+                .lines()
+                .count()
+        );
+
+        // the first large flush trains the dictionary of the table
+        for _ in 0..100 {
+            instance = instance.tick();
+            writer.begin()?.record(&instance)?.end()?;
+        }
+        writer.flush()?;
+        drop(writer);
+        let trained = std::fs::read(directory.join(dictionary::DICTIONARY_FILE))?;
+        let content = std::fs::read(directory.join("000000001.jsonl.zst"))?;
+        let lines = String::from_utf8(dictionary::decompress(&content, Some(&trained))?)?;
+        assert_eq!(100, lines.lines().count());
+        assert!(content.len() < ZstdDictionary::default().compress(lines.as_bytes())?.len());
+
+        // appending to the table reuses its dictionary
+        config.overwrite = OverwriteMode::Append;
+        let mut writer = TableWriter::new("orderbook_zstd", &config)?;
+        for _ in 0..100 {
+            instance = instance.tick();
+            writer.begin()?.record(&instance)?.end()?;
+        }
+        writer.flush()?;
+        assert_eq!(trained, std::fs::read(directory.join(dictionary::DICTIONARY_FILE))?);
+        let content = std::fs::read(directory.join("000000002.jsonl.zst"))?;
+        let lines = String::from_utf8(dictionary::decompress(&content, Some(&trained))?)?;
+        assert_eq!(100, lines.lines().count());
+        let row: serde_json::Value = serde_json::from_str(lines.lines().next().unwrap_or_default())?;
+        assert_eq!(Some(100), row["exchange_id"].as_u64());
+
+        Ok(())
+    }
+
+    #[derive(Persist)]
+    struct Message {
+        symbol: String,
+        price: f64,
+        size: Option<f64>,
+        raw: String,
+    }
+
+    fn message(price: f64) -> Message {
+        Message {
+            symbol: "BTC-USD".to_owned(),
+            price,
+            size: None,
+            raw: format!(r#"{{"symbol":"BTC-USD","price":{price}}}"#),
+        }
+    }
+
+    #[test]
+    fn test_column_filter() -> Result<()> {
+        let mut config = purged("messages_pruned");
+        config.validate = true;
+        config.exclude_columns.insert(
+            "messages_pruned".to_owned(),
+            vec!["raw".to_owned(), "missing".to_owned()],
+        );
+        let mut writer = TableWriter::new("messages_pruned", &config)?;
+        for price in [1.5, 2.5] {
+            writer.begin()?.record(&message(price))?.end()?;
+        }
+        writer.flush()?;
+
+        let reader =
+            SerializedFileReader::new(std::fs::File::open(writer.current_file_path.join("000000000.parquet"))?)?;
+        let schema = reader.metadata().file_metadata().schema_descr();
+        let columns: Vec<_> = schema.columns().iter().map(|column| column.name()).collect();
+        assert_eq!(vec!["symbol", "price", "size"], columns);
+        let rows = reader.get_row_iter(None)?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(2.5, rows[1].get_double(1)?);
+
+        // included then excluded, by table and column patterns
+        let mut config = purged("messages_jsonl");
+        config.format = Format::Jsonl;
+        config
+            .include_columns
+            .insert("messages_*".to_owned(), vec!["s*".to_owned(), "raw".to_owned()]);
+        config
+            .exclude_columns
+            .insert("messages_*".to_owned(), vec!["size".to_owned()]);
+        let mut writer = TableWriter::new("messages_jsonl", &config)?;
+        writer.begin()?.record(&message(1.5))?.end()?;
+        writer.flush()?;
+        let line = std::fs::read_to_string(writer.current_file_path.join("000000000.jsonl"))?;
+        assert!(line.starts_with(r#"{"symbol":"BTC-USD","raw":"#), "{line}");
+
+        // a table without columns is not created
+        let mut config = purged("messages_empty");
+        config
+            .include_columns
+            .insert("messages_empty".to_owned(), vec!["missing".to_owned()]);
+        let mut writer = TableWriter::new("messages_empty", &config)?;
+        let e = writer.begin()?.record(&message(1.5))?.end().unwrap_err();
+        assert!(e.to_string().contains("every column"), "{e}");
+
+        Ok(())
+    }
+
+    #[derive(Persist)]
+    struct Tick {
+        time: chrono::DateTime<chrono::Utc>,
+        symbol: String,
+        price: f64,
+        size: Option<f64>,
+    }
+
+    #[test]
+    fn test_column_stats() -> Result<()> {
+        let mut config = purged("ticks_stats");
+        config.column_stats = true;
+        config
+            .exclude_columns
+            .insert("ticks_stats".to_owned(), vec!["size".to_owned()]);
+        let mut writer = TableWriter::new("ticks_stats", &config)?;
+        let start: chrono::DateTime<chrono::Utc> = "2024-03-01T00:00:00Z".parse()?;
+        for i in 0..10 {
+            let tick = Tick {
+                time: start + chrono::TimeDelta::seconds(i),
+                symbol: if i % 2 == 0 { "BTC-USD" } else { "ETH-USD" }.to_owned(),
+                price: 100.0 + i as f64,
+                size: None,
+            };
+            writer.begin()?.record(&tick)?.end()?;
+        }
+        assert_eq!(None, writer.take_column_stats());
+        writer.flush()?;
+
+        let stats = writer.take_column_stats().expect("stats of the flush");
+        assert_eq!(10, stats.rows);
+        let columns: Vec<_> = stats.columns.iter().map(|column| column.column.as_str()).collect();
+        assert_eq!(vec!["time", "symbol", "price"], columns);
+        let (time, symbol, price) = (&stats.columns[0], &stats.columns[1], &stats.columns[2]);
+        assert_eq!(Some(start), time.min_time);
+        assert_eq!(Some(start + chrono::TimeDelta::seconds(9)), time.max_time);
+        assert_eq!(None, time.min);
+        assert_eq!(2, symbol.distinct);
+        assert_eq!((None, None), (symbol.min, symbol.max));
+        assert_eq!((Some(100.0), Some(109.0)), (price.min, price.max));
+        assert_eq!(10, price.distinct);
+        assert_eq!(0, price.nulls);
+        assert_eq!(None, writer.take_column_stats());
+
+        // nulls counted apart from the distinct values
+        let mut config = purged("ticks_nulls");
+        config.column_stats = true;
+        let mut writer = TableWriter::new("ticks_nulls", &config)?;
+        for size in [Some(1.0), None, None, Some(1.0)] {
+            let tick = Tick {
+                time: start,
+                symbol: "BTC-USD".to_owned(),
+                price: 100.0,
+                size,
+            };
+            writer.begin()?.record(&tick)?.end()?;
+        }
+        writer.close()?;
+        let stats = writer.take_column_stats().expect("stats of the flush");
+        let size = &stats.columns[3];
+        assert_eq!((2, 1), (size.nulls, size.distinct));
+        assert_eq!(0.5, size.null_rate(stats.rows));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_time_index() -> Result<()> {
+        let config = PersistConfig {
+            time_index: Some("time".to_owned()),
+            rows_per_file: 4,
+            ..purged("ticks_index")
+        };
+        let mut writer = TableWriter::new("ticks_index", &config)?;
+        let start: chrono::DateTime<chrono::Utc> = "2024-03-01T00:00:00Z".parse()?;
+        let minutes = |i: i64| start + chrono::TimeDelta::minutes(i);
+        // the ranges of the files span their flushes, whatever the order of the times
+        for times in [[1, 0], [3, 2], [5, 4]] {
+            for i in times {
+                let tick = Tick {
+                    time: minutes(i),
+                    symbol: "BTC-USD".to_owned(),
+                    price: 100.0,
+                    size: None,
+                };
+                writer.begin()?.record(&tick)?.end()?;
+            }
+            writer.flush()?;
+        }
+        let directory = writer.current_file_path.clone();
+        let index = TableIndex::load(&directory)?;
+        assert_eq!("time", index.column);
+        assert_eq!(
+            FileRange {
+                start: Some(minutes(0)),
+                end: Some(minutes(3)),
+                rows: 4,
+            },
+            index.files["000000000.parquet"]
+        );
+        // the open file is indexed once completed
+        assert_eq!(1, index.files.len());
+        writer.close()?;
+        let index = TableIndex::load(&directory)?;
+        assert_eq!(Some(minutes(4)), index.files["000000001.parquet"].start);
+        assert_eq!(2, index.files["000000001.parquet"].rows);
+
+        assert!(index.skips("000000000.parquet", "time", Some(minutes(4)), None));
+        assert!(index.skips("000000001.parquet", "time", None, Some(minutes(4))));
+        assert!(!index.skips("000000001.parquet", "time", Some(minutes(5)), Some(minutes(6))));
+        // the files missing from the index and the other columns are not skipped
+        assert!(!index.skips("000000002.parquet", "time", Some(minutes(4)), None));
+        assert!(!index.skips("000000000.parquet", "received", Some(minutes(4)), None));
+
+        // the index is extended by the next writers of the table
+        drop(writer);
+        let config = PersistConfig {
+            overwrite: OverwriteMode::Append,
+            ..config
+        };
+        let mut writer = TableWriter::new("ticks_index", &config)?;
+        let tick = Tick {
+            time: minutes(10),
+            symbol: "BTC-USD".to_owned(),
+            price: 100.0,
+            size: None,
+        };
+        writer.begin()?.record(&tick)?.end()?;
+        writer.close()?;
+        let index = TableIndex::load(&directory)?;
+        assert_eq!(3, index.files.len());
+        assert_eq!(Some(minutes(10)), index.files["000000002.parquet"].end);
+
+        Ok(())
+    }
+
+    /// A record of a plugin, its columns known at runtime only.
+    struct Plugin {
+        values: Vec<Field>,
+    }
+
+    impl DynPersistable for Plugin {
+        fn append(&self, row: &mut RowBuffer) -> Result<(), ParquetError> {
+            for value in self.values.iter() {
+                row.push(value.clone());
+            }
+            std::result::Result::Ok(())
+        }
+    }
+
+    #[test]
+    fn test_dyn_table_writer() -> Result<()> {
+        let mut fields = Vec::new();
+        String::schema(&mut fields, Some("venue"), None, None);
+        f64::schema(&mut fields, Some("price"), None, None);
+        Option::<u64>::schema(&mut fields, Some("size"), None, None);
+        let descriptor = Descriptor::new("plugin", fields);
+
+        let config = purged("plugin");
+        let mut writer = DynTableWriter::new("plugin", &config, descriptor)?;
+        let records: Vec<Box<dyn DynPersistable>> = vec![
+            Box::new(Plugin {
+                values: vec![Field::Str("a".to_owned()), Field::Double(1.5), Field::Long(3)],
+            }),
+            Box::new(Plugin {
+                values: vec![Field::Str("b".to_owned()), Field::Double(2.5), Field::Null],
+            }),
+        ];
+        for record in records.iter() {
+            writer.write(record.as_ref())?;
+        }
+
+        // the row is dropped, the writer keeps going
+        let e = writer
+            .write(&Plugin {
+                values: vec![Field::Str("c".to_owned())],
+            })
+            .unwrap_err();
+        assert!(
+            matches!(e.downcast_ref(), Some(PersistError::SchemaMismatch { expected, found })
+                if expected == "3 columns of plugin" && found == "1 values appended"),
+            "{e}"
+        );
+        assert_eq!(2, writer.stats().rows_buffered);
+        writer.close()?;
+
+        let path = TMP_FOLDER.join("plugin").join("000000000.parquet");
+        let reader = SerializedFileReader::new(std::fs::File::open(path)?)?;
+        let rows = reader.get_row_iter(None)?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(2, rows.len());
+        assert_eq!("b", rows[1].get_string(0)?);
+        assert_eq!(2.5, rows[1].get_double(1)?);
+        assert_eq!(3, rows[0].get_long(2)?);
+
+        // typed records are dynamic records too, with the columns of their type
+        let config = purged("plugin_typed");
+        let mut writer = DynTableWriter::new("plugin_typed", &config, Descriptor::of::<Inner>())?;
+        writer.write(&Inner { bar: 1.0 })?;
+        assert_eq!(1, writer.stats().rows_buffered);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_builder() -> Result<()> {
+        let descriptor = SchemaBuilder::new("ticks")
+            .column("symbol", ColumnType::String)
+            .column("time", ColumnType::Timestamp)
+            .column("price", ColumnType::F64)
+            .optional("size", ColumnType::I64)
+            .build()?;
+        assert_eq!(4, descriptor.fields().len());
+
+        let config = purged("ticks");
+        let mut writer = DynTableWriter::new("ticks", &config, descriptor)?;
+        let time = chrono::DateTime::from_timestamp_millis(1_700_000_000_000).unwrap();
+        writer.write(&DynamicRow::new([
+            "BTC-USD".into(),
+            time.into(),
+            37000.5.into(),
+            Some(2i64).into(),
+        ]))?;
+        let mut row = DynamicRow::default();
+        row.push("ETH-USD").push(time).push(2000.0).push(None::<i64>);
+        writer.write(&row)?;
+
+        // the values are checked against their columns even without validation
+        let mistyped = DynamicRow::new([
+            "SOL-USD".into(),
+            time.into(),
+            DynamicValue::I64(150),
+            DynamicValue::Null,
+        ]);
+        let e = writer.write(&mistyped).unwrap_err();
+        assert!(e.to_string().contains("column price (index 2) at row 2"), "{e}");
+        writer.close()?;
+
+        let path = TMP_FOLDER.join("ticks").join("000000000.parquet");
+        let reader = SerializedFileReader::new(std::fs::File::open(path)?)?;
+        let rows = reader.get_row_iter(None)?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(2, rows.len());
+        assert_eq!("ETH-USD", rows[1].get_string(0)?);
+        assert_eq!(2, rows[0].get_long(3)?);
+        assert!(rows[1].get_long(3).is_err());
+
+        let e = SchemaBuilder::new("ticks")
+            .column("price", ColumnType::F64)
+            .optional("price", ColumnType::F32)
+            .build()
+            .unwrap_err();
+        assert!(e.to_string().contains("duplicate column price in ticks"), "{e}");
+
+        Ok(())
+    }
+
+    type MemoryFiles = std::sync::Arc<std::sync::Mutex<Vec<(std::path::PathBuf, Vec<u8>)>>>;
+
+    /// File written to memory, handed over to the files of the test once complete.
+    struct MemoryFile {
+        path: std::path::PathBuf,
+        bytes: Vec<u8>,
+        files: MemoryFiles,
+    }
+
+    impl std::io::Write for MemoryFile {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.bytes.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.bytes.flush()
+        }
+    }
+
+    impl Drop for MemoryFile {
+        fn drop(&mut self) {
+            let file = (self.path.clone(), std::mem::take(&mut self.bytes));
+            self.files.lock().unwrap().push(file);
+        }
+    }
+
+    #[test]
+    fn test_output() -> Result<()> {
+        let directory = std::path::Path::new("memory");
+        let config = PersistConfig {
+            rows_per_file: 25,
+            ..PersistConfig::new(directory, "orderbook_output")
+        };
+        let files = MemoryFiles::default();
+        let output = {
+            let files = std::sync::Arc::clone(&files);
+            move |path: &std::path::Path| -> std::io::Result<Box<dyn std::io::Write + Send>> {
+                let files = files.clone();
+                std::io::Result::Ok(Box::new(MemoryFile {
+                    path: path.to_path_buf(),
+                    bytes: vec![],
+                    files,
+                }))
+            }
+        };
+        let mut writer = TableWriter::with_output("orderbook_output", &config, output)?;
+
+        let mut instance = OrderBook::random_instance(100, 200, 50.0);
+        for _ in 0..5 {
+            for _ in 0..10 {
+                instance = instance.tick();
+                writer.begin()?.record(&instance)?.end()?;
+            }
+            writer.flush()?;
+        }
+        writer.close()?;
+        assert!(!directory.exists());
+
+        let files = std::mem::take(&mut *files.lock().unwrap());
+        let paths = files.iter().map(|(path, _)| path.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                directory.join("orderbook_output").join("000000000.parquet"),
+                directory.join("orderbook_output").join("000000001.parquet")
+            ],
+            paths
+        );
+        let rows = files
+            .into_iter()
+            .map(|(_, bytes)| {
+                Ok(SerializedFileReader::new(bytes::Bytes::from(bytes))?
+                    .metadata()
+                    .file_metadata()
+                    .num_rows())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(vec![30, 20], rows);
+
+        // the table log and the checksums read the files back from the filesystem
+        let delta = PersistConfig {
+            format: Format::Delta,
+            ..config
+        };
+        assert!(TableWriter::with_output("orderbook_output", &delta, FileOutput).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_flush_retry() -> Result<()> {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::time::Duration;
+
+        // the files fail to open as many times as set
+        let failures = std::sync::Arc::new(AtomicU32::new(0));
+        let output = |failures: std::sync::Arc<AtomicU32>| {
+            move |_: &std::path::Path| -> std::io::Result<Box<dyn std::io::Write + Send>> {
+                if failures
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                    .is_ok()
+                {
+                    return Err(std::io::ErrorKind::TimedOut.into());
+                }
+                std::io::Result::Ok(Box::new(std::io::sink()))
+            }
+        };
+        let recovery = TMP_FOLDER.join("recovery");
+        let _ = std::fs::remove_dir_all(&recovery);
+        let config = PersistConfig {
+            flush_retry: RetryPolicy::new(3, Duration::from_millis(1)).with_recovery_directory(&recovery),
+            ..PersistConfig::new(std::path::Path::new("memory"), "orderbook_retry")
+        };
+        let mut writer = TableWriter::with_output("orderbook_retry", &config, output(failures.clone()))?;
+        let record = |writer: &mut TableWriter| -> Result<()> {
+            for _ in 0..10 {
+                writer
+                    .begin()?
+                    .record(&OrderBook::random_instance(100, 200, 50.0))?
+                    .end()?;
+            }
+            Ok(())
+        };
+
+        // a transient failure is retried, the rows written once it is over
+        failures.store(2, Ordering::SeqCst);
+        record(&mut writer)?;
+        writer.flush()?;
+        let stats = writer.stats();
+        assert_eq!(
+            (0, 10, 2),
+            (stats.rows_buffered, stats.rows_written, stats.flush_retries)
+        );
+
+        // the rows are dumped once the retries are exhausted
+        failures.store(u32::MAX, Ordering::SeqCst);
+        record(&mut writer)?;
+        let e = writer.flush().unwrap_err();
+        let PersistError::FlushFailed {
+            attempts: 4,
+            recovery: Some(dump),
+            source,
+        } = &e
+        else {
+            panic!("not exhausted: {e}");
+        };
+        assert!(matches!(**source, PersistError::Io(_)), "{e}");
+        assert!(dump.starts_with(recovery.join("orderbook_retry")), "{e}");
+        assert_eq!(10, std::fs::read_to_string(dump)?.lines().count());
+        assert_eq!(0, writer.stats().rows_buffered);
+
+        // without a policy the flush fails at once, its rows kept buffered
+        let config = PersistConfig::new(std::path::Path::new("memory"), "orderbook_retry");
+        let mut writer = TableWriter::with_output("orderbook_retry", &config, output(failures.clone()))?;
+        record(&mut writer)?;
+        assert!(matches!(writer.flush(), Err(PersistError::Io(_))));
+        assert_eq!((10, 0), (writer.stats().rows_buffered, writer.stats().flush_retries));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_spill() -> Result<()> {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        // an object store, unavailable while set
+        let down = std::sync::Arc::new(AtomicBool::new(false));
+        let files = MemoryFiles::default();
+        let output = || {
+            let (down, files) = (down.clone(), files.clone());
+            move |path: &std::path::Path| -> std::io::Result<Box<dyn std::io::Write + Send>> {
+                if down.load(Ordering::SeqCst) {
+                    return Err(std::io::ErrorKind::ConnectionRefused.into());
+                }
+                std::io::Result::Ok(Box::new(MemoryFile {
+                    path: path.to_path_buf(),
+                    bytes: vec![],
+                    files: files.clone(),
+                }))
+            }
+        };
+        let spill = TMP_FOLDER.join("spill");
+        let _ = std::fs::remove_dir_all(&spill);
+        let config = PersistConfig {
+            spill_directory: Some(spill.clone()),
+            ..PersistConfig::new(std::path::Path::new("store"), "orderbook_spill")
+        };
+        let record = |writer: &mut TableWriter, rows: usize| -> Result<()> {
+            for _ in 0..rows {
+                writer
+                    .begin()?
+                    .record(&OrderBook::random_instance(100, 200, 50.0))?
+                    .end()?;
+            }
+            Ok(())
+        };
+        let spilled = || -> Result<Vec<String>> {
+            let mut names = std::fs::read_dir(spill.join("orderbook_spill"))?
+                .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                .collect::<Result<Vec<_>>>()?;
+            names.sort();
+            Ok(names)
+        };
+        let written = || -> Result<Vec<(String, i64)>> {
+            std::mem::take(&mut *files.lock().unwrap())
+                .into_iter()
+                .map(|(path, bytes)| {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                    let rows = SerializedFileReader::new(bytes::Bytes::from(bytes))?
+                        .metadata()
+                        .file_metadata()
+                        .num_rows();
+                    Ok((name, rows))
+                })
+                .collect()
+        };
+
+        // the flushes failing while the store is down are spilled
+        let mut writer = TableWriter::with_output("orderbook_spill", &config, output())?;
+        down.store(true, Ordering::SeqCst);
+        record(&mut writer, 10)?;
+        writer.flush()?;
+        record(&mut writer, 5)?;
+        writer.flush()?;
+        assert_eq!((0, 15), (writer.stats().rows_buffered, writer.stats().rows_spilled));
+        assert_eq!(vec!["000000000-10.parquet", "000000001-5.parquet"], spilled()?);
+
+        // then drained in order once it recovers, before the rows of the flush
+        down.store(false, Ordering::SeqCst);
+        record(&mut writer, 3)?;
+        writer.flush()?;
+        assert_eq!(
+            vec![
+                ("000000002.parquet".to_owned(), 10),
+                ("000000003.parquet".to_owned(), 5),
+                ("000000004.parquet".to_owned(), 3)
+            ],
+            written()?
+        );
+        assert_eq!((18, 0), (writer.stats().rows_written, writer.stats().rows_spilled));
+        assert!(spilled()?.is_empty());
+
+        // the files spilled when the writer is dropped are drained by the next writer
+        down.store(true, Ordering::SeqCst);
+        record(&mut writer, 7)?;
+        drop(writer);
+        assert_eq!(vec!["000000002-7.parquet"], spilled()?);
+        down.store(false, Ordering::SeqCst);
+        let mut writer = TableWriter::with_output("orderbook_spill", &config, output())?;
+        assert_eq!(7, writer.stats().rows_spilled);
+        record(&mut writer, 1)?;
+        writer.close()?;
+        assert_eq!(
+            vec![("000000000.parquet".to_owned(), 7), ("000000001.parquet".to_owned(), 1)],
+            written()?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_table_writer() -> Result<()> {
+        let config = PersistConfig {
+            rows_per_row_group: 64,
+            ..Default::default()
+        };
+        let mut writer = MemoryTableWriter::new("orderbook_memory", &config)?;
+        assert!(writer.flush_to_bytes()?.is_none());
+
+        // more rows than buffered, flushed to the same file
+        let mut instance = OrderBook::random_instance(100, 200, 50.0);
+        for _ in 0..150 {
+            instance = instance.tick();
+            writer.begin()?.record(&instance)?.end()?;
+        }
+        let bytes = writer.flush_to_bytes()?.expect("rows recorded");
+        let reader = SerializedFileReader::new(bytes)?;
+        assert_eq!(150, reader.metadata().file_metadata().num_rows());
+        let row_groups = reader.metadata().row_groups().iter().map(|group| group.num_rows());
+        assert_eq!(vec![64, 36, 50], row_groups.collect::<Vec<_>>());
+        assert_eq!(0, writer.stats().rows_buffered);
+        assert!(writer.flush_to_bytes()?.is_none());
+
+        // the next rows are encoded as a new file
+        writer.begin()?.record(&instance.tick())?.end()?;
+        let bytes = writer.flush_to_bytes()?.expect("row recorded");
+        assert_eq!(
+            1,
+            SerializedFileReader::new(bytes)?.metadata().file_metadata().num_rows()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_reader() -> Result<()> {
+        let descriptor = SchemaBuilder::new("ticks_reader")
+            .column("symbol", ColumnType::String)
+            .optional("size", ColumnType::I64)
+            .build()?;
+        let config = PersistConfig {
+            checksum: true,
+            ..purged("ticks_reader")
+        };
+        let mut writer = DynTableWriter::new("ticks_reader", &config, descriptor)?;
+        for (symbol, size) in [("BTC-USD", Some(1i64)), ("ETH-USD", None)] {
+            writer.write(&DynamicRow::new([symbol.into(), size.into()]))?;
+            writer.flush()?;
+        }
+        writer.write(&DynamicRow::new(["SOL-USD".into(), Some(3i64).into()]))?;
+        writer.close()?;
+
+        // the checksum sidecars are skipped, the files are read in order
+        let reader: TableReader = TableReader::open(&TMP_FOLDER.join("ticks_reader"))?;
+        assert_eq!(3, reader.files().len());
+        let rows = reader.collect::<Result<Vec<_>, _>>()?;
+        let symbols = rows
+            .iter()
+            .map(|row| row.get_string(0).cloned())
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(vec!["BTC-USD", "ETH-USD", "SOL-USD"], symbols);
+        assert!(rows[1].get_long(1).is_err());
+        assert_eq!(3, rows[2].get_long(1)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_reader_filters() -> Result<()> {
+        let config = PersistConfig {
+            time_index: Some("time".to_owned()),
+            rows_per_row_group: 5,
+            ..purged("ticks_filters")
+        };
+        let mut writer = TableWriter::new("ticks_filters", &config)?;
+        let start: chrono::DateTime<chrono::Utc> = "2024-03-01T00:00:00Z".parse()?;
+        let minutes = |i: i64| start + chrono::TimeDelta::minutes(i);
+        for file in 0..3 {
+            for i in 0..10 {
+                let tick = Tick {
+                    time: minutes(file * 10 + i),
+                    symbol: if i < 5 { "BTC-USD" } else { "ETH-USD" }.to_owned(),
+                    price: (file * 10 + i) as f64,
+                    size: (i % 2 == 0).then_some(1.0),
+                };
+                writer.begin()?.record(&tick)?.end()?;
+            }
+            writer.flush()?;
+        }
+        writer.close()?;
+        let directory = TMP_FOLDER.join("ticks_filters");
+        let prices = |reader: TableReader| -> Result<Vec<f64>> {
+            let rows = reader.collect::<Result<Vec<_>, _>>()?;
+            Ok(rows
+                .iter()
+                .map(|row| row.get_double(2))
+                .collect::<Result<Vec<_>, _>>()?)
+        };
+
+        let reader = TableReader::open(&directory)?.between(minutes(12), minutes(21));
+        assert_eq!(
+            vec![12.0, 13.0, 14.0, 15.0, 16.0, 17.0, 18.0, 19.0, 20.0],
+            prices(reader)?
+        );
+
+        let reader = TableReader::open(&directory)?
+            .between(minutes(10), minutes(30))
+            .with_predicate(Predicate::new("symbol", Comparison::Eq, "ETH-USD"))
+            .with_predicate(Predicate::new("price", Comparison::Le, 26.0));
+        assert_eq!(vec![15.0, 16.0, 17.0, 18.0, 19.0, 25.0, 26.0], prices(reader)?);
+
+        // nulls never match
+        let reader = TableReader::open(&directory)?
+            .between(minutes(20), minutes(25))
+            .with_predicate(Predicate::new("size", Comparison::Ge, 0.0));
+        assert_eq!(vec![20.0, 22.0, 24.0], prices(reader)?);
+
+        // the times are compared to timestamp columns only
+        let mut reader: TableReader = TableReader::open(&directory)?
+            .between(minutes(10), minutes(20))
+            .time_column("symbol");
+        let e = reader.next().unwrap().unwrap_err();
+        assert!(
+            matches!(&e, PersistError::ColumnTypeMismatch { column, found, .. }
+                if column == "symbol" && found == "BYTE_ARRAY"),
+            "{e}"
+        );
+
+        // the files without the column have no matching rows
+        let reader: TableReader = TableReader::open(&directory)?
+            .between(minutes(10), minutes(20))
+            .time_column("received");
+        assert_eq!(0, reader.count());
+
+        // the files outside of the range are not opened
+        std::fs::write(directory.join("000000000.parquet"), b"corrupted")?;
+        let reader = TableReader::open(&directory)?.between(minutes(12), minutes(21));
+        assert_eq!(9, prices(reader)?.len());
+
+        Ok(())
+    }
+
+    #[derive(Persist)]
+    struct QuoteV1 {
+        time: chrono::DateTime<chrono::Utc>,
+        symbol: String,
+        bid: f64,
+    }
+
+    /// `QuoteV1` once the ask, the venue and the market were added.
+    #[derive(Persist, Readable, Debug, PartialEq)]
+    struct QuoteV2 {
+        time: chrono::DateTime<chrono::Utc>,
+        symbol: String,
+        bid: f64,
+        #[persist(default)]
+        ask: Option<f64>,
+        #[persist(default = "\"spot\".to_owned()")]
+        market: String,
+        #[persist(ignore)]
+        sequence: u64,
+    }
+
+    #[derive(Persist, Readable, Debug)]
+    struct QuoteV3 {
+        symbol: String,
+        venue: String,
+    }
+
+    #[test]
+    fn test_readable_defaults() -> Result<()> {
+        let time: chrono::DateTime<chrono::Utc> = "2024-03-01T00:00:00Z".parse()?;
+        let mut writer = TableWriter::new("quotes_evolution", &purged("quotes_evolution"))?;
+        let old = QuoteV1 {
+            time,
+            symbol: "BTC-USD".to_owned(),
+            bid: 100.0,
+        };
+        writer.begin()?.record(&old)?.end()?;
+        drop(writer);
+        let config = PersistConfig::new(&TMP_FOLDER, "quotes_evolution");
+        let mut writer = TableWriter::new("quotes_evolution", &config)?;
+        let new = QuoteV2 {
+            time,
+            symbol: "ETH-USD".to_owned(),
+            bid: 10.0,
+            ask: None,
+            market: "perp".to_owned(),
+            sequence: 7,
+        };
+        writer.begin()?.record(&new)?.end()?;
+        writer.close()?;
+
+        // the columns missing from the older file are filled with the defaults, the null ones are kept
+        let directory = TMP_FOLDER.join("quotes_evolution");
+        let quotes = TableReader::open(&directory)?.collect::<Result<Vec<QuoteV2>, _>>()?;
+        let expected = vec![
+            QuoteV2 {
+                time,
+                symbol: "BTC-USD".to_owned(),
+                bid: 100.0,
+                ask: None,
+                market: "spot".to_owned(),
+                sequence: 0,
+            },
+            QuoteV2 { sequence: 0, ..new },
+        ];
+        assert_eq!(expected, quotes);
+
+        // without a default the missing column fails the read
+        let e = TableReader::<QuoteV3>::open(&directory)?.next().unwrap().unwrap_err();
+        assert!(
+            matches!(&e, PersistError::ColumnMissing(column) if column == "venue"),
+            "{e}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_reader() -> Result<()> {
+        let start: chrono::DateTime<chrono::Utc> = "2024-03-01T00:00:00Z".parse()?;
+        let seconds = |i: i64| start + chrono::TimeDelta::seconds(i);
+        // the trades of three venues, over several files
+        let venues = [
+            ("merge/a", vec![0, 3, 3, 7]),
+            ("merge/b", vec![1, 3, 8]),
+            ("merge/c", vec![]),
+        ];
+        for (table, times) in venues.iter() {
+            let mut writer = TableWriter::new(table, &purged(table))?;
+            for (i, time) in times.iter().enumerate() {
+                let tick = Tick {
+                    time: seconds(*time),
+                    symbol: table.to_string(),
+                    price: *time as f64,
+                    size: None,
+                };
+                writer.begin()?.record(&tick)?.end()?;
+                if i % 2 == 1 {
+                    writer.flush()?;
+                }
+            }
+            writer.close()?;
+        }
+
+        let directories = venues.iter().map(|(table, _)| TMP_FOLDER.join(table));
+        let reader: MergeReader = MergeReader::open(directories)?;
+        let rows = reader
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .map(|(table, row)| Ok((table, row.get_double(2)?)))
+            .collect::<Result<Vec<_>>>()?;
+        // the rows of the same time in the order of the tables
+        let expected = vec![(0, 0.0), (1, 1.0), (0, 3.0), (0, 3.0), (1, 3.0), (0, 7.0), (1, 8.0)];
+        assert_eq!(expected, rows);
+
+        // the readers keep their own range
+        let readers = vec![
+            TableReader::open(&TMP_FOLDER.join("merge/a"))?.between(seconds(3), seconds(10)),
+            TableReader::open(&TMP_FOLDER.join("merge/b"))?.between(seconds(0), seconds(3)),
+        ];
+        let reader: MergeReader = MergeReader::new(readers);
+        let tables = reader
+            .map(|row| row.map(|(table, _)| table))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(vec![1, 0, 0, 0], tables);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_context() -> Result<()> {
+        let config = purged("levels_context");
+        let mut writer = TableWriter::new("levels_context", &config)?;
+        for (i, symbol) in ["BTC-USD", "ETH-USD", "BTC-USD"].into_iter().enumerate() {
+            let level = Level {
+                price: 100.0 + i as f64,
+                quantity: 1.0,
+                stale: false,
+            };
+            writer
+                .begin()?
+                .context("exchange", &"coinbase")?
+                .tag(symbol)?
+                .record(&level)?
+                .context("sequence", &(i as u64))?
+                .end()?;
+        }
+
+        // the context is set in the order of the first row
+        let e = writer.begin()?.tag("BTC-USD").err().unwrap();
+        assert!(
+            matches!(&e, PersistError::SchemaMismatch { expected, found }
+                if expected.starts_with("column 0 (exchange)") && found == "context symbol"),
+            "{e}"
+        );
+        assert_eq!(3, writer.stats().rows_buffered);
+        writer.close()?;
+
+        let reader: TableReader = TableReader::open(&TMP_FOLDER.join("levels_context"))?;
+        let rows = reader.collect::<Result<Vec<_>, _>>()?;
+        let names = rows[0]
+            .get_column_iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec!["exchange", "symbol", "price", "quantity", "stale", "sequence"],
+            names
+        );
+        assert_eq!("ETH-USD", rows[1].get_string(1)?);
+        assert_eq!(2, rows[2].get_long(5)?);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,46 @@
+/*
+ * C API of the dixit persist crate, see dixit_persist_ffi/src/lib.rs.
+ *
+ * Functions returning an int return 0 on success and -1 on failure, the message of the last failure of the calling
+ * thread being returned by dixit_persist_last_error.
+ */
+
+#ifndef DIXIT_PERSIST_H
+#define DIXIT_PERSIST_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct DixitWriter DixitWriter;
+
+/*
+ * Creates the writer of a table, returns NULL on failure.
+ *
+ * config:  JSON of a PersistConfig, e.g. {"directory": "output", "format": "parquet"}
+ * table:   table name, e.g. "venue/trades"
+ * columns: JSON array of the columns, e.g. [{"name": "price", "type": "f64"}, {"name": "size", "type": "f64",
+ *          "optional": true}], the types being bool, i32, i64, u64, f32, f64, string or timestamp
+ */
+DixitWriter *dixit_persist_writer_new(const char *config, const char *table, const char *columns);
+
+/*
+ * Appends a row given as a JSON object keyed by column name, a missing key being a null, timestamps being given as
+ * nanoseconds since the epoch or RFC 3339 strings. A row failing to append is dropped.
+ */
+int dixit_persist_writer_append_json(DixitWriter *writer, const char *row);
+
+/* Writes the buffered rows to the table. */
+int dixit_persist_writer_flush(DixitWriter *writer);
+
+/* Writes the buffered rows, closes the open file and frees the writer, even on failure. */
+int dixit_persist_writer_close(DixitWriter *writer);
+
+/* Message of the last failure of the calling thread, NULL if none, valid until the next failure of the thread. */
+const char *dixit_persist_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* DIXIT_PERSIST_H */
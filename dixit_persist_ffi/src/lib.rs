@@ -0,0 +1,203 @@
+//! C API of the persist crate, so market data systems in other languages (e.g. C++ or Java through JNI/JNA) write
+//! the same Parquet tables as the recorder.
+//!
+//! ## Features
+//! - A writer is created from the JSON of a `PersistConfig`, a table name and the JSON of its columns, e.g.
+//!   `[{"name": "symbol", "type": "string"}, {"name": "size", "type": "f64", "optional": true}]`, see
+//!   [`ColumnType`] for the types.
+//! - Rows are appended as JSON objects keyed by column name, a missing key being a null, timestamps being given as
+//!   nanoseconds since the epoch or RFC 3339 strings.
+//! - Functions return 0 on success and -1 on failure, the message of the last failure of the calling thread being
+//!   returned by [`dixit_persist_last_error`].
+//! - The C declarations are in `include/dixit_persist.h`.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::DateTime;
+use dixit_persist::config::PersistConfig;
+use dixit_persist::dynamic::{ColumnType, DynTableWriter, DynamicRow, DynamicValue, SchemaBuilder};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Writer of a table, opaque to C.
+pub struct DixitWriter {
+    writer: DynTableWriter,
+    columns: Vec<(String, ColumnType)>,
+}
+
+#[derive(Deserialize)]
+struct Column {
+    name: String,
+    #[serde(rename = "type")]
+    column_type: String,
+    #[serde(default)]
+    optional: bool,
+}
+
+impl DixitWriter {
+    fn new(config: &str, table: &str, columns: &str) -> Result<Self> {
+        let config: PersistConfig = serde_json::from_str(config).context("invalid config")?;
+        let columns: Vec<Column> = serde_json::from_str(columns).context("invalid columns")?;
+        let mut builder = SchemaBuilder::new(table);
+        let mut types = vec![];
+        for column in columns {
+            let column_type = column.column_type.parse::<ColumnType>().map_err(|e| anyhow!(e))?;
+            builder = if column.optional {
+                builder.optional(&column.name, column_type)
+            } else {
+                builder.column(&column.name, column_type)
+            };
+            types.push((column.name, column_type));
+        }
+        Ok(Self {
+            writer: DynTableWriter::new(table, &config, builder.build()?)?,
+            columns: types,
+        })
+    }
+
+    fn append(&mut self, row: &str) -> Result<()> {
+        let row: Map<String, Value> = serde_json::from_str(row).context("row is not a JSON object")?;
+        let values = self
+            .columns
+            .iter()
+            .map(|(name, column_type)| {
+                let value = row.get(name).unwrap_or(&Value::Null);
+                dynamic_value(*column_type, value).with_context(|| format!("invalid value {value} for column {name}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.writer.write(&DynamicRow::new(values))
+    }
+}
+
+fn dynamic_value(column_type: ColumnType, value: &Value) -> Result<DynamicValue> {
+    if value.is_null() {
+        return Ok(DynamicValue::Null);
+    }
+    let value = match column_type {
+        ColumnType::Bool => value.as_bool().map(DynamicValue::from),
+        ColumnType::I32 => value
+            .as_i64()
+            .and_then(|value| i32::try_from(value).ok())
+            .map(DynamicValue::from),
+        ColumnType::I64 => value.as_i64().map(DynamicValue::from),
+        ColumnType::U64 => value.as_u64().map(DynamicValue::from),
+        ColumnType::F32 => value.as_f64().map(|value| DynamicValue::from(value as f32)),
+        ColumnType::F64 => value.as_f64().map(DynamicValue::from),
+        ColumnType::String => value.as_str().map(DynamicValue::from),
+        ColumnType::Timestamp => match value {
+            Value::String(time) => DateTime::parse_from_rfc3339(time)
+                .ok()
+                .map(|time| DynamicValue::from(time.to_utc())),
+            _ => value
+                .as_i64()
+                .map(|nanos| DynamicValue::from(DateTime::from_timestamp_nanos(nanos))),
+        },
+    };
+    match value {
+        Some(value) => Ok(value),
+        None => bail!("expected a value of type {column_type:?}"),
+    }
+}
+
+fn set_last_error(e: anyhow::Error) {
+    // interior nul bytes cannot be passed to C, they are replaced
+    let message = format!("{e:#}").replace('\0', " ");
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+}
+
+fn status(result: Result<()>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            set_last_error(e);
+            -1
+        }
+    }
+}
+
+/// # Safety
+/// `s` is null or a valid nul terminated string.
+unsafe fn to_str<'a>(s: *const c_char, name: &str) -> Result<&'a str> {
+    if s.is_null() {
+        bail!("{name} is null");
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .with_context(|| format!("{name} is not UTF-8"))
+}
+
+/// # Safety
+/// `writer` is null or a writer returned by [`dixit_persist_writer_new`] and not closed yet.
+unsafe fn to_writer<'a>(writer: *mut DixitWriter) -> Result<&'a mut DixitWriter> {
+    writer.as_mut().ok_or_else(|| anyhow!("writer is null"))
+}
+
+/// Creates the writer of `table`, returns null on failure.
+///
+/// # Safety
+/// `config`, `table` and `columns` are valid nul terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn dixit_persist_writer_new(
+    config: *const c_char,
+    table: *const c_char,
+    columns: *const c_char,
+) -> *mut DixitWriter {
+    let writer = (|| {
+        DixitWriter::new(
+            to_str(config, "config")?,
+            to_str(table, "table")?,
+            to_str(columns, "columns")?,
+        )
+    })();
+    match writer {
+        Ok(writer) => Box::into_raw(Box::new(writer)),
+        Err(e) => {
+            set_last_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Appends a row given as a JSON object, a row failing to append is dropped.
+///
+/// # Safety
+/// `writer` is a writer not closed yet and `row` a valid nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn dixit_persist_writer_append_json(writer: *mut DixitWriter, row: *const c_char) -> c_int {
+    status((|| to_writer(writer)?.append(to_str(row, "row")?))())
+}
+
+/// Writes the buffered rows to the table.
+///
+/// # Safety
+/// `writer` is a writer not closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn dixit_persist_writer_flush(writer: *mut DixitWriter) -> c_int {
+    status((|| Ok(to_writer(writer)?.writer.flush()?))())
+}
+
+/// Writes the buffered rows, closes the open file and frees the writer, even on failure.
+///
+/// # Safety
+/// `writer` is null or a writer not closed yet, it must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn dixit_persist_writer_close(writer: *mut DixitWriter) -> c_int {
+    if writer.is_null() {
+        return 0;
+    }
+    let mut writer = Box::from_raw(writer);
+    status(writer.writer.close().map_err(Into::into))
+}
+
+/// Message of the last failure of the calling thread, null if none, valid until the next failure of the thread.
+#[no_mangle]
+pub extern "C" fn dixit_persist_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
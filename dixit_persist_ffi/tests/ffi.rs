@@ -0,0 +1,71 @@
+use std::{env, path::PathBuf, sync::LazyLock};
+
+static TMP_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| {
+    let mut path_buf = env::current_dir().unwrap();
+    path_buf.push("target");
+    path_buf.push("test");
+    path_buf
+});
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, CString};
+
+    use anyhow::Result;
+    use dixit_persist::reader::TableReader;
+    use dixit_persist_ffi::*;
+    use parquet::record::RowAccessor;
+
+    use crate::TMP_FOLDER;
+
+    fn last_error() -> String {
+        let message = dixit_persist_last_error();
+        assert!(!message.is_null());
+        unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_writer() -> Result<()> {
+        let config = serde_json::json!({"directory": *TMP_FOLDER, "overwrite": "purge"}).to_string();
+        let config = CString::new(config)?;
+        let table = CString::new("ticks_ffi")?;
+        let columns = CString::new(
+            r#"[{"name": "symbol", "type": "string"}, {"name": "time", "type": "timestamp"},
+                {"name": "price", "type": "f64"}, {"name": "size", "type": "i64", "optional": true}]"#,
+        )?;
+
+        let writer = unsafe { dixit_persist_writer_new(config.as_ptr(), table.as_ptr(), columns.as_ptr()) };
+        assert!(!writer.is_null(), "{}", last_error());
+
+        let rows = [
+            r#"{"symbol": "BTC-USD", "time": 1700000000000000000, "price": 37000.5, "size": 2}"#,
+            r#"{"symbol": "ETH-USD", "time": "2023-11-14T22:13:20Z", "price": 2000}"#,
+        ];
+        for row in rows {
+            let row = CString::new(row)?;
+            assert_eq!(0, unsafe { dixit_persist_writer_append_json(writer, row.as_ptr()) });
+        }
+        let mistyped = CString::new(r#"{"symbol": "SOL-USD", "time": 0, "price": "150"}"#)?;
+        assert_eq!(-1, unsafe {
+            dixit_persist_writer_append_json(writer, mistyped.as_ptr())
+        });
+        assert!(last_error().contains("column price"), "{}", last_error());
+        assert_eq!(0, unsafe { dixit_persist_writer_flush(writer) });
+        assert_eq!(0, unsafe { dixit_persist_writer_close(writer) });
+
+        let reader: TableReader = TableReader::open(&TMP_FOLDER.join("ticks_ffi"))?;
+        let rows = reader.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(2, rows.len());
+        assert_eq!("ETH-USD", rows[1].get_string(0)?);
+        assert_eq!(rows[0].get_long(1)?, rows[1].get_long(1)?);
+        assert_eq!(2000.0, rows[1].get_double(2)?);
+        assert!(rows[1].get_long(3).is_err());
+
+        let columns = CString::new(r#"[{"name": "price", "type": "decimal"}]"#)?;
+        let writer = unsafe { dixit_persist_writer_new(config.as_ptr(), table.as_ptr(), columns.as_ptr()) };
+        assert!(writer.is_null());
+        assert!(last_error().contains("unknown column type decimal"), "{}", last_error());
+
+        Ok(())
+    }
+}
@@ -6,3 +6,8 @@ mod parquet;
 pub fn parquet_record_writer(input: TokenStream) -> TokenStream {
     parquet::persist_derive(input)
 }
+
+#[proc_macro_derive(Readable, attributes(persist_timestamp, persist))]
+pub fn parquet_record_reader(input: TokenStream) -> TokenStream {
+    parquet::readable_derive(input)
+}
@@ -2,19 +2,83 @@ use crate::parquet::LogicalType::Timestamp;
 use crate::parquet::TimeUnit::{Micros, Millis, Nanos};
 
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, Attribute, Data, DeriveInput, Fields, Lit, Meta, NestedMeta};
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Fields, LitBool, LitStr, Token};
 
 /// A procedural macro that implements the `Persistable` trait for a given struct or enum.
 ///
 /// This macro generates the `schema` and `append` methods, which are used to persist
 /// data structures into Parquet format.
+///
+/// A struct marked `#[persist(transparent)]` is persisted as its single field, which takes the column name of the
+/// outer field instead of a `_0` suffixed one (e.g. `struct Price(Decimal);`).
+///
+/// `#[persist(ignore_if = "path")]` calls `path(&value) -> bool` before persisting: on a struct a matching record is
+/// not written, on a field a matching value is written as null (the column becomes optional).
+///
+/// `#[persist(intern)]` stores the repeated values of a string field once per buffer (e.g. sides or symbols), the
+/// names of enum variants are always interned.
+///
+/// `#[persist(compress = "zstd")]` compresses every value of a string or bytes field on its own, the column being a
+/// plain `BYTE_ARRAY` read back with `dixit_persist::compression::decompress`.
+///
+/// `#[persist(const(exchange = "coinbase", channel = "rfq_match"))]` on a struct adds a string column per constant
+/// before the columns of the fields, holding the same value in every row (interned).
+///
+/// `#[persist(flatten)]` inlines the columns of a struct field without prefixing them by the field name, so message
+/// types sharing a header struct (e.g. exchange, channel, receive time) get the same header columns.
+///
+/// `#[persist(default)]` and `#[persist(default = "expr")]` are used when reading, see [`readable_derive`].
 pub fn persist_derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let name = &input.ident;
+    if let Err(e) = check_target(&input).and_then(|_| check_attributes(&input)) {
+        return e.to_compile_error().into();
+    }
+    let persist_attrs = persist_attributes(&input.attrs);
+
+    let ignored_fn = persist_attrs.ignore_if.map(|predicate| {
+        quote! {
+            fn ignored(&self) -> bool {
+                #predicate(self)
+            }
+        }
+    });
 
-    let schema_body = generate_schema_body(&input.data, name);
-    let append_body = generate_append_body(&input.data, name);
+    let (schema_body, append_body, field_count_body) = if persist_attrs.transparent {
+        match transparent_field(&input.data, name) {
+            Ok((accessor, field)) => (
+                generate_transparent_schema_body(field),
+                {
+                    let append = append_fn(&field.ty);
+                    quote! { #append(&self.#accessor, row)?; }
+                },
+                {
+                    let field_count = field_count_fn(&field.ty);
+                    quote! { #field_count() }
+                },
+            ),
+            Err(e) => return e.to_compile_error().into(),
+        }
+    } else {
+        let (constant_schemas, constant_appends) = generate_constants(&persist_attrs.constants);
+        let (schema_body, append_body) = (
+            generate_schema_body(&input.data, name),
+            generate_append_body(&input.data, name),
+        );
+        (
+            quote! {
+                #constant_schemas
+                #schema_body
+            },
+            quote! {
+                #constant_appends
+                #append_body
+            },
+            generate_field_count_body(&input.data, persist_attrs.constants.len()),
+        )
+    };
 
     let expanded = quote! {
         impl dixit_persist::Persistable for #name {
@@ -33,7 +97,81 @@ pub fn persist_derive(input: TokenStream) -> TokenStream {
                 use parquet::basic::Type as PhysicalType;
 
                 #append_body
-                Ok(())
+                ::core::result::Result::Ok(())
+            }
+
+            fn field_count() -> usize {
+                #field_count_body
+            }
+
+            #ignored_fn
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// A procedural macro that implements the `Readable` trait for a struct with named fields, so the rows of a
+/// `TableReader` are read back into it.
+///
+/// Every field is read from the column named after it with `FromField`, the ignored fields being set to their
+/// default. A column missing from the row (e.g. added after the file was written) fails the read unless the field is
+/// marked `#[persist(default)]`, set to `Default::default()`, or `#[persist(default = "expr")]`, set to `expr`.
+pub fn readable_derive(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    if let Err(e) = check_target(&input)
+        .and_then(|_| check_attributes(&input))
+        .and_then(|_| check_readable(&input))
+    {
+        return e.to_compile_error().into();
+    }
+    let Data::Struct(data) = &input.data else {
+        unreachable!("enums are rejected by check_readable")
+    };
+
+    let field_reads = data.fields.iter().map(|f| {
+        let field_name = &f.ident;
+        let field_type = &f.ty;
+        let persist_attrs = persist_attributes(&f.attrs);
+        if persist_attrs.ignore {
+            return quote! {
+                #field_name: ::core::default::Default::default()
+            };
+        }
+        let read = match persist_attrs.default {
+            None => {
+                let column = quote_spanned! {field_type.span()=>
+                    dixit_persist::reader::column::<#field_type>
+                };
+                quote! { #column(&row, stringify!(#field_name)) }
+            }
+            Some(default) => {
+                let column_or = quote_spanned! {field_type.span()=>
+                    dixit_persist::reader::column_or::<#field_type>
+                };
+                let value = match default {
+                    FieldDefault::Trait => quote! { ::core::default::Default::default() },
+                    FieldDefault::Expr(expr) => quote! { #expr },
+                };
+                quote! {
+                    #column_or(&row, stringify!(#field_name), || ::core::result::Result::Ok(#value))
+                }
+            }
+        };
+        quote! {
+            #field_name: #read?
+        }
+    });
+
+    let expanded = quote! {
+        impl dixit_persist::reader::Readable for #name {
+            fn from_row(
+                row: parquet::record::Row,
+            ) -> ::core::result::Result<Self, dixit_persist::error::PersistError> {
+                ::core::result::Result::Ok(Self {
+                    #(#field_reads),*
+                })
             }
         }
     };
@@ -53,8 +191,9 @@ fn generate_schema_body(data: &Data, name: &syn::Ident) -> proc_macro2::TokenStr
                 let field_schemas = fields.named.iter().filter_map(|f| {
                     let field_name = &f.ident;
                     let field_type = &f.ty;
+                    let schema = schema_fn(field_type);
 
-                    let persist_attrs = parse_persist_attributes(&f.attrs);
+                    let persist_attrs = persist_attributes(&f.attrs);
                     if persist_attrs.ignore {
                         None
                     } else {
@@ -69,13 +208,35 @@ fn generate_schema_body(data: &Data, name: &syn::Ident) -> proc_macro2::TokenStr
                             }
                         };
 
+                        let repetition_code = if persist_attrs.ignore_if.is_some() {
+                            quote! {
+                                Some(parquet::basic::Repetition::OPTIONAL)
+                            }
+                        } else {
+                            quote! {
+                                repetition_override
+                            }
+                        };
+
+                        if persist_attrs.flatten {
+                            return Some(quote! {
+                                #schema(fields, prefix, #repetition_code, #logical_type_code);
+                            });
+                        }
+                        let binary_columns = persist_attrs.compress.map(|_| {
+                            quote! {
+                                dixit_persist::compression::binary_columns(fields, start);
+                            }
+                        });
                         Some(quote! {
                             let name = stringify!(#field_name);
                             let name = match prefix {
                                 Some(p) => format!("{}_{}", p, name),
                                 None => name.to_string(),
                             };
-                            <#field_type>::schema(fields, Some(&name), repetition_override, #logical_type_code);
+                            let start = fields.len();
+                            #schema(fields, Some(&name), #repetition_code, #logical_type_code);
+                            #binary_columns
                         })
                     }
                 });
@@ -86,9 +247,9 @@ fn generate_schema_body(data: &Data, name: &syn::Ident) -> proc_macro2::TokenStr
             }
             Fields::Unnamed(fields) => {
                 let field_schemas = fields.unnamed.iter().enumerate().map(|(i, f)| {
-                    let field_type = &f.ty;
+                    let schema = schema_fn(&f.ty);
                     let index = syn::Index::from(i);
-                    let persist_attrs = parse_persist_attributes(&f.attrs);
+                    let persist_attrs = persist_attributes(&f.attrs);
                     let logical_type_code = if let Some(logical_type) = persist_attrs.logical_type {
                         let logical_type_tokens = logical_type_to_tokens(&logical_type);
                         quote! {
@@ -100,12 +261,17 @@ fn generate_schema_body(data: &Data, name: &syn::Ident) -> proc_macro2::TokenStr
                         }
                     };
 
+                    if persist_attrs.flatten {
+                        return Some(quote! {
+                            #schema(fields, prefix, repetition_override, #logical_type_code);
+                        });
+                    }
                     Some(quote! {
                         let name = match prefix {
                             Some(p) => format!("{}_{}", p, #index),
                             None => stringify!(#index).to_string(),
                         };
-                        <#field_type>::schema(fields, Some(&name), repetition_override, #logical_type_code);
+                        #schema(fields, Some(&name), repetition_override, #logical_type_code);
                     })
                 });
 
@@ -113,9 +279,7 @@ fn generate_schema_body(data: &Data, name: &syn::Ident) -> proc_macro2::TokenStr
                     #(#field_schemas)*
                 }
             }
-            _ => quote! {
-                return Err(::parquet::errors::ParquetError::General(format!("Unimplemented field type: {:?}", #name)));
-            },
+            Fields::Unit => unreachable!("unit structs are rejected by check_target"),
         },
         Data::Enum(_) => {
             quote! {
@@ -132,9 +296,96 @@ fn generate_schema_body(data: &Data, name: &syn::Ident) -> proc_macro2::TokenStr
                 );
             }
         }
-        _ => quote! {
-            return Err(::parquet::errors::ParquetError::General(format!("Unimplemented data type: {:?}", #name)));
-        },
+        Data::Union(_) => unreachable!("unions are rejected by check_target"),
+    }
+}
+
+/// Generates the schema and append code of the constant columns of a struct, see `#[persist(const(...))]`.
+fn generate_constants(constants: &[(syn::Ident, LitStr)]) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    let schemas = constants.iter().map(|(column, _)| {
+        quote! {
+            let name = stringify!(#column);
+            let name = match prefix {
+                Some(p) => format!("{}_{}", p, name),
+                None => name.to_string(),
+            };
+            <&str as dixit_persist::Persistable>::schema(fields, Some(&name), repetition_override, None);
+        }
+    });
+    let appends = constants.iter().map(|(_, value)| {
+        quote! {
+            row.push_interned(#value);
+        }
+    });
+    (quote! { #(#schemas)* }, quote! { #(#appends)* })
+}
+
+/// Generates the column count of a struct or enum, the sum of the column counts of its persisted fields and its
+/// constant columns, without building its schema.
+fn generate_field_count_body(data: &Data, constants: usize) -> proc_macro2::TokenStream {
+    match data {
+        Data::Struct(ref data) => {
+            let counts = data
+                .fields
+                .iter()
+                .filter(|f| matches!(data.fields, Fields::Unnamed(_)) || !persist_attributes(&f.attrs).ignore)
+                .map(|f| {
+                    let field_count = field_count_fn(&f.ty);
+                    quote! { + #field_count() }
+                });
+            quote! { #constants #(#counts)* }
+        }
+        Data::Enum(_) => quote! { 1 },
+        Data::Union(_) => unreachable!("unions are rejected by check_target"),
+    }
+}
+
+/// Returns the accessor and the single persisted field of a `#[persist(transparent)]` struct.
+fn transparent_field<'a>(data: &'a Data, name: &syn::Ident) -> syn::Result<(proc_macro2::TokenStream, &'a syn::Field)> {
+    let fields: Vec<_> = match data {
+        Data::Struct(ref data) => data
+            .fields
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| !persist_attributes(&f.attrs).ignore)
+            .collect(),
+        _ => vec![],
+    };
+    match fields.as_slice() {
+        [(i, field)] => {
+            let accessor = match &field.ident {
+                Some(ident) => quote! { #ident },
+                None => {
+                    let index = syn::Index::from(*i);
+                    quote! { #index }
+                }
+            };
+            Ok((accessor, field))
+        }
+        _ => Err(syn::Error::new_spanned(
+            name,
+            "#[persist(transparent)] requires a struct with exactly one persisted field",
+        )),
+    }
+}
+
+/// Generates the schema body of a transparent struct, the field is named after the prefix and inherits the
+/// logical type of the outer field unless it sets its own.
+fn generate_transparent_schema_body(field: &syn::Field) -> proc_macro2::TokenStream {
+    let schema = schema_fn(&field.ty);
+    let logical_type_code = if let Some(logical_type) = persist_attributes(&field.attrs).logical_type {
+        let logical_type_tokens = logical_type_to_tokens(&logical_type);
+        quote! {
+            Some(#logical_type_tokens)
+        }
+    } else {
+        quote! {
+            logical_type
+        }
+    };
+
+    quote! {
+        #schema(fields, prefix, repetition_override, #logical_type_code);
     }
 }
 
@@ -163,14 +414,31 @@ fn generate_append_body(data: &Data, name: &syn::Ident) -> proc_macro2::TokenStr
             Fields::Named(fields) => {
                 let field_appends = fields.named.iter().filter_map(|f| {
                     let field_name = &f.ident;
-
-                    let persist_attrs = parse_persist_attributes(&f.attrs);
+                    let persist_attrs = persist_attributes(&f.attrs);
+                    let append = if persist_attrs.intern {
+                        append_interned_fn(&f.ty)
+                    } else if persist_attrs.compress.is_some() {
+                        append_compressed_fn(&f.ty)
+                    } else {
+                        append_fn(&f.ty)
+                    };
 
                     if persist_attrs.ignore {
                         None
+                    } else if let Some(predicate) = persist_attrs.ignore_if {
+                        let field_type = &f.ty;
+                        Some(quote! {
+                            if #predicate(&self.#field_name) {
+                                for _ in 0..<#field_type>::field_count() {
+                                    row.push(parquet::record::Field::Null);
+                                }
+                            } else {
+                                #append(&self.#field_name, row)?;
+                            }
+                        })
                     } else {
                         Some(quote! {
-                            self.#field_name.append(row)?;
+                            #append(&self.#field_name, row)?;
                         })
                     }
                 });
@@ -180,10 +448,11 @@ fn generate_append_body(data: &Data, name: &syn::Ident) -> proc_macro2::TokenStr
                 }
             }
             Fields::Unnamed(fields) => {
-                let field_appends = fields.unnamed.iter().enumerate().map(|(i, _)| {
+                let field_appends = fields.unnamed.iter().enumerate().map(|(i, f)| {
                     let index = syn::Index::from(i);
+                    let append = append_fn(&f.ty);
                     Some(quote! {
-                        self.#index.append(row)?;
+                        #append(&self.#index, row)?;
                     })
                 });
 
@@ -191,9 +460,7 @@ fn generate_append_body(data: &Data, name: &syn::Ident) -> proc_macro2::TokenStr
                     #(#field_appends)*
                 }
             }
-            _ => quote! {
-                return Err(::parquet::errors::ParquetError::General(format!("Unimplemented field type: {:?}", #name)));
-            },
+            Fields::Unit => unreachable!("unit structs are rejected by check_target"),
         },
         Data::Enum(ref data) => {
             let match_arms = data.variants.iter().map(|v| {
@@ -205,14 +472,14 @@ fn generate_append_body(data: &Data, name: &syn::Ident) -> proc_macro2::TokenStr
                     Fields::Unit => {
                         quote! {
                             #name::#variant_name => {
-                                row.push(parquet::record::Field::Str(#variant_str.to_string()));
+                                row.push_interned(#variant_str);
                             }
                         }
                     }
                     Fields::Unnamed(_) | Fields::Named(_) => {
                         quote! {
                             #name::#variant_name(..) => {
-                                row.push(parquet::record::Field::Str(#variant_str.to_string()));
+                                row.push_interned(#variant_str);
                             }
                         }
                     }
@@ -225,15 +492,37 @@ fn generate_append_body(data: &Data, name: &syn::Ident) -> proc_macro2::TokenStr
                 }
             }
         }
-        _ => quote! {
-            return Err(::parquet::errors::ParquetError::General(format!("Unimplemented data type: {:?}", #name)));
-        },
+        Data::Union(_) => unreachable!("unions are rejected by check_target"),
     }
 }
 
+#[derive(Default)]
 struct PersistAttributes {
     ignore: bool,
+    ignore_if: Option<syn::ExprPath>,
+    intern: bool,
+    compress: Option<Compression>,
+    /// constant columns of a struct, with their value
+    constants: Vec<(syn::Ident, LitStr)>,
+    transparent: bool,
+    flatten: bool,
     logical_type: Option<LogicalType>,
+    /// value of a field whose column is missing when reading, see `#[persist(default)]`
+    default: Option<FieldDefault>,
+}
+
+/// Default of a field read from a row without its column.
+enum FieldDefault {
+    /// `#[persist(default)]`, the `Default` of the field type
+    Trait,
+    /// `#[persist(default = "expr")]`
+    Expr(syn::Expr),
+}
+
+/// Compression of the values of a field, see `#[persist(compress = "...")]`.
+#[derive(Clone, Copy)]
+enum Compression {
+    Zstd,
 }
 
 enum TimeUnit {
@@ -246,44 +535,326 @@ enum LogicalType {
     Timestamp(TimeUnit),
 }
 
-fn parse_persist_attributes(attrs: &Vec<Attribute>) -> PersistAttributes {
-    let mut persist_attributes = PersistAttributes {
-        ignore: false,
-        logical_type: None,
+/// Rejects the types the derive cannot persist, pointing at the offending item with a hint.
+fn check_target(input: &DeriveInput) -> syn::Result<()> {
+    if let Some(param) = input.generics.params.first() {
+        return Err(syn::Error::new_spanned(
+            param,
+            "Persist cannot be derived for generic types, derive it on a concrete struct wrapping the generic one",
+        ));
+    }
+    match &input.data {
+        Data::Union(data) => Err(syn::Error::new_spanned(
+            data.union_token,
+            "Persist cannot be derived for unions, use a struct or an enum",
+        )),
+        Data::Struct(data) if matches!(data.fields, Fields::Unit) => Err(syn::Error::new_spanned(
+            &input.ident,
+            "Persist cannot be derived for unit structs as they have no fields to persist, add fields or use an enum",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Path to `Persistable::schema` of a field type, spanned so a missing implementation is reported on the field.
+fn schema_fn(field_type: &syn::Type) -> proc_macro2::TokenStream {
+    quote_spanned! {field_type.span()=>
+        <#field_type as dixit_persist::Persistable>::schema
+    }
+}
+
+/// Path to `Persistable::append` of a field type, spanned as [`schema_fn`].
+fn append_fn(field_type: &syn::Type) -> proc_macro2::TokenStream {
+    quote_spanned! {field_type.span()=>
+        <#field_type as dixit_persist::Persistable>::append
+    }
+}
+
+/// Path to `Persistable::field_count` of a field type, spanned as [`schema_fn`].
+fn field_count_fn(field_type: &syn::Type) -> proc_macro2::TokenStream {
+    quote_spanned! {field_type.span()=>
+        <#field_type as dixit_persist::Persistable>::field_count
+    }
+}
+
+/// Path to `Persistable::append_interned` of a field type, spanned as [`schema_fn`].
+fn append_interned_fn(field_type: &syn::Type) -> proc_macro2::TokenStream {
+    quote_spanned! {field_type.span()=>
+        <#field_type as dixit_persist::Persistable>::append_interned
+    }
+}
+
+/// Path to `Persistable::append_compressed` of a field type, spanned as [`schema_fn`].
+fn append_compressed_fn(field_type: &syn::Type) -> proc_macro2::TokenStream {
+    quote_spanned! {field_type.span()=>
+        <#field_type as dixit_persist::Persistable>::append_compressed
+    }
+}
+
+/// Parses the `persist` and `persist_timestamp` attributes of every field and variant, so malformed attributes are
+/// reported at once instead of being ignored.
+fn check_attributes(input: &DeriveInput) -> syn::Result<()> {
+    let container = parse_persist_attributes(&input.attrs).and_then(|attrs| {
+        if attrs.flatten {
+            Err(syn::Error::new_spanned(
+                &input.ident,
+                "#[persist(flatten)] applies to the struct fields to inline, not to the struct itself",
+            ))
+        } else if attrs.default.is_some() {
+            Err(syn::Error::new_spanned(
+                &input.ident,
+                "#[persist(default)] applies to the struct fields read from missing columns, not to the struct itself",
+            ))
+        } else {
+            check_constants(input, &attrs)
+        }
+    });
+    let mut results = vec![container];
+    match &input.data {
+        Data::Struct(data) => results.extend(data.fields.iter().map(|f| {
+            let attrs = parse_persist_attributes(&f.attrs)?;
+            if let Some((column, _)) = attrs.constants.first() {
+                return Err(syn::Error::new_spanned(
+                    column,
+                    "#[persist(const(...))] applies to the struct, add the constant columns there",
+                ));
+            }
+            if attrs.compress.is_some() {
+                if attrs.intern {
+                    return Err(syn::Error::new_spanned(
+                        f,
+                        "persist(intern) and persist(compress) cannot be combined",
+                    ));
+                }
+                check_compressed_type(&f.ty)?;
+            }
+            match attrs.logical_type {
+                Some(Timestamp(_)) => check_timestamp_type(&f.ty),
+                None => Ok(()),
+            }
+        })),
+        Data::Enum(data) => results.extend(
+            data.variants
+                .iter()
+                .map(|v| parse_persist_attributes(&v.attrs).map(|_| ())),
+        ),
+        Data::Union(_) => (),
+    }
+    let mut errors: Option<syn::Error> = None;
+    for e in results.into_iter().filter_map(Result::err) {
+        match errors.as_mut() {
+            Some(errors) => errors.combine(e),
+            None => errors = Some(e),
+        }
+    }
+    errors.map_or(Ok(()), Err)
+}
+
+/// Only the structs with named fields persisted as their fields are read back, the fields being read from a single
+/// column each.
+fn check_readable(input: &DeriveInput) -> syn::Result<()> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "Readable requires a struct with named fields, read from the columns named after them",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "Readable cannot be derived for enums, read them from a string field",
+            ))
+        }
+    };
+    if persist_attributes(&input.attrs).transparent {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "Readable cannot be derived for transparent structs, implement `FromField` for them instead",
+        ));
+    }
+    for field in fields.named.iter() {
+        let attrs = persist_attributes(&field.attrs);
+        if attrs.ignore {
+            continue;
+        }
+        if attrs.flatten || attrs.compress.is_some() {
+            return Err(syn::Error::new_spanned(
+                field,
+                "persist(flatten) and persist(compress) fields cannot be read, ignore them with persist(ignore)",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The constant columns are added to the structs with named fields only, each with a name of its own.
+fn check_constants(input: &DeriveInput, attrs: &PersistAttributes) -> syn::Result<()> {
+    let Some((first, _)) = attrs.constants.first() else {
+        return Ok(());
     };
+    let fields = match &input.data {
+        Data::Struct(data) if !attrs.transparent => match &data.fields {
+            Fields::Named(fields) => fields,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    first,
+                    "#[persist(const(...))] requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                first,
+                "#[persist(const(...))] applies to the structs persisted as their fields, not to enums or \
+                 transparent structs",
+            ))
+        }
+    };
+    for (i, (column, _)) in attrs.constants.iter().enumerate() {
+        let duplicate = attrs.constants[..i].iter().any(|(other, _)| other == column)
+            || fields.named.iter().any(|field| field.ident.as_ref() == Some(column));
+        if duplicate {
+            return Err(syn::Error::new_spanned(column, format!("duplicate column {column}")));
+        }
+    }
+    Ok(())
+}
+
+/// Timestamps are INT64 columns, `persist_timestamp` is rejected on the types written with another physical type.
+fn check_timestamp_type(field_type: &syn::Type) -> syn::Result<()> {
+    const NOT_INT64: &[&str] = &[
+        "bool", "u8", "u16", "u32", "i8", "i16", "i32", "f32", "f64", "String", "str", "Decimal",
+    ];
+    match last_segment(field_type) {
+        Some(segment) if segment.ident == "Option" => match &segment.arguments {
+            syn::PathArguments::AngleBracketed(args) => match args.args.first() {
+                Some(syn::GenericArgument::Type(inner)) => check_timestamp_type(inner),
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        },
+        Some(segment) if NOT_INT64.iter().any(|name| segment.ident == name) => Err(syn::Error::new_spanned(
+            field_type,
+            "persist_timestamp requires a u64 or i64 epoch or a DateTime field",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Only the strings and bytes are compressed, `persist(compress)` is rejected on the other types.
+fn check_compressed_type(field_type: &syn::Type) -> syn::Result<()> {
+    const COMPRESSED: &[&str] = &["String", "str", "CompactString", "Bytes"];
+    match last_segment(field_type) {
+        Some(segment) if segment.ident == "Option" => match &segment.arguments {
+            syn::PathArguments::AngleBracketed(args) => match args.args.first() {
+                Some(syn::GenericArgument::Type(inner)) => check_compressed_type(inner),
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        },
+        Some(segment) if COMPRESSED.iter().any(|name| segment.ident == name) => Ok(()),
+        _ => Err(syn::Error::new_spanned(
+            field_type,
+            "persist(compress) requires a String, &str, CompactString or Bytes field",
+        )),
+    }
+}
+
+fn last_segment(field_type: &syn::Type) -> Option<&syn::PathSegment> {
+    match field_type {
+        syn::Type::Path(path) => path.path.segments.last(),
+        syn::Type::Reference(reference) => last_segment(&reference.elem),
+        _ => None,
+    }
+}
+
+/// Returns the attributes checked by [`check_attributes`].
+fn persist_attributes(attrs: &[Attribute]) -> PersistAttributes {
+    parse_persist_attributes(attrs).expect("persist attributes are checked before generating code")
+}
+
+fn parse_persist_attributes(attrs: &[Attribute]) -> syn::Result<PersistAttributes> {
+    let mut persist_attributes = PersistAttributes::default();
 
     for attr in attrs {
-        if attr.path.is_ident("persist") {
-            if let Ok(Meta::List(meta_list)) = attr.parse_meta() {
-                for nested_meta in meta_list.nested {
-                    if let NestedMeta::Meta(Meta::NameValue(meta_name_value)) = nested_meta {
-                        if meta_name_value.path.is_ident("ignore") {
-                            if let Lit::Bool(lit_bool) = meta_name_value.lit {
-                                persist_attributes.ignore = lit_bool.value;
-                            }
+        if attr.path().is_ident("persist") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("ignore") {
+                    // `ignore` alone is the same as `ignore = true`
+                    persist_attributes.ignore = if meta.input.peek(Token![=]) {
+                        meta.value()?.parse::<LitBool>()?.value
+                    } else {
+                        true
+                    };
+                } else if meta.path.is_ident("ignore_if") {
+                    persist_attributes.ignore_if = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+                } else if meta.path.is_ident("intern") {
+                    persist_attributes.intern = true;
+                } else if meta.path.is_ident("compress") {
+                    let compression = meta.value()?.parse::<LitStr>()?;
+                    persist_attributes.compress = Some(match compression.value().as_str() {
+                        "zstd" => Compression::Zstd,
+                        _ => {
+                            return Err(syn::Error::new_spanned(
+                                compression,
+                                "unsupported compression, expected zstd",
+                            ))
                         }
-                    }
+                    });
+                } else if meta.path.is_ident("transparent") {
+                    persist_attributes.transparent = true;
+                } else if meta.path.is_ident("flatten") {
+                    persist_attributes.flatten = true;
+                } else if meta.path.is_ident("default") {
+                    // `default` alone is the `Default` of the field type
+                    persist_attributes.default = Some(if meta.input.peek(Token![=]) {
+                        FieldDefault::Expr(meta.value()?.parse::<LitStr>()?.parse()?)
+                    } else {
+                        FieldDefault::Trait
+                    });
+                } else if meta.path.is_ident("const") {
+                    meta.parse_nested_meta(|constant| {
+                        let column = constant
+                            .path
+                            .get_ident()
+                            .cloned()
+                            .ok_or_else(|| constant.error("expected a column name"))?;
+                        let value = constant.value()?.parse::<LitStr>()?;
+                        persist_attributes.constants.push((column, value));
+                        Ok(())
+                    })?;
+                } else {
+                    return Err(meta.error(
+                        "unsupported persist attribute, expected ignore, ignore_if, intern, compress, transparent, flatten, const or default",
+                    ));
                 }
-            }
+                Ok(())
+            })?;
         }
-        if attr.path.is_ident("persist_timestamp") {
-            if let Ok(Meta::List(meta_list)) = attr.parse_meta() {
-                for nested_meta in meta_list.nested {
-                    if let NestedMeta::Meta(Meta::NameValue(meta_name_value)) = nested_meta {
-                        if meta_name_value.path.is_ident("unit") {
-                            if let Lit::Str(lit_str) = meta_name_value.lit {
-                                persist_attributes.logical_type = match lit_str.value().as_str() {
-                                    "ns" => Some(Timestamp(Nanos)),
-                                    "ms" => Some(Timestamp(Millis)),
-                                    "us" => Some(Timestamp(Micros)),
-                                    _ => None,
-                                };
-                            }
-                        }
-                    }
+        if attr.path().is_ident("persist_timestamp") {
+            attr.parse_nested_meta(|meta| {
+                if !meta.path.is_ident("unit") {
+                    return Err(meta.error("unsupported persist_timestamp attribute, expected unit"));
                 }
-            }
+                let unit = meta.value()?.parse::<LitStr>()?;
+                persist_attributes.logical_type = Some(Timestamp(match unit.value().as_str() {
+                    "ns" => Nanos,
+                    "us" => Micros,
+                    "ms" => Millis,
+                    _ => {
+                        return Err(syn::Error::new_spanned(
+                            unit,
+                            "unsupported timestamp unit, expected ns, us or ms",
+                        ))
+                    }
+                }));
+                Ok(())
+            })?;
         }
     }
-    persist_attributes
+    Ok(persist_attributes)
 }
@@ -0,0 +1,295 @@
+//! Python bindings of the persist crate, writing and reading the same Parquet tables as the recorder from Python
+//! (e.g. research notebooks).
+//!
+//! ## Features
+//! - `PersistConfig` mirrors the Rust config, enums being passed as their names (e.g. `format="parquet"`).
+//! - `Writer` records rows of a table whose columns are given at runtime, as `(name, type)` or
+//!   `(name, type, optional)` tuples, the types being `bool`, `i32`, `i64`, `u64`, `f32`, `f64`, `string` or
+//!   `timestamp`.
+//! - Timestamps are written from timezone aware datetimes or nanoseconds since the epoch.
+//! - `Reader` iterates over the rows of a table as dicts, nanosecond timestamps being read as integers.
+//!
+//! ## Example
+//! ```python
+//! import dixit_persist as dp
+//!
+//! config = dp.PersistConfig("output")
+//! with dp.Writer(config, "ticks", [("symbol", "string"), ("price", "f64"), ("size", "f64", True)]) as writer:
+//!     writer.write(["BTC-USD", 37000.5, None])
+//! rows = list(dp.Reader(config, "ticks"))
+//! ```
+
+// the wrappers generated by `#[pymethods]` convert the errors of a `PyResult` into themselves
+#![allow(clippy::useless_conversion)]
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use dixit_persist::config::PersistConfig;
+use dixit_persist::dynamic::{ColumnType, DynTableWriter, DynamicRow, DynamicValue, SchemaBuilder};
+use dixit_persist::path::table_path;
+use dixit_persist::reader::TableReader;
+use parquet::record::{Field, Row};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+
+fn runtime_error(e: impl ToString) -> PyErr {
+    PyRuntimeError::new_err(e.to_string())
+}
+
+#[pyclass(name = "PersistConfig", module = "dixit_persist")]
+#[derive(Clone)]
+struct PyPersistConfig {
+    config: PersistConfig,
+}
+
+#[pymethods]
+impl PyPersistConfig {
+    #[new]
+    #[pyo3(signature = (
+        directory,
+        tables = None,
+        format = "parquet",
+        overwrite = "append",
+        file_naming = "index",
+        validate = false,
+        rows_per_row_group = 0,
+        rows_per_file = 0,
+        checksum = false
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        directory: PathBuf,
+        tables: Option<HashSet<String>>,
+        format: &str,
+        overwrite: &str,
+        file_naming: &str,
+        validate: bool,
+        rows_per_row_group: usize,
+        rows_per_file: usize,
+        checksum: bool,
+    ) -> PyResult<Self> {
+        Ok(Self {
+            config: PersistConfig {
+                directory,
+                tables: tables.unwrap_or_default(),
+                format: format.parse().map_err(PyValueError::new_err)?,
+                overwrite: overwrite.parse().map_err(PyValueError::new_err)?,
+                file_naming: file_naming.parse().map_err(PyValueError::new_err)?,
+                validate,
+                rows_per_row_group,
+                rows_per_file,
+                checksum,
+                ..Default::default()
+            },
+        })
+    }
+
+    #[getter]
+    fn directory(&self) -> PathBuf {
+        self.config.directory.clone()
+    }
+
+    /// Directory of a table, as written by a `Writer` with this config.
+    fn table_path(&self, table: &str) -> PathBuf {
+        table_path(&self.config.directory, table)
+    }
+
+    fn __repr__(&self) -> String {
+        self.config.to_string()
+    }
+}
+
+/// Column given as `(name, type)` or `(name, type, optional)`.
+#[derive(FromPyObject)]
+enum Column {
+    WithOptional(String, String, bool),
+    Required(String, String),
+}
+
+#[pyclass(name = "Writer", module = "dixit_persist")]
+struct PyWriter {
+    writer: DynTableWriter,
+    columns: Vec<ColumnType>,
+}
+
+#[pymethods]
+impl PyWriter {
+    #[new]
+    fn new(config: &PyPersistConfig, table: &str, columns: Vec<Column>) -> PyResult<Self> {
+        let mut builder = SchemaBuilder::new(table);
+        let mut types = vec![];
+        for column in columns {
+            let (name, column_type, optional) = match column {
+                Column::WithOptional(name, column_type, optional) => (name, column_type, optional),
+                Column::Required(name, column_type) => (name, column_type, false),
+            };
+            let column_type = column_type.parse::<ColumnType>().map_err(PyValueError::new_err)?;
+            builder = if optional {
+                builder.optional(&name, column_type)
+            } else {
+                builder.column(&name, column_type)
+            };
+            types.push(column_type);
+        }
+        let descriptor = builder.build().map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(Self {
+            writer: DynTableWriter::new(table, &config.config, descriptor).map_err(runtime_error)?,
+            columns: types,
+        })
+    }
+
+    /// Records a row, one value per column in the order of the columns, `None` for a null.
+    fn write(&mut self, row: Vec<Bound<'_, PyAny>>) -> PyResult<()> {
+        if row.len() != self.columns.len() {
+            return Err(PyValueError::new_err(format!(
+                "{} values for the {} columns of {}",
+                row.len(),
+                self.columns.len(),
+                self.writer.descriptor().name()
+            )));
+        }
+        let values = self
+            .columns
+            .iter()
+            .zip(row.iter())
+            .map(|(column_type, value)| dynamic_value(*column_type, value))
+            .collect::<PyResult<Vec<_>>>()?;
+        self.writer.write(&DynamicRow::new(values)).map_err(runtime_error)
+    }
+
+    fn flush(&mut self) -> PyResult<()> {
+        self.writer.flush().map_err(runtime_error)
+    }
+
+    fn close(&mut self) -> PyResult<()> {
+        self.writer.close().map_err(runtime_error)
+    }
+
+    /// Counters of the table as a dict, see `TableStats`.
+    fn stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let stats = self.writer.stats();
+        let dict = PyDict::new_bound(py);
+        dict.set_item("rows_buffered", stats.rows_buffered)?;
+        dict.set_item("rows_written", stats.rows_written)?;
+        dict.set_item("files_written", stats.files_written)?;
+        Ok(dict)
+    }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    #[pyo3(signature = (*_args))]
+    fn __exit__(&mut self, _args: &Bound<'_, PyAny>) -> PyResult<bool> {
+        self.close()?;
+        Ok(false)
+    }
+}
+
+fn dynamic_value(column_type: ColumnType, value: &Bound<'_, PyAny>) -> PyResult<DynamicValue> {
+    if value.is_none() {
+        return Ok(DynamicValue::Null);
+    }
+    Ok(match column_type {
+        ColumnType::Bool => value.extract::<bool>()?.into(),
+        ColumnType::I32 => value.extract::<i32>()?.into(),
+        ColumnType::I64 => value.extract::<i64>()?.into(),
+        ColumnType::U64 => value.extract::<u64>()?.into(),
+        ColumnType::F32 => value.extract::<f32>()?.into(),
+        ColumnType::F64 => value.extract::<f64>()?.into(),
+        ColumnType::String => value.extract::<String>()?.into(),
+        ColumnType::Timestamp => match value.extract::<i64>() {
+            Ok(nanos) => DateTime::from_timestamp_nanos(nanos).into(),
+            Err(_) => value.extract::<DateTime<Utc>>()?.into(),
+        },
+    })
+}
+
+#[pyclass(name = "Reader", module = "dixit_persist")]
+struct PyReader {
+    reader: TableReader,
+}
+
+#[pymethods]
+impl PyReader {
+    #[new]
+    fn new(config: &PyPersistConfig, table: &str) -> PyResult<Self> {
+        let directory = table_path(&config.config.directory, table);
+        Ok(Self {
+            reader: TableReader::open(&directory).map_err(runtime_error)?,
+        })
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        match self.reader.next() {
+            Some(row) => row_dict(py, &row.map_err(runtime_error)?).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+fn row_dict<'py>(py: Python<'py>, row: &Row) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new_bound(py);
+    for (name, field) in row.get_column_iter() {
+        dict.set_item(name, field_value(py, field)?)?;
+    }
+    Ok(dict)
+}
+
+fn field_value(py: Python<'_>, field: &Field) -> PyResult<PyObject> {
+    Ok(match field {
+        Field::Null => py.None(),
+        Field::Bool(value) => value.into_py(py),
+        Field::Byte(value) => value.into_py(py),
+        Field::Short(value) => value.into_py(py),
+        Field::Int(value) => value.into_py(py),
+        Field::Long(value) => value.into_py(py),
+        Field::UByte(value) => value.into_py(py),
+        Field::UShort(value) => value.into_py(py),
+        Field::UInt(value) => value.into_py(py),
+        Field::ULong(value) => value.into_py(py),
+        Field::Float16(value) => f32::from(*value).into_py(py),
+        Field::Float(value) => value.into_py(py),
+        Field::Double(value) => value.into_py(py),
+        Field::Decimal(_) => field.to_string().into_py(py),
+        Field::Str(value) => value.into_py(py),
+        Field::Bytes(value) => PyBytes::new_bound(py, value.data()).into_py(py),
+        Field::Date(days) => DateTime::from_timestamp(*days as i64 * 86_400, 0)
+            .map(|time| time.date_naive())
+            .into_py(py),
+        Field::TimestampMillis(millis) => DateTime::from_timestamp_millis(*millis).into_py(py),
+        Field::TimestampMicros(micros) => DateTime::from_timestamp_micros(*micros).into_py(py),
+        Field::Group(row) => row_dict(py, row)?.into_py(py),
+        Field::ListInternal(list) => {
+            let elements = list
+                .elements()
+                .iter()
+                .map(|element| field_value(py, element))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyList::new_bound(py, elements).into_py(py)
+        }
+        Field::MapInternal(map) => {
+            let dict = PyDict::new_bound(py);
+            for (key, value) in map.entries() {
+                dict.set_item(field_value(py, key)?, field_value(py, value)?)?;
+            }
+            dict.into_py(py)
+        }
+    })
+}
+
+#[pymodule]
+#[pyo3(name = "dixit_persist")]
+fn dixit_persist_py(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyPersistConfig>()?;
+    module.add_class::<PyWriter>()?;
+    module.add_class::<PyReader>()?;
+    Ok(())
+}